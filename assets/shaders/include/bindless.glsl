@@ -0,0 +1,22 @@
+// Array of textures indexed by the shaders, see BindlessTextures in the
+// vulkan-renderer crate. Define BINDLESS_SET before including it to bind the
+// array to another descriptor set than 1.
+
+#ifndef BINDLESS_GLSL
+#define BINDLESS_GLSL
+
+#extension GL_EXT_nonuniform_qualifier : require
+
+#ifndef BINDLESS_SET
+#define BINDLESS_SET 1
+#endif
+
+layout (set = BINDLESS_SET, binding = 0) uniform sampler2D bindlessTextures[];
+
+// Samples the texture of an index that may differ between invocations, e.g.
+// read from the vertex data.
+vec4 sampleBindless(uint index, vec2 uv) {
+    return texture(bindlessTextures[nonuniformEXT(index)], uv);
+}
+
+#endif
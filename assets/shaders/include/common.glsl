@@ -0,0 +1,25 @@
+// Definitions shared by the shaders of all the crates, included with
+// `#include "common.glsl"`.
+
+#ifndef COMMON_GLSL
+#define COMMON_GLSL
+
+// Converts a color from linear light gamma to sRGB gamma
+// https://gamedev.stackexchange.com/a/148088
+vec4 fromLinear(vec4 linearRGB) {
+    bvec3 cutoff = lessThan(linearRGB.rgb, vec3(0.0031308));
+    vec3 higher = vec3(1.055)*pow(linearRGB.rgb, vec3(1.0/2.4)) - vec3(0.055);
+    vec3 lower = linearRGB.rgb * vec3(12.92);
+    return vec4(mix(higher, lower, cutoff), linearRGB.a);
+}
+
+// Converts a color from sRGB gamma to linear light gamma
+// https://gamedev.stackexchange.com/a/148088
+vec4 toLinear(vec4 sRGB) {
+    bvec3 cutoff = lessThan(sRGB.rgb, vec3(0.04045));
+    vec3 higher = pow((sRGB.rgb + vec3(0.055))/vec3(1.055), vec3(2.4));
+    vec3 lower = sRGB.rgb/vec3(12.92);
+    return vec4(mix(higher, lower, cutoff), sRGB.a);
+}
+
+#endif
@@ -2,17 +2,32 @@ use std::ops::{Add, Mul, Sub};
 use std::time;
 
 use cgmath::{EuclideanSpace, InnerSpace, Matrix4, Point3, SquareMatrix, Vector3};
-use input::InputSystem;
+use input::{Binding, InputMap, InputSystem, ScrollDirection};
 use winit::event::{Event, VirtualKeyCode, WindowEvent};
 
 use crate::Camera;
 
+/// Actions read by the camera controller, see
+/// `CameraController::default_input_map()`.
+pub mod actions {
+    pub const MOVE_FORWARD: &str = "move_forward";
+    pub const MOVE_BACKWARD: &str = "move_backward";
+    pub const MOVE_UP: &str = "move_up";
+    pub const MOVE_DOWN: &str = "move_down";
+    pub const MOVE_LEFT: &str = "move_left";
+    pub const MOVE_RIGHT: &str = "move_right";
+    pub const ZOOM_IN: &str = "zoom_in";
+    pub const ZOOM_OUT: &str = "zoom_out";
+    pub const ZOOM_RESET: &str = "zoom_reset";
+}
+
 const HORIZONTAL_VEC: Vector3<f32> = Vector3::new(1.0, 0.0, 0.0);
 const VERTICAL_VEC: Vector3<f32> = Vector3::new(0.0, 1.0, 0.0);
 
 #[derive(Debug, Clone)]
 pub struct CameraController<T: Camera> {
     camera: T,
+    input_map: InputMap,
 
     speed_base: f32,
     pos: Vector3<f32>,
@@ -37,6 +52,7 @@ where
         let initial_zoom = camera.zoom();
         let mut controller = Self {
             camera,
+            input_map: Self::default_input_map(),
             speed_base: 1.0,
             pos: Vector3::new(0.0, 0.0, 10.0),
             target: Vector3::new(0.0, 0.0, -1.0),
@@ -52,8 +68,48 @@ where
         controller
     }
 
+    /// Returns the bindings of the actions of the controller: Q/E to move
+    /// along Z, WASD to move along Y and X, the mouse wheel to zoom and Z to
+    /// reset the zoom.
+    pub fn default_input_map() -> InputMap {
+        InputMap::new()
+            .with_binding(actions::MOVE_BACKWARD, Binding::Key(VirtualKeyCode::Q))
+            .with_binding(actions::MOVE_FORWARD, Binding::Key(VirtualKeyCode::E))
+            .with_binding(actions::MOVE_UP, Binding::Key(VirtualKeyCode::W))
+            .with_binding(actions::MOVE_DOWN, Binding::Key(VirtualKeyCode::S))
+            .with_binding(actions::MOVE_LEFT, Binding::Key(VirtualKeyCode::A))
+            .with_binding(actions::MOVE_RIGHT, Binding::Key(VirtualKeyCode::D))
+            .with_binding(actions::ZOOM_IN, Binding::Scroll(ScrollDirection::Up))
+            .with_binding(actions::ZOOM_OUT, Binding::Scroll(ScrollDirection::Down))
+            .with_binding(actions::ZOOM_RESET, Binding::Key(VirtualKeyCode::Z))
+    }
+
+    pub fn input_map(&self) -> &InputMap {
+        &self.input_map
+    }
+
+    /// Returns the bindings of the controller, which can be changed at any
+    /// time.
+    pub fn input_map_mut(&mut self) -> &mut InputMap {
+        &mut self.input_map
+    }
+
     pub fn on_update(&mut self, input: &InputSystem, delta: time::Duration) {
         self.compute_view_matrix();
+        let input_map = &self.input_map;
+        let value = |action| input_map.value(input, action);
+        let [forward, backward, up, down, left, right, zoom_in, zoom_out, zoom_reset] = [
+            actions::MOVE_FORWARD,
+            actions::MOVE_BACKWARD,
+            actions::MOVE_UP,
+            actions::MOVE_DOWN,
+            actions::MOVE_LEFT,
+            actions::MOVE_RIGHT,
+            actions::ZOOM_IN,
+            actions::ZOOM_OUT,
+            actions::ZOOM_RESET,
+        ]
+        .map(value);
 
         let speed = self.speed_base * delta.as_secs_f32();
 
@@ -73,29 +129,29 @@ where
         };
 
         // move camera => Z
-        if input.is_key_pressed(VirtualKeyCode::Q) {
-            self.move_backward(movement_speed)
+        if backward > 0.0 {
+            self.move_backward(movement_speed * backward)
         }
-        if input.is_key_pressed(VirtualKeyCode::E) {
-            self.move_forward(movement_speed)
+        if forward > 0.0 {
+            self.move_forward(movement_speed * forward)
         }
         // move camera => Y
-        if input.is_key_pressed(VirtualKeyCode::W) {
-            self.move_up(movement_speed)
+        if up > 0.0 {
+            self.move_up(movement_speed * up)
         }
-        if input.is_key_pressed(VirtualKeyCode::S) {
-            self.move_down(movement_speed)
+        if down > 0.0 {
+            self.move_down(movement_speed * down)
         }
         // move camera => X
-        if input.is_key_pressed(VirtualKeyCode::A) {
-            self.move_left(movement_speed)
+        if left > 0.0 {
+            self.move_left(movement_speed * left)
         }
-        if input.is_key_pressed(VirtualKeyCode::D) {
-            self.move_right(movement_speed)
+        if right > 0.0 {
+            self.move_right(movement_speed * right)
         }
 
-        // on scroll, update zoom_target
-        self.zoom_target -= input.mouse_scoll_y() * self.zoom_sensitivity;
+        // on zoom, update zoom_target in proportion to the amount scrolled
+        self.zoom_target -= (zoom_in - zoom_out) * self.zoom_sensitivity;
 
         // clamp zoom target between min and max
         self.zoom_target = clamp(self.zoom_target, self.zoom_min, self.zoom_max);
@@ -109,7 +165,7 @@ where
         self.camera.set_zoom(zoom_amount);
 
         // reset zoom
-        if input.is_key_pressed(VirtualKeyCode::Z) {
+        if zoom_reset > 0.0 {
             self.camera.reset_zoom();
             self.zoom_target = self.camera.zoom();
         }
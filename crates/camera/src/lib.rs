@@ -3,7 +3,7 @@ mod ortho;
 mod perspective;
 
 use cgmath::Matrix4;
-pub use controller::CameraController;
+pub use controller::{actions, CameraController};
 pub use ortho::CameraOrthographic;
 pub use perspective::CameraPerspective;
 
@@ -0,0 +1,487 @@
+//! Imports meshes from Wavefront OBJ files, along with the materials of their
+//! MTL libraries.
+//!
+//! Faces are triangulated as fans, and the vertices sharing a position,
+//! texture coordinates and normal are merged. Vertices without a normal get
+//! the average normal of the faces around their position, and vertices
+//! without texture coordinates get (0, 0). Texture coordinates are flipped
+//! vertically, since OBJ places their origin at the bottom of the texture.
+//! Lines, points, curves and smoothing groups are ignored.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::{error, fmt, fs, io};
+
+use cgmath::{InnerSpace, Vector2, Vector3, Vector4, Zero};
+use log::warn;
+
+use crate::jobs::JobPool;
+use crate::mesh::{Material, Mesh};
+
+/// Part of an OBJ model sharing an object or group name and a material.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ObjMesh {
+    /// Name of the object or group the mesh is part of, empty if none.
+    pub name: String,
+    pub mesh: Mesh,
+    /// Index of the material of the mesh in `ObjModel::materials`.
+    pub material: Option<usize>,
+}
+
+/// Meshes and materials of an OBJ file.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ObjModel {
+    pub meshes: Vec<ObjMesh>,
+    pub materials: Vec<Material>,
+}
+
+#[derive(Debug)]
+pub enum ObjError {
+    Io {
+        path: PathBuf,
+        error: io::Error,
+    },
+    /// A line of an OBJ or MTL file, numbered from 1, can not be parsed.
+    Parse {
+        file: String,
+        line: usize,
+        message: String,
+    },
+}
+
+impl fmt::Display for ObjError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io { path, error } => write!(f, "read {}: {error}", path.display()),
+            Self::Parse {
+                file,
+                line,
+                message,
+            } => write!(f, "{file}:{line}: {message}"),
+        }
+    }
+}
+
+impl error::Error for ObjError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Self::Io { error, .. } => Some(error),
+            Self::Parse { .. } => None,
+        }
+    }
+}
+
+type Result<T> = std::result::Result<T, ObjError>;
+
+/// Loads an OBJ file and the MTL libraries it refers to, which are looked
+/// for next to it. Texture paths are made relative to the working directory.
+pub fn load_obj(path: impl AsRef<Path>) -> Result<ObjModel> {
+    let path = path.as_ref();
+    let dir = path.parent().unwrap_or_else(|| Path::new(""));
+    let read = |path: &Path| {
+        fs::read_to_string(path).map_err(|error| ObjError::Io {
+            path: path.to_path_buf(),
+            error,
+        })
+    };
+
+    let source = read(path)?;
+    parse_obj(&source, &path.display().to_string(), |library| {
+        let path = dir.join(library);
+        let mut materials = parse_mtl(&read(&path)?, &path.display().to_string())?;
+        for material in &mut materials {
+            if let Some(map) = &mut material.diffuse_map {
+                *map = dir.join(&*map);
+            }
+        }
+        Ok(materials)
+    })
+}
+
+/// Loads OBJ files like `load_obj()`, decoding them in parallel on the jobs
+/// of a pool. Results are in the order of the paths.
+pub fn load_objs<P>(paths: &[P], jobs: &JobPool) -> Vec<Result<ObjModel>>
+where
+    P: AsRef<Path> + Sync,
+{
+    jobs.map(paths, |path| load_obj(path))
+}
+
+/// Parses the content of an OBJ file, named `file` in errors. MTL libraries
+/// are loaded by `load_library` from their name.
+pub fn parse_obj<F>(source: &str, file: &str, mut load_library: F) -> Result<ObjModel>
+where
+    F: FnMut(&str) -> Result<Vec<Material>>,
+{
+    let mut model = ObjModel::default();
+    let mut positions = Vec::new();
+    let mut normals = Vec::new();
+    let mut uvs = Vec::new();
+    let mut builder = MeshBuilder::new(String::new(), None);
+
+    for (index, line) in source.lines().enumerate() {
+        let error = |message: String| ObjError::Parse {
+            file: file.to_string(),
+            line: index + 1,
+            message,
+        };
+        let Some((keyword, args)) = split_line(line) else {
+            continue;
+        };
+        match keyword {
+            "v" => {
+                let [x, y, z] = floats(&args, 3).map_err(error)?;
+                positions.push(Vector3::new(x, y, z));
+            }
+            "vn" => {
+                let [x, y, z] = floats(&args, 3).map_err(error)?;
+                normals.push(Vector3::new(x, y, z));
+            }
+            "vt" => {
+                let [u, v] = floats(&args, 1).map_err(error)?;
+                uvs.push(Vector2::new(u, 1.0 - v));
+            }
+            "f" => {
+                if args.len() < 3 {
+                    return Err(error(format!("face has {} vertices", args.len())));
+                }
+                let mut face = Vec::with_capacity(args.len());
+                for arg in &args {
+                    face.push(
+                        parse_face_vertex(arg, positions.len(), uvs.len(), normals.len())
+                            .map_err(error)?,
+                    );
+                }
+                builder.add_face(&face, &positions, &uvs, &normals);
+            }
+            "o" | "g" => {
+                let name = args.join(" ");
+                let material = builder.material;
+                builder.finish_into(&mut model.meshes);
+                builder = MeshBuilder::new(name, material);
+            }
+            "usemtl" => {
+                let name = args.join(" ");
+                let material = model.materials.iter().rposition(|m| m.name == name);
+                if material.is_none() {
+                    warn!("{file}:{}: unknown material {name}", index + 1);
+                }
+                let name = builder.name.clone();
+                builder.finish_into(&mut model.meshes);
+                builder = MeshBuilder::new(name, material);
+            }
+            "mtllib" => {
+                for library in args {
+                    model.materials.extend(load_library(library)?);
+                }
+            }
+            _ => {}
+        }
+    }
+    builder.finish_into(&mut model.meshes);
+
+    Ok(model)
+}
+
+/// Parses the content of an MTL file, named `file` in errors.
+pub fn parse_mtl(source: &str, file: &str) -> Result<Vec<Material>> {
+    let mut materials: Vec<Material> = Vec::new();
+    for (index, line) in source.lines().enumerate() {
+        let error = |message: String| ObjError::Parse {
+            file: file.to_string(),
+            line: index + 1,
+            message,
+        };
+        let Some((keyword, args)) = split_line(line) else {
+            continue;
+        };
+        if keyword == "newmtl" {
+            materials.push(Material {
+                name: args.join(" "),
+                ..Default::default()
+            });
+            continue;
+        }
+        let Some(material) = materials.last_mut() else {
+            return Err(error(format!("{keyword} before newmtl")));
+        };
+        match keyword {
+            "Kd" => {
+                let [r, g, b] = floats(&args, 3).map_err(error)?;
+                material.diffuse = Vector4::new(r, g, b, material.diffuse.w);
+            }
+            "Ks" => {
+                let [r, g, b] = floats(&args, 3).map_err(error)?;
+                material.specular = Vector3::new(r, g, b);
+            }
+            "Ns" => {
+                let [shininess] = floats(&args, 1).map_err(error)?;
+                material.shininess = shininess;
+            }
+            "d" => {
+                let [opacity] = floats(&args, 1).map_err(error)?;
+                material.diffuse.w = opacity;
+            }
+            "Tr" => {
+                let [transparency] = floats(&args, 1).map_err(error)?;
+                material.diffuse.w = 1.0 - transparency;
+            }
+            "map_Kd" => {
+                // NOTE: options come before the path, which may hold spaces
+                let path = match args.first() {
+                    Some(arg) if !arg.starts_with('-') => args.join(" "),
+                    _ => args.last().map(|arg| arg.to_string()).unwrap_or_default(),
+                };
+                if path.is_empty() {
+                    return Err(error("map_Kd has no path".to_string()));
+                }
+                material.diffuse_map = Some(PathBuf::from(path));
+            }
+            _ => {}
+        }
+    }
+    Ok(materials)
+}
+
+/// Returns the keyword and the arguments of a line, None if it holds no
+/// statement.
+fn split_line(line: &str) -> Option<(&str, Vec<&str>)> {
+    let line = line.split('#').next().unwrap_or_default();
+    let mut words = line.split_whitespace();
+    let keyword = words.next()?;
+    Some((keyword, words.collect()))
+}
+
+/// Parses the first N arguments as floats, the ones past `required` being
+/// optional and 0 by default.
+fn floats<const N: usize>(args: &[&str], required: usize) -> std::result::Result<[f32; N], String> {
+    if args.len() < required {
+        return Err(format!("expected {required} numbers, got {}", args.len()));
+    }
+    let mut values = [0.0; N];
+    for (value, arg) in values.iter_mut().zip(args) {
+        *value = arg
+            .parse()
+            .map_err(|e| format!("invalid number {arg}: {e}"))?;
+    }
+    Ok(values)
+}
+
+/// Indices of the position, texture coordinates and normal of a vertex, see
+/// `parse_face_vertex()`.
+type VertexKey = (usize, Option<usize>, Option<usize>);
+
+/// Parses a face vertex `v`, `v/vt`, `v//vn` or `v/vt/vn` into indices from
+/// 0. OBJ indices start at 1, negative ones counting back from the last
+/// element defined so far.
+fn parse_face_vertex(
+    arg: &str,
+    positions: usize,
+    uvs: usize,
+    normals: usize,
+) -> std::result::Result<VertexKey, String> {
+    let resolve = |index: &str, count: usize| -> std::result::Result<usize, String> {
+        let index: isize = index
+            .parse()
+            .map_err(|e| format!("invalid index {index}: {e}"))?;
+        let resolved = match index {
+            1.. => index - 1,
+            ..=-1 => count as isize + index,
+            0 => -1,
+        };
+        if resolved < 0 || resolved as usize >= count {
+            return Err(format!("index {index} is out of {count} elements"));
+        }
+        Ok(resolved as usize)
+    };
+
+    let mut indices = arg.split('/');
+    let position = resolve(indices.next().unwrap_or_default(), positions)?;
+    let uv = match indices.next() {
+        None | Some("") => None,
+        Some(index) => Some(resolve(index, uvs)?),
+    };
+    let normal = match indices.next() {
+        None | Some("") => None,
+        Some(index) => Some(resolve(index, normals)?),
+    };
+    Ok((position, uv, normal))
+}
+
+/// Mesh of the current object or group and material.
+struct MeshBuilder {
+    name: String,
+    material: Option<usize>,
+    mesh: Mesh,
+    /// Index of each vertex of the mesh.
+    vertices: HashMap<VertexKey, u32>,
+    /// Sum of the normals of the faces around each position, weighted by
+    /// their area, given to the vertices without a normal.
+    face_normals: HashMap<usize, Vector3<f32>>,
+    /// Vertices without a normal, along with their position.
+    missing_normals: Vec<(u32, usize)>,
+}
+
+impl MeshBuilder {
+    fn new(name: String, material: Option<usize>) -> Self {
+        Self {
+            name,
+            material,
+            mesh: Mesh::default(),
+            vertices: HashMap::new(),
+            face_normals: HashMap::new(),
+            missing_normals: Vec::new(),
+        }
+    }
+
+    fn add_face(
+        &mut self,
+        face: &[VertexKey],
+        positions: &[Vector3<f32>],
+        uvs: &[Vector2<f32>],
+        normals: &[Vector3<f32>],
+    ) {
+        // NOTE: the cross products of a fan sum up to twice the area of the
+        //       face, along its normal
+        if face.iter().any(|(_, _, normal)| normal.is_none()) {
+            let origin = positions[face[0].0];
+            let normal = face.windows(2).skip(1).fold(Vector3::zero(), |sum, edge| {
+                sum + (positions[edge[0].0] - origin).cross(positions[edge[1].0] - origin)
+            });
+            for (position, ..) in face {
+                *self
+                    .face_normals
+                    .entry(*position)
+                    .or_insert_with(Vector3::zero) += normal;
+            }
+        }
+
+        let mut indices = Vec::with_capacity(face.len());
+        for &key in face {
+            let index = *self.vertices.entry(key).or_insert_with(|| {
+                let (position, uv, normal) = key;
+                let index = self.mesh.positions.len() as u32;
+                self.mesh.positions.push(positions[position]);
+                self.mesh.uvs.push(uv.map_or(Vector2::zero(), |uv| uvs[uv]));
+                self.mesh
+                    .normals
+                    .push(normal.map_or(Vector3::zero(), |normal| normals[normal]));
+                if normal.is_none() {
+                    self.missing_normals.push((index, position));
+                }
+                index
+            });
+            indices.push(index);
+        }
+        for i in 1..indices.len() - 1 {
+            self.mesh
+                .indices
+                .extend([indices[0], indices[i], indices[i + 1]]);
+        }
+    }
+
+    /// Adds the mesh to `meshes` if it has faces.
+    fn finish_into(mut self, meshes: &mut Vec<ObjMesh>) {
+        if self.mesh.indices.is_empty() {
+            return;
+        }
+        for (vertex, position) in self.missing_normals {
+            let normal = self.face_normals[&position];
+            self.mesh.normals[vertex as usize] = if normal.is_zero() {
+                Vector3::unit_z()
+            } else {
+                normal.normalize()
+            };
+        }
+        meshes.push(ObjMesh {
+            name: self.name,
+            mesh: self.mesh,
+            material: self.material,
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(source: &str) -> ObjModel {
+        parse_obj(source, "test.obj", |_| Ok(Vec::new())).expect("parse obj")
+    }
+
+    #[test]
+    fn shared_vertices_are_merged() {
+        let model = parse(
+            "v 0 0 0\nv 1 0 0\nv 1 1 0\nv 0 1 0\n\
+             vt 0 0\nvt 1 1\nvn 0 0 1\n\
+             # a quad, then the same quad with relative indices\n\
+             f 1/1/1 2/1/1 3/2/1 4/2/1\n\
+             f -4/-2/-1 -3/-2/-1 -2/-1/-1 -1/-1/-1\n",
+        );
+        assert_eq!(model.meshes.len(), 1);
+        let mesh = &model.meshes[0].mesh;
+        assert_eq!(mesh.validate(), Ok(()));
+        assert_eq!(mesh.positions.len(), 4);
+        assert_eq!(mesh.indices, [0, 1, 2, 0, 2, 3, 0, 1, 2, 0, 2, 3]);
+        // flipped vertically
+        assert_eq!(mesh.uvs[0], Vector2::new(0.0, 1.0));
+        assert_eq!(mesh.uvs[2], Vector2::new(1.0, 0.0));
+    }
+
+    #[test]
+    fn missing_normals_are_averaged_from_faces() {
+        let model = parse("v 0 0 0\nv 1 0 0\nv 0 1 0\nv 0 0 1\nf 1 2 3\nf 1 4 2\n");
+        let mesh = &model.meshes[0].mesh;
+        assert_eq!(mesh.validate(), Ok(()));
+        assert_eq!(mesh.normals[2], Vector3::unit_z());
+        assert_eq!(mesh.normals[3], Vector3::unit_y());
+        // the shared edge gets the average of both faces
+        let shared = Vector3::new(0.0, 1.0, 1.0).normalize();
+        assert!((mesh.normals[0] - shared).magnitude() < 1e-6);
+    }
+
+    #[test]
+    fn meshes_are_split_by_group_and_material() {
+        let mtl = "newmtl red\nKd 1 0 0\nd 0.5\nnewmtl blue\nKd 0 0 1\nmap_Kd -s 1 1 1 blue.png\n";
+        let obj = "mtllib scene.mtl\nv 0 0 0\nv 1 0 0\nv 0 1 0\n\
+                   o first\nusemtl red\nf 1 2 3\nusemtl blue\nf 1 2 3\n\
+                   g second\nf 3 2 1\n";
+        let model = parse_obj(obj, "scene.obj", |library| {
+            assert_eq!(library, "scene.mtl");
+            parse_mtl(mtl, library)
+        })
+        .expect("parse obj");
+
+        assert_eq!(model.materials.len(), 2);
+        assert_eq!(model.materials[0].diffuse, Vector4::new(1.0, 0.0, 0.0, 0.5));
+        assert_eq!(
+            model.materials[1].diffuse_map,
+            Some(PathBuf::from("blue.png"))
+        );
+
+        let meshes = model
+            .meshes
+            .iter()
+            .map(|m| (m.name.as_str(), m.material))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            meshes,
+            [("first", Some(0)), ("first", Some(1)), ("second", Some(1))]
+        );
+    }
+
+    #[test]
+    fn errors_point_to_their_line() {
+        let result = parse_obj("v 0 0 0\nv 1 0\n", "bad.obj", |_| Ok(Vec::new()));
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "bad.obj:2: expected 3 numbers, got 2"
+        );
+
+        let result = parse_obj("v 0 0 0\nf 1 2 3\n", "bad.obj", |_| Ok(Vec::new()));
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "bad.obj:2: index 2 is out of 1 elements"
+        );
+    }
+}
@@ -1,8 +1,10 @@
-use cgmath::{Vector3, Vector4};
+use cgmath::{Vector2, Vector3, Vector4};
 
 #[derive(Clone, Copy, Debug, PartialEq)]
 pub struct Transform {
     pub position: Vector3<f32>,
+    /// Rotation in radians around the X, then Y, then Z axis. Only applies to
+    /// meshes.
     pub rotation: Vector3<f32>,
     pub scale: Vector3<f32>,
 }
@@ -41,3 +43,108 @@ impl Default for Color {
         }
     }
 }
+
+/// Solid border drawn around a quad.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Outline {
+    pub color: Vector4<f32>,
+    /// Width of the border, in world units.
+    pub thickness: f32,
+}
+
+/// Copy of a quad drawn behind it, offset in world units.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DropShadow {
+    pub color: Vector4<f32>,
+    pub offset: Vector2<f32>,
+}
+
+/// Linear velocity of an object, in world units per second. Moved by the
+/// movement system of the engine.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Velocity {
+    pub velocity: Vector3<f32>,
+}
+
+impl Velocity {
+    pub fn new(velocity: Vector3<f32>) -> Self {
+        Self { velocity }
+    }
+}
+
+/// Angular velocity of an object, in radians per second around the X, Y and
+/// Z axis, added to `Transform::rotation`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct AngularVelocity {
+    pub velocity: Vector3<f32>,
+}
+
+impl AngularVelocity {
+    pub fn new(velocity: Vector3<f32>) -> Self {
+        Self { velocity }
+    }
+}
+
+/// Shape an object collides with, centered on the object, in world units.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Collider {
+    /// Axis-aligned box, ignoring the rotation of the object.
+    Aabb {
+        half_extents: Vector2<f32>,
+    },
+    Circle {
+        radius: f32,
+    },
+}
+
+impl Collider {
+    pub fn aabb(half_extents: Vector2<f32>) -> Self {
+        Self::Aabb { half_extents }
+    }
+
+    pub fn circle(radius: f32) -> Self {
+        Self::Circle { radius }
+    }
+
+    /// Returns half the size of the box holding the shape.
+    pub fn half_extents(&self) -> Vector2<f32> {
+        match *self {
+            Self::Aabb { half_extents } => half_extents,
+            Self::Circle { radius } => Vector2::new(radius, radius),
+        }
+    }
+}
+
+/// How an object is moved by the physics of the engine, along with its
+/// `Collider`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RigidBody {
+    /// Moved by gravity and collisions.
+    Dynamic,
+    /// Never moved by the physics, e.g. the ground.
+    Fixed,
+    /// Moved by the application, pushing the dynamic bodies in its way.
+    Kinematic,
+}
+
+/// Surface and mass of a rigid body.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PhysicsMaterial {
+    /// Friction coefficient, usually in [0, 1].
+    pub friction: f32,
+    /// Bounciness, from 0 for no bounce to 1 for bounces keeping all the
+    /// energy.
+    pub restitution: f32,
+    /// Mass per unit of area.
+    pub density: f32,
+}
+
+impl Default for PhysicsMaterial {
+    fn default() -> Self {
+        Self {
+            friction: 0.5,
+            restitution: 0.0,
+            density: 1.0,
+        }
+    }
+}
@@ -0,0 +1,789 @@
+//! Entity-component-system storing the objects of the engine.
+//!
+//! An entity is a generational id to which components of any type can be
+//! attached, at most one per type. Components of a type are stored in a
+//! sparse set: densely packed, with an array indexed by entity mapping to
+//! them, so that lookups are O(1) and iteration is cache friendly.
+//!
+//! Queries iterate the entities having a set of components, e.g.
+//! `world.query_mut::<(&Transform, &mut Color)>()`. They walk the smallest
+//! set among the required components and look up the others.
+
+use std::any::{Any, TypeId};
+use std::cell::UnsafeCell;
+use std::collections::HashMap;
+use std::marker::PhantomData;
+use std::{fmt, ptr, slice};
+
+/// Generational id of an entity. The generation is bumped when the entity is
+/// despawned, so that stale ids do not refer to the entity reusing its slot.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Entity {
+    index: u32,
+    generation: u32,
+}
+
+impl Entity {
+    pub fn index(&self) -> u32 {
+        self.index
+    }
+
+    pub fn generation(&self) -> u32 {
+        self.generation
+    }
+
+    /// Packs the entity into a u64, e.g. to send it over FFI.
+    pub fn to_bits(&self) -> u64 {
+        (self.generation as u64) << 32 | self.index as u64
+    }
+
+    pub fn from_bits(bits: u64) -> Self {
+        Self {
+            index: bits as u32,
+            generation: (bits >> 32) as u32,
+        }
+    }
+}
+
+impl fmt::Debug for Entity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Entity({}v{})", self.index, self.generation)
+    }
+}
+
+/// Data attached to entities. Any type can be a component.
+pub trait Component: 'static {}
+
+impl<T: 'static> Component for T {}
+
+/// Allocates entity ids, reusing the slots of despawned entities.
+#[derive(Debug, Default)]
+struct Entities {
+    generations: Vec<u32>,
+    alive: Vec<bool>,
+    free: Vec<u32>,
+    len: usize,
+}
+
+impl Entities {
+    fn alloc(&mut self) -> Entity {
+        self.len += 1;
+        match self.free.pop() {
+            Some(index) => {
+                self.alive[index as usize] = true;
+                Entity {
+                    index,
+                    generation: self.generations[index as usize],
+                }
+            }
+            None => {
+                self.generations.push(0);
+                self.alive.push(true);
+                Entity {
+                    index: self.generations.len() as u32 - 1,
+                    generation: 0,
+                }
+            }
+        }
+    }
+
+    fn contains(&self, entity: Entity) -> bool {
+        let index = entity.index as usize;
+        self.alive.get(index) == Some(&true) && self.generations[index] == entity.generation
+    }
+
+    fn free(&mut self, entity: Entity) -> bool {
+        if !self.contains(entity) {
+            return false;
+        }
+        let index = entity.index as usize;
+        self.alive[index] = false;
+        self.generations[index] = self.generations[index].wrapping_add(1);
+        self.free.push(entity.index);
+        self.len -= 1;
+        true
+    }
+
+    /// Returns the entity alive in a slot, if any.
+    fn get(&self, index: usize) -> Option<Entity> {
+        self.alive
+            .get(index)
+            .copied()
+            .unwrap_or(false)
+            .then(|| Entity {
+                index: index as u32,
+                generation: self.generations[index],
+            })
+    }
+}
+
+/// Marks a slot of the sparse array without component.
+const EMPTY: u32 = u32::MAX;
+
+/// Components of a type, packed in insertion order, swapped on removal.
+#[doc(hidden)]
+pub struct SparseSet<T> {
+    /// Index in `dense` of the component of each entity slot.
+    sparse: Vec<u32>,
+    dense: Vec<T>,
+    /// Entity owning each component of `dense`.
+    entities: Vec<Entity>,
+}
+
+impl<T> SparseSet<T> {
+    fn new() -> Self {
+        Self {
+            sparse: Vec::new(),
+            dense: Vec::new(),
+            entities: Vec::new(),
+        }
+    }
+
+    /// Returns the index of the component of an entity in `dense`.
+    ///
+    /// NOTE: only the sparse array and the entities are read, so that it can
+    ///       be called while components are borrowed mutably.
+    unsafe fn index(set: *const Self, entity: Entity) -> Option<usize> {
+        let sparse = &*ptr::addr_of!((*set).sparse);
+        let entities = &*ptr::addr_of!((*set).entities);
+        let index = *sparse.get(entity.index as usize)?;
+        (index != EMPTY && entities[index as usize] == entity).then_some(index as usize)
+    }
+
+    fn get(&self, entity: Entity) -> Option<&T> {
+        let index = unsafe { Self::index(self, entity) }?;
+        Some(&self.dense[index])
+    }
+
+    fn get_mut(&mut self, entity: Entity) -> Option<&mut T> {
+        let index = unsafe { Self::index(self, entity) }?;
+        Some(&mut self.dense[index])
+    }
+
+    fn insert(&mut self, entity: Entity, component: T) -> Option<T> {
+        if let Some(previous) = self.get_mut(entity) {
+            return Some(std::mem::replace(previous, component));
+        }
+        let slot = entity.index as usize;
+        if self.sparse.len() <= slot {
+            self.sparse.resize(slot + 1, EMPTY);
+        }
+        self.sparse[slot] = self.dense.len() as u32;
+        self.dense.push(component);
+        self.entities.push(entity);
+        None
+    }
+
+    fn remove(&mut self, entity: Entity) -> Option<T> {
+        let index = unsafe { Self::index(self, entity) }?;
+        self.sparse[entity.index as usize] = EMPTY;
+        let component = self.dense.swap_remove(index);
+        self.entities.swap_remove(index);
+        if let Some(moved) = self.entities.get(index) {
+            self.sparse[moved.index as usize] = index as u32;
+        }
+        Some(component)
+    }
+}
+
+/// Storage of a component type, type erased.
+trait Storage {
+    fn remove_entity(&mut self, entity: Entity);
+    fn clear(&mut self);
+    fn as_any(&self) -> &dyn Any;
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+}
+
+/// Sparse set that queries can borrow mutably through a shared world.
+///
+/// NOTE: components are only mutated through `&mut World`, either directly
+///       or by a mutable query checked not to borrow a type twice.
+struct ComponentStorage<T>(UnsafeCell<SparseSet<T>>);
+
+impl<T: Component> Storage for ComponentStorage<T> {
+    fn remove_entity(&mut self, entity: Entity) {
+        self.0.get_mut().remove(entity);
+    }
+
+    fn clear(&mut self) {
+        let set = self.0.get_mut();
+        set.sparse.clear();
+        set.dense.clear();
+        set.entities.clear();
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+/// Entities and their components.
+#[derive(Default)]
+pub struct World {
+    entities: Entities,
+    storages: HashMap<TypeId, Box<dyn Storage>>,
+}
+
+impl fmt::Debug for World {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("World")
+            .field("entities", &self.entities.len)
+            .field("component_types", &self.storages.len())
+            .finish()
+    }
+}
+
+impl World {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Returns the number of entities.
+    pub fn len(&self) -> usize {
+        self.entities.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entities.len == 0
+    }
+
+    /// Creates an entity with the components of a bundle.
+    pub fn spawn(&mut self, bundle: impl Bundle) -> Entity {
+        let entity = self.entities.alloc();
+        bundle.insert(self, entity);
+        entity
+    }
+
+    /// Removes an entity and its components. Returns false if it was already
+    /// despawned.
+    pub fn despawn(&mut self, entity: Entity) -> bool {
+        if !self.entities.free(entity) {
+            return false;
+        }
+        for storage in self.storages.values_mut() {
+            storage.remove_entity(entity);
+        }
+        true
+    }
+
+    /// Despawns every entity, invalidating their ids.
+    pub fn clear(&mut self) {
+        for index in 0..self.entities.alive.len() {
+            if let Some(entity) = self.entities.get(index) {
+                self.entities.free(entity);
+            }
+        }
+        for storage in self.storages.values_mut() {
+            storage.clear();
+        }
+    }
+
+    pub fn contains(&self, entity: Entity) -> bool {
+        self.entities.contains(entity)
+    }
+
+    /// Iterates the entities in slot order.
+    pub fn entities(&self) -> impl Iterator<Item = Entity> + '_ {
+        (0..self.entities.alive.len()).filter_map(|index| self.entities.get(index))
+    }
+
+    /// Attaches a component to an entity, replacing the one of the same type.
+    /// Returns false if the entity was despawned.
+    pub fn insert<T: Component>(&mut self, entity: Entity, component: T) -> bool {
+        if !self.contains(entity) {
+            return false;
+        }
+        self.storage_mut::<T>().insert(entity, component);
+        true
+    }
+
+    /// Detaches a component from an entity.
+    pub fn remove<T: Component>(&mut self, entity: Entity) -> Option<T> {
+        self.storages
+            .get_mut(&TypeId::of::<T>())?
+            .as_any_mut()
+            .downcast_mut::<ComponentStorage<T>>()?
+            .0
+            .get_mut()
+            .remove(entity)
+    }
+
+    pub fn has<T: Component>(&self, entity: Entity) -> bool {
+        self.get::<T>(entity).is_some()
+    }
+
+    pub fn get<T: Component>(&self, entity: Entity) -> Option<&T> {
+        // SAFETY: components are not mutated while the world is borrowed
+        unsafe { &*self.storage::<T>()?.0.get() }.get(entity)
+    }
+
+    pub fn get_mut<T: Component>(&mut self, entity: Entity) -> Option<&mut T> {
+        self.storages
+            .get_mut(&TypeId::of::<T>())?
+            .as_any_mut()
+            .downcast_mut::<ComponentStorage<T>>()?
+            .0
+            .get_mut()
+            .get_mut(entity)
+    }
+
+    /// Iterates the entities matching a query that only reads components.
+    pub fn query<Q: ReadOnlyQuery>(&self) -> QueryIter<'_, Q> {
+        QueryIter::new(self)
+    }
+
+    /// Iterates the entities matching a query, which can write components.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the query borrows a component type mutably more than once,
+    /// e.g. `(&mut Transform, &Transform)`.
+    pub fn query_mut<Q: Query>(&mut self) -> QueryIter<'_, Q> {
+        check_borrows::<Q>();
+        QueryIter::new(self)
+    }
+
+    /// Returns the components of an entity matching a query that only reads
+    /// components, or None if the entity was despawned or does not match.
+    pub fn query_one<Q: ReadOnlyQuery>(&self, entity: Entity) -> Option<Q::Item<'_>> {
+        if !self.contains(entity) {
+            return None;
+        }
+        // SAFETY: the world is borrowed for the lifetime of the item
+        unsafe { Q::get(Q::fetch(self)?, entity) }
+    }
+
+    /// Returns the components of an entity matching a query, which can write
+    /// components, or None if the entity was despawned or does not match.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the query borrows a component type mutably more than once,
+    /// see `query_mut()`.
+    pub fn query_one_mut<Q: Query>(&mut self, entity: Entity) -> Option<Q::Item<'_>> {
+        check_borrows::<Q>();
+        if !self.contains(entity) {
+            return None;
+        }
+        // SAFETY: the world is borrowed mutably for the lifetime of the item
+        unsafe { Q::get(Q::fetch(self)?, entity) }
+    }
+
+    fn storage<T: Component>(&self) -> Option<&ComponentStorage<T>> {
+        self.storages
+            .get(&TypeId::of::<T>())?
+            .as_any()
+            .downcast_ref::<ComponentStorage<T>>()
+    }
+
+    fn storage_mut<T: Component>(&mut self) -> &mut SparseSet<T> {
+        self.storages
+            .entry(TypeId::of::<T>())
+            .or_insert_with(|| Box::new(ComponentStorage(UnsafeCell::new(SparseSet::<T>::new()))))
+            .as_any_mut()
+            .downcast_mut::<ComponentStorage<T>>()
+            .expect("storage of its component type")
+            .0
+            .get_mut()
+    }
+}
+
+/// Panics if a query borrows a component type mutably more than once.
+fn check_borrows<Q: Query>() {
+    let mut borrows = Vec::new();
+    Q::borrows(&mut borrows);
+    for (i, (type_id, mutable)) in borrows.iter().enumerate() {
+        let aliased = borrows[..i]
+            .iter()
+            .any(|(other, other_mutable)| other == type_id && (*mutable || *other_mutable));
+        assert!(!aliased, "query borrows a component mutably twice");
+    }
+}
+
+/// Components spawned together, see `World::spawn()`. Implemented for tuples
+/// of components.
+pub trait Bundle {
+    fn insert(self, world: &mut World, entity: Entity);
+}
+
+/// Set of components fetched for each matching entity by a query.
+///
+/// Implemented for `&T`, `&mut T`, `Option<&T>`, `Option<&mut T>`, `Entity`,
+/// the `With<T>` and `Without<T>` filters, and tuples of these.
+///
+/// # Safety
+///
+/// `borrows()` must report every component type accessed by `get()`, and
+/// whether it is written.
+pub unsafe trait Query {
+    type Item<'w>;
+
+    #[doc(hidden)]
+    type Fetch: Copy;
+
+    /// Returns None if no entity can match, e.g. when a required component
+    /// type was never inserted.
+    #[doc(hidden)]
+    fn fetch(world: &World) -> Option<Self::Fetch>;
+
+    /// Returns the entities having a required component, to iterate instead
+    /// of every entity.
+    #[doc(hidden)]
+    unsafe fn candidates<'w>(_fetch: Self::Fetch) -> Option<&'w [Entity]> {
+        None
+    }
+
+    #[doc(hidden)]
+    unsafe fn get<'w>(fetch: Self::Fetch, entity: Entity) -> Option<Self::Item<'w>>;
+
+    #[doc(hidden)]
+    fn borrows(_borrows: &mut Vec<(TypeId, bool)>) {}
+}
+
+/// Query that does not write components, see `World::query()`.
+///
+/// # Safety
+///
+/// `Query::get()` must not write components.
+pub unsafe trait ReadOnlyQuery: Query {}
+
+unsafe impl Query for Entity {
+    type Item<'w> = Entity;
+    type Fetch = ();
+
+    fn fetch(_: &World) -> Option<Self::Fetch> {
+        Some(())
+    }
+
+    unsafe fn get<'w>(_: Self::Fetch, entity: Entity) -> Option<Self::Item<'w>> {
+        Some(entity)
+    }
+}
+
+unsafe impl ReadOnlyQuery for Entity {}
+
+/// Returns the sparse set of a component type, to be accessed through raw
+/// pointers by queries.
+fn sparse_set<T: Component>(world: &World) -> Option<*mut SparseSet<T>> {
+    world.storage::<T>().map(|storage| storage.0.get())
+}
+
+unsafe impl<T: Component> Query for &T {
+    type Item<'w> = &'w T;
+    type Fetch = *mut SparseSet<T>;
+
+    fn fetch(world: &World) -> Option<Self::Fetch> {
+        sparse_set::<T>(world)
+    }
+
+    unsafe fn candidates<'w>(fetch: Self::Fetch) -> Option<&'w [Entity]> {
+        Some(&*ptr::addr_of!((*fetch).entities))
+    }
+
+    unsafe fn get<'w>(fetch: Self::Fetch, entity: Entity) -> Option<Self::Item<'w>> {
+        let index = SparseSet::index(fetch, entity)?;
+        let dense = &*ptr::addr_of!((*fetch).dense);
+        Some(&*dense.as_ptr().add(index))
+    }
+
+    fn borrows(borrows: &mut Vec<(TypeId, bool)>) {
+        borrows.push((TypeId::of::<T>(), false));
+    }
+}
+
+unsafe impl<T: Component> ReadOnlyQuery for &T {}
+
+unsafe impl<T: Component> Query for &mut T {
+    type Item<'w> = &'w mut T;
+    type Fetch = *mut SparseSet<T>;
+
+    fn fetch(world: &World) -> Option<Self::Fetch> {
+        sparse_set::<T>(world)
+    }
+
+    unsafe fn candidates<'w>(fetch: Self::Fetch) -> Option<&'w [Entity]> {
+        Some(&*ptr::addr_of!((*fetch).entities))
+    }
+
+    unsafe fn get<'w>(fetch: Self::Fetch, entity: Entity) -> Option<Self::Item<'w>> {
+        // NOTE: the components yielded before stay borrowed, only the
+        //       component of the entity is borrowed mutably
+        let index = SparseSet::index(fetch, entity)?;
+        let dense = ptr::addr_of_mut!((*fetch).dense);
+        Some(&mut *(*dense).as_mut_ptr().add(index))
+    }
+
+    fn borrows(borrows: &mut Vec<(TypeId, bool)>) {
+        borrows.push((TypeId::of::<T>(), true));
+    }
+}
+
+unsafe impl<Q: Query> Query for Option<Q> {
+    type Item<'w> = Option<Q::Item<'w>>;
+    type Fetch = Option<Q::Fetch>;
+
+    fn fetch(world: &World) -> Option<Self::Fetch> {
+        Some(Q::fetch(world))
+    }
+
+    unsafe fn get<'w>(fetch: Self::Fetch, entity: Entity) -> Option<Self::Item<'w>> {
+        Some(fetch.and_then(|fetch| Q::get(fetch, entity)))
+    }
+
+    fn borrows(borrows: &mut Vec<(TypeId, bool)>) {
+        Q::borrows(borrows);
+    }
+}
+
+unsafe impl<Q: ReadOnlyQuery> ReadOnlyQuery for Option<Q> {}
+
+/// Filter matching the entities having a component, without reading it.
+pub struct With<T>(PhantomData<T>);
+
+unsafe impl<T: Component> Query for With<T> {
+    type Item<'w> = ();
+    type Fetch = *mut SparseSet<T>;
+
+    fn fetch(world: &World) -> Option<Self::Fetch> {
+        sparse_set::<T>(world)
+    }
+
+    unsafe fn candidates<'w>(fetch: Self::Fetch) -> Option<&'w [Entity]> {
+        Some(&*ptr::addr_of!((*fetch).entities))
+    }
+
+    unsafe fn get<'w>(fetch: Self::Fetch, entity: Entity) -> Option<Self::Item<'w>> {
+        SparseSet::index(fetch, entity).map(|_| ())
+    }
+}
+
+unsafe impl<T: Component> ReadOnlyQuery for With<T> {}
+
+/// Filter matching the entities not having a component.
+pub struct Without<T>(PhantomData<T>);
+
+unsafe impl<T: Component> Query for Without<T> {
+    type Item<'w> = ();
+    type Fetch = Option<*mut SparseSet<T>>;
+
+    fn fetch(world: &World) -> Option<Self::Fetch> {
+        Some(sparse_set::<T>(world))
+    }
+
+    unsafe fn get<'w>(fetch: Self::Fetch, entity: Entity) -> Option<Self::Item<'w>> {
+        match fetch {
+            Some(fetch) if SparseSet::index(fetch, entity).is_some() => None,
+            _ => Some(()),
+        }
+    }
+}
+
+unsafe impl<T: Component> ReadOnlyQuery for Without<T> {}
+
+macro_rules! tuple_impls {
+    ($($name:ident)+) => {
+        #[allow(non_snake_case)]
+        unsafe impl<$($name: Query),+> Query for ($($name,)+) {
+            type Item<'w> = ($($name::Item<'w>,)+);
+            type Fetch = ($($name::Fetch,)+);
+
+            fn fetch(world: &World) -> Option<Self::Fetch> {
+                Some(($($name::fetch(world)?,)+))
+            }
+
+            unsafe fn candidates<'w>(fetch: Self::Fetch) -> Option<&'w [Entity]> {
+                let ($($name,)+) = fetch;
+                // iterate the smallest set of required components
+                [$($name::candidates($name)),+]
+                    .into_iter()
+                    .flatten()
+                    .min_by_key(|entities| entities.len())
+            }
+
+            unsafe fn get<'w>(fetch: Self::Fetch, entity: Entity) -> Option<Self::Item<'w>> {
+                let ($($name,)+) = fetch;
+                Some(($($name::get($name, entity)?,)+))
+            }
+
+            fn borrows(borrows: &mut Vec<(TypeId, bool)>) {
+                $($name::borrows(borrows);)+
+            }
+        }
+
+        unsafe impl<$($name: ReadOnlyQuery),+> ReadOnlyQuery for ($($name,)+) {}
+
+        #[allow(non_snake_case)]
+        impl<$($name: Component),+> Bundle for ($($name,)+) {
+            fn insert(self, world: &mut World, entity: Entity) {
+                let ($($name,)+) = self;
+                $(world.insert(entity, $name);)+
+            }
+        }
+    };
+}
+
+tuple_impls!(A);
+tuple_impls!(A B);
+tuple_impls!(A B C);
+tuple_impls!(A B C D);
+tuple_impls!(A B C D E);
+tuple_impls!(A B C D E F);
+tuple_impls!(A B C D E F G);
+tuple_impls!(A B C D E F G H);
+
+/// Entities to test against a query.
+enum Candidates<'w> {
+    /// Entities having a component required by the query.
+    Set(slice::Iter<'w, Entity>),
+    /// Every entity, from the slot of the next one.
+    All(&'w World, usize),
+}
+
+/// Iterator over the entities matching a query, see `World::query()`.
+pub struct QueryIter<'w, Q: Query> {
+    fetch: Option<Q::Fetch>,
+    candidates: Candidates<'w>,
+}
+
+impl<'w, Q: Query> QueryIter<'w, Q> {
+    fn new(world: &'w World) -> Self {
+        let fetch = Q::fetch(world);
+        let candidates = match fetch.and_then(|fetch| unsafe { Q::candidates(fetch) }) {
+            Some(entities) => Candidates::Set(entities.iter()),
+            None => Candidates::All(world, 0),
+        };
+        Self { fetch, candidates }
+    }
+}
+
+impl<'w, Q: Query> Iterator for QueryIter<'w, Q> {
+    type Item = Q::Item<'w>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let fetch = self.fetch?;
+        loop {
+            let entity = match &mut self.candidates {
+                Candidates::Set(entities) => *entities.next()?,
+                Candidates::All(world, index) => {
+                    let entities = &world.entities;
+                    let entity = (*index..entities.alive.len()).find_map(|i| entities.get(i))?;
+                    *index = entity.index as usize + 1;
+                    entity
+                }
+            };
+            // SAFETY: the world is borrowed for 'w, mutably if the query
+            //         writes components, and each entity is yielded once
+            if let Some(item) = unsafe { Q::get(fetch, entity) } {
+                return Some(item);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, PartialEq)]
+    struct Position(i32);
+
+    #[derive(Debug, PartialEq)]
+    struct Velocity(i32);
+
+    #[test]
+    fn despawned_ids_are_stale() {
+        let mut world = World::new();
+        let a = world.spawn((Position(1),));
+        assert!(world.despawn(a));
+        assert!(!world.despawn(a));
+
+        let b = world.spawn((Position(2),));
+        assert_eq!(a.index(), b.index());
+        assert_eq!(world.get::<Position>(a), None);
+        assert!(!world.insert(a, Velocity(0)));
+        assert_eq!(world.get::<Position>(b), Some(&Position(2)));
+        assert_eq!(world.len(), 1);
+        assert_eq!(Entity::from_bits(b.to_bits()), b);
+    }
+
+    #[test]
+    fn queries_match_required_components() {
+        let mut world = World::new();
+        let a = world.spawn((Position(0), Velocity(1)));
+        let b = world.spawn((Position(10),));
+        let c = world.spawn((Position(20), Velocity(2)));
+
+        for (position, velocity) in world.query_mut::<(&mut Position, &Velocity)>() {
+            position.0 += velocity.0;
+        }
+        let positions: Vec<_> = world.query::<(Entity, &Position)>().collect();
+        assert_eq!(
+            positions,
+            [(a, &Position(1)), (b, &Position(10)), (c, &Position(22))]
+        );
+
+        let still: Vec<_> = world
+            .query::<(Entity, Without<Velocity>)>()
+            .map(|(entity, _)| entity)
+            .collect();
+        assert_eq!(still, [b]);
+
+        let velocities: Vec<_> = world
+            .query::<(Option<&Velocity>, With<Position>)>()
+            .map(|(velocity, _)| velocity.map(|v| v.0))
+            .collect();
+        assert_eq!(velocities, [Some(1), None, Some(2)]);
+    }
+
+    #[test]
+    fn single_entities_can_be_queried() {
+        let mut world = World::new();
+        let a = world.spawn((Position(0), Velocity(1)));
+        let b = world.spawn((Position(10),));
+
+        let (position, velocity) = world
+            .query_one_mut::<(&mut Position, &Velocity)>(a)
+            .unwrap();
+        position.0 += velocity.0;
+        assert_eq!(world.query_one::<&Position>(a), Some(&Position(1)));
+        assert!(world.query_one::<(&Position, &Velocity)>(b).is_none());
+        assert_eq!(
+            world.query_one::<(Entity, Option<&Velocity>)>(b),
+            Some((b, None))
+        );
+
+        world.despawn(b);
+        assert!(world.query_one::<Entity>(b).is_none());
+    }
+
+    #[test]
+    fn removal_keeps_other_components() {
+        let mut world = World::new();
+        let entities: Vec<_> = (0..4).map(|i| world.spawn((Position(i),))).collect();
+        assert_eq!(world.remove::<Position>(entities[1]), Some(Position(1)));
+        world.despawn(entities[0]);
+
+        let mut positions: Vec<_> = world.query::<&Position>().map(|p| p.0).collect();
+        positions.sort();
+        assert_eq!(positions, [2, 3]);
+        assert_eq!(world.get::<Position>(entities[3]), Some(&Position(3)));
+        assert!(world.contains(entities[1]));
+
+        world.clear();
+        assert!(world.is_empty());
+        assert_eq!(world.query::<&Position>().count(), 0);
+        assert!(!world.contains(entities[3]));
+    }
+
+    #[test]
+    #[should_panic(expected = "query borrows a component mutably twice")]
+    fn aliased_queries_panic() {
+        let mut world = World::new();
+        world.spawn((Position(0),));
+        world.query_mut::<(&mut Position, &Position)>().count();
+    }
+}
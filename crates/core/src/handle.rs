@@ -0,0 +1,296 @@
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::marker::PhantomData;
+
+/// A typed handle into a `HandleMap<T>`.
+///
+/// Handles are made of a slot index and a generation. The generation is bumped
+/// every time a slot is freed, which means a handle to a removed value is
+/// detected as stale instead of silently aliasing whatever value reuses the
+/// slot.
+pub struct Handle<T> {
+    index: u32,
+    generation: u32,
+    _marker: PhantomData<fn() -> T>,
+}
+
+impl<T> Handle<T> {
+    const fn new(index: u32, generation: u32) -> Self {
+        Self {
+            index,
+            generation,
+            _marker: PhantomData,
+        }
+    }
+
+    pub fn index(&self) -> u32 {
+        self.index
+    }
+
+    pub fn generation(&self) -> u32 {
+        self.generation
+    }
+
+    /// Packs the handle into a single integer, e.g. to hand it to an API that
+    /// only knows about opaque ids (imgui TextureId).
+    pub fn to_bits(&self) -> u64 {
+        (self.generation as u64) << 32 | self.index as u64
+    }
+
+    /// Unpacks a handle previously packed using `to_bits()`.
+    pub fn from_bits(bits: u64) -> Self {
+        Self::new(bits as u32, (bits >> 32) as u32)
+    }
+}
+
+// NOTE: implemented by hand so that T is not required to implement these traits.
+impl<T> Clone for Handle<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Copy for Handle<T> {}
+
+impl<T> PartialEq for Handle<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.index == other.index && self.generation == other.generation
+    }
+}
+
+impl<T> Eq for Handle<T> {}
+
+impl<T> Hash for Handle<T> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.to_bits().hash(state)
+    }
+}
+
+impl<T> fmt::Debug for Handle<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Handle({}v{})", self.index, self.generation)
+    }
+}
+
+#[derive(Debug)]
+struct Slot<T> {
+    generation: u32,
+    value: Option<T>,
+}
+
+/// Stores values addressed by generational `Handle<T>`.
+///
+/// Slots of removed values are reused in LIFO order, which keeps handle
+/// allocation deterministic for a given sequence of inserts and removals.
+#[derive(Debug)]
+pub struct HandleMap<T> {
+    slots: Vec<Slot<T>>,
+    free: Vec<u32>,
+    len: usize,
+}
+
+impl<T> Default for HandleMap<T> {
+    fn default() -> Self {
+        Self {
+            slots: Vec::new(),
+            free: Vec::new(),
+            len: 0,
+        }
+    }
+}
+
+impl<T> HandleMap<T> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn insert(&mut self, value: T) -> Handle<T> {
+        self.len += 1;
+        match self.free.pop() {
+            Some(index) => {
+                let slot = &mut self.slots[index as usize];
+                slot.value = Some(value);
+                Handle::new(index, slot.generation)
+            }
+            None => {
+                let index = self.slots.len() as u32;
+                self.slots.push(Slot {
+                    generation: 0,
+                    value: Some(value),
+                });
+                Handle::new(index, 0)
+            }
+        }
+    }
+
+    /// Returns true if the handle points to a live value.
+    pub fn contains(&self, handle: Handle<T>) -> bool {
+        self.get(handle).is_some()
+    }
+
+    /// Returns the value for this handle, or None if the handle is stale.
+    pub fn get(&self, handle: Handle<T>) -> Option<&T> {
+        self.slots
+            .get(handle.index as usize)
+            .filter(|slot| slot.generation == handle.generation)
+            .and_then(|slot| slot.value.as_ref())
+    }
+
+    /// Returns the value for this handle, or None if the handle is stale.
+    pub fn get_mut(&mut self, handle: Handle<T>) -> Option<&mut T> {
+        self.slots
+            .get_mut(handle.index as usize)
+            .filter(|slot| slot.generation == handle.generation)
+            .and_then(|slot| slot.value.as_mut())
+    }
+
+    /// Removes and returns the value for this handle, or None if the handle is
+    /// stale. Every handle to this slot becomes stale afterwards.
+    pub fn remove(&mut self, handle: Handle<T>) -> Option<T> {
+        let slot = self
+            .slots
+            .get_mut(handle.index as usize)
+            .filter(|slot| slot.generation == handle.generation)?;
+        let value = slot.value.take()?;
+        slot.generation = slot.generation.wrapping_add(1);
+        self.free.push(handle.index);
+        self.len -= 1;
+        Some(value)
+    }
+
+    /// Removes all values, invalidating every handle.
+    pub fn clear(&mut self) {
+        self.drain();
+    }
+
+    /// Iterates live values and their handles in slot order.
+    pub fn iter(&self) -> impl Iterator<Item = (Handle<T>, &T)> {
+        self.slots.iter().enumerate().filter_map(|(index, slot)| {
+            slot.value
+                .as_ref()
+                .map(|value| (Handle::new(index as u32, slot.generation), value))
+        })
+    }
+
+    /// Iterates live values mutably and their handles in slot order.
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = (Handle<T>, &mut T)> {
+        self.slots
+            .iter_mut()
+            .enumerate()
+            .filter_map(|(index, slot)| {
+                slot.value
+                    .as_mut()
+                    .map(|value| (Handle::new(index as u32, slot.generation), value))
+            })
+    }
+
+    /// Iterates live values in slot order.
+    pub fn values(&self) -> impl Iterator<Item = &T> {
+        self.slots.iter().filter_map(|slot| slot.value.as_ref())
+    }
+
+    /// Iterates live values mutably in slot order.
+    pub fn values_mut(&mut self) -> impl Iterator<Item = &mut T> {
+        self.slots.iter_mut().filter_map(|slot| slot.value.as_mut())
+    }
+
+    /// Removes all values and returns them in slot order, invalidating every
+    /// handle.
+    pub fn drain(&mut self) -> std::vec::IntoIter<T> {
+        let mut values = Vec::with_capacity(self.len);
+        for (index, slot) in self.slots.iter_mut().enumerate() {
+            if let Some(value) = slot.value.take() {
+                slot.generation = slot.generation.wrapping_add(1);
+                self.free.push(index as u32);
+                values.push(value);
+            }
+        }
+        self.len = 0;
+        values.into_iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn insert_get() {
+        let mut map = HandleMap::new();
+        let a = map.insert("a");
+        let b = map.insert("b");
+        assert_eq!(map.get(a), Some(&"a"));
+        assert_eq!(map.get(b), Some(&"b"));
+        assert_eq!(map.len(), 2);
+    }
+
+    #[test]
+    fn stale_handle() {
+        let mut map = HandleMap::new();
+        let a = map.insert("a");
+        assert_eq!(map.remove(a), Some("a"));
+        let b = map.insert("b");
+        // the slot is reused but the old handle must not alias the new value
+        assert_eq!(a.index(), b.index());
+        assert_eq!(map.get(a), None);
+        assert_eq!(map.remove(a), None);
+        assert_eq!(map.get(b), Some(&"b"));
+    }
+
+    #[test]
+    fn iter_mut_yields_live_handles() {
+        let mut map = HandleMap::new();
+        let a = map.insert(1);
+        let b = map.insert(2);
+        map.remove(a);
+        let c = map.insert(3);
+        for (_, value) in map.iter_mut() {
+            *value *= 10;
+        }
+        let handles: Vec<_> = map.iter_mut().map(|(handle, _)| handle).collect();
+        assert_eq!(handles, [c, b]);
+        assert_eq!(map.get(b), Some(&20));
+        assert_eq!(map.get(c), Some(&30));
+    }
+
+    #[test]
+    fn clear_invalidates() {
+        let mut map = HandleMap::new();
+        let a = map.insert(1);
+        map.clear();
+        assert!(map.is_empty());
+        assert!(!map.contains(a));
+        let b = map.insert(2);
+        assert_ne!(a, b);
+    }
+
+    #[test]
+    fn deterministic_allocation() {
+        let run = || {
+            let mut map = HandleMap::new();
+            let a = map.insert(0);
+            let b = map.insert(1);
+            map.remove(a);
+            map.remove(b);
+            (map.insert(2), map.insert(3))
+        };
+        assert_eq!(run(), run());
+    }
+
+    #[test]
+    fn bits_roundtrip() {
+        let mut map = HandleMap::new();
+        let a = map.insert(());
+        map.remove(a);
+        let b = map.insert(());
+        assert_eq!(Handle::<()>::from_bits(b.to_bits()), b);
+    }
+}
@@ -0,0 +1,328 @@
+//! Pool of worker threads running jobs in parallel, e.g. to build the quad
+//! batches of a frame or to decode assets.
+//!
+//! Each worker has its own queue, to which the jobs it spawns are added, and
+//! steals the oldest jobs of the other queues once its own is empty. Jobs are
+//! spawned within a scope, which waits for them to finish, so that they can
+//! borrow from the stack of the frame. The thread waiting on a scope runs jobs
+//! meanwhile: a pool without workers runs every job on the calling thread.
+
+use std::any::Any;
+use std::cell::Cell;
+use std::collections::VecDeque;
+use std::marker::PhantomData;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard, PoisonError};
+use std::{fmt, mem, thread};
+
+use log::error;
+
+type Job = Box<dyn FnOnce() + Send + 'static>;
+
+thread_local! {
+    /// Pool and queue of the worker running on the thread, if any.
+    static WORKER: Cell<Option<(usize, usize)>> = const { Cell::new(None) };
+}
+
+/// Returns the number of workers keeping every core busy, along with the
+/// thread waiting on the jobs.
+pub fn default_workers() -> usize {
+    thread::available_parallelism().map_or(0, |cores| cores.get() - 1)
+}
+
+/// Locks a mutex, ignoring poisoning: jobs never run while holding a lock.
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// State shared by the pool, its workers and its scopes.
+struct Shared {
+    /// Queue of each worker, then the queue of the jobs spawned by other
+    /// threads.
+    queues: Vec<Mutex<VecDeque<Job>>>,
+    /// Number of jobs in the queues.
+    queued: AtomicUsize,
+    sleep: Mutex<()>,
+    wake: Condvar,
+    shutdown: AtomicBool,
+}
+
+impl Shared {
+    fn id(&self) -> usize {
+        self as *const Self as usize
+    }
+
+    /// Returns the queue of the current thread.
+    fn queue(&self) -> usize {
+        match WORKER.with(Cell::get) {
+            Some((pool, queue)) if pool == self.id() => queue,
+            _ => self.queues.len() - 1,
+        }
+    }
+
+    fn push(&self, job: Job) {
+        lock(&self.queues[self.queue()]).push_back(job);
+        self.queued.fetch_add(1, Ordering::SeqCst);
+        // NOTE: the lock orders the wake up after the check of a worker about
+        //       to sleep
+        let _sleep = lock(&self.sleep);
+        self.wake.notify_one();
+    }
+
+    /// Pops the newest job of a queue, or steals the oldest one of another
+    /// queue.
+    fn pop(&self, queue: usize) -> Option<Job> {
+        // NOTE: a single queue is locked at a time, as threads steal from
+        //       each other
+        let own = lock(&self.queues[queue]).pop_back();
+        let job = own.or_else(|| {
+            (1..self.queues.len())
+                .map(|i| (queue + i) % self.queues.len())
+                .find_map(|i| lock(&self.queues[i]).pop_front())
+        })?;
+        self.queued.fetch_sub(1, Ordering::SeqCst);
+        Some(job)
+    }
+
+    fn run_worker(&self, queue: usize) {
+        WORKER.with(|worker| worker.set(Some((self.id(), queue))));
+        loop {
+            if let Some(job) = self.pop(queue) {
+                job();
+                continue;
+            }
+            let sleep = lock(&self.sleep);
+            if self.shutdown.load(Ordering::SeqCst) {
+                break;
+            }
+            if self.queued.load(Ordering::SeqCst) == 0 {
+                drop(self.wake.wait(sleep));
+            }
+        }
+    }
+}
+
+pub struct JobPool {
+    shared: Arc<Shared>,
+    workers: Vec<thread::JoinHandle<()>>,
+}
+
+impl JobPool {
+    /// Starts a pool of worker threads, e.g. `default_workers()` of them.
+    /// Workers failing to start are logged, the pool runs without them.
+    pub fn new(workers: usize) -> Self {
+        let shared = Arc::new(Shared {
+            queues: (0..=workers).map(|_| Default::default()).collect(),
+            queued: AtomicUsize::new(0),
+            sleep: Mutex::new(()),
+            wake: Condvar::new(),
+            shutdown: AtomicBool::new(false),
+        });
+        let mut threads = Vec::with_capacity(workers);
+        for queue in 0..workers {
+            let shared = Arc::clone(&shared);
+            let spawned = thread::Builder::new()
+                .name(format!("job worker {queue}"))
+                .spawn(move || shared.run_worker(queue));
+            match spawned {
+                Ok(thread) => threads.push(thread),
+                Err(e) => {
+                    error!("spawn job worker: {e}");
+                    break;
+                }
+            }
+        }
+        Self {
+            shared,
+            workers: threads,
+        }
+    }
+
+    /// Returns the number of worker threads.
+    pub fn workers(&self) -> usize {
+        self.workers.len()
+    }
+
+    /// Calls `f` with a scope to spawn jobs in, and waits for the jobs to
+    /// finish, running jobs meanwhile. Panics of `f` and of the jobs are
+    /// resumed once every job finished.
+    pub fn scope<'env, F, R>(&self, f: F) -> R
+    where
+        F: FnOnce(&Scope<'env>) -> R,
+    {
+        let scope = Scope {
+            shared: Arc::clone(&self.shared),
+            state: Arc::default(),
+            _env: PhantomData,
+        };
+        // NOTE: the jobs are waited for even if `f` panics, as they may borrow
+        //       from its caller
+        let result = panic::catch_unwind(AssertUnwindSafe(|| f(&scope)));
+        scope.wait();
+        if let Some(payload) = lock(&scope.state.panic).take() {
+            panic::resume_unwind(payload);
+        }
+        result.unwrap_or_else(|payload| panic::resume_unwind(payload))
+    }
+
+    /// Calls `f` on every item in parallel, returning the results in order.
+    pub fn map<T, R, F>(&self, items: &[T], f: F) -> Vec<R>
+    where
+        T: Sync,
+        R: Send,
+        F: Fn(&T) -> R + Sync,
+    {
+        let mut results: Vec<Option<R>> = items.iter().map(|_| None).collect();
+        self.scope(|scope| {
+            let f = &f;
+            for (item, result) in items.iter().zip(&mut results) {
+                scope.spawn(move || *result = Some(f(item)));
+            }
+        });
+        results
+            .into_iter()
+            .map(|result| result.expect("job finished"))
+            .collect()
+    }
+}
+
+impl Drop for JobPool {
+    fn drop(&mut self) {
+        {
+            let _sleep = lock(&self.shared.sleep);
+            self.shared.shutdown.store(true, Ordering::SeqCst);
+            self.shared.wake.notify_all();
+        }
+        for worker in self.workers.drain(..) {
+            if worker.join().is_err() {
+                error!("job worker panicked");
+            }
+        }
+    }
+}
+
+impl fmt::Debug for JobPool {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("JobPool")
+            .field("workers", &self.workers.len())
+            .finish()
+    }
+}
+
+#[derive(Default)]
+struct ScopeState {
+    /// Number of jobs spawned and not finished yet.
+    pending: Mutex<usize>,
+    finished: Condvar,
+    /// Payload of the first job that panicked.
+    panic: Mutex<Option<Box<dyn Any + Send>>>,
+}
+
+/// Jobs spawned by `JobPool::scope()`, which may borrow data living for
+/// `'env`.
+pub struct Scope<'env> {
+    shared: Arc<Shared>,
+    state: Arc<ScopeState>,
+    /// Invariant, so that `'env` can not be shortened.
+    _env: PhantomData<&'env mut &'env ()>,
+}
+
+impl<'env> Scope<'env> {
+    /// Queues a job. Jobs spawned by a job are run first by its worker.
+    pub fn spawn<F>(&self, job: F)
+    where
+        F: FnOnce() + Send + 'env,
+    {
+        let state = Arc::clone(&self.state);
+        *lock(&state.pending) += 1;
+        let job: Box<dyn FnOnce() + Send + 'env> = Box::new(move || {
+            if let Err(payload) = panic::catch_unwind(AssertUnwindSafe(job)) {
+                lock(&state.panic).get_or_insert(payload);
+            }
+            let mut pending = lock(&state.pending);
+            *pending -= 1;
+            if *pending == 0 {
+                state.finished.notify_all();
+            }
+        });
+        // SAFETY: the scope waits for its jobs to finish before returning, so
+        //         the job does not outlive the data it borrows
+        let job = unsafe { mem::transmute::<Box<dyn FnOnce() + Send + 'env>, Job>(job) };
+        self.shared.push(job);
+    }
+
+    /// Waits for the jobs of the scope to finish, running jobs meanwhile.
+    fn wait(&self) {
+        let queue = self.shared.queue();
+        loop {
+            if *lock(&self.state.pending) == 0 {
+                return;
+            }
+            if let Some(job) = self.shared.pop(queue) {
+                job();
+                continue;
+            }
+            // NOTE: the remaining jobs are running on workers
+            let pending = lock(&self.state.pending);
+            if *pending > 0 {
+                drop(self.state.finished.wait(pending));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn jobs_borrow_from_the_scope() {
+        for workers in [0, 3] {
+            let pool = JobPool::new(workers);
+            let mut sums = [0; 8];
+            let values: Vec<u32> = (0..800).collect();
+            pool.scope(|scope| {
+                for (chunk, sum) in values.chunks(100).zip(&mut sums) {
+                    scope.spawn(move || *sum = chunk.iter().sum());
+                }
+            });
+            assert_eq!(sums.iter().sum::<u32>(), values.iter().sum());
+
+            // nested scopes run on the workers
+            let counts = pool.map(&[10, 20, 30], |&n| {
+                let count = AtomicUsize::new(0);
+                pool.scope(|scope| {
+                    for _ in 0..n {
+                        scope.spawn(|| {
+                            count.fetch_add(1, Ordering::Relaxed);
+                        });
+                    }
+                });
+                count.into_inner()
+            });
+            assert_eq!(counts, [10, 20, 30]);
+        }
+    }
+
+    #[test]
+    fn panics_are_resumed_after_the_scope() {
+        let pool = JobPool::new(2);
+        let finished = AtomicUsize::new(0);
+        let result = panic::catch_unwind(AssertUnwindSafe(|| {
+            pool.scope(|scope| {
+                scope.spawn(|| panic!("job failed"));
+                for _ in 0..10 {
+                    scope.spawn(|| {
+                        finished.fetch_add(1, Ordering::Relaxed);
+                    });
+                }
+            })
+        }));
+        assert!(result.is_err());
+        assert_eq!(finished.load(Ordering::Relaxed), 10);
+
+        // the workers survived
+        assert_eq!(pool.map(&[1, 2], |n| n * 2), [2, 4]);
+    }
+}
@@ -1,3 +1,11 @@
+pub mod assets;
 pub mod component;
-pub mod debug;
+pub mod ecs;
+pub mod handle;
+pub mod jobs;
+pub mod mesh;
 pub mod object;
+pub mod prefab;
+pub mod sprite;
+pub mod texture;
+pub mod tween;
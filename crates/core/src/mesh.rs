@@ -0,0 +1,198 @@
+use std::path::PathBuf;
+use std::{error, fmt};
+
+use cgmath::{Vector2, Vector3, Vector4};
+
+use crate::handle::Handle;
+
+/// Typed handle to a `Mesh` owned by the engine.
+pub type MeshId = Handle<Mesh>;
+
+/// Indexed triangle list drawn by the 3D renderer, see
+/// `GameObject::with_mesh()`.
+///
+/// Each vertex has a position, a normal and texture coordinates, stored in
+/// separate arrays of the same length.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Mesh {
+    pub positions: Vec<Vector3<f32>>,
+    pub normals: Vec<Vector3<f32>>,
+    pub uvs: Vec<Vector2<f32>>,
+    /// Vertices of the triangles, three per triangle, counter-clockwise when
+    /// seen from the front.
+    pub indices: Vec<u32>,
+}
+
+/// Look of the surface of a mesh, as described by an MTL file.
+///
+/// Meshes are shaded with the diffuse color, see `GameObject::with_material()`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Material {
+    pub name: String,
+    /// Diffuse color, with the opacity of the material in alpha.
+    pub diffuse: Vector4<f32>,
+    pub specular: Vector3<f32>,
+    /// Specular exponent, the higher the sharper the highlights.
+    pub shininess: f32,
+    /// Texture of the diffuse color, relative to the working directory.
+    pub diffuse_map: Option<PathBuf>,
+}
+
+impl Default for Material {
+    fn default() -> Self {
+        Self {
+            name: String::new(),
+            diffuse: Vector4::new(1.0, 1.0, 1.0, 1.0),
+            specular: Vector3::new(0.0, 0.0, 0.0),
+            shininess: 0.0,
+            diffuse_map: None,
+        }
+    }
+}
+
+/// Reason a mesh can not be drawn, see `Mesh::validate()`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MeshError {
+    /// The mesh has no triangle.
+    Empty,
+    /// The normals or texture coordinates do not match the positions.
+    AttributeCount {
+        positions: usize,
+        normals: usize,
+        uvs: usize,
+    },
+    /// The number of indices is not a multiple of 3.
+    PartialTriangle(usize),
+    /// An index does not refer to a vertex.
+    IndexOutOfBounds { index: u32, vertices: usize },
+}
+
+impl fmt::Display for MeshError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Empty => write!(f, "mesh has no triangle"),
+            Self::AttributeCount {
+                positions,
+                normals,
+                uvs,
+            } => write!(
+                f,
+                "mesh has {positions} positions, {normals} normals and {uvs} uvs"
+            ),
+            Self::PartialTriangle(count) => {
+                write!(f, "mesh has {count} indices, not a multiple of 3")
+            }
+            Self::IndexOutOfBounds { index, vertices } => {
+                write!(f, "mesh index {index} is out of its {vertices} vertices")
+            }
+        }
+    }
+}
+
+impl error::Error for MeshError {}
+
+impl Mesh {
+    /// Returns an error if the mesh can not be drawn.
+    pub fn validate(&self) -> Result<(), MeshError> {
+        let vertices = self.positions.len();
+        if self.normals.len() != vertices || self.uvs.len() != vertices {
+            return Err(MeshError::AttributeCount {
+                positions: vertices,
+                normals: self.normals.len(),
+                uvs: self.uvs.len(),
+            });
+        }
+        if self.indices.is_empty() {
+            return Err(MeshError::Empty);
+        }
+        if self.indices.len() % 3 != 0 {
+            return Err(MeshError::PartialTriangle(self.indices.len()));
+        }
+        match self.indices.iter().find(|i| **i as usize >= vertices) {
+            Some(index) => Err(MeshError::IndexOutOfBounds {
+                index: *index,
+                vertices,
+            }),
+            None => Ok(()),
+        }
+    }
+
+    pub fn triangle_count(&self) -> usize {
+        self.indices.len() / 3
+    }
+
+    /// Returns a cube spanning [-1, 1] on each axis, with a normal per face.
+    pub fn cube() -> Self {
+        // normal, then the axes along which the face spans, so that
+        // u x v = normal
+        let faces = [
+            (Vector3::unit_x(), Vector3::unit_y(), Vector3::unit_z()),
+            (-Vector3::unit_x(), Vector3::unit_z(), Vector3::unit_y()),
+            (Vector3::unit_y(), Vector3::unit_z(), Vector3::unit_x()),
+            (-Vector3::unit_y(), Vector3::unit_x(), Vector3::unit_z()),
+            (Vector3::unit_z(), Vector3::unit_x(), Vector3::unit_y()),
+            (-Vector3::unit_z(), Vector3::unit_y(), Vector3::unit_x()),
+        ];
+        let corners = [(-1.0, -1.0), (1.0, -1.0), (1.0, 1.0), (-1.0, 1.0)];
+
+        let mut mesh = Self::default();
+        for (normal, u, v) in faces {
+            let first = mesh.positions.len() as u32;
+            for (x, y) in corners {
+                mesh.positions.push(normal + u * x + v * y);
+                mesh.normals.push(normal);
+                mesh.uvs
+                    .push(Vector2::new((x + 1.0) / 2.0, (1.0 - y) / 2.0));
+            }
+            mesh.indices
+                .extend([0, 1, 2, 2, 3, 0].iter().map(|i| first + i));
+        }
+        mesh
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cgmath::InnerSpace;
+
+    use super::*;
+
+    #[test]
+    fn cube_faces_wind_around_their_normal() {
+        let cube = Mesh::cube();
+        assert_eq!(cube.validate(), Ok(()));
+        assert_eq!(cube.triangle_count(), 12);
+
+        for triangle in cube.indices.chunks(3) {
+            let [a, b, c] = [0, 1, 2].map(|i| cube.positions[triangle[i] as usize]);
+            let normal = cube.normals[triangle[0] as usize];
+            assert_eq!((b - a).cross(c - a).normalize(), normal);
+        }
+    }
+
+    #[test]
+    fn invalid_meshes_are_rejected() {
+        let mut mesh = Mesh::cube();
+        mesh.uvs.pop();
+        assert!(matches!(
+            mesh.validate(),
+            Err(MeshError::AttributeCount { uvs: 23, .. })
+        ));
+
+        let mut mesh = Mesh::cube();
+        mesh.indices.pop();
+        assert_eq!(mesh.validate(), Err(MeshError::PartialTriangle(35)));
+
+        let mut mesh = Mesh::cube();
+        mesh.indices[4] = 24;
+        assert_eq!(
+            mesh.validate(),
+            Err(MeshError::IndexOutOfBounds {
+                index: 24,
+                vertices: 24
+            })
+        );
+
+        assert_eq!(Mesh::default().validate(), Err(MeshError::Empty));
+    }
+}
@@ -1,11 +1,29 @@
-use cgmath::{Vector3, Vector4};
+use cgmath::{Vector2, Vector3, Vector4};
 
-use crate::component;
+use crate::component::{
+    self, AngularVelocity, Collider, Color, DropShadow, Outline, Transform, Velocity,
+};
+use crate::ecs::{Bundle, Entity, Query, ReadOnlyQuery, Without, World};
+use crate::mesh::{Material, MeshId};
+use crate::sprite::AnimatedSprite;
 
+/// Entity of an object owned by the engine.
+pub type ObjectId = Entity;
+
+/// Components of an object, spawned as a bundle of `Transform`, `Color` and
+/// the optional `Outline`, `DropShadow`, `MeshId`, `Velocity`,
+/// `AngularVelocity` and `Collider`.
 #[derive(Clone, Copy, Debug, Default, PartialEq)]
 pub struct GameObject {
     pub transform: component::Transform,
     pub color: component::Color,
+    pub outline: Option<component::Outline>,
+    pub shadow: Option<component::DropShadow>,
+    /// Mesh drawn by the 3D renderer instead of the quad of the object.
+    pub mesh: Option<MeshId>,
+    pub velocity: Option<Velocity>,
+    pub angular_velocity: Option<AngularVelocity>,
+    pub collider: Option<Collider>,
 }
 
 impl GameObject {
@@ -32,4 +50,327 @@ impl GameObject {
         self.color.color = color;
         self
     }
+
+    pub fn with_outline(mut self, color: Vector4<f32>, thickness: f32) -> Self {
+        self.outline = Some(component::Outline { color, thickness });
+        self
+    }
+
+    pub fn with_drop_shadow(mut self, color: Vector4<f32>, offset: Vector2<f32>) -> Self {
+        self.shadow = Some(component::DropShadow { color, offset });
+        self
+    }
+
+    /// Draws the object as a mesh, lit and shaded with its color. Its outline
+    /// and drop shadow are not drawn.
+    pub fn with_mesh(mut self, mesh: MeshId) -> Self {
+        self.mesh = Some(mesh);
+        self
+    }
+
+    /// Moves the object at a constant velocity, in world units per second.
+    pub fn with_velocity(mut self, velocity: Vector3<f32>) -> Self {
+        self.velocity = Some(Velocity::new(velocity));
+        self
+    }
+
+    /// Rotates the object at a constant speed, in radians per second.
+    pub fn with_angular_velocity(mut self, velocity: Vector3<f32>) -> Self {
+        self.angular_velocity = Some(AngularVelocity::new(velocity));
+        self
+    }
+
+    /// Reports the collisions of the object with the other objects that have
+    /// a collider.
+    pub fn with_collider(mut self, collider: Collider) -> Self {
+        self.collider = Some(collider);
+        self
+    }
+
+    /// Shades the object with the diffuse color of a material.
+    pub fn with_material(mut self, material: &Material) -> Self {
+        self.color.color = material.diffuse;
+        self
+    }
+}
+
+impl Bundle for GameObject {
+    fn insert(self, world: &mut World, entity: Entity) {
+        world.insert(entity, self.transform);
+        world.insert(entity, self.color);
+        if let Some(outline) = self.outline {
+            world.insert(entity, outline);
+        }
+        if let Some(shadow) = self.shadow {
+            world.insert(entity, shadow);
+        }
+        if let Some(mesh) = self.mesh {
+            world.insert(entity, mesh);
+        }
+        if let Some(velocity) = self.velocity {
+            world.insert(entity, velocity);
+        }
+        if let Some(angular_velocity) = self.angular_velocity {
+            world.insert(entity, angular_velocity);
+        }
+        if let Some(collider) = self.collider {
+            world.insert(entity, collider);
+        }
+    }
+}
+
+/// Components of an object spawned from a `GameObject`. Objects without a
+/// `Transform` and a `Color` are left out.
+#[derive(Clone, Copy, Debug)]
+pub struct ObjectView<'w> {
+    pub transform: &'w Transform,
+    pub color: &'w Color,
+    pub outline: Option<&'w Outline>,
+    pub shadow: Option<&'w DropShadow>,
+    pub mesh: Option<&'w MeshId>,
+    pub velocity: Option<&'w Velocity>,
+    pub angular_velocity: Option<&'w AngularVelocity>,
+    pub collider: Option<&'w Collider>,
+}
+
+type ObjectQuery<'a> = (
+    &'a Transform,
+    &'a Color,
+    Option<&'a Outline>,
+    Option<&'a DropShadow>,
+    Option<&'a MeshId>,
+    Option<&'a Velocity>,
+    Option<&'a AngularVelocity>,
+    Option<&'a Collider>,
+);
+
+unsafe impl<'a> Query for ObjectView<'a> {
+    type Item<'w> = ObjectView<'w>;
+    type Fetch = <ObjectQuery<'a> as Query>::Fetch;
+
+    fn fetch(world: &World) -> Option<Self::Fetch> {
+        ObjectQuery::fetch(world)
+    }
+
+    unsafe fn candidates<'w>(fetch: Self::Fetch) -> Option<&'w [Entity]> {
+        ObjectQuery::candidates(fetch)
+    }
+
+    unsafe fn get<'w>(fetch: Self::Fetch, entity: Entity) -> Option<Self::Item<'w>> {
+        let (transform, color, outline, shadow, mesh, velocity, angular_velocity, collider) =
+            ObjectQuery::get(fetch, entity)?;
+        Some(ObjectView {
+            transform,
+            color,
+            outline,
+            shadow,
+            mesh,
+            velocity,
+            angular_velocity,
+            collider,
+        })
+    }
+
+    fn borrows(borrows: &mut Vec<(std::any::TypeId, bool)>) {
+        ObjectQuery::borrows(borrows);
+    }
+}
+
+unsafe impl<'a> ReadOnlyQuery for ObjectView<'a> {}
+
+/// Components of an object spawned from a `GameObject`, to modify them.
+/// Objects without a `Transform` and a `Color` are left out.
+#[derive(Debug)]
+pub struct ObjectMut<'w> {
+    pub transform: &'w mut Transform,
+    pub color: &'w mut Color,
+    pub outline: Option<&'w mut Outline>,
+    pub shadow: Option<&'w mut DropShadow>,
+    pub mesh: Option<&'w mut MeshId>,
+    pub velocity: Option<&'w mut Velocity>,
+    pub angular_velocity: Option<&'w mut AngularVelocity>,
+    pub collider: Option<&'w mut Collider>,
+}
+
+type ObjectMutQuery<'a> = (
+    &'a mut Transform,
+    &'a mut Color,
+    Option<&'a mut Outline>,
+    Option<&'a mut DropShadow>,
+    Option<&'a mut MeshId>,
+    Option<&'a mut Velocity>,
+    Option<&'a mut AngularVelocity>,
+    Option<&'a mut Collider>,
+);
+
+unsafe impl<'a> Query for ObjectMut<'a> {
+    type Item<'w> = ObjectMut<'w>;
+    type Fetch = <ObjectMutQuery<'a> as Query>::Fetch;
+
+    fn fetch(world: &World) -> Option<Self::Fetch> {
+        ObjectMutQuery::fetch(world)
+    }
+
+    unsafe fn candidates<'w>(fetch: Self::Fetch) -> Option<&'w [Entity]> {
+        ObjectMutQuery::candidates(fetch)
+    }
+
+    unsafe fn get<'w>(fetch: Self::Fetch, entity: Entity) -> Option<Self::Item<'w>> {
+        let (transform, color, outline, shadow, mesh, velocity, angular_velocity, collider) =
+            ObjectMutQuery::get(fetch, entity)?;
+        Some(ObjectMut {
+            transform,
+            color,
+            outline,
+            shadow,
+            mesh,
+            velocity,
+            angular_velocity,
+            collider,
+        })
+    }
+
+    fn borrows(borrows: &mut Vec<(std::any::TypeId, bool)>) {
+        ObjectMutQuery::borrows(borrows);
+    }
+}
+
+/// Components of an object drawn as quads by the 2D renderers. Objects with
+/// a mesh are left out.
+#[derive(Clone, Copy, Debug)]
+pub struct QuadView<'w> {
+    pub transform: &'w Transform,
+    pub color: &'w Color,
+    pub outline: Option<&'w Outline>,
+    pub shadow: Option<&'w DropShadow>,
+    /// Frame of an atlas drawn on the quad, tinted by the color.
+    pub sprite: Option<&'w AnimatedSprite>,
+}
+
+type QuadQuery<'a> = (
+    &'a Transform,
+    &'a Color,
+    Option<&'a Outline>,
+    Option<&'a DropShadow>,
+    Option<&'a AnimatedSprite>,
+    Without<MeshId>,
+);
+
+unsafe impl<'a> Query for QuadView<'a> {
+    type Item<'w> = QuadView<'w>;
+    type Fetch = <QuadQuery<'a> as Query>::Fetch;
+
+    fn fetch(world: &World) -> Option<Self::Fetch> {
+        QuadQuery::fetch(world)
+    }
+
+    unsafe fn candidates<'w>(fetch: Self::Fetch) -> Option<&'w [Entity]> {
+        QuadQuery::candidates(fetch)
+    }
+
+    unsafe fn get<'w>(fetch: Self::Fetch, entity: Entity) -> Option<Self::Item<'w>> {
+        let (transform, color, outline, shadow, sprite, ()) = QuadQuery::get(fetch, entity)?;
+        Some(QuadView {
+            transform,
+            color,
+            outline,
+            shadow,
+            sprite,
+        })
+    }
+
+    fn borrows(borrows: &mut Vec<(std::any::TypeId, bool)>) {
+        QuadQuery::borrows(borrows);
+    }
+}
+
+unsafe impl<'a> ReadOnlyQuery for QuadView<'a> {}
+
+/// Components of an object drawn as a mesh by the 3D renderer.
+#[derive(Clone, Copy, Debug)]
+pub struct MeshView<'w> {
+    pub transform: &'w Transform,
+    pub color: &'w Color,
+    pub mesh: &'w MeshId,
+}
+
+type MeshQuery<'a> = (&'a Transform, &'a Color, &'a MeshId);
+
+unsafe impl<'a> Query for MeshView<'a> {
+    type Item<'w> = MeshView<'w>;
+    type Fetch = <MeshQuery<'a> as Query>::Fetch;
+
+    fn fetch(world: &World) -> Option<Self::Fetch> {
+        MeshQuery::fetch(world)
+    }
+
+    unsafe fn candidates<'w>(fetch: Self::Fetch) -> Option<&'w [Entity]> {
+        MeshQuery::candidates(fetch)
+    }
+
+    unsafe fn get<'w>(fetch: Self::Fetch, entity: Entity) -> Option<Self::Item<'w>> {
+        let (transform, color, mesh) = MeshQuery::get(fetch, entity)?;
+        Some(MeshView {
+            transform,
+            color,
+            mesh,
+        })
+    }
+
+    fn borrows(borrows: &mut Vec<(std::any::TypeId, bool)>) {
+        MeshQuery::borrows(borrows);
+    }
+}
+
+unsafe impl<'a> ReadOnlyQuery for MeshView<'a> {}
+
+#[cfg(test)]
+mod tests {
+    use cgmath::{vec3, vec4};
+
+    use super::*;
+
+    #[test]
+    fn meshes_are_not_drawn_as_quads() {
+        let mut world = World::new();
+        let quad = world.spawn(GameObject::new().with_outline(vec4(0.0, 0.0, 0.0, 1.0), 1.0));
+        let mesh = world.spawn(GameObject::new().with_mesh(MeshId::from_bits(0)));
+
+        let quads: Vec<_> = world.query::<(Entity, QuadView)>().collect();
+        assert_eq!(quads.len(), 1);
+        assert_eq!(quads[0].0, quad);
+        assert!(quads[0].1.outline.is_some());
+
+        world.get_mut::<Transform>(mesh).unwrap().position = vec3(1.0, 2.0, 3.0);
+        let meshes: Vec<_> = world.query::<MeshView>().collect();
+        assert_eq!(meshes.len(), 1);
+        assert_eq!(meshes[0].transform.position, vec3(1.0, 2.0, 3.0));
+    }
+
+    #[test]
+    fn objects_are_viewed_as_spawned() {
+        let mut world = World::new();
+        let object = GameObject::new()
+            .with_velocity(vec3(1.0, 0.0, 0.0))
+            .with_collider(Collider::circle(0.5));
+        let id = world.spawn(object);
+        world.spawn((Transform::default(),));
+
+        let view = world.query_one::<ObjectView>(id).unwrap();
+        assert_eq!(view.velocity, object.velocity.as_ref());
+        assert_eq!(view.collider, object.collider.as_ref());
+        assert!(view.outline.is_none());
+
+        let ObjectMut {
+            transform,
+            velocity,
+            ..
+        } = world.query_one_mut::<ObjectMut>(id).unwrap();
+        transform.position += velocity.unwrap().velocity;
+        assert_eq!(world.query_mut::<ObjectMut>().count(), 1);
+        assert_eq!(
+            world.get::<Transform>(id).unwrap().position,
+            vec3(1.0, 0.0, 0.0)
+        );
+    }
 }
@@ -0,0 +1,335 @@
+//! Named object templates, defined in asset files and spawned by name.
+//!
+//! Prefabs are written as text, one section per prefab. Properties left out
+//! keep the default value of a `GameObject`:
+//!
+//! ```text
+//! # comment
+//! [enemy]
+//! color = 1 0 0 1
+//! scale = 0.05 0.05 1
+//! outline = 0 0 0 1, 0.01
+//! velocity = 0.2 0 0
+//! collider = circle 0.05
+//! body = dynamic
+//! ```
+//!
+//! Vectors are written as space separated numbers. The properties are
+//! `position`, `rotation`, `scale`, `color`, `outline` (color, thickness),
+//! `shadow` (color, offset), `velocity`, `angular_velocity`, `collider`
+//! (`aabb` half width and height, or `circle` radius), `body` (`dynamic`,
+//! `fixed` or `kinematic`) and `material` (friction, restitution, density).
+//! Meshes are only known at runtime, so they can not be set in a file.
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::{error, fmt, fs, io};
+
+use cgmath::{Vector2, Vector3, Vector4};
+
+use crate::component::{
+    AngularVelocity, Collider, DropShadow, Outline, PhysicsMaterial, RigidBody, Velocity,
+};
+use crate::ecs::{Bundle, Entity, World};
+use crate::object::GameObject;
+
+#[derive(Debug)]
+pub enum PrefabError {
+    Io {
+        path: PathBuf,
+        error: io::Error,
+    },
+    /// A line of a prefab file, numbered from 1, can not be parsed.
+    Parse {
+        line: usize,
+        message: String,
+    },
+}
+
+impl fmt::Display for PrefabError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io { path, error } => write!(f, "read {}: {error}", path.display()),
+            Self::Parse { line, message } => write!(f, "line {line}: {message}"),
+        }
+    }
+}
+
+impl error::Error for PrefabError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Self::Io { error, .. } => Some(error),
+            Self::Parse { .. } => None,
+        }
+    }
+}
+
+/// Components an object is spawned with.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Prefab {
+    pub object: GameObject,
+    pub body: Option<RigidBody>,
+    pub material: Option<PhysicsMaterial>,
+}
+
+impl Prefab {
+    pub fn new(object: GameObject) -> Self {
+        Self {
+            object,
+            ..Default::default()
+        }
+    }
+
+    /// Sets a property from its text, as written in prefab files, e.g.
+    /// `set("color", "0 1 0 1")`.
+    pub fn set(&mut self, property: &str, value: &str) -> Result<(), String> {
+        let object = &mut self.object;
+        match property {
+            "position" => object.transform.position = vector3(value)?,
+            "rotation" => object.transform.rotation = vector3(value)?,
+            "scale" => object.transform.scale = vector3(value)?,
+            "color" => object.color.color = vector4(value)?,
+            "outline" => {
+                let (color, thickness) = pair(value)?;
+                object.outline = Some(Outline {
+                    color: vector4(color)?,
+                    thickness: float(thickness)?,
+                });
+            }
+            "shadow" => {
+                let (color, offset) = pair(value)?;
+                let [x, y] = floats(offset)?;
+                object.shadow = Some(DropShadow {
+                    color: vector4(color)?,
+                    offset: Vector2::new(x, y),
+                });
+            }
+            "velocity" => object.velocity = Some(Velocity::new(vector3(value)?)),
+            "angular_velocity" => {
+                object.angular_velocity = Some(AngularVelocity::new(vector3(value)?));
+            }
+            "collider" => {
+                let (shape, size) = value.trim().split_once(' ').unwrap_or((value.trim(), ""));
+                object.collider = Some(match shape {
+                    "aabb" => {
+                        let [x, y] = floats(size)?;
+                        Collider::aabb(Vector2::new(x, y))
+                    }
+                    "circle" => Collider::circle(float(size)?),
+                    _ => return Err(format!("invalid collider {shape:?}")),
+                });
+            }
+            "body" => {
+                self.body = Some(match value.trim() {
+                    "dynamic" => RigidBody::Dynamic,
+                    "fixed" => RigidBody::Fixed,
+                    "kinematic" => RigidBody::Kinematic,
+                    body => return Err(format!("invalid body {body:?}")),
+                });
+            }
+            "material" => {
+                let [friction, restitution, density] = floats(value)?;
+                self.material = Some(PhysicsMaterial {
+                    friction,
+                    restitution,
+                    density,
+                });
+            }
+            _ => return Err(format!("unknown property {property:?}")),
+        }
+        Ok(())
+    }
+}
+
+impl Bundle for Prefab {
+    fn insert(self, world: &mut World, entity: Entity) {
+        self.object.insert(world, entity);
+        if let Some(body) = self.body {
+            world.insert(entity, body);
+        }
+        if let Some(material) = self.material {
+            world.insert(entity, material);
+        }
+    }
+}
+
+/// Splits a value made of two comma separated parts.
+fn pair(value: &str) -> Result<(&str, &str), String> {
+    value
+        .split_once(',')
+        .ok_or_else(|| format!("expected two comma separated values, got {value:?}"))
+}
+
+fn float(value: &str) -> Result<f32, String> {
+    let [value] = floats(value)?;
+    Ok(value)
+}
+
+fn floats<const N: usize>(value: &str) -> Result<[f32; N], String> {
+    let mut floats = [0.0; N];
+    let mut words = value.split_whitespace();
+    for float in &mut floats {
+        let word = words
+            .next()
+            .ok_or_else(|| format!("expected {N} numbers, got {value:?}"))?;
+        *float = word
+            .parse()
+            .map_err(|_| format!("invalid number {word:?}"))?;
+    }
+    if words.next().is_some() {
+        return Err(format!("expected {N} numbers, got {value:?}"));
+    }
+    Ok(floats)
+}
+
+fn vector3(value: &str) -> Result<Vector3<f32>, String> {
+    floats(value).map(Vector3::from)
+}
+
+fn vector4(value: &str) -> Result<Vector4<f32>, String> {
+    floats(value).map(Vector4::from)
+}
+
+/// Prefabs by name.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct PrefabLibrary {
+    prefabs: BTreeMap<String, Prefab>,
+}
+
+impl PrefabLibrary {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Adds a prefab, replacing the one of the same name.
+    pub fn insert(&mut self, name: impl Into<String>, prefab: Prefab) {
+        self.prefabs.insert(name.into(), prefab);
+    }
+
+    pub fn remove(&mut self, name: &str) -> Option<Prefab> {
+        self.prefabs.remove(name)
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Prefab> {
+        self.prefabs.get(name)
+    }
+
+    /// Returns the names of the prefabs, sorted.
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.prefabs.keys().map(String::as_str)
+    }
+
+    /// Adds the prefabs of `other`, replacing the ones of the same name.
+    pub fn merge(&mut self, other: PrefabLibrary) {
+        self.prefabs.extend(other.prefabs);
+    }
+
+    /// Parses prefabs written by hand.
+    pub fn parse(source: &str) -> Result<Self, PrefabError> {
+        let mut library = Self::new();
+        let mut current = None;
+        for (index, line) in source.lines().enumerate() {
+            let error = |message| PrefabError::Parse {
+                line: index + 1,
+                message,
+            };
+            let line = line.split('#').next().unwrap_or_default().trim();
+            if line.is_empty() {
+                continue;
+            }
+            if let Some(name) = line.strip_prefix('[') {
+                let name = name
+                    .strip_suffix(']')
+                    .map(str::trim)
+                    .filter(|name| !name.is_empty())
+                    .ok_or_else(|| error(format!("invalid section {line:?}")))?;
+                library.insert(name, Prefab::default());
+                current = Some(name);
+                continue;
+            }
+            let name = current.ok_or_else(|| error("property outside of a prefab".to_string()))?;
+            let (property, value) = line
+                .split_once('=')
+                .ok_or_else(|| error("expected `property = value`".to_string()))?;
+            library
+                .prefabs
+                .get_mut(name)
+                .expect("prefab of the current section")
+                .set(property.trim(), value)
+                .map_err(error)?;
+        }
+        Ok(library)
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, PrefabError> {
+        let path = path.as_ref();
+        let source = fs::read_to_string(path).map_err(|error| PrefabError::Io {
+            path: path.to_path_buf(),
+            error,
+        })?;
+        Self::parse(&source)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn prefabs_are_parsed_by_section() {
+        let library = PrefabLibrary::parse(
+            "
+            # enemies
+            [enemy]
+            color = 1 0 0 1
+            outline = 0 0 0 1, 0.5 # thick
+            collider = circle 0.25
+            body = dynamic
+
+            [wall]
+            scale = 2 1 1
+            shadow = 0 0 0 0.5, 0.1 -0.1
+            collider = aabb 1 0.5
+            material = 0.9 0.1 2
+            ",
+        )
+        .unwrap();
+        assert_eq!(library.names().collect::<Vec<_>>(), ["enemy", "wall"]);
+
+        let enemy = library.get("enemy").unwrap();
+        assert_eq!(enemy.object.color.color, Vector4::new(1.0, 0.0, 0.0, 1.0));
+        assert_eq!(enemy.object.outline.unwrap().thickness, 0.5);
+        assert_eq!(enemy.object.collider, Some(Collider::circle(0.25)));
+        assert_eq!(enemy.body, Some(RigidBody::Dynamic));
+
+        let wall = library.get("wall").unwrap();
+        assert_eq!(wall.object.transform.scale, Vector3::new(2.0, 1.0, 1.0));
+        assert_eq!(wall.object.shadow.unwrap().offset, Vector2::new(0.1, -0.1));
+        assert_eq!(wall.material.unwrap().density, 2.0);
+        assert_eq!(wall.body, None);
+
+        let mut world = World::new();
+        let entity = world.spawn(*wall);
+        assert_eq!(world.get::<PhysicsMaterial>(entity), wall.material.as_ref());
+        assert!(!world.has::<RigidBody>(entity));
+    }
+
+    #[test]
+    fn errors_point_to_their_line() {
+        let error = |source| match PrefabLibrary::parse(source) {
+            Err(PrefabError::Parse { line, message }) => (line, message),
+            result => panic!("unexpected {result:?}"),
+        };
+        assert_eq!(error("color = 1 1 1 1").0, 1);
+        assert_eq!(
+            error("[a]\n\ncolor = 1 1 1"),
+            (3, "expected 4 numbers, got \" 1 1 1\"".to_string())
+        );
+        assert_eq!(error("[a]\nbody = floating").0, 2);
+        assert_eq!(error("[]").0, 1);
+
+        let mut prefab = Prefab::default();
+        assert!(prefab.set("size", "1").is_err());
+        assert!(prefab.set("collider", "circle").is_err());
+    }
+}
@@ -0,0 +1,201 @@
+//! Flipbook animations, drawing the frames of a texture atlas in turn on the
+//! quad of an object.
+//!
+//! The engine advances the `AnimatedSprite` of the objects every frame by the
+//! game time, and the 2D renderer draws their current frame.
+
+use std::collections::BTreeMap;
+use std::ops::Range;
+use std::time;
+
+use cgmath::Vector4;
+
+/// Texture split into a grid of frames of the same size, numbered row by row
+/// from the top left.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SpriteAtlas {
+    /// Index of the texture in the texture array of the 2D renderer, see
+    /// `Renderer2DSystem::set_textures()`.
+    pub texture: u32,
+    pub columns: u32,
+    pub rows: u32,
+}
+
+impl SpriteAtlas {
+    pub fn new(texture: u32, columns: u32, rows: u32) -> Self {
+        Self {
+            texture,
+            columns: columns.max(1),
+            rows: rows.max(1),
+        }
+    }
+
+    /// Returns the number of frames of the atlas.
+    pub fn frames(&self) -> u32 {
+        self.columns * self.rows
+    }
+
+    /// Returns the texture coordinates of a frame, as the minimum U and V
+    /// followed by the maximum ones. Frames past the last one wrap around.
+    pub fn frame_uv(&self, frame: u32) -> Vector4<f32> {
+        let frame = frame % self.frames();
+        let (column, row) = (frame % self.columns, frame / self.columns);
+        let (width, height) = (1.0 / self.columns as f32, 1.0 / self.rows as f32);
+        Vector4::new(
+            column as f32 * width,
+            row as f32 * height,
+            (column + 1) as f32 * width,
+            (row + 1) as f32 * height,
+        )
+    }
+}
+
+/// Animation drawing the frames of an atlas on the quad of an object, e.g. a
+/// walk cycle. The frames are played from named clips, ranges of frames of the
+/// atlas; a new sprite loops over every frame.
+#[derive(Clone, Debug, PartialEq)]
+pub struct AnimatedSprite {
+    pub atlas: SpriteAtlas,
+    /// Frames drawn per second.
+    pub frame_rate: f32,
+    clips: BTreeMap<String, Range<u32>>,
+    /// Frames of the clip playing.
+    frames: Range<u32>,
+    looping: bool,
+    playing: bool,
+    frame: u32,
+    /// Time elapsed since the frame was drawn.
+    elapsed: time::Duration,
+}
+
+impl AnimatedSprite {
+    pub fn new(atlas: SpriteAtlas, frame_rate: f32) -> Self {
+        Self {
+            atlas,
+            frame_rate,
+            clips: BTreeMap::new(),
+            frames: 0..atlas.frames(),
+            looping: true,
+            playing: true,
+            frame: 0,
+            elapsed: time::Duration::ZERO,
+        }
+    }
+
+    /// Adds a clip playing a range of frames of the atlas.
+    pub fn with_clip(mut self, name: impl Into<String>, frames: Range<u32>) -> Self {
+        self.clips.insert(name.into(), frames);
+        self
+    }
+
+    /// Returns the names of the clips, in alphabetical order.
+    pub fn clips(&self) -> impl Iterator<Item = &str> {
+        self.clips.keys().map(String::as_str)
+    }
+
+    /// Plays a clip in a loop, from its first frame. Returns false if there is
+    /// no clip with that name.
+    pub fn play(&mut self, name: &str) -> bool {
+        self.start(name, true)
+    }
+
+    /// Plays a clip once, stopping on its last frame. Returns false if there is
+    /// no clip with that name.
+    pub fn play_once(&mut self, name: &str) -> bool {
+        self.start(name, false)
+    }
+
+    fn start(&mut self, name: &str, looping: bool) -> bool {
+        let Some(frames) = self.clips.get(name) else {
+            return false;
+        };
+        self.frames = frames.clone();
+        self.frame = frames.start;
+        self.looping = looping;
+        self.playing = true;
+        self.elapsed = time::Duration::ZERO;
+        true
+    }
+
+    /// Returns true until a clip played once reaches its last frame.
+    pub fn is_playing(&self) -> bool {
+        self.playing
+    }
+
+    /// Returns the frame of the atlas drawn.
+    pub fn frame(&self) -> u32 {
+        self.frame
+    }
+
+    /// Returns the texture coordinates of the frame drawn, see
+    /// `SpriteAtlas::frame_uv()`.
+    pub fn uv(&self) -> Vector4<f32> {
+        self.atlas.frame_uv(self.frame)
+    }
+
+    /// Advances the animation by the time elapsed since the last frame.
+    pub fn advance(&mut self, delta_time: time::Duration) {
+        let count = self.frames.len() as u64;
+        if !self.playing || count == 0 || self.frame_rate <= 0.0 {
+            return;
+        }
+        self.elapsed += delta_time;
+
+        // NOTE: only the time elapsed since the frame was drawn is kept, so
+        //       that it stays precise
+        let frame_time = time::Duration::from_secs_f64(1.0 / self.frame_rate as f64);
+        let frame_time = frame_time.as_nanos().max(1);
+        let steps = (self.elapsed.as_nanos() / frame_time) as u64;
+        self.elapsed = time::Duration::from_nanos((self.elapsed.as_nanos() % frame_time) as u64);
+
+        let index = u64::from(self.frame - self.frames.start) + steps;
+        let index = if self.looping {
+            index % count
+        } else if index >= count - 1 {
+            self.playing = false;
+            count - 1
+        } else {
+            index
+        };
+        self.frame = self.frames.start + index as u32;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn atlas_frames_go_row_by_row() {
+        let atlas = SpriteAtlas::new(0, 4, 2);
+        assert_eq!(atlas.frames(), 8);
+        assert_eq!(atlas.frame_uv(0), Vector4::new(0.0, 0.0, 0.25, 0.5));
+        assert_eq!(atlas.frame_uv(5), Vector4::new(0.25, 0.5, 0.5, 1.0));
+        assert_eq!(atlas.frame_uv(9), atlas.frame_uv(1));
+    }
+
+    #[test]
+    fn clips_loop_or_stop_on_their_last_frame() {
+        let frame = time::Duration::from_millis(100);
+        let mut sprite = AnimatedSprite::new(SpriteAtlas::new(0, 4, 2), 10.0)
+            .with_clip("walk", 4..8)
+            .with_clip("jump", 1..3);
+        sprite.advance(frame * 9);
+        assert_eq!(sprite.frame(), 1);
+
+        assert!(sprite.play("walk"));
+        assert_eq!(sprite.frame(), 4);
+        sprite.advance(frame * 3);
+        assert_eq!(sprite.frame(), 7);
+        sprite.advance(frame * 2);
+        assert_eq!(sprite.frame(), 5);
+
+        assert!(sprite.play_once("jump"));
+        sprite.advance(frame);
+        assert!(!sprite.is_playing());
+        sprite.advance(frame * 5);
+        assert_eq!(sprite.frame(), 2);
+        assert!(!sprite.play("run"));
+        assert_eq!(sprite.clips().collect::<Vec<_>>(), ["jump", "walk"]);
+    }
+}
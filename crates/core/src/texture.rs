@@ -0,0 +1,252 @@
+//! Import settings of textures, read from a sidecar file next to the texture
+//! instead of being chosen by the code loading it.
+//!
+//! The sidecar of `hero.png` is `hero.png.meta`. Settings left out keep their
+//! default value:
+//!
+//! ```text
+//! # pixel art
+//! filter = nearest
+//! wrap = clamp
+//! srgb = true
+//! premultiplied_alpha = false
+//! ```
+
+use std::path::{Path, PathBuf};
+use std::{error, fmt, fs, io};
+
+/// Extension added to the file name of a texture to get its sidecar.
+pub const SIDECAR_EXTENSION: &str = "meta";
+
+/// Filtering of the texels when the texture is sampled between them.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum TextureFilter {
+    /// Blends the nearest texels.
+    #[default]
+    Linear,
+    /// Uses the nearest texel, e.g. for pixel art.
+    Nearest,
+}
+
+/// Sampling of the texture outside of its coordinates.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum TextureWrap {
+    #[default]
+    Repeat,
+    MirroredRepeat,
+    /// Extends the texels of the edges.
+    Clamp,
+}
+
+#[derive(Debug)]
+pub enum TextureSettingsError {
+    Io {
+        path: PathBuf,
+        error: io::Error,
+    },
+    /// A line of a sidecar file, numbered from 1, can not be parsed.
+    Parse {
+        line: usize,
+        message: String,
+    },
+}
+
+impl fmt::Display for TextureSettingsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io { path, error } => write!(f, "access {}: {error}", path.display()),
+            Self::Parse { line, message } => write!(f, "line {line}: {message}"),
+        }
+    }
+}
+
+impl error::Error for TextureSettingsError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Self::Io { error, .. } => Some(error),
+            Self::Parse { .. } => None,
+        }
+    }
+}
+
+/// How a texture is imported.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TextureSettings {
+    pub filter: TextureFilter,
+    pub wrap: TextureWrap,
+    /// The colors of the image are in the sRGB color space, as for most
+    /// images drawn by hand. Turn it off for textures holding data, e.g.
+    /// masks or normals.
+    pub srgb: bool,
+    /// The colors of the image are premultiplied by its alpha, as exported by
+    /// some tools. They are divided by it when the texture is imported, since
+    /// the renderers blend colors that are not.
+    pub premultiplied_alpha: bool,
+}
+
+impl Default for TextureSettings {
+    fn default() -> Self {
+        Self {
+            filter: TextureFilter::default(),
+            wrap: TextureWrap::default(),
+            srgb: true,
+            premultiplied_alpha: false,
+        }
+    }
+}
+
+impl TextureSettings {
+    /// Returns the path of the sidecar of a texture.
+    pub fn sidecar_path(texture: impl AsRef<Path>) -> PathBuf {
+        let mut path = texture.as_ref().as_os_str().to_owned();
+        path.push(".");
+        path.push(SIDECAR_EXTENSION);
+        PathBuf::from(path)
+    }
+
+    /// Loads the settings of a texture from its sidecar, the default ones if
+    /// it has none.
+    pub fn load_for(texture: impl AsRef<Path>) -> Result<Self, TextureSettingsError> {
+        let path = Self::sidecar_path(texture);
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        Self::load(path)
+    }
+
+    /// Parses settings saved with `save()`, or written by hand.
+    pub fn parse(source: &str) -> Result<Self, TextureSettingsError> {
+        let mut settings = Self::default();
+        for (index, line) in source.lines().enumerate() {
+            let error = |message| TextureSettingsError::Parse {
+                line: index + 1,
+                message,
+            };
+            let line = line.split('#').next().unwrap_or_default().trim();
+            if line.is_empty() {
+                continue;
+            }
+            let (setting, value) = line
+                .split_once('=')
+                .ok_or_else(|| error("expected `setting = value`".to_string()))?;
+            settings.set(setting.trim(), value.trim()).map_err(error)?;
+        }
+        Ok(settings)
+    }
+
+    fn set(&mut self, setting: &str, value: &str) -> Result<(), String> {
+        let flag = |value: &str| match value {
+            "true" => Ok(true),
+            "false" => Ok(false),
+            other => Err(format!("expected true or false, got {other:?}")),
+        };
+        match setting {
+            "filter" => {
+                self.filter = match value {
+                    "linear" => TextureFilter::Linear,
+                    "nearest" => TextureFilter::Nearest,
+                    other => return Err(format!("unknown filter {other:?}")),
+                }
+            }
+            "wrap" => {
+                self.wrap = match value {
+                    "repeat" => TextureWrap::Repeat,
+                    "mirrored_repeat" => TextureWrap::MirroredRepeat,
+                    "clamp" => TextureWrap::Clamp,
+                    other => return Err(format!("unknown wrap {other:?}")),
+                }
+            }
+            "srgb" => self.srgb = flag(value)?,
+            "premultiplied_alpha" => self.premultiplied_alpha = flag(value)?,
+            other => return Err(format!("unknown setting {other:?}")),
+        }
+        Ok(())
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, TextureSettingsError> {
+        let path = path.as_ref();
+        let source = fs::read_to_string(path).map_err(|error| TextureSettingsError::Io {
+            path: path.to_path_buf(),
+            error,
+        })?;
+        Self::parse(&source)
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), TextureSettingsError> {
+        let path = path.as_ref();
+        fs::write(path, self.to_string()).map_err(|error| TextureSettingsError::Io {
+            path: path.to_path_buf(),
+            error,
+        })
+    }
+}
+
+impl fmt::Display for TextureSettings {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let filter = match self.filter {
+            TextureFilter::Linear => "linear",
+            TextureFilter::Nearest => "nearest",
+        };
+        let wrap = match self.wrap {
+            TextureWrap::Repeat => "repeat",
+            TextureWrap::MirroredRepeat => "mirrored_repeat",
+            TextureWrap::Clamp => "clamp",
+        };
+        writeln!(f, "filter = {filter}")?;
+        writeln!(f, "wrap = {wrap}")?;
+        writeln!(f, "srgb = {}", self.srgb)?;
+        writeln!(f, "premultiplied_alpha = {}", self.premultiplied_alpha)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn settings_round_trip_through_text() {
+        let settings = TextureSettings {
+            filter: TextureFilter::Nearest,
+            wrap: TextureWrap::MirroredRepeat,
+            srgb: false,
+            premultiplied_alpha: true,
+        };
+        let text = settings.to_string();
+        assert_eq!(
+            text,
+            "filter = nearest\nwrap = mirrored_repeat\nsrgb = false\npremultiplied_alpha = true\n"
+        );
+        assert_eq!(TextureSettings::parse(&text).unwrap(), settings);
+    }
+
+    #[test]
+    fn missing_settings_keep_their_default() {
+        let settings = TextureSettings::parse("# pixel art\n\nfilter = nearest # sharp\n").unwrap();
+        assert_eq!(
+            settings,
+            TextureSettings {
+                filter: TextureFilter::Nearest,
+                ..Default::default()
+            }
+        );
+        assert!(TextureSettings::load_for("does/not/exist.png").is_ok());
+        assert_eq!(
+            TextureSettings::sidecar_path("sprites/hero.png"),
+            PathBuf::from("sprites/hero.png.meta")
+        );
+    }
+
+    #[test]
+    fn invalid_lines_are_reported() {
+        match TextureSettings::parse("filter = linear\nwrap = sideways\n") {
+            Err(TextureSettingsError::Parse { line, message }) => {
+                assert_eq!(line, 2);
+                assert!(message.contains("sideways"), "{message}");
+            }
+            other => panic!("unexpected result {other:?}"),
+        }
+        assert!(TextureSettings::parse("srgb = yes").is_err());
+        assert!(TextureSettings::parse("mipmaps = true").is_err());
+        assert!(TextureSettings::parse("filter nearest").is_err());
+    }
+}
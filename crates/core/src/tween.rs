@@ -0,0 +1,269 @@
+//! Tweens, animating the transform or the color of an object over time.
+//!
+//! The tweens of an object are held by its `Tweens` component. The engine
+//! advances them every frame by the game time, and removes them once they
+//! are finished.
+
+use std::f32::consts::PI;
+use std::time;
+
+use cgmath::{Vector3, Vector4};
+
+use crate::component::{Color, Transform};
+
+/// Curve of the progress of a tween over its duration.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Easing {
+    #[default]
+    Linear,
+    /// Starts slowly and accelerates.
+    CubicIn,
+    /// Starts quickly and decelerates.
+    CubicOut,
+    CubicInOut,
+    /// Winds up around the start before moving.
+    ElasticIn,
+    /// Overshoots the end and settles around it.
+    ElasticOut,
+}
+
+impl Easing {
+    /// Returns the progress at `t`, from 0 at the start to 1 at the end.
+    pub fn apply(self, t: f32) -> f32 {
+        let t = t.clamp(0.0, 1.0);
+        match self {
+            Self::Linear => t,
+            Self::CubicIn => t * t * t,
+            Self::CubicOut => 1.0 - (1.0 - t).powi(3),
+            Self::CubicInOut if t < 0.5 => 4.0 * t * t * t,
+            Self::CubicInOut => 1.0 - (2.0 - 2.0 * t).powi(3) / 2.0,
+            // NOTE: the curves are pinned at their ends, where they do not
+            //       quite reach 0 and 1
+            Self::ElasticIn | Self::ElasticOut if t == 0.0 || t == 1.0 => t,
+            Self::ElasticIn => {
+                -(2.0_f32.powf(10.0 * t - 10.0)) * ((t * 10.0 - 10.75) * PI * 2.0 / 3.0).sin()
+            }
+            Self::ElasticOut => {
+                2.0_f32.powf(-10.0 * t) * ((t * 10.0 - 0.75) * PI * 2.0 / 3.0).sin() + 1.0
+            }
+        }
+    }
+}
+
+/// What happens once a tween reaches its end.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Repeat {
+    /// The tween finishes.
+    #[default]
+    Once,
+    /// The tween starts over from its start.
+    Loop,
+    /// The tween plays back to its start, then forward again.
+    PingPong,
+}
+
+/// Property of an object animated by a tween.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum TweenProperty {
+    Position,
+    Rotation,
+    Scale,
+    Color,
+}
+
+impl TweenProperty {
+    fn get(self, transform: &Transform, color: &Color) -> Vector4<f32> {
+        match self {
+            Self::Position => transform.position.extend(0.0),
+            Self::Rotation => transform.rotation.extend(0.0),
+            Self::Scale => transform.scale.extend(0.0),
+            Self::Color => color.color,
+        }
+    }
+
+    fn set(self, value: Vector4<f32>, transform: &mut Transform, color: &mut Color) {
+        match self {
+            Self::Position => transform.position = value.truncate(),
+            Self::Rotation => transform.rotation = value.truncate(),
+            Self::Scale => transform.scale = value.truncate(),
+            Self::Color => color.color = value,
+        }
+    }
+}
+
+/// Animation of a property of an object, from its value when the tween
+/// starts to a target value.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Tween {
+    property: TweenProperty,
+    /// Values at the start and the end. Vectors of 3 components are extended
+    /// with 0.
+    from: Option<Vector4<f32>>,
+    to: Vector4<f32>,
+    duration: time::Duration,
+    easing: Easing,
+    repeat: Repeat,
+    elapsed: time::Duration,
+}
+
+impl Tween {
+    fn new(property: TweenProperty, to: Vector4<f32>, duration: time::Duration) -> Self {
+        Self {
+            property,
+            from: None,
+            to,
+            duration,
+            easing: Easing::default(),
+            repeat: Repeat::default(),
+            elapsed: time::Duration::ZERO,
+        }
+    }
+
+    pub fn position(to: Vector3<f32>, duration: time::Duration) -> Self {
+        Self::new(TweenProperty::Position, to.extend(0.0), duration)
+    }
+
+    pub fn rotation(to: Vector3<f32>, duration: time::Duration) -> Self {
+        Self::new(TweenProperty::Rotation, to.extend(0.0), duration)
+    }
+
+    pub fn scale(to: Vector3<f32>, duration: time::Duration) -> Self {
+        Self::new(TweenProperty::Scale, to.extend(0.0), duration)
+    }
+
+    pub fn color(to: Vector4<f32>, duration: time::Duration) -> Self {
+        Self::new(TweenProperty::Color, to, duration)
+    }
+
+    pub fn with_easing(mut self, easing: Easing) -> Self {
+        self.easing = easing;
+        self
+    }
+
+    pub fn with_repeat(mut self, repeat: Repeat) -> Self {
+        self.repeat = repeat;
+        self
+    }
+
+    pub fn property(&self) -> TweenProperty {
+        self.property
+    }
+
+    /// Advances the tween by the time elapsed since the last frame and
+    /// updates the property. Returns true once the tween is finished, which
+    /// repeating tweens never are.
+    pub fn advance(
+        &mut self,
+        delta_time: time::Duration,
+        transform: &mut Transform,
+        color: &mut Color,
+    ) -> bool {
+        let from = *self
+            .from
+            .get_or_insert_with(|| self.property.get(transform, color));
+        self.elapsed += delta_time;
+
+        // NOTE: repeating tweens keep their elapsed time within a period, so
+        //       that it stays precise
+        let period = match self.repeat {
+            Repeat::Once => None,
+            Repeat::Loop => Some(self.duration),
+            Repeat::PingPong => Some(self.duration * 2),
+        };
+        if let Some(period) = period.filter(|period| !period.is_zero()) {
+            let elapsed = self.elapsed.as_nanos() % period.as_nanos();
+            self.elapsed = time::Duration::from_nanos(elapsed as u64);
+        }
+        let t = if self.duration.is_zero() {
+            1.0
+        } else {
+            self.elapsed.as_secs_f32() / self.duration.as_secs_f32()
+        };
+        let t = match self.repeat {
+            Repeat::PingPong if t > 1.0 => 2.0 - t,
+            _ => t.min(1.0),
+        };
+
+        let value = from + (self.to - from) * self.easing.apply(t);
+        self.property.set(value, transform, color);
+        self.repeat == Repeat::Once && t >= 1.0
+    }
+}
+
+/// Tweens of an object, advanced together.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Tweens {
+    pub tweens: Vec<Tween>,
+}
+
+impl Tweens {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with(mut self, tween: Tween) -> Self {
+        self.tweens.push(tween);
+        self
+    }
+}
+
+impl From<Tween> for Tweens {
+    fn from(tween: Tween) -> Self {
+        Self::new().with(tween)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cgmath::assert_relative_eq;
+
+    use super::*;
+
+    #[test]
+    fn easings_go_from_start_to_end() {
+        for easing in [
+            Easing::Linear,
+            Easing::CubicIn,
+            Easing::CubicOut,
+            Easing::CubicInOut,
+            Easing::ElasticIn,
+            Easing::ElasticOut,
+        ] {
+            assert_eq!(easing.apply(0.0), 0.0, "{easing:?}");
+            assert_eq!(easing.apply(1.0), 1.0, "{easing:?}");
+        }
+        assert_eq!(Easing::CubicIn.apply(0.5), 0.125);
+        assert_eq!(Easing::CubicOut.apply(0.5), 0.875);
+        assert_eq!(Easing::CubicInOut.apply(0.5), 0.5);
+        // elastic easings overshoot
+        assert!(Easing::ElasticOut.apply(0.1) > 1.0);
+        assert!(Easing::ElasticIn.apply(0.9) < 0.0);
+    }
+
+    #[test]
+    fn tweens_start_from_the_current_value() {
+        let mut transform = Transform::new();
+        transform.position.x = 1.0;
+        let mut color = Color::new();
+        let second = time::Duration::from_secs(1);
+        let mut advance = |tween: &mut Tween, seconds: f32| {
+            tween.advance(second.mul_f32(seconds), &mut transform, &mut color)
+        };
+
+        let mut position = Tween::position(Vector3::new(3.0, 0.0, 0.0), second * 2);
+        assert!(!advance(&mut position, 0.5));
+        assert!(!advance(&mut position, 0.5));
+        assert!(advance(&mut position, 2.0));
+        assert_eq!(position.from, Some(Vector4::new(1.0, 0.0, 0.0, 0.0)));
+
+        let mut color_tween = Tween::color(Vector4::new(1.0, 1.0, 1.0, 1.0), second)
+            .with_easing(Easing::CubicIn)
+            .with_repeat(Repeat::PingPong);
+        assert!(!advance(&mut color_tween, 1.5));
+        assert!(!advance(&mut color_tween, 1.0));
+        assert_eq!(color_tween.elapsed, second / 2);
+
+        assert_eq!(transform.position, Vector3::new(3.0, 0.0, 0.0));
+        assert_relative_eq!(color.color, Vector4::new(0.125, 0.125, 0.125, 0.125));
+    }
+}
@@ -0,0 +1,181 @@
+//! Counts heap allocations per frame per subsystem when the `alloc-audit`
+//! feature is enabled. Without the feature, scopes are no-ops.
+//!
+//! This helps enforcing the zero-allocation steady-state goal of hot paths
+//! such as the quad batcher and imgui prepare.
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Subsystem {
+    Engine,
+    Input,
+    Camera,
+    Application,
+    Renderer2D,
+    Renderer3D,
+    Passes,
+    ImGui,
+}
+
+impl Subsystem {
+    pub const ALL: [Subsystem; 8] = [
+        Subsystem::Engine,
+        Subsystem::Input,
+        Subsystem::Camera,
+        Subsystem::Application,
+        Subsystem::Renderer2D,
+        Subsystem::Renderer3D,
+        Subsystem::Passes,
+        Subsystem::ImGui,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Subsystem::Engine => "engine",
+            Subsystem::Input => "input",
+            Subsystem::Camera => "camera",
+            Subsystem::Application => "application",
+            Subsystem::Renderer2D => "renderer 2D",
+            Subsystem::Renderer3D => "renderer 3D",
+            Subsystem::Passes => "custom passes",
+            Subsystem::ImGui => "imgui",
+        }
+    }
+}
+
+/// Number of allocations made by each subsystem during a frame.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct AllocationReport {
+    counts: [usize; Subsystem::ALL.len()],
+}
+
+impl AllocationReport {
+    pub fn count(&self, subsystem: Subsystem) -> usize {
+        self.counts[subsystem as usize]
+    }
+
+    /// Returns the subsystems that allocated during the frame.
+    pub fn offenders(&self) -> impl Iterator<Item = (Subsystem, usize)> + '_ {
+        Subsystem::ALL
+            .iter()
+            .map(|s| (*s, self.count(*s)))
+            .filter(|(_, count)| *count > 0)
+    }
+}
+
+#[cfg(feature = "alloc-audit")]
+mod counting {
+    use std::alloc::{GlobalAlloc, Layout, System};
+    use std::cell::Cell;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::{AllocationReport, Subsystem};
+
+    #[allow(clippy::declare_interior_mutable_const)]
+    const ZERO: AtomicUsize = AtomicUsize::new(0);
+
+    static COUNTS: [AtomicUsize; Subsystem::ALL.len()] = [ZERO; Subsystem::ALL.len()];
+    thread_local! {
+        /// Subsystem of the innermost scope of the thread.
+        /// NOTE: the const initializer does not allocate, as required from
+        ///       the allocator
+        static CURRENT: Cell<Subsystem> = const { Cell::new(Subsystem::Engine) };
+    }
+
+    struct CountingAllocator;
+
+    #[global_allocator]
+    static GLOBAL: CountingAllocator = CountingAllocator;
+
+    unsafe impl GlobalAlloc for CountingAllocator {
+        unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+            record();
+            System.alloc(layout)
+        }
+
+        unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+            record();
+            System.alloc_zeroed(layout)
+        }
+
+        unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+            record();
+            System.realloc(ptr, layout, new_size)
+        }
+
+        unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+            System.dealloc(ptr, layout)
+        }
+    }
+
+    fn record() {
+        // NOTE: threads being destroyed may allocate after their locals
+        let current = CURRENT.try_with(Cell::get).unwrap_or(Subsystem::Engine);
+        COUNTS[current as usize].fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn enter(subsystem: Subsystem) -> Subsystem {
+        CURRENT.with(|current| current.replace(subsystem))
+    }
+
+    pub fn leave(previous: Subsystem) {
+        CURRENT.with(|current| current.set(previous));
+    }
+
+    pub fn take_report() -> AllocationReport {
+        let mut report = AllocationReport::default();
+        for (count, counter) in report.counts.iter_mut().zip(COUNTS.iter()) {
+            *count = counter.swap(0, Ordering::Relaxed);
+        }
+        report
+    }
+}
+
+/// Attributes allocations to a subsystem until dropped.
+#[must_use]
+pub struct Scope {
+    #[cfg(feature = "alloc-audit")]
+    previous: Subsystem,
+}
+
+/// Attributes allocations made by the current thread until the returned
+/// guard is dropped to the provided subsystem. Threads outside of a scope
+/// allocate for the engine.
+#[inline]
+pub fn scope(_subsystem: Subsystem) -> Scope {
+    Scope {
+        #[cfg(feature = "alloc-audit")]
+        previous: counting::enter(_subsystem),
+    }
+}
+
+impl Drop for Scope {
+    #[inline]
+    fn drop(&mut self) {
+        #[cfg(feature = "alloc-audit")]
+        counting::leave(self.previous);
+    }
+}
+
+/// Returns the allocations made since the last call and resets the counters.
+#[inline]
+pub fn take_report() -> AllocationReport {
+    #[cfg(feature = "alloc-audit")]
+    return counting::take_report();
+    #[cfg(not(feature = "alloc-audit"))]
+    AllocationReport::default()
+}
+
+/// Draws the allocation report in an imgui window.
+#[cfg(all(feature = "alloc-audit", feature = "imgui"))]
+pub fn draw_hud(ui: &vulkan_imgui::imgui::Ui, report: &AllocationReport) {
+    ui.window("Allocations").build(|| {
+        let mut clean = true;
+        for (subsystem, count) in report.offenders() {
+            ui.text(format!("{}: {count}", subsystem.name()));
+            clean = false;
+        }
+        if clean {
+            ui.text("no allocations");
+        }
+    });
+}
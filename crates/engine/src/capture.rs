@@ -0,0 +1,69 @@
+//! Frame captures using RenderDoc when the `renderdoc` feature is enabled.
+//!
+//! The RenderDoc API is only available when the application is launched from
+//! RenderDoc or has it injected. The built-in capture keys of RenderDoc are
+//! replaced by the engine hotkey, so that a single capture is taken per press.
+
+use log::{info, warn};
+use renderdoc::{InputButton, RenderDoc, V110};
+use winit::event::{Event, VirtualKeyCode};
+
+use crate::hotkey::Hotkey;
+
+/// Key capturing the next frame by default.
+pub const DEFAULT_CAPTURE_KEY: VirtualKeyCode = VirtualKeyCode::F12;
+
+pub(crate) struct FrameCapture {
+    renderdoc: Option<RenderDoc<V110>>,
+    hotkey: Hotkey,
+    requested: bool,
+}
+
+impl FrameCapture {
+    /// Connects to RenderDoc. Captures are disabled when it is not attached.
+    pub fn new(key: Option<VirtualKeyCode>) -> Self {
+        let renderdoc = match RenderDoc::<V110>::new() {
+            Ok(mut renderdoc) => {
+                renderdoc.set_capture_keys::<InputButton>(&[]);
+                info!("RenderDoc attached, frame captures are enabled");
+                Some(renderdoc)
+            }
+            Err(e) => {
+                warn!("RenderDoc is not attached, frame captures are disabled: {e}");
+                None
+            }
+        };
+        Self {
+            renderdoc,
+            hotkey: Hotkey::new(key),
+            requested: false,
+        }
+    }
+
+    /// Requests a capture when the hotkey is pressed.
+    pub fn on_event<T>(&mut self, event: &Event<T>) {
+        if self.hotkey.on_event(event) {
+            self.request();
+        }
+    }
+
+    /// Captures the next frame.
+    pub fn request(&mut self) {
+        self.requested = true;
+    }
+
+    /// Triggers the requested capture. Must be called before the frame to
+    /// capture starts.
+    pub fn on_frame(&mut self) {
+        if !std::mem::take(&mut self.requested) {
+            return;
+        }
+        match self.renderdoc.as_mut() {
+            Some(renderdoc) => {
+                renderdoc.trigger_capture();
+                info!("capturing frame");
+            }
+            None => warn!("frame capture requested but RenderDoc is not attached"),
+        }
+    }
+}
@@ -0,0 +1,357 @@
+//! Built-in collision detection between the objects that have a `Collider`.
+//!
+//! Colliders are sorted into a uniform grid, and only the colliders sharing a
+//! cell are tested against each other. Cells work best about the size of the
+//! common colliders, which they are sized after unless a size is set:
+//! colliders spanning too many cells are tested against all the others
+//! instead.
+//!
+//! Collisions are reported to the application when they start and stop, see
+//! `Application::on_collision()`, and sent to the event bus.
+
+use core::component::{Collider, Transform};
+use core::ecs::{Entity, World};
+use core::mesh::MeshId;
+use core::object::ObjectId;
+use std::collections::HashMap;
+
+use cgmath::{ElementWise, InnerSpace, Vector2};
+use log::warn;
+
+/// Most cells a collider is added to. Larger colliders are tested against
+/// every other collider.
+const MAX_COLLIDER_CELLS: i64 = 64;
+
+/// Change in the contact between two objects, the first one having the
+/// smaller id.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CollisionEvent {
+    /// The colliders of the objects started overlapping.
+    Started(ObjectId, ObjectId),
+    /// The colliders of the objects stopped overlapping, or one of the
+    /// objects was removed or lost its collider.
+    Stopped(ObjectId, ObjectId),
+}
+
+impl CollisionEvent {
+    pub fn objects(&self) -> (ObjectId, ObjectId) {
+        match *self {
+            Self::Started(a, b) | Self::Stopped(a, b) => (a, b),
+        }
+    }
+}
+
+/// Collider placed in the world.
+#[derive(Debug)]
+struct Body {
+    entity: Entity,
+    center: Vector2<f32>,
+    collider: Collider,
+}
+
+impl Body {
+    fn overlaps(&self, other: &Body) -> bool {
+        let delta = other.center - self.center;
+        match (self.collider, other.collider) {
+            (Collider::Aabb { half_extents: a }, Collider::Aabb { half_extents: b }) => {
+                delta.x.abs() < a.x + b.x && delta.y.abs() < a.y + b.y
+            }
+            (Collider::Circle { radius: a }, Collider::Circle { radius: b }) => {
+                delta.magnitude2() < (a + b) * (a + b)
+            }
+            (Collider::Aabb { half_extents }, Collider::Circle { radius }) => {
+                circle_overlaps_aabb(delta, radius, half_extents)
+            }
+            (Collider::Circle { radius }, Collider::Aabb { half_extents }) => {
+                circle_overlaps_aabb(-delta, radius, half_extents)
+            }
+        }
+    }
+}
+
+/// Returns true if a circle centered at `delta` from the center of a box
+/// overlaps it.
+fn circle_overlaps_aabb(delta: Vector2<f32>, radius: f32, half_extents: Vector2<f32>) -> bool {
+    let closest = Vector2::new(
+        delta.x.clamp(-half_extents.x, half_extents.x),
+        delta.y.clamp(-half_extents.y, half_extents.y),
+    );
+    (delta - closest).magnitude2() < radius * radius
+}
+
+/// Detects the collisions of the objects, every frame.
+#[derive(Debug)]
+pub(crate) struct CollisionSystem {
+    /// Size of the cells set by the application, if any.
+    fixed_cell_size: Option<f32>,
+    /// Size of the cells of the current update.
+    cell_size: f32,
+    /// Widths of the colliders, to size the cells after.
+    widths: Vec<f32>,
+    bodies: Vec<Body>,
+    /// Bodies in each cell, by index in `bodies`.
+    cells: HashMap<(i32, i32), Vec<usize>>,
+    /// Bodies spanning too many cells.
+    oversized: Vec<usize>,
+    /// Pairs of overlapping objects, sorted.
+    contacts: Vec<(ObjectId, ObjectId)>,
+    pairs: Vec<(ObjectId, ObjectId)>,
+    events: Vec<CollisionEvent>,
+}
+
+impl CollisionSystem {
+    /// Creates a system whose grid has cells of the given size, in world
+    /// units, or of the median width of the colliders. An invalid size is
+    /// ignored.
+    pub fn new(cell_size: Option<f32>) -> Self {
+        let fixed_cell_size = cell_size.filter(|&cell_size| {
+            let valid = cell_size.is_finite() && cell_size > 0.0;
+            if !valid {
+                warn!("invalid collision cell size {cell_size}, sizing cells after the colliders");
+            }
+            valid
+        });
+        Self {
+            fixed_cell_size,
+            cell_size: 1.0,
+            widths: Vec::new(),
+            bodies: Vec::new(),
+            cells: HashMap::new(),
+            oversized: Vec::new(),
+            contacts: Vec::new(),
+            pairs: Vec::new(),
+            events: Vec::new(),
+        }
+    }
+
+    /// Detects the objects overlapping, returning the collisions that started
+    /// or stopped since the last update.
+    pub fn on_update(&mut self, world: &World) -> &[CollisionEvent] {
+        // place the colliders
+        self.bodies.clear();
+        for (entity, transform, collider, mesh) in
+            world.query::<(Entity, &Transform, &Collider, Option<&MeshId>)>()
+        {
+            let position = transform.position.truncate();
+            // NOTE: quads are scaled after being translated, see QuadBatcher
+            let center = match mesh {
+                Some(_) => position,
+                None => position.mul_element_wise(transform.scale.truncate()),
+            };
+            self.bodies.push(Body {
+                entity,
+                center,
+                collider: *collider,
+            });
+        }
+
+        // size the cells after the common colliders
+        // NOTE: the median is not skewed by a few large colliders
+        self.cell_size = match self.fixed_cell_size {
+            Some(cell_size) => cell_size,
+            None => {
+                self.widths.clear();
+                self.widths.extend(self.bodies.iter().map(|body| {
+                    let half_extents = body.collider.half_extents();
+                    2.0 * half_extents.x.max(half_extents.y)
+                }));
+                median(&mut self.widths)
+                    .filter(|&width| width.is_finite() && width > 0.0)
+                    .unwrap_or(self.cell_size)
+            }
+        };
+
+        // sort them into the grid, keeping the allocations of occupied cells
+        self.cells.retain(|_, bodies| !bodies.is_empty());
+        for bodies in self.cells.values_mut() {
+            bodies.clear();
+        }
+        self.oversized.clear();
+        for (i, body) in self.bodies.iter().enumerate() {
+            let half_extents = body.collider.half_extents();
+            let (min_x, min_y) = self.cell(body.center - half_extents);
+            let (max_x, max_y) = self.cell(body.center + half_extents);
+            let cells = (max_x as i64 - min_x as i64 + 1) * (max_y as i64 - min_y as i64 + 1);
+            if cells > MAX_COLLIDER_CELLS {
+                self.oversized.push(i);
+                continue;
+            }
+            for x in min_x..=max_x {
+                for y in min_y..=max_y {
+                    self.cells.entry((x, y)).or_default().push(i);
+                }
+            }
+        }
+
+        // test the colliders sharing a cell
+        // NOTE: colliders sharing several cells are tested once per cell
+        self.pairs.clear();
+        let bodies = &self.bodies;
+        let mut test = |a: usize, b: usize| {
+            let (a, b) = (&bodies[a], &bodies[b]);
+            if a.overlaps(b) {
+                self.pairs
+                    .push((a.entity.min(b.entity), a.entity.max(b.entity)));
+            }
+        };
+        for cell in self.cells.values() {
+            for (i, &a) in cell.iter().enumerate() {
+                for &b in &cell[i + 1..] {
+                    test(a, b);
+                }
+            }
+        }
+        for (i, &a) in self.oversized.iter().enumerate() {
+            for b in 0..bodies.len() {
+                // NOTE: pairs of oversized colliders are tested once
+                if b != a && !self.oversized[..i].contains(&b) {
+                    test(a, b);
+                }
+            }
+        }
+        self.pairs.sort_unstable();
+        self.pairs.dedup();
+
+        // compare with the contacts of the last update
+        self.events.clear();
+        let (mut previous, mut current) = (
+            self.contacts.iter().peekable(),
+            self.pairs.iter().peekable(),
+        );
+        loop {
+            let event = match (previous.peek(), current.peek()) {
+                (Some(&&p), Some(&&c)) if p == c => {
+                    previous.next();
+                    current.next();
+                    continue;
+                }
+                (Some(&&p), Some(&&c)) if p < c => {
+                    previous.next();
+                    CollisionEvent::Stopped(p.0, p.1)
+                }
+                (Some(&&p), None) => {
+                    previous.next();
+                    CollisionEvent::Stopped(p.0, p.1)
+                }
+                (_, Some(&&c)) => {
+                    current.next();
+                    CollisionEvent::Started(c.0, c.1)
+                }
+                (None, None) => break,
+            };
+            self.events.push(event);
+        }
+        std::mem::swap(&mut self.contacts, &mut self.pairs);
+
+        &self.events
+    }
+
+    /// Returns the cell holding a point.
+    fn cell(&self, point: Vector2<f32>) -> (i32, i32) {
+        let cell = point / self.cell_size;
+        (cell.x.floor() as i32, cell.y.floor() as i32)
+    }
+}
+
+/// Returns the median of values, reordering them, or None if there are none.
+fn median(values: &mut [f32]) -> Option<f32> {
+    if values.is_empty() {
+        return None;
+    }
+    let middle = values.len() / 2;
+    let (_, median, _) = values.select_nth_unstable_by(middle, f32::total_cmp);
+    Some(*median)
+}
+
+#[cfg(test)]
+mod tests {
+    use core::object::GameObject;
+
+    use cgmath::Vector3;
+
+    use super::*;
+
+    #[test]
+    fn collisions_start_and_stop() {
+        let mut world = World::new();
+        let spawn = |world: &mut World, x: f32, collider: Collider| {
+            world.spawn(
+                GameObject::new()
+                    .with_mesh(MeshId::from_bits(0))
+                    .with_position(Vector3::new(x, 0.0, 0.0))
+                    .with_collider(collider),
+            )
+        };
+        let a = spawn(&mut world, 0.0, Collider::aabb(Vector2::new(0.05, 0.05)));
+        let b = spawn(&mut world, 0.08, Collider::circle(0.05));
+        let far = spawn(&mut world, 1.0, Collider::circle(0.05));
+        // spans every cell in between
+        let wide = spawn(&mut world, 5.0, Collider::aabb(Vector2::new(4.96, 0.01)));
+
+        let mut collisions = CollisionSystem::new(Some(0.1));
+        assert_eq!(
+            collisions.on_update(&world),
+            [
+                CollisionEvent::Started(a, b),
+                CollisionEvent::Started(a, wide),
+                CollisionEvent::Started(b, wide),
+                CollisionEvent::Started(far, wide),
+            ]
+        );
+        assert_eq!(collisions.on_update(&world), []);
+
+        world.get_mut::<Transform>(b).unwrap().position.x = 0.2;
+        world.despawn(wide);
+        assert_eq!(
+            collisions.on_update(&world),
+            [
+                CollisionEvent::Stopped(a, b),
+                CollisionEvent::Stopped(a, wide),
+                CollisionEvent::Stopped(b, wide),
+                CollisionEvent::Stopped(far, wide),
+            ]
+        );
+    }
+
+    #[test]
+    fn quad_colliders_follow_the_scale() {
+        let mut world = World::new();
+        let collider = Collider::aabb(Vector2::new(0.5, 0.5));
+        // the quads are centered at x = 2 and x = 2.9
+        for (x, scale) in [(1.0, 2.0), (10.0, 0.29)] {
+            world.spawn(
+                GameObject::new()
+                    .with_position(Vector3::new(x, 0.0, 0.0))
+                    .with_scale(Vector3::new(scale, 1.0, 1.0))
+                    .with_collider(collider),
+            );
+        }
+        let mut collisions = CollisionSystem::new(Some(0.0));
+        assert_eq!(collisions.on_update(&world).len(), 1);
+    }
+
+    #[test]
+    fn cells_are_sized_after_the_common_colliders() {
+        let mut world = World::new();
+        for x in 0..10 {
+            world.spawn(
+                GameObject::new()
+                    .with_mesh(MeshId::from_bits(0))
+                    .with_position(Vector3::new(x as f32 * 3.0, 0.0, 0.0))
+                    .with_collider(Collider::circle(1.0)),
+            );
+        }
+        let ground = world.spawn(
+            GameObject::new()
+                .with_mesh(MeshId::from_bits(0))
+                .with_collider(Collider::aabb(Vector2::new(100.0, 0.5))),
+        );
+
+        let mut collisions = CollisionSystem::new(None);
+        assert_eq!(collisions.on_update(&world).len(), 10);
+        assert_eq!(collisions.cell_size, 2.0);
+        // only the ground spans too many cells
+        assert_eq!(collisions.oversized, [10]);
+        assert_eq!(collisions.bodies[10].entity, ground);
+    }
+}
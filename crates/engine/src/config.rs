@@ -0,0 +1,158 @@
+//! Runtime configuration of the engine, e.g. given on the command line.
+//!
+//! Binaries parse the options with `EngineConfig::from_args()`, which needs
+//! the `cli` feature, and apply them with `EngineBuilder::with_config()`. The
+//! scene file and the log level are left to the binary, which loads the scene
+//! and initializes its logger.
+//!
+//! ```text
+//! sandbox --window-size 1280x720 --vsync --gpu 1 --log-level debug
+//! ```
+
+use std::path::PathBuf;
+
+use log::LevelFilter;
+use winit::dpi::LogicalSize;
+
+use crate::frame_counter::FpsAverage;
+
+/// Options of the engine. Options left unset keep the values of the builder.
+#[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "cli", derive(clap::Parser))]
+// NOTE: the name is set explicitly, the default one being read with a
+//       macro of `::core`, which is shadowed by the core crate
+#[cfg_attr(feature = "cli", command(name = "engine"))]
+pub struct EngineConfig {
+    /// Size of the main window in logical pixels, e.g. 1280x720.
+    #[cfg_attr(
+        feature = "cli",
+        arg(long, value_name = "WIDTHxHEIGHT", value_parser = parse_window_size)
+    )]
+    pub window_size: Option<LogicalSize<u32>>,
+    /// Starts borderless fullscreen on the monitor of the window.
+    #[cfg_attr(feature = "cli", arg(long))]
+    pub fullscreen: bool,
+    /// Caps the frame rate to the refresh rate of the display.
+    #[cfg_attr(feature = "cli", arg(long))]
+    pub vsync: bool,
+    /// Index of the GPU to render with, in the order Vulkan enumerates them.
+    #[cfg_attr(feature = "cli", arg(long, value_name = "INDEX"))]
+    pub gpu: Option<usize>,
+    /// Enables or disables the Vulkan validation layers.
+    #[cfg_attr(feature = "cli", arg(long, value_name = "true|false"))]
+    pub validation: Option<bool>,
+    /// Averaging of the fps logged at the debug level.
+    #[cfg_attr(feature = "cli", arg(long, value_enum, value_name = "STRATEGY"))]
+    pub fps_average: Option<FpsAverage>,
+    /// File the settings of the UI, e.g. the layout of its windows, are kept
+    /// in across runs.
+    #[cfg_attr(feature = "cli", arg(long, value_name = "PATH"))]
+    pub ui_settings: Option<PathBuf>,
+    /// Directory the state of the engine, e.g. the startup failures counted
+    /// to enter safe mode, is kept in across runs.
+    #[cfg_attr(feature = "cli", arg(long, value_name = "PATH"))]
+    pub config_dir: Option<PathBuf>,
+    /// Scene file to load at startup.
+    #[cfg_attr(feature = "cli", arg(long, value_name = "PATH"))]
+    pub scene: Option<PathBuf>,
+    /// Most verbose level of the messages logged, e.g. info or debug.
+    #[cfg_attr(
+        feature = "cli",
+        arg(long, value_name = "LEVEL", value_parser = parse_log_level)
+    )]
+    pub log_level: Option<LevelFilter>,
+}
+
+#[cfg(feature = "cli")]
+impl EngineConfig {
+    /// Parses the options from the command line arguments of the process.
+    /// Prints the usage and exits when asked for help or when the arguments
+    /// are not valid.
+    pub fn from_args() -> Self {
+        <Self as clap::Parser>::parse()
+    }
+}
+
+/// Parses a window size written as `WIDTHxHEIGHT`.
+#[cfg(feature = "cli")]
+fn parse_window_size(s: &str) -> Result<LogicalSize<u32>, String> {
+    let (width, height) = s
+        .split_once('x')
+        .ok_or_else(|| format!("{s:?} is not WIDTHxHEIGHT"))?;
+    let parse = |n: &str| n.trim().parse::<u32>().map_err(|e| format!("{n:?}: {e}"));
+    let size = LogicalSize::new(parse(width)?, parse(height)?);
+    if size.width == 0 || size.height == 0 {
+        return Err(format!("{s:?} is empty"));
+    }
+    Ok(size)
+}
+
+/// Parses a log level, one of off, error, warn, info, debug and trace.
+#[cfg(feature = "cli")]
+fn parse_log_level(s: &str) -> Result<LevelFilter, String> {
+    s.parse()
+        .map_err(|_| format!("{s:?} is not one of off, error, warn, info, debug, trace"))
+}
+
+#[cfg(all(test, feature = "cli"))]
+mod tests {
+    use clap::Parser;
+
+    use super::*;
+
+    #[test]
+    fn options_are_parsed_from_the_arguments() {
+        let config = EngineConfig::try_parse_from(["sandbox"]).unwrap();
+        assert_eq!(config, EngineConfig::default());
+
+        let config = EngineConfig::try_parse_from([
+            "sandbox",
+            "--window-size",
+            "1280x720",
+            "--fullscreen",
+            "--vsync",
+            "--gpu",
+            "1",
+            "--validation",
+            "false",
+            "--fps-average",
+            "windowed",
+            "--ui-settings",
+            "imgui.ini",
+            "--config-dir",
+            "state",
+            "--scene",
+            "level.prefab",
+            "--log-level",
+            "debug",
+        ])
+        .unwrap();
+        assert_eq!(
+            config,
+            EngineConfig {
+                window_size: Some(LogicalSize::new(1280, 720)),
+                fullscreen: true,
+                vsync: true,
+                gpu: Some(1),
+                validation: Some(false),
+                fps_average: Some(FpsAverage::Windowed),
+                ui_settings: Some(PathBuf::from("imgui.ini")),
+                config_dir: Some(PathBuf::from("state")),
+                scene: Some(PathBuf::from("level.prefab")),
+                log_level: Some(LevelFilter::Debug),
+            }
+        );
+
+        assert!(EngineConfig::try_parse_from(["sandbox", "--gpu", "first"]).is_err());
+        assert!(EngineConfig::try_parse_from(["sandbox", "--log-level", "loud"]).is_err());
+        assert!(EngineConfig::try_parse_from(["sandbox", "--fps-average", "median"]).is_err());
+    }
+
+    #[test]
+    fn window_size_is_width_by_height() {
+        assert_eq!(parse_window_size("800x600"), Ok(LogicalSize::new(800, 600)));
+        assert!(parse_window_size("800").is_err());
+        assert!(parse_window_size("800x").is_err());
+        assert!(parse_window_size("0x600").is_err());
+    }
+}
@@ -0,0 +1,302 @@
+//! Display modes of the main window, and the monitors and video modes it can
+//! be shown on.
+//!
+//! The mode is set with `EngineBuilder::with_display_mode()`, or while running
+//! with `ApplicationContext::set_display_mode()`, and Alt+Enter toggles between
+//! windowed and fullscreen. Changing the mode resizes the window, and the
+//! renderer recreates its swapchain like for any resize.
+
+use log::{info, warn};
+use winit::dpi::{PhysicalPosition, PhysicalSize};
+use winit::event::{Event, VirtualKeyCode, WindowEvent};
+use winit::monitor::{MonitorHandle, VideoMode};
+use winit::window::{Fullscreen, Window};
+
+use crate::hotkey::Hotkey;
+
+/// Key toggling fullscreen along with Alt.
+const TOGGLE_KEY: VirtualKeyCode = VirtualKeyCode::Return;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum DisplayMode {
+    #[default]
+    Windowed,
+    /// Window without decorations covering a monitor, keeping its video mode.
+    /// Monitors are indices in `ApplicationContext::monitors()`, None being
+    /// the monitor of the window.
+    Borderless { monitor: Option<usize> },
+    /// Monitor switched to a video mode of its own, the best one if None.
+    Exclusive {
+        monitor: Option<usize>,
+        video_mode: Option<VideoModeInfo>,
+    },
+}
+
+impl DisplayMode {
+    pub fn is_fullscreen(&self) -> bool {
+        *self != Self::Windowed
+    }
+}
+
+/// Video mode of a monitor, see `MonitorInfo::video_modes`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct VideoModeInfo {
+    pub size: PhysicalSize<u32>,
+    /// Bits per pixel.
+    pub bit_depth: u16,
+    pub refresh_rate_millihertz: u32,
+}
+
+impl VideoModeInfo {
+    fn new(mode: &VideoMode) -> Self {
+        Self {
+            size: mode.size(),
+            bit_depth: mode.bit_depth(),
+            refresh_rate_millihertz: mode.refresh_rate_millihertz(),
+        }
+    }
+
+    /// Returns a key ordering modes by resolution, then refresh rate, then
+    /// bit depth.
+    fn rank(&self) -> (u64, u32, u16) {
+        let pixels = self.size.width as u64 * self.size.height as u64;
+        (pixels, self.refresh_rate_millihertz, self.bit_depth)
+    }
+}
+
+/// Monitor connected when the monitors were last enumerated.
+#[derive(Clone, Debug, PartialEq)]
+pub struct MonitorInfo {
+    pub name: Option<String>,
+    /// Resolution of the current video mode.
+    pub size: PhysicalSize<u32>,
+    /// Position of the top left corner on the desktop.
+    pub position: PhysicalPosition<i32>,
+    pub scale_factor: f64,
+    pub refresh_rate_millihertz: Option<u32>,
+    pub video_modes: Vec<VideoModeInfo>,
+}
+
+impl MonitorInfo {
+    fn new(monitor: &MonitorHandle) -> Self {
+        Self {
+            name: monitor.name(),
+            size: monitor.size(),
+            position: monitor.position(),
+            scale_factor: monitor.scale_factor(),
+            refresh_rate_millihertz: monitor.refresh_rate_millihertz(),
+            video_modes: monitor
+                .video_modes()
+                .map(|m| VideoModeInfo::new(&m))
+                .collect(),
+        }
+    }
+}
+
+/// Display mode of the main window.
+#[derive(Debug)]
+pub(crate) struct Display {
+    monitors: Vec<MonitorHandle>,
+    infos: Vec<MonitorInfo>,
+    mode: DisplayMode,
+    /// Mode toggled to from windowed, the last fullscreen mode.
+    fullscreen: DisplayMode,
+    toggle: Hotkey,
+    /// Set while Alt is held.
+    alt: bool,
+}
+
+impl Display {
+    /// Creates the display of a windowed window, toggling fullscreen on
+    /// Alt+Enter if `toggle` is true.
+    pub fn new(toggle: bool) -> Self {
+        Self {
+            monitors: Vec::new(),
+            infos: Vec::new(),
+            mode: DisplayMode::Windowed,
+            fullscreen: DisplayMode::Borderless { monitor: None },
+            toggle: Hotkey::new(toggle.then_some(TOGGLE_KEY)),
+            alt: false,
+        }
+    }
+
+    pub fn monitors(&self) -> &[MonitorInfo] {
+        &self.infos
+    }
+
+    pub fn mode(&self) -> DisplayMode {
+        self.mode
+    }
+
+    /// Enumerates the monitors, e.g. once one is connected.
+    pub fn refresh_monitors(&mut self, window: &Window) {
+        self.monitors = window.available_monitors().collect();
+        self.infos = self.monitors.iter().map(MonitorInfo::new).collect();
+    }
+
+    /// Returns the mode to switch to if the event presses Alt+Enter.
+    pub fn on_event<T>(&mut self, event: &Event<T>) -> Option<DisplayMode> {
+        if let Event::WindowEvent {
+            event: WindowEvent::ModifiersChanged(modifiers),
+            ..
+        } = event
+        {
+            self.alt = modifiers.alt();
+        }
+        (self.toggle.on_event(event) && self.alt).then(|| self.toggled())
+    }
+
+    /// Returns windowed when fullscreen, else the last fullscreen mode.
+    fn toggled(&self) -> DisplayMode {
+        if self.mode.is_fullscreen() {
+            DisplayMode::Windowed
+        } else {
+            self.fullscreen
+        }
+    }
+
+    /// Switches the window to a mode. Modes of a monitor or a video mode that
+    /// is not connected anymore are logged and ignored.
+    pub fn set_mode(&mut self, window: &Window, mode: DisplayMode) {
+        if mode == self.mode {
+            return;
+        }
+        match self.fullscreen_of(window, mode) {
+            Ok(fullscreen) => {
+                info!("display mode: {mode:?}");
+                window.set_fullscreen(fullscreen);
+                self.mode = mode;
+                if mode.is_fullscreen() {
+                    self.fullscreen = mode;
+                }
+            }
+            Err(e) => warn!("set display mode {mode:?}: {e}"),
+        }
+    }
+
+    fn fullscreen_of(
+        &mut self,
+        window: &Window,
+        mode: DisplayMode,
+    ) -> Result<Option<Fullscreen>, String> {
+        // NOTE: monitors may have been connected since they were enumerated
+        self.refresh_monitors(window);
+        let monitor = |index: Option<usize>| -> Result<Option<MonitorHandle>, String> {
+            match index {
+                Some(i) => self
+                    .monitors
+                    .get(i)
+                    .cloned()
+                    .map(Some)
+                    .ok_or_else(|| format!("no monitor {i}")),
+                None => Ok(window.current_monitor()),
+            }
+        };
+        match mode {
+            DisplayMode::Windowed => Ok(None),
+            DisplayMode::Borderless { monitor: index } => {
+                Ok(Some(Fullscreen::Borderless(monitor(index)?)))
+            }
+            DisplayMode::Exclusive {
+                monitor: index,
+                video_mode,
+            } => {
+                let monitor = monitor(index)?.ok_or("no monitor")?;
+                let mut modes = monitor.video_modes().map(|m| (VideoModeInfo::new(&m), m));
+                let mode = match video_mode {
+                    Some(wanted) => modes.find(|(info, _)| *info == wanted),
+                    None => best_video_mode(modes),
+                };
+                let (_, mode) = mode.ok_or("video mode not supported by the monitor")?;
+                Ok(Some(Fullscreen::Exclusive(mode)))
+            }
+        }
+    }
+}
+
+/// Returns the mode of the highest resolution, then refresh rate.
+fn best_video_mode<T>(
+    modes: impl IntoIterator<Item = (VideoModeInfo, T)>,
+) -> Option<(VideoModeInfo, T)> {
+    modes.into_iter().max_by_key(|(info, _)| info.rank())
+}
+
+#[cfg(test)]
+mod tests {
+    use winit::event::{DeviceId, ElementState, KeyboardInput, ModifiersState};
+    use winit::window::WindowId;
+
+    use super::*;
+
+    fn window_event(event: WindowEvent<'static>) -> Event<'static, ()> {
+        Event::WindowEvent {
+            window_id: unsafe { WindowId::dummy() },
+            event,
+        }
+    }
+
+    #[allow(deprecated)]
+    fn enter(state: ElementState) -> Event<'static, ()> {
+        window_event(WindowEvent::KeyboardInput {
+            device_id: unsafe { DeviceId::dummy() },
+            input: KeyboardInput {
+                scancode: 0,
+                state,
+                virtual_keycode: Some(TOGGLE_KEY),
+                modifiers: Default::default(),
+            },
+            is_synthetic: false,
+        })
+    }
+
+    #[test]
+    fn alt_enter_toggles_the_last_fullscreen_mode() {
+        let mut display = Display::new(true);
+        assert_eq!(display.on_event(&enter(ElementState::Pressed)), None);
+        display.on_event(&enter(ElementState::Released));
+
+        display.on_event(&window_event(WindowEvent::ModifiersChanged(
+            ModifiersState::ALT,
+        )));
+        let borderless = DisplayMode::Borderless { monitor: None };
+        assert_eq!(
+            display.on_event(&enter(ElementState::Pressed)),
+            Some(borderless)
+        );
+
+        // back to windowed, then to the last fullscreen mode
+        let exclusive = DisplayMode::Exclusive {
+            monitor: Some(1),
+            video_mode: None,
+        };
+        display.mode = exclusive;
+        display.fullscreen = exclusive;
+        assert_eq!(display.toggled(), DisplayMode::Windowed);
+        display.mode = DisplayMode::Windowed;
+        assert_eq!(display.toggled(), exclusive);
+
+        let mut disabled = Display::new(false);
+        disabled.on_event(&window_event(WindowEvent::ModifiersChanged(
+            ModifiersState::ALT,
+        )));
+        assert_eq!(disabled.on_event(&enter(ElementState::Pressed)), None);
+    }
+
+    #[test]
+    fn best_video_mode_has_the_highest_resolution() {
+        let mode = |width: u32, height: u32, hz: u32| VideoModeInfo {
+            size: PhysicalSize::new(width, height),
+            bit_depth: 32,
+            refresh_rate_millihertz: hz * 1000,
+        };
+        let modes = [
+            mode(1920, 1080, 60),
+            mode(2560, 1440, 60),
+            mode(1920, 1080, 144),
+            mode(2560, 1440, 120),
+        ];
+        let best = best_video_mode(modes.iter().map(|m| (*m, ())));
+        assert_eq!(best, Some((mode(2560, 1440, 120), ())));
+        assert_eq!(best_video_mode(Vec::<(VideoModeInfo, ())>::new()), None);
+    }
+}
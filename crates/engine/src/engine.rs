@@ -1,23 +1,186 @@
-use core::object::GameObject;
-use std::time;
+use core::ecs::{Bundle, World};
+use core::handle::HandleMap;
+use core::jobs::{self, JobPool};
+use core::mesh::{Mesh, MeshError, MeshId};
+use core::object::{MeshView, ObjectId, ObjectMut, ObjectView, QuadView};
+use core::prefab::{Prefab, PrefabLibrary};
+use core::tween::{Tween, Tweens};
+use std::cell::RefCell;
+#[cfg(feature = "metrics")]
+use std::net::SocketAddr;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::{mem, result, time};
 
+use ash::vk;
 use camera::{CameraController, CameraOrthographic};
-use input::InputSystem;
-use log::{debug, error};
-use vulkan_renderer::renderer::VulkanRenderer;
+use cgmath::{Vector3, Vector4};
+use input::{InputMap, InputSystem};
+use log::{debug, error, info, warn};
+#[cfg(feature = "imgui")]
+use vulkan_imgui::{imgui, Font as UiFont, UiOptions};
+use vulkan_renderer::renderer::{RendererSettings, VulkanRenderer};
+use vulkan_renderer_2d::compositor::{AntiAliasing, Compositor};
+use vulkan_renderer_2d::culling::CulledRenderer2D;
+#[cfg(feature = "shader-hot-reload")]
+use vulkan_renderer_2d::hot_reload::{ShaderWatcher, DEFAULT_SHADER_DIR};
+use vulkan_renderer_2d::transition::TransitionRenderer;
 use vulkan_renderer_2d::Renderer2DSystem;
+use vulkan_renderer_3d::Renderer3DSystem;
 use winit::dpi::PhysicalSize;
+#[cfg(any(feature = "renderdoc", feature = "editor-tools"))]
+use winit::event::VirtualKeyCode;
 use winit::event::{Event, WindowEvent};
-use winit::event_loop::{ControlFlow, EventLoop};
-use winit::window::WindowBuilder;
+use winit::event_loop::{ControlFlow, EventLoop, EventLoopWindowTarget};
+use winit::platform::run_return::EventLoopExtRunReturn;
+use winit::window::{CursorGrabMode, CursorIcon, Window, WindowBuilder, WindowId};
 
-use crate::frame_counter::{ExponentialMovingAverage, FPSPrinter, FrameCounter};
+use crate::alloc_audit::{self, Subsystem};
+#[cfg(feature = "renderdoc")]
+use crate::capture::{FrameCapture, DEFAULT_CAPTURE_KEY};
+use crate::collision::CollisionEvent;
+use crate::config::EngineConfig;
+use crate::display::{Display, DisplayMode, MonitorInfo};
+use crate::error::EngineError;
+use crate::events::EventBus;
+#[cfg(feature = "editor-tools")]
+use crate::frame_counter;
+use crate::frame_counter::{FPSPrinter, FpsAverage, FrameCounter, FrameTimeStats, FrameWatchdog};
+use crate::frame_limiter::{FrameLimit, FrameLimiter};
+#[cfg(feature = "editor-tools")]
+use crate::frame_stats::DEFAULT_FRAME_STATS_KEY;
+use crate::frame_stats::{self, FrameStats, FrameStatsRecorder};
+use crate::game_clock::GameClock;
+use crate::headless::{HeadlessConfig, HeadlessEngine};
+#[cfg(feature = "editor-tools")]
+use crate::hotkey::Hotkey;
+use crate::input_latency::InputLatencyTracker;
+use crate::layer::{LayerSettings, LayerTarget};
+#[cfg(feature = "editor-tools")]
+use crate::memory_hud;
+#[cfg(feature = "metrics")]
+use crate::metrics::{self, Metrics, MetricsExporter};
+use crate::panic_guard::{catch_panics, PanicHook};
+use crate::pass::{CustomPass, PassRegistry, PassStage};
+#[cfg(feature = "physics")]
+use crate::physics::PhysicsSettings;
+use crate::profiler;
+#[cfg(all(feature = "profile-with-puffin", feature = "editor-tools"))]
+use crate::profiler::ProfilerHud;
+use crate::render_callback::{RenderCallback, RenderCallbacks};
+use crate::renderer_system::{RendererSystem, RendererSystems};
+#[cfg(feature = "editor-tools")]
+use crate::ruler::{Ruler, DEFAULT_RULER_KEY};
+use crate::safe_mode::{self, StartupTracker};
+use crate::screen_transition::{Overlay, ScreenTransition, TransitionPlayer};
+use crate::simulation::Simulation;
+use crate::sprites::SpriteTextures;
+use crate::state::{State, StateStack, Transition};
+use crate::stress::StressScene;
 use crate::Result;
 
-#[derive(Default)]
+/// Frames taking longer than this are logged, along with the recent frame
+/// times and allocation counts.
+pub const DEFAULT_FRAME_SPIKE_THRESHOLD: time::Duration = time::Duration::from_millis(50);
+
+/// Key saving a screenshot of the next frame in the working directory by
+/// default.
+#[cfg(feature = "editor-tools")]
+pub const DEFAULT_SCREENSHOT_KEY: VirtualKeyCode = VirtualKeyCode::F2;
+
 pub struct EngineBuilder {
     app: Option<Box<dyn Application>>,
     wb: Option<WindowBuilder>,
+    additional_windows: Vec<WindowBuilder>,
+    display_mode: DisplayMode,
+    fullscreen_toggle: bool,
+    passes: Vec<Box<dyn CustomPass>>,
+    renderer_systems: Vec<Box<dyn RendererSystem>>,
+    renderer_settings: RendererSettings,
+    clear_color: Vector4<f32>,
+    world_layer: LayerSettings,
+    anti_aliasing: AntiAliasing,
+    screen_transition: Option<ScreenTransition>,
+    gpu_culling: bool,
+    depth_prepass: bool,
+    stress_scene: Option<StressScene>,
+    collision_cell_size: Option<f32>,
+    worker_threads: usize,
+    #[cfg(feature = "physics")]
+    physics: PhysicsSettings,
+    frame_spike_threshold: Option<time::Duration>,
+    fps_average: FpsAverage,
+    frame_limit: Option<FrameLimit>,
+    input_latency: bool,
+    input_map: InputMap,
+    prefabs: PrefabLibrary,
+    sprite_textures: Vec<PathBuf>,
+    config_dir: Option<PathBuf>,
+    #[cfg(feature = "metrics")]
+    metrics_address: Option<SocketAddr>,
+    #[cfg(feature = "renderdoc")]
+    capture_key: Option<VirtualKeyCode>,
+    #[cfg(feature = "editor-tools")]
+    screenshot_key: Option<VirtualKeyCode>,
+    #[cfg(feature = "editor-tools")]
+    frame_stats_key: Option<VirtualKeyCode>,
+    #[cfg(feature = "editor-tools")]
+    ruler_key: Option<VirtualKeyCode>,
+    #[cfg(feature = "imgui")]
+    ui_options: UiOptions,
+    #[cfg(feature = "shader-hot-reload")]
+    shader_dir: Option<PathBuf>,
+    headless_extent: Option<vk::Extent2D>,
+}
+
+impl Default for EngineBuilder {
+    fn default() -> Self {
+        Self {
+            app: None,
+            wb: None,
+            additional_windows: Vec::new(),
+            display_mode: DisplayMode::Windowed,
+            fullscreen_toggle: true,
+            passes: Vec::new(),
+            renderer_systems: Vec::new(),
+            renderer_settings: RendererSettings::default(),
+            clear_color: Vector4::new(0.0, 0.0, 0.0, 0.0),
+            world_layer: LayerSettings::default(),
+            anti_aliasing: AntiAliasing::None,
+            screen_transition: None,
+            gpu_culling: false,
+            depth_prepass: false,
+            stress_scene: None,
+            collision_cell_size: None,
+            worker_threads: jobs::default_workers(),
+            #[cfg(feature = "physics")]
+            physics: PhysicsSettings::default(),
+            frame_spike_threshold: Some(DEFAULT_FRAME_SPIKE_THRESHOLD),
+            fps_average: FpsAverage::default(),
+            frame_limit: None,
+            input_latency: false,
+            input_map: InputMap::new(),
+            prefabs: PrefabLibrary::new(),
+            sprite_textures: Vec::new(),
+            config_dir: safe_mode::default_config_dir(),
+            #[cfg(feature = "metrics")]
+            metrics_address: Some(metrics::default_metrics_address()),
+            #[cfg(feature = "renderdoc")]
+            capture_key: Some(DEFAULT_CAPTURE_KEY),
+            #[cfg(feature = "editor-tools")]
+            screenshot_key: Some(DEFAULT_SCREENSHOT_KEY),
+            #[cfg(feature = "editor-tools")]
+            frame_stats_key: Some(DEFAULT_FRAME_STATS_KEY),
+            #[cfg(feature = "editor-tools")]
+            ruler_key: Some(DEFAULT_RULER_KEY),
+            #[cfg(feature = "imgui")]
+            ui_options: UiOptions::default(),
+            #[cfg(feature = "shader-hot-reload")]
+            shader_dir: Some(PathBuf::from(DEFAULT_SHADER_DIR)),
+            headless_extent: None,
+        }
+    }
 }
 
 impl EngineBuilder {
@@ -28,6 +191,7 @@ impl EngineBuilder {
         Self {
             app: Some(app),
             wb: Some(wb),
+            ..Default::default()
         }
     }
 
@@ -43,18 +207,503 @@ impl EngineBuilder {
         self
     }
 
+    /// Opens another window showing the world through a camera of its own.
+    /// Windows share the device and the frames of the main window, and are
+    /// closed independently of it. Custom passes, render systems and render
+    /// callbacks are recorded in every window, see `PassContext::window()`.
+    /// The UI, the world layer and GPU culling only apply to the main window.
+    #[inline]
+    pub fn with_additional_window(mut self, wb: WindowBuilder) -> Self {
+        self.additional_windows.push(wb);
+        self
+    }
+
+    /// Sets the display mode of the main window at startup, windowed by
+    /// default. It can be changed while running with
+    /// `ApplicationContext::set_display_mode()`.
+    #[inline]
+    pub fn with_display_mode(mut self, mode: DisplayMode) -> Self {
+        self.display_mode = mode;
+        self
+    }
+
+    /// Toggles between windowed and the last fullscreen mode on Alt+Enter.
+    /// Enabled by default.
+    #[inline]
+    pub fn with_fullscreen_toggle(mut self, toggle: bool) -> Self {
+        self.fullscreen_toggle = toggle;
+        self
+    }
+
+    /// Registers a custom render pass. Passes of the same stage are recorded
+    /// in registration order.
+    #[inline]
+    pub fn with_pass(mut self, pass: Box<dyn CustomPass>) -> Self {
+        self.passes.push(pass);
+        self
+    }
+
+    /// Registers a render system, drawn with the device and command buffers
+    /// of the engine. Systems of the same stage are recorded in registration
+    /// order, after the custom passes of that stage.
+    #[inline]
+    pub fn with_renderer_system(mut self, system: Box<dyn RendererSystem>) -> Self {
+        self.renderer_systems.push(system);
+        self
+    }
+
+    /// Sets the number of frames the CPU can record ahead of the GPU. It must
+    /// be at least 1 and at most the number of swapchain images.
+    #[inline]
+    pub fn with_frames_in_flight(mut self, frames_in_flight: u32) -> Self {
+        self.renderer_settings.frames_in_flight = frames_in_flight;
+        self
+    }
+
+    /// Enables Vulkan validation layers. Defaults to true in debug builds.
+    /// The engine runs without validation when the layers are not installed
+    /// or the `validation` feature is disabled.
+    #[inline]
+    pub fn with_validation(mut self, validation: bool) -> Self {
+        self.renderer_settings.validation = validation;
+        self
+    }
+
+    /// Presents frames in FIFO mode, capping the frame rate to the refresh
+    /// rate of the display.
+    #[inline]
+    pub fn with_vsync(mut self, vsync: bool) -> Self {
+        self.renderer_settings.vsync = vsync;
+        self
+    }
+
+    /// Renders with the GPU at the given index, in the order Vulkan
+    /// enumerates them. Defaults to the first suitable GPU.
+    #[inline]
+    pub fn with_gpu(mut self, gpu: Option<usize>) -> Self {
+        self.renderer_settings.gpu = gpu;
+        self
+    }
+
+    /// Applies the options of a configuration, e.g. parsed from the command
+    /// line. The window size only applies once a window builder is set.
+    /// Options left unset keep the values set so far.
+    pub fn with_config(mut self, config: &EngineConfig) -> Self {
+        if let Some(size) = config.window_size {
+            self.wb = self.wb.map(|wb| wb.with_inner_size(size));
+        }
+        if config.fullscreen {
+            self.display_mode = DisplayMode::Borderless { monitor: None };
+        }
+        if config.vsync {
+            self.renderer_settings.vsync = true;
+        }
+        if let Some(gpu) = config.gpu {
+            self.renderer_settings.gpu = Some(gpu);
+        }
+        if let Some(validation) = config.validation {
+            self.renderer_settings.validation = validation;
+        }
+        if let Some(fps_average) = config.fps_average {
+            self.fps_average = fps_average;
+        }
+        #[cfg(feature = "imgui")]
+        if let Some(path) = &config.ui_settings {
+            self.ui_options.ini_path = Some(path.clone());
+        }
+        if let Some(dir) = &config.config_dir {
+            self.config_dir = Some(dir.clone());
+        }
+        self
+    }
+
+    /// Sets the color the windows are cleared to before the frame is drawn,
+    /// transparent black by default. It can be changed while running with
+    /// `ApplicationContext::set_clear_color()`.
+    #[inline]
+    pub fn with_clear_color(mut self, color: Vector4<f32>) -> Self {
+        self.clear_color = color;
+        self
+    }
+
+    /// Sets the resolution and refresh rate of the world, drawn along with the
+    /// custom passes of the world stages. A world other than native is drawn
+    /// to a target of its own and scaled to the window in the main render
+    /// pass, while the UI is drawn at the window resolution every frame. The
+    /// settings can be changed while running with
+    /// `ApplicationContext::set_world_layer()`.
+    #[inline]
+    pub fn with_world_layer(mut self, settings: LayerSettings) -> Self {
+        self.world_layer = settings;
+        self
+    }
+
+    /// Sets the anti-aliasing of the world. When enabled, the world is drawn
+    /// to a target of its own and anti-aliased while it is composited. The UI
+    /// is drawn as is.
+    #[inline]
+    pub fn with_anti_aliasing(mut self, anti_aliasing: AntiAliasing) -> Self {
+        self.anti_aliasing = anti_aliasing;
+        self
+    }
+
+    /// Sets the effect played over the world when the states switch, none by
+    /// default, see `screen_transition`. It can be changed while running with
+    /// `ApplicationContext::set_screen_transition()`.
+    #[inline]
+    pub fn with_screen_transition(mut self, transition: Option<ScreenTransition>) -> Self {
+        self.screen_transition = transition;
+        self
+    }
+
+    /// Culls the objects against the camera on the GPU and draws the visible
+    /// ones with a single indirect draw, instead of batching them on the CPU
+    /// every frame. Meant for scenes of many objects, most of them off
+    /// screen.
+    ///
+    /// Objects sharing a depth may overlap in any order, and render callbacks
+    /// are recorded after all the objects rather than after their own. The
+    /// objects are drawn with flat colors: running the engine fails if sprite
+    /// textures are added using `with_sprite_texture()`.
+    #[inline]
+    pub fn with_gpu_culling(mut self, gpu_culling: bool) -> Self {
+        self.gpu_culling = gpu_culling;
+        self
+    }
+
+    /// Draws the depth of opaque objects before their color, so that the
+    /// fragments hidden by other objects are not shaded. Worth it for scenes
+    /// of many overlapping objects.
+    #[inline]
+    pub fn with_depth_prepass(mut self, depth_prepass: bool) -> Self {
+        self.depth_prepass = depth_prepass;
+        self
+    }
+
+    /// Spawns a reproducible random scene of quads after the application is
+    /// initialized, to measure the renderer on a consistent workload. Use
+    /// None to disable it.
+    #[inline]
+    pub fn with_stress_scene(mut self, scene: Option<StressScene>) -> Self {
+        self.stress_scene = scene;
+        self
+    }
+
+    /// Sets the directory where the engine keeps its state across runs, such
+    /// as the startup failure count used to enter safe mode, by default the
+    /// `toy-engine` directory of the user configuration (see
+    /// `safe_mode::default_config_dir()`). Use None to disable safe mode.
+    #[inline]
+    pub fn with_config_dir(mut self, config_dir: Option<PathBuf>) -> Self {
+        self.config_dir = config_dir;
+        self
+    }
+
+    /// Sets the number of threads running jobs besides the main thread, e.g.
+    /// to build the quad batches, see `ApplicationContext::jobs()`. Defaults
+    /// to one less than the number of cores.
+    #[inline]
+    pub fn with_worker_threads(mut self, threads: usize) -> Self {
+        self.worker_threads = threads;
+        self
+    }
+
+    /// Sets the size of the cells of the grid colliders are sorted into, in
+    /// world units. Best about the size of the common colliders, which the
+    /// cells are sized after by default.
+    #[inline]
+    pub fn with_collision_cell_size(mut self, cell_size: f32) -> Self {
+        self.collision_cell_size = Some(cell_size);
+        self
+    }
+
+    /// Sets the gravity of the physics and the material of the rigid bodies
+    /// without one.
+    #[cfg(feature = "physics")]
+    #[inline]
+    pub fn with_physics(mut self, settings: PhysicsSettings) -> Self {
+        self.physics = settings;
+        self
+    }
+
+    /// Sets the duration above which a frame is logged as a spike. Use None to
+    /// disable the frame watchdog.
+    #[inline]
+    pub fn with_frame_spike_threshold(mut self, threshold: Option<time::Duration>) -> Self {
+        self.frame_spike_threshold = threshold;
+        self
+    }
+
+    /// Sets how the fps logged at the debug level are averaged, an
+    /// exponential moving average by default.
+    #[inline]
+    pub fn with_fps_average(mut self, fps_average: FpsAverage) -> Self {
+        self.fps_average = fps_average;
+        self
+    }
+
+    /// Caps the frame rate, to save power when frames do not need to be
+    /// rendered as fast as possible. With vsync disabled, frames are presented
+    /// in MAILBOX mode and paced by the limiter alone, without tearing. With
+    /// vsync, frames are also paced by the display. Use None to render frames
+    /// as fast as possible. The limit can be changed while running with
+    /// `ApplicationContext::set_frame_limit()`.
+    #[inline]
+    pub fn with_frame_limit(mut self, limit: Option<FrameLimit>) -> Self {
+        self.frame_limit = limit;
+        self
+    }
+
+    /// Measures the time between the arrival of input events and the present
+    /// of the frame that handled them. Percentiles are shown in the HUD and
+    /// logged on exit.
+    #[inline]
+    pub fn with_input_latency(mut self, input_latency: bool) -> Self {
+        self.input_latency = input_latency;
+        self
+    }
+
+    /// Sets the bindings of the camera actions, see `camera::actions`. They
+    /// replace the default bindings of the actions they bind, e.g. bindings
+    /// loaded with `InputMap::load()`.
+    #[inline]
+    pub fn with_input_map(mut self, input_map: InputMap) -> Self {
+        self.input_map = input_map;
+        self
+    }
+
+    /// Sets the prefabs the application spawns by name, e.g. loaded with
+    /// `PrefabLibrary::load()`, see `ApplicationContext::spawn_prefab()`.
+    #[inline]
+    pub fn with_prefabs(mut self, prefabs: PrefabLibrary) -> Self {
+        self.prefabs = prefabs;
+        self
+    }
+
+    /// Adds a texture the frames of sprites are drawn from, e.g. a PNG file
+    /// loaded when the engine starts. Textures are numbered in the order they
+    /// are added, the first one being `SpriteAtlas::texture` 0. Textures that
+    /// can not be loaded are drawn white. The filter, wrap and color space of
+    /// a texture are read from its sidecar, e.g. `hero.png.meta`, see
+    /// `TextureSettings`.
+    #[inline]
+    pub fn with_sprite_texture(mut self, path: impl Into<PathBuf>) -> Self {
+        self.sprite_textures.push(path.into());
+        self
+    }
+
+    /// Sets the address the Prometheus metrics endpoint listens on. Use None
+    /// to disable the exporter.
+    #[cfg(feature = "metrics")]
+    #[inline]
+    pub fn with_metrics_address(mut self, address: Option<SocketAddr>) -> Self {
+        self.metrics_address = address;
+        self
+    }
+
+    /// Sets the key capturing the next frame with RenderDoc. Use None to
+    /// only capture frames requested by the application.
+    #[cfg(feature = "renderdoc")]
+    #[inline]
+    pub fn with_capture_key(mut self, key: Option<VirtualKeyCode>) -> Self {
+        self.capture_key = key;
+        self
+    }
+
+    /// Sets the key saving a screenshot of the next frame in the working
+    /// directory. Use None to disable it.
+    #[cfg(feature = "editor-tools")]
+    #[inline]
+    pub fn with_screenshot_key(mut self, key: Option<VirtualKeyCode>) -> Self {
+        self.screenshot_key = key;
+        self
+    }
+
+    /// Sets the key starting and stopping the recording of frame statistics
+    /// to a CSV file in the working directory, see the `frame_stats` module.
+    /// Use None to disable it.
+    #[cfg(feature = "editor-tools")]
+    #[inline]
+    pub fn with_frame_stats_key(mut self, key: Option<VirtualKeyCode>) -> Self {
+        self.frame_stats_key = key;
+        self
+    }
+
+    /// Sets the key toggling the ruler, measuring world space distances and
+    /// angles by dragging over the viewport. Use None to disable it.
+    #[cfg(feature = "editor-tools")]
+    #[inline]
+    pub fn with_ruler_key(mut self, key: Option<VirtualKeyCode>) -> Self {
+        self.ruler_key = key;
+        self
+    }
+
+    /// Adds a TTF font to the UI, the first one added being the default font
+    /// instead of the imgui one. Fonts are rasterized for the scale factor
+    /// of the main window, and again whenever it changes.
+    #[cfg(feature = "imgui")]
+    #[inline]
+    pub fn with_ui_font(mut self, font: UiFont) -> Self {
+        self.ui_options.fonts.push(font);
+        self
+    }
+
+    /// Sets the file the settings of the UI, e.g. the position and size of
+    /// its windows, are loaded from at startup and saved to while running.
+    /// Use None, the default, to not keep them across runs.
+    #[cfg(feature = "imgui")]
+    #[inline]
+    pub fn with_ui_settings_path(mut self, path: Option<PathBuf>) -> Self {
+        self.ui_options.ini_path = path;
+        self
+    }
+
+    /// Sets the directory of the GLSL sources of the 2D renderers, watched
+    /// for changes. Modified shaders are recompiled and their pipelines
+    /// rebuilt between frames. Defaults to the sources the renderers are
+    /// built from. Use None to disable it.
+    #[cfg(feature = "shader-hot-reload")]
+    #[inline]
+    pub fn with_shader_dir(mut self, shader_dir: Option<PathBuf>) -> Self {
+        self.shader_dir = shader_dir;
+        self
+    }
+
+    /// Renders the frames of a headless engine offscreen at the given size,
+    /// so that they can be read back with `HeadlessEngine::read_frame()`.
+    /// Disabled by default: a headless engine does not need a GPU unless it
+    /// renders. Use None to disable it.
+    #[inline]
+    pub fn with_headless_rendering(mut self, extent: Option<vk::Extent2D>) -> Self {
+        self.headless_extent = extent;
+        self
+    }
+
     #[inline]
     pub fn build(mut self) -> Result<Engine> {
-        let app = self.app.take().ok_or("app is None")?;
-        let wb = self.wb.take().ok_or("window builder is None")?;
+        let app = self.app.take().ok_or(EngineError::MissingApplication)?;
+        let wb = self.wb.take().ok_or(EngineError::MissingWindowBuilder)?;
+
+        let mut engine = Engine::new(app, wb);
+        engine.additional_windows = self.additional_windows;
+        engine.display_mode = self.display_mode;
+        engine.fullscreen_toggle = self.fullscreen_toggle;
+        engine.passes = self.passes;
+        engine.renderer_systems = self.renderer_systems;
+        engine.renderer_settings = self.renderer_settings;
+        engine.clear_color = self.clear_color;
+        engine.world_layer = self.world_layer;
+        engine.anti_aliasing = self.anti_aliasing;
+        engine.screen_transition = self.screen_transition;
+        engine.gpu_culling = self.gpu_culling;
+        engine.depth_prepass = self.depth_prepass;
+        engine.stress_scene = self.stress_scene;
+        engine.collision_cell_size = self.collision_cell_size;
+        engine.worker_threads = self.worker_threads;
+        #[cfg(feature = "physics")]
+        {
+            engine.physics = self.physics;
+        }
+        engine.frame_spike_threshold = self.frame_spike_threshold;
+        engine.fps_average = self.fps_average;
+        engine.frame_limit = self.frame_limit;
+        engine.input_latency = self.input_latency;
+        engine.input_map = self.input_map;
+        engine.prefabs = self.prefabs;
+        engine.sprite_textures = self.sprite_textures;
+        engine.config_dir = self.config_dir;
+        #[cfg(feature = "metrics")]
+        {
+            engine.metrics_address = self.metrics_address;
+        }
+        #[cfg(feature = "renderdoc")]
+        {
+            engine.capture_key = self.capture_key;
+        }
+        #[cfg(feature = "editor-tools")]
+        {
+            engine.screenshot_key = self.screenshot_key;
+            engine.frame_stats_key = self.frame_stats_key;
+            engine.ruler_key = self.ruler_key;
+        }
+        #[cfg(feature = "imgui")]
+        {
+            engine.ui_options = self.ui_options;
+        }
+        #[cfg(feature = "shader-hot-reload")]
+        {
+            engine.shader_dir = self.shader_dir;
+        }
+        Ok(engine)
+    }
 
-        Ok(Engine::new(app, wb))
+    /// Builds an engine running the application without a window, e.g. to
+    /// test it in CI, see `HeadlessEngine`. The window builder is not needed,
+    /// and the settings of the window, the UI, the world layer and the
+    /// developer tools are ignored.
+    pub fn headless(mut self) -> Result<HeadlessEngine> {
+        let app = self.app.take().ok_or(EngineError::MissingApplication)?;
+        let config = HeadlessConfig {
+            extent: self.headless_extent,
+            renderer_settings: self.renderer_settings,
+            clear_color: self.clear_color,
+            depth_prepass: self.depth_prepass,
+            simulation: Simulation::new(
+                self.collision_cell_size,
+                #[cfg(feature = "physics")]
+                self.physics,
+            ),
+            stress_scene: self.stress_scene,
+            worker_threads: self.worker_threads,
+            prefabs: self.prefabs,
+            sprite_textures: self.sprite_textures,
+        };
+        HeadlessEngine::new(app, config)
     }
 }
 
 pub struct Engine {
     application: Option<Box<dyn Application>>,
     window_builder: Option<WindowBuilder>,
+    additional_windows: Vec<WindowBuilder>,
+    display_mode: DisplayMode,
+    fullscreen_toggle: bool,
+    passes: Vec<Box<dyn CustomPass>>,
+    renderer_systems: Vec<Box<dyn RendererSystem>>,
+    renderer_settings: RendererSettings,
+    clear_color: Vector4<f32>,
+    world_layer: LayerSettings,
+    anti_aliasing: AntiAliasing,
+    screen_transition: Option<ScreenTransition>,
+    gpu_culling: bool,
+    depth_prepass: bool,
+    stress_scene: Option<StressScene>,
+    collision_cell_size: Option<f32>,
+    worker_threads: usize,
+    #[cfg(feature = "physics")]
+    physics: PhysicsSettings,
+    frame_spike_threshold: Option<time::Duration>,
+    fps_average: FpsAverage,
+    frame_limit: Option<FrameLimit>,
+    input_latency: bool,
+    input_map: InputMap,
+    prefabs: PrefabLibrary,
+    sprite_textures: Vec<PathBuf>,
+    config_dir: Option<PathBuf>,
+    #[cfg(feature = "metrics")]
+    metrics_address: Option<SocketAddr>,
+    #[cfg(feature = "renderdoc")]
+    capture_key: Option<VirtualKeyCode>,
+    #[cfg(feature = "editor-tools")]
+    screenshot_key: Option<VirtualKeyCode>,
+    #[cfg(feature = "editor-tools")]
+    frame_stats_key: Option<VirtualKeyCode>,
+    #[cfg(feature = "editor-tools")]
+    ruler_key: Option<VirtualKeyCode>,
+    #[cfg(feature = "imgui")]
+    ui_options: UiOptions,
+    #[cfg(feature = "shader-hot-reload")]
+    shader_dir: Option<PathBuf>,
 }
 
 impl Engine {
@@ -64,201 +713,1998 @@ impl Engine {
         Self {
             application: Some(app),
             window_builder: Some(wb),
+            additional_windows: Vec::new(),
+            display_mode: DisplayMode::Windowed,
+            fullscreen_toggle: true,
+            passes: Vec::new(),
+            renderer_systems: Vec::new(),
+            renderer_settings: RendererSettings::default(),
+            clear_color: Vector4::new(0.0, 0.0, 0.0, 0.0),
+            world_layer: LayerSettings::default(),
+            anti_aliasing: AntiAliasing::None,
+            screen_transition: None,
+            gpu_culling: false,
+            depth_prepass: false,
+            stress_scene: None,
+            collision_cell_size: None,
+            worker_threads: jobs::default_workers(),
+            #[cfg(feature = "physics")]
+            physics: PhysicsSettings::default(),
+            frame_spike_threshold: Some(DEFAULT_FRAME_SPIKE_THRESHOLD),
+            fps_average: FpsAverage::default(),
+            frame_limit: None,
+            input_latency: false,
+            input_map: InputMap::new(),
+            prefabs: PrefabLibrary::new(),
+            sprite_textures: Vec::new(),
+            config_dir: safe_mode::default_config_dir(),
+            #[cfg(feature = "metrics")]
+            metrics_address: Some(metrics::default_metrics_address()),
+            #[cfg(feature = "renderdoc")]
+            capture_key: Some(DEFAULT_CAPTURE_KEY),
+            #[cfg(feature = "editor-tools")]
+            screenshot_key: Some(DEFAULT_SCREENSHOT_KEY),
+            #[cfg(feature = "editor-tools")]
+            frame_stats_key: Some(DEFAULT_FRAME_STATS_KEY),
+            #[cfg(feature = "editor-tools")]
+            ruler_key: Some(DEFAULT_RULER_KEY),
+            #[cfg(feature = "imgui")]
+            ui_options: UiOptions::default(),
+            #[cfg(feature = "shader-hot-reload")]
+            shader_dir: Some(PathBuf::from(DEFAULT_SHADER_DIR)),
         }
     }
 
-    pub fn run(&mut self) {
+    /// Runs the application until the main window is closed or the
+    /// application requests to exit. Returns the exit code requested by the
+    /// application, see `ApplicationContext::request_exit_with_code()`, 0
+    /// otherwise.
+    ///
+    /// Returns an error if the engine fails to start, or if a frame fails and
+    /// `Application::on_error()` does not keep running. The engine can only
+    /// run once.
+    pub fn run(&mut self) -> Result<i32> {
+        // NOTE: the culled renderer does not sample textures
+        if self.gpu_culling && !self.sprite_textures.is_empty() {
+            return Err(EngineError::system(
+                "culled renderer 2D",
+                "sprite textures can not be drawn with GPU culling".into(),
+            ));
+        }
+
         // take ownership of struct attributes
         let mut application = self
             .application
             .take()
-            .ok_or("app is None")
-            .expect("take app");
+            .ok_or(EngineError::MissingApplication)?;
         let window_builder = self
             .window_builder
             .take()
-            .ok_or("window builder is None")
-            .expect("take window builder");
+            .ok_or(EngineError::MissingWindowBuilder)?;
+
+        // start in safe mode after repeated failed startups
+        let mut startup = StartupTracker::begin(self.config_dir.as_deref());
+        let safe_mode = startup.safe_mode();
+        let renderer_settings = if safe_mode {
+            warn!(
+                "{} consecutive failed startups, starting in safe mode",
+                startup.failures()
+            );
+            safe_mode::safe_renderer_settings()
+        } else {
+            self.renderer_settings
+        };
+
+        // frame capture
+        // NOTE: RenderDoc is connected to before the renderer is created, so
+        //       that it hooks the Vulkan instance.
+        #[cfg(feature = "renderdoc")]
+        let mut frame_capture = FrameCapture::new(self.capture_key);
+
+        // screenshot hotkey
+        #[cfg(feature = "editor-tools")]
+        let mut screenshot_hotkey = Hotkey::new(self.screenshot_key);
+
+        // frame stats recording
+        #[cfg(feature = "editor-tools")]
+        let mut frame_stats_hotkey = Hotkey::new(self.frame_stats_key);
+        let mut frame_stats_recorder: Option<FrameStatsRecorder> = None;
+
+        // profiler
+        profiler::start();
+        #[cfg(all(feature = "profile-with-puffin", feature = "editor-tools"))]
+        let profiler_hud = ProfilerHud::new();
+
+        // ruler
+        #[cfg(feature = "editor-tools")]
+        let mut ruler = Ruler::new(self.ruler_key);
+
+        // requests made by the application for the next frame
+        let requests = FrameRequests::default();
 
         // window
-        let event_loop = EventLoop::new();
-        let window = window_builder
-            .build(&event_loop)
-            .expect("window builder builds");
+        let mut event_loop = EventLoop::new();
+        let window = window_builder.build(&event_loop)?;
+        let mut display = Display::new(self.fullscreen_toggle);
+        display.refresh_monitors(&window);
+        display.set_mode(&window, self.display_mode);
 
         // camera system
         let mut camera_controller = {
             let PhysicalSize { width, height } = window.inner_size();
             let camera = CameraOrthographic::new(width, height);
-            CameraController::new(camera)
+            let mut controller = CameraController::new(camera);
+            controller.input_map_mut().merge(&self.input_map);
+            controller
         };
 
         // input system
-        let mut input = InputSystem::new();
+        let input = InputSystem::new();
 
         // renderer system
         let mut vulkan_renderer =
-            unsafe { VulkanRenderer::new("Engine", &window).expect("create vulkan renderer") };
+            unsafe { VulkanRenderer::new("Engine", &window, renderer_settings)? };
+        vulkan_renderer.set_clear_color(self.clear_color.into());
+        let panic_hook = PanicHook::install(vulkan_renderer.device());
+
+        // job system
+        let jobs = Arc::new(JobPool::new(self.worker_threads));
+        info!("running jobs on {} worker threads", jobs.workers());
 
         let mut renderer2d_system = unsafe {
-            Renderer2DSystem::new(vulkan_renderer.device(), vulkan_renderer.renderpass())
-                .expect("create renderer2D system")
+            Renderer2DSystem::new(
+                vulkan_renderer.device(),
+                vulkan_renderer.renderpass(),
+                vulkan_renderer.max_frames_in_flight(),
+            )
+            .map_err(|e| EngineError::system("renderer 2D", e))?
+        };
+        renderer2d_system.set_depth_prepass(self.depth_prepass);
+        renderer2d_system.set_job_pool(Some(Arc::clone(&jobs)));
+        let mut renderer3d_system = unsafe {
+            Renderer3DSystem::new(
+                vulkan_renderer.device(),
+                vulkan_renderer.renderpass(),
+                vulkan_renderer.max_frames_in_flight(),
+            )
+            .map_err(|e| EngineError::system("renderer 3D", e))?
+        };
+        // additional windows
+        let mut additional_windows = Vec::with_capacity(self.additional_windows.len());
+        for wb in mem::take(&mut self.additional_windows) {
+            let additional_window = wb.build(&event_loop)?;
+            if let Err(e) = unsafe { vulkan_renderer.add_window(&additional_window) } {
+                error!("add window: {e:?}");
+                continue;
+            }
+            let mut additional_window =
+                unsafe { AdditionalWindow::new(additional_window, &vulkan_renderer)? };
+            additional_window
+                .renderer2d
+                .set_depth_prepass(self.depth_prepass);
+            additional_window
+                .renderer2d
+                .set_job_pool(Some(Arc::clone(&jobs)));
+            additional_window
+                .camera_controller
+                .input_map_mut()
+                .merge(&self.input_map);
+            additional_windows.push(additional_window);
+        }
+        let mut window_ids = vec![window.id()];
+        window_ids.extend(additional_windows.iter().map(|w| w.window.id()));
+
+        // sprite textures, drawn by the 2D renderers of every window
+        let sprite_textures = load_sprite_textures(&vulkan_renderer, &self.sprite_textures);
+        if let Some(sprite_textures) = &sprite_textures {
+            let renderers = std::iter::once(&mut renderer2d_system)
+                .chain(additional_windows.iter_mut().map(|w| &mut w.renderer2d));
+            for renderer2d in renderers {
+                unsafe {
+                    renderer2d.set_textures(
+                        vulkan_renderer.device(),
+                        vulkan_renderer.renderpass(),
+                        sprite_textures.array(),
+                    )
+                }
+                .map_err(|e| EngineError::system("renderer 2D", e))?;
+            }
+        }
+
+        // input is only given to the camera of the focused window
+        let mut focused_window = window.id();
+        let idle_input = InputSystem::new();
+
+        let mut culled_renderer = self
+            .gpu_culling
+            .then(|| unsafe {
+                CulledRenderer2D::new(
+                    vulkan_renderer.device(),
+                    vulkan_renderer.renderpass(),
+                    vulkan_renderer.max_frames_in_flight(),
+                )
+            })
+            .transpose()
+            .map_err(|e| EngineError::system("culled renderer 2D", e))?;
+
+        // custom passes
+        let mut pass_registry = unsafe {
+            PassRegistry::new(
+                vulkan_renderer.device(),
+                vulkan_renderer.renderpass(),
+                mem::take(&mut self.passes),
+            )?
+        };
+
+        // world layer
+        // NOTE: anti-aliasing is applied by the compositor, the world must be
+        //       drawn to a target even at the window resolution
+        let world_offscreen = self.anti_aliasing != AntiAliasing::None;
+        let mut world_layer = LayerTarget::new("world layer", self.world_layer, world_offscreen);
+        let mut compositor = unsafe {
+            Compositor::new(
+                vulkan_renderer.device(),
+                vulkan_renderer.renderpass(),
+                vulkan_renderer.max_frames_in_flight(),
+                self.anti_aliasing,
+            )
+            .map_err(|e| EngineError::system("compositor", e))?
+        };
+
+        // screen transitions
+        let mut screen_transition = TransitionPlayer::new(self.screen_transition);
+        // NOTE: last frame of the world captured for a crossfade
+        let mut transition_snapshot = None;
+        let mut transition_renderer = unsafe {
+            TransitionRenderer::new(
+                vulkan_renderer.device(),
+                vulkan_renderer.renderpass(),
+                vulkan_renderer.max_frames_in_flight(),
+            )
+            .map_err(|e| EngineError::system("transition renderer", e))?
         };
 
+        // shader hot reload
+        #[cfg(feature = "shader-hot-reload")]
+        let mut shader_watcher = self.shader_dir.as_ref().and_then(|dir| {
+            ShaderWatcher::new(dir)
+                .map_err(|e| warn!("watch shaders in {}: {e}", dir.display()))
+                .ok()
+        });
+
         // ImGui
-        let (mut winit_platform, mut imgui_context) = vulkan_imgui::init(&window);
-        let mut imgui_renderer = unsafe {
+        #[cfg(feature = "imgui")]
+        let ui_options = mem::take(&mut self.ui_options);
+        #[cfg(feature = "imgui")]
+        let (mut winit_platform, mut imgui_context) =
+            vulkan_imgui::init_with_options(&window, &ui_options);
+        #[cfg(feature = "imgui")]
+        let imgui_renderer = unsafe {
             vulkan_imgui::Renderer::new(
                 &mut imgui_context,
                 vulkan_renderer.device(),
                 vulkan_renderer.renderpass(),
+                vulkan_renderer.max_frames_in_flight(),
             )
-            .expect("initialize imgui renderer")
+            .map(RefCell::new)
+            .map_err(|e| EngineError::system("imgui renderer", e))?
         };
+        #[cfg(feature = "imgui-viewports")]
+        let mut imgui_viewports =
+            vulkan_imgui::viewports::Viewports::new(&mut imgui_context, &winit_platform, &window);
+
+        // render systems of the application
+        // NOTE: they are created once the engine can not fail to start anymore,
+        //       as they must be destroyed explicitly
+        let mut renderer_systems = RendererSystems::new(
+            vulkan_renderer.device(),
+            vulkan_renderer.renderpass(),
+            vulkan_renderer.max_frames_in_flight(),
+            mem::take(&mut self.renderer_systems),
+        );
 
         // frame counter system
         let mut frame_counter = FrameCounter::new();
 
         // fps printer system
         let mut fps_printer = {
-            let moving_average = ExponentialMovingAverage::new().with_alpha(0.95);
-            let print_fn = |fps| debug!("fps: {:.2}", fps);
+            let moving_average = self.fps_average.moving_average();
+            let print_fn = |fps, stats: Option<FrameTimeStats>| match stats {
+                Some(stats) => debug!("fps: {fps:.2}, frame times: {stats}"),
+                None => debug!("fps: {fps:.2}"),
+            };
             FPSPrinter::new(moving_average, print_fn).with_throttle_ms(500)
         };
 
-        // game objects
-        let mut objects = Vec::new();
+        // frame watchdog system
+        let mut frame_watchdog = self.frame_spike_threshold.map(FrameWatchdog::new);
 
-        // run application initialization
-        application.on_init(ApplicationContext::new(
-            &mut objects,
-            frame_counter.delta_time(),
-        ));
+        // frame limiter system
+        // NOTE: in FIFO mode, which is used when MAILBOX is not supported,
+        //       presenting blocks until the display is ready for a new frame
+        let mut frame_limiter = self.frame_limit.and_then(FrameLimiter::new);
+        let mut cursor_visible = true;
+        let mut cursor_icon = CursorIcon::Default;
+        let game_clock = GameClock::default();
+        if frame_limiter.is_some() {
+            let present_mode = vulkan_renderer.present_mode();
+            if present_mode == vk::PresentModeKHR::MAILBOX {
+                info!("frames are paced by the frame limiter");
+            } else {
+                info!(
+                    "frames are paced by the frame limiter and by the display ({present_mode:?})"
+                );
+            }
+        }
 
-        // run main loop
-        event_loop.run(move |event, _, control_flow| {
-            *control_flow = ControlFlow::Poll;
+        // input latency system
+        let mut latency_tracker = self.input_latency.then(InputLatencyTracker::new);
 
-            // update ImGui system
-            winit_platform.handle_event(imgui_context.io_mut(), &window, &event);
-            // update input system
-            input.on_event(&event);
-            // update camera system
-            camera_controller.on_event(&event);
+        // metrics exporter
+        #[cfg(feature = "metrics")]
+        let metrics_exporter =
+            self.metrics_address
+                .and_then(|addr| match MetricsExporter::start(addr) {
+                    Ok(exporter) => {
+                        info!(
+                            "serving metrics on http://{}/metrics",
+                            exporter.local_addr()
+                        );
+                        Some(exporter)
+                    }
+                    Err(e) => {
+                        error!("start metrics exporter on {addr}: {e}");
+                        None
+                    }
+                });
 
-            match event {
-                // handle close window
-                Event::WindowEvent {
-                    event: WindowEvent::CloseRequested,
-                    ..
-                } => *control_flow = ControlFlow::Exit,
+        // game objects, and the systems the application reaches through its
+        // context
+        let mut resources = Resources {
+            world: World::new(),
+            meshes: HandleMap::new(),
+            prefabs: mem::take(&mut self.prefabs),
+            event_bus: EventBus::new(),
+            requests,
+            input,
+            game_clock,
+            display,
+            jobs,
+            window_ids,
+            safe_mode,
+            render_callbacks: RenderCallbacks::default(),
+        };
+        let mut simulation = Simulation::new(
+            self.collision_cell_size,
+            #[cfg(feature = "physics")]
+            self.physics,
+        );
+        let mut states = StateStack::default();
 
-                // Emitted when new events arrive from the OS to be processed.
-                // This event type is useful as a place to put code that should be done before you
-                // start processing events.
-                Event::NewEvents(_) => {
-                    frame_counter.on_update(time::Instant::now());
-                    // update ImGui delta time
-                    imgui_context
-                        .io_mut()
-                        .update_delta_time(frame_counter.delta_time());
+        // run application initialization
+        // NOTE: a panic exits the main loop right away, see `catch_panics()`
+        let mut panic_payload = panic::catch_unwind(AssertUnwindSafe(|| {
+            application.on_init(resources.context(frame_counter.delta_time()));
+
+            // enter the states pushed by the application
+            while !resources.requests.states.is_empty() {
+                for transition in mem::take(&mut resources.requests.states) {
+                    states.apply(transition, |state, hook| {
+                        hook.call(state, resources.context(frame_counter.delta_time()));
+                    });
                 }
+            }
+        }))
+        .err();
 
-                // handle window resize
-                Event::WindowEvent {
-                    event: WindowEvent::Resized(PhysicalSize { width, height }),
-                    ..
-                } => vulkan_renderer.resize(width, height),
+        // set once the application is notified that the window is minimized
+        let mut was_minimized = false;
 
-                // handle shutdown
-                Event::LoopDestroyed => unsafe {
-                    renderer2d_system.destroy(vulkan_renderer.device());
-                    imgui_renderer.destroy(vulkan_renderer.device(), &mut imgui_context);
-                    vulkan_renderer.destroy();
-                },
+        // spawn the stress scene
+        if let Some(scene) = self.stress_scene {
+            simulation.spawn_stress_scene(scene, &mut resources.world);
+        }
 
-                // NOTE: the MainEventsCleared event will be emitted when all input events
-                //       have been processed and redraw processing is about to begin.
-                Event::MainEventsCleared => {
-                    let delta_time = frame_counter.delta_time();
+        // set when a frame failed and the application did not keep running
+        let mut failure = None;
 
-                    // print fps
-                    fps_printer.on_update(delta_time, frame_counter.fps());
+        // main loop
+        let handle_event = |event: Event<()>,
+                            _target: &EventLoopWindowTarget<()>,
+                            control_flow: &mut ControlFlow| {
+            *control_flow = ControlFlow::Poll;
 
-                    // update application state
-                    application.on_update(ApplicationContext::new(&mut objects, delta_time));
+            // events of the additional windows only reach their own systems
+            let event_window = match &event {
+                Event::WindowEvent { window_id, .. } => Some(*window_id),
+                _ => None,
+            };
+            let main_window_event = event_window.map_or(true, |id| id == window.id());
 
-                    // update camera
-                    camera_controller.on_update(&input, delta_time);
+            // let the application see the event first
+            {
+                let _scope = alloc_audit::scope(Subsystem::Application);
+                application.on_event(&event, resources.context(frame_counter.delta_time()));
+                if let Some(state) = states.top_mut() {
+                    state.on_event(&event, resources.context(frame_counter.delta_time()));
+                }
+            }
 
-                    // render
-                    unsafe {
-                        if vulkan_renderer.begin_frame().expect("begin frame succeeds") {
-                            if let Err(e) = vulkan_renderer.draw(|_, command_buffer| {
-                                // Renderer 2D
-                                renderer2d_system
-                                    .render(
-                                        vulkan_renderer.device(),
+            // timestamp input events
+            if let Some(tracker) = latency_tracker.as_mut() {
+                tracker.on_event(&event, time::Instant::now());
+            }
+
+            // request a frame capture on hotkey press
+            #[cfg(feature = "renderdoc")]
+            frame_capture.on_event(&event);
+
+            // take a screenshot on hotkey press
+            #[cfg(feature = "editor-tools")]
+            if screenshot_hotkey.on_event(&event) {
+                resources.requests.screenshot = Some(default_screenshot_path());
+            }
+
+            // start or stop recording frame stats on hotkey press
+            #[cfg(feature = "editor-tools")]
+            if frame_stats_hotkey.on_event(&event) {
+                resources.requests.frame_stats = Some(match frame_stats_recorder {
+                    Some(_) => None,
+                    None => Some(frame_stats::default_frame_stats_path()),
+                });
+            }
+
+            // toggle fullscreen on Alt+Enter
+            if main_window_event {
+                if let Some(mode) = resources.display.on_event(&event) {
+                    resources.requests.display_mode = Some(mode);
+                }
+            }
+
+            // toggle the ruler on hotkey press
+            #[cfg(feature = "editor-tools")]
+            if main_window_event {
+                ruler.on_event(&event);
+            }
+
+            // update ImGui system
+            #[cfg(feature = "imgui")]
+            if main_window_event {
+                winit_platform.handle_event(imgui_context.io_mut(), &window, &event);
+            }
+            #[cfg(feature = "imgui-viewports")]
+            let viewport_event = imgui_viewports.handle_event(
+                &mut imgui_context,
+                &mut winit_platform,
+                &window,
+                &event,
+            );
+            #[cfg(not(feature = "imgui-viewports"))]
+            let viewport_event = false;
+            // update input system
+            {
+                let _scope = alloc_audit::scope(Subsystem::Input);
+                // NOTE: the UI is only drawn in the main window and in the
+                //       windows of the imgui viewports
+                #[cfg(feature = "imgui")]
+                {
+                    let io = imgui_context.io();
+                    let ui_event = main_window_event || viewport_event;
+                    resources
+                        .input
+                        .set_mouse_captured(ui_event && io.want_capture_mouse);
+                    resources
+                        .input
+                        .set_keyboard_captured(ui_event && io.want_capture_keyboard);
+                }
+                resources.input.on_event(&event);
+            }
+            // update camera system
+            {
+                let _scope = alloc_audit::scope(Subsystem::Camera);
+                if main_window_event {
+                    camera_controller.on_event(&event);
+                } else if let Some(additional_window) = additional_windows
+                    .iter_mut()
+                    .find(|w| Some(w.window.id()) == event_window)
+                {
+                    additional_window.camera_controller.on_event(&event);
+                }
+            }
+
+            match event {
+                // handle close window, unless the application vetoes it
+                Event::WindowEvent {
+                    event: WindowEvent::CloseRequested,
+                    window_id,
+                } if window_id == window.id() => {
+                    let _scope = alloc_audit::scope(Subsystem::Application);
+                    let exit = application
+                        .on_exit_requested(resources.context(frame_counter.delta_time()));
+                    if exit {
+                        *control_flow = ControlFlow::Exit;
+                    } else {
+                        debug!("exit vetoed by the application");
+                    }
+                }
+
+                // additional windows are closed on their own
+                // NOTE: the windows of the imgui viewports are closed by imgui
+                Event::WindowEvent {
+                    event: WindowEvent::CloseRequested,
+                    window_id,
+                } if !viewport_event => {
+                    if let Err(e) = unsafe { vulkan_renderer.remove_window(window_id) } {
+                        error!("remove window: {e:?}");
+                    }
+                    additional_windows.retain(|w| w.window.id() != window_id);
+                    resources.window_ids.retain(|id| *id != window_id);
+                }
+
+                Event::WindowEvent {
+                    event: WindowEvent::Focused(true),
+                    window_id,
+                } => focused_window = window_id,
+
+                // rasterize the fonts of the UI for the new scale factor
+                #[cfg(feature = "imgui")]
+                Event::WindowEvent {
+                    event: WindowEvent::ScaleFactorChanged { .. },
+                    window_id,
+                } if window_id == window.id() => {
+                    vulkan_imgui::build_fonts(
+                        &mut imgui_context,
+                        &ui_options.fonts,
+                        winit_platform.hidpi_factor(),
+                    );
+                    let result = unsafe {
+                        imgui_renderer
+                            .borrow_mut()
+                            .reload_fonts(vulkan_renderer.device(), &mut imgui_context)
+                    };
+                    if let Err(e) = result {
+                        error!("reload imgui fonts: {e}");
+                    }
+                }
+
+                // Emitted when new events arrive from the OS to be processed.
+                // This event type is useful as a place to put code that should be done before you
+                // start processing events.
+                Event::NewEvents(_) => {
+                    frame_counter.on_update(time::Instant::now());
+                    // update ImGui delta time
+                    #[cfg(feature = "imgui")]
+                    imgui_context
+                        .io_mut()
+                        .update_delta_time(frame_counter.delta_time());
+                }
+
+                // handle window resize
+                Event::WindowEvent {
+                    event: WindowEvent::Resized(PhysicalSize { width, height }),
+                    window_id,
+                } => {
+                    if window_id == window.id() {
+                        vulkan_renderer.resize(width, height);
+                        renderer_systems
+                            .resize(vulkan_renderer.device(), vk::Extent2D { width, height });
+
+                        let _scope = alloc_audit::scope(Subsystem::Application);
+                        application.on_resize(
+                            width,
+                            height,
+                            resources.context(frame_counter.delta_time()),
+                        );
+                    } else {
+                        vulkan_renderer.resize_window(window_id, width, height);
+                    }
+                }
+
+                // let the application save its state while the renderers
+                // still exist
+                Event::LoopDestroyed => {
+                    let _scope = alloc_audit::scope(Subsystem::Application);
+                    states.clear(|state, hook| {
+                        hook.call(state, resources.context(frame_counter.delta_time()));
+                    });
+                    application.on_shutdown(resources.context(frame_counter.delta_time()));
+                }
+
+                // the window can not be drawn to while the application is
+                // suspended, e.g. in the background on Android
+                Event::Suspended => {
+                    if let Err(e) = unsafe { vulkan_renderer.suspend() } {
+                        error!("suspend renderer: {e:?}");
+                    }
+                }
+                Event::Resumed => {
+                    if let Err(e) = unsafe { vulkan_renderer.resume(&window) } {
+                        error!("resume renderer: {e:?}");
+                        *control_flow = ControlFlow::Exit;
+                        return;
+                    }
+                    // the additional windows are removed while suspended
+                    for additional_window in &additional_windows {
+                        let window = &additional_window.window;
+                        if vulkan_renderer.window(window.id()).is_none() {
+                            if let Err(e) = unsafe { vulkan_renderer.add_window(window) } {
+                                error!("add window: {e:?}");
+                            }
+                        }
+                    }
+                    #[cfg(feature = "imgui-viewports")]
+                    for window in imgui_viewports.windows() {
+                        if vulkan_renderer.window(window.id()).is_none() {
+                            if let Err(e) = unsafe { vulkan_renderer.add_window(window) } {
+                                error!("add viewport window: {e:?}");
+                            }
+                        }
+                    }
+                }
+
+                // NOTE: the MainEventsCleared event will be emitted when all input events
+                //       have been processed and redraw processing is about to begin.
+                Event::MainEventsCleared => {
+                    // NOTE: the previous frame ends once the events since have been
+                    //       handled
+                    profiling::finish_frame!();
+                    profiling::scope!("frame");
+                    let frame_time = frame_counter.delta_time();
+                    let frame_start = time::Instant::now();
+
+                    // drop the events sent before the last frame
+                    resources.event_bus.update();
+
+                    // print fps
+                    fps_printer.on_update(&mut frame_counter);
+
+                    // report allocations made since the last frame
+                    let alloc_report = alloc_audit::take_report();
+
+                    // log the last frame if it was a spike
+                    if let Some(spike) = frame_watchdog
+                        .as_mut()
+                        .and_then(|w| w.on_update(frame_counter.frame_count(), frame_time))
+                    {
+                        warn!("{spike}");
+                        for (subsystem, count) in alloc_report.offenders() {
+                            warn!("  {}: {count} allocations", subsystem.name());
+                        }
+                    }
+
+                    // notify the application when the main window is minimized
+                    // or restored
+                    let minimized = vulkan_renderer.is_minimized();
+                    if minimized != was_minimized {
+                        was_minimized = minimized;
+                        let _scope = alloc_audit::scope(Subsystem::Application);
+                        let ctx = resources.context(frame_time);
+                        if minimized {
+                            application.on_minimized(ctx);
+                        } else {
+                            application.on_restored(ctx);
+                        }
+                    }
+
+                    // update application state
+                    {
+                        profiling::scope!("update");
+                        let _scope = alloc_audit::scope(Subsystem::Application);
+                        application.on_update(resources.context(frame_time));
+                        if let Some(state) = states.top_mut() {
+                            state.on_update(resources.context(frame_time));
+                        }
+
+                        // switch the states requested during the frame, once the
+                        // screen is covered by the transition
+                        if let Some(transition) = resources.requests.screen_transition.take() {
+                            screen_transition.set_transition(transition);
+                        }
+                        screen_transition.request(mem::take(&mut resources.requests.states));
+                        let mut switches = screen_transition.update(frame_time);
+                        // NOTE: the states requested by their hooks switch right away
+                        while !switches.is_empty() {
+                            for transition in switches {
+                                states.apply(transition, |state, hook| {
+                                    hook.call(state, resources.context(frame_time));
+                                });
+                            }
+                            switches = mem::take(&mut resources.requests.states);
+                        }
+
+                        // keep the world captured for a crossfade while it plays
+                        match screen_transition.overlay() {
+                            Some(Overlay::Crossfade { .. }) => {
+                                if transition_snapshot.is_none() {
+                                    transition_snapshot = world_layer.take_target();
+                                }
+                            }
+                            _ => transition_snapshot = None,
+                        }
+                        world_layer
+                            .set_offscreen(world_offscreen || screen_transition.is_capturing());
+                    }
+
+                    // NOTE: the application may have changed the time scale
+                    let delta_time = resources.game_clock.apply(frame_time);
+
+                    // move and animate the objects, then report their collisions
+                    {
+                        let events = simulation.update(
+                            &mut resources.world,
+                            delta_time,
+                            &mut resources.event_bus,
+                        );
+                        let _scope = alloc_audit::scope(Subsystem::Application);
+                        for event in events {
+                            resources.event_bus.send(*event);
+                            application.on_collision(*event, resources.context(frame_time));
+                        }
+                    }
+
+                    // capture the frame about to be rendered if requested
+                    if mem::take(&mut resources.requests.capture) {
+                        #[cfg(feature = "renderdoc")]
+                        frame_capture.request();
+                        #[cfg(not(feature = "renderdoc"))]
+                        warn!("frame capture requested but the renderdoc feature is disabled");
+                    }
+                    #[cfg(feature = "renderdoc")]
+                    frame_capture.on_frame();
+                    if let Some(path) = resources.requests.screenshot.take() {
+                        if let Err(e) = vulkan_renderer.capture_screenshot(&path) {
+                            error!("capture screenshot to {}: {e}", path.display());
+                        }
+                    }
+                    if let Some(path) = resources.requests.frame_stats.take() {
+                        frame_stats::switch_recording(&mut frame_stats_recorder, path);
+                    }
+                    if let Some(settings) = resources.requests.world_layer.take() {
+                        world_layer.set_settings(settings);
+                    }
+                    if let Some(color) = resources.requests.clear_color.take() {
+                        vulkan_renderer.set_clear_color(color.into());
+                    }
+                    if let Some(limit) = resources.requests.frame_limit.take() {
+                        frame_limiter = limit.and_then(FrameLimiter::new);
+                    }
+                    if let Some(allowed) = resources.requests.ime_allowed.take() {
+                        window.set_ime_allowed(allowed);
+                    }
+                    if let Some(visible) = resources.requests.cursor_visible.take() {
+                        window.set_cursor_visible(visible);
+                        cursor_visible = visible;
+                    }
+                    if let Some(mode) = resources.requests.cursor_grab.take() {
+                        set_cursor_grab(&window, mode);
+                    }
+                    if let Some(icon) = resources.requests.cursor_icon.take() {
+                        window.set_cursor_icon(icon);
+                        cursor_icon = icon;
+                    }
+                    // NOTE: imgui sets the cursor of the window on every frame, which
+                    //       would show it again and reset its icon
+                    #[cfg(feature = "imgui")]
+                    imgui_context.io_mut().config_flags.set(
+                        imgui::ConfigFlags::NO_MOUSE_CURSOR_CHANGE,
+                        !cursor_visible || cursor_icon != CursorIcon::Default,
+                    );
+                    if let Some(mode) = resources.requests.display_mode.take() {
+                        resources.display.set_mode(&window, mode);
+                    }
+                    if let Some(code) = resources.requests.exit.take() {
+                        info!("exit requested by the application with code {code}");
+                        *control_flow = ControlFlow::ExitWithCode(code);
+                    }
+
+                    // create the resources of new render callbacks
+                    {
+                        let _scope = alloc_audit::scope(Subsystem::Passes);
+                        unsafe {
+                            resources.render_callbacks.prepare(
+                                vulkan_renderer.device(),
+                                vulkan_renderer.renderpass(),
+                                &resources.world,
+                            );
+                        }
+                    }
+
+                    // update cameras
+                    {
+                        let _scope = alloc_audit::scope(Subsystem::Camera);
+                        let input_of = |id| {
+                            if id == focused_window {
+                                &resources.input
+                            } else {
+                                &idle_input
+                            }
+                        };
+                        camera_controller.on_update(input_of(window.id()), frame_time);
+                        for additional_window in &mut additional_windows {
+                            additional_window
+                                .camera_controller
+                                .on_update(input_of(additional_window.window.id()), frame_time);
+                        }
+                    }
+
+                    // first error of the frame
+                    // NOTE: the frame is still submitted and presented when recording
+                    //       fails, the error is handled once it ends
+                    let frame_error = RefCell::new(None);
+                    let fail = |e: EngineError| {
+                        frame_error.borrow_mut().get_or_insert(e);
+                    };
+
+                    // build UI
+                    // NOTE: this is done before starting the frame so that UI CPU work does
+                    //       not happen while recording commands. Nothing is built while the
+                    //       window is minimized.
+                    #[cfg(feature = "imgui")]
+                    let draw_data =
+                        if vulkan_renderer.is_minimized() || vulkan_renderer.is_suspended() {
+                            None
+                        } else {
+                            profiling::scope!("build ui");
+                            let _scope = alloc_audit::scope(Subsystem::ImGui);
+                            if let Err(e) =
+                                winit_platform.prepare_frame(imgui_context.io_mut(), &window)
+                            {
+                                fail(EngineError::system("imgui", Box::new(e)));
+                            }
+                            let ui = imgui_context.new_frame();
+                            {
+                                let _scope = alloc_audit::scope(Subsystem::Application);
+                                application.on_render_ui(ui);
+                                for state in states.visible_mut() {
+                                    state.on_render_ui(ui);
+                                }
+                            }
+                            #[cfg(feature = "editor-tools")]
+                            ruler.draw(ui, camera_controller.view_projection_matrix());
+                            #[cfg(feature = "editor-tools")]
+                            memory_hud::draw_hud(ui, &vulkan_renderer.device().memory_stats());
+                            #[cfg(feature = "editor-tools")]
+                            frame_counter::draw_hud(ui, &mut frame_counter);
+                            #[cfg(all(feature = "profile-with-puffin", feature = "editor-tools"))]
+                            profiler_hud.draw(ui);
+                            #[cfg(feature = "alloc-audit")]
+                            alloc_audit::draw_hud(ui, &alloc_report);
+                            if let Some(tracker) = latency_tracker.as_mut() {
+                                crate::input_latency::draw_hud(ui, tracker.percentiles());
+                            }
+                            winit_platform.prepare_render(ui, &window);
+                            #[cfg(not(feature = "imgui-viewports"))]
+                            let draw_data = imgui_context.render();
+                            // create, move and destroy the windows of the viewports
+                            #[cfg(feature = "imgui-viewports")]
+                            let draw_data = {
+                                imgui_context.render();
+                                imgui_context.update_platform_windows();
+                                let result = unsafe {
+                                    imgui_viewports.update(
+                                        _target,
+                                        &mut vulkan_renderer,
+                                        &mut imgui_renderer.borrow_mut(),
+                                    )
+                                };
+                                if let Err(e) = result {
+                                    fail(EngineError::system("imgui viewports", e));
+                                }
+                                imgui_context.main_viewport().draw_data()
+                            };
+                            Some(draw_data).filter(|d| d.total_vtx_count > 0)
+                        };
+
+                    // rebuild the pipelines using modified shaders
+                    // NOTE: the previous pipelines are destroyed once the frames using
+                    //       them have completed
+                    #[cfg(feature = "shader-hot-reload")]
+                    for shader in shader_watcher
+                        .as_mut()
+                        .map(|w| w.poll())
+                        .unwrap_or_default()
+                    {
+                        let device = vulkan_renderer.device();
+                        let renderpass = vulkan_renderer.renderpass();
+                        let results = unsafe {
+                            [
+                                renderer2d_system.reload_shader(device, renderpass, &shader),
+                                compositor.reload_shader(device, renderpass, &shader),
+                                transition_renderer.reload_shader(device, renderpass, &shader),
+                                culled_renderer.as_mut().map_or(Ok(false), |r| {
+                                    r.reload_shader(device, renderpass, &shader)
+                                }),
+                            ]
+                        };
+                        let mut reloaded = false;
+                        for result in results {
+                            match result {
+                                Ok(used) => reloaded |= used,
+                                Err(e) => error!("reload shader {}: {e}", shader.name),
+                            }
+                        }
+                        for additional_window in &mut additional_windows {
+                            let result = unsafe {
+                                additional_window
+                                    .renderer2d
+                                    .reload_shader(device, renderpass, &shader)
+                            };
+                            if let Err(e) = result {
+                                error!("reload shader {}: {e}", shader.name);
+                            }
+                        }
+                        if reloaded {
+                            info!("reloaded shader {}", shader.name);
+                        }
+                    }
+
+                    // render
+                    unsafe {
+                        profiling::scope!("render");
+                        let frame_started = match vulkan_renderer.begin_frame() {
+                            Err(e) if e.is_surface_lost() => {
+                                if let Err(e) = recover_surface(&mut vulkan_renderer, &window) {
+                                    fail(e);
+                                }
+                                false
+                            }
+                            Err(e) => {
+                                fail(e.into());
+                                false
+                            }
+                            Ok(started) => started,
+                        };
+                        if frame_started {
+                            let extent = vulkan_renderer.window_extent();
+
+                            // redraw the world layer when due
+                            // NOTE: the world is drawn in the main render pass when it has
+                            //       no target
+                            let redraw_world =
+                                world_layer.prepare(&vulkan_renderer).unwrap_or_else(|e| {
+                                    error!("prepare world layer: {e}");
+                                    false
+                                });
+                            let world_target = world_layer.target();
+
+                            let pass_registry = RefCell::new(&mut pass_registry);
+                            let render_callbacks = RefCell::new(&mut resources.render_callbacks);
+                            let culled_renderer = RefCell::new(culled_renderer.as_mut());
+                            let renderer_systems = RefCell::new(&mut renderer_systems);
+                            let record_passes =
+                                |stage, command_buffer, extent, window_id, view_projection| {
+                                    let _scope = alloc_audit::scope(Subsystem::Passes);
+                                    pass_registry.borrow_mut().record(
+                                        stage,
+                                        vulkan_renderer.device(),
+                                        command_buffer,
+                                        extent,
+                                        &mut vulkan_renderer.staging(),
+                                        delta_time,
+                                        window_id,
+                                    );
+                                    renderer_systems.borrow_mut().record(
+                                        stage,
+                                        vulkan_renderer.device(),
                                         command_buffer,
+                                        extent,
+                                        &mut vulkan_renderer.staging(),
+                                        view_projection,
+                                        &resources.world,
                                         delta_time,
+                                        window_id,
+                                    );
+                                };
+                            let record_world = RefCell::new(
+                                |device: &ash::Device,
+                                 command_buffer: vk::CommandBuffer,
+                                 extent: vk::Extent2D| {
+                                    record_passes(
+                                        PassStage::BeforeWorld,
+                                        command_buffer,
+                                        extent,
+                                        window.id(),
                                         camera_controller.view_projection_matrix(),
-                                        &objects,
-                                    )
-                                    .expect("renderer 2D render");
-
-                                // ImGui
-                                winit_platform
-                                    .prepare_frame(imgui_context.io_mut(), &window)
-                                    .expect("prepare ImGui frame");
-                                let ui = imgui_context.new_frame();
-                                ui.show_demo_window(&mut true);
-                                winit_platform.prepare_render(ui, &window);
-                                imgui_renderer
-                                    .render(
+                                    );
+
+                                    // Renderer 3D
+                                    {
+                                        let _scope = alloc_audit::scope(Subsystem::Renderer3D);
+                                        vulkan_renderer
+                                            .device()
+                                            .begin_label(command_buffer, "renderer 3D");
+                                        renderer3d_system
+                                            .render(
+                                                vulkan_renderer.device(),
+                                                command_buffer,
+                                                &mut vulkan_renderer.staging(),
+                                                camera_controller.view_projection_matrix(),
+                                                &resources.meshes,
+                                                resources.world.query::<MeshView>(),
+                                            )
+                                            .unwrap_or_else(|e| {
+                                                fail(EngineError::system("renderer 3D", e))
+                                            });
+                                        vulkan_renderer.device().end_label(command_buffer);
+                                    }
+
+                                    // Renderer 2D
+                                    // NOTE: objects drawn as meshes are left out, along
+                                    //       with their render callbacks
+                                    let scope = alloc_audit::scope(Subsystem::Renderer2D);
+                                    vulkan_renderer
+                                        .device()
+                                        .begin_label(command_buffer, "renderer 2D");
+                                    vulkan_renderer.gpu_profiler().begin_scope(
+                                        device,
+                                        command_buffer,
+                                        "renderer 2D",
+                                    );
+                                    let mut render_callbacks = render_callbacks.borrow_mut();
+                                    let draw_order = render_callbacks.draw_order(
+                                        &resources.world,
                                         vulkan_renderer.device(),
+                                        extent,
+                                        delta_time,
+                                        window.id(),
+                                    );
+                                    match culled_renderer.borrow().as_deref() {
+                                        // NOTE: the objects were culled before the render pass
+                                        Some(culled_renderer) => {
+                                            culled_renderer
+                                                .draw(vulkan_renderer.device(), command_buffer);
+                                            for callback in draw_order.filter_map(|(_, c)| c) {
+                                                callback(
+                                                    command_buffer,
+                                                    &mut vulkan_renderer.staging(),
+                                                );
+                                            }
+                                        }
+                                        None => renderer2d_system
+                                            .render(
+                                                vulkan_renderer.device(),
+                                                command_buffer,
+                                                &mut vulkan_renderer.staging(),
+                                                delta_time,
+                                                camera_controller.view_projection_matrix(),
+                                                draw_order,
+                                            )
+                                            .unwrap_or_else(|e| {
+                                                fail(EngineError::system("renderer 2D", e))
+                                            }),
+                                    }
+                                    vulkan_renderer
+                                        .gpu_profiler()
+                                        .end_scope(device, command_buffer);
+                                    vulkan_renderer.device().end_label(command_buffer);
+                                    drop(render_callbacks);
+                                    drop(scope);
+
+                                    record_passes(
+                                        PassStage::AfterWorld,
                                         command_buffer,
-                                        imgui_context.render(),
-                                    )
-                                    .expect("imgui renderer render");
-                            }) {
-                                error!("draw {e:?}");
+                                        extent,
+                                        window.id(),
+                                        camera_controller.view_projection_matrix(),
+                                    );
+                                },
+                            );
+
+                            if let Err(e) = vulkan_renderer.draw_with_windows(
+                                |device, command_buffer| {
+                                    // cull the objects of the world drawn this frame
+                                    let draw_world = world_target.is_none() || redraw_world;
+                                    if let Some(culled_renderer) = culled_renderer
+                                        .borrow_mut()
+                                        .as_deref_mut()
+                                        .filter(|_| draw_world)
+                                    {
+                                        let _scope = alloc_audit::scope(Subsystem::Renderer2D);
+                                        vulkan_renderer
+                                            .device()
+                                            .begin_label(command_buffer, "gpu culling");
+                                        culled_renderer
+                                            .cull(
+                                                vulkan_renderer.device(),
+                                                command_buffer,
+                                                camera_controller.view_projection_matrix(),
+                                                resources.world.query::<QuadView>(),
+                                            )
+                                            .unwrap_or_else(|e| {
+                                                fail(EngineError::system("culled renderer 2D", e))
+                                            });
+                                        vulkan_renderer.device().end_label(command_buffer);
+                                    }
+
+                                    let Some(target) = world_target.filter(|_| redraw_world) else {
+                                        return;
+                                    };
+                                    vulkan_renderer
+                                        .device()
+                                        .begin_label(command_buffer, "world layer");
+                                    target.begin(device, command_buffer);
+                                    (record_world.borrow_mut())(
+                                        device,
+                                        command_buffer,
+                                        target.extent(),
+                                    );
+                                    target.end(device, command_buffer);
+                                    vulkan_renderer.device().end_label(command_buffer);
+                                },
+                                |device, command_buffer| {
+                                    match world_target {
+                                        Some(target) => {
+                                            vulkan_renderer
+                                                .device()
+                                                .begin_label(command_buffer, "compositor");
+                                            compositor
+                                                .composite(
+                                                    vulkan_renderer.device(),
+                                                    command_buffer,
+                                                    target,
+                                                )
+                                                .unwrap_or_else(|e| {
+                                                    fail(EngineError::system("compositor", e))
+                                                });
+                                            vulkan_renderer.device().end_label(command_buffer);
+                                        }
+                                        None => (record_world.borrow_mut())(
+                                            device,
+                                            command_buffer,
+                                            extent,
+                                        ),
+                                    }
+
+                                    // screen transition
+                                    if let Some(overlay) = screen_transition.overlay() {
+                                        let device = vulkan_renderer.device();
+                                        device.begin_label(command_buffer, "screen transition");
+                                        match overlay {
+                                            Overlay::Fade { color, amount } => transition_renderer
+                                                .fade(device, command_buffer, color, amount),
+                                            Overlay::Wipe {
+                                                color,
+                                                direction,
+                                                amount,
+                                            } => transition_renderer.wipe(
+                                                device,
+                                                command_buffer,
+                                                color,
+                                                direction,
+                                                amount,
+                                            ),
+                                            Overlay::Crossfade { opacity } => {
+                                                if let Some(snapshot) = &transition_snapshot {
+                                                    transition_renderer
+                                                        .crossfade(
+                                                            device,
+                                                            command_buffer,
+                                                            snapshot,
+                                                            opacity,
+                                                        )
+                                                        .unwrap_or_else(|e| {
+                                                            fail(EngineError::system(
+                                                                "transition renderer",
+                                                                e,
+                                                            ))
+                                                        });
+                                                }
+                                            }
+                                        }
+                                        device.end_label(command_buffer);
+                                    }
+
+                                    // ImGui
+                                    #[cfg(feature = "imgui")]
+                                    if let Some(draw_data) = draw_data {
+                                        let _scope = alloc_audit::scope(Subsystem::ImGui);
+                                        vulkan_renderer
+                                            .device()
+                                            .begin_label(command_buffer, "imgui");
+                                        vulkan_renderer.gpu_profiler().begin_scope(
+                                            device,
+                                            command_buffer,
+                                            "imgui",
+                                        );
+                                        imgui_renderer
+                                            .borrow_mut()
+                                            .render(
+                                                vulkan_renderer.device(),
+                                                command_buffer,
+                                                draw_data,
+                                            )
+                                            .unwrap_or_else(|e| {
+                                                fail(EngineError::system("imgui renderer", e))
+                                            });
+                                        vulkan_renderer
+                                            .gpu_profiler()
+                                            .end_scope(device, command_buffer);
+                                        vulkan_renderer.device().end_label(command_buffer);
+                                    }
+
+                                    record_passes(
+                                        PassStage::AfterUi,
+                                        command_buffer,
+                                        extent,
+                                        window.id(),
+                                        camera_controller.view_projection_matrix(),
+                                    );
+                                },
+                                |_, command_buffer, target| {
+                                    // draw the imgui windows dragged out of the main window
+                                    #[cfg(feature = "imgui-viewports")]
+                                    if let Some(id) = imgui_viewports.viewport_of(target.id()) {
+                                        let Some(viewport) = imgui_context.viewport_by_id(id)
+                                        else {
+                                            return;
+                                        };
+                                        let _scope = alloc_audit::scope(Subsystem::ImGui);
+                                        vulkan_renderer
+                                            .device()
+                                            .begin_label(command_buffer, "imgui viewport");
+                                        imgui_renderer
+                                            .borrow_mut()
+                                            .render_viewport(
+                                                vulkan_renderer.device(),
+                                                command_buffer,
+                                                id,
+                                                viewport.draw_data(),
+                                            )
+                                            .unwrap_or_else(|e| {
+                                                fail(EngineError::system("imgui renderer", e))
+                                            });
+                                        vulkan_renderer.device().end_label(command_buffer);
+                                        return;
+                                    }
+
+                                    // draw the world seen by the camera of the window
+                                    let Some(additional_window) = additional_windows
+                                        .iter_mut()
+                                        .find(|w| w.window.id() == target.id())
+                                    else {
+                                        return;
+                                    };
+                                    let (extent, window_id) = (target.extent(), target.id());
+                                    vulkan_renderer
+                                        .device()
+                                        .begin_label(command_buffer, "additional window");
+                                    let view_projection = additional_window
+                                        .camera_controller
+                                        .view_projection_matrix();
+                                    record_passes(
+                                        PassStage::BeforeWorld,
+                                        command_buffer,
+                                        extent,
+                                        window_id,
+                                        view_projection,
+                                    );
+                                    {
+                                        let _scope = alloc_audit::scope(Subsystem::Renderer3D);
+                                        additional_window
+                                            .renderer3d
+                                            .render(
+                                                vulkan_renderer.device(),
+                                                command_buffer,
+                                                &mut vulkan_renderer.staging(),
+                                                view_projection,
+                                                &resources.meshes,
+                                                resources.world.query::<MeshView>(),
+                                            )
+                                            .unwrap_or_else(|e| {
+                                                fail(EngineError::system("renderer 3D", e))
+                                            });
+                                    }
+                                    {
+                                        let _scope = alloc_audit::scope(Subsystem::Renderer2D);
+                                        let mut render_callbacks = render_callbacks.borrow_mut();
+                                        let draw_order = render_callbacks.draw_order(
+                                            &resources.world,
+                                            vulkan_renderer.device(),
+                                            extent,
+                                            delta_time,
+                                            window_id,
+                                        );
+                                        additional_window
+                                            .renderer2d
+                                            .render(
+                                                vulkan_renderer.device(),
+                                                command_buffer,
+                                                &mut vulkan_renderer.staging(),
+                                                delta_time,
+                                                view_projection,
+                                                draw_order,
+                                            )
+                                            .unwrap_or_else(|e| {
+                                                fail(EngineError::system("renderer 2D", e))
+                                            });
+                                    }
+                                    for stage in [PassStage::AfterWorld, PassStage::AfterUi] {
+                                        record_passes(
+                                            stage,
+                                            command_buffer,
+                                            extent,
+                                            window_id,
+                                            view_projection,
+                                        );
+                                    }
+                                    vulkan_renderer.device().end_label(command_buffer);
+                                },
+                            ) {
+                                fail(e.into());
                             }
 
-                            vulkan_renderer.end_frame().expect("end frame succeeds");
+                            match vulkan_renderer.end_frame() {
+                                Err(e) if e.is_surface_lost() => {
+                                    if let Err(e) = recover_surface(&mut vulkan_renderer, &window) {
+                                        fail(e);
+                                    }
+                                }
+                                Err(e) => fail(e.into()),
+                                Ok(_) => (),
+                            }
+                            if let Some(tracker) = latency_tracker.as_mut() {
+                                tracker.on_present(time::Instant::now());
+                            }
+
+                            // the startup succeeded once a frame has been presented
+                            startup.complete();
+                        }
+                    }
+
+                    // let the application decide whether to keep running
+                    // NOTE: nothing can be rendered anymore once the device is lost
+                    if let Some(e) = frame_error.into_inner() {
+                        let _scope = alloc_audit::scope(Subsystem::Application);
+                        let keep_running = !e.is_device_lost()
+                            && application.on_error(&e, resources.context(frame_time));
+                        if keep_running {
+                            error!("frame failed: {e}");
+                        } else {
+                            error!("frame failed, exiting: {e}");
+                            failure = Some(e);
+                            *control_flow = ControlFlow::Exit;
+                            return;
+                        }
+                    }
+
+                    // export metrics
+                    #[cfg(feature = "metrics")]
+                    if let Some(exporter) = &metrics_exporter {
+                        let memory = vulkan_renderer.device().memory_stats();
+                        let render_stats = culled_renderer
+                            .as_ref()
+                            .map_or_else(|| renderer2d_system.stats(), |r| r.stats());
+                        let profiler = vulkan_renderer.gpu_profiler();
+                        exporter.update(Metrics {
+                            frames: frame_counter.frame_count(),
+                            frame_time,
+                            fps: frame_counter.fps(),
+                            objects: resources.world.len(),
+                            draw_calls: render_stats.draw_calls
+                                + renderer3d_system.stats().draw_calls,
+                            quads: render_stats.quads,
+                            memory_blocks: memory.block_count,
+                            memory_reserved: memory.reserved,
+                            memory_used: memory.used,
+                            gpu_frame_time: profiler.frame_time(),
+                            gpu_scopes: profiler.results().to_vec(),
+                        });
+                    }
+
+                    // record frame stats
+                    if let Some(recorder) = frame_stats_recorder.as_mut() {
+                        let memory = vulkan_renderer.device().memory_stats();
+                        let render_stats = culled_renderer
+                            .as_ref()
+                            .map_or_else(|| renderer2d_system.stats(), |r| r.stats());
+                        let result = recorder.record(&FrameStats {
+                            frame: frame_counter.frame_count(),
+                            frame_time,
+                            cpu_time: frame_start.elapsed(),
+                            gpu_time: vulkan_renderer.gpu_profiler().frame_time(),
+                            draw_calls: render_stats.draw_calls
+                                + renderer3d_system.stats().draw_calls,
+                            quads: render_stats.quads,
+                            memory_used: memory.used,
+                            memory_reserved: memory.reserved,
+                        });
+                        if let Err(e) = result {
+                            error!("record frame stats: {e}");
+                            frame_stats::switch_recording(&mut frame_stats_recorder, None);
                         }
                     }
+
+                    // wait for the next frame when the frame rate is limited
+                    if let Some(frame_limiter) = frame_limiter.as_mut() {
+                        frame_limiter.wait();
+                    }
                 }
 
                 // catch-all
                 _ => (),
             }
-        });
+        };
+
+        // run main loop
+        // NOTE: run_return() gives the systems back once the loop exits, so that
+        //       they are dropped in reverse order of creation: subsystems release
+        //       their resources before the renderer, which owns the device.
+        let exit_code = event_loop.run_return(catch_panics(&mut panic_payload, handle_event));
+
+        // NOTE: the render systems of the application own raw Vulkan objects,
+        //       which must not be in use anymore
+        unsafe {
+            if let Err(e) = vulkan_renderer.device().device_wait_idle() {
+                error!("wait for device idle: {e:?}");
+            }
+        }
+        renderer_systems.destroy(vulkan_renderer.device());
+
+        // NOTE: the surfaces of the additional windows are destroyed before the
+        //       windows themselves
+        #[cfg(feature = "imgui-viewports")]
+        unsafe {
+            imgui_viewports.destroy(&mut vulkan_renderer, &mut imgui_renderer.borrow_mut());
+        }
+        for additional_window in &additional_windows {
+            if let Err(e) = unsafe { vulkan_renderer.remove_window(additional_window.window.id()) }
+            {
+                error!("remove window: {e:?}");
+            }
+        }
+
+        frame_stats::switch_recording(&mut frame_stats_recorder, None);
+
+        if let Some(p) = latency_tracker.and_then(|mut tracker| tracker.percentiles()) {
+            info!(
+                "input latency: p50 {:?}, p90 {:?}, p99 {:?}, max {:?}",
+                p.p50, p.p90, p.p99, p.max
+            );
+        }
+
+        // stop serving the metrics
+        #[cfg(feature = "metrics")]
+        drop(metrics_exporter);
+
+        // the engine is shut down, the panic can unwind
+        // NOTE: the previous hook is restored first, which can not be done
+        //       while unwinding
+        drop(panic_hook);
+        if let Some(payload) = panic_payload {
+            panic::resume_unwind(payload);
+        }
+        match failure {
+            Some(e) => Err(e),
+            None => Ok(exit_code),
+        }
     }
 }
 
-pub struct ApplicationContext<'a> {
-    objects: &'a mut Vec<GameObject>,
-    delta_time: time::Duration,
+/// Loads the sprite textures, if any. Sprites are drawn with their color only
+/// if they can not be loaded, e.g. without descriptor indexing.
+pub(crate) fn load_sprite_textures(
+    renderer: &VulkanRenderer,
+    paths: &[PathBuf],
+) -> Option<SpriteTextures> {
+    if paths.is_empty() {
+        return None;
+    }
+    unsafe { SpriteTextures::load(renderer.device(), paths, renderer.max_frames_in_flight()) }
+        .map_err(|e| warn!("load sprite textures, sprites are drawn untextured: {e}"))
+        .ok()
 }
 
-impl<'a> ApplicationContext<'a> {
-    fn new(objects: &'a mut Vec<GameObject>, delta_time: time::Duration) -> Self {
-        Self {
-            objects,
+/// Grabs the cursor, confining it instead when it can not be locked, e.g. on
+/// Windows and X11.
+fn set_cursor_grab(window: &Window, mode: CursorGrabMode) {
+    let result = window.set_cursor_grab(mode).or_else(|e| match mode {
+        CursorGrabMode::Locked => window.set_cursor_grab(CursorGrabMode::Confined),
+        _ => Err(e),
+    });
+    if let Err(e) = result {
+        error!("set cursor grab {mode:?}: {e}");
+    }
+}
+
+/// Recreates the surface after it has been lost, e.g. when the window was
+/// destroyed by the platform.
+unsafe fn recover_surface(renderer: &mut VulkanRenderer, window: &Window) -> Result<()> {
+    warn!("surface lost, recreating it");
+    renderer.recreate_surface(window)?;
+    Ok(())
+}
+
+/// Returns a path in the working directory named after the current time.
+#[cfg(feature = "editor-tools")]
+fn default_screenshot_path() -> PathBuf {
+    let millis = time::SystemTime::now()
+        .duration_since(time::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis();
+    PathBuf::from(format!("screenshot-{millis}.png"))
+}
+
+/// Window drawn in addition to the main one, showing the world through its
+/// own camera. It has its own 2D and 3D renderers, which render once per
+/// frame.
+struct AdditionalWindow {
+    window: Window,
+    camera_controller: CameraController<CameraOrthographic>,
+    renderer2d: Renderer2DSystem,
+    renderer3d: Renderer3DSystem,
+}
+
+impl AdditionalWindow {
+    /// Creates the systems drawing to a window added to the renderer.
+    unsafe fn new(window: Window, renderer: &VulkanRenderer) -> Result<Self> {
+        let PhysicalSize { width, height } = window.inner_size();
+        let camera_controller = CameraController::new(CameraOrthographic::new(width, height));
+        let renderer2d = Renderer2DSystem::new(
+            renderer.device(),
+            renderer.renderpass(),
+            renderer.max_frames_in_flight(),
+        )
+        .map_err(|e| EngineError::system("renderer 2D", e))?;
+        let renderer3d = Renderer3DSystem::new(
+            renderer.device(),
+            renderer.renderpass(),
+            renderer.max_frames_in_flight(),
+        )
+        .map_err(|e| EngineError::system("renderer 3D", e))?;
+        Ok(Self {
+            window,
+            camera_controller,
+            renderer2d,
+            renderer3d,
+        })
+    }
+}
+
+/// Actions requested by the application, applied to the next frame.
+#[derive(Default)]
+pub(crate) struct FrameRequests {
+    capture: bool,
+    pub(crate) screenshot: Option<PathBuf>,
+    world_layer: Option<LayerSettings>,
+    pub(crate) clear_color: Option<Vector4<f32>>,
+    frame_limit: Option<Option<FrameLimit>>,
+    /// File to record frame stats to, None stopping the recording.
+    frame_stats: Option<Option<PathBuf>>,
+    ime_allowed: Option<bool>,
+    cursor_visible: Option<bool>,
+    cursor_grab: Option<CursorGrabMode>,
+    cursor_icon: Option<CursorIcon>,
+    display_mode: Option<DisplayMode>,
+    /// Exit code requested by the application.
+    pub(crate) exit: Option<i32>,
+    pub(crate) states: Vec<Transition>,
+    screen_transition: Option<Option<ScreenTransition>>,
+}
+
+/// Objects of the engine given to the application through its context.
+pub(crate) struct Resources {
+    pub world: World,
+    pub meshes: HandleMap<Mesh>,
+    pub prefabs: PrefabLibrary,
+    pub event_bus: EventBus,
+    pub requests: FrameRequests,
+    pub input: InputSystem,
+    pub game_clock: GameClock,
+    pub display: Display,
+    pub jobs: Arc<JobPool>,
+    pub window_ids: Vec<WindowId>,
+    pub safe_mode: bool,
+    // NOTE: the render callbacks own Vulkan objects, they are dropped before
+    //       the renderer
+    pub render_callbacks: RenderCallbacks,
+}
+
+impl Resources {
+    /// Returns the context given to the application for a frame lasting
+    /// `delta_time`.
+    pub fn context(&mut self, delta_time: time::Duration) -> ApplicationContext<'_> {
+        ApplicationContext {
+            world: &mut self.world,
+            meshes: &mut self.meshes,
+            prefabs: &mut self.prefabs,
+            events: &mut self.event_bus,
+            render_callbacks: &mut self.render_callbacks,
+            requests: &mut self.requests,
+            input: &self.input,
+            game_clock: &mut self.game_clock,
+            windows: &self.window_ids,
+            jobs: &self.jobs,
+            display: &self.display,
             delta_time,
+            safe_mode: self.safe_mode,
         }
     }
+}
+
+pub struct ApplicationContext<'a> {
+    world: &'a mut World,
+    meshes: &'a mut HandleMap<Mesh>,
+    prefabs: &'a mut PrefabLibrary,
+    events: &'a mut EventBus,
+    render_callbacks: &'a mut RenderCallbacks,
+    requests: &'a mut FrameRequests,
+    input: &'a InputSystem,
+    game_clock: &'a mut GameClock,
+    windows: &'a [WindowId],
+    jobs: &'a JobPool,
+    display: &'a Display,
+    delta_time: time::Duration,
+    safe_mode: bool,
+}
 
+impl<'a> ApplicationContext<'a> {
+    /// Returns the duration of the last frame, scaled by the time scale and
+    /// zero while paused, see `set_time_scale()`.
     pub fn delta_time(&self) -> time::Duration {
+        self.game_clock.apply(self.delta_time)
+    }
+
+    /// Returns the duration of the last frame, ignoring the time scale, e.g.
+    /// to animate a pause menu.
+    pub fn real_delta_time(&self) -> time::Duration {
         self.delta_time
     }
 
-    pub fn add_object(&mut self, object: GameObject) {
-        self.objects.push(object);
+    pub fn time_scale(&self) -> f32 {
+        self.game_clock.scale()
+    }
+
+    /// Scales the delta time given to updates, render callbacks, passes and
+    /// render systems, e.g. 0.5 for slow motion. The UI and the camera keep
+    /// running in real time. A scale that is negative or not finite is
+    /// ignored, and one above 100 is clamped.
+    pub fn set_time_scale(&mut self, scale: f32) {
+        self.game_clock.set_scale(scale);
+    }
+
+    /// Pauses the simulation: the delta time is zero until `resume()`. The
+    /// time scale is kept.
+    pub fn pause(&mut self) {
+        self.game_clock.set_paused(true);
+    }
+
+    pub fn resume(&mut self) {
+        self.game_clock.set_paused(false);
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.game_clock.is_paused()
+    }
+
+    /// Returns the state of the keyboard, mouse and text input. Input is only
+    /// given to the focused window.
+    pub fn input(&self) -> &InputSystem {
+        self.input
+    }
+
+    /// Returns the ids of the open windows, the main window first. Passes and
+    /// render callbacks can tell which window they draw to from
+    /// `PassContext::window()`.
+    pub fn windows(&self) -> &[WindowId] {
+        self.windows
+    }
+
+    /// Returns the pool running jobs on the worker threads, e.g. to load
+    /// assets in parallel with `assets::load_objs()`.
+    pub fn jobs(&self) -> &JobPool {
+        self.jobs
+    }
+
+    /// Returns true if the engine started in safe mode, with conservative
+    /// renderer settings, because previous startups failed.
+    pub fn safe_mode(&self) -> bool {
+        self.safe_mode
+    }
+
+    /// Captures the next frame with RenderDoc. Requires the `renderdoc`
+    /// feature and the application to run under RenderDoc.
+    pub fn request_frame_capture(&mut self) {
+        self.requests.capture = true;
+    }
+
+    /// Saves the next frame to a PNG file. The file is written in the
+    /// background a few frames later; failures are logged.
+    pub fn capture_screenshot(&mut self, path: impl Into<PathBuf>) {
+        self.requests.screenshot = Some(path.into());
+    }
+
+    /// Starts recording the statistics of each frame to a file from the next
+    /// frame, stopping any previous recording. The file is CSV, or JSON with
+    /// the `.json` extension, see the `frame_stats` module; failures are
+    /// logged.
+    pub fn start_frame_stats(&mut self, path: impl Into<PathBuf>) {
+        self.requests.frame_stats = Some(Some(path.into()));
+    }
+
+    /// Stops recording frame stats, completing the file.
+    pub fn stop_frame_stats(&mut self) {
+        self.requests.frame_stats = Some(None);
+    }
+
+    /// Changes the resolution and refresh rate of the world from the next
+    /// frame, e.g. to lower the resolution when frames take too long.
+    pub fn set_world_layer(&mut self, settings: LayerSettings) {
+        self.requests.world_layer = Some(settings);
+    }
+
+    /// Changes the color the windows are cleared to from the next frame. The
+    /// world layer, when drawn to a target of its own, is composited over it.
+    pub fn set_clear_color(&mut self, color: Vector4<f32>) {
+        self.requests.clear_color = Some(color);
+    }
+
+    /// Changes the frame rate limit from the next frame, e.g. to lower it
+    /// while the application is idle. Use None to render frames as fast as
+    /// possible.
+    pub fn set_frame_limit(&mut self, limit: Option<FrameLimit>) {
+        self.requests.frame_limit = Some(limit);
+    }
+
+    /// Enables IME composition in the main window from the next frame, e.g.
+    /// while a text field is focused, see `InputSystem::ime_preedit()`. It is
+    /// disabled by default, as it can intercept key presses.
+    pub fn set_ime_allowed(&mut self, allowed: bool) {
+        self.requests.ime_allowed = Some(allowed);
+    }
+
+    /// Shows or hides the cursor over the main window from the next frame.
+    /// The UI does not change the cursor while it is hidden.
+    pub fn set_cursor_visible(&mut self, visible: bool) {
+        self.requests.cursor_visible = Some(visible);
+    }
+
+    /// Confines the cursor to the main window or locks it in place from the
+    /// next frame, e.g. for mouse look with `InputSystem::mouse_delta()`. Not
+    /// every platform supports both modes: locking falls back to confining,
+    /// and failures are logged.
+    pub fn set_cursor_grab(&mut self, mode: CursorGrabMode) {
+        self.requests.cursor_grab = Some(mode);
+    }
+
+    /// Changes the cursor shown over the main window from the next frame.
+    /// The UI does not change the cursor while its icon is not the default
+    /// one.
+    pub fn set_cursor_icon(&mut self, icon: CursorIcon) {
+        self.requests.cursor_icon = Some(icon);
+    }
+
+    /// Exits the event loop at the end of the frame, like closing the main
+    /// window without asking `Application::on_exit_requested()`.
+    pub fn request_exit(&mut self) {
+        self.request_exit_with_code(0);
+    }
+
+    /// Exits like `request_exit()`, `Engine::run()` returning the code, e.g.
+    /// for the process to exit with it.
+    pub fn request_exit_with_code(&mut self, code: i32) {
+        self.requests.exit = Some(code);
+    }
+
+    /// Returns the monitors the main window can be shown on, as enumerated
+    /// at startup or on the last change of display mode.
+    pub fn monitors(&self) -> &[MonitorInfo] {
+        self.display.monitors()
+    }
+
+    pub fn display_mode(&self) -> DisplayMode {
+        self.display.mode()
+    }
+
+    /// Switches the main window to a display mode from the next frame, e.g.
+    /// to exclusive fullscreen with a video mode of `monitors()`.
+    pub fn set_display_mode(&mut self, mode: DisplayMode) {
+        self.requests.display_mode = Some(mode);
+    }
+
+    /// Spawns an object with the components of a bundle, e.g. a
+    /// `GameObject`.
+    pub fn add_object(&mut self, object: impl Bundle) -> ObjectId {
+        self.world.spawn(object)
+    }
+
+    /// Despawns an object, along with its render callback. Returns false if
+    /// the object was already removed.
+    pub fn remove_object(&mut self, id: ObjectId) -> bool {
+        self.world.despawn(id)
+    }
+
+    /// Returns the components of an object spawned from a `GameObject`, see
+    /// `world()` for the other components.
+    pub fn get_object(&self, id: ObjectId) -> Option<ObjectView<'_>> {
+        self.world.query_one::<ObjectView>(id)
+    }
+
+    /// Returns the components of an object to modify, e.g. to move it.
+    /// Changes are drawn from the current frame.
+    pub fn get_object_mut(&mut self, id: ObjectId) -> Option<ObjectMut<'_>> {
+        self.world.query_one_mut::<ObjectMut>(id)
+    }
+
+    /// Iterates the objects and their ids.
+    pub fn objects(&self) -> impl Iterator<Item = (ObjectId, ObjectView<'_>)> {
+        self.world.query::<(ObjectId, ObjectView)>()
+    }
+
+    pub fn objects_mut(&mut self) -> impl Iterator<Item = (ObjectId, ObjectMut<'_>)> {
+        self.world.query_mut::<(ObjectId, ObjectMut)>()
+    }
+
+    /// Spawns an object from the prefab of the given name, at a position.
+    /// Returns None if there is no such prefab.
+    pub fn spawn_prefab(&mut self, name: &str, position: Vector3<f32>) -> Option<ObjectId> {
+        self.spawn_prefab_with(name, position, |_| {})
+    }
+
+    /// Spawns an object from the prefab of the given name, at a position,
+    /// after overriding some of its components, e.g. its color:
+    ///
+    /// ```ignore
+    /// ctx.spawn_prefab_with("enemy", position, |enemy| {
+    ///     enemy.object.color.color = Vector4::new(0.0, 1.0, 0.0, 1.0);
+    /// });
+    /// ```
+    ///
+    /// Returns None if there is no such prefab.
+    pub fn spawn_prefab_with(
+        &mut self,
+        name: &str,
+        position: Vector3<f32>,
+        overrides: impl FnOnce(&mut Prefab),
+    ) -> Option<ObjectId> {
+        let Some(prefab) = self.prefabs.get(name) else {
+            warn!("no prefab named {name:?}");
+            return None;
+        };
+        let mut prefab = *prefab;
+        prefab.object.transform.position = position;
+        overrides(&mut prefab);
+        Some(self.world.spawn(prefab))
+    }
+
+    /// Returns the prefabs, e.g. to list them in an editor palette.
+    pub fn prefabs(&self) -> &PrefabLibrary {
+        self.prefabs
+    }
+
+    /// Returns the prefabs to modify, e.g. to load more of them.
+    pub fn prefabs_mut(&mut self) -> &mut PrefabLibrary {
+        self.prefabs
+    }
+
+    /// Sends an event to the readers of its type, see `EventBus`.
+    pub fn send_event<T: 'static>(&mut self, event: T) {
+        self.events.send(event);
+    }
+
+    /// Returns the events sent during the last two frames, e.g. the
+    /// `CollisionEvent`s, to read them with an `EventReader`.
+    pub fn events(&self) -> &EventBus {
+        self.events
+    }
+
+    pub fn events_mut(&mut self) -> &mut EventBus {
+        self.events
+    }
+
+    /// Pushes a state on top of the stack once the update of the frame is
+    /// done, pausing the current one, see `State`.
+    pub fn push_state(&mut self, state: impl State + 'static) {
+        self.requests.states.push(Transition::Push(Box::new(state)));
+    }
+
+    /// Pops the state on top of the stack once the update of the frame is
+    /// done, resuming the one below.
+    pub fn pop_state(&mut self) {
+        self.requests.states.push(Transition::Pop);
+    }
+
+    /// Replaces the state on top of the stack once the update of the frame
+    /// is done, e.g. the menu by the game.
+    pub fn replace_state(&mut self, state: impl State + 'static) {
+        self.requests
+            .states
+            .push(Transition::Replace(Box::new(state)));
+    }
+
+    /// Changes the effect played over the world when the states switch, from
+    /// the next switch. Use None to switch them right away.
+    pub fn set_screen_transition(&mut self, transition: Option<ScreenTransition>) {
+        self.requests.screen_transition = Some(transition);
+    }
+
+    /// Starts animating a property of an object, along with its other tweens.
+    /// A `TweenFinished` event is sent once it finishes. Returns false if the
+    /// object was removed.
+    pub fn add_tween(&mut self, id: ObjectId, tween: Tween) -> bool {
+        match self.world.get_mut::<Tweens>(id) {
+            Some(tweens) => {
+                tweens.tweens.push(tween);
+                true
+            }
+            None => self.world.insert(id, Tweens::from(tween)),
+        }
+    }
+
+    /// Returns the world holding the objects and their components.
+    pub fn world(&self) -> &World {
+        self.world
+    }
+
+    /// Returns the world to modify, e.g. to move objects. Changes are drawn
+    /// from the current frame.
+    pub fn world_mut(&mut self) -> &mut World {
+        self.world
+    }
+
+    /// Adds a mesh objects can be drawn as, see `GameObject::with_mesh()`.
+    /// Fails if the mesh can not be drawn.
+    pub fn add_mesh(&mut self, mesh: Mesh) -> result::Result<MeshId, MeshError> {
+        mesh.validate()?;
+        Ok(self.meshes.insert(mesh))
+    }
+
+    /// Removes a mesh. The objects drawn as the mesh are not drawn anymore.
+    pub fn remove_mesh(&mut self, id: MeshId) -> Option<Mesh> {
+        self.meshes.remove(id)
+    }
+
+    /// Attaches a callback that records custom commands right after the
+    /// object is drawn, replacing the previous one. The callback is dropped
+    /// along with the object, and is not recorded while the object is drawn
+    /// as a mesh.
+    pub fn set_render_callback(&mut self, id: ObjectId, callback: Box<dyn RenderCallback>) {
+        self.render_callbacks.insert(id, callback);
+    }
+
+    pub fn remove_render_callback(&mut self, id: ObjectId) -> Option<Box<dyn RenderCallback>> {
+        self.render_callbacks.remove(id)
     }
 }
 
 pub trait Application {
     fn on_init(&mut self, _ctx: ApplicationContext) {}
     fn on_update(&mut self, _ctx: ApplicationContext) {}
+    /// Called before the update of the first frame the main window is
+    /// minimized, from which frames are updated but not rendered.
+    fn on_minimized(&mut self, _ctx: ApplicationContext) {}
+    /// Called before the update of the first frame the main window is
+    /// restored after being minimized.
+    fn on_restored(&mut self, _ctx: ApplicationContext) {}
+    /// Called when the main window is resized, with its new size in physical
+    /// pixels.
+    fn on_resize(&mut self, _width: u32, _height: u32, _ctx: ApplicationContext) {}
+    /// Called when the colliders of two objects start or stop overlapping,
+    /// once the objects moved during the frame. The event is also sent to the
+    /// event bus, see `ApplicationContext::events()`.
+    fn on_collision(&mut self, _event: CollisionEvent, _ctx: ApplicationContext) {}
+    /// Called once when the event loop exits, before the renderers are
+    /// destroyed, e.g. to save the state of the application.
+    /// It is not called once the application panicked.
+    fn on_shutdown(&mut self, _ctx: ApplicationContext) {}
+    /// Called when the main window is asked to close, e.g. with its close
+    /// button. Returns false to keep running, e.g. to ask for confirmation
+    /// before calling `ApplicationContext::request_exit()`.
+    fn on_exit_requested(&mut self, _ctx: ApplicationContext) -> bool {
+        true
+    }
+    /// Called when a frame fails, e.g. when a renderer runs out of memory,
+    /// once the frame ended. Returns true to keep running, or false to exit,
+    /// `Engine::run()` returning the error. The engine exits whatever is
+    /// returned once the device is lost.
+    fn on_error(&mut self, _error: &EngineError, _ctx: ApplicationContext) -> bool {
+        false
+    }
+    /// Called for every event of the event loop, before the engine handles
+    /// it, e.g. to react to dropped files. The input system does not include
+    /// the event yet.
+    fn on_event(&mut self, _event: &Event<()>, _ctx: ApplicationContext) {}
+    /// Builds the UI of the application, drawn on top of the main window.
+    /// Called every frame the main window is visible, after the update.
+    #[cfg(feature = "imgui")]
+    fn on_render_ui(&mut self, _ui: &mut imgui::Ui) {}
 }
@@ -0,0 +1,85 @@
+use std::{error, fmt, result};
+
+use vulkan_renderer::error::RendererError;
+use winit::error::OsError;
+
+pub type Result<T> = result::Result<T, EngineError>;
+
+/// Errors returned by the engine.
+#[derive(Debug)]
+pub enum EngineError {
+    /// The builder was not given an application.
+    MissingApplication,
+
+    /// The builder was not given a window builder.
+    MissingWindowBuilder,
+
+    /// A headless engine was asked for a frame without rendering enabled.
+    HeadlessRenderingDisabled,
+
+    /// A custom pass uploaded more data than its buffer can hold.
+    PassBufferOverflow { size: u64, capacity: u64 },
+
+    /// A window could not be created.
+    Window(OsError),
+
+    /// The renderer failed.
+    Renderer(RendererError),
+
+    /// A system drawing with the renderer failed, e.g. the 2D renderer.
+    System {
+        name: &'static str,
+        source: Box<dyn error::Error>,
+    },
+}
+
+impl EngineError {
+    pub(crate) fn system(name: &'static str, source: Box<dyn error::Error>) -> Self {
+        Self::System { name, source }
+    }
+
+    /// The logical device has been lost and the renderer must be recreated.
+    pub fn is_device_lost(&self) -> bool {
+        matches!(self, Self::Renderer(e) if e.is_device_lost())
+    }
+}
+
+impl fmt::Display for EngineError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::MissingApplication => write!(f, "app is None"),
+            Self::MissingWindowBuilder => write!(f, "window builder is None"),
+            Self::HeadlessRenderingDisabled => write!(f, "headless rendering is disabled"),
+            Self::PassBufferOverflow { size, capacity } => write!(
+                f,
+                "pass buffer overflow: {size} bytes written, capacity is {capacity}"
+            ),
+            Self::Window(e) => write!(f, "create window: {e}"),
+            Self::Renderer(e) => write!(f, "renderer: {e}"),
+            Self::System { name, source } => write!(f, "{name}: {source}"),
+        }
+    }
+}
+
+impl error::Error for EngineError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Self::Window(e) => Some(e),
+            Self::Renderer(e) => Some(e),
+            Self::System { source, .. } => Some(source.as_ref()),
+            _ => None,
+        }
+    }
+}
+
+impl From<OsError> for EngineError {
+    fn from(e: OsError) -> Self {
+        Self::Window(e)
+    }
+}
+
+impl From<RendererError> for EngineError {
+    fn from(e: RendererError) -> Self {
+        Self::Renderer(e)
+    }
+}
@@ -0,0 +1,241 @@
+//! Typed event queues, for the systems and the application to communicate
+//! without knowing each other, e.g. gameplay code reacting to collisions.
+//!
+//! Events are kept for two frames, the one they are sent in and the next one,
+//! so that a reader sees every event once whether it runs before or after
+//! the writer in a frame. Each reader keeps track of the events it read with
+//! an `EventReader`.
+//!
+//! The engine sends the `CollisionEvent`s and the `TweenFinished` events to
+//! the bus of the application context, see `ApplicationContext::events()`.
+
+use std::any::{Any, TypeId};
+use std::collections::HashMap;
+use std::marker::PhantomData;
+use std::{fmt, mem};
+
+/// Events of a type sent during the last two frames.
+#[derive(Debug)]
+pub struct Events<T> {
+    /// Events sent during the previous frame.
+    previous: Vec<T>,
+    /// Events sent during the current frame.
+    current: Vec<T>,
+    /// Number of events dropped, sent before the previous frame.
+    dropped: usize,
+}
+
+impl<T> Default for Events<T> {
+    fn default() -> Self {
+        Self {
+            previous: Vec::new(),
+            current: Vec::new(),
+            dropped: 0,
+        }
+    }
+}
+
+impl<T> Events<T> {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn send(&mut self, event: T) {
+        self.current.push(event);
+    }
+
+    /// Returns the events of the last two frames, oldest first.
+    pub fn iter(&self) -> impl Iterator<Item = &T> {
+        self.previous.iter().chain(&self.current)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.previous.is_empty() && self.current.is_empty()
+    }
+
+    /// Returns a reader of the events sent from now on. Readers created with
+    /// `EventReader::default()` also read the events already sent.
+    pub fn reader(&self) -> EventReader<T> {
+        EventReader::new(self.sent())
+    }
+
+    /// Returns the events a reader did not read yet, oldest first, and marks
+    /// them as read. Events dropped before being read are missed.
+    pub fn read<'a>(&'a self, reader: &mut EventReader<T>) -> impl Iterator<Item = &'a T> {
+        let unread = reader.read.saturating_sub(self.dropped);
+        reader.read = self.sent();
+        self.iter().skip(unread)
+    }
+
+    /// Drops the events of the previous frame. Called by the engine at the
+    /// start of every frame.
+    pub fn update(&mut self) {
+        self.dropped += self.previous.len();
+        mem::swap(&mut self.previous, &mut self.current);
+        self.current.clear();
+    }
+
+    /// Returns the number of events sent since the queue was created.
+    fn sent(&self) -> usize {
+        self.dropped + self.previous.len() + self.current.len()
+    }
+}
+
+/// Position of a reader in a queue of events, see `Events::read()`.
+pub struct EventReader<T> {
+    /// Number of events read since the queue was created.
+    read: usize,
+    _events: PhantomData<fn() -> T>,
+}
+
+impl<T> EventReader<T> {
+    fn new(read: usize) -> Self {
+        Self {
+            read,
+            _events: PhantomData,
+        }
+    }
+}
+
+impl<T> Default for EventReader<T> {
+    fn default() -> Self {
+        Self::new(0)
+    }
+}
+
+impl<T> Clone for EventReader<T> {
+    fn clone(&self) -> Self {
+        Self::new(self.read)
+    }
+}
+
+impl<T> fmt::Debug for EventReader<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("EventReader")
+            .field("read", &self.read)
+            .finish()
+    }
+}
+
+/// Queue of events of any type, to update them together.
+trait Queue: Any {
+    fn update(&mut self);
+    fn as_any(&self) -> &dyn Any;
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+}
+
+impl<T: 'static> Queue for Events<T> {
+    fn update(&mut self) {
+        Events::update(self);
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+/// Queues of events by type, created as events are sent.
+#[derive(Default)]
+pub struct EventBus {
+    queues: HashMap<TypeId, Box<dyn Queue>>,
+}
+
+impl EventBus {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn send<T: 'static>(&mut self, event: T) {
+        self.events_mut::<T>().send(event);
+    }
+
+    /// Returns the events of a type, or None if none were ever sent.
+    pub fn events<T: 'static>(&self) -> Option<&Events<T>> {
+        self.queues
+            .get(&TypeId::of::<T>())
+            .map(|queue| queue.as_any().downcast_ref().expect("queue of its type"))
+    }
+
+    pub fn events_mut<T: 'static>(&mut self) -> &mut Events<T> {
+        self.queues
+            .entry(TypeId::of::<T>())
+            .or_insert_with(|| Box::<Events<T>>::default())
+            .as_any_mut()
+            .downcast_mut()
+            .expect("queue of its type")
+    }
+
+    /// Returns the events of a type a reader did not read yet, see
+    /// `Events::read()`.
+    pub fn read<'a, T: 'static>(
+        &'a self,
+        reader: &mut EventReader<T>,
+    ) -> impl Iterator<Item = &'a T> {
+        self.events::<T>()
+            .map(|events| events.read(reader))
+            .into_iter()
+            .flatten()
+    }
+
+    /// Drops the events of the previous frame, of every type.
+    pub(crate) fn update(&mut self) {
+        for queue in self.queues.values_mut() {
+            queue.update();
+        }
+    }
+}
+
+impl fmt::Debug for EventBus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("EventBus")
+            .field("queues", &self.queues.len())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn events_are_read_once_within_two_frames() {
+        let mut bus = EventBus::new();
+        let mut early = EventReader::<u32>::default();
+        let mut late = bus.events_mut::<u32>().reader();
+        assert_eq!(bus.read(&mut early).count(), 0);
+
+        // frame 1: the early reader runs before the writer
+        bus.send(1_u32);
+        bus.send(2_u32);
+        bus.send("other type");
+        assert_eq!(bus.read(&mut late).collect::<Vec<_>>(), [&1, &2]);
+
+        // frame 2
+        bus.update();
+        bus.send(3_u32);
+        assert_eq!(bus.read(&mut early).collect::<Vec<_>>(), [&1, &2, &3]);
+        assert_eq!(bus.read(&mut late).collect::<Vec<_>>(), [&3]);
+
+        // frame 3: the events of frame 1 are dropped
+        bus.update();
+        bus.send(4_u32);
+        assert_eq!(bus.events::<u32>().unwrap().iter().count(), 2);
+        assert_eq!(bus.read(&mut early).collect::<Vec<_>>(), [&4]);
+        assert_eq!(
+            bus.read(&mut EventReader::<u32>::default())
+                .collect::<Vec<_>>(),
+            [&3, &4]
+        );
+
+        // frame 5: the late reader missed the events of frame 3
+        bus.update();
+        bus.update();
+        bus.send(5_u32);
+        assert_eq!(bus.read(&mut late).collect::<Vec<_>>(), [&5]);
+        assert!(bus.events::<f32>().is_none());
+    }
+}
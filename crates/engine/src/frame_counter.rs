@@ -1,7 +1,12 @@
-use std::time;
+use std::collections::VecDeque;
+use std::{fmt, time};
 
 use cgmath::Zero;
 
+/// Number of recent frame times kept by `FrameCounter`, about four seconds
+/// at 60 fps.
+pub const FRAME_HISTORY_LEN: usize = 240;
+
 #[derive(Debug, PartialEq, PartialOrd)]
 pub struct FrameCounter {
     // number of frames is incremented each time on_update() is called.
@@ -14,6 +19,10 @@ pub struct FrameCounter {
     delta_time: time::Duration,
     // the last instant provided to on_update().
     last_time: time::Instant,
+    // delta times of the most recent frames, oldest first.
+    history: VecDeque<time::Duration>,
+    // scratch buffer used to sort the history.
+    sorted: Vec<time::Duration>,
 }
 
 impl FrameCounter {
@@ -26,6 +35,10 @@ impl FrameCounter {
         // compute delta time and set last time to current
         self.delta_time = current_time - self.last_time;
         self.last_time = current_time;
+        if self.history.len() == FRAME_HISTORY_LEN {
+            self.history.pop_front();
+        }
+        self.history.push_back(self.delta_time);
 
         // increment counters
         self.frame_count += 1;
@@ -38,7 +51,6 @@ impl FrameCounter {
         }
     }
 
-    #[allow(unused)]
     pub fn frame_count(&self) -> u64 {
         self.frame_count
     }
@@ -50,6 +62,114 @@ impl FrameCounter {
     pub fn delta_time(&self) -> time::Duration {
         self.delta_time
     }
+
+    /// Returns the delta times of the recent frames, oldest first.
+    #[cfg_attr(not(feature = "editor-tools"), allow(dead_code))]
+    pub fn frame_times(&self) -> impl ExactSizeIterator<Item = time::Duration> + '_ {
+        self.history.iter().copied()
+    }
+
+    /// Returns the average, 1% low and percentiles of the recent frame
+    /// times, if any frame has been counted.
+    pub fn frame_time_stats(&mut self) -> Option<FrameTimeStats> {
+        if self.history.is_empty() {
+            return None;
+        }
+        self.sorted.clear();
+        self.sorted.extend(self.history.iter());
+        self.sorted.sort_unstable();
+
+        let sorted = &self.sorted;
+        let percentile = |p: usize| sorted[(sorted.len() - 1) * p / 100];
+        // NOTE: the 1% low is averaged over at least the slowest frame
+        let slowest = &sorted[sorted.len() - (sorted.len() / 100).max(1)..];
+        Some(FrameTimeStats {
+            frames: sorted.len(),
+            average: sorted.iter().sum::<time::Duration>() / sorted.len() as u32,
+            one_percent_low: slowest.iter().sum::<time::Duration>() / slowest.len() as u32,
+            p50: percentile(50),
+            p99: percentile(99),
+            max: sorted[sorted.len() - 1],
+        })
+    }
+}
+
+/// Statistics of the recent frame times.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FrameTimeStats {
+    /// Number of frames the statistics are computed over.
+    pub frames: usize,
+    pub average: time::Duration,
+    /// Average time of the slowest 1% of the frames.
+    pub one_percent_low: time::Duration,
+    pub p50: time::Duration,
+    pub p99: time::Duration,
+    pub max: time::Duration,
+}
+
+impl FrameTimeStats {
+    pub fn average_fps(&self) -> f64 {
+        fps(self.average)
+    }
+
+    /// Returns the frame rate of the slowest 1% of the frames.
+    pub fn one_percent_low_fps(&self) -> f64 {
+        fps(self.one_percent_low)
+    }
+}
+
+impl fmt::Display for FrameTimeStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "avg {:.2} fps ({:.2?}), 1% low {:.2} fps ({:.2?}), p99 {:.2?}, max {:.2?}",
+            self.average_fps(),
+            self.average,
+            self.one_percent_low_fps(),
+            self.one_percent_low,
+            self.p99,
+            self.max
+        )
+    }
+}
+
+fn fps(frame_time: time::Duration) -> f64 {
+    if frame_time.is_zero() {
+        0.0
+    } else {
+        1.0 / frame_time.as_secs_f64()
+    }
+}
+
+/// Draws the recent frame times as a histogram and their statistics in an
+/// imgui window.
+#[cfg(feature = "editor-tools")]
+pub(crate) fn draw_hud(ui: &vulkan_imgui::imgui::Ui, frame_counter: &mut FrameCounter) {
+    ui.window("Frame times").build(|| {
+        let Some(stats) = frame_counter.frame_time_stats() else {
+            ui.text("no frame yet");
+            return;
+        };
+        let ms = |t: time::Duration| t.as_secs_f32() * 1000.0;
+        ui.text(format!(
+            "avg: {:.2} ms ({:.1} fps)",
+            ms(stats.average),
+            stats.average_fps()
+        ));
+        ui.text(format!(
+            "1% low: {:.2} ms ({:.1} fps)",
+            ms(stats.one_percent_low),
+            stats.one_percent_low_fps()
+        ));
+        ui.text(format!("p50: {:.2} ms", ms(stats.p50)));
+        ui.text(format!("p99: {:.2} ms", ms(stats.p99)));
+        let frame_times: Vec<f32> = frame_counter.frame_times().map(ms).collect();
+        ui.plot_histogram("##frame times", &frame_times)
+            .scale_min(0.0)
+            .scale_max(ms(stats.max))
+            .graph_size([0.0, 80.0])
+            .build();
+    });
 }
 
 impl Default for FrameCounter {
@@ -60,11 +180,96 @@ impl Default for FrameCounter {
             fps_frame_count: 0,
             delta_time: time::Duration::ZERO,
             last_time: time::Instant::now(),
+            history: VecDeque::with_capacity(FRAME_HISTORY_LEN),
+            sorted: Vec::with_capacity(FRAME_HISTORY_LEN),
+        }
+    }
+}
+
+/// Number of frames kept by `FrameWatchdog` to describe a spike.
+const WATCHDOG_HISTORY_LEN: usize = 16;
+
+/// Detects frames that took longer than a threshold.
+///
+/// Spikes are only reported once the history is full, so that the slow
+/// frames following startup are not reported.
+#[derive(Debug)]
+pub struct FrameWatchdog {
+    threshold: time::Duration,
+    // delta times of the most recent frames, including the last one.
+    history: VecDeque<time::Duration>,
+}
+
+impl FrameWatchdog {
+    pub fn new(threshold: time::Duration) -> Self {
+        Self {
+            threshold,
+            history: VecDeque::with_capacity(WATCHDOG_HISTORY_LEN),
+        }
+    }
+
+    /// Records the duration of the last frame and returns a spike if it took
+    /// longer than the threshold.
+    pub fn on_update(&mut self, frame: u64, delta_time: time::Duration) -> Option<FrameSpike> {
+        let warmed_up = self.history.len() == WATCHDOG_HISTORY_LEN;
+        if warmed_up {
+            self.history.pop_front();
+        }
+        self.history.push_back(delta_time);
+
+        if !warmed_up || delta_time <= self.threshold {
+            return None;
+        }
+        Some(FrameSpike {
+            frame,
+            delta_time,
+            threshold: self.threshold,
+            recent: self.history.iter().copied().collect(),
+        })
+    }
+}
+
+/// Snapshot of a frame that took longer than the watchdog threshold.
+#[derive(Clone, Debug, PartialEq)]
+pub struct FrameSpike {
+    pub frame: u64,
+    pub delta_time: time::Duration,
+    pub threshold: time::Duration,
+    /// Delta times of the frames leading to the spike, oldest first.
+    pub recent: Vec<time::Duration>,
+}
+
+impl FrameSpike {
+    /// Average delta time of the frames before the spike.
+    pub fn average(&self) -> time::Duration {
+        let before = &self.recent[..self.recent.len() - 1];
+        before.iter().sum::<time::Duration>() / before.len().max(1) as u32
+    }
+}
+
+impl fmt::Display for FrameSpike {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "frame {} took {:.2?} (threshold {:.2?}, average {:.2?}), recent frames: [",
+            self.frame,
+            self.delta_time,
+            self.threshold,
+            self.average()
+        )?;
+        for (i, delta_time) in self.recent.iter().enumerate() {
+            if i > 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "{delta_time:.2?}")?;
         }
+        write!(f, "]")
     }
 }
 
-pub struct FPSPrinter<T: MovingAverage, F: Fn(f64)> {
+/// Prints the moving average of the fps and the statistics of the recent
+/// frame times, throttled.
+pub struct FPSPrinter<T: MovingAverage, F: Fn(f64, Option<FrameTimeStats>)> {
     throttle_ms: u128,
     delta_time_accumulator: time::Duration,
 
@@ -75,7 +280,7 @@ pub struct FPSPrinter<T: MovingAverage, F: Fn(f64)> {
 impl<T, F> FPSPrinter<T, F>
 where
     T: MovingAverage,
-    F: Fn(f64),
+    F: Fn(f64, Option<FrameTimeStats>),
 {
     pub fn new(moving_average: T, print_fn: F) -> Self {
         Self {
@@ -91,22 +296,61 @@ where
         self
     }
 
-    pub fn on_update(&mut self, delta_time: time::Duration, fps: f64) {
-        self.delta_time_accumulator += delta_time;
+    pub fn on_update(&mut self, frame_counter: &mut FrameCounter) {
+        self.moving_average.on_frame(frame_counter.delta_time());
+        self.delta_time_accumulator += frame_counter.delta_time();
         // throttle to every second using accumulator
         if self.delta_time_accumulator.as_millis() >= self.throttle_ms {
             // make sure we reset accumulator
             self.delta_time_accumulator = time::Duration::ZERO;
             // compute fps moving average
-            let fps_ma = self.moving_average.compute(fps);
+            let fps_ma = self.moving_average.compute(frame_counter.fps());
             // print fps
-            (self.print_fn)(fps_ma);
+            (self.print_fn)(fps_ma, frame_counter.frame_time_stats());
         }
     }
 }
 
+/// Smooths the fps printed by `FPSPrinter`.
 pub trait MovingAverage {
+    /// Returns the average fps, given the latest fps sample.
     fn compute(&mut self, fps: f64) -> f64;
+
+    /// Called on every frame, while `compute()` is only called when printing.
+    fn on_frame(&mut self, _delta_time: time::Duration) {}
+}
+
+impl<T: MovingAverage + ?Sized> MovingAverage for Box<T> {
+    fn compute(&mut self, fps: f64) -> f64 {
+        (**self).compute(fps)
+    }
+
+    fn on_frame(&mut self, delta_time: time::Duration) {
+        (**self).on_frame(delta_time)
+    }
+}
+
+/// Strategy averaging the fps printed by the engine.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+pub enum FpsAverage {
+    /// Exponential moving average of the fps samples.
+    #[default]
+    Exponential,
+    /// Average of the last fps samples.
+    Simple,
+    /// Frames counted over a fixed window of time.
+    Windowed,
+}
+
+impl FpsAverage {
+    pub fn moving_average(self) -> Box<dyn MovingAverage> {
+        match self {
+            Self::Exponential => Box::new(ExponentialMovingAverage::new().with_alpha(0.95)),
+            Self::Simple => Box::new(SimpleMovingAverage::new()),
+            Self::Windowed => Box::new(WindowedFps::new()),
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -144,3 +388,171 @@ impl Default for ExponentialMovingAverage {
         }
     }
 }
+
+/// Average of the last samples, all weighted equally.
+#[derive(Debug)]
+pub struct SimpleMovingAverage {
+    window: usize,
+    samples: VecDeque<f64>,
+    sum: f64,
+}
+
+impl SimpleMovingAverage {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the number of samples averaged, at least one.
+    pub fn with_window(mut self, window: usize) -> Self {
+        self.window = window.max(1);
+        self
+    }
+}
+
+impl MovingAverage for SimpleMovingAverage {
+    fn compute(&mut self, value: f64) -> f64 {
+        while self.samples.len() >= self.window {
+            self.sum -= self.samples.pop_front().unwrap_or_default();
+        }
+        self.samples.push_back(value);
+        self.sum += value;
+        self.sum / self.samples.len() as f64
+    }
+}
+
+impl Default for SimpleMovingAverage {
+    fn default() -> Self {
+        Self {
+            window: 10,
+            samples: VecDeque::new(),
+            sum: 0.0,
+        }
+    }
+}
+
+/// Counts the frames over fixed windows of time, the fps being the number of
+/// frames of the last complete window divided by its duration. The samples
+/// are only used until the first window completes.
+#[derive(Debug)]
+pub struct WindowedFps {
+    window: time::Duration,
+    frames: u32,
+    elapsed: time::Duration,
+    fps: Option<f64>,
+}
+
+impl WindowedFps {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_window(mut self, window: time::Duration) -> Self {
+        self.window = window;
+        self
+    }
+}
+
+impl MovingAverage for WindowedFps {
+    fn compute(&mut self, value: f64) -> f64 {
+        self.fps.unwrap_or(value)
+    }
+
+    fn on_frame(&mut self, delta_time: time::Duration) {
+        self.frames += 1;
+        self.elapsed += delta_time;
+        if self.elapsed >= self.window && !self.elapsed.is_zero() {
+            self.fps = Some(self.frames as f64 / self.elapsed.as_secs_f64());
+            self.frames = 0;
+            self.elapsed = time::Duration::ZERO;
+        }
+    }
+}
+
+impl Default for WindowedFps {
+    fn default() -> Self {
+        Self {
+            window: time::Duration::from_secs(1),
+            frames: 0,
+            elapsed: time::Duration::ZERO,
+            fps: None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn frame_time_stats_of_recent_frames() {
+        let mut frame_counter = FrameCounter::new();
+        assert_eq!(frame_counter.frame_time_stats(), None);
+
+        // 10 ms frames followed by 2 slow ones, exceeding the history
+        let mut now = frame_counter.last_time;
+        for frame in 0..FRAME_HISTORY_LEN + 10 {
+            let slow = frame >= FRAME_HISTORY_LEN + 8;
+            now += time::Duration::from_millis(if slow { 40 } else { 10 });
+            frame_counter.on_update(now);
+        }
+        assert_eq!(frame_counter.frame_times().len(), FRAME_HISTORY_LEN);
+
+        let stats = frame_counter.frame_time_stats().expect("stats");
+        assert_eq!(stats.frames, FRAME_HISTORY_LEN);
+        assert_eq!(stats.average, time::Duration::from_millis(2_460) / 240);
+        assert_eq!(stats.one_percent_low, time::Duration::from_millis(40));
+        assert_eq!(stats.p50, time::Duration::from_millis(10));
+        assert_eq!(stats.p99, time::Duration::from_millis(10));
+        assert_eq!(stats.max, time::Duration::from_millis(40));
+        assert_eq!(stats.one_percent_low_fps(), 25.0);
+    }
+
+    #[test]
+    fn simple_moving_average_of_the_last_samples() {
+        let mut average = SimpleMovingAverage::new().with_window(3);
+        assert_eq!(average.compute(30.0), 30.0);
+        assert_eq!(average.compute(60.0), 45.0);
+        assert_eq!(average.compute(60.0), 50.0);
+        assert_eq!(average.compute(90.0), 70.0);
+    }
+
+    #[test]
+    fn windowed_fps_counts_frames_over_the_window() {
+        let mut windowed = WindowedFps::new().with_window(time::Duration::from_millis(100));
+        for _ in 0..9 {
+            windowed.on_frame(time::Duration::from_millis(10));
+        }
+        // the window is not complete yet
+        assert_eq!(windowed.compute(50.0), 50.0);
+
+        windowed.on_frame(time::Duration::from_millis(10));
+        assert_eq!(windowed.compute(50.0), 100.0);
+        for _ in 0..4 {
+            windowed.on_frame(time::Duration::from_millis(25));
+        }
+        assert_eq!(windowed.compute(50.0), 40.0);
+    }
+
+    #[test]
+    fn watchdog_reports_spikes_after_warmup() {
+        let threshold = time::Duration::from_millis(50);
+        let frame_time = time::Duration::from_millis(16);
+        let spike_time = time::Duration::from_millis(80);
+        let mut watchdog = FrameWatchdog::new(threshold);
+
+        // slow frames are ignored until the history is full
+        assert_eq!(watchdog.on_update(0, spike_time), None);
+        for frame in 1..WATCHDOG_HISTORY_LEN as u64 {
+            assert_eq!(watchdog.on_update(frame, frame_time), None);
+        }
+        assert_eq!(watchdog.on_update(16, threshold), None);
+
+        let spike = watchdog
+            .on_update(17, spike_time)
+            .expect("spike is reported");
+        assert_eq!(spike.frame, 17);
+        assert_eq!(spike.recent.len(), WATCHDOG_HISTORY_LEN);
+        assert_eq!(spike.recent.last(), Some(&spike_time));
+        assert!(spike.average() < threshold);
+    }
+}
@@ -0,0 +1,120 @@
+//! Caps the frame rate, so that the GPU is not kept busy rendering frames
+//! faster than they are needed, e.g. on battery powered machines.
+//!
+//! Frames are paced by sleeping until shortly before the start of the next
+//! frame, then spinning until it is due, since sleeps can overshoot by more
+//! than a millisecond.
+
+use std::{thread, time};
+
+/// Time before the start of a frame spent spinning rather than sleeping.
+const SPIN_MARGIN: time::Duration = time::Duration::from_millis(2);
+
+/// Limit of the frame rate, see `EngineBuilder::with_frame_limit()`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FrameLimit {
+    /// Maximum number of frames per second.
+    Fps(f64),
+    /// Minimum duration of a frame.
+    FrameTime(time::Duration),
+}
+
+impl FrameLimit {
+    /// Returns the minimum duration of a frame, None if frames are not
+    /// limited, e.g. for a rate that is not positive.
+    pub fn frame_time(self) -> Option<time::Duration> {
+        match self {
+            Self::Fps(fps) if fps.is_finite() && fps > 0.0 => {
+                Some(time::Duration::from_secs_f64(1.0 / fps))
+            }
+            Self::Fps(_) => None,
+            Self::FrameTime(frame_time) => (!frame_time.is_zero()).then_some(frame_time),
+        }
+    }
+}
+
+/// Waits between frames to keep them at least a frame time apart.
+#[derive(Debug)]
+pub(crate) struct FrameLimiter {
+    frame_time: time::Duration,
+    /// Start of the next frame, None before the first frame.
+    next_frame: Option<time::Instant>,
+}
+
+impl FrameLimiter {
+    /// Returns a limiter, None if the limit does not limit frames.
+    pub fn new(limit: FrameLimit) -> Option<Self> {
+        limit.frame_time().map(|frame_time| Self {
+            frame_time,
+            next_frame: None,
+        })
+    }
+
+    /// Blocks until the next frame is due.
+    pub fn wait(&mut self) {
+        let now = time::Instant::now();
+        let next_frame = schedule(self.next_frame, now, self.frame_time);
+        self.next_frame = Some(next_frame);
+
+        let Some(remaining) = next_frame.checked_duration_since(now) else {
+            return;
+        };
+        if remaining > SPIN_MARGIN {
+            thread::sleep(remaining - SPIN_MARGIN);
+        }
+        while time::Instant::now() < next_frame {
+            std::hint::spin_loop();
+        }
+    }
+}
+
+/// Returns the start of the frame following the one started at `previous`.
+/// Frames late by more than a frame time are started right away rather than
+/// caught up on.
+fn schedule(
+    previous: Option<time::Instant>,
+    now: time::Instant,
+    frame_time: time::Duration,
+) -> time::Instant {
+    match previous {
+        Some(previous) if previous + frame_time * 2 > now => previous + frame_time,
+        _ => now,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn limits_are_converted_to_frame_times() {
+        assert_eq!(
+            FrameLimit::Fps(50.0).frame_time(),
+            Some(time::Duration::from_millis(20))
+        );
+        assert_eq!(FrameLimit::Fps(0.0).frame_time(), None);
+        assert_eq!(FrameLimit::Fps(f64::INFINITY).frame_time(), None);
+        assert_eq!(
+            FrameLimit::FrameTime(time::Duration::ZERO).frame_time(),
+            None
+        );
+        assert!(FrameLimiter::new(FrameLimit::Fps(-1.0)).is_none());
+    }
+
+    #[test]
+    fn frames_are_scheduled_a_frame_time_apart() {
+        let frame_time = time::Duration::from_millis(10);
+        let start = time::Instant::now();
+        assert_eq!(schedule(None, start, frame_time), start);
+
+        // frames ending early or slightly late keep the cadence
+        let now = start + time::Duration::from_millis(4);
+        assert_eq!(schedule(Some(start), now, frame_time), start + frame_time);
+        let now = start + time::Duration::from_millis(15);
+        assert_eq!(schedule(Some(start), now, frame_time), start + frame_time);
+
+        // frames late by more than a frame time are not caught up on
+        let now = start + time::Duration::from_millis(25);
+        assert_eq!(schedule(Some(start), now, frame_time), now);
+    }
+}
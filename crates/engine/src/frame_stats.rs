@@ -0,0 +1,249 @@
+//! Recording of per-frame statistics to a file for offline analysis.
+//!
+//! Recording is started and stopped with
+//! `ApplicationContext::start_frame_stats()` and `stop_frame_stats()` or,
+//! along with the editor tools, with the frame stats key, see
+//! `EngineBuilder::with_frame_stats_key()`.
+//!
+//! Files with the `.json` extension get an array with an object per frame,
+//! any other a CSV table with a row per frame. Times are in milliseconds and
+//! memory in bytes; the GPU time is left empty until the GPU profiler has
+//! measured a frame.
+
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use log::{error, info};
+#[cfg(feature = "editor-tools")]
+use winit::event::VirtualKeyCode;
+
+/// Key starting and stopping the recording of frame statistics by default.
+#[cfg(feature = "editor-tools")]
+pub const DEFAULT_FRAME_STATS_KEY: VirtualKeyCode = VirtualKeyCode::F4;
+
+const CSV_HEADER: &str =
+    "frame,frame_time_ms,cpu_time_ms,gpu_time_ms,draw_calls,quads,memory_used,memory_reserved";
+
+/// Statistics of a frame.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct FrameStats {
+    pub frame: u64,
+    /// Time since the previous frame.
+    pub frame_time: Duration,
+    /// Time spent on the CPU updating and rendering the frame, excluding
+    /// the wait of the frame limiter.
+    pub cpu_time: Duration,
+    /// Time the GPU took to render the last measured frame.
+    pub gpu_time: Option<Duration>,
+    pub draw_calls: u32,
+    pub quads: u32,
+    /// Bytes of device memory used by allocations.
+    pub memory_used: u64,
+    /// Bytes of device memory reserved in blocks.
+    pub memory_reserved: u64,
+}
+
+/// Format of a frame statistics file.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StatsFormat {
+    Csv,
+    Json,
+}
+
+impl StatsFormat {
+    /// Returns JSON for paths with the `.json` extension, CSV otherwise.
+    pub fn from_path(path: &Path) -> Self {
+        match path.extension() {
+            Some(extension) if extension.eq_ignore_ascii_case("json") => Self::Json,
+            _ => Self::Csv,
+        }
+    }
+}
+
+/// Writes the statistics of each recorded frame. The output is complete once
+/// `finish()` has been called.
+pub struct FrameStatsRecorder<W: Write = BufWriter<File>> {
+    writer: W,
+    format: StatsFormat,
+    frames: u64,
+}
+
+impl FrameStatsRecorder {
+    /// Creates the file at path, its format depending on the extension.
+    pub fn create(path: &Path) -> io::Result<Self> {
+        let file = File::create(path)?;
+        Self::new(BufWriter::new(file), StatsFormat::from_path(path))
+    }
+}
+
+impl<W: Write> FrameStatsRecorder<W> {
+    pub fn new(mut writer: W, format: StatsFormat) -> io::Result<Self> {
+        match format {
+            StatsFormat::Csv => writeln!(writer, "{CSV_HEADER}")?,
+            StatsFormat::Json => write!(writer, "[")?,
+        }
+        Ok(Self {
+            writer,
+            format,
+            frames: 0,
+        })
+    }
+
+    /// Returns the number of frames recorded.
+    pub fn frames(&self) -> u64 {
+        self.frames
+    }
+
+    pub fn record(&mut self, stats: &FrameStats) -> io::Result<()> {
+        let gpu_time = stats.gpu_time.map(ms);
+        match self.format {
+            StatsFormat::Csv => writeln!(
+                self.writer,
+                "{},{:.3},{:.3},{},{},{},{},{}",
+                stats.frame,
+                ms(stats.frame_time),
+                ms(stats.cpu_time),
+                gpu_time.map_or_else(String::new, |t| format!("{t:.3}")),
+                stats.draw_calls,
+                stats.quads,
+                stats.memory_used,
+                stats.memory_reserved,
+            )?,
+            StatsFormat::Json => write!(
+                self.writer,
+                "{}\n  {{\"frame\":{},\"frame_time_ms\":{:.3},\"cpu_time_ms\":{:.3},\
+                 \"gpu_time_ms\":{},\"draw_calls\":{},\"quads\":{},\
+                 \"memory_used\":{},\"memory_reserved\":{}}}",
+                if self.frames == 0 { "" } else { "," },
+                stats.frame,
+                ms(stats.frame_time),
+                ms(stats.cpu_time),
+                gpu_time.map_or_else(|| "null".to_string(), |t| format!("{t:.3}")),
+                stats.draw_calls,
+                stats.quads,
+                stats.memory_used,
+                stats.memory_reserved,
+            )?,
+        }
+        self.frames += 1;
+        Ok(())
+    }
+
+    /// Completes the output and flushes it, returning the writer.
+    pub fn finish(mut self) -> io::Result<W> {
+        if self.format == StatsFormat::Json {
+            writeln!(self.writer, "\n]")?;
+        }
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
+
+/// Stops the current recording, if any, then starts recording to path if
+/// given. Failures are logged.
+pub(crate) fn switch_recording(recorder: &mut Option<FrameStatsRecorder>, path: Option<PathBuf>) {
+    if let Some(recorder) = recorder.take() {
+        let frames = recorder.frames();
+        match recorder.finish() {
+            Ok(_) => info!("frame stats recording stopped after {frames} frames"),
+            Err(e) => error!("finish frame stats recording: {e}"),
+        }
+    }
+    if let Some(path) = path {
+        match FrameStatsRecorder::create(&path) {
+            Ok(new) => {
+                info!("recording frame stats to {}", path.display());
+                *recorder = Some(new);
+            }
+            Err(e) => error!("record frame stats to {}: {e}", path.display()),
+        }
+    }
+}
+
+/// Returns a path in the working directory named after the current time.
+#[cfg(feature = "editor-tools")]
+pub(crate) fn default_frame_stats_path() -> PathBuf {
+    let millis = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis();
+    PathBuf::from(format!("frame_stats-{millis}.csv"))
+}
+
+fn ms(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1e3
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stats(frame: u64, gpu_time: Option<Duration>) -> FrameStats {
+        FrameStats {
+            frame,
+            frame_time: Duration::from_micros(16_667),
+            cpu_time: Duration::from_micros(4_250),
+            gpu_time,
+            draw_calls: 3,
+            quads: 1200,
+            memory_used: 1024,
+            memory_reserved: 4096,
+        }
+    }
+
+    fn record(format: StatsFormat) -> String {
+        let mut recorder = FrameStatsRecorder::new(Vec::new(), format).unwrap();
+        recorder.record(&stats(1, None)).unwrap();
+        recorder
+            .record(&stats(2, Some(Duration::from_micros(2_500))))
+            .unwrap();
+        assert_eq!(recorder.frames(), 2);
+        String::from_utf8(recorder.finish().unwrap()).unwrap()
+    }
+
+    #[test]
+    fn format_depends_on_the_extension() {
+        assert_eq!(
+            StatsFormat::from_path(Path::new("stats.json")),
+            StatsFormat::Json
+        );
+        assert_eq!(
+            StatsFormat::from_path(Path::new("stats.JSON")),
+            StatsFormat::Json
+        );
+        assert_eq!(
+            StatsFormat::from_path(Path::new("stats.csv")),
+            StatsFormat::Csv
+        );
+        assert_eq!(StatsFormat::from_path(Path::new("stats")), StatsFormat::Csv);
+    }
+
+    #[test]
+    fn frames_are_recorded_as_csv_rows() {
+        assert_eq!(
+            record(StatsFormat::Csv),
+            format!(
+                "{CSV_HEADER}\n\
+                 1,16.667,4.250,,3,1200,1024,4096\n\
+                 2,16.667,4.250,2.500,3,1200,1024,4096\n"
+            )
+        );
+    }
+
+    #[test]
+    fn frames_are_recorded_as_a_json_array() {
+        assert_eq!(
+            record(StatsFormat::Json),
+            "[\n  \
+             {\"frame\":1,\"frame_time_ms\":16.667,\"cpu_time_ms\":4.250,\"gpu_time_ms\":null,\
+             \"draw_calls\":3,\"quads\":1200,\"memory_used\":1024,\"memory_reserved\":4096},\n  \
+             {\"frame\":2,\"frame_time_ms\":16.667,\"cpu_time_ms\":4.250,\"gpu_time_ms\":2.500,\
+             \"draw_calls\":3,\"quads\":1200,\"memory_used\":1024,\"memory_reserved\":4096}\n]\n"
+        );
+
+        let empty = FrameStatsRecorder::new(Vec::new(), StatsFormat::Json).unwrap();
+        assert_eq!(empty.finish().unwrap(), b"[\n]\n");
+    }
+}
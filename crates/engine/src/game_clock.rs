@@ -0,0 +1,98 @@
+//! Scales the time seen by the simulation, for slow motion and pause menus.
+//!
+//! Only the delta given to updates and animations is scaled: the UI, the
+//! camera and the frame statistics keep measuring real time.
+
+use std::time;
+
+use log::warn;
+
+/// Largest time scale, larger ones being clamped.
+///
+/// NOTE: `Duration::mul_f64()` panics on overflow, which large scales could
+///       cause even for frames of usual lengths
+pub(crate) const MAX_TIME_SCALE: f32 = 100.0;
+
+#[derive(Debug)]
+pub(crate) struct GameClock {
+    scale: f32,
+    paused: bool,
+}
+
+impl Default for GameClock {
+    fn default() -> Self {
+        Self {
+            scale: 1.0,
+            paused: false,
+        }
+    }
+}
+
+impl GameClock {
+    pub fn scale(&self) -> f32 {
+        self.scale
+    }
+
+    /// Sets the factor applied to the delta time. A scale that is negative
+    /// or not finite is ignored, and one above `MAX_TIME_SCALE` is clamped.
+    pub fn set_scale(&mut self, scale: f32) {
+        if !scale.is_finite() || scale < 0.0 {
+            warn!("ignoring invalid time scale {scale}");
+            return;
+        }
+        if scale > MAX_TIME_SCALE {
+            warn!("clamping time scale {scale} to {MAX_TIME_SCALE}");
+        }
+        self.scale = scale.min(MAX_TIME_SCALE);
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    pub fn set_paused(&mut self, paused: bool) {
+        self.paused = paused;
+    }
+
+    /// Returns the delta time seen by the simulation for a frame lasting
+    /// `delta`.
+    pub fn apply(&self, delta: time::Duration) -> time::Duration {
+        if self.paused {
+            return time::Duration::ZERO;
+        }
+        if self.scale == 1.0 {
+            return delta;
+        }
+        delta.mul_f64(self.scale.into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn delta_is_scaled_until_paused() {
+        let frame = time::Duration::from_millis(16);
+        let mut clock = GameClock::default();
+        assert_eq!(clock.apply(frame), frame);
+
+        clock.set_scale(0.5);
+        assert_eq!(clock.apply(frame), time::Duration::from_millis(8));
+
+        clock.set_scale(-1.0);
+        clock.set_scale(f32::NAN);
+        assert_eq!(clock.scale(), 0.5);
+
+        clock.set_scale(f32::MAX);
+        assert_eq!(clock.scale(), MAX_TIME_SCALE);
+        let hang = time::Duration::from_secs(3600);
+        assert_eq!(clock.apply(hang), hang * 100);
+        clock.set_scale(0.5);
+
+        clock.set_paused(true);
+        assert_eq!(clock.apply(frame), time::Duration::ZERO);
+        clock.set_paused(false);
+        assert_eq!(clock.apply(frame), time::Duration::from_millis(8));
+    }
+}
@@ -0,0 +1,479 @@
+//! Engine running without a window, e.g. to test the logic of an application
+//! in CI.
+//!
+//! A headless engine is created using `EngineBuilder::headless()` and runs
+//! frames on demand with `HeadlessEngine::run_frames()`. Frames last a fixed
+//! duration rather than the time elapsed, so that runs are reproducible. The
+//! application is initialized when the engine is created, and shut down when
+//! it is dropped.
+//!
+//! Frames are only rendered when enabled with
+//! `EngineBuilder::with_headless_rendering()`, offscreen, and can then be read
+//! back. The 2D and 3D objects of the world are drawn as seen by the default
+//! camera of the engine; custom passes, render systems and the UI are not.
+//! Requests of the application that need a window, e.g. to change the display
+//! mode, are ignored.
+
+use core::ecs::World;
+use core::handle::HandleMap;
+use core::jobs::JobPool;
+use core::object::MeshView;
+use core::prefab::PrefabLibrary;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::{mem, thread, time};
+
+use ash::vk;
+use camera::{CameraController, CameraOrthographic};
+use cgmath::{Matrix4, Vector4};
+use input::InputSystem;
+use log::{error, info};
+use vulkan_renderer::renderer::{RendererSettings, VulkanRenderer};
+use vulkan_renderer_2d::Renderer2DSystem;
+use vulkan_renderer_3d::Renderer3DSystem;
+use winit::window::WindowId;
+
+use crate::alloc_audit::{self, Subsystem};
+use crate::display::Display;
+use crate::engine::{load_sprite_textures, Application, FrameRequests, Resources};
+use crate::error::EngineError;
+use crate::events::EventBus;
+use crate::game_clock::GameClock;
+use crate::movement::FIXED_TIMESTEP;
+use crate::render_callback::RenderCallbacks;
+use crate::simulation::Simulation;
+use crate::sprites::SpriteTextures;
+use crate::state::StateStack;
+use crate::stress::StressScene;
+use crate::Result;
+
+/// Options of a headless engine, taken from the `EngineBuilder`.
+pub(crate) struct HeadlessConfig {
+    pub extent: Option<vk::Extent2D>,
+    pub renderer_settings: RendererSettings,
+    pub clear_color: Vector4<f32>,
+    pub depth_prepass: bool,
+    pub simulation: Simulation,
+    pub worker_threads: usize,
+    pub prefabs: PrefabLibrary,
+    pub sprite_textures: Vec<PathBuf>,
+    pub stress_scene: Option<StressScene>,
+}
+
+/// Renderers drawing the frames of a headless engine.
+struct OffscreenRenderer {
+    view_projection: Matrix4<f32>,
+    renderer2d: Renderer2DSystem,
+    renderer3d: Renderer3DSystem,
+    _sprite_textures: Option<SpriteTextures>,
+    // NOTE: dropped last, as it owns the device
+    renderer: VulkanRenderer,
+}
+
+impl OffscreenRenderer {
+    unsafe fn new(
+        extent: vk::Extent2D,
+        config: &HeadlessConfig,
+        jobs: &Arc<JobPool>,
+    ) -> Result<Self> {
+        let mut renderer =
+            VulkanRenderer::new_headless("Engine", extent, config.renderer_settings)?;
+        renderer.set_clear_color(config.clear_color.into());
+        let mut renderer2d = Renderer2DSystem::new(
+            renderer.device(),
+            renderer.renderpass(),
+            renderer.max_frames_in_flight(),
+        )
+        .map_err(|e| EngineError::system("renderer 2D", e))?;
+        renderer2d.set_depth_prepass(config.depth_prepass);
+        renderer2d.set_job_pool(Some(Arc::clone(jobs)));
+        let sprite_textures = load_sprite_textures(&renderer, &config.sprite_textures);
+        if let Some(sprite_textures) = &sprite_textures {
+            renderer2d
+                .set_textures(
+                    renderer.device(),
+                    renderer.renderpass(),
+                    sprite_textures.array(),
+                )
+                .map_err(|e| EngineError::system("renderer 2D", e))?;
+        }
+        let renderer3d = Renderer3DSystem::new(
+            renderer.device(),
+            renderer.renderpass(),
+            renderer.max_frames_in_flight(),
+        )
+        .map_err(|e| EngineError::system("renderer 3D", e))?;
+        let camera = CameraOrthographic::new(extent.width, extent.height);
+        Ok(Self {
+            view_projection: CameraController::new(camera).view_projection_matrix(),
+            renderer2d,
+            renderer3d,
+            _sprite_textures: sprite_textures,
+            renderer,
+        })
+    }
+}
+
+/// Engine updating an application without a window, see the module
+/// documentation.
+pub struct HeadlessEngine {
+    application: Box<dyn Application>,
+    states: StateStack,
+    simulation: Simulation,
+    resources: Resources,
+    frame_time: time::Duration,
+    frame_count: u64,
+    exit_code: Option<i32>,
+    renderer: Option<OffscreenRenderer>,
+}
+
+impl HeadlessEngine {
+    /// Creates the engine and initializes the application.
+    pub(crate) fn new(
+        mut application: Box<dyn Application>,
+        mut config: HeadlessConfig,
+    ) -> Result<Self> {
+        let jobs = Arc::new(JobPool::new(config.worker_threads));
+        let renderer = match config.extent {
+            Some(extent) => Some(unsafe { OffscreenRenderer::new(extent, &config, &jobs)? }),
+            None => None,
+        };
+        let mut resources = Resources {
+            world: World::new(),
+            meshes: HandleMap::new(),
+            prefabs: mem::take(&mut config.prefabs),
+            event_bus: EventBus::new(),
+            requests: FrameRequests::default(),
+            input: InputSystem::new(),
+            game_clock: GameClock::default(),
+            display: Display::new(false),
+            jobs,
+            window_ids: Vec::new(),
+            safe_mode: false,
+            render_callbacks: RenderCallbacks::default(),
+        };
+        info!("running headless");
+
+        // run application initialization
+        {
+            let _scope = alloc_audit::scope(Subsystem::Application);
+            application.on_init(resources.context(FIXED_TIMESTEP));
+        }
+        let mut engine = Self {
+            application,
+            states: StateStack::default(),
+            simulation: config.simulation,
+            resources,
+            frame_time: FIXED_TIMESTEP,
+            frame_count: 0,
+            exit_code: None,
+            renderer,
+        };
+        engine.apply_transitions();
+        if let Some(scene) = config.stress_scene {
+            engine
+                .simulation
+                .spawn_stress_scene(scene, &mut engine.resources.world);
+        }
+        Ok(engine)
+    }
+
+    pub fn world(&self) -> &World {
+        &self.resources.world
+    }
+
+    pub fn world_mut(&mut self) -> &mut World {
+        &mut self.resources.world
+    }
+
+    /// Returns the number of frames run so far.
+    pub fn frame_count(&self) -> u64 {
+        self.frame_count
+    }
+
+    /// Returns the duration of every frame, `movement::FIXED_TIMESTEP` by
+    /// default.
+    pub fn frame_time(&self) -> time::Duration {
+        self.frame_time
+    }
+
+    pub fn set_frame_time(&mut self, frame_time: time::Duration) {
+        self.frame_time = frame_time;
+    }
+
+    /// Returns the exit code requested by the application, if any. No frame
+    /// runs once the application requested to exit.
+    pub fn exit_code(&self) -> Option<i32> {
+        self.exit_code
+    }
+
+    /// Runs `n` frames, or until the application requests to exit. Returns
+    /// the number of frames run.
+    ///
+    /// Returns an error if rendering a frame fails and
+    /// `Application::on_error()` does not keep running.
+    pub fn run_frames(&mut self, n: u64) -> Result<u64> {
+        let mut count = 0;
+        while count < n && self.exit_code.is_none() {
+            self.run_frame()?;
+            count += 1;
+        }
+        Ok(count)
+    }
+
+    /// Returns the RGBA pixels of the last frame rendered, row by row, along
+    /// with its size. Returns an error if rendering is disabled, see
+    /// `EngineBuilder::with_headless_rendering()`, or if no frame was
+    /// rendered yet.
+    pub fn read_frame(&self) -> Result<(vk::Extent2D, Vec<u8>)> {
+        let renderer = &self
+            .renderer
+            .as_ref()
+            .ok_or(EngineError::HeadlessRenderingDisabled)?
+            .renderer;
+        let pixels = unsafe { renderer.read_frame()? };
+        Ok((renderer.window_extent(), pixels))
+    }
+
+    fn run_frame(&mut self) -> Result<()> {
+        let resources = &mut self.resources;
+        let frame_time = self.frame_time;
+
+        // drop the events sent before the last frame
+        resources.event_bus.update();
+
+        // update application state
+        {
+            let _scope = alloc_audit::scope(Subsystem::Application);
+            self.application.on_update(resources.context(frame_time));
+            if let Some(state) = self.states.top_mut() {
+                state.on_update(resources.context(frame_time));
+            }
+        }
+        self.apply_transitions();
+        let resources = &mut self.resources;
+
+        // NOTE: the application may have changed the time scale
+        let delta_time = resources.game_clock.apply(frame_time);
+
+        // move and animate the objects, then report their collisions
+        {
+            let events =
+                self.simulation
+                    .update(&mut resources.world, delta_time, &mut resources.event_bus);
+            let _scope = alloc_audit::scope(Subsystem::Application);
+            for event in events {
+                resources.event_bus.send(*event);
+                self.application
+                    .on_collision(*event, resources.context(frame_time));
+            }
+        }
+
+        // apply the requests that do not need a window
+        let requests = mem::take(&mut resources.requests);
+        resources.requests.states = requests.states;
+        if let Some(code) = requests.exit {
+            info!("exit requested by the application with code {code}");
+            self.exit_code = Some(code);
+        }
+        if let Some(renderer) = self.renderer.as_mut() {
+            if let Some(color) = requests.clear_color {
+                renderer.renderer.set_clear_color(color.into());
+            }
+            if let Some(path) = requests.screenshot {
+                if let Err(e) = renderer.renderer.capture_screenshot(&path) {
+                    error!("capture screenshot to {}: {e}", path.display());
+                }
+            }
+        }
+
+        self.frame_count += 1;
+        if let Err(e) = self.render(delta_time) {
+            let _scope = alloc_audit::scope(Subsystem::Application);
+            let keep_running = !e.is_device_lost()
+                && self
+                    .application
+                    .on_error(&e, self.resources.context(self.frame_time));
+            if !keep_running {
+                return Err(e);
+            }
+            error!("frame failed: {e}");
+        }
+        Ok(())
+    }
+
+    /// Draws the world offscreen, if rendering is enabled.
+    fn render(&mut self, delta_time: time::Duration) -> Result<()> {
+        let Some(OffscreenRenderer {
+            view_projection,
+            renderer2d,
+            renderer3d,
+            renderer,
+            ..
+        }) = self.renderer.as_mut()
+        else {
+            return Ok(());
+        };
+        let resources = &mut self.resources;
+        unsafe {
+            {
+                let _scope = alloc_audit::scope(Subsystem::Passes);
+                resources.render_callbacks.prepare(
+                    renderer.device(),
+                    renderer.renderpass(),
+                    &resources.world,
+                );
+            }
+
+            if !renderer.begin_frame()? {
+                return Ok(());
+            }
+            let extent = renderer.window_extent();
+            let mut result = Ok(());
+            renderer.draw(|_, command_buffer| {
+                {
+                    let _scope = alloc_audit::scope(Subsystem::Renderer3D);
+                    result = renderer3d
+                        .render(
+                            renderer.device(),
+                            command_buffer,
+                            &mut renderer.staging(),
+                            *view_projection,
+                            &resources.meshes,
+                            resources.world.query::<MeshView>(),
+                        )
+                        .map_err(|e| EngineError::system("renderer 3D", e));
+                }
+                let _scope = alloc_audit::scope(Subsystem::Renderer2D);
+                let draw_order = resources.render_callbacks.draw_order(
+                    &resources.world,
+                    renderer.device(),
+                    extent,
+                    delta_time,
+                    WindowId::dummy(),
+                );
+                let rendered = renderer2d
+                    .render(
+                        renderer.device(),
+                        command_buffer,
+                        &mut renderer.staging(),
+                        delta_time,
+                        *view_projection,
+                        draw_order,
+                    )
+                    .map_err(|e| EngineError::system("renderer 2D", e));
+                if result.is_ok() {
+                    result = rendered;
+                }
+            })?;
+            renderer.end_frame()?;
+            result
+        }
+    }
+
+    /// Switches the states requested by the application.
+    fn apply_transitions(&mut self) {
+        let resources = &mut self.resources;
+        let frame_time = self.frame_time;
+        while !resources.requests.states.is_empty() {
+            for transition in mem::take(&mut resources.requests.states) {
+                self.states.apply(transition, |state, hook| {
+                    hook.call(state, resources.context(frame_time));
+                });
+            }
+        }
+    }
+}
+
+impl Drop for HeadlessEngine {
+    /// Lets the application save its state, then waits for the frames
+    /// rendered to complete.
+    fn drop(&mut self) {
+        // NOTE: the application may have panicked in a callback, its state
+        //       can not be trusted
+        if !thread::panicking() {
+            let _scope = alloc_audit::scope(Subsystem::Application);
+            let resources = &mut self.resources;
+            let frame_time = self.frame_time;
+            self.states.clear(|state, hook| {
+                hook.call(state, resources.context(frame_time));
+            });
+            self.application.on_shutdown(resources.context(frame_time));
+        }
+        if let Some(renderer) = self.renderer.as_ref() {
+            if let Err(e) = unsafe { renderer.renderer.device().device_wait_idle() } {
+                error!("wait for device idle: {e:?}");
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::component::Transform;
+    use core::object::GameObject;
+    use std::cell::Cell;
+    use std::rc::Rc;
+
+    use cgmath::{assert_relative_eq, Vector3};
+
+    use super::*;
+    use crate::engine::{ApplicationContext, EngineBuilder};
+
+    struct Mover {
+        updates: Rc<Cell<u32>>,
+        shut_down: Rc<Cell<bool>>,
+    }
+
+    impl Application for Mover {
+        fn on_init(&mut self, mut ctx: ApplicationContext) {
+            ctx.world_mut()
+                .spawn(GameObject::new().with_velocity(Vector3::new(6.0, 0.0, 0.0)));
+        }
+
+        fn on_update(&mut self, mut ctx: ApplicationContext) {
+            self.updates.set(self.updates.get() + 1);
+            if self.updates.get() == 90 {
+                ctx.request_exit_with_code(3);
+            }
+        }
+
+        fn on_shutdown(&mut self, _ctx: ApplicationContext) {
+            self.shut_down.set(true);
+        }
+    }
+
+    #[test]
+    fn frames_run_without_a_window() {
+        let updates = Rc::new(Cell::new(0));
+        let shut_down = Rc::new(Cell::new(false));
+        let app = Mover {
+            updates: Rc::clone(&updates),
+            shut_down: Rc::clone(&shut_down),
+        };
+        let mut engine = EngineBuilder::new(Box::new(app))
+            .with_window_builder(None)
+            .headless()
+            .unwrap();
+        assert!(matches!(
+            engine.read_frame(),
+            Err(EngineError::HeadlessRenderingDisabled)
+        ));
+
+        // a second went by
+        assert_eq!(engine.run_frames(60).unwrap(), 60);
+        assert_eq!(engine.frame_count(), 60);
+        let (transform,) = engine.world().query::<(&Transform,)>().next().unwrap();
+        assert_relative_eq!(transform.position.x, 6.0, epsilon = 1e-3);
+
+        // no frame runs once the application requested to exit
+        assert_eq!(engine.run_frames(60).unwrap(), 30);
+        assert_eq!(engine.exit_code(), Some(3));
+        assert_eq!(engine.run_frames(1).unwrap(), 0);
+        assert_eq!(updates.get(), 90);
+
+        assert!(!shut_down.get());
+        drop(engine);
+        assert!(shut_down.get());
+    }
+}
@@ -0,0 +1,85 @@
+//! Keys triggering an engine action when pressed.
+
+use winit::event::{ElementState, Event, KeyboardInput, VirtualKeyCode, WindowEvent};
+
+#[derive(Debug)]
+pub(crate) struct Hotkey {
+    key: Option<VirtualKeyCode>,
+    /// Set while the key is held, to ignore key repeats.
+    down: bool,
+}
+
+impl Hotkey {
+    /// Creates a hotkey, disabled if the key is None.
+    pub fn new(key: Option<VirtualKeyCode>) -> Self {
+        Self { key, down: false }
+    }
+
+    /// Returns true if the event presses the key.
+    pub fn on_event<T>(&mut self, event: &Event<T>) -> bool {
+        let Event::WindowEvent {
+            event:
+                WindowEvent::KeyboardInput {
+                    input:
+                        KeyboardInput {
+                            state,
+                            virtual_keycode: Some(keycode),
+                            ..
+                        },
+                    ..
+                },
+            ..
+        } = event
+        else {
+            return false;
+        };
+        if Some(*keycode) != self.key {
+            return false;
+        }
+        let pressed = *state == ElementState::Pressed;
+        let was_down = std::mem::replace(&mut self.down, pressed);
+        pressed && !was_down
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use winit::event::DeviceId;
+    use winit::window::WindowId;
+
+    use super::*;
+
+    #[allow(deprecated)]
+    fn key_event(keycode: VirtualKeyCode, state: ElementState) -> Event<'static, ()> {
+        Event::WindowEvent {
+            window_id: unsafe { WindowId::dummy() },
+            event: WindowEvent::KeyboardInput {
+                device_id: unsafe { DeviceId::dummy() },
+                input: KeyboardInput {
+                    scancode: 0,
+                    state,
+                    virtual_keycode: Some(keycode),
+                    modifiers: Default::default(),
+                },
+                is_synthetic: false,
+            },
+        }
+    }
+
+    #[test]
+    fn hotkey_ignores_key_repeats() {
+        let mut hotkey = Hotkey::new(Some(VirtualKeyCode::F12));
+
+        assert!(!hotkey.on_event(&key_event(VirtualKeyCode::F11, ElementState::Pressed)));
+        assert!(hotkey.on_event(&key_event(VirtualKeyCode::F12, ElementState::Pressed)));
+
+        // held down
+        assert!(!hotkey.on_event(&key_event(VirtualKeyCode::F12, ElementState::Pressed)));
+
+        assert!(!hotkey.on_event(&key_event(VirtualKeyCode::F12, ElementState::Released)));
+        assert!(hotkey.on_event(&key_event(VirtualKeyCode::F12, ElementState::Pressed)));
+
+        let mut disabled = Hotkey::new(None);
+        assert!(!disabled.on_event(&key_event(VirtualKeyCode::F12, ElementState::Pressed)));
+    }
+}
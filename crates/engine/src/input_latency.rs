@@ -0,0 +1,129 @@
+//! Measures the end-to-end latency of input events.
+//!
+//! Input events are timestamped when they reach the event loop. They are
+//! handled during the update of the next frame, so their latency is the time
+//! until that frame is presented, as measured when the present request
+//! returns. The time the image then spends in the presentation engine is not
+//! included.
+
+use std::collections::VecDeque;
+use std::time;
+
+use winit::event::{DeviceEvent, Event, WindowEvent};
+
+/// Number of samples the percentiles are computed over.
+const SAMPLE_COUNT: usize = 512;
+
+/// Latency percentiles of the recent input events.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct LatencyPercentiles {
+    pub p50: time::Duration,
+    pub p90: time::Duration,
+    pub p99: time::Duration,
+    pub max: time::Duration,
+}
+
+#[derive(Debug)]
+pub(crate) struct InputLatencyTracker {
+    /// Arrival time of the events not presented yet.
+    pending: Vec<time::Instant>,
+    samples: VecDeque<time::Duration>,
+    /// Scratch buffer used to sort the samples.
+    sorted: Vec<time::Duration>,
+}
+
+impl InputLatencyTracker {
+    pub fn new() -> Self {
+        Self {
+            pending: Vec::new(),
+            samples: VecDeque::with_capacity(SAMPLE_COUNT),
+            sorted: Vec::with_capacity(SAMPLE_COUNT),
+        }
+    }
+
+    /// Records the arrival of the event if it is an input event.
+    pub fn on_event<T>(&mut self, event: &Event<T>, now: time::Instant) {
+        let is_input = matches!(
+            event,
+            Event::WindowEvent {
+                event: WindowEvent::KeyboardInput { .. } | WindowEvent::MouseInput { .. },
+                ..
+            } | Event::DeviceEvent {
+                event: DeviceEvent::MouseWheel { .. },
+                ..
+            }
+        );
+        if is_input {
+            self.pending.push(now);
+        }
+    }
+
+    /// Records the latency of the events handled by the frame presented at
+    /// the given time.
+    pub fn on_present(&mut self, now: time::Instant) {
+        for arrival in self.pending.drain(..) {
+            if self.samples.len() == SAMPLE_COUNT {
+                self.samples.pop_front();
+            }
+            self.samples
+                .push_back(now.saturating_duration_since(arrival));
+        }
+    }
+
+    /// Returns the latency percentiles of the recent events, if any.
+    pub fn percentiles(&mut self) -> Option<LatencyPercentiles> {
+        if self.samples.is_empty() {
+            return None;
+        }
+        self.sorted.clear();
+        self.sorted.extend(self.samples.iter());
+        self.sorted.sort_unstable();
+
+        let sorted = &self.sorted;
+        let percentile = |p: usize| sorted[(sorted.len() - 1) * p / 100];
+        Some(LatencyPercentiles {
+            p50: percentile(50),
+            p90: percentile(90),
+            p99: percentile(99),
+            max: sorted[sorted.len() - 1],
+        })
+    }
+}
+
+/// Draws the latency percentiles in an imgui window.
+#[cfg(feature = "imgui")]
+pub(crate) fn draw_hud(ui: &vulkan_imgui::imgui::Ui, percentiles: Option<LatencyPercentiles>) {
+    ui.window("Input latency").build(|| match percentiles {
+        Some(p) => {
+            ui.text(format!("p50: {:.2} ms", p.p50.as_secs_f64() * 1000.0));
+            ui.text(format!("p90: {:.2} ms", p.p90.as_secs_f64() * 1000.0));
+            ui.text(format!("p99: {:.2} ms", p.p99.as_secs_f64() * 1000.0));
+            ui.text(format!("max: {:.2} ms", p.max.as_secs_f64() * 1000.0));
+        }
+        None => ui.text("no input yet"),
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn latency_percentiles() {
+        let mut tracker = InputLatencyTracker::new();
+        assert_eq!(tracker.percentiles(), None);
+
+        // one event per frame, with a latency of 1 to 100 ms
+        let start = time::Instant::now();
+        for ms in 1..=100 {
+            tracker.pending.push(start);
+            tracker.on_present(start + time::Duration::from_millis(ms));
+        }
+
+        let p = tracker.percentiles().expect("percentiles");
+        assert_eq!(p.p50, time::Duration::from_millis(50));
+        assert_eq!(p.p90, time::Duration::from_millis(90));
+        assert_eq!(p.p99, time::Duration::from_millis(99));
+        assert_eq!(p.max, time::Duration::from_millis(100));
+    }
+}
@@ -0,0 +1,257 @@
+//! Layers drawn at their own resolution and refresh rate.
+//!
+//! A layer other than native is drawn to a render target of its own, outside
+//! of the main render pass, then stretched over the window by the compositor
+//! of the main render pass. Frames that do not redraw the layer composite the
+//! content of its last redraw.
+//!
+//! Scales below 1 trade sharpness for fill rate on weak GPUs. Scales above 1
+//! supersample the layer, the compositor filtering it down to the window.
+//!
+//! A layer keeping its content is drawn over its last redraw instead of being
+//! cleared, e.g. to leave trails behind moving objects.
+
+use ash::vk;
+use log::warn;
+use vulkan_renderer::render_target::RenderTarget;
+use vulkan_renderer::renderer::VulkanRenderer;
+use vulkan_renderer::renderpass::ColorLoad;
+
+use crate::Result;
+
+/// Highest resolution of a layer relative to the window.
+pub const MAX_LAYER_SCALE: f32 = 2.0;
+
+/// Options used to draw a layer.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct LayerSettings {
+    /// Resolution of the layer relative to the window, in (0, 2].
+    pub scale: f32,
+    /// Number of frames between redraws of the layer, at least 1.
+    pub refresh_interval: u32,
+    /// Draws over the last redraw instead of clearing the layer.
+    pub keep_content: bool,
+}
+
+impl LayerSettings {
+    /// Returns true if the layer is drawn at the window resolution every
+    /// frame, directly in the main render pass.
+    pub fn is_native(&self) -> bool {
+        self.scale == 1.0 && self.refresh_interval == 1 && !self.keep_content
+    }
+
+    fn color_load(&self) -> ColorLoad {
+        if self.keep_content {
+            ColorLoad::Load
+        } else {
+            ColorLoad::Clear
+        }
+    }
+
+    /// Returns the settings clamped to their valid range.
+    fn clamped(self) -> Self {
+        let scale = if self.scale.is_finite() {
+            self.scale.clamp(f32::EPSILON, MAX_LAYER_SCALE)
+        } else {
+            1.0
+        };
+        Self {
+            scale,
+            refresh_interval: self.refresh_interval.max(1),
+            keep_content: self.keep_content,
+        }
+    }
+}
+
+impl Default for LayerSettings {
+    fn default() -> Self {
+        Self {
+            scale: 1.0,
+            refresh_interval: 1,
+            keep_content: false,
+        }
+    }
+}
+
+/// Render target of a layer, recreated when the window is resized.
+pub(crate) struct LayerTarget {
+    name: &'static str,
+    settings: LayerSettings,
+    /// Set when the layer is drawn to a target even when native.
+    offscreen: bool,
+    target: Option<RenderTarget>,
+    /// Frames since the last redraw.
+    age: u32,
+}
+
+impl LayerTarget {
+    /// Creates a layer, drawn to a target when it is not native or when
+    /// `offscreen` is set.
+    pub fn new(name: &'static str, settings: LayerSettings, offscreen: bool) -> Self {
+        let mut layer = Self {
+            name,
+            settings: LayerSettings::default(),
+            offscreen,
+            target: None,
+            age: 0,
+        };
+        layer.set_settings(settings);
+        layer
+    }
+
+    /// Changes the settings, applied from the next frame.
+    pub fn set_settings(&mut self, settings: LayerSettings) {
+        let clamped = settings.clamped();
+        if clamped != settings {
+            warn!(
+                "invalid {} settings {settings:?}, using {clamped:?}",
+                self.name
+            );
+        }
+        self.settings = clamped;
+        self.age = 0;
+    }
+
+    /// Draws the layer to a target even when native, e.g. to capture it.
+    pub fn set_offscreen(&mut self, offscreen: bool) {
+        self.offscreen = offscreen;
+    }
+
+    /// Creates the target at the size of the window if needed. Returns true
+    /// if the layer must be redrawn this frame. Native layers are drawn in
+    /// the main render pass and have no target.
+    pub unsafe fn prepare(&mut self, renderer: &VulkanRenderer) -> Result<bool> {
+        if self.settings.is_native() && !self.offscreen {
+            self.target = None;
+            return Ok(false);
+        }
+
+        let extent = scaled_extent(renderer.window_extent(), self.settings.scale);
+        let color_load = self.settings.color_load();
+        if self.target.as_ref().map(|t| (t.extent(), t.color_load())) != Some((extent, color_load))
+        {
+            // NOTE: the previous target is destroyed once the frames using it
+            //       have completed, and the content of a resized layer is lost
+            self.target = Some(renderer.create_render_target(extent, color_load, self.name)?);
+            self.age = 0;
+            return Ok(true);
+        }
+        Ok(self.tick())
+    }
+
+    /// Counts a frame using the current target. Returns true if it is time to
+    /// redraw the layer.
+    fn tick(&mut self) -> bool {
+        self.age += 1;
+        if self.age < self.settings.refresh_interval {
+            return false;
+        }
+        self.age = 0;
+        true
+    }
+
+    /// Returns the target the layer is drawn to, None when it is drawn in the
+    /// main render pass.
+    pub fn target(&self) -> Option<&RenderTarget> {
+        self.target.as_ref()
+    }
+
+    /// Takes the target holding the last redraw of the layer, e.g. to keep
+    /// drawing it while the layer is redrawn to a new target.
+    pub fn take_target(&mut self) -> Option<RenderTarget> {
+        self.target.take()
+    }
+}
+
+/// Returns the scaled extent, at least one pixel wide and high.
+fn scaled_extent(extent: vk::Extent2D, scale: f32) -> vk::Extent2D {
+    let scale = |size: u32| ((size as f32 * scale).round() as u32).max(1);
+    vk::Extent2D {
+        width: scale(extent.width),
+        height: scale(extent.height),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn layer_extent_is_scaled_down_to_one_pixel() {
+        let extent = vk::Extent2D {
+            width: 1280,
+            height: 721,
+        };
+        assert_eq!(scaled_extent(extent, 1.0), extent);
+        assert_eq!(
+            scaled_extent(extent, 2.0),
+            vk::Extent2D {
+                width: 2560,
+                height: 1442,
+            }
+        );
+        assert_eq!(
+            scaled_extent(extent, 0.5),
+            vk::Extent2D {
+                width: 640,
+                height: 361,
+            }
+        );
+        assert_eq!(
+            scaled_extent(extent, f32::EPSILON),
+            vk::Extent2D {
+                width: 1,
+                height: 1,
+            }
+        );
+    }
+
+    #[test]
+    fn layer_is_redrawn_every_refresh_interval() {
+        let settings = LayerSettings {
+            scale: 0.5,
+            refresh_interval: 3,
+            ..Default::default()
+        };
+        let mut layer = LayerTarget::new("test", settings, false);
+        let redraws: Vec<_> = (0..7).map(|_| layer.tick()).collect();
+        assert_eq!(redraws, [false, false, true, false, false, true, false]);
+
+        let mut layer = LayerTarget::new("test", LayerSettings::default(), true);
+        assert!((0..3).all(|_| layer.tick()));
+    }
+
+    #[test]
+    fn invalid_layer_settings_are_clamped() {
+        let settings = LayerSettings {
+            scale: 0.0,
+            refresh_interval: 0,
+            ..Default::default()
+        };
+        assert_eq!(settings.clamped().scale, f32::EPSILON);
+        assert_eq!(settings.clamped().refresh_interval, 1);
+
+        let settings = LayerSettings {
+            scale: 4.0,
+            refresh_interval: 1,
+            ..Default::default()
+        };
+        assert_eq!(settings.clamped().scale, MAX_LAYER_SCALE);
+        assert_eq!(LayerSettings::default().clamped(), LayerSettings::default());
+        assert!(LayerSettings::default().is_native());
+        let settings = LayerSettings {
+            keep_content: true,
+            ..Default::default()
+        };
+        assert!(!settings.is_native());
+        assert_eq!(settings.clamped(), settings);
+
+        let settings = LayerSettings {
+            scale: f32::NAN,
+            refresh_interval: 3,
+            ..Default::default()
+        };
+        assert_eq!(settings.clamped().scale, 1.0);
+        assert!(!settings.clamped().is_native());
+    }
+}
@@ -1,6 +1,46 @@
+pub mod alloc_audit;
+#[cfg(feature = "renderdoc")]
+pub mod capture;
+pub mod collision;
+pub mod config;
+pub mod display;
 pub mod engine;
-mod frame_counter;
+pub mod error;
+pub mod events;
+pub mod frame_counter;
+pub mod frame_limiter;
+pub mod frame_stats;
+mod game_clock;
+pub mod headless;
+mod hotkey;
+mod input_latency;
+pub mod layer;
+#[cfg(feature = "editor-tools")]
+mod memory_hud;
+#[cfg(feature = "metrics")]
+pub mod metrics;
+pub mod movement;
+mod panic_guard;
+pub mod pass;
+#[cfg(feature = "physics")]
+pub mod physics;
+mod profiler;
+#[cfg(feature = "imgui")]
+pub mod remap;
+pub mod render_callback;
+pub mod renderer_system;
+#[cfg(feature = "editor-tools")]
+mod ruler;
+pub mod safe_mode;
+pub mod screen_transition;
+mod simulation;
+mod sprites;
+pub mod state;
+pub mod stress;
+pub mod tween;
 
-use std::{error, result};
-
-type Result<T> = result::Result<T, Box<dyn error::Error>>;
+use error::Result;
+#[cfg(feature = "imgui-freetype")]
+pub use vulkan_imgui::Hinting;
+#[cfg(feature = "imgui")]
+pub use vulkan_imgui::{imgui, Font as UiFont, GlyphRanges};
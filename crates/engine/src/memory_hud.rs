@@ -0,0 +1,70 @@
+//! Debug panel showing the usage and budget of the device memory heaps, to
+//! notice when assets are about to exceed the video memory.
+
+use vulkan_imgui::imgui::Ui;
+use vulkan_renderer::allocator::{HeapStats, MemoryStats};
+
+/// Fraction of the budget above which a heap is highlighted.
+const WARNING_BUDGET_RATIO: f64 = 0.9;
+
+const WARNING_COLOR: [f32; 4] = [1.0, 0.8, 0.0, 1.0];
+const OVER_BUDGET_COLOR: [f32; 4] = [1.0, 0.3, 0.3, 1.0];
+
+/// Draws the memory usage of the allocator and of each heap in an imgui
+/// window.
+pub(crate) fn draw_hud(ui: &Ui, stats: &MemoryStats) {
+    ui.window("GPU memory").build(|| {
+        ui.text(format!(
+            "allocator: {} used / {} reserved in {} blocks",
+            mib(stats.used),
+            mib(stats.reserved),
+            stats.block_count
+        ));
+        for (index, heap) in stats.heaps.iter().enumerate() {
+            let text = heap_text(index, heap);
+            let ratio = heap.budget_ratio();
+            if ratio > 1.0 {
+                ui.text_colored(OVER_BUDGET_COLOR, text);
+            } else if ratio > WARNING_BUDGET_RATIO {
+                ui.text_colored(WARNING_COLOR, text);
+            } else {
+                ui.text(text);
+            }
+        }
+    });
+}
+
+fn heap_text(index: usize, heap: &HeapStats) -> String {
+    let kind = if heap.device_local { "device" } else { "host" };
+    format!(
+        "heap {index} ({kind}): {} / {} budget ({:.0}%), {} by the engine",
+        mib(heap.usage),
+        mib(heap.budget),
+        heap.budget_ratio() * 100.0,
+        mib(heap.reserved)
+    )
+}
+
+fn mib(bytes: u64) -> String {
+    format!("{:.1} MiB", bytes as f64 / (1024.0 * 1024.0))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn heaps_are_described_in_mib() {
+        let heap = HeapStats {
+            size: 1 << 30,
+            device_local: true,
+            reserved: 64 << 20,
+            usage: 768 << 20,
+            budget: 1 << 30,
+        };
+        assert_eq!(
+            heap_text(0, &heap),
+            "heap 0 (device): 768.0 MiB / 1024.0 MiB budget (75%), 64.0 MiB by the engine"
+        );
+    }
+}
@@ -0,0 +1,284 @@
+//! Serves engine statistics over HTTP in the Prometheus text format when the
+//! `metrics` feature is enabled.
+//!
+//! The engine updates a snapshot of its statistics every frame. A background
+//! thread answers scrapes of `/metrics` with the latest snapshot, so the main
+//! loop never waits on the network.
+
+use std::fmt::Write as _;
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{IpAddr, Ipv4Addr, SocketAddr, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::{thread, time};
+
+use log::{debug, error};
+use vulkan_renderer::profiler::GpuScope;
+
+/// Port of the metrics endpoint, which listens on localhost by default.
+pub const DEFAULT_METRICS_PORT: u16 = 9184;
+
+const READ_TIMEOUT: time::Duration = time::Duration::from_secs(1);
+
+/// Returns the default address of the metrics endpoint.
+pub fn default_metrics_address() -> SocketAddr {
+    (Ipv4Addr::LOCALHOST, DEFAULT_METRICS_PORT).into()
+}
+
+/// Engine statistics exported to Prometheus.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Metrics {
+    pub frames: u64,
+    pub frame_time: time::Duration,
+    pub fps: f64,
+    pub objects: usize,
+    pub draw_calls: u32,
+    pub quads: u32,
+    pub memory_blocks: usize,
+    pub memory_reserved: u64,
+    pub memory_used: u64,
+    /// GPU time of the last measured frame, None without timestamp support.
+    pub gpu_frame_time: Option<time::Duration>,
+    pub gpu_scopes: Vec<GpuScope>,
+}
+
+impl Metrics {
+    /// Encodes the metrics in the Prometheus text exposition format.
+    pub fn encode(&self) -> String {
+        let metrics: [(&str, &str, &str, f64); 9] = [
+            (
+                "frames_total",
+                "counter",
+                "Number of frames since startup.",
+                self.frames as f64,
+            ),
+            (
+                "frame_time_seconds",
+                "gauge",
+                "Duration of the last frame.",
+                self.frame_time.as_secs_f64(),
+            ),
+            ("fps", "gauge", "Frames per second.", self.fps),
+            (
+                "objects",
+                "gauge",
+                "Number of game objects.",
+                self.objects as f64,
+            ),
+            (
+                "draw_calls",
+                "gauge",
+                "Draw calls of the 2D renderer during the last frame.",
+                self.draw_calls as f64,
+            ),
+            (
+                "quads",
+                "gauge",
+                "Quads drawn by the 2D renderer during the last frame.",
+                self.quads as f64,
+            ),
+            (
+                "device_memory_blocks",
+                "gauge",
+                "Number of device memory blocks.",
+                self.memory_blocks as f64,
+            ),
+            (
+                "device_memory_reserved_bytes",
+                "gauge",
+                "Device memory allocated by the engine.",
+                self.memory_reserved as f64,
+            ),
+            (
+                "device_memory_used_bytes",
+                "gauge",
+                "Device memory used by buffers and images.",
+                self.memory_used as f64,
+            ),
+        ];
+
+        let mut out = String::new();
+        for (name, kind, help, value) in metrics {
+            let _ = writeln!(out, "# HELP toy_engine_{name} {help}");
+            let _ = writeln!(out, "# TYPE toy_engine_{name} {kind}");
+            let _ = writeln!(out, "toy_engine_{name} {value}");
+        }
+
+        if let Some(gpu_frame_time) = self.gpu_frame_time {
+            let name = "toy_engine_gpu_frame_time_seconds";
+            let _ = writeln!(out, "# HELP {name} GPU time of the last measured frame.");
+            let _ = writeln!(out, "# TYPE {name} gauge");
+            let _ = writeln!(out, "{name} {}", gpu_frame_time.as_secs_f64());
+
+            let name = "toy_engine_gpu_scope_time_seconds";
+            let _ = writeln!(
+                out,
+                "# HELP {name} GPU time of the scopes of the last measured frame."
+            );
+            let _ = writeln!(out, "# TYPE {name} gauge");
+            for scope in &self.gpu_scopes {
+                let _ = writeln!(
+                    out,
+                    "{name}{{scope=\"{}\"}} {}",
+                    scope.name,
+                    scope.duration.as_secs_f64()
+                );
+            }
+        }
+        out
+    }
+}
+
+/// Answers Prometheus scrapes from a background thread, which is stopped and
+/// joined when the exporter is dropped.
+pub(crate) struct MetricsExporter {
+    metrics: Arc<Mutex<Metrics>>,
+    local_addr: SocketAddr,
+    shutdown: Arc<AtomicBool>,
+    thread: Option<thread::JoinHandle<()>>,
+}
+
+impl MetricsExporter {
+    /// Binds the address and starts serving the metrics.
+    pub fn start(addr: SocketAddr) -> io::Result<Self> {
+        let listener = TcpListener::bind(addr)?;
+        let local_addr = listener.local_addr()?;
+        let metrics = Arc::new(Mutex::new(Metrics::default()));
+
+        let shutdown = Arc::new(AtomicBool::new(false));
+
+        let shared = Arc::clone(&metrics);
+        let stop = Arc::clone(&shutdown);
+        let thread = thread::Builder::new()
+            .name("metrics exporter".into())
+            .spawn(move || {
+                for stream in listener.incoming() {
+                    if stop.load(Ordering::Acquire) {
+                        break;
+                    }
+                    let result = stream.and_then(|stream| serve(stream, &shared));
+                    if let Err(e) = result {
+                        debug!("serve metrics: {e}");
+                    }
+                }
+            })?;
+
+        Ok(Self {
+            metrics,
+            local_addr,
+            shutdown,
+            thread: Some(thread),
+        })
+    }
+
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+
+    /// Replaces the metrics served to the next scrapes.
+    pub fn update(&self, metrics: Metrics) {
+        match self.metrics.lock() {
+            Ok(mut guard) => *guard = metrics,
+            Err(e) => error!("update metrics: {e}"),
+        }
+    }
+}
+
+impl Drop for MetricsExporter {
+    fn drop(&mut self) {
+        self.shutdown.store(true, Ordering::Release);
+
+        // NOTE: the thread is blocked accepting scrapes, it is woken up by
+        //       connecting to it
+        let mut addr = self.local_addr;
+        if addr.ip().is_unspecified() {
+            addr.set_ip(IpAddr::V4(Ipv4Addr::LOCALHOST));
+        }
+        if let Err(e) = TcpStream::connect(addr) {
+            error!("stop metrics exporter: {e}");
+            return;
+        }
+        if let Some(thread) = self.thread.take() {
+            if thread.join().is_err() {
+                error!("metrics exporter thread panicked");
+            }
+        }
+    }
+}
+
+fn serve(stream: TcpStream, metrics: &Mutex<Metrics>) -> io::Result<()> {
+    stream.set_read_timeout(Some(READ_TIMEOUT))?;
+    let mut reader = BufReader::new(&stream);
+
+    // read the request line and skip the headers
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+    let mut line = String::new();
+    while reader.read_line(&mut line)? > 2 {
+        line.clear();
+    }
+
+    let path = request_line.split_whitespace().nth(1).unwrap_or_default();
+    let (status, body) = if path == "/metrics" {
+        let metrics = metrics
+            .lock()
+            .map_err(|e| io::Error::new(io::ErrorKind::Other, e.to_string()))?;
+        ("200 OK", metrics.encode())
+    } else {
+        ("404 Not Found", String::new())
+    };
+
+    let mut stream = &stream;
+    write!(
+        stream,
+        "HTTP/1.1 {status}\r\n\
+         Content-Type: text/plain; version=0.0.4\r\n\
+         Content-Length: {}\r\n\
+         Connection: close\r\n\r\n{body}",
+        body.len()
+    )?;
+    stream.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Read;
+
+    use super::*;
+
+    #[test]
+    fn exporter_serves_metrics() {
+        let exporter = MetricsExporter::start(([127, 0, 0, 1], 0).into()).expect("start exporter");
+        exporter.update(Metrics {
+            frames: 42,
+            draw_calls: 3,
+            gpu_frame_time: Some(time::Duration::from_millis(2)),
+            gpu_scopes: vec![GpuScope {
+                name: "renderer 2D",
+                duration: time::Duration::from_millis(1),
+            }],
+            ..Default::default()
+        });
+
+        let mut stream = TcpStream::connect(exporter.local_addr()).expect("connect");
+        stream
+            .write_all(b"GET /metrics HTTP/1.1\r\nHost: localhost\r\n\r\n")
+            .expect("send request");
+        let mut response = String::new();
+        stream.read_to_string(&mut response).expect("read response");
+
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(response.contains("# TYPE toy_engine_frames_total counter\n"));
+        assert!(response.contains("\ntoy_engine_frames_total 42\n"));
+        assert!(response.contains("\ntoy_engine_draw_calls 3\n"));
+        assert!(response.contains("\ntoy_engine_gpu_frame_time_seconds 0.002\n"));
+        assert!(
+            response.contains("\ntoy_engine_gpu_scope_time_seconds{scope=\"renderer 2D\"} 0.001\n")
+        );
+
+        // the listener is closed once the exporter is dropped
+        let addr = exporter.local_addr();
+        drop(exporter);
+        assert!(TcpStream::connect(addr).is_err());
+    }
+}
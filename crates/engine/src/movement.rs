@@ -0,0 +1,136 @@
+//! Built-in movement system, moving the objects that have a `Velocity` or an
+//! `AngularVelocity`.
+//!
+//! Velocities are integrated at a fixed rate, so that motion does not depend
+//! on the frame rate. The steps follow the scaled game time: none are run
+//! while the game is paused. Rigid bodies are left to the physics, which
+//! runs on the same steps.
+
+use core::component::{AngularVelocity, RigidBody, Transform, Velocity};
+use core::ecs::{Without, World};
+use core::mesh::MeshId;
+use std::time;
+
+use cgmath::Vector3;
+
+/// Duration of a step of the movement system and of the physics.
+pub const FIXED_TIMESTEP: time::Duration = time::Duration::from_micros(16_667);
+
+/// Most steps run in a frame. A frame lagging further behind drops the rest
+/// of its time, rather than slowing the next frames down catching up.
+const MAX_STEPS: u32 = 8;
+
+/// Splits the game time into fixed steps.
+#[derive(Debug, Default)]
+pub(crate) struct FixedTimestep {
+    /// Time not yet stepped, shorter than a step.
+    accumulator: time::Duration,
+}
+
+impl FixedTimestep {
+    /// Returns the number of steps fitting in the time elapsed since the
+    /// last frame.
+    pub fn steps(&mut self, delta_time: time::Duration) -> u32 {
+        self.accumulator += delta_time;
+        let mut steps = 0;
+        while self.accumulator >= FIXED_TIMESTEP {
+            if steps == MAX_STEPS {
+                self.accumulator = time::Duration::ZERO;
+                break;
+            }
+            self.accumulator -= FIXED_TIMESTEP;
+            steps += 1;
+        }
+        steps
+    }
+}
+
+/// Moves the objects by their velocity over a step.
+pub(crate) fn step(world: &mut World) {
+    let dt = FIXED_TIMESTEP.as_secs_f32();
+    for (transform, velocity, mesh, ()) in world.query_mut::<(
+        &mut Transform,
+        &Velocity,
+        Option<&MeshId>,
+        Without<RigidBody>,
+    )>() {
+        let mut displacement = velocity.velocity * dt;
+        // NOTE: quads are scaled after being translated, see QuadBatcher
+        if mesh.is_none() {
+            displacement = unscale(displacement, transform.scale);
+        }
+        transform.position += displacement;
+    }
+    for (transform, velocity, ()) in
+        world.query_mut::<(&mut Transform, &AngularVelocity, Without<RigidBody>)>()
+    {
+        transform.rotation += velocity.velocity * dt;
+    }
+}
+
+/// Divides a displacement by the scale of a quad. Quads of a zero scale are
+/// drawn at the origin along that axis whatever their position, which is
+/// left as is rather than made infinite or NaN.
+fn unscale(displacement: Vector3<f32>, scale: Vector3<f32>) -> Vector3<f32> {
+    let unscale = |d: f32, s: f32| if s == 0.0 { 0.0 } else { d / s };
+    Vector3::new(
+        unscale(displacement.x, scale.x),
+        unscale(displacement.y, scale.y),
+        unscale(displacement.z, scale.z),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use core::object::GameObject;
+
+    use cgmath::{assert_relative_eq, Vector3};
+
+    use super::*;
+
+    #[test]
+    fn objects_move_in_fixed_steps() {
+        let mut world = World::new();
+        let quad = world.spawn(
+            GameObject::new()
+                .with_scale(Vector3::new(2.0, 4.0, 1.0))
+                .with_velocity(Vector3::new(6.0, 6.0, 0.0)),
+        );
+        let mesh = world.spawn(
+            GameObject::new()
+                .with_mesh(MeshId::from_bits(0))
+                .with_velocity(Vector3::new(6.0, 6.0, 0.0))
+                .with_angular_velocity(Vector3::new(0.0, 0.0, 3.0)),
+        );
+        let body = world.spawn(GameObject::new().with_velocity(Vector3::new(6.0, 6.0, 0.0)));
+        world.insert(body, RigidBody::Dynamic);
+
+        let mut timestep = FixedTimestep::default();
+        assert_eq!(timestep.steps(FIXED_TIMESTEP / 2), 0);
+        for _ in 0..60 {
+            assert_eq!(timestep.steps(FIXED_TIMESTEP), 1);
+            step(&mut world);
+        }
+
+        // a second went by
+        let position = |id| world.get::<Transform>(id).unwrap().position;
+        assert_relative_eq!(position(quad), Vector3::new(3.0, 1.5, 0.0), epsilon = 1e-3);
+        assert_relative_eq!(position(mesh), Vector3::new(6.0, 6.0, 0.0), epsilon = 1e-3);
+        assert_eq!(position(body), Vector3::new(0.0, 0.0, 0.0));
+        let rotation = world.get::<Transform>(mesh).unwrap().rotation;
+        assert_relative_eq!(rotation.z, 3.0, epsilon = 1e-3);
+
+        // quads without a size along an axis do not move along it
+        world.get_mut::<Transform>(quad).unwrap().scale = Vector3::new(0.0, 4.0, 1.0);
+        step(&mut world);
+        assert_relative_eq!(
+            world.get::<Transform>(quad).unwrap().position,
+            Vector3::new(3.0, 1.525, 0.0),
+            epsilon = 1e-3
+        );
+
+        // long frames are not caught up
+        assert_eq!(timestep.steps(time::Duration::from_secs(1)), MAX_STEPS);
+        assert_eq!(timestep.steps(time::Duration::ZERO), 0);
+    }
+}
@@ -0,0 +1,123 @@
+//! Shutdown of the engine when the application panics.
+//!
+//! Panics raised by the callbacks of the application, or anywhere else while
+//! handling an event, exit the event loop instead of unwinding through it:
+//! the engine shuts down as when the window is closed, letting the states and
+//! the application shut down, waiting for the device to be idle and
+//! destroying the Vulkan objects in order, then resumes the panic.
+//!
+//! Panics raised elsewhere on the thread running the engine, e.g. while it
+//! starts, unwind right away. The panic hook waits for the device to be idle
+//! first, so that the objects dropped while unwinding are not in use anymore.
+
+use std::any::Any;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::thread;
+
+use log::error;
+use winit::event::Event;
+use winit::event_loop::{ControlFlow, EventLoopWindowTarget};
+
+/// Payload of a panic, as returned by `panic::catch_unwind()`.
+pub(crate) type PanicPayload = Box<dyn Any + Send + 'static>;
+
+/// Locks a mutex, ignoring poisoning: the hook may run while it is locked.
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Panic hook waiting for the device to be idle, installed while the engine
+/// runs.
+pub(crate) struct PanicHook {
+    /// Device waited for, None once the hook is uninstalled.
+    device: Arc<Mutex<Option<ash::Device>>>,
+    /// Restores the hook installed before, once the engine stops.
+    restore: Option<Box<dyn FnOnce()>>,
+}
+
+impl PanicHook {
+    /// Installs a hook calling the previous one, then waiting for the device
+    /// to be idle when the current thread panics.
+    pub fn install(device: &ash::Device) -> Self {
+        let device = Arc::new(Mutex::new(Some(device.clone())));
+        let hook_device = Arc::clone(&device);
+        let engine_thread = thread::current().id();
+        let previous = Arc::new(panic::take_hook());
+        let hook_previous = Arc::clone(&previous);
+        panic::set_hook(Box::new(move |info| {
+            hook_previous(info);
+            // NOTE: other threads do not record commands, and waiting from
+            //       them would race with the submissions of the engine
+            if thread::current().id() != engine_thread {
+                return;
+            }
+            if let Some(device) = lock(&hook_device).as_ref() {
+                if let Err(e) = unsafe { device.device_wait_idle() } {
+                    error!("wait for device idle: {e}");
+                }
+            }
+        }));
+        // NOTE: dropping the hook releases its reference to the previous one,
+        //       which is restored as it was unless another hook holds it
+        let restore = Box::new(move || {
+            drop(panic::take_hook());
+            match Arc::try_unwrap(previous) {
+                Ok(previous) => panic::set_hook(previous),
+                Err(previous) => panic::set_hook(Box::new(move |info| previous(info))),
+            }
+        });
+        Self {
+            device,
+            restore: Some(restore),
+        }
+    }
+}
+
+impl Drop for PanicHook {
+    /// Stops waiting for the device, which is about to be destroyed, and
+    /// restores the previous hook. The hook stays installed when dropped
+    /// while panicking, as it can not be changed then.
+    fn drop(&mut self) {
+        lock(&self.device).take();
+        if thread::panicking() {
+            return;
+        }
+        if let Some(restore) = self.restore.take() {
+            restore();
+        }
+    }
+}
+
+/// Wraps the event handler of the event loop, so that a panic exits the loop
+/// instead of unwinding through it. The payload of the panic is kept, to be
+/// resumed once the engine is shut down, and the remaining events are
+/// ignored but `LoopDestroyed`, so that the application still shuts down.
+pub(crate) fn catch_panics<'a, F>(
+    payload: &'a mut Option<PanicPayload>,
+    mut handler: F,
+) -> impl FnMut(Event<'_, ()>, &EventLoopWindowTarget<()>, &mut ControlFlow) + 'a
+where
+    F: FnMut(Event<'_, ()>, &EventLoopWindowTarget<()>, &mut ControlFlow) + 'a,
+{
+    move |event, target, control_flow| {
+        let panicked = payload.is_some();
+        if panicked && !matches!(event, Event::LoopDestroyed) {
+            *control_flow = ControlFlow::Exit;
+            return;
+        }
+        let result = panic::catch_unwind(AssertUnwindSafe(|| {
+            handler(event, target, control_flow);
+        }));
+        if let Err(e) = result {
+            *control_flow = ControlFlow::Exit;
+            // NOTE: the first panic is resumed, later ones are only logged
+            if panicked {
+                error!("panic while shutting down after a panic");
+            } else {
+                error!("panic while handling an event, shutting down");
+                *payload = Some(e);
+            }
+        }
+    }
+}
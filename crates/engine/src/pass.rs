@@ -0,0 +1,281 @@
+//! Custom render passes registered by applications.
+//!
+//! A pass declares the resources it needs once, when the renderer is created,
+//! and then records draw commands every frame at its stage, inside the main
+//! render pass. Passes do not have access to raw Vulkan handles: resources are
+//! referenced by ids and commands go through a `CommandEncoder`.
+
+use std::io::Cursor;
+use std::{mem, time};
+
+use ash::vk;
+use log::error;
+use vulkan_renderer::buffer::Buffer;
+use vulkan_renderer::device::Device;
+use vulkan_renderer::encoder::CommandEncoder;
+use vulkan_renderer::pipeline::Pipeline;
+use vulkan_renderer::renderpass::RenderPass;
+use vulkan_renderer::shader::Shader;
+use vulkan_renderer::staging::StagingRing;
+use winit::window::WindowId;
+
+use crate::error::EngineError;
+use crate::Result;
+
+/// Where a pass is recorded within the frame.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PassStage {
+    /// Before the 2D world is drawn.
+    BeforeWorld,
+    /// After the 2D world, before the UI.
+    AfterWorld,
+    /// After the UI, on top of everything.
+    AfterUi,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BufferUsage {
+    Vertex,
+    /// Holds u32 indices.
+    Index,
+}
+
+/// Id of a buffer declared using `PassBuilder::buffer()`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BufferId(usize);
+
+/// Id of a pipeline declared using `PassBuilder::pipeline()`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PipelineId(usize);
+
+struct PipelineDesc {
+    vertex_spv: Vec<u8>,
+    fragment_spv: Vec<u8>,
+    bindings: Vec<vk::VertexInputBindingDescription>,
+    attributes: Vec<vk::VertexInputAttributeDescription>,
+}
+
+/// Collects the resources declared by a pass.
+#[derive(Default)]
+pub struct PassBuilder {
+    buffers: Vec<(BufferUsage, u64)>,
+    pipelines: Vec<PipelineDesc>,
+}
+
+impl PassBuilder {
+    /// Declares a device local buffer of the given size (in bytes). Its
+    /// content is updated using `PassContext::update_buffer()`.
+    pub fn buffer(&mut self, usage: BufferUsage, size: u64) -> BufferId {
+        self.buffers.push((usage, size));
+        BufferId(self.buffers.len() - 1)
+    }
+
+    /// Declares a graphics pipeline made of SPIR-V vertex and fragment shaders.
+    pub fn pipeline(
+        &mut self,
+        vertex_spv: &[u8],
+        fragment_spv: &[u8],
+        bindings: &[vk::VertexInputBindingDescription],
+        attributes: &[vk::VertexInputAttributeDescription],
+    ) -> PipelineId {
+        self.pipelines.push(PipelineDesc {
+            vertex_spv: vertex_spv.to_vec(),
+            fragment_spv: fragment_spv.to_vec(),
+            bindings: bindings.to_vec(),
+            attributes: attributes.to_vec(),
+        });
+        PipelineId(self.pipelines.len() - 1)
+    }
+}
+
+/// A render pass provided by the application.
+pub trait CustomPass {
+    fn name(&self) -> &str;
+
+    fn stage(&self) -> PassStage;
+
+    /// Declares the resources used by the pass. Called once, when the
+    /// renderer is created.
+    fn declare(&mut self, builder: &mut PassBuilder);
+
+    /// Records the commands of the pass. Called every frame.
+    fn record(&mut self, ctx: &mut PassContext) -> Result<()>;
+}
+
+/// Resources created from the declarations of a pass.
+pub(crate) struct PassResources {
+    buffers: Vec<(Buffer, u64)>,
+    pipelines: Vec<Pipeline>,
+}
+
+impl PassResources {
+    /// Creates the declared resources, named after the pass for debuggers.
+    pub unsafe fn new(
+        device: &Device,
+        renderpass: &RenderPass,
+        name: &str,
+        builder: PassBuilder,
+    ) -> Result<Self> {
+        let mut resources = Self {
+            buffers: Vec::with_capacity(builder.buffers.len()),
+            pipelines: Vec::with_capacity(builder.pipelines.len()),
+        };
+
+        for (idx, (usage, size)) in builder.buffers.into_iter().enumerate() {
+            let usage = match usage {
+                BufferUsage::Vertex => vk::BufferUsageFlags::VERTEX_BUFFER,
+                BufferUsage::Index => vk::BufferUsageFlags::INDEX_BUFFER,
+            };
+            let buffer = Buffer::new(
+                device,
+                usage | vk::BufferUsageFlags::TRANSFER_DST,
+                vk::MemoryPropertyFlags::DEVICE_LOCAL,
+                size,
+            )?;
+            device.set_object_name(*buffer, &format!("{name} buffer {idx}"));
+            resources.buffers.push((buffer, size));
+        }
+
+        for (idx, desc) in builder.pipelines.into_iter().enumerate() {
+            // NOTE: shader modules are dropped once the pipeline is created
+            let vertex_shader = Shader::new(device, &mut Cursor::new(&desc.vertex_spv))?;
+            let fragment_shader = Shader::new(device, &mut Cursor::new(&desc.fragment_spv))?;
+            let pipeline = Pipeline::new(
+                device,
+                renderpass,
+                &vertex_shader,
+                &fragment_shader,
+                &desc.bindings,
+                &desc.attributes,
+                &[],
+            )?;
+            pipeline.set_name(device, &format!("{name} pipeline {idx}"));
+            resources.pipelines.push(pipeline);
+        }
+
+        Ok(resources)
+    }
+}
+
+/// Gives a pass access to its resources while it is recorded.
+pub struct PassContext<'a> {
+    encoder: CommandEncoder<'a>,
+    resources: &'a PassResources,
+    staging: &'a mut StagingRing,
+    delta_time: time::Duration,
+    window: WindowId,
+}
+
+impl<'a> PassContext<'a> {
+    pub(crate) fn new(
+        encoder: CommandEncoder<'a>,
+        resources: &'a PassResources,
+        staging: &'a mut StagingRing,
+        delta_time: time::Duration,
+        window: WindowId,
+    ) -> Self {
+        Self {
+            encoder,
+            resources,
+            staging,
+            delta_time,
+            window,
+        }
+    }
+
+    pub fn delta_time(&self) -> time::Duration {
+        self.delta_time
+    }
+
+    /// Returns the window being drawn. Passes and render callbacks are
+    /// recorded once per window, see `EngineBuilder::with_additional_window()`.
+    pub fn window(&self) -> WindowId {
+        self.window
+    }
+
+    /// Uploads data to a buffer. The upload is executed before any pass of
+    /// the frame is drawn, in any window: a pass drawing different data in
+    /// each window must use a buffer per window.
+    pub fn update_buffer<T: Copy>(&mut self, id: BufferId, data: &[T]) -> Result<()> {
+        let (buffer, capacity) = &self.resources.buffers[id.0];
+        let size = mem::size_of_val(data) as u64;
+        if size > *capacity {
+            return Err(EngineError::PassBufferOverflow {
+                size,
+                capacity: *capacity,
+            });
+        }
+        unsafe { self.staging.copy_to_buffer(data, **buffer, 0)? };
+        Ok(())
+    }
+
+    pub fn bind_pipeline(&mut self, id: PipelineId) {
+        self.encoder.bind_pipeline(&self.resources.pipelines[id.0]);
+    }
+
+    pub fn bind_vertex_buffer(&mut self, id: BufferId) {
+        self.encoder
+            .bind_vertex_buffer(&self.resources.buffers[id.0].0);
+    }
+
+    pub fn bind_index_buffer(&mut self, id: BufferId) {
+        self.encoder
+            .bind_index_buffer(&self.resources.buffers[id.0].0);
+    }
+
+    pub fn encoder(&mut self) -> &mut CommandEncoder<'a> {
+        &mut self.encoder
+    }
+}
+
+/// Owns the custom passes and their resources.
+pub(crate) struct PassRegistry {
+    passes: Vec<(Box<dyn CustomPass>, PassResources)>,
+}
+
+impl PassRegistry {
+    pub unsafe fn new(
+        device: &Device,
+        renderpass: &RenderPass,
+        passes: Vec<Box<dyn CustomPass>>,
+    ) -> Result<Self> {
+        let mut registry = Self {
+            passes: Vec::with_capacity(passes.len()),
+        };
+        for mut pass in passes {
+            let mut builder = PassBuilder::default();
+            pass.declare(&mut builder);
+            let resources = PassResources::new(device, renderpass, pass.name(), builder)?;
+            registry.passes.push((pass, resources));
+        }
+        Ok(registry)
+    }
+
+    /// Records the passes of the given stage, each in a labeled region. The
+    /// scissor is reset to the whole framebuffer before each pass.
+    #[allow(clippy::too_many_arguments)]
+    pub unsafe fn record(
+        &mut self,
+        stage: PassStage,
+        device: &Device,
+        command_buffer: vk::CommandBuffer,
+        extent: vk::Extent2D,
+        staging: &mut StagingRing,
+        delta_time: time::Duration,
+        window: WindowId,
+    ) {
+        for (pass, resources) in &mut self.passes {
+            if pass.stage() != stage {
+                continue;
+            }
+            device.begin_label(command_buffer, pass.name());
+            let mut encoder = CommandEncoder::new(device, command_buffer);
+            encoder.set_scissor(extent.into());
+            let mut ctx = PassContext::new(encoder, resources, &mut *staging, delta_time, window);
+            if let Err(e) = pass.record(&mut ctx) {
+                error!("record pass {}: {e}", pass.name());
+            }
+            device.end_label(command_buffer);
+        }
+    }
+}
@@ -0,0 +1,385 @@
+//! Physics of rigid bodies, simulated by rapier.
+//!
+//! The objects that have a `RigidBody` and a `Collider` are mirrored by
+//! rapier bodies, synced on every fixed step of the movement system: bodies
+//! are created for new objects and removed along with them, fixed and
+//! kinematic bodies follow the transform of their object, and dynamic bodies
+//! are teleported when the application moves their object and take the
+//! velocities it sets. After the step, the transforms and velocities of the
+//! dynamic bodies are written back.
+//!
+//! The shape and material of a body are read when it is created: changing
+//! them requires removing the `RigidBody` of the object for a step.
+
+use core::component::{AngularVelocity, Collider, PhysicsMaterial, RigidBody, Transform, Velocity};
+use core::ecs::{Entity, With, World};
+use core::mesh::MeshId;
+use std::collections::HashMap;
+
+use cgmath::{ElementWise, Vector2};
+use rapier2d::prelude as rapier;
+
+use crate::movement::FIXED_TIMESTEP;
+
+/// Settings of the physics, see `EngineBuilder::with_physics()`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PhysicsSettings {
+    /// Acceleration of the dynamic bodies, in world units per second squared.
+    pub gravity: Vector2<f32>,
+    /// Material of the bodies without a `PhysicsMaterial`.
+    pub material: PhysicsMaterial,
+}
+
+impl Default for PhysicsSettings {
+    fn default() -> Self {
+        Self {
+            gravity: Vector2::new(0.0, -9.81),
+            material: PhysicsMaterial::default(),
+        }
+    }
+}
+
+/// Rapier body mirroring an object.
+#[derive(Debug)]
+struct Body {
+    handle: rapier::RigidBodyHandle,
+    kind: RigidBody,
+    /// Transform and velocities of the object after the last step, to detect
+    /// the application changing them.
+    ///
+    /// NOTE: the transform is compared as written rather than as a world
+    /// center, which is not exact once divided and multiplied by the scale.
+    position: Vector2<f32>,
+    scale: Vector2<f32>,
+    angle: f32,
+    linvel: Vector2<f32>,
+    angvel: f32,
+    /// Last step the object was seen in.
+    step: u64,
+}
+
+/// Owns the rapier world.
+pub(crate) struct PhysicsSystem {
+    settings: PhysicsSettings,
+    pipeline: rapier::PhysicsPipeline,
+    parameters: rapier::IntegrationParameters,
+    islands: rapier::IslandManager,
+    broad_phase: rapier::BroadPhase,
+    narrow_phase: rapier::NarrowPhase,
+    bodies: rapier::RigidBodySet,
+    colliders: rapier::ColliderSet,
+    impulse_joints: rapier::ImpulseJointSet,
+    multibody_joints: rapier::MultibodyJointSet,
+    ccd_solver: rapier::CCDSolver,
+    objects: HashMap<Entity, Body>,
+    step: u64,
+}
+
+impl PhysicsSystem {
+    pub fn new(settings: PhysicsSettings) -> Self {
+        let parameters = rapier::IntegrationParameters {
+            dt: FIXED_TIMESTEP.as_secs_f32(),
+            ..Default::default()
+        };
+        Self {
+            settings,
+            pipeline: rapier::PhysicsPipeline::new(),
+            parameters,
+            islands: rapier::IslandManager::new(),
+            broad_phase: rapier::BroadPhase::new(),
+            narrow_phase: rapier::NarrowPhase::new(),
+            bodies: rapier::RigidBodySet::new(),
+            colliders: rapier::ColliderSet::new(),
+            impulse_joints: rapier::ImpulseJointSet::new(),
+            multibody_joints: rapier::MultibodyJointSet::new(),
+            ccd_solver: rapier::CCDSolver::new(),
+            objects: HashMap::new(),
+            step: 0,
+        }
+    }
+
+    /// Syncs the bodies with the objects, simulates a fixed step and writes
+    /// the dynamic bodies back.
+    pub fn step(&mut self, world: &mut World) {
+        self.step += 1;
+
+        // mirror the objects
+        for (entity, transform, kind, collider, material, velocity, angular_velocity, mesh) in world
+            .query::<(
+                Entity,
+                &Transform,
+                &RigidBody,
+                &Collider,
+                Option<&PhysicsMaterial>,
+                Option<&Velocity>,
+                Option<&AngularVelocity>,
+                Option<&MeshId>,
+            )>()
+        {
+            let center = world_center(transform, mesh.is_some());
+            let position = transform.position.truncate();
+            let scale = transform.scale.truncate();
+            let angle = transform.rotation.z;
+            let linvel = velocity.map(|velocity| velocity.velocity.truncate());
+            let angvel = angular_velocity.map(|angular_velocity| angular_velocity.velocity.z);
+            match self.objects.get_mut(&entity) {
+                Some(body) if body.kind == *kind => {
+                    let moved =
+                        body.position != position || body.scale != scale || body.angle != angle;
+                    let rigid_body = &mut self.bodies[body.handle];
+                    match body.kind {
+                        RigidBody::Kinematic => {
+                            rigid_body.set_next_kinematic_translation(vector(center));
+                            rigid_body.set_next_kinematic_rotation(rapier::Rotation::new(angle));
+                        }
+                        RigidBody::Dynamic | RigidBody::Fixed if moved => {
+                            rigid_body.set_translation(vector(center), true);
+                            rigid_body.set_rotation(rapier::Rotation::new(angle), true);
+                        }
+                        _ => {}
+                    }
+                    if body.kind == RigidBody::Dynamic {
+                        if let Some(linvel) = linvel.filter(|linvel| *linvel != body.linvel) {
+                            rigid_body.set_linvel(vector(linvel), true);
+                            body.linvel = linvel;
+                        }
+                        if let Some(angvel) = angvel.filter(|angvel| *angvel != body.angvel) {
+                            rigid_body.set_angvel(angvel, true);
+                            body.angvel = angvel;
+                        }
+                    }
+                    body.position = position;
+                    body.scale = scale;
+                    body.angle = angle;
+                    body.step = self.step;
+                }
+                _ => {
+                    // NOTE: bodies whose kind changed are created anew
+                    if let Some(body) = self.objects.remove(&entity) {
+                        self.remove_body(body.handle);
+                    }
+                    let mut builder = match kind {
+                        RigidBody::Dynamic => rapier::RigidBodyBuilder::dynamic(),
+                        RigidBody::Fixed => rapier::RigidBodyBuilder::fixed(),
+                        RigidBody::Kinematic => {
+                            rapier::RigidBodyBuilder::kinematic_position_based()
+                        }
+                    }
+                    .translation(vector(center))
+                    .rotation(angle);
+                    if let Some(linvel) = linvel {
+                        builder = builder.linvel(vector(linvel));
+                    }
+                    if let Some(angvel) = angvel {
+                        builder = builder.angvel(angvel);
+                    }
+                    // NOTE: boxes stay aligned with the axes, like their quad
+                    let shape = match *collider {
+                        Collider::Aabb { half_extents } => {
+                            builder = builder.lock_rotations();
+                            rapier::ColliderBuilder::cuboid(half_extents.x, half_extents.y)
+                        }
+                        Collider::Circle { radius } => rapier::ColliderBuilder::ball(radius),
+                    };
+                    let material = material.unwrap_or(&self.settings.material);
+                    let handle = self.bodies.insert(builder.build());
+                    self.colliders.insert_with_parent(
+                        shape
+                            .friction(material.friction)
+                            .restitution(material.restitution)
+                            .density(material.density)
+                            .build(),
+                        handle,
+                        &mut self.bodies,
+                    );
+                    let body = Body {
+                        handle,
+                        kind: *kind,
+                        position,
+                        scale,
+                        angle,
+                        linvel: linvel.unwrap_or(Vector2::new(0.0, 0.0)),
+                        angvel: angvel.unwrap_or(0.0),
+                        step: self.step,
+                    };
+                    self.objects.insert(entity, body);
+                }
+            }
+        }
+
+        // remove the bodies of the objects despawned or without a body
+        let step = self.step;
+        let mut objects = std::mem::take(&mut self.objects);
+        objects.retain(|_, body| {
+            if body.step != step {
+                self.remove_body(body.handle);
+            }
+            body.step == step
+        });
+        self.objects = objects;
+
+        // simulate
+        self.pipeline.step(
+            &vector(self.settings.gravity),
+            &self.parameters,
+            &mut self.islands,
+            &mut self.broad_phase,
+            &mut self.narrow_phase,
+            &mut self.bodies,
+            &mut self.colliders,
+            &mut self.impulse_joints,
+            &mut self.multibody_joints,
+            &mut self.ccd_solver,
+            None,
+            &(),
+            &(),
+        );
+
+        // write the dynamic bodies back
+        for (entity, transform, velocity, angular_velocity, mesh, ()) in world.query_mut::<(
+            Entity,
+            &mut Transform,
+            Option<&mut Velocity>,
+            Option<&mut AngularVelocity>,
+            Option<&MeshId>,
+            With<RigidBody>,
+        )>() {
+            let Some(body) = self.objects.get_mut(&entity) else {
+                continue;
+            };
+            if body.kind != RigidBody::Dynamic {
+                continue;
+            }
+            let rigid_body = &self.bodies[body.handle];
+            let translation = rigid_body.translation();
+            let center = Vector2::new(translation.x, translation.y);
+            body.position = match mesh {
+                Some(_) => center,
+                // NOTE: quads are scaled after being translated, see QuadBatcher
+                None => center.div_element_wise(body.scale),
+            };
+            body.angle = rigid_body.rotation().angle();
+            let linvel = rigid_body.linvel();
+            body.linvel = Vector2::new(linvel.x, linvel.y);
+            body.angvel = rigid_body.angvel();
+
+            transform.position.x = body.position.x;
+            transform.position.y = body.position.y;
+            transform.rotation.z = body.angle;
+            if let Some(velocity) = velocity {
+                velocity.velocity.x = body.linvel.x;
+                velocity.velocity.y = body.linvel.y;
+            }
+            if let Some(angular_velocity) = angular_velocity {
+                angular_velocity.velocity.z = body.angvel;
+            }
+        }
+    }
+
+    fn remove_body(&mut self, handle: rapier::RigidBodyHandle) {
+        self.bodies.remove(
+            handle,
+            &mut self.islands,
+            &mut self.colliders,
+            &mut self.impulse_joints,
+            &mut self.multibody_joints,
+            true,
+        );
+    }
+}
+
+/// Returns the center of an object in world units.
+fn world_center(transform: &Transform, is_mesh: bool) -> Vector2<f32> {
+    let position = transform.position.truncate();
+    if is_mesh {
+        position
+    } else {
+        // NOTE: quads are scaled after being translated, see QuadBatcher
+        position.mul_element_wise(transform.scale.truncate())
+    }
+}
+
+fn vector(v: Vector2<f32>) -> rapier::Vector<rapier::Real> {
+    rapier::Vector::new(v.x, v.y)
+}
+
+#[cfg(test)]
+mod tests {
+    use core::object::GameObject;
+
+    use cgmath::Vector3;
+
+    use super::*;
+
+    #[test]
+    fn dynamic_bodies_fall_on_fixed_ones() {
+        let mut world = World::new();
+        let ground = world.spawn(
+            GameObject::new()
+                .with_mesh(MeshId::from_bits(0))
+                .with_collider(Collider::aabb(Vector2::new(10.0, 0.5))),
+        );
+        world.insert(ground, RigidBody::Fixed);
+        let ball = world.spawn(
+            GameObject::new()
+                .with_mesh(MeshId::from_bits(0))
+                .with_position(Vector3::new(0.0, 2.0, 0.0))
+                .with_velocity(Vector3::new(0.0, 0.0, 0.0))
+                .with_collider(Collider::circle(0.5)),
+        );
+        world.insert(ball, RigidBody::Dynamic);
+
+        let mut physics = PhysicsSystem::new(PhysicsSettings::default());
+        physics.step(&mut world);
+        assert!(world.get::<Velocity>(ball).unwrap().velocity.y < 0.0);
+        for _ in 0..300 {
+            physics.step(&mut world);
+        }
+
+        // the ball rests on the ground
+        let position = world.get::<Transform>(ball).unwrap().position;
+        assert!((position.y - 1.0).abs() < 0.05, "{position:?}");
+        assert_eq!(
+            world.get::<Transform>(ground).unwrap().position,
+            Vector3::new(0.0, 0.0, 0.0)
+        );
+
+        world.despawn(ball);
+        physics.step(&mut world);
+        assert_eq!(physics.bodies.len(), 1);
+        assert_eq!(physics.colliders.len(), 1);
+    }
+
+    #[test]
+    fn velocities_set_by_the_application_move_dynamic_bodies() {
+        let mut world = World::new();
+        let quad = world.spawn(
+            GameObject::new()
+                .with_position(Vector3::new(1.0, 0.0, 0.0))
+                .with_scale(Vector3::new(3.0, 3.0, 1.0))
+                .with_velocity(Vector3::new(0.0, 0.0, 0.0))
+                .with_collider(Collider::aabb(Vector2::new(0.5, 0.5))),
+        );
+        world.insert(quad, RigidBody::Dynamic);
+
+        let mut physics = PhysicsSystem::new(PhysicsSettings {
+            gravity: Vector2::new(0.0, 0.0),
+            ..Default::default()
+        });
+        for _ in 0..10 {
+            physics.step(&mut world);
+        }
+        // the quad at rest is left where it is
+        assert_eq!(
+            world.get::<Transform>(quad).unwrap().position,
+            Vector3::new(1.0, 0.0, 0.0)
+        );
+
+        world.get_mut::<Velocity>(quad).unwrap().velocity = Vector3::new(3.0, 0.0, 0.0);
+        physics.step(&mut world);
+        let velocity = world.get::<Velocity>(quad).unwrap().velocity;
+        assert!((velocity.x - 3.0).abs() < 1e-4, "{velocity:?}");
+        let position = world.get::<Transform>(quad).unwrap().position;
+        assert!(position.x > 1.0, "{position:?}");
+    }
+}
@@ -0,0 +1,126 @@
+//! CPU profiling of the engine with puffin or Tracy.
+//!
+//! The frame, the update of the application and of the world, the batching
+//! of the 2D renderer, the buffer uploads and the submits are measured with
+//! `profiling` scopes. They compile to nothing unless the
+//! `profile-with-puffin` or `profile-with-tracy` feature is enabled.
+//!
+//! With Tracy, the scopes are streamed to the Tracy profiler once it
+//! connects. With puffin, they are recorded in memory and, along with the
+//! editor tools, the recent frames are summed up in an imgui window.
+
+/// Starts recording the profiling scopes of the enabled profilers.
+pub(crate) fn start() {
+    #[cfg(feature = "profile-with-puffin")]
+    profiling::puffin::set_scopes_on(true);
+    #[cfg(feature = "profile-with-tracy")]
+    profiling::tracy_client::Client::start();
+}
+
+#[cfg(all(feature = "profile-with-puffin", feature = "editor-tools"))]
+pub(crate) use hud::ProfilerHud;
+
+#[cfg(all(feature = "profile-with-puffin", feature = "editor-tools"))]
+mod hud {
+    use std::collections::BTreeSet;
+    use std::sync::Arc;
+
+    use profiling::puffin::{
+        self, GlobalFrameView, MergeScope, ScopeCollection, UnpackedFrameData,
+    };
+    use vulkan_imgui::imgui::Ui;
+
+    /// Number of recent frames the scopes are averaged over.
+    const HUD_FRAMES: usize = 60;
+
+    /// Imgui window showing the average time per frame of the scopes of each
+    /// thread over the recent frames, nested as they were recorded.
+    pub(crate) struct ProfilerHud {
+        view: GlobalFrameView,
+    }
+
+    impl ProfilerHud {
+        pub fn new() -> Self {
+            Self {
+                view: GlobalFrameView::default(),
+            }
+        }
+
+        pub fn draw(&self, ui: &Ui) {
+            ui.window("Profiler").build(|| {
+                let view = self.view.lock();
+                let frames: Vec<Arc<UnpackedFrameData>> = view
+                    .latest_frames(HUD_FRAMES)
+                    .filter_map(|frame| frame.unpacked().ok())
+                    .collect();
+                if frames.is_empty() {
+                    ui.text("no frame recorded yet");
+                    return;
+                }
+                let total_ns: i64 = frames.iter().map(|frame| frame.duration_ns()).sum();
+                ui.text(format!(
+                    "{} per frame over {} frames",
+                    ms(total_ns / frames.len() as i64),
+                    frames.len()
+                ));
+
+                let threads: BTreeSet<_> = frames
+                    .iter()
+                    .flat_map(|frame| frame.thread_streams.keys().cloned())
+                    .collect();
+                for thread in threads {
+                    match puffin::merge_scopes_for_thread(view.scope_collection(), &frames, &thread)
+                    {
+                        Ok(scopes) => {
+                            if let Some(_node) = ui.tree_node(&thread.name) {
+                                draw_scopes(ui, view.scope_collection(), &scopes, frames.len());
+                            }
+                        }
+                        Err(e) => ui.text(format!("{}: {e:?}", thread.name)),
+                    }
+                }
+            });
+        }
+    }
+
+    fn draw_scopes(ui: &Ui, collection: &ScopeCollection, scopes: &[MergeScope], frames: usize) {
+        for scope in scopes {
+            let name = collection
+                .fetch_by_id(&scope.id)
+                .map_or("unknown scope", |details| details.name().as_ref());
+            let calls_per_frame = scope.num_pieces as f64 / frames as f64;
+            let label = scope_label(name, scope.duration_per_frame_ns, calls_per_frame);
+            // NOTE: nodes are identified by their scope, as their label
+            //       changes every frame
+            if scope.children.is_empty() {
+                ui.bullet_text(label);
+            } else if let Some(_node) = ui.tree_node(format!("{label}###{}", scope.id.0)) {
+                draw_scopes(ui, collection, &scope.children, frames);
+            }
+        }
+    }
+
+    fn scope_label(name: &str, duration_per_frame_ns: i64, calls_per_frame: f64) -> String {
+        format!(
+            "{name}: {} ({calls_per_frame:.1} calls)",
+            ms(duration_per_frame_ns)
+        )
+    }
+
+    fn ms(ns: i64) -> String {
+        format!("{:.3} ms", ns as f64 / 1e6)
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn scopes_are_labeled_in_milliseconds() {
+            assert_eq!(
+                scope_label("QuadPhase.add", 1_234_567, 2.0),
+                "QuadPhase.add: 1.235 ms (2.0 calls)"
+            );
+        }
+    }
+}
@@ -0,0 +1,312 @@
+//! Imgui window remapping the bindings of an input map, so that games do not
+//! each write their own controls screen.
+//!
+//! The application forwards its events to the widget, which binds the next
+//! key, mouse button or scroll direction pressed after a "Set" or "Add"
+//! button is clicked:
+//!
+//! ```ignore
+//! fn on_event(&mut self, event: &Event<()>, _ctx: ApplicationContext) {
+//!     if self.remap.on_event(&mut self.input_map, event) {
+//!         return;
+//!     }
+//!     // game input
+//! }
+//!
+//! fn on_render_ui(&mut self, ui: &mut imgui::Ui) {
+//!     self.remap.draw(ui, &mut self.input_map);
+//! }
+//! ```
+
+use std::path::PathBuf;
+
+use input::{Binding, InputMap};
+use log::error;
+use vulkan_imgui::imgui::Ui;
+use winit::event::{ElementState, Event, KeyboardInput, VirtualKeyCode, WindowEvent};
+
+const LISTENING_COLOR: [f32; 4] = [0.4, 0.8, 1.0, 1.0];
+const WARNING_COLOR: [f32; 4] = [1.0, 0.8, 0.0, 1.0];
+
+/// Action waiting for the next pressed input.
+struct Listening {
+    action: String,
+    /// Replaces the bindings of the action instead of adding to them.
+    replace: bool,
+}
+
+/// Captured input already bound to other actions, applied once confirmed.
+struct Conflict {
+    action: String,
+    binding: Binding,
+    replace: bool,
+    others: Vec<String>,
+}
+
+/// Lists the bindings of actions and rebinds them to the inputs pressed by
+/// the user. Inputs bound to several actions are only applied once
+/// confirmed. The bindings are saved after each change when a save path is
+/// set.
+pub struct RemapWidget {
+    actions: Vec<String>,
+    save_path: Option<PathBuf>,
+    listening: Option<Listening>,
+    conflict: Option<Conflict>,
+}
+
+impl RemapWidget {
+    /// Creates a widget listing the actions in order, including the ones not
+    /// bound yet.
+    pub fn new(actions: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        Self {
+            actions: actions.into_iter().map(Into::into).collect(),
+            save_path: None,
+            listening: None,
+            conflict: None,
+        }
+    }
+
+    /// Sets the file the bindings are saved to after each change, with
+    /// `InputMap::save()`.
+    #[inline]
+    pub fn with_save_path(mut self, save_path: impl Into<PathBuf>) -> Self {
+        self.save_path = Some(save_path.into());
+        self
+    }
+
+    /// Returns true while waiting for the input to bind, during which the
+    /// application should ignore its game input.
+    pub fn is_listening(&self) -> bool {
+        self.listening.is_some()
+    }
+
+    /// Binds the next pressed input to an action, replacing its bindings or
+    /// adding to them.
+    pub fn listen(&mut self, action: impl Into<String>, replace: bool) {
+        self.listening = Some(Listening {
+            action: action.into(),
+            replace,
+        });
+        self.conflict = None;
+    }
+
+    /// Binds the input pressed by the event to the action listening, if any.
+    /// Escape stops listening. Returns true if the event was consumed.
+    pub fn on_event(&mut self, map: &mut InputMap, event: &Event<()>) -> bool {
+        if self.listening.is_none() {
+            return false;
+        }
+        if let Event::WindowEvent {
+            event:
+                WindowEvent::KeyboardInput {
+                    input:
+                        KeyboardInput {
+                            virtual_keycode: Some(VirtualKeyCode::Escape),
+                            state: ElementState::Pressed,
+                            ..
+                        },
+                    ..
+                },
+            ..
+        } = event
+        {
+            self.listening = None;
+            return true;
+        }
+        let Some(binding) = Binding::from_event(event) else {
+            return false;
+        };
+        let Some(Listening { action, replace }) = self.listening.take() else {
+            return false;
+        };
+        let others: Vec<String> = map
+            .actions_bound_to(binding)
+            .filter(|other| *other != action)
+            .map(str::to_owned)
+            .collect();
+        if others.is_empty() {
+            self.apply(map, &action, binding, replace);
+        } else {
+            self.conflict = Some(Conflict {
+                action,
+                binding,
+                replace,
+                others,
+            });
+        }
+        true
+    }
+
+    /// Draws the actions and their bindings in an imgui window.
+    pub fn draw(&mut self, ui: &Ui, map: &mut InputMap) {
+        ui.window("Controls").build(|| {
+            let mut listen = None;
+            for action in &self.actions {
+                let _id = ui.push_id(action);
+                ui.text(action);
+                ui.same_line();
+                let bindings = map.bindings(action);
+                if matches!(&self.listening, Some(listening) if listening.action == *action) {
+                    ui.text_colored(LISTENING_COLOR, "press an input, Escape to cancel");
+                } else if bindings.is_empty() {
+                    ui.text_disabled("unbound");
+                } else {
+                    let bindings: Vec<String> = bindings.iter().map(Binding::to_string).collect();
+                    ui.text(bindings.join(", "));
+                }
+                ui.same_line();
+                if ui.small_button("Set") {
+                    listen = Some((action.clone(), true));
+                }
+                ui.same_line();
+                if ui.small_button("Add") {
+                    listen = Some((action.clone(), false));
+                }
+                ui.same_line();
+                if ui.small_button("Clear") && !map.bindings(action).is_empty() {
+                    map.rebind(action.as_str(), []);
+                    self.save(map);
+                }
+            }
+            if let Some((action, replace)) = listen {
+                self.listen(action, replace);
+            }
+
+            let Some(conflict) = &self.conflict else {
+                return;
+            };
+            ui.separator();
+            ui.text_colored(
+                WARNING_COLOR,
+                format!(
+                    "{} is also bound to {}",
+                    conflict.binding,
+                    conflict.others.join(", ")
+                ),
+            );
+            if ui.button("Move here") {
+                self.resolve_conflict(map, true);
+            }
+            ui.same_line();
+            if ui.button("Keep both") {
+                self.resolve_conflict(map, false);
+            }
+            ui.same_line();
+            if ui.button("Cancel") {
+                self.conflict = None;
+            }
+        });
+    }
+
+    /// Applies the conflicting binding, removing it from the other actions if
+    /// `unbind_others` is true.
+    fn resolve_conflict(&mut self, map: &mut InputMap, unbind_others: bool) {
+        let Some(conflict) = self.conflict.take() else {
+            return;
+        };
+        if unbind_others {
+            for other in &conflict.others {
+                map.unbind(other, conflict.binding);
+            }
+        }
+        self.apply(map, &conflict.action, conflict.binding, conflict.replace);
+    }
+
+    fn apply(&self, map: &mut InputMap, action: &str, binding: Binding, replace: bool) {
+        if replace {
+            map.rebind(action, [binding]);
+        } else {
+            map.bind(action, binding);
+        }
+        self.save(map);
+    }
+
+    fn save(&self, map: &InputMap) {
+        if let Some(path) = &self.save_path {
+            if let Err(e) = map.save(path) {
+                error!("save input bindings: {e}");
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use winit::event::DeviceId;
+    use winit::window::WindowId;
+
+    use super::*;
+
+    #[allow(deprecated)]
+    fn key_pressed(keycode: VirtualKeyCode) -> Event<'static, ()> {
+        Event::WindowEvent {
+            window_id: unsafe { WindowId::dummy() },
+            event: WindowEvent::KeyboardInput {
+                device_id: unsafe { DeviceId::dummy() },
+                input: KeyboardInput {
+                    scancode: 0,
+                    state: ElementState::Pressed,
+                    virtual_keycode: Some(keycode),
+                    modifiers: Default::default(),
+                },
+                is_synthetic: false,
+            },
+        }
+    }
+
+    #[test]
+    fn next_pressed_input_is_bound() {
+        let mut map = InputMap::new().with_binding("jump", Binding::Key(VirtualKeyCode::Space));
+        let mut widget = RemapWidget::new(["jump"]);
+
+        assert!(!widget.on_event(&mut map, &key_pressed(VirtualKeyCode::Q)));
+
+        widget.listen("jump", false);
+        assert!(widget.on_event(&mut map, &key_pressed(VirtualKeyCode::Q)));
+        assert!(!widget.is_listening());
+        assert_eq!(
+            map.bindings("jump"),
+            [
+                Binding::Key(VirtualKeyCode::Space),
+                Binding::Key(VirtualKeyCode::Q)
+            ]
+        );
+
+        widget.listen("jump", true);
+        assert!(widget.on_event(&mut map, &key_pressed(VirtualKeyCode::E)));
+        assert_eq!(map.bindings("jump"), [Binding::Key(VirtualKeyCode::E)]);
+
+        widget.listen("jump", true);
+        assert!(widget.on_event(&mut map, &key_pressed(VirtualKeyCode::Escape)));
+        assert!(!widget.is_listening());
+        assert_eq!(map.bindings("jump"), [Binding::Key(VirtualKeyCode::E)]);
+    }
+
+    #[test]
+    fn conflicting_input_is_applied_once_confirmed() {
+        let space = Binding::Key(VirtualKeyCode::Space);
+        let mut map = InputMap::new()
+            .with_binding("jump", Binding::Key(VirtualKeyCode::W))
+            .with_binding("confirm", space);
+        let mut widget = RemapWidget::new(["jump", "confirm"]);
+
+        widget.listen("jump", true);
+        assert!(widget.on_event(&mut map, &key_pressed(VirtualKeyCode::Space)));
+        assert_eq!(map.bindings("jump"), [Binding::Key(VirtualKeyCode::W)]);
+
+        widget.resolve_conflict(&mut map, true);
+        assert_eq!(map.bindings("jump"), [space]);
+        assert!(map.bindings("confirm").is_empty());
+
+        // binding an action to its own input is not a conflict
+        widget.listen("jump", false);
+        assert!(widget.on_event(&mut map, &key_pressed(VirtualKeyCode::Space)));
+        assert!(widget.conflict.is_none());
+
+        map.bind("confirm", space);
+        widget.listen("jump", true);
+        widget.on_event(&mut map, &key_pressed(VirtualKeyCode::Space));
+        widget.resolve_conflict(&mut map, false);
+        assert_eq!(map.actions_bound_to(space).count(), 2);
+    }
+}
@@ -0,0 +1,133 @@
+//! Per-object render callbacks.
+//!
+//! A callback is attached to a game object and records commands right after
+//! the object is drawn by the 2D renderer, at its place in the draw order. It
+//! is meant for one-off custom drawing that does not warrant a full pass. Like
+//! passes, callbacks declare their resources and record their commands through
+//! a `PassContext`.
+
+use core::ecs::{Entity, World};
+use core::object::{ObjectId, QuadView};
+use std::collections::HashMap;
+use std::time;
+
+use ash::vk;
+use log::error;
+use vulkan_renderer::device::Device;
+use vulkan_renderer::encoder::CommandEncoder;
+use vulkan_renderer::renderpass::RenderPass;
+use vulkan_renderer::staging::StagingRing;
+use winit::window::WindowId;
+
+use crate::pass::{PassBuilder, PassContext, PassResources};
+use crate::Result;
+
+/// Custom drawing attached to a game object.
+pub trait RenderCallback {
+    fn name(&self) -> &str;
+
+    /// Declares the resources used by the callback. Called once, before the
+    /// callback is first recorded.
+    fn declare(&mut self, _builder: &mut PassBuilder) {}
+
+    /// Records the commands of the callback. Called every frame, right after
+    /// the object is drawn.
+    fn record(&mut self, ctx: &mut PassContext, object: QuadView) -> Result<()>;
+}
+
+struct Entry {
+    callback: Box<dyn RenderCallback>,
+    /// None until the resources declared by the callback are created.
+    resources: Option<PassResources>,
+}
+
+/// Owns the render callbacks and their resources.
+#[derive(Default)]
+pub(crate) struct RenderCallbacks {
+    entries: HashMap<ObjectId, Entry>,
+}
+
+impl RenderCallbacks {
+    /// Attaches a callback to an object, replacing the previous one.
+    pub fn insert(&mut self, id: ObjectId, callback: Box<dyn RenderCallback>) {
+        let entry = Entry {
+            callback,
+            resources: None,
+        };
+        self.entries.insert(id, entry);
+    }
+
+    pub fn remove(&mut self, id: ObjectId) -> Option<Box<dyn RenderCallback>> {
+        self.entries.remove(&id).map(|entry| entry.callback)
+    }
+
+    /// Drops the callbacks of removed objects and creates the resources of
+    /// the new ones. Callbacks whose resources can not be created are dropped.
+    pub unsafe fn prepare(&mut self, device: &Device, renderpass: &RenderPass, world: &World) {
+        self.entries.retain(|id, entry| {
+            if !world.contains(*id) {
+                return false;
+            }
+            if entry.resources.is_some() {
+                return true;
+            }
+            let mut builder = PassBuilder::default();
+            entry.callback.declare(&mut builder);
+            match PassResources::new(device, renderpass, entry.callback.name(), builder) {
+                Ok(resources) => {
+                    entry.resources = Some(resources);
+                    true
+                }
+                Err(e) => {
+                    error!("create render callback {}: {e}", entry.callback.name());
+                    false
+                }
+            }
+        });
+    }
+
+    /// Pairs the objects with the recording of their callback, in draw order.
+    /// Each callback is recorded in a labeled region and the scissor is reset
+    /// to the whole framebuffer around it.
+    pub fn draw_order<'a>(
+        &'a mut self,
+        world: &'a World,
+        device: &'a Device,
+        extent: vk::Extent2D,
+        delta_time: time::Duration,
+        window: WindowId,
+    ) -> impl Iterator<
+        Item = (
+            QuadView<'a>,
+            Option<impl FnOnce(vk::CommandBuffer, &mut StagingRing) + 'a>,
+        ),
+    > {
+        let mut entries: HashMap<_, _> = self.entries.iter_mut().collect();
+        world
+            .query::<(Entity, QuadView)>()
+            .map(move |(id, object)| {
+                let callback = entries.remove(&id).and_then(|entry| {
+                    let Entry {
+                        callback,
+                        resources,
+                    } = entry;
+                    let resources = resources.as_ref()?;
+                    Some(
+                        move |command_buffer: vk::CommandBuffer, staging: &mut StagingRing| unsafe {
+                            device.begin_label(command_buffer, callback.name());
+                            let mut encoder = CommandEncoder::new(device, command_buffer);
+                            encoder.set_scissor(extent.into());
+                            let mut ctx =
+                                PassContext::new(encoder, resources, staging, delta_time, window);
+                            if let Err(e) = callback.record(&mut ctx, object) {
+                                error!("record render callback {}: {e}", callback.name());
+                            }
+                            ctx.encoder().set_scissor(extent.into());
+                            device.end_label(command_buffer);
+                        },
+                    )
+                });
+                (object, callback)
+            })
+    }
+}
@@ -0,0 +1,208 @@
+//! Render systems registered by applications, drawing next to the built-in
+//! 2D and 3D renderers.
+//!
+//! Unlike custom passes, systems work with the device and command buffers
+//! directly: they create their own Vulkan objects, compatible with the main
+//! render pass, and record any command inside it. They are the way to add a
+//! renderer to the engine without changing it.
+
+use core::ecs::World;
+use std::time;
+
+use ash::vk;
+use cgmath::Matrix4;
+use log::error;
+use vulkan_renderer::device::Device;
+use vulkan_renderer::renderpass::RenderPass;
+use vulkan_renderer::staging::StagingRing;
+use winit::window::WindowId;
+
+use crate::pass::PassStage;
+use crate::Result;
+
+/// A renderer provided by the application, see
+/// `EngineBuilder::with_renderer_system()`.
+pub trait RendererSystem {
+    fn name(&self) -> &str;
+
+    /// Where the system is recorded within the frame.
+    fn stage(&self) -> PassStage;
+
+    /// Creates the resources of the system. Called once, when the renderer
+    /// is created. The system is dropped if it fails.
+    fn init(&mut self, ctx: &InitContext) -> Result<()>;
+
+    /// Called when the main window is resized, with its new size.
+    fn resize(&mut self, _device: &Device, _extent: vk::Extent2D) -> Result<()> {
+        Ok(())
+    }
+
+    /// Records the commands of the system. Called every frame, once per
+    /// window.
+    fn render(&mut self, ctx: &mut RenderContext) -> Result<()>;
+
+    /// Destroys the resources of the system. Called once the device is idle,
+    /// before the renderer is destroyed.
+    fn destroy(&mut self, _device: &Device) {}
+}
+
+/// What a system creates its resources for.
+pub struct InitContext<'a> {
+    device: &'a Device,
+    renderpass: &'a RenderPass,
+    frames_in_flight: u32,
+}
+
+impl<'a> InitContext<'a> {
+    pub fn device(&self) -> &Device {
+        self.device
+    }
+
+    /// Returns the render pass the system is recorded in. Pipelines created
+    /// for it can draw to every window and to the world layer.
+    pub fn renderpass(&self) -> &RenderPass {
+        self.renderpass
+    }
+
+    /// Returns the number of frames recorded ahead of the GPU. Resources
+    /// written every frame need a copy per frame in flight.
+    pub fn frames_in_flight(&self) -> u32 {
+        self.frames_in_flight
+    }
+}
+
+/// What a system records its commands with.
+pub struct RenderContext<'a> {
+    device: &'a Device,
+    command_buffer: vk::CommandBuffer,
+    staging: &'a mut StagingRing,
+    extent: vk::Extent2D,
+    view_projection: Matrix4<f32>,
+    world: &'a World,
+    delta_time: time::Duration,
+    window: WindowId,
+}
+
+impl<'a> RenderContext<'a> {
+    pub fn device(&self) -> &Device {
+        self.device
+    }
+
+    /// Returns the command buffer being recorded, inside the render pass.
+    pub fn command_buffer(&self) -> vk::CommandBuffer {
+        self.command_buffer
+    }
+
+    /// Returns the staging ring, whose uploads are executed before any
+    /// command of the frame.
+    pub fn staging(&mut self) -> &mut StagingRing {
+        self.staging
+    }
+
+    /// Returns the size of the framebuffer being drawn.
+    pub fn extent(&self) -> vk::Extent2D {
+        self.extent
+    }
+
+    /// Returns the view projection matrix of the camera of the window.
+    pub fn view_projection(&self) -> Matrix4<f32> {
+        self.view_projection
+    }
+
+    /// Returns the world, to query the objects the system draws.
+    pub fn world(&self) -> &World {
+        self.world
+    }
+
+    pub fn delta_time(&self) -> time::Duration {
+        self.delta_time
+    }
+
+    /// Returns the window being drawn, see `PassContext::window()`.
+    pub fn window(&self) -> WindowId {
+        self.window
+    }
+}
+
+/// Owns the systems registered by the application.
+pub(crate) struct RendererSystems {
+    systems: Vec<Box<dyn RendererSystem>>,
+}
+
+impl RendererSystems {
+    /// Initializes the systems, dropping the ones that fail.
+    pub fn new(
+        device: &Device,
+        renderpass: &RenderPass,
+        frames_in_flight: u32,
+        systems: Vec<Box<dyn RendererSystem>>,
+    ) -> Self {
+        let ctx = InitContext {
+            device,
+            renderpass,
+            frames_in_flight,
+        };
+        let systems = systems
+            .into_iter()
+            .filter_map(|mut system| match system.init(&ctx) {
+                Ok(()) => Some(system),
+                Err(e) => {
+                    error!("init renderer system {}: {e}", system.name());
+                    None
+                }
+            })
+            .collect();
+        Self { systems }
+    }
+
+    pub fn resize(&mut self, device: &Device, extent: vk::Extent2D) {
+        for system in &mut self.systems {
+            if let Err(e) = system.resize(device, extent) {
+                error!("resize renderer system {}: {e}", system.name());
+            }
+        }
+    }
+
+    /// Records the systems of the given stage, each in a labeled region.
+    #[allow(clippy::too_many_arguments)]
+    pub unsafe fn record(
+        &mut self,
+        stage: PassStage,
+        device: &Device,
+        command_buffer: vk::CommandBuffer,
+        extent: vk::Extent2D,
+        staging: &mut StagingRing,
+        view_projection: Matrix4<f32>,
+        world: &World,
+        delta_time: time::Duration,
+        window: WindowId,
+    ) {
+        for system in &mut self.systems {
+            if system.stage() != stage {
+                continue;
+            }
+            device.begin_label(command_buffer, system.name());
+            let mut ctx = RenderContext {
+                device,
+                command_buffer,
+                staging: &mut *staging,
+                extent,
+                view_projection,
+                world,
+                delta_time,
+                window,
+            };
+            if let Err(e) = system.render(&mut ctx) {
+                error!("render renderer system {}: {e}", system.name());
+            }
+            device.end_label(command_buffer);
+        }
+    }
+
+    /// Destroys the systems. The device must be idle.
+    pub fn destroy(&mut self, device: &Device) {
+        for mut system in self.systems.drain(..) {
+            system.destroy(device);
+        }
+    }
+}
@@ -0,0 +1,185 @@
+//! Viewport ruler measuring world space distances and angles.
+//!
+//! While the ruler is enabled, dragging with the left mouse button over the
+//! viewport measures from the press to the cursor. The measured segment stays
+//! anchored in the world when the camera moves, until the next drag or until
+//! the ruler is disabled.
+
+use cgmath::{InnerSpace, Matrix4, Point2, SquareMatrix, Vector4};
+use vulkan_imgui::imgui::{Condition, MouseButton, Ui};
+use winit::event::{Event, VirtualKeyCode};
+
+use crate::hotkey::Hotkey;
+
+/// Key toggling the ruler by default.
+pub const DEFAULT_RULER_KEY: VirtualKeyCode = VirtualKeyCode::F3;
+
+const LINE_COLOR: [f32; 4] = [1.0, 0.8, 0.0, 1.0];
+
+/// Distance and angle between two world space points.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) struct Measurement {
+    pub dx: f32,
+    pub dy: f32,
+    pub distance: f32,
+    /// Angle in degrees from the X axis, counter-clockwise, in (-180, 180].
+    pub angle: f32,
+}
+
+impl Measurement {
+    pub fn between(start: Point2<f32>, end: Point2<f32>) -> Self {
+        let delta = end - start;
+        Self {
+            dx: delta.x,
+            dy: delta.y,
+            distance: delta.magnitude(),
+            angle: delta.y.atan2(delta.x).to_degrees(),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub(crate) struct Ruler {
+    hotkey: Hotkey,
+    enabled: bool,
+    /// World space segment being or last measured.
+    segment: Option<(Point2<f32>, Point2<f32>)>,
+}
+
+impl Ruler {
+    /// Creates a disabled ruler, toggled by the key if any.
+    pub fn new(key: Option<VirtualKeyCode>) -> Self {
+        Self {
+            hotkey: Hotkey::new(key),
+            enabled: false,
+            segment: None,
+        }
+    }
+
+    /// Toggles the ruler when the hotkey is pressed.
+    pub fn on_event<T>(&mut self, event: &Event<T>) {
+        if self.hotkey.on_event(event) {
+            self.enabled = !self.enabled;
+            self.segment = None;
+        }
+    }
+
+    /// Measures the mouse drags not captured by other windows, then draws the
+    /// segment over the viewport and the measurement in an overlay.
+    pub fn draw(&mut self, ui: &Ui, view_projection: Matrix4<f32>) {
+        if !self.enabled {
+            return;
+        }
+        let Some(inverse) = view_projection.invert() else {
+            return;
+        };
+        let io = ui.io();
+        let display_size = io.display_size;
+
+        // measure
+        if !io.want_capture_mouse {
+            let cursor = screen_to_world(inverse, display_size, io.mouse_pos);
+            if ui.is_mouse_clicked(MouseButton::Left) {
+                self.segment = Some((cursor, cursor));
+            } else if ui.is_mouse_down(MouseButton::Left) {
+                if let Some((_, end)) = self.segment.as_mut() {
+                    *end = cursor;
+                }
+            }
+        }
+
+        // draw the segment where it is in the world
+        let measurement = self.segment.map(|(start, end)| {
+            let start_pos = world_to_screen(view_projection, display_size, start);
+            let end_pos = world_to_screen(view_projection, display_size, end);
+            let draw_list = ui.get_foreground_draw_list();
+            draw_list
+                .add_line(start_pos, end_pos, LINE_COLOR)
+                .thickness(2.0)
+                .build();
+            for pos in [start_pos, end_pos] {
+                draw_list
+                    .add_circle(pos, 4.0, LINE_COLOR)
+                    .filled(true)
+                    .build();
+            }
+            Measurement::between(start, end)
+        });
+
+        // readout
+        ui.window("Ruler")
+            .position([10.0, 10.0], Condition::FirstUseEver)
+            .always_auto_resize(true)
+            .build(|| match measurement {
+                Some(m) => {
+                    ui.text(format!("distance: {:.3}", m.distance));
+                    ui.text(format!("angle: {:.1} deg", m.angle));
+                    ui.text(format!("dx: {:.3}, dy: {:.3}", m.dx, m.dy));
+                }
+                None => ui.text("drag to measure"),
+            });
+    }
+}
+
+/// Returns the world space point under a screen position. Only X and Y are
+/// kept, which do not depend on the depth with an orthographic camera.
+fn screen_to_world(
+    inverse_view_projection: Matrix4<f32>,
+    display_size: [f32; 2],
+    pos: [f32; 2],
+) -> Point2<f32> {
+    let ndc = Vector4::new(
+        pos[0] / display_size[0] * 2.0 - 1.0,
+        pos[1] / display_size[1] * 2.0 - 1.0,
+        0.0,
+        1.0,
+    );
+    let world = inverse_view_projection * ndc;
+    Point2::new(world.x / world.w, world.y / world.w)
+}
+
+fn world_to_screen(
+    view_projection: Matrix4<f32>,
+    display_size: [f32; 2],
+    point: Point2<f32>,
+) -> [f32; 2] {
+    let clip = view_projection * Vector4::new(point.x, point.y, 0.0, 1.0);
+    [
+        (clip.x / clip.w + 1.0) / 2.0 * display_size[0],
+        (clip.y / clip.w + 1.0) / 2.0 * display_size[1],
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use camera::{CameraController, CameraOrthographic};
+
+    use super::*;
+
+    #[test]
+    fn measurement_between_points() {
+        let m = Measurement::between(Point2::new(1.0, 1.0), Point2::new(4.0, 5.0));
+        assert_eq!((m.dx, m.dy, m.distance), (3.0, 4.0, 5.0));
+        assert!((m.angle - 53.130).abs() < 1e-3);
+
+        let m = Measurement::between(Point2::new(0.0, 0.0), Point2::new(-1.0, 0.0));
+        assert_eq!(m.angle, 180.0);
+    }
+
+    #[test]
+    fn screen_positions_round_trip_through_world_space() {
+        let camera = CameraController::new(CameraOrthographic::new(800, 600));
+        let view_projection = camera.view_projection_matrix();
+        let inverse = view_projection.invert().unwrap();
+        let display_size = [800.0, 600.0];
+
+        // the camera looks at the origin
+        let center = screen_to_world(inverse, display_size, [400.0, 300.0]);
+        assert!(center.x.abs() < 1e-5 && center.y.abs() < 1e-5);
+
+        let pos = [123.0, 456.0];
+        let world = screen_to_world(inverse, display_size, pos);
+        let screen = world_to_screen(view_projection, display_size, world);
+        assert!((screen[0] - pos[0]).abs() < 1e-3 && (screen[1] - pos[1]).abs() < 1e-3);
+    }
+}
@@ -0,0 +1,131 @@
+//! Safe mode startup after repeated crashes.
+//!
+//! Each startup is recorded in the config directory and cleared once the first
+//! frame has been presented. When too many startups in a row did not get that
+//! far, the engine starts in safe mode, using conservative renderer settings.
+
+use std::path::{Path, PathBuf};
+use std::{env, fs};
+
+use log::warn;
+use vulkan_renderer::renderer::{RendererSettings, DEFAULT_FRAMES_IN_FLIGHT};
+
+/// Number of consecutive failed startups after which safe mode is entered.
+pub const SAFE_MODE_THRESHOLD: u32 = 3;
+
+const STARTUP_FAILURES_FILE: &str = "startup_failures";
+
+/// Returns the default directory used to keep the engine state across runs.
+/// There is none in the tests of the engine, which keep off the directory of
+/// the user.
+pub fn default_config_dir() -> Option<PathBuf> {
+    if cfg!(test) {
+        return None;
+    }
+    let base = if cfg!(windows) {
+        env::var_os("APPDATA").map(PathBuf::from)
+    } else if cfg!(target_os = "macos") {
+        env::var_os("HOME").map(|home| Path::new(&home).join("Library/Application Support"))
+    } else {
+        env::var_os("XDG_CONFIG_HOME")
+            .map(PathBuf::from)
+            .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".config")))
+    };
+    base.map(|base| base.join("toy-engine"))
+}
+
+/// Returns the settings used in safe mode: validation and vsync on, the
+/// default number of frames in flight and the first suitable GPU.
+pub fn safe_renderer_settings() -> RendererSettings {
+    RendererSettings {
+        frames_in_flight: DEFAULT_FRAMES_IN_FLIGHT,
+        validation: true,
+        vsync: true,
+        gpu: None,
+    }
+}
+
+/// Tracks the consecutive startups that did not present a frame.
+#[derive(Debug)]
+pub(crate) struct StartupTracker {
+    path: Option<PathBuf>,
+    /// Failed startups before this one.
+    failures: u32,
+    completed: bool,
+}
+
+impl StartupTracker {
+    /// Reads the number of failed startups and records this one as failed
+    /// until `complete()` is called. Tracking is disabled without a config
+    /// directory or when it can not be written.
+    pub fn begin(config_dir: Option<&Path>) -> Self {
+        let path = config_dir.map(|dir| dir.join(STARTUP_FAILURES_FILE));
+        let failures = path
+            .as_ref()
+            .and_then(|path| fs::read_to_string(path).ok())
+            .and_then(|content| content.trim().parse().ok())
+            .unwrap_or(0);
+
+        let mut tracker = Self {
+            path,
+            failures,
+            completed: false,
+        };
+        tracker.write(failures + 1);
+        tracker
+    }
+
+    pub fn failures(&self) -> u32 {
+        self.failures
+    }
+
+    pub fn safe_mode(&self) -> bool {
+        self.failures >= SAFE_MODE_THRESHOLD
+    }
+
+    /// Marks the startup as successful.
+    pub fn complete(&mut self) {
+        if !self.completed {
+            self.completed = true;
+            self.write(0);
+        }
+    }
+
+    fn write(&mut self, failures: u32) {
+        let Some(path) = &self.path else {
+            return;
+        };
+        let result = path
+            .parent()
+            .map_or(Ok(()), fs::create_dir_all)
+            .and_then(|_| fs::write(path, failures.to_string()));
+        if let Err(e) = result {
+            warn!("record startup in {}: {e}", path.display());
+            self.path = None;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn safe_mode_after_consecutive_failures() {
+        let dir = env::temp_dir().join(format!("toy-engine-safe-mode-{}", std::process::id()));
+
+        for failures in 0..SAFE_MODE_THRESHOLD {
+            let tracker = StartupTracker::begin(Some(&dir));
+            assert_eq!(tracker.failures(), failures);
+            assert!(!tracker.safe_mode());
+        }
+        let mut tracker = StartupTracker::begin(Some(&dir));
+        assert!(tracker.safe_mode());
+
+        tracker.complete();
+        let tracker = StartupTracker::begin(Some(&dir));
+        assert_eq!(tracker.failures(), 0);
+
+        fs::remove_dir_all(&dir).expect("remove test dir");
+    }
+}
@@ -0,0 +1,357 @@
+//! Effects played over the world when the states switch, see `State`.
+//!
+//! Fades and wipes cover the screen with a color during the first half of
+//! the transition, switch the states once it is covered, then uncover it. A
+//! crossfade draws the world to a target for a frame, switches the states
+//! and blends the captured frame out over the new world. The UI is drawn
+//! over the transitions.
+//!
+//! States switched while the screen is being covered wait for it along with
+//! the first ones. States switched while it is uncovered switch right away,
+//! without a transition of their own. The headless engine switches them
+//! right away.
+
+use core::tween::Easing;
+use std::{mem, time};
+
+use cgmath::Vector4;
+use vulkan_renderer_2d::transition::WipeDirection;
+
+use crate::state::Transition;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TransitionEffect {
+    /// Fades the world out to a color, and the new world in.
+    Fade(Vector4<f32>),
+    /// Blends the last frame of the world out over the new world.
+    Crossfade,
+    /// Covers the world with a color moving in a direction, and uncovers the
+    /// new world moving on in the same direction.
+    Wipe {
+        color: Vector4<f32>,
+        direction: WipeDirection,
+    },
+}
+
+/// Effect played when the states switch, over its whole duration.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ScreenTransition {
+    pub effect: TransitionEffect,
+    pub duration: time::Duration,
+    /// Curve of the progress of the effect, over each half of fades and
+    /// wipes.
+    pub easing: Easing,
+}
+
+impl ScreenTransition {
+    pub fn new(effect: TransitionEffect, duration: time::Duration) -> Self {
+        Self {
+            effect,
+            duration,
+            easing: Easing::default(),
+        }
+    }
+
+    pub fn with_easing(mut self, easing: Easing) -> Self {
+        self.easing = easing;
+        self
+    }
+
+    /// Returns how long the screen takes to be covered, and then uncovered.
+    fn phases(&self) -> (time::Duration, time::Duration) {
+        match self.effect {
+            TransitionEffect::Crossfade => (time::Duration::ZERO, self.duration),
+            _ => (self.duration / 2, self.duration / 2),
+        }
+    }
+}
+
+/// Effect drawn over the world during a frame.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum Overlay {
+    Fade {
+        color: Vector4<f32>,
+        amount: f32,
+    },
+    Wipe {
+        color: Vector4<f32>,
+        direction: WipeDirection,
+        amount: f32,
+    },
+    /// Draws the captured frame of the world.
+    Crossfade {
+        opacity: f32,
+    },
+}
+
+struct Playing {
+    transition: ScreenTransition,
+    /// Time since the transition started, then since the states switched.
+    elapsed: time::Duration,
+    /// Frames since the transition started.
+    frames: u32,
+    /// State changes waiting for the screen to be covered, None once they
+    /// are applied.
+    pending: Option<Vec<Transition>>,
+}
+
+impl Playing {
+    /// Returns the time elapsed in the current phase, from 0 to 1.
+    fn phase_time(&self) -> f32 {
+        let (cover, uncover) = self.transition.phases();
+        let duration = match self.pending {
+            Some(_) => cover,
+            None => uncover,
+        };
+        if duration.is_zero() {
+            return 1.0;
+        }
+        (self.elapsed.as_secs_f32() / duration.as_secs_f32()).min(1.0)
+    }
+}
+
+/// Delays the state changes until the screen is covered by the transition,
+/// and tells which effect to draw every frame.
+#[derive(Default)]
+pub(crate) struct TransitionPlayer {
+    /// Transition played on the next switches.
+    transition: Option<ScreenTransition>,
+    playing: Option<Playing>,
+    /// State changes applied on the next update.
+    ready: Vec<Transition>,
+}
+
+impl TransitionPlayer {
+    pub fn new(transition: Option<ScreenTransition>) -> Self {
+        Self {
+            transition,
+            ..Default::default()
+        }
+    }
+
+    /// Changes the transition played on the next switches. The playing one,
+    /// if any, goes on.
+    pub fn set_transition(&mut self, transition: Option<ScreenTransition>) {
+        self.transition = transition;
+    }
+
+    /// Queues the state changes requested during a frame, starting the
+    /// transition if none is playing.
+    pub fn request(&mut self, changes: Vec<Transition>) {
+        if changes.is_empty() {
+            return;
+        }
+        match &mut self.playing {
+            Some(Playing {
+                pending: Some(pending),
+                ..
+            }) => pending.extend(changes),
+            Some(_) => self.ready.extend(changes),
+            None => match self.transition {
+                Some(transition) => {
+                    self.playing = Some(Playing {
+                        transition,
+                        elapsed: time::Duration::ZERO,
+                        frames: 0,
+                        pending: Some(changes),
+                    })
+                }
+                None => self.ready.extend(changes),
+            },
+        }
+    }
+
+    /// Advances the transition by the time of the frame. Returns the state
+    /// changes to apply during this frame.
+    pub fn update(&mut self, frame_time: time::Duration) -> Vec<Transition> {
+        let mut changes = mem::take(&mut self.ready);
+        let Some(playing) = &mut self.playing else {
+            return changes;
+        };
+        playing.elapsed += frame_time;
+        playing.frames += 1;
+
+        let (cover, uncover) = playing.transition.phases();
+        let covered = match playing.transition.effect {
+            // NOTE: the world is captured by the frame the transition starts in
+            TransitionEffect::Crossfade => playing.frames > 1,
+            _ => playing.elapsed >= cover,
+        };
+        if covered {
+            if let Some(pending) = playing.pending.take() {
+                changes.extend(pending);
+                playing.elapsed = time::Duration::ZERO;
+            }
+        }
+        if playing.pending.is_none() && playing.elapsed >= uncover {
+            self.playing = None;
+        }
+        changes
+    }
+
+    /// Returns true if the world must be drawn to a target this frame, for a
+    /// crossfade to capture it.
+    pub fn is_capturing(&self) -> bool {
+        matches!(
+            self.playing,
+            Some(Playing {
+                transition: ScreenTransition {
+                    effect: TransitionEffect::Crossfade,
+                    ..
+                },
+                pending: Some(_),
+                ..
+            })
+        )
+    }
+
+    /// Returns the effect drawn over the world this frame, if any.
+    pub fn overlay(&self) -> Option<Overlay> {
+        let playing = self.playing.as_ref()?;
+        let easing = playing.transition.easing;
+        let time = playing.phase_time();
+        let covering = playing.pending.is_some();
+        // NOTE: the screen is uncovered as it was covered, backwards in time
+        let amount = if covering {
+            easing.apply(time)
+        } else {
+            easing.apply(1.0 - time)
+        };
+        let overlay = match playing.transition.effect {
+            TransitionEffect::Fade(color) => Overlay::Fade { color, amount },
+            TransitionEffect::Wipe { color, direction } if covering => Overlay::Wipe {
+                color,
+                direction,
+                amount,
+            },
+            // NOTE: the color moves on, uncovering the side it covered first
+            TransitionEffect::Wipe { color, direction } => Overlay::Wipe {
+                color,
+                direction: opposite(direction),
+                amount,
+            },
+            TransitionEffect::Crossfade if covering => return None,
+            TransitionEffect::Crossfade => Overlay::Crossfade {
+                opacity: 1.0 - easing.apply(time),
+            },
+        };
+        Some(overlay)
+    }
+}
+
+fn opposite(direction: WipeDirection) -> WipeDirection {
+    match direction {
+        WipeDirection::Left => WipeDirection::Right,
+        WipeDirection::Right => WipeDirection::Left,
+        WipeDirection::Up => WipeDirection::Down,
+        WipeDirection::Down => WipeDirection::Up,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FRAME: time::Duration = time::Duration::from_millis(250);
+
+    fn player(effect: TransitionEffect) -> TransitionPlayer {
+        TransitionPlayer::new(Some(ScreenTransition::new(
+            effect,
+            time::Duration::from_secs(1),
+        )))
+    }
+
+    #[test]
+    fn states_switch_once_the_screen_is_covered() {
+        let black = Vector4::new(0.0, 0.0, 0.0, 1.0);
+        let mut player = player(TransitionEffect::Fade(black));
+
+        player.request(vec![Transition::Pop]);
+        assert!(player.update(FRAME).is_empty());
+        assert_eq!(
+            player.overlay(),
+            Some(Overlay::Fade {
+                color: black,
+                amount: 0.5
+            })
+        );
+
+        // states switched during the fade out wait for it
+        player.request(vec![Transition::Pop]);
+        assert_eq!(player.update(FRAME).len(), 2);
+        assert_eq!(
+            player.overlay(),
+            Some(Overlay::Fade {
+                color: black,
+                amount: 1.0
+            })
+        );
+
+        // states switched during the fade in switch right away
+        player.request(vec![Transition::Pop]);
+        assert_eq!(player.update(FRAME).len(), 1);
+        assert_eq!(
+            player.overlay(),
+            Some(Overlay::Fade {
+                color: black,
+                amount: 0.5
+            })
+        );
+        assert!(player.update(FRAME).is_empty());
+        assert_eq!(player.overlay(), None);
+
+        player.set_transition(None);
+        player.request(vec![Transition::Pop]);
+        assert_eq!(player.update(FRAME).len(), 1);
+        assert_eq!(player.overlay(), None);
+    }
+
+    #[test]
+    fn crossfade_captures_a_frame_before_switching() {
+        let mut player = player(TransitionEffect::Crossfade);
+
+        player.request(vec![Transition::Pop]);
+        assert!(player.update(FRAME).is_empty());
+        assert!(player.is_capturing());
+        assert_eq!(player.overlay(), None);
+
+        assert_eq!(player.update(FRAME).len(), 1);
+        assert!(!player.is_capturing());
+        assert_eq!(player.overlay(), Some(Overlay::Crossfade { opacity: 1.0 }));
+
+        player.update(FRAME * 2);
+        assert_eq!(player.overlay(), Some(Overlay::Crossfade { opacity: 0.5 }));
+        player.update(FRAME * 2);
+        assert_eq!(player.overlay(), None);
+    }
+
+    #[test]
+    fn wipe_uncovers_the_side_it_covered_first() {
+        let color = Vector4::new(1.0, 1.0, 1.0, 1.0);
+        let mut player = player(TransitionEffect::Wipe {
+            color,
+            direction: WipeDirection::Right,
+        });
+
+        player.request(vec![Transition::Pop]);
+        player.update(FRAME);
+        assert_eq!(
+            player.overlay(),
+            Some(Overlay::Wipe {
+                color,
+                direction: WipeDirection::Right,
+                amount: 0.5
+            })
+        );
+        player.update(FRAME);
+        player.update(FRAME);
+        assert_eq!(
+            player.overlay(),
+            Some(Overlay::Wipe {
+                color,
+                direction: WipeDirection::Left,
+                amount: 0.5
+            })
+        );
+    }
+}
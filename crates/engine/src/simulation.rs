@@ -0,0 +1,79 @@
+//! Systems advancing the objects of the world every frame by the game time,
+//! shared by the engine and the headless engine.
+
+use core::ecs::World;
+use core::sprite::AnimatedSprite;
+use std::time;
+
+use crate::alloc_audit::{self, Subsystem};
+use crate::collision::{CollisionEvent, CollisionSystem};
+use crate::events::EventBus;
+use crate::movement::{self, FixedTimestep};
+#[cfg(feature = "physics")]
+use crate::physics::{PhysicsSettings, PhysicsSystem};
+use crate::stress::{StressScene, StressSceneGenerator};
+use crate::tween::TweenSystem;
+
+pub(crate) struct Simulation {
+    fixed_timestep: FixedTimestep,
+    #[cfg(feature = "physics")]
+    physics: PhysicsSystem,
+    collisions: CollisionSystem,
+    tweens: TweenSystem,
+    stress_scene: Option<StressSceneGenerator>,
+}
+
+impl Simulation {
+    pub fn new(
+        collision_cell_size: Option<f32>,
+        #[cfg(feature = "physics")] physics: PhysicsSettings,
+    ) -> Self {
+        Self {
+            fixed_timestep: FixedTimestep::default(),
+            #[cfg(feature = "physics")]
+            physics: PhysicsSystem::new(physics),
+            collisions: CollisionSystem::new(collision_cell_size),
+            tweens: TweenSystem::default(),
+            stress_scene: None,
+        }
+    }
+
+    /// Spawns the objects of a stress scene, animated by `update()`.
+    pub fn spawn_stress_scene(&mut self, scene: StressScene, world: &mut World) {
+        self.stress_scene = Some(StressSceneGenerator::spawn(scene, world));
+    }
+
+    /// Moves and animates the objects by the game time elapsed since the last
+    /// frame. Returns the collisions of the objects once they moved.
+    pub fn update(
+        &mut self,
+        world: &mut World,
+        delta_time: time::Duration,
+        events: &mut EventBus,
+    ) -> &[CollisionEvent] {
+        profiling::scope!("Simulation.update");
+        let _scope = alloc_audit::scope(Subsystem::Engine);
+
+        // move the objects in fixed steps
+        for _ in 0..self.fixed_timestep.steps(delta_time) {
+            movement::step(world);
+            #[cfg(feature = "physics")]
+            self.physics.step(world);
+        }
+
+        // animate the stress scene
+        if let Some(stress_scene) = self.stress_scene.as_mut() {
+            stress_scene.on_update(world, delta_time);
+        }
+
+        // animate the tweens of the objects
+        self.tweens.on_update(world, delta_time, events);
+
+        // advance the sprite animations
+        for sprite in world.query_mut::<&mut AnimatedSprite>() {
+            sprite.advance(delta_time);
+        }
+
+        self.collisions.on_update(world)
+    }
+}
@@ -0,0 +1,209 @@
+//! Textures of the sprite atlases, loaded when the engine starts into the
+//! bindless texture array sampled by the 2D renderers. Each texture is
+//! imported using the settings of its `.meta` sidecar, see
+//! `core::texture::TextureSettings`.
+
+use std::path::{Path, PathBuf};
+
+use core::texture::{TextureFilter, TextureSettings, TextureWrap};
+
+use ash::vk;
+use image::{Rgba, RgbaImage};
+use log::warn;
+use vulkan_renderer::bindless::BindlessTextures;
+use vulkan_renderer::deletion::Resource;
+use vulkan_renderer::device::Device;
+use vulkan_renderer::error::Result;
+use vulkan_renderer::image::Image;
+use vulkan_renderer::texture::{Sampler, Texture};
+
+/// Texture array holding the atlases of the sprites, the n-th texture added
+/// using `EngineBuilder::with_sprite_texture()` being `SpriteAtlas::texture` n.
+pub(crate) struct SpriteTextures {
+    array: BindlessTextures,
+    // NOTE: the textures must outlive their slot of the array
+    _textures: Vec<Texture>,
+}
+
+impl SpriteTextures {
+    /// Loads the textures in order. Textures that can not be read are
+    /// replaced by a white one, so that their sprites keep their color, and
+    /// sidecars that can not be read are ignored.
+    pub(crate) unsafe fn load(
+        device: &Device,
+        paths: &[PathBuf],
+        frames_in_flight: u32,
+    ) -> Result<Self> {
+        let mut array = BindlessTextures::new(device, paths.len() as u32, frames_in_flight)?;
+        if array.capacity() < paths.len() as u32 {
+            return Err(format!(
+                "{} sprite textures exceed the limit of {}",
+                paths.len(),
+                array.capacity()
+            )
+            .into());
+        }
+
+        let command_pool = device.create_command_pool()?;
+        let mut textures = Vec::with_capacity(paths.len());
+        for path in paths {
+            let settings = TextureSettings::load_for(path).unwrap_or_else(|e| {
+                warn!("load import settings of {}: {e}", path.display());
+                TextureSettings::default()
+            });
+            let texture = upload(device, command_pool, &decode(path, &settings), &settings)?;
+            let index = array.insert(device, &texture)?;
+            debug_assert_eq!(
+                index.0 as usize,
+                textures.len(),
+                "slots are allocated in order"
+            );
+            textures.push(texture);
+        }
+        // NOTE: the command buffers of the uploads are freed before the pool
+        device.defer_destroy(Resource::CommandPool(command_pool));
+
+        Ok(Self {
+            array,
+            _textures: textures,
+        })
+    }
+
+    /// Returns the array the 2D renderers draw the sprites from, see
+    /// `Renderer2DSystem::set_textures()`.
+    pub(crate) fn array(&self) -> &BindlessTextures {
+        &self.array
+    }
+}
+
+/// Decodes the image of a texture, or a white pixel if it can not be read.
+fn decode(path: &Path, settings: &TextureSettings) -> RgbaImage {
+    let mut image = match image::open(path) {
+        Ok(image) => image.into_rgba8(),
+        Err(e) => {
+            warn!("load sprite texture {}: {e}", path.display());
+            return RgbaImage::from_pixel(1, 1, Rgba([255; 4]));
+        }
+    };
+    if settings.premultiplied_alpha {
+        unpremultiply(&mut image);
+    }
+    image
+}
+
+/// Divides the colors by their alpha, since the 2D renderers blend colors
+/// that are not premultiplied.
+fn unpremultiply(image: &mut RgbaImage) {
+    for Rgba([r, g, b, a]) in image.pixels_mut() {
+        if *a == 0 {
+            continue;
+        }
+        for channel in [r, g, b] {
+            *channel = ((*channel as u32 * 255 + *a as u32 / 2) / *a as u32).min(255) as u8;
+        }
+    }
+}
+
+unsafe fn upload(
+    device: &Device,
+    command_pool: vk::CommandPool,
+    image: &RgbaImage,
+    settings: &TextureSettings,
+) -> Result<Texture> {
+    let format = if settings.srgb {
+        vk::Format::R8G8B8A8_SRGB
+    } else {
+        vk::Format::R8G8B8A8_UNORM
+    };
+    let create_info = vk::ImageCreateInfo::builder()
+        .image_type(vk::ImageType::TYPE_2D)
+        .format(format)
+        .extent(vk::Extent3D {
+            width: image.width(),
+            height: image.height(),
+            depth: 1,
+        })
+        .mip_levels(1)
+        .array_layers(1)
+        .samples(vk::SampleCountFlags::TYPE_1)
+        .tiling(vk::ImageTiling::OPTIMAL)
+        .usage(vk::ImageUsageFlags::TRANSFER_DST | vk::ImageUsageFlags::SAMPLED)
+        .sharing_mode(vk::SharingMode::EXCLUSIVE);
+    let mut gpu_image = Image::new(device, *create_info, vk::MemoryPropertyFlags::DEVICE_LOCAL)?;
+    device.set_object_name(*gpu_image, "sprite texture");
+    gpu_image.upload_gpu(device, command_pool, image.as_raw())?;
+    let sampler = Sampler::new(device, sampler_info(settings))?;
+    Texture::new(device, gpu_image, sampler)
+}
+
+/// Returns the sampler of `Sampler::basic()`, using the filter and wrap of the
+/// settings.
+fn sampler_info(settings: &TextureSettings) -> vk::SamplerCreateInfo {
+    let (filter, mipmap_mode) = match settings.filter {
+        TextureFilter::Linear => (vk::Filter::LINEAR, vk::SamplerMipmapMode::LINEAR),
+        TextureFilter::Nearest => (vk::Filter::NEAREST, vk::SamplerMipmapMode::NEAREST),
+    };
+    let address_mode = match settings.wrap {
+        TextureWrap::Repeat => vk::SamplerAddressMode::REPEAT,
+        TextureWrap::MirroredRepeat => vk::SamplerAddressMode::MIRRORED_REPEAT,
+        TextureWrap::Clamp => vk::SamplerAddressMode::CLAMP_TO_EDGE,
+    };
+    *vk::SamplerCreateInfo::builder()
+        .mag_filter(filter)
+        .min_filter(filter)
+        .address_mode_u(address_mode)
+        .address_mode_v(address_mode)
+        .address_mode_w(address_mode)
+        .anisotropy_enable(false)
+        .max_anisotropy(1.0)
+        .border_color(vk::BorderColor::INT_OPAQUE_BLACK)
+        .unnormalized_coordinates(false)
+        .compare_enable(false)
+        .compare_op(vk::CompareOp::ALWAYS)
+        .mipmap_mode(mipmap_mode)
+        .mip_lod_bias(0.0)
+        .min_lod(0.0)
+        .max_lod(vk::LOD_CLAMP_NONE)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unreadable_textures_are_white() {
+        let settings = TextureSettings {
+            premultiplied_alpha: true,
+            ..Default::default()
+        };
+        let image = decode(Path::new("does/not/exist.png"), &settings);
+        assert_eq!(image.dimensions(), (1, 1));
+        assert_eq!(image.get_pixel(0, 0), &Rgba([255; 4]));
+    }
+
+    #[test]
+    fn premultiplied_colors_are_divided_by_their_alpha() {
+        let mut image = RgbaImage::from_fn(3, 1, |x, _| match x {
+            0 => Rgba([64, 32, 0, 128]),
+            1 => Rgba([10, 20, 30, 0]),
+            _ => Rgba([200, 100, 50, 255]),
+        });
+        unpremultiply(&mut image);
+        assert_eq!(image.get_pixel(0, 0), &Rgba([128, 64, 0, 128]));
+        assert_eq!(image.get_pixel(1, 0), &Rgba([10, 20, 30, 0]));
+        assert_eq!(image.get_pixel(2, 0), &Rgba([200, 100, 50, 255]));
+    }
+
+    #[test]
+    fn samplers_follow_the_settings() {
+        let info = sampler_info(&TextureSettings {
+            filter: TextureFilter::Nearest,
+            wrap: TextureWrap::Clamp,
+            ..Default::default()
+        });
+        assert_eq!(info.mag_filter, vk::Filter::NEAREST);
+        assert_eq!(info.min_filter, vk::Filter::NEAREST);
+        assert_eq!(info.address_mode_u, vk::SamplerAddressMode::CLAMP_TO_EDGE);
+        assert_eq!(info.address_mode_v, vk::SamplerAddressMode::CLAMP_TO_EDGE);
+    }
+}
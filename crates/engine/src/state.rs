@@ -0,0 +1,195 @@
+//! Stack of game states, e.g. a menu, the game and a pause screen over it.
+//!
+//! Only the state on top of the stack is updated and receives the events,
+//! after the application. States are pushed, popped and replaced through the
+//! application context, e.g. `ApplicationContext::push_state()`, once the
+//! update of the frame is done, or once the screen is covered when a screen
+//! transition is set, see `screen_transition`.
+
+use log::{debug, warn};
+use winit::event::Event;
+
+use crate::engine::ApplicationContext;
+#[cfg(feature = "imgui")]
+use crate::imgui;
+
+pub trait State {
+    /// Returns the name of the state, for logs.
+    fn name(&self) -> &str {
+        std::any::type_name::<Self>()
+    }
+    /// Returns true if the states below are still drawn, e.g. for a pause
+    /// screen over the game. They are not updated.
+    fn is_overlay(&self) -> bool {
+        false
+    }
+    /// Called when the state is pushed on the stack, e.g. to spawn its
+    /// objects.
+    fn on_enter(&mut self, _ctx: ApplicationContext) {}
+    /// Called when the state is popped or replaced, e.g. to remove its
+    /// objects, and at shutdown.
+    fn on_exit(&mut self, _ctx: ApplicationContext) {}
+    /// Called when another state is pushed over this one.
+    fn on_pause(&mut self, _ctx: ApplicationContext) {}
+    /// Called when this state is back on top of the stack.
+    fn on_resume(&mut self, _ctx: ApplicationContext) {}
+    /// Called every frame the state is on top, after the update of the
+    /// application.
+    fn on_update(&mut self, _ctx: ApplicationContext) {}
+    /// Called for every event of the event loop while the state is on top,
+    /// after the application.
+    fn on_event(&mut self, _event: &Event<()>, _ctx: ApplicationContext) {}
+    /// Builds the UI of the state, drawn over the UI of the application and
+    /// of the states below. Called every frame the state is visible.
+    #[cfg(feature = "imgui")]
+    fn on_render_ui(&mut self, _ui: &mut imgui::Ui) {}
+}
+
+/// Change of the stack requested by the application.
+pub(crate) enum Transition {
+    Push(Box<dyn State>),
+    Pop,
+    Replace(Box<dyn State>),
+}
+
+/// Hook of a state called when the stack changes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Hook {
+    Enter,
+    Exit,
+    Pause,
+    Resume,
+}
+
+impl Hook {
+    pub fn call(self, state: &mut dyn State, ctx: ApplicationContext) {
+        match self {
+            Self::Enter => state.on_enter(ctx),
+            Self::Exit => state.on_exit(ctx),
+            Self::Pause => state.on_pause(ctx),
+            Self::Resume => state.on_resume(ctx),
+        }
+    }
+}
+
+#[derive(Default)]
+pub(crate) struct StateStack {
+    /// States from the bottom of the stack.
+    states: Vec<Box<dyn State>>,
+}
+
+impl StateStack {
+    pub fn top_mut(&mut self) -> Option<&mut Box<dyn State>> {
+        self.states.last_mut()
+    }
+
+    /// Returns the states drawn, from the bottom: the top one and the ones
+    /// below the overlays.
+    #[cfg_attr(not(feature = "imgui"), allow(dead_code))]
+    pub fn visible_mut(&mut self) -> impl Iterator<Item = &mut Box<dyn State>> {
+        let bottom = self
+            .states
+            .iter()
+            .rposition(|state| !state.is_overlay())
+            .unwrap_or(0);
+        self.states[bottom..].iter_mut()
+    }
+
+    /// Changes the stack, calling the hooks of the states involved with
+    /// `call`.
+    pub fn apply(&mut self, transition: Transition, mut call: impl FnMut(&mut dyn State, Hook)) {
+        match transition {
+            Transition::Push(mut state) => {
+                if let Some(top) = self.top_mut() {
+                    call(top.as_mut(), Hook::Pause);
+                }
+                debug!("enter state {}", state.name());
+                call(state.as_mut(), Hook::Enter);
+                self.states.push(state);
+            }
+            Transition::Pop => {
+                let Some(mut state) = self.states.pop() else {
+                    warn!("no state to pop");
+                    return;
+                };
+                debug!("exit state {}", state.name());
+                call(state.as_mut(), Hook::Exit);
+                if let Some(top) = self.top_mut() {
+                    call(top.as_mut(), Hook::Resume);
+                }
+            }
+            Transition::Replace(mut state) => {
+                if let Some(mut previous) = self.states.pop() {
+                    debug!("exit state {}", previous.name());
+                    call(previous.as_mut(), Hook::Exit);
+                }
+                debug!("enter state {}", state.name());
+                call(state.as_mut(), Hook::Enter);
+                self.states.push(state);
+            }
+        }
+    }
+
+    /// Pops every state, from the top.
+    pub fn clear(&mut self, mut call: impl FnMut(&mut dyn State, Hook)) {
+        while let Some(mut state) = self.states.pop() {
+            debug!("exit state {}", state.name());
+            call(state.as_mut(), Hook::Exit);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Named(&'static str, bool);
+
+    impl State for Named {
+        fn name(&self) -> &str {
+            self.0
+        }
+
+        fn is_overlay(&self) -> bool {
+            self.1
+        }
+    }
+
+    #[test]
+    fn transitions_call_the_hooks_in_order() {
+        let mut stack = StateStack::default();
+        let mut calls = Vec::new();
+        let mut apply = |stack: &mut StateStack, transition: Transition| {
+            stack.apply(transition, |state, hook| {
+                calls.push(format!("{} {hook:?}", state.name()));
+            });
+        };
+        apply(&mut stack, Transition::Push(Box::new(Named("menu", false))));
+        apply(
+            &mut stack,
+            Transition::Replace(Box::new(Named("game", false))),
+        );
+        apply(&mut stack, Transition::Push(Box::new(Named("pause", true))));
+
+        let visible = stack.visible_mut().map(|state| state.name().to_string());
+        assert_eq!(visible.collect::<Vec<_>>(), ["game", "pause"]);
+
+        apply(&mut stack, Transition::Pop);
+        apply(&mut stack, Transition::Pop);
+        apply(&mut stack, Transition::Pop);
+        assert!(stack.top_mut().is_none());
+        assert_eq!(
+            calls,
+            [
+                "menu Enter",
+                "menu Exit",
+                "game Enter",
+                "game Pause",
+                "pause Enter",
+                "pause Exit",
+                "game Resume",
+                "game Exit",
+            ]
+        );
+    }
+}
@@ -0,0 +1,229 @@
+//! Built-in stress scene exercising the renderer with many quads.
+//!
+//! The scene is generated from a seed, so that a given set of options creates
+//! the same workload on every machine. Quads are spread over the center of
+//! the view. Animated quads orbit around their spawn point, decorated quads
+//! have an outline or a drop shadow, drawn as extra quads.
+
+use core::component::Transform;
+use core::ecs::World;
+use core::object::{GameObject, ObjectId};
+use std::f32::consts::TAU;
+use std::time;
+
+use cgmath::{Vector2, Vector3, Vector4};
+use log::info;
+
+/// Seed used by `StressScene::new()`.
+pub const DEFAULT_STRESS_SEED: u64 = 0x5eed;
+
+/// Half the size of the square the quads are spawned in, in world units.
+const SPAWN_EXTENT: f32 = 0.9;
+
+/// Options of the stress scene.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct StressScene {
+    /// Number of objects spawned.
+    pub quads: u32,
+    /// Fraction of the objects moving every frame, in [0, 1].
+    pub animated: f32,
+    /// Fraction of the objects drawn with an outline or a drop shadow, in
+    /// [0, 1].
+    pub decorated: f32,
+    pub seed: u64,
+}
+
+impl StressScene {
+    /// Creates a scene of static and animated quads, a tenth of them
+    /// decorated.
+    pub fn new(quads: u32) -> Self {
+        Self {
+            quads,
+            animated: 0.5,
+            decorated: 0.1,
+            seed: DEFAULT_STRESS_SEED,
+        }
+    }
+}
+
+/// Circular motion of an animated quad, in world units.
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Orbit {
+    center: Vector2<f32>,
+    radius: f32,
+    /// Angular speed, in radians per second.
+    speed: f32,
+    phase: f32,
+}
+
+impl Orbit {
+    fn position(&self, time: f32) -> Vector2<f32> {
+        let angle = self.phase + self.speed * time;
+        self.center + Vector2::new(angle.cos(), angle.sin()) * self.radius
+    }
+}
+
+/// Objects of the stress scene, animated every frame.
+pub(crate) struct StressSceneGenerator {
+    animated: Vec<(ObjectId, Orbit)>,
+    /// Seconds since the scene was spawned.
+    time: f32,
+}
+
+impl StressSceneGenerator {
+    /// Adds the objects of the scene.
+    pub fn spawn(scene: StressScene, world: &mut World) -> Self {
+        let mut animated = Vec::new();
+        for (object, orbit) in generate(scene) {
+            let id = world.spawn(object);
+            if let Some(orbit) = orbit {
+                animated.push((id, orbit));
+            }
+        }
+        info!(
+            "stress scene: {} quads, {} animated",
+            scene.quads,
+            animated.len()
+        );
+        Self {
+            animated,
+            time: 0.0,
+        }
+    }
+
+    /// Moves the animated objects still in the scene.
+    pub fn on_update(&mut self, world: &mut World, delta_time: time::Duration) {
+        self.time += delta_time.as_secs_f32();
+        for (id, orbit) in &self.animated {
+            if let Some(transform) = world.get_mut::<Transform>(*id) {
+                set_world_position(transform, orbit.position(self.time));
+            }
+        }
+    }
+}
+
+/// Returns the objects of the scene, along with the orbit of the animated
+/// ones.
+fn generate(scene: StressScene) -> Vec<(GameObject, Option<Orbit>)> {
+    let mut rng = Rng::new(scene.seed);
+
+    // shrink the quads as their number grows, to keep most of them visible
+    let size = (2.0 * SPAWN_EXTENT / (scene.quads.max(1) as f32).sqrt()).clamp(0.002, 0.1);
+
+    (0..scene.quads)
+        .map(|_| {
+            let center = Vector2::new(
+                rng.range(-SPAWN_EXTENT, SPAWN_EXTENT),
+                rng.range(-SPAWN_EXTENT, SPAWN_EXTENT),
+            );
+            let scale = Vector3::new(size * rng.range(0.5, 1.5), size * rng.range(0.5, 1.5), 1.0);
+            let color = Vector4::new(rng.next_f32(), rng.next_f32(), rng.next_f32(), 1.0);
+            let mut object = GameObject::new().with_scale(scale).with_color(color);
+            object.transform.position.z = 1.0;
+
+            if rng.next_f32() < scene.decorated {
+                let decoration = Vector4::new(0.0, 0.0, 0.0, 0.6);
+                object = if rng.next_f32() < 0.5 {
+                    object.with_outline(decoration, size * 0.2)
+                } else {
+                    object.with_drop_shadow(decoration, Vector2::new(size * 0.2, -size * 0.2))
+                };
+            }
+
+            let orbit = (rng.next_f32() < scene.animated).then(|| Orbit {
+                center,
+                radius: size * rng.range(1.0, 4.0),
+                speed: rng.range(-TAU, TAU),
+                phase: rng.range(0.0, TAU),
+            });
+            set_world_position(
+                &mut object.transform,
+                orbit.map_or(center, |o| o.position(0.0)),
+            );
+
+            (object, orbit)
+        })
+        .collect()
+}
+
+/// Places the center of the quad at a world position.
+// NOTE: quads are scaled after being translated, see QuadBatcher
+fn set_world_position(transform: &mut Transform, position: Vector2<f32>) {
+    let scale = transform.scale;
+    transform.position.x = position.x / scale.x;
+    transform.position.y = position.y / scale.y;
+}
+
+/// SplitMix64 generator, giving the same sequence on every platform.
+struct Rng(u64);
+
+impl Rng {
+    fn new(seed: u64) -> Self {
+        Self(seed)
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// Returns a number in [0, 1).
+    fn next_f32(&mut self) -> f32 {
+        (self.next_u64() >> 40) as f32 / (1u64 << 24) as f32
+    }
+
+    fn range(&mut self, min: f32, max: f32) -> f32 {
+        min + (max - min) * self.next_f32()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stress_scene_is_reproducible() {
+        let scene = StressScene::new(1000);
+        let objects = generate(scene);
+        assert_eq!(objects.len(), 1000);
+        assert_eq!(objects, generate(scene));
+
+        let other = generate(StressScene { seed: 1, ..scene });
+        assert_ne!(objects, other);
+
+        // roughly the requested mix
+        let animated = objects.iter().filter(|(_, o)| o.is_some()).count();
+        assert!((400..600).contains(&animated), "{animated} animated");
+        let decorated = objects
+            .iter()
+            .filter(|(o, _)| o.outline.is_some() || o.shadow.is_some())
+            .count();
+        assert!((50..150).contains(&decorated), "{decorated} decorated");
+    }
+
+    #[test]
+    fn stress_scene_animates_objects() {
+        let scene = StressScene {
+            animated: 1.0,
+            ..StressScene::new(10)
+        };
+        let mut world = World::new();
+        let mut generator = StressSceneGenerator::spawn(scene, &mut world);
+        let before: Vec<_> = world.query::<&Transform>().copied().collect();
+
+        generator.on_update(&mut world, time::Duration::from_millis(100));
+        let after: Vec<_> = world.query::<&Transform>().copied().collect();
+        assert_eq!(after.len(), 10);
+        assert!(before
+            .iter()
+            .zip(&after)
+            .all(|(b, a)| b.position != a.position));
+
+        // despawned objects are skipped
+        world.clear();
+        generator.on_update(&mut world, time::Duration::from_millis(100));
+    }
+}
@@ -0,0 +1,101 @@
+//! Built-in tween system, advancing the tweens of the objects every frame by
+//! the game time, see `core::tween`.
+//!
+//! Finished tweens are removed, and a `TweenFinished` event is sent to the
+//! event bus for each of them.
+
+use core::component::{Color, Transform};
+use core::ecs::{Entity, World};
+use core::object::ObjectId;
+use core::tween::{TweenProperty, Tweens};
+use std::time;
+
+use crate::events::EventBus;
+
+/// Sent to the event bus when a tween of an object finishes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TweenFinished {
+    pub object: ObjectId,
+    pub property: TweenProperty,
+}
+
+#[derive(Debug, Default)]
+pub(crate) struct TweenSystem {
+    /// Objects whose tweens all finished.
+    finished: Vec<ObjectId>,
+}
+
+impl TweenSystem {
+    pub fn on_update(
+        &mut self,
+        world: &mut World,
+        delta_time: time::Duration,
+        events: &mut EventBus,
+    ) {
+        for (entity, tweens, transform, color) in
+            world.query_mut::<(Entity, &mut Tweens, &mut Transform, &mut Color)>()
+        {
+            tweens.tweens.retain_mut(|tween| {
+                let finished = tween.advance(delta_time, transform, color);
+                if finished {
+                    events.send(TweenFinished {
+                        object: entity,
+                        property: tween.property(),
+                    });
+                }
+                !finished
+            });
+            if tweens.tweens.is_empty() {
+                self.finished.push(entity);
+            }
+        }
+        for entity in self.finished.drain(..) {
+            world.remove::<Tweens>(entity);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::object::GameObject;
+    use core::tween::{Repeat, Tween};
+
+    use cgmath::{Vector3, Vector4};
+
+    use super::*;
+    use crate::events::EventReader;
+
+    #[test]
+    fn finished_tweens_are_removed() {
+        let mut world = World::new();
+        let second = time::Duration::from_secs(1);
+        let object = world.spawn(GameObject::new());
+        world.insert(
+            object,
+            Tweens::new()
+                .with(Tween::scale(Vector3::new(2.0, 2.0, 1.0), second))
+                .with(Tween::color(Vector4::new(1.0, 0.0, 0.0, 1.0), second * 2)),
+        );
+        let looping = world.spawn(GameObject::new());
+        world.insert(
+            looping,
+            Tweens::from(
+                Tween::position(Vector3::new(1.0, 0.0, 0.0), second).with_repeat(Repeat::Loop),
+            ),
+        );
+
+        let mut tweens = TweenSystem::default();
+        let mut events = EventBus::new();
+        let mut reader = EventReader::<TweenFinished>::default();
+        tweens.on_update(&mut world, second, &mut events);
+        assert_eq!(world.get::<Tweens>(object).unwrap().tweens.len(), 1);
+        tweens.on_update(&mut world, second, &mut events);
+        assert!(!world.has::<Tweens>(object));
+        assert!(world.has::<Tweens>(looping));
+
+        let finished: Vec<_> = events.read(&mut reader).map(|e| e.property).collect();
+        assert_eq!(finished, [TweenProperty::Scale, TweenProperty::Color]);
+        let transform = world.get::<Transform>(object).unwrap();
+        assert_eq!(transform.scale, Vector3::new(2.0, 2.0, 1.0));
+    }
+}
@@ -1,9 +1,20 @@
+mod map;
+
 use std::collections::HashMap;
 
+pub use map::{AxisDirection, Binding, InputMap, InputMapError, ScrollDirection, AXIS_DEAD_ZONE};
+pub use winit::event::ModifiersState;
 use winit::event::{
-    DeviceEvent, ElementState, Event, KeyboardInput, MouseScrollDelta, VirtualKeyCode, WindowEvent,
+    DeviceEvent, ElementState, Event, Ime, KeyboardInput, MouseButton, MouseScrollDelta,
+    VirtualKeyCode, WindowEvent,
 };
 
+/// Number of pixels scrolled by touchpads counted as a line, so that both
+/// kinds of scroll deltas add up.
+pub const PIXELS_PER_SCROLL_LINE: f32 = 20.0;
+
+// NOTE: amount scrolled during the frame, in lines, positive when scrolling
+//       up or right
 #[derive(Default, Debug)]
 struct ScrollState {
     x: f32,
@@ -15,7 +26,22 @@ pub struct InputSystem {
     focused: bool,
 
     keyboard: HashMap<VirtualKeyCode, ElementState>,
+    modifiers: ModifiersState,
+    mouse_buttons: HashMap<MouseButton, ElementState>,
+    // NOTE: last value of the analog axes of the input devices, e.g. gamepad
+    //       sticks, by axis id
+    axes: HashMap<u32, f32>,
     scroll_state: ScrollState,
+    // NOTE: raw motion reported by the device, not clamped by the edges of
+    //       the screen nor affected by cursor acceleration
+    mouse_delta: (f32, f32),
+    // NOTE: characters typed or committed by the IME since the start of the
+    //       event batch
+    text: String,
+    ime_preedit: Option<(String, Option<(usize, usize)>)>,
+    // NOTE: set while the UI uses the mouse or keyboard
+    mouse_captured: bool,
+    keyboard_captured: bool,
 }
 
 impl InputSystem {
@@ -23,8 +49,24 @@ impl InputSystem {
         Default::default()
     }
 
+    /// Ignores the presses of mouse buttons, scroll and mouse motion while
+    /// the mouse is captured, e.g. by the UI. Buttons are still released.
+    pub fn set_mouse_captured(&mut self, captured: bool) {
+        self.mouse_captured = captured;
+    }
+
+    /// Ignores key presses and text while the keyboard is captured, e.g. by
+    /// the UI. Keys are still released.
+    pub fn set_keyboard_captured(&mut self, captured: bool) {
+        self.keyboard_captured = captured;
+    }
+
+    /// Clears the state accumulated during a frame: scroll, mouse motion and
+    /// text.
     pub fn reset(&mut self) {
         self.scroll_state = ScrollState::default();
+        self.mouse_delta = (0.0, 0.0);
+        self.text.clear();
     }
 
     pub fn on_event(&mut self, event: &Event<()>) {
@@ -38,7 +80,13 @@ impl InputSystem {
             // when losing focus, reset states
             if !f {
                 self.keyboard.clear();
+                self.modifiers = ModifiersState::empty();
+                self.mouse_buttons.clear();
+                self.axes.clear();
                 self.scroll_state = ScrollState::default();
+                self.mouse_delta = (0.0, 0.0);
+                self.text.clear();
+                self.ime_preedit = None;
             }
             return;
         }
@@ -48,10 +96,18 @@ impl InputSystem {
             return;
         }
 
+        // leave the input captured by the UI to it
+        if self.is_captured(event) {
+            return;
+        }
+
         #[allow(clippy::single_match)]
         #[allow(clippy::collapsible_match)]
         match event {
-            Event::MainEventsCleared => self.reset(),
+            // NOTE: the state of the frame is accumulated from the start of
+            //       the event batch, so that it is complete when the frame is
+            //       updated on MainEventsCleared
+            Event::NewEvents(_) => self.reset(),
             Event::WindowEvent { ref event, .. } => match *event {
                 // handle keys
                 WindowEvent::KeyboardInput {
@@ -67,19 +123,48 @@ impl InputSystem {
                         self.keyboard.insert(keycode, state);
                     }
                 }
+                WindowEvent::ModifiersChanged(modifiers) => self.modifiers = modifiers,
+                // handle text input
+                WindowEvent::ReceivedCharacter(c) => self.text.push(c),
+                WindowEvent::Ime(ref ime) => match ime {
+                    Ime::Preedit(text, cursor) if !text.is_empty() => {
+                        self.ime_preedit = Some((text.clone(), *cursor));
+                    }
+                    Ime::Preedit(..) | Ime::Disabled => self.ime_preedit = None,
+                    Ime::Commit(text) => {
+                        self.text.push_str(text);
+                        self.ime_preedit = None;
+                    }
+                    Ime::Enabled => {}
+                },
+                // handle mouse buttons
+                WindowEvent::MouseInput { state, button, .. } => {
+                    self.mouse_buttons.insert(button, state);
+                }
+                // handle mouse scroll, in lines for wheels and in pixels for
+                // touchpads
+                WindowEvent::MouseWheel { delta, .. } => {
+                    let (x, y) = match delta {
+                        MouseScrollDelta::LineDelta(x, y) => (x, y),
+                        MouseScrollDelta::PixelDelta(position) => (
+                            position.x as f32 / PIXELS_PER_SCROLL_LINE,
+                            position.y as f32 / PIXELS_PER_SCROLL_LINE,
+                        ),
+                    };
+                    self.scroll_state.x += x;
+                    self.scroll_state.y += y;
+                }
                 _ => {}
             },
             Event::DeviceEvent { ref event, .. } => match *event {
-                // handle mouse scroll
-                DeviceEvent::MouseWheel {
-                    delta: MouseScrollDelta::LineDelta(delta_x, delta_y),
-                } => {
-                    if delta_x != 0.0 {
-                        self.scroll_state.x = delta_x.signum();
-                    }
-                    if delta_y != 0.0 {
-                        self.scroll_state.y = delta_y.signum();
-                    }
+                // handle mouse motion
+                DeviceEvent::MouseMotion { delta: (x, y) } => {
+                    self.mouse_delta.0 += x as f32;
+                    self.mouse_delta.1 += y as f32;
+                }
+                // handle analog axes
+                DeviceEvent::Motion { axis, value } => {
+                    self.axes.insert(axis, value as f32);
                 }
                 _ => {}
             },
@@ -94,6 +179,41 @@ impl InputSystem {
         }
     }
 
+    /// Returns true if the event is left to the UI. Releases always go
+    /// through, so that no key or button stays pressed.
+    fn is_captured(&self, event: &Event<()>) -> bool {
+        match event {
+            Event::WindowEvent { event, .. } => match event {
+                WindowEvent::KeyboardInput { input, .. } => {
+                    self.keyboard_captured && input.state == ElementState::Pressed
+                }
+                WindowEvent::ReceivedCharacter(_) | WindowEvent::Ime(_) => self.keyboard_captured,
+                WindowEvent::MouseInput { state, .. } => {
+                    self.mouse_captured && *state == ElementState::Pressed
+                }
+                WindowEvent::MouseWheel { .. } => self.mouse_captured,
+                _ => false,
+            },
+            Event::DeviceEvent {
+                event: DeviceEvent::MouseMotion { .. },
+                ..
+            } => self.mouse_captured,
+            _ => false,
+        }
+    }
+
+    /// Returns the modifiers held, on either side of the keyboard.
+    pub fn modifiers(&self) -> ModifiersState {
+        self.modifiers
+    }
+
+    /// Returns true if the key is pressed while exactly the given modifiers
+    /// are held, e.g. `is_chord_pressed(ModifiersState::CTRL, VirtualKeyCode::S)`
+    /// is false while Ctrl+Shift+S is pressed.
+    pub fn is_chord_pressed(&self, modifiers: ModifiersState, key: VirtualKeyCode) -> bool {
+        self.modifiers == modifiers && self.is_key_pressed(key)
+    }
+
     #[allow(unused)]
     pub fn is_key_released(&self, key: VirtualKeyCode) -> bool {
         match self.keyboard.get(&key) {
@@ -102,12 +222,115 @@ impl InputSystem {
         }
     }
 
-    #[allow(unused)]
+    /// Returns the text typed during the current frame, including the text
+    /// committed by the IME. Control characters, such as backspace ('\u{8}')
+    /// and return ('\r'), are kept so that text fields can handle them.
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    /// Returns the text being composed with the IME, not yet part of
+    /// `text()`, along with the byte range of the cursor in it, if shown.
+    pub fn ime_preedit(&self) -> Option<(&str, Option<(usize, usize)>)> {
+        self.ime_preedit
+            .as_ref()
+            .map(|(text, cursor)| (text.as_str(), *cursor))
+    }
+
+    pub fn is_mouse_button_pressed(&self, button: MouseButton) -> bool {
+        self.mouse_buttons.get(&button) == Some(&ElementState::Pressed)
+    }
+
+    /// Returns the last value reported for an analog axis, or 0 if none was.
+    /// Axis ids are platform specific, and some platforms also report the
+    /// motion of the mouse on axes 0 and 1.
+    pub fn axis(&self, axis: u32) -> f32 {
+        self.axes.get(&axis).copied().unwrap_or(0.0)
+    }
+
+    /// Returns the amount scrolled right during the current frame, in lines,
+    /// see `PIXELS_PER_SCROLL_LINE`.
     pub fn mouse_scoll_x(&self) -> f32 {
         self.scroll_state.x
     }
 
+    /// Returns the amount scrolled up during the current frame, in lines.
     pub fn mouse_scoll_y(&self) -> f32 {
         self.scroll_state.y
     }
+
+    /// Returns the relative motion of the mouse during the current frame, in
+    /// unspecified device units, with y pointing down. Unlike the cursor
+    /// position, it keeps changing at the edges of the screen.
+    pub fn mouse_delta(&self) -> (f32, f32) {
+        self.mouse_delta
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use winit::event::DeviceId;
+    use winit::window::WindowId;
+
+    use super::*;
+
+    fn window_event(event: WindowEvent<'static>) -> Event<'static, ()> {
+        Event::WindowEvent {
+            window_id: unsafe { WindowId::dummy() },
+            event,
+        }
+    }
+
+    #[allow(deprecated)]
+    fn key_event(keycode: VirtualKeyCode, state: ElementState) -> Event<'static, ()> {
+        window_event(WindowEvent::KeyboardInput {
+            device_id: unsafe { DeviceId::dummy() },
+            input: KeyboardInput {
+                scancode: 0,
+                state,
+                virtual_keycode: Some(keycode),
+                modifiers: ModifiersState::empty(),
+            },
+            is_synthetic: false,
+        })
+    }
+
+    #[test]
+    fn chords_require_exact_modifiers() {
+        let mut input = InputSystem::new();
+        input.on_event(&window_event(WindowEvent::Focused(true)));
+        input.on_event(&key_event(VirtualKeyCode::S, ElementState::Pressed));
+        assert!(input.is_chord_pressed(ModifiersState::empty(), VirtualKeyCode::S));
+        assert!(!input.is_chord_pressed(ModifiersState::CTRL, VirtualKeyCode::S));
+
+        input.on_event(&window_event(WindowEvent::ModifiersChanged(
+            ModifiersState::CTRL,
+        )));
+        assert!(input.is_chord_pressed(ModifiersState::CTRL, VirtualKeyCode::S));
+
+        input.on_event(&window_event(WindowEvent::ModifiersChanged(
+            ModifiersState::CTRL | ModifiersState::SHIFT,
+        )));
+        assert!(!input.is_chord_pressed(ModifiersState::CTRL, VirtualKeyCode::S));
+
+        // modifiers are released when the window loses focus
+        input.on_event(&window_event(WindowEvent::Focused(false)));
+        assert_eq!(input.modifiers(), ModifiersState::empty());
+    }
+
+    #[test]
+    fn captured_keys_are_only_released() {
+        let mut input = InputSystem::new();
+        input.on_event(&window_event(WindowEvent::Focused(true)));
+        input.on_event(&key_event(VirtualKeyCode::W, ElementState::Pressed));
+
+        input.set_keyboard_captured(true);
+        input.on_event(&key_event(VirtualKeyCode::S, ElementState::Pressed));
+        input.on_event(&window_event(WindowEvent::ReceivedCharacter('s')));
+        assert!(!input.is_key_pressed(VirtualKeyCode::S));
+        assert_eq!(input.text(), "");
+
+        input.on_event(&key_event(VirtualKeyCode::W, ElementState::Released));
+        assert!(!input.is_key_pressed(VirtualKeyCode::W));
+    }
 }
@@ -0,0 +1,564 @@
+//! Named actions bound to inputs, so that controls can be changed without
+//! changing the code reading them.
+//!
+//! Bindings are saved as text, one action per line:
+//!
+//! ```text
+//! # comment
+//! move_up = key W, key Up
+//! zoom_in = scroll up
+//! fire = mouse left, axis 5 +
+//! ```
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::{error, fmt, fs, io};
+
+use winit::event::{
+    ElementState, Event, MouseButton, MouseScrollDelta, VirtualKeyCode, WindowEvent,
+};
+
+use crate::InputSystem;
+
+/// Axis values whose magnitude is below it are ignored, so that sticks at
+/// rest do not trigger actions.
+pub const AXIS_DEAD_ZONE: f32 = 0.15;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ScrollDirection {
+    Up,
+    Down,
+    Left,
+    Right,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AxisDirection {
+    Positive,
+    Negative,
+}
+
+/// Input triggering an action.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Binding {
+    Key(VirtualKeyCode),
+    MouseButton(MouseButton),
+    /// Active on the frames the wheel is scrolled in the direction. Its value
+    /// is the number of lines scrolled, which can be more than 1.
+    Scroll(ScrollDirection),
+    /// Analog axis of an input device, e.g. a gamepad stick, see
+    /// `InputSystem::axis()`.
+    Axis {
+        axis: u32,
+        direction: AxisDirection,
+    },
+}
+
+impl Binding {
+    /// Returns how much the input is engaged: from 0 to 1 for keys, buttons
+    /// and axes, and the number of lines scrolled during the frame, which is
+    /// not bounded, for scroll bindings.
+    pub fn value(&self, input: &InputSystem) -> f32 {
+        let pressed = |pressed: bool| if pressed { 1.0 } else { 0.0 };
+        match *self {
+            Self::Key(key) => pressed(input.is_key_pressed(key)),
+            Self::MouseButton(button) => pressed(input.is_mouse_button_pressed(button)),
+            Self::Scroll(direction) => {
+                let (x, y) = (input.mouse_scoll_x(), input.mouse_scoll_y());
+                let lines = match direction {
+                    ScrollDirection::Up => y,
+                    ScrollDirection::Down => -y,
+                    ScrollDirection::Left => -x,
+                    ScrollDirection::Right => x,
+                };
+                lines.max(0.0)
+            }
+            Self::Axis { axis, direction } => {
+                let value = match direction {
+                    AxisDirection::Positive => input.axis(axis),
+                    AxisDirection::Negative => -input.axis(axis),
+                };
+                if value < AXIS_DEAD_ZONE {
+                    0.0
+                } else {
+                    value.min(1.0)
+                }
+            }
+        }
+    }
+
+    /// Returns the binding pressed by an event, e.g. to bind the next input
+    /// of the user to an action. Axes are never returned, since some
+    /// platforms report the motion of the mouse on axes.
+    pub fn from_event(event: &Event<()>) -> Option<Self> {
+        let Event::WindowEvent { event, .. } = event else {
+            return None;
+        };
+        match *event {
+            WindowEvent::KeyboardInput { input, .. } if input.state == ElementState::Pressed => {
+                input.virtual_keycode.map(Self::Key)
+            }
+            WindowEvent::MouseInput {
+                state: ElementState::Pressed,
+                button,
+                ..
+            } => Some(Self::MouseButton(button)),
+            WindowEvent::MouseWheel { delta, .. } => {
+                let (x, y) = match delta {
+                    MouseScrollDelta::LineDelta(x, y) => (x as f64, y as f64),
+                    MouseScrollDelta::PixelDelta(position) => (position.x, position.y),
+                };
+                let direction = if x.abs() > y.abs() {
+                    if x > 0.0 {
+                        ScrollDirection::Right
+                    } else {
+                        ScrollDirection::Left
+                    }
+                } else if y > 0.0 {
+                    ScrollDirection::Up
+                } else if y < 0.0 {
+                    ScrollDirection::Down
+                } else {
+                    return None;
+                };
+                Some(Self::Scroll(direction))
+            }
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Binding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Key(key) => write!(f, "key {}", key_name(*key)),
+            Self::MouseButton(MouseButton::Left) => write!(f, "mouse left"),
+            Self::MouseButton(MouseButton::Right) => write!(f, "mouse right"),
+            Self::MouseButton(MouseButton::Middle) => write!(f, "mouse middle"),
+            Self::MouseButton(MouseButton::Other(button)) => write!(f, "mouse {button}"),
+            Self::Scroll(direction) => {
+                let direction = match direction {
+                    ScrollDirection::Up => "up",
+                    ScrollDirection::Down => "down",
+                    ScrollDirection::Left => "left",
+                    ScrollDirection::Right => "right",
+                };
+                write!(f, "scroll {direction}")
+            }
+            Self::Axis { axis, direction } => {
+                let direction = match direction {
+                    AxisDirection::Positive => '+',
+                    AxisDirection::Negative => '-',
+                };
+                write!(f, "axis {axis} {direction}")
+            }
+        }
+    }
+}
+
+impl FromStr for Binding {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let words: Vec<&str> = s.split_whitespace().collect();
+        let binding = match words[..] {
+            ["key", name] => Self::Key(
+                KEYS.iter()
+                    .find(|(_, n)| *n == name)
+                    .map(|(key, _)| *key)
+                    .ok_or_else(|| format!("unknown key {name:?}"))?,
+            ),
+            ["mouse", button] => Self::MouseButton(match button {
+                "left" => MouseButton::Left,
+                "right" => MouseButton::Right,
+                "middle" => MouseButton::Middle,
+                other => MouseButton::Other(
+                    other
+                        .parse()
+                        .map_err(|_| format!("unknown mouse button {other:?}"))?,
+                ),
+            }),
+            ["scroll", direction] => Self::Scroll(match direction {
+                "up" => ScrollDirection::Up,
+                "down" => ScrollDirection::Down,
+                "left" => ScrollDirection::Left,
+                "right" => ScrollDirection::Right,
+                other => return Err(format!("unknown scroll direction {other:?}")),
+            }),
+            ["axis", axis, direction] => Self::Axis {
+                axis: axis
+                    .parse()
+                    .map_err(|_| format!("invalid axis id {axis:?}"))?,
+                direction: match direction {
+                    "+" => AxisDirection::Positive,
+                    "-" => AxisDirection::Negative,
+                    other => return Err(format!("unknown axis direction {other:?}")),
+                },
+            },
+            _ => return Err(format!("invalid binding {s:?}")),
+        };
+        Ok(binding)
+    }
+}
+
+#[derive(Debug)]
+pub enum InputMapError {
+    Io {
+        path: PathBuf,
+        error: io::Error,
+    },
+    /// A line of a bindings file, numbered from 1, can not be parsed.
+    Parse {
+        line: usize,
+        message: String,
+    },
+}
+
+impl fmt::Display for InputMapError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io { path, error } => write!(f, "access {}: {error}", path.display()),
+            Self::Parse { line, message } => write!(f, "line {line}: {message}"),
+        }
+    }
+}
+
+impl error::Error for InputMapError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Self::Io { error, .. } => Some(error),
+            Self::Parse { .. } => None,
+        }
+    }
+}
+
+/// Bindings of named actions. An action is active while any of its bindings
+/// is.
+///
+/// Action names must not contain whitespace, '=', ',' or '#', so that they
+/// can be saved.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct InputMap {
+    actions: BTreeMap<String, Vec<Binding>>,
+}
+
+impl InputMap {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Adds a binding to an action, see `bind()`.
+    pub fn with_binding(mut self, action: impl Into<String>, binding: Binding) -> Self {
+        self.bind(action, binding);
+        self
+    }
+
+    /// Adds a binding to an action, keeping its other bindings.
+    pub fn bind(&mut self, action: impl Into<String>, binding: Binding) {
+        let bindings = self.actions.entry(action.into()).or_default();
+        if !bindings.contains(&binding) {
+            bindings.push(binding);
+        }
+    }
+
+    /// Replaces the bindings of an action.
+    pub fn rebind(
+        &mut self,
+        action: impl Into<String>,
+        bindings: impl IntoIterator<Item = Binding>,
+    ) {
+        let action = action.into();
+        self.actions.remove(&action);
+        for binding in bindings {
+            self.bind(action.clone(), binding);
+        }
+    }
+
+    /// Removes a binding from an action. Returns false if it was not bound.
+    pub fn unbind(&mut self, action: &str, binding: Binding) -> bool {
+        let Some(bindings) = self.actions.get_mut(action) else {
+            return false;
+        };
+        let len = bindings.len();
+        bindings.retain(|b| *b != binding);
+        let removed = bindings.len() != len;
+        if bindings.is_empty() {
+            self.actions.remove(action);
+        }
+        removed
+    }
+
+    /// Replaces the bindings of the actions bound in `other`, e.g. to apply
+    /// the bindings of the user over the default ones.
+    pub fn merge(&mut self, other: &InputMap) {
+        for (action, bindings) in &other.actions {
+            self.actions.insert(action.clone(), bindings.clone());
+        }
+    }
+
+    pub fn bindings(&self, action: &str) -> &[Binding] {
+        self.actions.get(action).map_or(&[], Vec::as_slice)
+    }
+
+    /// Returns the actions bound to a binding, sorted by name, e.g. to warn
+    /// that an input would trigger several actions.
+    pub fn actions_bound_to(&self, binding: Binding) -> impl Iterator<Item = &str> {
+        self.actions
+            .iter()
+            .filter(move |(_, bindings)| bindings.contains(&binding))
+            .map(|(action, _)| action.as_str())
+    }
+
+    /// Returns the bound actions, sorted by name.
+    pub fn actions(&self) -> impl Iterator<Item = &str> {
+        self.actions.keys().map(String::as_str)
+    }
+
+    /// Returns how much an action is engaged, the highest value of its
+    /// bindings, see `Binding::value()`. The value can be more than 1 when
+    /// the action is bound to the scroll wheel.
+    pub fn value(&self, input: &InputSystem, action: &str) -> f32 {
+        self.bindings(action)
+            .iter()
+            .map(|binding| binding.value(input))
+            .fold(0.0, f32::max)
+    }
+
+    pub fn is_active(&self, input: &InputSystem, action: &str) -> bool {
+        self.value(input, action) > 0.0
+    }
+
+    /// Parses bindings saved with `save()`, or written by hand.
+    pub fn parse(source: &str) -> Result<Self, InputMapError> {
+        let mut map = Self::new();
+        for (index, line) in source.lines().enumerate() {
+            let error = |message| InputMapError::Parse {
+                line: index + 1,
+                message,
+            };
+            let line = line.split('#').next().unwrap_or_default().trim();
+            if line.is_empty() {
+                continue;
+            }
+            let (action, bindings) = line
+                .split_once('=')
+                .ok_or_else(|| error("expected `action = bindings`".to_string()))?;
+            let action = action.trim();
+            if action.is_empty() || action.contains(char::is_whitespace) {
+                return Err(error(format!("invalid action name {action:?}")));
+            }
+            let bindings = bindings
+                .split(',')
+                .map(|binding| binding.parse().map_err(error))
+                .collect::<Result<Vec<Binding>, _>>()?;
+            map.rebind(action, bindings);
+        }
+        Ok(map)
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, InputMapError> {
+        let path = path.as_ref();
+        let source = fs::read_to_string(path).map_err(|error| InputMapError::Io {
+            path: path.to_path_buf(),
+            error,
+        })?;
+        Self::parse(&source)
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), InputMapError> {
+        let path = path.as_ref();
+        fs::write(path, self.to_string()).map_err(|error| InputMapError::Io {
+            path: path.to_path_buf(),
+            error,
+        })
+    }
+}
+
+impl fmt::Display for InputMap {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (action, bindings) in &self.actions {
+            write!(f, "{action} =")?;
+            for (i, binding) in bindings.iter().enumerate() {
+                let separator = if i == 0 { "" } else { "," };
+                write!(f, "{separator} {binding}")?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+fn key_name(key: VirtualKeyCode) -> &'static str {
+    KEYS.iter()
+        .find(|(k, _)| *k == key)
+        .map(|(_, name)| *name)
+        .expect("every key is named")
+}
+
+macro_rules! keys {
+    ($($key:ident)*) => {
+        /// Keys with their name in bindings files, the name of their variant.
+        const KEYS: &[(VirtualKeyCode, &str)] = &[$((VirtualKeyCode::$key, stringify!($key))),*];
+    };
+}
+
+keys! {
+    Key1 Key2 Key3 Key4 Key5 Key6 Key7 Key8 Key9 Key0
+    A B C D E F G H I J K L M N O P Q R S T U V W X Y Z
+    Escape F1 F2 F3 F4 F5 F6 F7 F8 F9 F10 F11 F12
+    F13 F14 F15 F16 F17 F18 F19 F20 F21 F22 F23 F24
+    Snapshot Scroll Pause Insert Home Delete End PageDown PageUp
+    Left Up Right Down Back Return Space Compose Caret Numlock
+    Numpad0 Numpad1 Numpad2 Numpad3 Numpad4 Numpad5 Numpad6 Numpad7 Numpad8 Numpad9
+    NumpadAdd NumpadDivide NumpadDecimal NumpadComma NumpadEnter NumpadEquals
+    NumpadMultiply NumpadSubtract
+    AbntC1 AbntC2 Apostrophe Apps Asterisk At Ax Backslash Calculator Capital Colon
+    Comma Convert Equals Grave Kana Kanji LAlt LBracket LControl LShift LWin Mail
+    MediaSelect MediaStop Minus Mute MyComputer NavigateForward NavigateBackward
+    NextTrack NoConvert OEM102 Period PlayPause Plus Power PrevTrack RAlt RBracket
+    RControl RShift RWin Semicolon Slash Sleep Stop Sysrq Tab Underline Unlabeled
+    VolumeDown VolumeUp Wake WebBack WebFavorites WebForward WebHome WebRefresh
+    WebSearch WebStop Yen Copy Paste Cut
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bindings_round_trip_through_text() {
+        let map = InputMap::new()
+            .with_binding("move_up", Binding::Key(VirtualKeyCode::W))
+            .with_binding("move_up", Binding::Key(VirtualKeyCode::Up))
+            .with_binding("zoom_in", Binding::Scroll(ScrollDirection::Up))
+            .with_binding("fire", Binding::MouseButton(MouseButton::Other(4)))
+            .with_binding(
+                "fire",
+                Binding::Axis {
+                    axis: 5,
+                    direction: AxisDirection::Negative,
+                },
+            );
+
+        let text = map.to_string();
+        assert_eq!(
+            text,
+            "fire = mouse 4, axis 5 -\nmove_up = key W, key Up\nzoom_in = scroll up\n"
+        );
+        assert_eq!(InputMap::parse(&text).unwrap(), map);
+    }
+
+    #[test]
+    fn invalid_lines_are_reported() {
+        let source = "# controls\n\nmove_up = key W # default\nzoom_in = scroll sideways\n";
+        match InputMap::parse(source) {
+            Err(InputMapError::Parse { line, message }) => {
+                assert_eq!(line, 4);
+                assert!(message.contains("sideways"), "{message}");
+            }
+            other => panic!("unexpected result {other:?}"),
+        }
+        assert!(InputMap::parse("move up = key W").is_err());
+        assert!(InputMap::parse("move_up key W").is_err());
+    }
+
+    #[test]
+    fn rebinding_replaces_bindings() {
+        let mut map = InputMap::new()
+            .with_binding("jump", Binding::Key(VirtualKeyCode::Space))
+            .with_binding("jump", Binding::Key(VirtualKeyCode::Space));
+        assert_eq!(map.bindings("jump"), [Binding::Key(VirtualKeyCode::Space)]);
+
+        map.rebind("jump", [Binding::MouseButton(MouseButton::Right)]);
+        assert_eq!(
+            map.bindings("jump"),
+            [Binding::MouseButton(MouseButton::Right)]
+        );
+
+        assert!(!map.unbind("jump", Binding::Key(VirtualKeyCode::Space)));
+        assert!(map.unbind("jump", Binding::MouseButton(MouseButton::Right)));
+        assert_eq!(map.actions().count(), 0);
+    }
+
+    #[test]
+    fn conflicting_actions_are_listed() {
+        let map = InputMap::new()
+            .with_binding("jump", Binding::Key(VirtualKeyCode::Space))
+            .with_binding("fire", Binding::MouseButton(MouseButton::Left))
+            .with_binding("confirm", Binding::Key(VirtualKeyCode::Space));
+        let actions = |binding| map.actions_bound_to(binding).collect::<Vec<_>>();
+        assert_eq!(
+            actions(Binding::Key(VirtualKeyCode::Space)),
+            ["confirm", "jump"]
+        );
+        assert_eq!(actions(Binding::MouseButton(MouseButton::Left)), ["fire"]);
+        assert!(actions(Binding::Scroll(ScrollDirection::Up)).is_empty());
+    }
+
+    #[test]
+    #[allow(deprecated)]
+    fn pressed_inputs_are_captured_as_bindings() {
+        use winit::event::{DeviceId, KeyboardInput, ModifiersState, TouchPhase};
+        use winit::window::WindowId;
+
+        let window_event = |event| Event::WindowEvent {
+            window_id: unsafe { WindowId::dummy() },
+            event,
+        };
+        let key = |state| {
+            window_event(WindowEvent::KeyboardInput {
+                device_id: unsafe { DeviceId::dummy() },
+                input: KeyboardInput {
+                    scancode: 0,
+                    state,
+                    virtual_keycode: Some(VirtualKeyCode::Q),
+                    modifiers: ModifiersState::empty(),
+                },
+                is_synthetic: false,
+            })
+        };
+        let scroll = |x, y| {
+            window_event(WindowEvent::MouseWheel {
+                device_id: unsafe { DeviceId::dummy() },
+                delta: MouseScrollDelta::LineDelta(x, y),
+                phase: TouchPhase::Moved,
+                modifiers: ModifiersState::empty(),
+            })
+        };
+
+        assert_eq!(
+            Binding::from_event(&key(ElementState::Pressed)),
+            Some(Binding::Key(VirtualKeyCode::Q))
+        );
+        assert_eq!(Binding::from_event(&key(ElementState::Released)), None);
+        assert_eq!(
+            Binding::from_event(&window_event(WindowEvent::MouseInput {
+                device_id: unsafe { DeviceId::dummy() },
+                state: ElementState::Pressed,
+                button: MouseButton::Middle,
+                modifiers: ModifiersState::empty(),
+            })),
+            Some(Binding::MouseButton(MouseButton::Middle))
+        );
+        assert_eq!(
+            Binding::from_event(&scroll(0.5, -2.0)),
+            Some(Binding::Scroll(ScrollDirection::Down))
+        );
+        assert_eq!(
+            Binding::from_event(&scroll(-3.0, 1.0)),
+            Some(Binding::Scroll(ScrollDirection::Left))
+        );
+        assert_eq!(Binding::from_event(&scroll(0.0, 0.0)), None);
+    }
+
+    #[test]
+    fn every_key_has_a_distinct_name() {
+        for (i, (key, name)) in KEYS.iter().enumerate() {
+            assert!(
+                KEYS[..i].iter().all(|(k, n)| k != key && n != name),
+                "{name}"
+            );
+        }
+    }
+}
@@ -0,0 +1,286 @@
+//! Golden-image tests: scenes are rendered by a headless renderer, read back
+//! and compared against the reference images stored in `references/`.
+//!
+//! The tests need a Vulkan driver, e.g. lavapipe or SwiftShader, and are
+//! ignored by default. Run them using `cargo test -p render-test -- --ignored`.
+//! Set `UPDATE_REFERENCES=1` to write the rendered images as references. On
+//! mismatch, the rendered image is saved next to its reference as
+//! `<name>.actual.png`.
+
+#![allow(clippy::missing_safety_doc)]
+
+use core::ecs::World;
+use core::object::QuadView;
+use std::path::PathBuf;
+use std::{env, error, fmt, result, time};
+
+use ash::vk;
+use cgmath::Matrix4;
+use image::RgbaImage;
+use vulkan_renderer::renderer::VulkanRenderer;
+use vulkan_renderer::staging::StagingRing;
+use vulkan_renderer_2d::Renderer2DSystem;
+
+pub type Result<T> = result::Result<T, Box<dyn error::Error>>;
+
+/// Environment variable set to overwrite the references with the rendered
+/// images.
+pub const UPDATE_REFERENCES_VAR: &str = "UPDATE_REFERENCES";
+
+/// Differences allowed between a rendered image and its reference, which
+/// absorb the rounding differences between drivers.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Tolerance {
+    /// Largest difference of a channel for which pixels are considered equal.
+    pub channel: u8,
+    /// Fraction of the pixels allowed to differ, e.g. along edges.
+    pub differing_pixels: f32,
+}
+
+impl Default for Tolerance {
+    fn default() -> Self {
+        Self {
+            channel: 2,
+            differing_pixels: 0.001,
+        }
+    }
+}
+
+/// Difference between a rendered image and its reference, beyond the
+/// tolerance.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Mismatch {
+    Size {
+        actual: (u32, u32),
+        expected: (u32, u32),
+    },
+    Pixels {
+        differing: usize,
+        total: usize,
+        /// Coordinates of the first differing pixel.
+        first: (u32, u32),
+    },
+}
+
+impl fmt::Display for Mismatch {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Size { actual, expected } => write!(
+                f,
+                "image is {}x{} instead of {}x{}",
+                actual.0, actual.1, expected.0, expected.1
+            ),
+            Self::Pixels {
+                differing,
+                total,
+                first,
+            } => write!(
+                f,
+                "{differing} of {total} pixels differ, the first one at {first:?}"
+            ),
+        }
+    }
+}
+
+impl error::Error for Mismatch {}
+
+/// Compares an image with its reference.
+pub fn compare(
+    actual: &RgbaImage,
+    expected: &RgbaImage,
+    tolerance: Tolerance,
+) -> result::Result<(), Mismatch> {
+    if actual.dimensions() != expected.dimensions() {
+        return Err(Mismatch::Size {
+            actual: actual.dimensions(),
+            expected: expected.dimensions(),
+        });
+    }
+
+    let mut differing = 0;
+    let mut first = None;
+    for ((x, y, a), e) in actual.enumerate_pixels().zip(expected.pixels()) {
+        let equal =
+            a.0.iter()
+                .zip(e.0)
+                .all(|(a, e)| a.abs_diff(e) <= tolerance.channel);
+        if !equal {
+            differing += 1;
+            first.get_or_insert((x, y));
+        }
+    }
+
+    let total = actual.pixels().len();
+    match first {
+        Some(first) if differing as f32 > total as f32 * tolerance.differing_pixels => {
+            Err(Mismatch::Pixels {
+                differing,
+                total,
+                first,
+            })
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Returns the path of the reference image of the given name.
+pub fn reference_path(name: &str) -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("references")
+        .join(format!("{name}.png"))
+}
+
+/// Compares an image with the reference of the given name, or writes it as
+/// the reference when `UPDATE_REFERENCES` is set. On mismatch, the image is
+/// saved next to the reference.
+pub fn check_reference(name: &str, actual: &RgbaImage, tolerance: Tolerance) -> Result<()> {
+    let path = reference_path(name);
+    if env::var_os(UPDATE_REFERENCES_VAR).is_some() {
+        actual
+            .save(&path)
+            .map_err(|e| format!("write reference {}: {e}", path.display()))?;
+        return Ok(());
+    }
+
+    let expected = image::open(&path)
+        .map_err(|e| {
+            format!(
+                "read reference {}: {e}, run with {UPDATE_REFERENCES_VAR}=1 to create it",
+                path.display()
+            )
+        })?
+        .into_rgba8();
+    if let Err(mismatch) = compare(actual, &expected, tolerance) {
+        let actual_path = path.with_extension("actual.png");
+        actual
+            .save(&actual_path)
+            .map_err(|e| format!("write {}: {e}", actual_path.display()))?;
+        return Err(format!(
+            "{name} does not match its reference: {mismatch}, see {}",
+            actual_path.display()
+        )
+        .into());
+    }
+    Ok(())
+}
+
+/// Draws the objects of a world using the 2D renderer in a single frame of a headless
+/// renderer and reads the frame back.
+pub unsafe fn render_2d(
+    extent: vk::Extent2D,
+    view_projection: Matrix4<f32>,
+    world: &World,
+) -> Result<RgbaImage> {
+    let mut renderer = VulkanRenderer::new_headless("render-test", extent, Default::default())
+        .map_err(|e| format!("create headless renderer: {e}"))?;
+    let mut renderer2d = Renderer2DSystem::new(
+        renderer.device(),
+        renderer.renderpass(),
+        renderer.max_frames_in_flight(),
+    )
+    .map_err(|e| format!("create renderer 2D: {e}"))?;
+
+    if !renderer.begin_frame()? {
+        return Err("frame was not started".into());
+    }
+    let mut result = Ok(());
+    renderer.draw(|_, command_buffer| {
+        let draw_order = world
+            .query::<QuadView>()
+            .map(|object| (object, None::<fn(vk::CommandBuffer, &mut StagingRing)>));
+        result = renderer2d.render(
+            renderer.device(),
+            command_buffer,
+            &mut renderer.staging(),
+            time::Duration::ZERO,
+            view_projection,
+            draw_order,
+        );
+    })?;
+    result.map_err(|e| format!("render 2D: {e}"))?;
+    renderer.end_frame()?;
+
+    let pixels = renderer.read_frame()?;
+    let extent = renderer.window_extent();
+    RgbaImage::from_raw(extent.width, extent.height, pixels)
+        .ok_or_else(|| "frame size does not match its extent".into())
+}
+
+#[cfg(test)]
+mod tests {
+    use core::object::GameObject;
+
+    use cgmath::{SquareMatrix, Vector3, Vector4};
+    use image::Rgba;
+
+    use super::*;
+
+    #[test]
+    fn differences_within_tolerance_match() {
+        let expected = RgbaImage::from_pixel(10, 10, Rgba([100, 100, 100, 255]));
+        let mut actual = expected.clone();
+        actual.put_pixel(1, 2, Rgba([102, 98, 100, 255]));
+        assert_eq!(compare(&actual, &expected, Tolerance::default()), Ok(()));
+
+        actual.put_pixel(3, 4, Rgba([0, 100, 100, 255]));
+        let tolerance = Tolerance {
+            differing_pixels: 0.01,
+            ..Default::default()
+        };
+        assert_eq!(compare(&actual, &expected, tolerance), Ok(()));
+        assert_eq!(
+            compare(&actual, &expected, Tolerance::default()),
+            Err(Mismatch::Pixels {
+                differing: 1,
+                total: 100,
+                first: (3, 4)
+            })
+        );
+    }
+
+    #[test]
+    fn images_of_different_sizes_mismatch() {
+        let expected = RgbaImage::new(10, 10);
+        let actual = RgbaImage::new(10, 20);
+        assert_eq!(
+            compare(&actual, &expected, Tolerance::default()),
+            Err(Mismatch::Size {
+                actual: (10, 20),
+                expected: (10, 10)
+            })
+        );
+    }
+
+    /// Quads whose edges fall between pixel centers, so that drivers agree on
+    /// their coverage: red, green and an outlined blue one drawn on top of
+    /// its outline.
+    #[test]
+    #[ignore = "requires a Vulkan driver"]
+    fn quads_match_reference() {
+        let quad = |x: f32, y: f32, color: Vector4<f32>| {
+            GameObject::new()
+                .with_position(Vector3::new(x, y, 0.0))
+                .with_scale(Vector3::new(0.25, 0.25, 1.0))
+                .with_color(color)
+        };
+        let mut world = World::new();
+        for object in [
+            quad(-2.0, -2.0, Vector4::new(1.0, 0.0, 0.0, 1.0)),
+            quad(2.0, -2.0, Vector4::new(0.0, 1.0, 0.0, 1.0)),
+            quad(0.0, 2.0, Vector4::new(0.0, 0.0, 1.0, 1.0))
+                .with_outline(Vector4::new(1.0, 1.0, 1.0, 1.0), 0.125),
+        ] {
+            world.spawn(object);
+        }
+        let extent = vk::Extent2D {
+            width: 64,
+            height: 64,
+        };
+
+        let image = unsafe { render_2d(extent, Matrix4::identity(), &world) }
+            .unwrap_or_else(|e| panic!("render quads: {e}"));
+        if let Err(e) = check_reference("quads", &image, Tolerance::default()) {
+            panic!("{e}");
+        }
+    }
+}
@@ -1,8 +1,11 @@
 use core::object::GameObject;
+use core::prefab::PrefabLibrary;
+use std::path::PathBuf;
 
 use cgmath::{Vector3, Vector4};
+use engine::config::EngineConfig;
 use engine::engine::{Application, ApplicationContext, EngineBuilder};
-use log::LevelFilter;
+use log::{error, LevelFilter};
 use winit::dpi::LogicalSize;
 use winit::window::WindowBuilder;
 
@@ -11,11 +14,16 @@ const WINDOW_WIDTH: u32 = 800;
 const WINDOW_HEIGHT: u32 = 600;
 
 fn main() {
+    let config = EngineConfig::from_args();
+
     // initialize logger
-    env_logger::Builder::new()
-        .filter_level(LevelFilter::Info)
-        .parse_default_env()
-        .init();
+    // NOTE: the level given on the command line overrides RUST_LOG
+    let mut logger = env_logger::Builder::new();
+    logger.filter_level(LevelFilter::Info).parse_default_env();
+    if let Some(level) = config.log_level {
+        logger.filter_level(level);
+    }
+    logger.init();
 
     // setup window
     let window_builder = {
@@ -27,23 +35,47 @@ fn main() {
     };
 
     // setup sandbox impl
-    let application = Sandbox::default();
+    let application = Sandbox {
+        scene: config.scene.clone(),
+    };
 
     // setup engine
     let mut engine = EngineBuilder::new(Box::new(application))
         .with_window_builder(Some(window_builder))
+        .with_config(&config)
         .build()
         .expect("engine builder builds");
 
     // start engine
-    engine.run()
+    match engine.run() {
+        Ok(exit_code) => std::process::exit(exit_code),
+        Err(e) => {
+            error!("run engine: {e}");
+            std::process::exit(1)
+        }
+    }
 }
 
 #[derive(Default)]
-struct Sandbox {}
+struct Sandbox {
+    /// Prefab file whose prefabs are spawned instead of the grid of quads.
+    scene: Option<PathBuf>,
+}
 
 impl Application for Sandbox {
     fn on_init(&mut self, mut ctx: ApplicationContext) {
+        if let Some(path) = &self.scene {
+            match PrefabLibrary::load(path) {
+                Ok(scene) => {
+                    for prefab in scene.names().filter_map(|name| scene.get(name)) {
+                        ctx.world_mut().spawn(*prefab);
+                    }
+                    return;
+                }
+                Err(e) => error!("load scene {}: {e}", path.display()),
+            }
+        }
+
         let count = 50;
         for x in 0..count + 1 {
             for y in 0..count + 1 {
@@ -0,0 +1,140 @@
+//! Compiles the GLSL shaders of the renderers to SPIR-V, from the build
+//! scripts of their crates and when they are hot reloaded.
+//!
+//! `#include "file"` is resolved next to the including shader, then in the
+//! directory of the includes shared by the shaders of all the crates, and
+//! `#include <file>` in the latter only.
+
+// ref: https://falseidolfactory.com/2018/06/23/compiling-glsl-to-spirv-at-build-time.html
+// ref: https://github.com/google/shaderc-rs
+
+use std::error::Error;
+use std::path::Path;
+use std::{env, fs};
+
+/// Directory of the includes shared by the shaders of all the crates.
+pub const SHADER_INCLUDE_DIR: &str =
+    concat!(env!("CARGO_MANIFEST_DIR"), "/../../assets/shaders/include");
+
+pub type Result<T> = std::result::Result<T, Box<dyn Error>>;
+
+/// Compiles the shaders of a directory to SPIR-V in OUT_DIR, see
+/// `include_shader!`. Meant to be called from build scripts.
+pub fn compile_dir(src_dir: &str) -> Result<()> {
+    let out_dir = env::var("OUT_DIR")?;
+
+    // Tell the build script to only run again if we change our source shaders
+    println!("cargo:rerun-if-changed={src_dir}");
+    println!("cargo:rerun-if-changed={SHADER_INCLUDE_DIR}");
+
+    let compiler = Compiler::new()?;
+    for entry in fs::read_dir(src_dir).map_err(|e| format!("read shaders src dir: {e:?}"))? {
+        let entry = entry?;
+        let in_path = entry.path();
+        if !entry.file_type()?.is_file() || shader_kind(&in_path).is_none() {
+            continue;
+        }
+
+        // Write compiled (binary) spirv shader
+        let spv = compiler.compile(&in_path)?;
+        let out_path = Path::new(&out_dir).join(format!(
+            "{}.spv",
+            in_path.file_name().unwrap().to_string_lossy()
+        ));
+        fs::write(&out_path, spv).map_err(|e| format!("write compiled shader: {e:?}"))?;
+    }
+
+    Ok(())
+}
+
+/// Returns the kind of shader of a source from its extension.
+pub fn shader_kind(path: &Path) -> Option<shaderc::ShaderKind> {
+    match path.extension()?.to_str()? {
+        "vert" => Some(shaderc::ShaderKind::Vertex),
+        "frag" => Some(shaderc::ShaderKind::Fragment),
+        "comp" => Some(shaderc::ShaderKind::Compute),
+        "geom" => Some(shaderc::ShaderKind::Geometry),
+        "tesc" => Some(shaderc::ShaderKind::TessControl),
+        "tese" => Some(shaderc::ShaderKind::TessEvaluation),
+        _ => None,
+    }
+}
+
+pub struct Compiler {
+    compiler: shaderc::Compiler,
+}
+
+impl Compiler {
+    pub fn new() -> Result<Self> {
+        let compiler = shaderc::Compiler::new().ok_or("create shaderc compiler")?;
+        Ok(Self { compiler })
+    }
+
+    /// Compiles a GLSL source to SPIR-V, its kind given by its extension.
+    pub fn compile(&self, path: &Path) -> Result<Vec<u8>> {
+        let kind = shader_kind(path).ok_or("unknown shader kind")?;
+        let source =
+            fs::read_to_string(path).map_err(|e| format!("read shader file to string: {e:?}"))?;
+        let mut options =
+            shaderc::CompileOptions::new().ok_or("create shaderc compiler options")?;
+        options.set_include_callback(resolve_include);
+        let artifact = self.compiler.compile_into_spirv(
+            &source,
+            kind,
+            &path.display().to_string(),
+            "main",
+            Some(&options),
+        )?;
+        Ok(artifact.as_binary_u8().to_vec())
+    }
+}
+
+fn resolve_include(
+    name: &str,
+    include_type: shaderc::IncludeType,
+    source: &str,
+    _depth: usize,
+) -> std::result::Result<shaderc::ResolvedInclude, String> {
+    let relative = match include_type {
+        shaderc::IncludeType::Relative => Path::new(source).parent(),
+        shaderc::IncludeType::Standard => None,
+    };
+    let path = relative
+        .into_iter()
+        .chain([Path::new(SHADER_INCLUDE_DIR)])
+        .map(|dir| dir.join(name))
+        .find(|path| path.is_file())
+        .ok_or_else(|| format!("include {name} not found"))?;
+    let content = fs::read_to_string(&path).map_err(|e| format!("read include {name}: {e:?}"))?;
+    Ok(shaderc::ResolvedInclude {
+        resolved_name: path.display().to_string(),
+        content,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn includes_are_resolved_next_to_the_source_first() {
+        let dir = env::temp_dir().join(format!("shader-include-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("common.glsl"), "// local").unwrap();
+        let source = dir.join("a.vert").display().to_string();
+
+        let include =
+            resolve_include("common.glsl", shaderc::IncludeType::Relative, &source, 1).unwrap();
+        assert_eq!(include.content, "// local");
+
+        let include =
+            resolve_include("common.glsl", shaderc::IncludeType::Standard, &source, 1).unwrap();
+        assert_ne!(include.content, "// local");
+
+        assert!(
+            resolve_include("missing.glsl", shaderc::IncludeType::Relative, &source, 1).is_err()
+        );
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
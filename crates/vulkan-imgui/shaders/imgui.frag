@@ -3,7 +3,7 @@
 #extension GL_ARB_shading_language_420pack : enable
 
 // uniforms
-layout (binding = 1, set = 0) uniform sampler2D fontsSampler;
+layout (binding = 0, set = 0) uniform sampler2D fontsSampler;
 
 // inputs
 layout (location = 0) in vec2 vUv;
@@ -2,10 +2,13 @@
 #extension GL_ARB_separate_shader_objects : enable
 #extension GL_ARB_shading_language_420pack : enable
 
-// uniforms
-layout (binding = 0) uniform Matrices {
-    mat4 ortho;
-} matrices;
+#include "common.glsl"
+
+// transform from imgui coordinates to clip space
+layout (push_constant) uniform Transform {
+    vec2 scale;
+    vec2 translate;
+} transform;
 
 // inputs
 layout (location = 0) in vec2 vPos;
@@ -16,24 +19,6 @@ layout (location = 2) in vec4 vColor;
 layout (location = 0) out vec2 oUV;
 layout (location = 1) out vec4 oColor;
 
-// Converts a color from linear light gamma to sRGB gamma
-// https://gamedev.stackexchange.com/a/148088
-vec4 fromLinear(vec4 linearRGB) {
-    bvec3 cutoff = lessThan(linearRGB.rgb, vec3(0.0031308));
-    vec3 higher = vec3(1.055)*pow(linearRGB.rgb, vec3(1.0/2.4)) - vec3(0.055);
-    vec3 lower = linearRGB.rgb * vec3(12.92);
-    return vec4(mix(higher, lower, cutoff), linearRGB.a);
-}
-
-// Converts a color from sRGB gamma to linear light gamma
-// https://gamedev.stackexchange.com/a/148088
-vec4 toLinear(vec4 sRGB) {
-    bvec3 cutoff = lessThan(sRGB.rgb, vec3(0.04045));
-    vec3 higher = pow((sRGB.rgb + vec3(0.055))/vec3(1.055), vec3(2.4));
-    vec3 lower = sRGB.rgb/vec3(12.92);
-    return vec4(mix(higher, lower, cutoff), sRGB.a);
-}
-
 void main() {
     oUV = vUv;
     oColor = vColor;
@@ -46,5 +31,5 @@ void main() {
     // https://github.com/ocornut/imgui/issues/1724
     // https://github.com/ocornut/imgui/issues/578
     oColor = toLinear(oColor);
-    gl_Position = matrices.ortho * vec4(vPos.x, vPos.y, 0.0, 1.0);
+    gl_Position = vec4(vPos * transform.scale + transform.translate, 0.0, 1.0);
 }
@@ -0,0 +1,32 @@
+//! System clipboard of imgui, so that text can be copied from and pasted
+//! into its text fields.
+use imgui::ClipboardBackend;
+use log::warn;
+
+pub struct Clipboard(arboard::Clipboard);
+
+impl Clipboard {
+    /// Returns None if the system clipboard can not be accessed, e.g. on a
+    /// headless session.
+    pub fn new() -> Option<Self> {
+        match arboard::Clipboard::new() {
+            Ok(clipboard) => Some(Self(clipboard)),
+            Err(e) => {
+                warn!("access clipboard: {e}");
+                None
+            }
+        }
+    }
+}
+
+impl ClipboardBackend for Clipboard {
+    fn get(&mut self) -> Option<String> {
+        self.0.get_text().ok()
+    }
+
+    fn set(&mut self, value: &str) {
+        if let Err(e) = self.0.set_text(value) {
+            warn!("copy to clipboard: {e}");
+        }
+    }
+}
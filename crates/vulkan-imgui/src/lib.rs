@@ -4,50 +4,229 @@
 ///! https://github.com/Yatekii/imgui-wgpu-rs/blob/master/src/lib.rs
 ///! https://github.com/unknownue/vulkan-tutorial-rust/blob/master/src/tutorials/23_texture_image.rs
 ///! https://github.com/adrien-ben/imgui-rs-vulkan-renderer/blob/master/src/renderer/vulkan.rs
-use std::io::Cursor;
-use std::mem;
+use core::handle::HandleMap;
+use std::collections::HashMap;
+use std::io::{self, Cursor};
 use std::ops::Deref;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
 use std::{error, result};
+use std::{fs, mem, slice};
 
 use ash::vk;
-use cgmath::{Matrix4, SquareMatrix};
 use imgui::DrawCmd::Elements;
 use imgui::{DrawData, DrawIdx, DrawList, DrawVert, FontConfig};
 use log::debug;
 use vulkan_renderer::buffer::Buffer;
+use vulkan_renderer::deletion::{DeletionQueueHandle, Resource};
 use vulkan_renderer::descriptor::{DescriptorPool, DescriptorSet, DescriptorSetLayout};
 use vulkan_renderer::device::Device;
 use vulkan_renderer::image::Image;
-use vulkan_renderer::offset_of;
 use vulkan_renderer::pipeline::Pipeline;
 use vulkan_renderer::renderpass::RenderPass;
 use vulkan_renderer::shader::Shader;
-use vulkan_renderer::texture::Texture;
+use vulkan_renderer::texture::{Texture, TextureHandle};
+use vulkan_renderer::{include_shader, offset_of};
 use winit::window::Window;
 
+pub use imgui;
+
+#[cfg(feature = "clipboard")]
+pub mod clipboard;
+#[cfg(feature = "viewports")]
+pub mod viewports;
+
 type Result<T> = result::Result<T, Box<dyn error::Error>>;
 
+/// Size of the default font, in logical pixels.
+const DEFAULT_FONT_SIZE: f32 = 13.0;
+
+/// Options of the UI, see `init_with_options()`.
+#[derive(Clone, Debug, Default)]
+pub struct UiOptions {
+    /// Fonts of the UI, the first one being the default font. The default
+    /// imgui font is used if there are none.
+    pub fonts: Vec<Font>,
+    /// File the settings of the UI, e.g. the layout of the windows, are
+    /// loaded from and saved to. Settings are not kept across runs if None.
+    pub ini_path: Option<PathBuf>,
+}
+
 pub fn init(window: &Window) -> (imgui_winit_support::WinitPlatform, imgui::Context) {
+    init_with_options(window, &UiOptions::default())
+}
+
+pub fn init_with_options(
+    window: &Window,
+    options: &UiOptions,
+) -> (imgui_winit_support::WinitPlatform, imgui::Context) {
     let mut imgui_context = imgui::Context::create();
-    imgui_context.set_ini_filename(None);
+    // NOTE: imgui saves the settings periodically and when the context is
+    //       dropped
+    imgui_context.set_ini_filename(options.ini_path.clone());
+    #[cfg(feature = "clipboard")]
+    if let Some(clipboard) = clipboard::Clipboard::new() {
+        imgui_context.set_clipboard_backend(clipboard);
+    }
 
     let mut winit_platform = imgui_winit_support::WinitPlatform::init(&mut imgui_context);
 
-    let hidpi_factor = winit_platform.hidpi_factor();
-    let font_size = (13.0 * hidpi_factor) as f32;
-    imgui_context
-        .fonts()
-        .add_font(&[imgui::FontSource::DefaultFontData {
+    let dpi_mode = imgui_winit_support::HiDpiMode::Rounded;
+    winit_platform.attach_window(imgui_context.io_mut(), window, dpi_mode);
+
+    build_fonts(
+        &mut imgui_context,
+        &options.fonts,
+        winit_platform.hidpi_factor(),
+    );
+
+    (winit_platform, imgui_context)
+}
+
+/// Replaces the fonts of the atlas, rasterized for the hidpi factor, e.g.
+/// once the window moved to a display with another scale factor. The atlas
+/// must then be uploaded again using `Renderer::reload_fonts()`.
+pub fn build_fonts(ctx: &mut imgui::Context, fonts: &[Font], hidpi_factor: f64) {
+    let atlas = ctx.fonts();
+    atlas.clear();
+    if fonts.is_empty() {
+        atlas.add_font(&[imgui::FontSource::DefaultFontData {
+            config: Some(FontConfig {
+                size_pixels: (DEFAULT_FONT_SIZE as f64 * hidpi_factor) as f32,
+                ..FontConfig::default()
+            }),
+        }]);
+    }
+    for font in fonts {
+        atlas.add_font(&[imgui::FontSource::TtfData {
+            data: &font.data,
+            size_pixels: (font.size as f64 * hidpi_factor) as f32,
             config: Some(FontConfig {
-                size_pixels: font_size,
-                ..imgui::FontConfig::default()
+                glyph_ranges: font.glyph_ranges.into(),
+                #[cfg(feature = "freetype")]
+                font_builder_flags: font.hinting.builder_flags(),
+                ..FontConfig::default()
             }),
         }]);
+    }
 
-    let dpi_mode = imgui_winit_support::HiDpiMode::Rounded;
-    winit_platform.attach_window(imgui_context.io_mut(), window, dpi_mode);
+    // NOTE: the fonts are rasterized in physical pixels, while the UI is
+    //       laid out in logical pixels
+    ctx.io_mut().font_global_scale = (1.0 / hidpi_factor) as f32;
+}
 
-    (winit_platform, imgui_context)
+/// TTF font of the UI, see `UiOptions`.
+#[derive(Clone, Debug)]
+pub struct Font {
+    data: Rc<[u8]>,
+    /// Size in logical pixels.
+    size: f32,
+    glyph_ranges: GlyphRanges,
+    #[cfg(feature = "freetype")]
+    hinting: Hinting,
+}
+
+impl Font {
+    /// Creates a font from the content of a TTF file, with the glyphs of
+    /// the default ranges.
+    pub fn from_ttf(data: impl Into<Rc<[u8]>>, size: f32) -> Self {
+        Self {
+            data: data.into(),
+            size,
+            glyph_ranges: GlyphRanges::default(),
+            #[cfg(feature = "freetype")]
+            hinting: Hinting::default(),
+        }
+    }
+
+    /// Reads a TTF file.
+    pub fn load(path: impl AsRef<Path>, size: f32) -> io::Result<Self> {
+        Ok(Self::from_ttf(fs::read(path)?, size))
+    }
+
+    pub fn with_glyph_ranges(mut self, glyph_ranges: GlyphRanges) -> Self {
+        self.glyph_ranges = glyph_ranges;
+        self
+    }
+
+    /// Sets how FreeType hints the glyphs, e.g. light hinting for small
+    /// sizes.
+    #[cfg(feature = "freetype")]
+    pub fn with_hinting(mut self, hinting: Hinting) -> Self {
+        self.hinting = hinting;
+        self
+    }
+
+    pub fn size(&self) -> f32 {
+        self.size
+    }
+}
+
+/// Hinting of the glyphs rasterized by FreeType, snapping their outlines
+/// to the pixel grid.
+#[cfg(feature = "freetype")]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Hinting {
+    /// Hinting of the font, or of the FreeType auto-hinter if it has none.
+    #[default]
+    Normal,
+    /// Lighter hinting of the auto-hinter, vertically only. Glyphs are
+    /// fuzzier but closer to their original shape.
+    Light,
+    /// Strong hinting, for monochrome rendering.
+    Mono,
+    /// Hinting of the auto-hinter, even for fonts hinted on their own.
+    ForceAuto,
+    /// Unhinted glyphs.
+    None,
+}
+
+#[cfg(feature = "freetype")]
+impl Hinting {
+    fn builder_flags(self) -> u32 {
+        use imgui::sys;
+
+        match self {
+            Self::Normal => 0,
+            Self::Light => sys::ImGuiFreeTypeBuilderFlags_LightHinting,
+            Self::Mono => sys::ImGuiFreeTypeBuilderFlags_MonoHinting,
+            Self::ForceAuto => sys::ImGuiFreeTypeBuilderFlags_ForceAutoHint,
+            Self::None => sys::ImGuiFreeTypeBuilderFlags_NoHinting,
+        }
+    }
+}
+
+/// Glyphs rasterized from a font.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum GlyphRanges {
+    /// Basic Latin and Latin-1 supplement.
+    #[default]
+    Default,
+    ChineseSimplifiedCommon,
+    ChineseFull,
+    Cyrillic,
+    Japanese,
+    Korean,
+    Thai,
+    Vietnamese,
+    /// Pairs of inclusive code point ranges, terminated by 0.
+    Custom(&'static [u32]),
+}
+
+impl From<GlyphRanges> for imgui::FontGlyphRanges {
+    fn from(glyph_ranges: GlyphRanges) -> Self {
+        match glyph_ranges {
+            GlyphRanges::Default => Self::default(),
+            GlyphRanges::ChineseSimplifiedCommon => Self::chinese_simplified_common(),
+            GlyphRanges::ChineseFull => Self::chinese_full(),
+            GlyphRanges::Cyrillic => Self::cyrillic(),
+            GlyphRanges::Japanese => Self::japanese(),
+            GlyphRanges::Korean => Self::korean(),
+            GlyphRanges::Thai => Self::thai(),
+            GlyphRanges::Vietnamese => Self::vietnamese(),
+            GlyphRanges::Custom(ranges) => Self::from_slice(ranges),
+        }
+    }
 }
 
 struct VertexInputDescription {
@@ -104,44 +283,178 @@ impl Vertex {
     }
 }
 
-#[derive(Clone, Debug, Copy)]
-struct UniformBuffer {
-    #[allow(unused)]
-    ortho: Matrix4<f32>,
+/// Transform of the vertices from imgui coordinates to clip space, given
+/// to the vertex shader as push constants.
+#[derive(Clone, Debug, Copy, PartialEq)]
+#[repr(C)]
+struct Transform {
+    scale: [f32; 2],
+    translate: [f32; 2],
 }
 
-impl UniformBuffer {
-    fn new(ortho: Matrix4<f32>) -> Self {
-        Self { ortho }
+impl Transform {
+    /// Maps the display rectangle of the draw data to the clip space.
+    ///
+    /// NOTE: the position is not at the origin when viewports are enabled,
+    ///       the vertices being in desktop coordinates
+    fn new(display_pos: [f32; 2], display_size: [f32; 2]) -> Self {
+        let scale = [2.0 / display_size[0], 2.0 / display_size[1]];
+        let translate = [
+            -1.0 - display_pos[0] * scale[0],
+            -1.0 - display_pos[1] * scale[1],
+        ];
+        Self { scale, translate }
+    }
+
+    fn as_bytes(&self) -> &[u8] {
+        // Safety: Transform is #[repr(C)] over f32s, without padding.
+        unsafe { slice::from_raw_parts(self as *const _ as *const u8, mem::size_of::<Self>()) }
     }
 }
 
+/// Minimum number of elements the vertex/index buffers are created with.
+const MIN_BUFFER_ELEMENTS: usize = 1024;
+
+/// Maximum number of textures registered at once, besides the font atlas.
+pub const MAX_USER_TEXTURES: u32 = 256;
+
 pub struct RenderData {
     fb_size: [f32; 2],
-    last_size: [f32; 2],
-    last_pos: [f32; 2],
+    transform: Transform,
+    draw_list_offsets: Vec<(i32, u32)>,
+    render: bool,
+    /// Index of the FrameBuffers slot used by this frame.
+    frame: usize,
+}
+
+/// Vertex and index buffers used by a single frame in flight.
+///
+/// Sizes are expressed in number of elements.
+#[derive(Default)]
+struct FrameBuffers {
     vertex_buffer: Option<Buffer>,
     vertex_buffer_size: usize,
     index_buffer: Option<Buffer>,
     index_buffer_size: usize,
-    draw_list_offsets: Vec<(i32, u32)>,
-    render: bool,
+}
+
+/// Buffers drawing the UI of a viewport.
+struct ViewportResources {
+    render_data: Option<RenderData>,
+
+    /// Ring of vertex/index buffers, one slot per frame in flight.
+    frames: Vec<FrameBuffers>,
+    frame_index: usize,
+}
+
+impl ViewportResources {
+    fn new(frames_in_flight: usize) -> Self {
+        Self {
+            render_data: None,
+            frames: (0..frames_in_flight)
+                .map(|_| FrameBuffers::default())
+                .collect(),
+            frame_index: 0,
+        }
+    }
+
+    fn prepare(
+        &mut self,
+        device: &Device,
+        draw_data: &DrawData,
+        render_data: Option<RenderData>,
+    ) -> Result<RenderData> {
+        let fb_width = draw_data.display_size[0] * draw_data.framebuffer_scale[0];
+        let fb_height = draw_data.display_size[1] * draw_data.framebuffer_scale[1];
+
+        let mut render_data = render_data.unwrap_or_else(|| RenderData {
+            fb_size: [fb_width, fb_height],
+            transform: Transform::new(draw_data.display_pos, draw_data.display_size),
+            draw_list_offsets: Vec::new(),
+            render: false,
+            frame: 0,
+        });
+
+        // If the render area is <= 0, exit here and now.
+        if fb_width <= 0.0 || fb_height <= 0.0 || draw_data.draw_lists_count() == 0 {
+            render_data.render = false;
+            return Ok(render_data);
+        } else {
+            render_data.render = true;
+        }
+
+        // use the next slot of the buffer ring
+        render_data.frame = self.frame_index;
+        self.frame_index = (self.frame_index + 1) % self.frames.len();
+
+        render_data.fb_size = [fb_width, fb_height];
+        render_data.transform = Transform::new(draw_data.display_pos, draw_data.display_size);
+
+        render_data.draw_list_offsets.clear();
+
+        let mut vertex_count = 0;
+        let mut index_count = 0;
+        for draw_list in draw_data.draw_lists() {
+            render_data
+                .draw_list_offsets
+                .push((vertex_count as i32, index_count as u32));
+            vertex_count += draw_list.vtx_buffer().len();
+            index_count += draw_list.idx_buffer().len();
+        }
+
+        let mut vertex_buffer_data =
+            Vec::with_capacity(vertex_count * std::mem::size_of::<Vertex>());
+        let mut index_buffer_data =
+            Vec::with_capacity(index_count * std::mem::size_of::<DrawIdx>());
+
+        for draw_list in draw_data.draw_lists() {
+            // Safety: Vertex is #[repr(transparent)] over DrawVert.
+            let vertex_data: &[Vertex] = unsafe { draw_list.transmute_vtx_buffer() };
+            vertex_buffer_data.extend_from_slice(vertex_data);
+            index_buffer_data.extend_from_slice(draw_list.idx_buffer());
+        }
+
+        // upload buffers to the slot of this frame
+        // NOTE: the slot was last used frames_in_flight frames ago. Its fence has
+        //       been waited on by the renderer, so the buffers are no longer in use
+        //       by the GPU and can be updated or replaced without waiting.
+        let frame_buffers = &mut self.frames[render_data.frame];
+        unsafe {
+            upload_buffer(
+                device,
+                &mut frame_buffers.index_buffer,
+                &mut frame_buffers.index_buffer_size,
+                vk::BufferUsageFlags::INDEX_BUFFER,
+                "imgui index buffer",
+                &index_buffer_data,
+            )
+            .map_err(|e| format!("upload index buffer: {:?}", e))?;
+            upload_buffer(
+                device,
+                &mut frame_buffers.vertex_buffer,
+                &mut frame_buffers.vertex_buffer_size,
+                vk::BufferUsageFlags::VERTEX_BUFFER,
+                "imgui vertex buffer",
+                &vertex_buffer_data,
+            )
+            .map_err(|e| format!("upload vertex buffer: {:?}", e))?;
+        }
+
+        Ok(render_data)
+    }
 }
 
 pub struct Renderer {
     /// The vertex and fragment shaders
-    vertex_shader: Shader,
-    fragment_shader: Shader,
+    /// NOTE: only kept alive until the renderer is dropped.
+    _vertex_shader: Shader,
+    _fragment_shader: Shader,
 
     // The descriptor pool used to allocate descriptor sets
     descriptor_pool: DescriptorPool,
 
-    // The descriptor set layout used to allocate descriptor sets
+    // The descriptor set layouts used to allocate descriptor sets
     descriptor_set_layouts: Vec<DescriptorSetLayout>,
-    descriptor_sets: Vec<DescriptorSet>,
-
-    /// Uniform buffer
-    uniform_buffer: Buffer,
 
     // Command Pool
     command_pool: vk::CommandPool,
@@ -149,9 +462,20 @@ pub struct Renderer {
     // Graphics pipeline
     pipeline: Pipeline,
 
-    render_data: Option<RenderData>,
+    /// Buffers drawing the main viewport.
+    main_viewport: ViewportResources,
+    /// Buffers drawing the other viewports, created when first drawn.
+    #[cfg(feature = "viewports")]
+    viewports: HashMap<imgui::Id, ViewportResources>,
+    #[cfg(feature = "viewports")]
+    frames_in_flight: usize,
+
+    textures: HandleMap<Texture>,
+    font_texture: TextureHandle,
+    /// Descriptor sets of the textures, including the font atlas.
+    texture_sets: HashMap<TextureHandle, DescriptorSet>,
 
-    textures: imgui::Textures<Texture>,
+    deletion_queue: DeletionQueueHandle,
 }
 
 impl Renderer {
@@ -159,11 +483,12 @@ impl Renderer {
         ctx: &mut imgui::Context,
         device: &Device,
         renderpass: &RenderPass,
+        frames_in_flight: u32,
     ) -> Result<Self> {
         // create shaders
         let (vertex_shader, fragment_shader) = {
-            let mut vert_file = Cursor::new(&include_bytes!("../shaders/imgui.vert.spv")[..]);
-            let mut frag_file = Cursor::new(&include_bytes!("../shaders/imgui.frag.spv")[..]);
+            let mut vert_file = Cursor::new(&include_shader!("imgui.vert")[..]);
+            let mut frag_file = Cursor::new(&include_shader!("imgui.frag")[..]);
 
             let vert = Shader::new(device, &mut vert_file)
                 .map_err(|e| format!("create vertex shader module: {:?}", e))?;
@@ -173,118 +498,67 @@ impl Renderer {
             (vert, frag)
         };
 
-        // create uniform buffer
-        let (uniform_buffer, uniform_buffer_data_size) = {
-            let buf_data = UniformBuffer::new(Matrix4::identity());
-            let buf_size = mem::size_of_val(&buf_data) as u64;
-            let mut buf = Buffer::new(
-                device,
-                device.memory_properties(),
-                vk::BufferUsageFlags::UNIFORM_BUFFER,
-                vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT,
-                buf_size,
-            )
-            .map_err(|e| format!("create uniform buffer: {:?}", e))?;
-            buf.update(device, &[buf_data])
-                .map_err(|e| format!("update uniform buffer: {:?}", e))?;
-            (buf, buf_size)
-        };
-
         // create command pool
         let command_pool = device
             .create_command_pool()
             .map_err(|e| format!("create command buffer pool: {:?}", e))?;
 
         // create imgui font texture
-        let mut textures = imgui::Textures::new();
-        let font_tex_id = reload_font_texture(device, ctx, &command_pool, &mut textures)
+        let mut textures = HandleMap::new();
+        let font_tex_handle = reload_font_texture(device, ctx, &command_pool, &mut textures)
             .map_err(|e| format!("load font texture: {:?}", e))?;
         let font_tex = textures
-            .get(font_tex_id)
+            .get(font_tex_handle)
             .expect("imgui font texture exists");
 
         // create descriptor pool
         let descriptor_pool = {
-            let descriptor_pool_sizes = [
-                vk::DescriptorPoolSize {
-                    ty: vk::DescriptorType::UNIFORM_BUFFER,
-                    descriptor_count: 1,
-                },
-                vk::DescriptorPoolSize {
-                    ty: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
-                    descriptor_count: 1,
-                },
-            ];
-            DescriptorPool::new(device, &descriptor_pool_sizes, 1)
-                .map_err(|e| format!("create descriptor pool: {:?}", e))?
+            // NOTE: the sets of the registered textures are freed individually,
+            //       once the frames in flight drawing them have completed. Until
+            //       then, the sets of all the textures can be replaced during
+            //       each of these frames.
+            let max_sets = (1 + MAX_USER_TEXTURES) * (1 + frames_in_flight);
+            let descriptor_pool_sizes = [vk::DescriptorPoolSize {
+                ty: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+                descriptor_count: max_sets,
+            }];
+            DescriptorPool::with_flags(
+                device,
+                &descriptor_pool_sizes,
+                max_sets,
+                vk::DescriptorPoolCreateFlags::FREE_DESCRIPTOR_SET,
+            )
+            .map_err(|e| format!("create descriptor pool: {:?}", e))?
         };
 
-        // create descriptor sets and layouts
-        let (descriptor_sets, descriptor_set_layouts) = {
-            let ds_layouts = {
-                let ds_layout_bindings = [
-                    vk::DescriptorSetLayoutBinding {
-                        binding: 0,
-                        descriptor_type: vk::DescriptorType::UNIFORM_BUFFER,
-                        descriptor_count: 1,
-                        stage_flags: vk::ShaderStageFlags::VERTEX,
-                        ..Default::default()
-                    },
-                    vk::DescriptorSetLayoutBinding {
-                        binding: 1,
-                        descriptor_type: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
-                        descriptor_count: 1,
-                        stage_flags: vk::ShaderStageFlags::FRAGMENT,
-                        ..Default::default()
-                    },
-                ];
-                let ds_layout = DescriptorSetLayout::new(device, &ds_layout_bindings)
+        // create descriptor set layouts
+        let descriptor_set_layouts = {
+            let descriptor_set_layout_bindings = [vk::DescriptorSetLayoutBinding {
+                binding: 0,
+                descriptor_type: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+                descriptor_count: 1,
+                stage_flags: vk::ShaderStageFlags::FRAGMENT,
+                ..Default::default()
+            }];
+            let descriptor_set_layout =
+                DescriptorSetLayout::new(device, &descriptor_set_layout_bindings)
                     .map_err(|e| format!("create descriptor set layout: {:?}", e))?;
-                vec![ds_layout]
-            };
-            let ds = DescriptorSet::new(device, &descriptor_pool, &ds_layouts)
-                .map_err(|e| format!("create UBO descriptor set: {:?}", e))?;
-
-            (ds, ds_layouts)
+            vec![descriptor_set_layout]
         };
 
-        let descriptor_set = descriptor_sets[0];
-
-        let buffer_info = vk::DescriptorBufferInfo {
-            buffer: *uniform_buffer.buffer(),
-            range: uniform_buffer_data_size,
-            offset: 0,
-        };
-        let image_info = vk::DescriptorImageInfo {
-            image_layout: vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
-            image_view: *font_tex.image_view(),
-            sampler: **font_tex.sampler(),
-        };
-        let descriptor_writes = &[
-            vk::WriteDescriptorSet {
-                dst_set: *descriptor_set,
-                descriptor_count: 1,
-                descriptor_type: vk::DescriptorType::UNIFORM_BUFFER,
-                p_buffer_info: &buffer_info,
-                ..Default::default()
-            },
-            vk::WriteDescriptorSet {
-                dst_set: *descriptor_set,
-                dst_binding: 1,
-                descriptor_count: 1,
-                descriptor_type: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
-                p_image_info: &image_info,
-                ..Default::default()
-            },
-        ];
-        descriptor_set
-            .update(device, descriptor_writes)
-            .map_err(|e| format!("update descriptor set: {:?}", e))?;
+        // create the descriptor set of the font
+        let font_set =
+            create_texture_set(device, &descriptor_pool, &descriptor_set_layouts, font_tex)?;
 
         // create graphics pipeline
         let pipeline = {
             let vertex_input_description = Vertex::input_description();
-            Pipeline::new(
+            let push_constant_ranges = [vk::PushConstantRange {
+                stage_flags: vk::ShaderStageFlags::VERTEX,
+                offset: 0,
+                size: mem::size_of::<Transform>() as u32,
+            }];
+            Pipeline::new_with_push_constants(
                 device,
                 renderpass,
                 &vertex_shader,
@@ -292,178 +566,120 @@ impl Renderer {
                 &vertex_input_description.bindings,
                 &vertex_input_description.attributes,
                 &descriptor_set_layouts,
+                &push_constant_ranges,
             )
             .map_err(|e| format!("create pipeline and layout: {:?}", e))?
         };
+        pipeline.set_name(device, "imgui");
 
         let renderer = Self {
-            vertex_shader,
-            fragment_shader,
+            _vertex_shader: vertex_shader,
+            _fragment_shader: fragment_shader,
             descriptor_pool,
             descriptor_set_layouts,
-            descriptor_sets,
-            uniform_buffer,
             command_pool,
             pipeline,
-            render_data: None,
+            main_viewport: ViewportResources::new(frames_in_flight as usize),
+            #[cfg(feature = "viewports")]
+            viewports: HashMap::new(),
+            #[cfg(feature = "viewports")]
+            frames_in_flight: frames_in_flight as usize,
             textures,
+            font_texture: font_tex_handle,
+            texture_sets: HashMap::from([(font_tex_handle, font_set)]),
+            deletion_queue: device.deletion_queue(),
         };
 
         Ok(renderer)
     }
 
-    pub fn prepare(
+    /// Registers a texture to be drawn in the UI, e.g. with `Ui::image()`,
+    /// returning its id. The texture must be in the SHADER_READ_ONLY_OPTIMAL
+    /// layout. Registering fails when the sets of the textures unregistered
+    /// during the frames in flight exhaust the pool, which happens when more
+    /// textures than `MAX_USER_TEXTURES` are replaced every frame.
+    pub unsafe fn register_texture(
         &mut self,
         device: &Device,
-        draw_data: &DrawData,
-        render_data: Option<RenderData>,
-    ) -> Result<RenderData> {
-        let fb_width = draw_data.display_size[0] * draw_data.framebuffer_scale[0];
-        let fb_height = draw_data.display_size[1] * draw_data.framebuffer_scale[1];
-
-        let mut render_data = render_data.unwrap_or_else(|| RenderData {
-            fb_size: [fb_width, fb_height],
-            last_size: [0.0, 0.0],
-            last_pos: [0.0, 0.0],
-            vertex_buffer: None,
-            vertex_buffer_size: 0,
-            index_buffer: None,
-            index_buffer_size: 0,
-            draw_list_offsets: Vec::new(),
-            render: false,
-        });
-
-        // If the render area is <= 0, exit here and now.
-        if fb_width <= 0.0 || fb_height <= 0.0 || draw_data.draw_lists_count() == 0 {
-            render_data.render = false;
-            return Ok(render_data);
-        } else {
-            render_data.render = true;
-        }
-
-        // Only update matrices if the size or position changes
-        if (render_data.last_size[0] - draw_data.display_size[0]).abs() > f32::EPSILON
-            || (render_data.last_size[1] - draw_data.display_size[1]).abs() > f32::EPSILON
-            || (render_data.last_pos[0] - draw_data.display_pos[0]).abs() > f32::EPSILON
-            || (render_data.last_pos[1] - draw_data.display_pos[1]).abs() > f32::EPSILON
-        {
-            render_data.fb_size = [fb_width, fb_height];
-            render_data.last_size = draw_data.display_size;
-            render_data.last_pos = draw_data.display_pos;
-
-            let width = draw_data.display_size[0];
-            let height = draw_data.display_size[1];
-
-            // Create and update the transform matrix for the current frame.
-            // This is required to adapt to vulkan coordinates.
-            unsafe {
-                let ortho = cgmath::ortho(0.0, width, 0.0, height, -1.0, 1.0);
-                let ubo = UniformBuffer::new(ortho);
-                self.uniform_buffer
-                    .update(device, &[ubo])
-                    .map_err(|e| format!("update uniform buffer: {:?}", e))?;
-            }
-        }
-
-        render_data.draw_list_offsets.clear();
-
-        let mut vertex_count = 0;
-        let mut index_count = 0;
-        for draw_list in draw_data.draw_lists() {
-            render_data
-                .draw_list_offsets
-                .push((vertex_count as i32, index_count as u32));
-            vertex_count += draw_list.vtx_buffer().len();
-            index_count += draw_list.idx_buffer().len();
+        texture: Texture,
+    ) -> Result<imgui::TextureId> {
+        // NOTE: the font atlas has a set of its own
+        if self.texture_sets.len() as u32 > MAX_USER_TEXTURES {
+            return Err(format!("more than {MAX_USER_TEXTURES} textures registered").into());
         }
+        let descriptor_set = create_texture_set(
+            device,
+            &self.descriptor_pool,
+            &self.descriptor_set_layouts,
+            &texture,
+        )?;
 
-        let mut vertex_buffer_data =
-            Vec::with_capacity(vertex_count * std::mem::size_of::<Vertex>());
-        let mut index_buffer_data =
-            Vec::with_capacity(index_count * std::mem::size_of::<DrawIdx>());
+        let handle = self.textures.insert(texture);
+        self.texture_sets.insert(handle, descriptor_set);
+        Ok(texture_id(handle))
+    }
 
-        for draw_list in draw_data.draw_lists() {
-            // Safety: Vertex is #[repr(transparent)] over DrawVert.
-            let vertex_data: &[Vertex] = unsafe { draw_list.transmute_vtx_buffer() };
-            vertex_buffer_data.extend_from_slice(vertex_data);
-            index_buffer_data.extend_from_slice(draw_list.idx_buffer());
+    /// Unregisters a texture, returning it. Its descriptor set is freed once
+    /// the frames drawing it have completed. The font atlas can not be
+    /// unregistered.
+    pub fn unregister_texture(&mut self, id: imgui::TextureId) -> Option<Texture> {
+        let handle = texture_handle(id);
+        if handle == self.font_texture {
+            return None;
         }
+        let descriptor_set = self.texture_sets.remove(&handle)?;
+        self.deletion_queue
+            .borrow_mut()
+            .push(Resource::DescriptorSet(
+                *self.descriptor_pool,
+                *descriptor_set,
+            ));
+        self.textures.remove(handle)
+    }
 
-        // If the buffer is not created or is too small for the new indices, create a
-        // new buffer
-        if render_data.index_buffer.is_none()
-            || render_data.index_buffer_size < index_buffer_data.len()
-        {
-            unsafe {
-                let index_buffer_data_size = mem::size_of_val(&*index_buffer_data) as u64;
-                let mut index_buffer = Buffer::new(
-                    device,
-                    device.memory_properties(),
-                    vk::BufferUsageFlags::INDEX_BUFFER,
-                    vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT,
-                    index_buffer_data_size,
-                )
-                .map_err(|e| format!("create index buffer: {:?}", e))?;
-                index_buffer
-                    .update(device, &index_buffer_data)
-                    .map_err(|e| format!("update index buffer: {:?}", e))?;
-
-                if let Some(mut index_buffer) = render_data.index_buffer.take() {
-                    device.device_wait_idle().expect("device wait idle");
-                    index_buffer.destroy(device);
-                }
-                render_data.index_buffer = Some(index_buffer);
-                render_data.index_buffer_size = index_buffer_data.len();
-            }
-        } else if let Some(buffer) = render_data.index_buffer.as_mut() {
-            // The buffer is large enough for the new indices, so reuse it
-            unsafe {
-                buffer
-                    .update(device, &index_buffer_data)
-                    .map_err(|e| format!("update index buffer: {:?}", e))?
-            }
-        } else {
-            unreachable!()
-        }
+    fn descriptor_set(&self, id: imgui::TextureId) -> Option<DescriptorSet> {
+        self.texture_sets.get(&texture_handle(id)).copied()
+    }
 
-        // If the buffer is not created or is too small for the new vertices, create a
-        // new buffer
-        if render_data.vertex_buffer.is_none()
-            || render_data.vertex_buffer_size < vertex_buffer_data.len()
-        {
-            unsafe {
-                let vertex_buffer_data_size = mem::size_of_val(&*vertex_buffer_data) as u64;
-                let mut vertex_buffer = Buffer::new(
-                    device,
-                    device.memory_properties(),
-                    vk::BufferUsageFlags::VERTEX_BUFFER,
-                    vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT,
-                    vertex_buffer_data_size,
-                )
-                .map_err(|e| format!("create vertex buffer: {:?}", e))?;
-                vertex_buffer
-                    .update(device, &vertex_buffer_data)
-                    .map_err(|e| format!("update vertex buffer: {:?}", e))?;
-
-                if let Some(mut vertex_buffer) = render_data.vertex_buffer.take() {
-                    device.device_wait_idle().expect("device wait idle");
-                    vertex_buffer.destroy(device);
-                }
-                render_data.vertex_buffer = Some(vertex_buffer);
-                render_data.vertex_buffer_size = vertex_buffer_data.len();
-            }
-        } else if let Some(buffer) = render_data.vertex_buffer.as_mut() {
-            // The buffer is large enough for the new indices, so reuse it
-            unsafe {
-                buffer
-                    .update(device, &vertex_buffer_data)
-                    .map_err(|e| format!("update vertex buffer: {:?}", e))?;
-            }
-        } else {
-            unreachable!()
+    /// Uploads the font atlas again, e.g. once its fonts were replaced using
+    /// `build_fonts()`. The previous atlas is destroyed once the frames
+    /// drawing it have completed.
+    pub unsafe fn reload_fonts(&mut self, device: &Device, ctx: &mut imgui::Context) -> Result<()> {
+        if let Some(descriptor_set) = self.texture_sets.remove(&self.font_texture) {
+            self.deletion_queue
+                .borrow_mut()
+                .push(Resource::DescriptorSet(
+                    *self.descriptor_pool,
+                    *descriptor_set,
+                ));
         }
+        self.textures.remove(self.font_texture);
+
+        let font_tex_handle =
+            reload_font_texture(device, ctx, &self.command_pool, &mut self.textures)
+                .map_err(|e| format!("load font texture: {:?}", e))?;
+        let font_tex = self
+            .textures
+            .get(font_tex_handle)
+            .expect("imgui font texture exists");
+        let font_set = create_texture_set(
+            device,
+            &self.descriptor_pool,
+            &self.descriptor_set_layouts,
+            font_tex,
+        )?;
+        self.font_texture = font_tex_handle;
+        self.texture_sets.insert(font_tex_handle, font_set);
+        Ok(())
+    }
 
-        Ok(render_data)
+    pub fn prepare(
+        &mut self,
+        device: &Device,
+        draw_data: &DrawData,
+        render_data: Option<RenderData>,
+    ) -> Result<RenderData> {
+        self.main_viewport.prepare(device, draw_data, render_data)
     }
 
     pub unsafe fn render(
@@ -472,7 +688,12 @@ impl Renderer {
         command_buffer: vk::CommandBuffer,
         draw_data: &DrawData,
     ) -> Result<()> {
-        let render_data = self.render_data.take();
+        // nothing to draw, keep the buffers of the ring untouched
+        if draw_data.total_vtx_count == 0 {
+            return Ok(());
+        }
+
+        let render_data = self.main_viewport.render_data.take();
         let render_data = Some(self.prepare(device, draw_data, render_data)?);
         self.split_render(
             device,
@@ -480,46 +701,112 @@ impl Renderer {
             draw_data,
             render_data.as_ref().unwrap(),
         )?;
-        self.render_data = render_data;
+        self.main_viewport.render_data = render_data;
 
         Ok(())
     }
 
+    /// Draws the UI of a viewport other than the main one, e.g. to the
+    /// window created for it by `viewports::Viewports`. Its resources are
+    /// created on the first call.
+    #[cfg(feature = "viewports")]
+    pub unsafe fn render_viewport(
+        &mut self,
+        device: &Device,
+        command_buffer: vk::CommandBuffer,
+        id: imgui::Id,
+        draw_data: &DrawData,
+    ) -> Result<()> {
+        if draw_data.total_vtx_count == 0 {
+            return Ok(());
+        }
+
+        let mut resources = self
+            .viewports
+            .remove(&id)
+            .unwrap_or_else(|| ViewportResources::new(self.frames_in_flight));
+        // NOTE: the resources are put back even if drawing fails, to be
+        //       destroyed along with the viewport
+        let render_data = resources.render_data.take();
+        let result = resources
+            .prepare(device, draw_data, render_data)
+            .and_then(|render_data| {
+                self.record(device, command_buffer, &resources, draw_data, &render_data)?;
+                resources.render_data = Some(render_data);
+                Ok(())
+            });
+        self.viewports.insert(id, resources);
+
+        result
+    }
+
+    /// Destroys the buffers of a viewport once the frames drawing it have
+    /// completed, e.g. when its window is closed.
+    #[cfg(feature = "viewports")]
+    pub fn remove_viewport(&mut self, id: imgui::Id) {
+        self.viewports.remove(&id);
+    }
+
     pub unsafe fn split_render(
         &mut self,
         device: &Device,
         command_buffer: vk::CommandBuffer,
         draw_data: &DrawData,
         render_data: &RenderData,
+    ) -> Result<()> {
+        self.record(
+            device,
+            command_buffer,
+            &self.main_viewport,
+            draw_data,
+            render_data,
+        )
+    }
+
+    unsafe fn record(
+        &self,
+        device: &Device,
+        command_buffer: vk::CommandBuffer,
+        viewport: &ViewportResources,
+        draw_data: &DrawData,
+        render_data: &RenderData,
     ) -> Result<()> {
         if !render_data.render {
             return Ok(());
         }
 
-        // bind descriptor sets
-        device.cmd_bind_descriptor_sets(
-            command_buffer,
-            vk::PipelineBindPoint::GRAPHICS,
-            self.pipeline.layout,
-            0,
-            &[*self.descriptor_sets[0]],
-            &[],
-        );
-
         // bind pipeline
+        // NOTE: the descriptor set of each texture is bound while drawing
         device.cmd_bind_pipeline(
             command_buffer,
             vk::PipelineBindPoint::GRAPHICS,
             *self.pipeline,
         );
 
+        // set the transform of the viewport
+        device.cmd_push_constants(
+            command_buffer,
+            self.pipeline.layout,
+            vk::ShaderStageFlags::VERTEX,
+            0,
+            render_data.transform.as_bytes(),
+        );
+
+        let frame_buffers = &viewport.frames[render_data.frame];
+
         // bind vertex buffers
-        let vertex_buffer = render_data.vertex_buffer.expect("vertex buffer is set");
-        device.cmd_bind_vertex_buffers(command_buffer, 0, &[*vertex_buffer], &[0]);
+        let vertex_buffer = frame_buffers
+            .vertex_buffer
+            .as_ref()
+            .expect("vertex buffer is set");
+        device.cmd_bind_vertex_buffers(command_buffer, 0, &[**vertex_buffer], &[0]);
 
         // bind index buffer
-        let index_buffer = render_data.index_buffer.expect("index buffer is set");
-        device.cmd_bind_index_buffer(command_buffer, *index_buffer, 0, vk::IndexType::UINT16);
+        let index_buffer = frame_buffers
+            .index_buffer
+            .as_ref()
+            .expect("index buffer is set");
+        device.cmd_bind_index_buffer(command_buffer, **index_buffer, 0, vk::IndexType::UINT16);
 
         // Execute all the imgui render work.
         for (draw_list, bases) in draw_data
@@ -542,7 +829,7 @@ impl Renderer {
 
     #[allow(clippy::too_many_arguments)]
     pub unsafe fn render_draw_list(
-        &mut self,
+        &self,
         device: &Device,
         command_buffer: vk::CommandBuffer,
         draw_list: &DrawList,
@@ -552,6 +839,7 @@ impl Renderer {
         (vertex_base, index_base): (i32, u32),
     ) -> Result<()> {
         let mut start = index_base;
+        let mut bound_set = None;
 
         for cmd in draw_list.commands() {
             if let Elements { count, cmd_params } = cmd {
@@ -564,24 +852,49 @@ impl Renderer {
 
                 let end = start + count as u32;
 
-                if clip_rect[0] < fb_size[0]
+                let visible = clip_rect[0] < fb_size[0]
                     && clip_rect[1] < fb_size[1]
                     && clip_rect[2] >= 0.0
-                    && clip_rect[3] >= 0.0
-                {
-                    // set scissor
-                    let scissor = vk::Rect2D {
-                        offset: vk::Offset2D {
-                            x: clip_rect[0].max(0.0).floor() as i32,
-                            y: clip_rect[1].max(0.0).floor() as i32,
-                        },
-                        extent: vk::Extent2D {
-                            width: (clip_rect[2] - clip_rect[0]).abs().ceil() as u32,
-                            height: (clip_rect[3] - clip_rect[1]).abs().ceil() as u32,
-                        },
-                    };
-                    device.cmd_set_scissor(command_buffer, 0, &[scissor]);
-                    device.cmd_draw_indexed(command_buffer, count as u32, 1, start, vertex_base, 0);
+                    && clip_rect[3] >= 0.0;
+
+                // NOTE: the commands of unregistered textures are skipped
+                match self.descriptor_set(cmd_params.texture_id) {
+                    Some(descriptor_set) if visible => {
+                        // bind the descriptor set of the texture
+                        if bound_set != Some(descriptor_set) {
+                            device.cmd_bind_descriptor_sets(
+                                command_buffer,
+                                vk::PipelineBindPoint::GRAPHICS,
+                                self.pipeline.layout,
+                                0,
+                                &[*descriptor_set],
+                                &[],
+                            );
+                            bound_set = Some(descriptor_set);
+                        }
+
+                        // set scissor
+                        let scissor = vk::Rect2D {
+                            offset: vk::Offset2D {
+                                x: clip_rect[0].max(0.0).floor() as i32,
+                                y: clip_rect[1].max(0.0).floor() as i32,
+                            },
+                            extent: vk::Extent2D {
+                                width: (clip_rect[2] - clip_rect[0]).abs().ceil() as u32,
+                                height: (clip_rect[3] - clip_rect[1]).abs().ceil() as u32,
+                            },
+                        };
+                        device.cmd_set_scissor(command_buffer, 0, &[scissor]);
+                        device.cmd_draw_indexed(
+                            command_buffer,
+                            count as u32,
+                            1,
+                            start,
+                            vertex_base,
+                            0,
+                        );
+                    }
+                    _ => (),
                 }
 
                 // Increment the index regardless of whether or not this batch
@@ -592,43 +905,69 @@ impl Renderer {
 
         Ok(())
     }
+}
 
-    pub unsafe fn destroy(&mut self, device: &Device, ctx: &mut imgui::Context) {
+impl Drop for Renderer {
+    fn drop(&mut self) {
         debug!("Destroying imgui::Renderer");
 
-        // NOTE: All submitted commands that refer to these resources must have
-        // completed execution.
-        device.device_wait_idle().expect("device wait idle");
+        self.deletion_queue
+            .borrow_mut()
+            .push(Resource::CommandPool(self.command_pool));
+    }
+}
 
-        // buffers
-        if let Some(render_data) = self.render_data.take() {
-            if let Some(mut buf) = render_data.vertex_buffer {
-                buf.destroy(device);
-            }
-            if let Some(mut buf) = render_data.index_buffer {
-                buf.destroy(device);
-            }
-        }
-        // pipeline
-        self.pipeline.destroy(device);
-        // command pool
-        device.destroy_command_pool(self.command_pool, None);
-        // uniform buffer
-        self.uniform_buffer.destroy(device);
-        // font atlas texture
-        if let Some(mut tex) = self.textures.remove(ctx.fonts().tex_id) {
-            tex.destroy(device);
-        }
-        // descriptor set layout
-        for mut layout in &mut self.descriptor_set_layouts.drain(..) {
-            layout.destroy(device);
-        }
-        // descriptor pool
-        self.descriptor_pool.destroy(device);
-        // shaders
-        self.vertex_shader.destroy(device);
-        self.fragment_shader.destroy(device);
+/// Allocates the descriptor set sampling a texture.
+unsafe fn create_texture_set(
+    device: &Device,
+    descriptor_pool: &DescriptorPool,
+    layouts: &[DescriptorSetLayout],
+    texture: &Texture,
+) -> Result<DescriptorSet> {
+    let descriptor_set = DescriptorSet::new(device, descriptor_pool, layouts)
+        .map_err(|e| format!("create texture descriptor set: {:?}", e))?[0];
+    descriptor_set
+        .update_image(device, 0, *texture.image_view(), **texture.sampler())
+        .map_err(|e| format!("update texture descriptor set: {:?}", e))?;
+    Ok(descriptor_set)
+}
+
+/// Writes data into the provided buffer, replacing it with a bigger one if it
+/// is too small. New buffers are sized with headroom to avoid recreating them
+/// every time the UI grows a little.
+///
+/// The buffer must not be in use by the GPU.
+unsafe fn upload_buffer<T: Copy>(
+    device: &Device,
+    buffer: &mut Option<Buffer>,
+    buffer_size: &mut usize,
+    usage: vk::BufferUsageFlags,
+    name: &str,
+    data: &[T],
+) -> Result<()> {
+    if buffer.is_none() || *buffer_size < data.len() {
+        let size = (data.len() + data.len() / 2).max(MIN_BUFFER_ELEMENTS);
+        let new_buffer = Buffer::new(
+            device,
+            usage,
+            vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT,
+            (size * mem::size_of::<T>()) as u64,
+        )
+        .map_err(|e| format!("create buffer: {:?}", e))?;
+        device.set_object_name(*new_buffer, name);
+
+        // NOTE: the old buffer is destroyed once the frames using it are done
+        *buffer = Some(new_buffer);
+        *buffer_size = size;
     }
+
+    buffer
+        .as_mut()
+        .expect("buffer exists")
+        .update(data)
+        .map_err(|e| format!("update buffer: {:?}", e))?;
+
+    Ok(())
 }
 
 /// Updates the texture on the GPU corresponding to the current imgui font
@@ -639,13 +978,11 @@ pub unsafe fn reload_font_texture(
     device: &Device,
     ctx: &mut imgui::Context,
     command_pool: &vk::CommandPool,
-    textures: &mut imgui::Textures<Texture>,
-) -> Result<imgui::TextureId> {
+    textures: &mut HandleMap<Texture>,
+) -> Result<TextureHandle> {
     let mut fonts = ctx.fonts();
     // Remove possible font atlas texture.
-    if let Some(mut tex) = textures.remove(fonts.tex_id) {
-        tex.destroy(device);
-    }
+    textures.remove(texture_handle(fonts.tex_id));
 
     // Create font texture and upload it.
     let handle = fonts.build_rgba32_texture();
@@ -664,20 +1001,29 @@ pub unsafe fn reload_font_texture(
         .tiling(vk::ImageTiling::OPTIMAL)
         .usage(vk::ImageUsageFlags::TRANSFER_DST | vk::ImageUsageFlags::SAMPLED)
         .sharing_mode(vk::SharingMode::EXCLUSIVE);
-    let mut font_image = Image::new(
-        device,
-        device.memory_properties(),
-        *create_info,
-        vk::MemoryPropertyFlags::DEVICE_LOCAL,
-    )?;
+    let mut font_image = Image::new(device, *create_info, vk::MemoryPropertyFlags::DEVICE_LOCAL)?;
+    device.set_object_name(*font_image, "imgui font atlas");
     font_image
         .upload_gpu(device, *command_pool, handle.data)
         .map_err(|e| format!("update font texture data: {:?}", e))?;
     let font_texture = Texture::from_image(device, font_image)?;
-    fonts.tex_id = textures.insert(font_texture);
+    let font_texture_handle = textures.insert(font_texture);
+    fonts.tex_id = texture_id(font_texture_handle);
 
     // Clear imgui texture data to save memory.
     fonts.clear_tex_data();
 
-    Ok(fonts.tex_id)
+    Ok(font_texture_handle)
+}
+
+/// Converts a texture handle into the opaque id understood by imgui.
+fn texture_id(handle: TextureHandle) -> imgui::TextureId {
+    imgui::TextureId::new(handle.to_bits() as usize)
+}
+
+/// Converts an imgui texture id back into a texture handle.
+///
+/// NOTE: stale ids resolve to a stale handle, which is rejected by HandleMap.
+fn texture_handle(id: imgui::TextureId) -> TextureHandle {
+    TextureHandle::from_bits(id.id() as u64)
 }
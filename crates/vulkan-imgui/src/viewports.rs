@@ -0,0 +1,429 @@
+//! Imgui windows dragged outside the main window get an OS window of their
+//! own, drawn to using `Renderer::render_viewport()`.
+//!
+//! Imgui asks for the windows of its viewports from within
+//! `Context::update_platform_windows()`, while the event loop is needed to
+//! create them. The platform backend thus queues the requests, which are
+//! carried out by `Viewports::update()` once the frame is built.
+//!
+//! Positions and sizes are in the logical pixels of imgui, relative to the
+//! desktop.
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::mem;
+use std::rc::Rc;
+
+use ash::vk;
+use imgui::{
+    BackendFlags, ConfigFlags, Id, PlatformMonitor, PlatformViewportBackend, Viewport,
+    ViewportFlags,
+};
+use imgui_winit_support::WinitPlatform;
+use log::{debug, error};
+use vulkan_renderer::renderer::VulkanRenderer;
+use winit::dpi::{LogicalPosition, LogicalSize, PhysicalPosition, PhysicalSize};
+use winit::event::{Event, WindowEvent};
+use winit::event_loop::EventLoopWindowTarget;
+use winit::window::{Window, WindowBuilder, WindowId};
+
+use crate::{Renderer, Result};
+
+/// Windows of the viewports, along with the platform backend of imgui.
+pub struct Viewports {
+    shared: Rc<RefCell<Shared>>,
+    windows: HashMap<Id, Window>,
+    hidpi_factor: f64,
+}
+
+impl Viewports {
+    /// Enables the viewports of the context, whose main viewport is drawn to
+    /// main_window.
+    pub fn new(ctx: &mut imgui::Context, platform: &WinitPlatform, main_window: &Window) -> Self {
+        let io = ctx.io_mut();
+        io.config_flags.insert(ConfigFlags::VIEWPORTS_ENABLE);
+        io.backend_flags
+            .insert(BackendFlags::PLATFORM_HAS_VIEWPORTS | BackendFlags::RENDERER_HAS_VIEWPORTS);
+
+        let shared = Rc::new(RefCell::new(Shared::default()));
+        ctx.set_platform_backend(Backend {
+            shared: Rc::clone(&shared),
+        });
+
+        let viewports = Self {
+            shared,
+            windows: HashMap::new(),
+            hidpi_factor: platform.hidpi_factor(),
+        };
+        viewports.shared.borrow_mut().main = WindowState {
+            pos: viewports.window_pos(main_window),
+            size: viewports.logical_size(main_window.inner_size()),
+            focused: true,
+            minimized: false,
+        };
+        viewports.refresh_monitors(ctx, main_window);
+        viewports
+    }
+
+    /// Gives imgui the monitors the viewports can be moved to.
+    pub fn refresh_monitors(&self, ctx: &mut imgui::Context, window: &Window) {
+        let monitors: Vec<_> = window
+            .available_monitors()
+            .map(|monitor| {
+                let PhysicalPosition { x, y } = monitor.position();
+                let pos = self.logical_pos(PhysicalPosition::new(x as f64, y as f64));
+                let size = self.logical_size(monitor.size());
+                PlatformMonitor {
+                    main_pos: pos,
+                    main_size: size,
+                    work_pos: pos,
+                    work_size: size,
+                    dpi_scale: 1.0,
+                }
+            })
+            .collect();
+        ctx.platform_io_mut().monitors.replace_from_slice(&monitors);
+    }
+
+    /// Updates imgui with an event. Events of the main window must be given
+    /// to the winit platform first. Returns whether the event is an event of
+    /// the window of a viewport.
+    pub fn handle_event<T>(
+        &mut self,
+        ctx: &mut imgui::Context,
+        platform: &mut WinitPlatform,
+        main_window: &Window,
+        event: &Event<T>,
+    ) -> bool {
+        let Event::WindowEvent {
+            window_id,
+            event: window_event,
+        } = event
+        else {
+            return false;
+        };
+
+        if *window_id == main_window.id() {
+            let mut shared = self.shared.borrow_mut();
+            match window_event {
+                WindowEvent::Moved(_) => shared.main.pos = self.window_pos(main_window),
+                WindowEvent::Resized(size) => {
+                    shared.main.size = self.logical_size(*size);
+                    shared.main.minimized = size.width == 0 || size.height == 0;
+                }
+                WindowEvent::Focused(focused) => shared.main.focused = *focused,
+                // NOTE: imgui expects the mouse position relative to the desktop
+                WindowEvent::CursorMoved { position, .. } => {
+                    let [x, y] = self.logical_pos(*position);
+                    ctx.io_mut()
+                        .add_mouse_pos_event([shared.main.pos[0] + x, shared.main.pos[1] + y]);
+                }
+                _ => (),
+            }
+            return false;
+        }
+
+        let Some(id) = self.viewport_of(*window_id) else {
+            return false;
+        };
+        let window = &self.windows[&id];
+        let mut shared = self.shared.borrow_mut();
+        let Some(state) = shared.states.get_mut(&id) else {
+            return true;
+        };
+        match window_event {
+            WindowEvent::CloseRequested => {
+                if let Some(viewport) = ctx.viewport_by_id_mut(id) {
+                    viewport.platform_request_close = true;
+                }
+            }
+            WindowEvent::Moved(_) => {
+                state.pos = self.window_pos(window);
+                if let Some(viewport) = ctx.viewport_by_id_mut(id) {
+                    viewport.platform_request_move = true;
+                }
+            }
+            WindowEvent::Resized(size) => {
+                state.size = self.logical_size(*size);
+                state.minimized = size.width == 0 || size.height == 0;
+                if let Some(viewport) = ctx.viewport_by_id_mut(id) {
+                    viewport.platform_request_resize = true;
+                }
+            }
+            WindowEvent::Focused(focused) => state.focused = *focused,
+            WindowEvent::CursorMoved { position, .. } => {
+                let [x, y] = self.logical_pos(*position);
+                let [left, top] = state.pos;
+                ctx.io_mut().add_mouse_pos_event([left + x, top + y]);
+            }
+            // NOTE: the other events, e.g. resizes, are about the main window
+            //       for the winit platform
+            WindowEvent::KeyboardInput { .. }
+            | WindowEvent::ReceivedCharacter(_)
+            | WindowEvent::ModifiersChanged(_)
+            | WindowEvent::MouseInput { .. }
+            | WindowEvent::MouseWheel { .. } => platform.handle_event(ctx.io_mut(), window, event),
+            _ => (),
+        }
+        true
+    }
+
+    /// Creates, updates and destroys the windows of the viewports as
+    /// requested by imgui. Must be called after
+    /// `Context::update_platform_windows()`.
+    pub unsafe fn update<T>(
+        &mut self,
+        target: &EventLoopWindowTarget<T>,
+        vulkan_renderer: &mut VulkanRenderer,
+        renderer: &mut Renderer,
+    ) -> Result<()> {
+        let requests = mem::take(&mut self.shared.borrow_mut().requests);
+        for request in requests {
+            match request {
+                Request::Create {
+                    id,
+                    pos,
+                    size,
+                    decorations,
+                } => {
+                    // NOTE: the window is hidden until imgui shows it
+                    let window = WindowBuilder::new()
+                        .with_title("imgui")
+                        .with_visible(false)
+                        .with_decorations(decorations)
+                        .with_position(self.physical_pos(pos))
+                        .with_inner_size(self.physical_size(size))
+                        .build(target)
+                        .map_err(|e| format!("create viewport window: {e}"))?;
+                    vulkan_renderer
+                        .add_window(&window)
+                        .map_err(|e| format!("add viewport window: {e:?}"))?;
+                    debug!("created window {:?} of viewport {id:?}", window.id());
+                    self.windows.insert(id, window);
+                }
+                Request::Destroy(id) => {
+                    if let Some(window) = self.windows.remove(&id) {
+                        renderer.remove_viewport(id);
+                        vulkan_renderer
+                            .remove_window(window.id())
+                            .map_err(|e| format!("remove viewport window: {e:?}"))?;
+                    }
+                }
+                Request::Show(id) => {
+                    if let Some(window) = self.windows.get(&id) {
+                        window.set_visible(true);
+                    }
+                }
+                // NOTE: winit positions the outer window, imgui its content
+                Request::SetPos(id, pos) => {
+                    if let Some(window) = self.windows.get(&id) {
+                        window.set_outer_position(self.physical_pos(pos));
+                    }
+                }
+                Request::SetSize(id, size) => {
+                    if let Some(window) = self.windows.get(&id) {
+                        window.set_inner_size(self.physical_size(size));
+                    }
+                }
+                Request::Focus(id) => {
+                    if let Some(window) = self.windows.get(&id) {
+                        window.focus_window();
+                    }
+                }
+                Request::SetTitle(id, title) => {
+                    if let Some(window) = self.windows.get(&id) {
+                        window.set_title(&title);
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Stops drawing to the windows of the viewports, before they are
+    /// destroyed.
+    pub unsafe fn destroy(
+        &mut self,
+        vulkan_renderer: &mut VulkanRenderer,
+        renderer: &mut Renderer,
+    ) {
+        for (id, window) in self.windows.drain() {
+            renderer.remove_viewport(id);
+            if let Err(e) = vulkan_renderer.remove_window(window.id()) {
+                error!("remove viewport window: {e:?}");
+            }
+        }
+    }
+
+    /// Returns the viewport drawn to a window.
+    pub fn viewport_of(&self, window_id: WindowId) -> Option<Id> {
+        self.windows
+            .iter()
+            .find(|(_, window)| window.id() == window_id)
+            .map(|(id, _)| *id)
+    }
+
+    /// Returns the windows of the viewports.
+    pub fn windows(&self) -> impl Iterator<Item = &Window> {
+        self.windows.values()
+    }
+
+    fn window_pos(&self, window: &Window) -> [f32; 2] {
+        let position = window.inner_position().unwrap_or_default();
+        self.logical_pos(PhysicalPosition::new(position.x as f64, position.y as f64))
+    }
+
+    fn logical_pos(&self, position: PhysicalPosition<f64>) -> [f32; 2] {
+        let LogicalPosition { x, y } = position.to_logical::<f32>(self.hidpi_factor);
+        [x, y]
+    }
+
+    fn logical_size(&self, size: PhysicalSize<u32>) -> [f32; 2] {
+        let LogicalSize { width, height } = size.to_logical::<f32>(self.hidpi_factor);
+        [width, height]
+    }
+
+    fn physical_pos(&self, [x, y]: [f32; 2]) -> PhysicalPosition<i32> {
+        LogicalPosition::new(x, y).to_physical(self.hidpi_factor)
+    }
+
+    fn physical_size(&self, [width, height]: [f32; 2]) -> PhysicalSize<u32> {
+        LogicalSize::new(width, height).to_physical(self.hidpi_factor)
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+struct WindowState {
+    pos: [f32; 2],
+    size: [f32; 2],
+    focused: bool,
+    minimized: bool,
+}
+
+/// Change of the window of a viewport requested by imgui.
+#[derive(Debug)]
+enum Request {
+    Create {
+        id: Id,
+        pos: [f32; 2],
+        size: [f32; 2],
+        decorations: bool,
+    },
+    Destroy(Id),
+    Show(Id),
+    SetPos(Id, [f32; 2]),
+    SetSize(Id, [f32; 2]),
+    Focus(Id),
+    SetTitle(Id, String),
+}
+
+/// State shared by the platform backend and `Viewports`.
+#[derive(Default)]
+struct Shared {
+    main: WindowState,
+    /// States of the windows of the viewports, set as requested until the
+    /// windows report their actual state.
+    states: HashMap<Id, WindowState>,
+    requests: Vec<Request>,
+}
+
+impl Shared {
+    /// Returns the state of the window of a viewport, the main window being
+    /// the one of any viewport without a window of its own.
+    fn state(&mut self, id: Id) -> &mut WindowState {
+        self.states.get_mut(&id).unwrap_or(&mut self.main)
+    }
+}
+
+struct Backend {
+    shared: Rc<RefCell<Shared>>,
+}
+
+impl PlatformViewportBackend for Backend {
+    fn create_window(&mut self, viewport: &mut Viewport) {
+        let mut shared = self.shared.borrow_mut();
+        shared.states.insert(
+            viewport.id,
+            WindowState {
+                pos: viewport.pos,
+                size: viewport.size,
+                ..Default::default()
+            },
+        );
+        shared.requests.push(Request::Create {
+            id: viewport.id,
+            pos: viewport.pos,
+            size: viewport.size,
+            decorations: !viewport.flags.contains(ViewportFlags::NO_DECORATION),
+        });
+    }
+
+    fn destroy_window(&mut self, viewport: &mut Viewport) {
+        let mut shared = self.shared.borrow_mut();
+        shared.states.remove(&viewport.id);
+        shared.requests.push(Request::Destroy(viewport.id));
+    }
+
+    fn show_window(&mut self, viewport: &mut Viewport) {
+        let mut shared = self.shared.borrow_mut();
+        shared.requests.push(Request::Show(viewport.id));
+    }
+
+    fn set_window_pos(&mut self, viewport: &mut Viewport, pos: [f32; 2]) {
+        let mut shared = self.shared.borrow_mut();
+        shared.state(viewport.id).pos = pos;
+        shared.requests.push(Request::SetPos(viewport.id, pos));
+    }
+
+    fn get_window_pos(&mut self, viewport: &mut Viewport) -> [f32; 2] {
+        self.shared.borrow_mut().state(viewport.id).pos
+    }
+
+    fn set_window_size(&mut self, viewport: &mut Viewport, size: [f32; 2]) {
+        let mut shared = self.shared.borrow_mut();
+        shared.state(viewport.id).size = size;
+        shared.requests.push(Request::SetSize(viewport.id, size));
+    }
+
+    fn get_window_size(&mut self, viewport: &mut Viewport) -> [f32; 2] {
+        self.shared.borrow_mut().state(viewport.id).size
+    }
+
+    fn set_window_focus(&mut self, viewport: &mut Viewport) {
+        let mut shared = self.shared.borrow_mut();
+        shared.requests.push(Request::Focus(viewport.id));
+    }
+
+    fn get_window_focus(&mut self, viewport: &mut Viewport) -> bool {
+        self.shared.borrow_mut().state(viewport.id).focused
+    }
+
+    fn get_window_minimized(&mut self, viewport: &mut Viewport) -> bool {
+        self.shared.borrow_mut().state(viewport.id).minimized
+    }
+
+    fn set_window_title(&mut self, viewport: &mut Viewport, title: &str) {
+        let mut shared = self.shared.borrow_mut();
+        shared
+            .requests
+            .push(Request::SetTitle(viewport.id, title.to_string()));
+    }
+
+    fn set_window_alpha(&mut self, _viewport: &mut Viewport, _alpha: f32) {}
+
+    fn update_window(&mut self, _viewport: &mut Viewport) {}
+
+    // NOTE: the viewports are drawn along with the frame of the main window
+    fn render_window(&mut self, _viewport: &mut Viewport) {}
+
+    fn swap_buffers(&mut self, _viewport: &mut Viewport) {}
+
+    // NOTE: the surfaces are created by the renderer, see `add_window()`
+    fn create_vk_surface(
+        &mut self,
+        _viewport: &mut Viewport,
+        _instance: u64,
+        _out_surface: &mut u64,
+    ) -> i32 {
+        vk::Result::ERROR_FEATURE_NOT_PRESENT.as_raw()
+    }
+}
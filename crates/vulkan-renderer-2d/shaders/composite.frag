@@ -0,0 +1,62 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+#extension GL_ARB_shading_language_420pack : enable
+
+#define FXAA_REDUCE_MIN (1.0 / 128.0)
+#define FXAA_REDUCE_MUL (1.0 / 8.0)
+#define FXAA_SPAN_MAX 8.0
+
+// specialization constants
+// anti-aliases the layer, see AntiAliasing::Fxaa
+layout (constant_id = 0) const bool FXAA = false;
+
+// uniforms
+layout (binding = 0) uniform sampler2D layer;
+
+// inputs
+layout (location = 0) in vec2 uv;
+
+// outputs
+layout (location = 0) out vec4 uFragColor;
+
+// FXAA, as described in "FXAA" by Timothy Lottes (NVIDIA, 2009), without the
+// end of edge search of FXAA 3.11.
+vec3 fxaa() {
+    vec2 texel = 1.0 / vec2(textureSize(layer, 0));
+    const vec3 luma = vec3(0.299, 0.587, 0.114);
+
+    // luma of the pixel and its diagonal neighbours
+    vec3 rgbM = texture(layer, uv).rgb;
+    float lumaM = dot(rgbM, luma);
+    float lumaNW = dot(texture(layer, uv + vec2(-1.0, -1.0) * texel).rgb, luma);
+    float lumaNE = dot(texture(layer, uv + vec2(1.0, -1.0) * texel).rgb, luma);
+    float lumaSW = dot(texture(layer, uv + vec2(-1.0, 1.0) * texel).rgb, luma);
+    float lumaSE = dot(texture(layer, uv + vec2(1.0, 1.0) * texel).rgb, luma);
+    float lumaMin = min(lumaM, min(min(lumaNW, lumaNE), min(lumaSW, lumaSE)));
+    float lumaMax = max(lumaM, max(max(lumaNW, lumaNE), max(lumaSW, lumaSE)));
+
+    // blur along the edge, perpendicular to the luma gradient
+    vec2 dir = vec2(
+        -((lumaNW + lumaNE) - (lumaSW + lumaSE)),
+        (lumaNW + lumaSW) - (lumaNE + lumaSE));
+    float dirReduce = max((lumaNW + lumaNE + lumaSW + lumaSE) * (0.25 * FXAA_REDUCE_MUL),
+                          FXAA_REDUCE_MIN);
+    float rcpDirMin = 1.0 / (min(abs(dir.x), abs(dir.y)) + dirReduce);
+    dir = clamp(dir * rcpDirMin, vec2(-FXAA_SPAN_MAX), vec2(FXAA_SPAN_MAX)) * texel;
+
+    vec3 rgbA = 0.5 * (texture(layer, uv + dir * (1.0 / 3.0 - 0.5)).rgb +
+                       texture(layer, uv + dir * (2.0 / 3.0 - 0.5)).rgb);
+    vec3 rgbB = rgbA * 0.5 + 0.25 * (texture(layer, uv - dir * 0.5).rgb +
+                                     texture(layer, uv + dir * 0.5).rgb);
+
+    // the wide blur went past the edge, keep the narrow one
+    float lumaB = dot(rgbB, luma);
+    return (lumaB < lumaMin || lumaB > lumaMax) ? rgbA : rgbB;
+}
+
+void main() {
+    vec3 color = FXAA ? fxaa() : texture(layer, uv).rgb;
+
+    // the layer already holds blended colors, it replaces what is below
+    uFragColor = vec4(color, 1.0);
+}
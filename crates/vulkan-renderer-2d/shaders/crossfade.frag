@@ -0,0 +1,21 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+#extension GL_ARB_shading_language_420pack : enable
+
+// uniforms
+layout (binding = 0) uniform sampler2D snapshot;
+
+// push constants
+layout (push_constant) uniform Constants {
+    float opacity;
+} constants;
+
+// inputs
+layout (location = 0) in vec2 uv;
+
+// outputs
+layout (location = 0) out vec4 uFragColor;
+
+void main() {
+    uFragColor = vec4(texture(snapshot, uv).rgb, constants.opacity);
+}
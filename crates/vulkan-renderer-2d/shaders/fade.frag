@@ -0,0 +1,32 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+#extension GL_ARB_shading_language_420pack : enable
+
+// push constants
+layout (push_constant) uniform Constants {
+    vec4 color;
+    // direction the wipe moves in, in uv space, zero for a fade
+    vec2 direction;
+    // progress of the fade or wipe, from 0 to 1
+    float amount;
+} constants;
+
+// inputs
+layout (location = 0) in vec2 uv;
+
+// outputs
+layout (location = 0) out vec4 uFragColor;
+
+void main() {
+    if (constants.direction == vec2(0.0)) {
+        uFragColor = vec4(constants.color.rgb, constants.color.a * constants.amount);
+        return;
+    }
+
+    // a wipe covers the viewport from the edge opposite to its direction
+    float edge = dot(uv - 0.5, constants.direction) + 0.5;
+    if (edge > constants.amount) {
+        discard;
+    }
+    uFragColor = constants.color;
+}
@@ -0,0 +1,24 @@
+// Instances drawn by CulledRenderer2D, see QuadInstance.
+
+#ifndef INSTANCE_GLSL
+#define INSTANCE_GLSL
+
+struct Instance {
+    vec4 rects[3];
+    vec4 colors[3];
+    vec4 bounds;
+    float depth;
+    uint quadCount;
+};
+
+// view projection and number of instances
+layout (binding = 0) uniform UBO {
+    mat4 vp;
+    uint count;
+} ubo;
+
+layout (std430, binding = 1) readonly buffer Instances {
+    Instance instances[];
+};
+
+#endif
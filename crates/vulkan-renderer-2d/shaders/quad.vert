@@ -10,12 +10,18 @@ layout (binding = 0) uniform UBO {
 // inputs
 layout (location = 0) in vec4 vPos;
 layout (location = 1) in vec4 vColor;
+layout (location = 2) in vec2 vUv;
+layout (location = 3) in uint vTexture;
 
 // outputs
 layout (location = 0) out vec4 color;
+layout (location = 1) out vec2 uv;
+layout (location = 2) flat out uint textureIndex;
 
 void main() {
     //color = vPos;
     color = vColor;
+    uv = vUv;
+    textureIndex = vTexture;
     gl_Position = ubo.vp * vPos;
 }
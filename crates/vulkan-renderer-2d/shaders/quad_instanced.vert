@@ -0,0 +1,34 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+#extension GL_ARB_shading_language_420pack : enable
+
+// uniforms and instances
+#include "instance.glsl"
+
+layout (std430, binding = 2) readonly buffer Visible {
+    uint visible[];
+};
+
+// outputs
+layout (location = 0) out vec4 color;
+
+// draws the quads of an instance, 4 vertices each
+void main() {
+    Instance instance = instances[visible[gl_InstanceIndex]];
+    uint quad = gl_VertexIndex / 4;
+    uint corner = gl_VertexIndex % 4;
+
+    // collapse the quads the instance does not have
+    if (quad >= instance.quadCount) {
+        color = vec4(0.0);
+        gl_Position = vec4(0.0, 0.0, 0.0, 1.0);
+        return;
+    }
+
+    // same corner order as QUAD_VERTICES
+    vec4 rect = instance.rects[quad];
+    vec2 pos = vec2(corner == 1 || corner == 2 ? rect.z : rect.x,
+                    corner >= 2 ? rect.w : rect.y);
+    color = instance.colors[quad];
+    gl_Position = ubo.vp * vec4(pos, instance.depth, 1.0);
+}
@@ -0,0 +1,24 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+#extension GL_ARB_shading_language_420pack : enable
+
+// textures of the sprites, see Renderer2DSystem::set_textures
+#include <bindless.glsl>
+
+// texture index of the quads drawn without one, see NO_TEXTURE
+const uint NO_TEXTURE = 0xFFFFFFFFu;
+
+// inputs
+layout (location = 0) in vec4 color;
+layout (location = 1) in vec2 uv;
+layout (location = 2) flat in uint textureIndex;
+
+// outputs
+layout (location = 0) out vec4 uFragColor;
+
+void main() {
+    uFragColor = color;
+    if (textureIndex != NO_TEXTURE) {
+        uFragColor *= sampleBindless(textureIndex, uv);
+    }
+}
@@ -0,0 +1,214 @@
+use std::io::Cursor;
+
+use ash::vk;
+use vulkan_renderer::descriptor::{DescriptorPool, DescriptorSet, DescriptorSetLayout};
+use vulkan_renderer::device::Device;
+use vulkan_renderer::include_shader;
+use vulkan_renderer::pipeline::Pipeline;
+use vulkan_renderer::render_target::RenderTarget;
+use vulkan_renderer::renderpass::RenderPass;
+use vulkan_renderer::shader::{Shader, Specialization};
+
+use crate::Result;
+
+/// Specialization constant enabling FXAA in composite.frag.
+const FXAA_CONSTANT_ID: u32 = 0;
+
+/// Anti-aliasing applied to the targets while they are composited.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum AntiAliasing {
+    #[default]
+    None,
+    /// Fast approximate anti-aliasing, blurring the edges found from the luma
+    /// of the target. Cheaper than multisampling, at the cost of softening
+    /// details such as text.
+    Fxaa,
+}
+
+/// Draws render targets over the viewport of the current render pass,
+/// stretching them to its size.
+pub struct Compositor {
+    /// The vertex and fragment shaders.
+    /// NOTE: only read to rebuild the pipeline when a shader is reloaded,
+    ///       along with the anti-aliasing and the descriptor set layouts.
+    #[cfg_attr(not(feature = "shader-hot-reload"), allow(dead_code))]
+    vertex_shader: Shader,
+    #[cfg_attr(not(feature = "shader-hot-reload"), allow(dead_code))]
+    fragment_shader: Shader,
+    #[cfg_attr(not(feature = "shader-hot-reload"), allow(dead_code))]
+    anti_aliasing: AntiAliasing,
+
+    // The descriptor pool used to allocate descriptor sets, only kept alive
+    // until the compositor is dropped.
+    _descriptor_pool: DescriptorPool,
+
+    // The descriptor set layout used to allocate descriptor sets.
+    #[cfg_attr(not(feature = "shader-hot-reload"), allow(dead_code))]
+    descriptor_set_layouts: Vec<DescriptorSetLayout>,
+
+    /// Descriptor sets pointing at the composited target, one per frame in
+    /// flight.
+    descriptor_sets: Vec<DescriptorSet>,
+    frame_index: usize,
+
+    pipeline: Pipeline,
+}
+
+impl Compositor {
+    pub unsafe fn new(
+        device: &Device,
+        renderpass: &RenderPass,
+        frames_in_flight: u32,
+        anti_aliasing: AntiAliasing,
+    ) -> Result<Self> {
+        // create shaders
+        let mut vertex_spv_file = Cursor::new(&include_shader!("composite.vert")[..]);
+        let mut frag_spv_file = Cursor::new(&include_shader!("composite.frag")[..]);
+
+        let vertex_shader = Shader::new(device, &mut vertex_spv_file)
+            .map_err(|e| format!("create vertex shader module: {:?}", e))?;
+
+        let fragment_shader = Shader::new(device, &mut frag_spv_file)
+            .map_err(|e| format!("create fragment shader module: {:?}", e))?;
+
+        // create descriptor pool
+        let descriptor_pool_sizes = [vk::DescriptorPoolSize {
+            ty: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+            descriptor_count: frames_in_flight,
+        }];
+        let descriptor_pool = DescriptorPool::new(device, &descriptor_pool_sizes, frames_in_flight)
+            .map_err(|e| format!("create descriptor pool: {:?}", e))?;
+
+        // create descriptor set layouts
+        let descriptor_set_layouts = {
+            let ds_layout_bindings = [vk::DescriptorSetLayoutBinding {
+                binding: 0,
+                descriptor_type: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+                descriptor_count: 1,
+                stage_flags: vk::ShaderStageFlags::FRAGMENT,
+                ..Default::default()
+            }];
+            let ds_layout = DescriptorSetLayout::new(device, &ds_layout_bindings)
+                .map_err(|e| format!("create descriptor set layout: {:?}", e))?;
+            vec![ds_layout]
+        };
+
+        // create descriptor sets
+        // NOTE: the target can be replaced, e.g. when the window is resized, so
+        //       each frame in flight points its own set at the target it draws
+        let mut descriptor_sets = Vec::with_capacity(frames_in_flight as usize);
+        for _ in 0..frames_in_flight {
+            let ds = DescriptorSet::new(device, &descriptor_pool, &descriptor_set_layouts)
+                .map_err(|e| format!("create descriptor set: {:?}", e))?[0];
+            descriptor_sets.push(ds);
+        }
+
+        // create graphics pipeline
+        let pipeline = Self::create_pipeline(
+            device,
+            renderpass,
+            &vertex_shader,
+            &fragment_shader,
+            anti_aliasing,
+            &descriptor_set_layouts,
+        )?;
+
+        Ok(Self {
+            vertex_shader,
+            fragment_shader,
+            anti_aliasing,
+            _descriptor_pool: descriptor_pool,
+            descriptor_set_layouts,
+            descriptor_sets,
+            frame_index: 0,
+            pipeline,
+        })
+    }
+
+    unsafe fn create_pipeline(
+        device: &Device,
+        renderpass: &RenderPass,
+        vertex_shader: &Shader,
+        fragment_shader: &Shader,
+        anti_aliasing: AntiAliasing,
+        descriptor_set_layouts: &[DescriptorSetLayout],
+    ) -> Result<Pipeline> {
+        let specialization =
+            Specialization::new().with(FXAA_CONSTANT_ID, anti_aliasing == AntiAliasing::Fxaa);
+        let pipeline = Pipeline::new_specialized(
+            device,
+            renderpass,
+            vertex_shader,
+            fragment_shader,
+            &specialization,
+            &[],
+            &[],
+            descriptor_set_layouts,
+        )
+        .map_err(|e| format!("create pipeline and layout: {:?}", e))?;
+        pipeline.set_name(device, "compositor");
+        Ok(pipeline)
+    }
+
+    /// Rebuilds the pipeline if it uses the shader. Returns true if it does.
+    #[cfg(feature = "shader-hot-reload")]
+    pub unsafe fn reload_shader(
+        &mut self,
+        device: &Device,
+        renderpass: &RenderPass,
+        shader: &crate::hot_reload::ReloadedShader,
+    ) -> Result<bool> {
+        let module = match shader.name.as_str() {
+            "composite.vert" => &mut self.vertex_shader,
+            "composite.frag" => &mut self.fragment_shader,
+            _ => return Ok(false),
+        };
+        *module = Shader::new(device, &mut Cursor::new(&shader.spv))
+            .map_err(|e| format!("create shader module: {:?}", e))?;
+        // NOTE: the previous pipeline is destroyed once the frames using it
+        //       have completed
+        self.pipeline = Self::create_pipeline(
+            device,
+            renderpass,
+            &self.vertex_shader,
+            &self.fragment_shader,
+            self.anti_aliasing,
+            &self.descriptor_set_layouts,
+        )?;
+        Ok(true)
+    }
+
+    /// Draws the target, which must have been rendered to earlier in the
+    /// frame. Its content replaces what was drawn below it.
+    pub unsafe fn composite(
+        &mut self,
+        device: &Device,
+        command_buffer: vk::CommandBuffer,
+        target: &RenderTarget,
+    ) -> Result<()> {
+        // use the descriptor set of the next frame in flight
+        // NOTE: it was last used frames_in_flight frames ago, its fence has been
+        //       waited on by the renderer.
+        self.frame_index = (self.frame_index + 1) % self.descriptor_sets.len();
+        let ds = self.descriptor_sets[self.frame_index];
+        ds.update_image(device, 0, *target.image_view(), **target.sampler())
+            .map_err(|e| format!("update descriptor set: {:?}", e))?;
+
+        device.cmd_bind_descriptor_sets(
+            command_buffer,
+            vk::PipelineBindPoint::GRAPHICS,
+            self.pipeline.layout,
+            0,
+            &[*ds],
+            &[],
+        );
+        device.cmd_bind_pipeline(
+            command_buffer,
+            vk::PipelineBindPoint::GRAPHICS,
+            *self.pipeline,
+        );
+        device.cmd_draw(command_buffer, 3, 1, 0, 0);
+
+        Ok(())
+    }
+}
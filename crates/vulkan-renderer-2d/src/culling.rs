@@ -0,0 +1,626 @@
+//! Objects drawn as instances culled on the GPU.
+//!
+//! Each object becomes an instance holding its quads, written to a buffer
+//! without batching. A compute pass culls the instances against the camera
+//! and appends the visible ones to a list, counted in the command of a
+//! single indirect draw.
+//!
+//! Instances are appended in no particular order: the quads of an object are
+//! drawn in order, but objects sharing a depth may overlap differently from
+//! one frame to the next.
+//!
+//! Unlike `Renderer2D`, the quads are drawn with flat colors: sprites are not
+//! textured, their quad being drawn with its color only. All the instances
+//! are also written every frame, and render callbacks can only be recorded
+//! after all the objects rather than in depth order.
+
+use core::object::QuadView;
+use std::io::Cursor;
+use std::mem;
+
+use ash::vk;
+use cgmath::{Matrix4, Vector4};
+use vulkan_renderer::buffer::Buffer;
+use vulkan_renderer::descriptor::{DescriptorPool, DescriptorSet, DescriptorSetLayout};
+use vulkan_renderer::device::Device;
+use vulkan_renderer::include_shader;
+use vulkan_renderer::pipeline::Pipeline;
+use vulkan_renderer::renderpass::RenderPass;
+use vulkan_renderer::shader::Shader;
+
+use crate::{object_quads, RenderStats, Result, QUAD_INDICES, QUAD_VERTICES};
+
+/// Maximum number of quads of an instance: drop shadow, outline and object.
+const MAX_INSTANCE_QUADS: usize = 3;
+
+/// Number of instances culled by a compute shader work group, see cull.comp.
+const WORK_GROUP_SIZE: u32 = 64;
+
+/// Instances the buffers are first created for.
+const INITIAL_CAPACITY: u32 = 1024;
+
+/// Instance of an object, see instance.glsl.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct QuadInstance {
+    /// World space rectangle of each quad, as (min x, min y, max x, max y).
+    rects: [Vector4<f32>; MAX_INSTANCE_QUADS],
+    colors: [Vector4<f32>; MAX_INSTANCE_QUADS],
+    /// Rectangle holding all the quads, tested against the view.
+    bounds: Vector4<f32>,
+    depth: f32,
+    quad_count: u32,
+    _padding: [u32; 2],
+}
+
+impl QuadInstance {
+    fn new(object: QuadView) -> Self {
+        let zero = Vector4::new(0.0, 0.0, 0.0, 0.0);
+        let mut instance = Self {
+            rects: [zero; MAX_INSTANCE_QUADS],
+            colors: [zero; MAX_INSTANCE_QUADS],
+            bounds: zero,
+            depth: 0.0,
+            quad_count: 0,
+            _padding: [0; 2],
+        };
+        for (i, quad) in object_quads(object).enumerate() {
+            // NOTE: placed like the batched quads, so that both renderers
+            //       draw the same pixels
+            let model = Matrix4::from_nonuniform_scale(quad.size.x, quad.size.y, quad.size.z)
+                * Matrix4::from_translation(quad.position);
+            let min = model * QUAD_VERTICES[0];
+            let max = model * QUAD_VERTICES[2];
+            let rect = Vector4::new(min.x, min.y, max.x, max.y);
+            instance.bounds = if i == 0 {
+                rect
+            } else {
+                let b = instance.bounds;
+                Vector4::new(
+                    b.x.min(rect.x),
+                    b.y.min(rect.y),
+                    b.z.max(rect.z),
+                    b.w.max(rect.w),
+                )
+            };
+            instance.rects[i] = rect;
+            instance.colors[i] = quad.color;
+            instance.depth = min.z;
+            instance.quad_count += 1;
+        }
+        instance
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+struct UniformBuffer {
+    vp: Matrix4<f32>,
+    /// Number of instances to cull.
+    count: u32,
+    _padding: [u32; 3],
+}
+
+/// Buffers written during a frame in flight.
+struct FrameResources {
+    uniform_buffer: Buffer,
+    instance_buffer: Buffer,
+    visible_buffer: Buffer,
+    draw_buffer: Buffer,
+    /// Number of instances the buffers can hold.
+    capacity: u32,
+    descriptor_set: DescriptorSet,
+}
+
+impl FrameResources {
+    unsafe fn new(
+        device: &Device,
+        descriptor_pool: &DescriptorPool,
+        descriptor_set_layouts: &[DescriptorSetLayout],
+    ) -> Result<Self> {
+        let uniform_buffer = Buffer::new(
+            device,
+            vk::BufferUsageFlags::UNIFORM_BUFFER,
+            vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT,
+            mem::size_of::<UniformBuffer>() as u64,
+        )
+        .map_err(|e| format!("create uniform buffer: {:?}", e))?;
+        device.set_object_name(*uniform_buffer, "gpu culling uniform buffer");
+
+        // NOTE: the command is written by the host every frame before its
+        //       instance count is incremented by the compute shader
+        let draw_buffer = Buffer::new(
+            device,
+            vk::BufferUsageFlags::STORAGE_BUFFER | vk::BufferUsageFlags::INDIRECT_BUFFER,
+            vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT,
+            mem::size_of::<vk::DrawIndexedIndirectCommand>() as u64,
+        )
+        .map_err(|e| format!("create indirect draw buffer: {:?}", e))?;
+        device.set_object_name(*draw_buffer, "gpu culling draw buffer");
+
+        let (instance_buffer, visible_buffer) = create_instance_buffers(device, INITIAL_CAPACITY)?;
+
+        let descriptor_set = DescriptorSet::new(device, descriptor_pool, descriptor_set_layouts)
+            .map_err(|e| format!("create descriptor set: {:?}", e))?[0];
+        descriptor_set
+            .update_ubo(
+                device,
+                &uniform_buffer,
+                0,
+                mem::size_of::<UniformBuffer>() as u64,
+            )
+            .map_err(|e| format!("update descriptor set: {:?}", e))?;
+        descriptor_set
+            .update_storage_buffer(device, 3, &draw_buffer)
+            .map_err(|e| format!("update descriptor set: {:?}", e))?;
+
+        let mut resources = Self {
+            uniform_buffer,
+            instance_buffer,
+            visible_buffer,
+            draw_buffer,
+            capacity: INITIAL_CAPACITY,
+            descriptor_set,
+        };
+        resources.update_instance_descriptors(device)?;
+        Ok(resources)
+    }
+
+    /// Grows the instance buffers to hold at least `count` instances.
+    unsafe fn reserve(&mut self, device: &Device, count: u32) -> Result<()> {
+        if count <= self.capacity {
+            return Ok(());
+        }
+        let capacity = count.next_power_of_two();
+        // NOTE: the previous buffers are destroyed once the frames using them
+        //       have completed
+        (self.instance_buffer, self.visible_buffer) = create_instance_buffers(device, capacity)?;
+        self.capacity = capacity;
+        self.update_instance_descriptors(device)
+    }
+
+    unsafe fn update_instance_descriptors(&mut self, device: &Device) -> Result<()> {
+        self.descriptor_set
+            .update_storage_buffer(device, 1, &self.instance_buffer)
+            .map_err(|e| format!("update descriptor set: {:?}", e))?;
+        self.descriptor_set
+            .update_storage_buffer(device, 2, &self.visible_buffer)
+            .map_err(|e| format!("update descriptor set: {:?}", e))?;
+        Ok(())
+    }
+}
+
+/// Creates the buffers of the instances and of the visible instance indices.
+unsafe fn create_instance_buffers(device: &Device, capacity: u32) -> Result<(Buffer, Buffer)> {
+    let instance_buffer = Buffer::new(
+        device,
+        vk::BufferUsageFlags::STORAGE_BUFFER,
+        vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT,
+        capacity as u64 * mem::size_of::<QuadInstance>() as u64,
+    )
+    .map_err(|e| format!("create instance buffer: {:?}", e))?;
+    device.set_object_name(*instance_buffer, "gpu culling instance buffer");
+
+    let visible_buffer = Buffer::new(
+        device,
+        vk::BufferUsageFlags::STORAGE_BUFFER,
+        vk::MemoryPropertyFlags::DEVICE_LOCAL,
+        capacity as u64 * mem::size_of::<u32>() as u64,
+    )
+    .map_err(|e| format!("create visible instance buffer: {:?}", e))?;
+    device.set_object_name(*visible_buffer, "gpu culling visible buffer");
+
+    Ok((instance_buffer, visible_buffer))
+}
+
+/// Draws objects culled on the GPU with flat colors, with a single indirect
+/// draw.
+pub struct CulledRenderer2D {
+    /// The compute, vertex and fragment shaders.
+    /// NOTE: only read to rebuild the pipelines when a shader is reloaded,
+    ///       along with the descriptor set layouts.
+    #[cfg_attr(not(feature = "shader-hot-reload"), allow(dead_code))]
+    cull_shader: Shader,
+    #[cfg_attr(not(feature = "shader-hot-reload"), allow(dead_code))]
+    vertex_shader: Shader,
+    #[cfg_attr(not(feature = "shader-hot-reload"), allow(dead_code))]
+    fragment_shader: Shader,
+
+    // The descriptor pool used to allocate descriptor sets, only kept alive
+    // until the renderer is dropped.
+    _descriptor_pool: DescriptorPool,
+
+    // The descriptor set layout shared by both pipelines.
+    #[cfg_attr(not(feature = "shader-hot-reload"), allow(dead_code))]
+    descriptor_set_layouts: Vec<DescriptorSetLayout>,
+
+    /// Buffers and their descriptor set, one per frame in flight.
+    frames: Vec<FrameResources>,
+    frame_index: usize,
+
+    cull_pipeline: Pipeline,
+    pipeline: Pipeline,
+
+    /// Indices of the quads of an instance.
+    index_buffer: Buffer,
+
+    /// Instances of the current frame.
+    instances: Vec<QuadInstance>,
+
+    stats: RenderStats,
+}
+
+impl CulledRenderer2D {
+    pub unsafe fn new(
+        device: &Device,
+        renderpass: &RenderPass,
+        frames_in_flight: u32,
+    ) -> Result<Self> {
+        // create shaders
+        let mut cull_spv_file = Cursor::new(&include_shader!("cull.comp")[..]);
+        let mut vertex_spv_file = Cursor::new(&include_shader!("quad_instanced.vert")[..]);
+        let mut frag_spv_file = Cursor::new(&include_shader!("quad.frag")[..]);
+
+        let cull_shader = Shader::new(device, &mut cull_spv_file)
+            .map_err(|e| format!("create compute shader module: {:?}", e))?;
+
+        let vertex_shader = Shader::new(device, &mut vertex_spv_file)
+            .map_err(|e| format!("create vertex shader module: {:?}", e))?;
+
+        let fragment_shader = Shader::new(device, &mut frag_spv_file)
+            .map_err(|e| format!("create fragment shader module: {:?}", e))?;
+
+        // create descriptor pool
+        let descriptor_pool_sizes = [
+            vk::DescriptorPoolSize {
+                ty: vk::DescriptorType::UNIFORM_BUFFER,
+                descriptor_count: frames_in_flight,
+            },
+            vk::DescriptorPoolSize {
+                ty: vk::DescriptorType::STORAGE_BUFFER,
+                descriptor_count: 3 * frames_in_flight,
+            },
+        ];
+        let descriptor_pool = DescriptorPool::new(device, &descriptor_pool_sizes, frames_in_flight)
+            .map_err(|e| format!("create descriptor pool: {:?}", e))?;
+
+        // create descriptor set layouts
+        let descriptor_set_layouts = {
+            let binding = |binding, descriptor_type, stage_flags| vk::DescriptorSetLayoutBinding {
+                binding,
+                descriptor_type,
+                descriptor_count: 1,
+                stage_flags,
+                ..Default::default()
+            };
+            let both_stages = vk::ShaderStageFlags::COMPUTE | vk::ShaderStageFlags::VERTEX;
+            let ds_layout_bindings = [
+                // view projection and instance count
+                binding(0, vk::DescriptorType::UNIFORM_BUFFER, both_stages),
+                // instances
+                binding(1, vk::DescriptorType::STORAGE_BUFFER, both_stages),
+                // visible instances
+                binding(2, vk::DescriptorType::STORAGE_BUFFER, both_stages),
+                // indirect draw command
+                binding(
+                    3,
+                    vk::DescriptorType::STORAGE_BUFFER,
+                    vk::ShaderStageFlags::COMPUTE,
+                ),
+            ];
+            let ds_layout = DescriptorSetLayout::new(device, &ds_layout_bindings)
+                .map_err(|e| format!("create descriptor set layout: {:?}", e))?;
+            vec![ds_layout]
+        };
+
+        // create the buffers of each frame in flight
+        let mut frames = Vec::with_capacity(frames_in_flight as usize);
+        for _ in 0..frames_in_flight {
+            frames.push(FrameResources::new(
+                device,
+                &descriptor_pool,
+                &descriptor_set_layouts,
+            )?);
+        }
+
+        // create pipelines
+        let cull_pipeline =
+            Self::create_cull_pipeline(device, &cull_shader, &descriptor_set_layouts)?;
+        let pipeline = Self::create_pipeline(
+            device,
+            renderpass,
+            &vertex_shader,
+            &fragment_shader,
+            &descriptor_set_layouts,
+        )?;
+
+        // create index buffer
+        let indices = instance_indices();
+        let mut index_buffer = Buffer::new(
+            device,
+            vk::BufferUsageFlags::INDEX_BUFFER,
+            vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT,
+            mem::size_of_val(&indices) as u64,
+        )
+        .map_err(|e| format!("create index buffer: {:?}", e))?;
+        device.set_object_name(*index_buffer, "renderer 2D instanced index buffer");
+        index_buffer
+            .update(&indices)
+            .map_err(|e| format!("update index buffer: {:?}", e))?;
+
+        Ok(Self {
+            cull_shader,
+            vertex_shader,
+            fragment_shader,
+            _descriptor_pool: descriptor_pool,
+            descriptor_set_layouts,
+            frames,
+            frame_index: 0,
+            cull_pipeline,
+            pipeline,
+            index_buffer,
+            instances: Vec::new(),
+            stats: RenderStats::default(),
+        })
+    }
+
+    unsafe fn create_cull_pipeline(
+        device: &Device,
+        cull_shader: &Shader,
+        descriptor_set_layouts: &[DescriptorSetLayout],
+    ) -> Result<Pipeline> {
+        let pipeline = Pipeline::compute(device, cull_shader, descriptor_set_layouts)
+            .map_err(|e| format!("create compute pipeline and layout: {:?}", e))?;
+        pipeline.set_name(device, "gpu culling");
+        Ok(pipeline)
+    }
+
+    unsafe fn create_pipeline(
+        device: &Device,
+        renderpass: &RenderPass,
+        vertex_shader: &Shader,
+        fragment_shader: &Shader,
+        descriptor_set_layouts: &[DescriptorSetLayout],
+    ) -> Result<Pipeline> {
+        let pipeline = Pipeline::new(
+            device,
+            renderpass,
+            vertex_shader,
+            fragment_shader,
+            &[],
+            &[],
+            descriptor_set_layouts,
+        )
+        .map_err(|e| format!("create pipeline and layout: {:?}", e))?;
+        pipeline.set_name(device, "renderer 2D instanced");
+        Ok(pipeline)
+    }
+
+    /// Rebuilds the pipeline using the shader, if any. Returns true if one
+    /// does.
+    #[cfg(feature = "shader-hot-reload")]
+    pub unsafe fn reload_shader(
+        &mut self,
+        device: &Device,
+        renderpass: &RenderPass,
+        shader: &crate::hot_reload::ReloadedShader,
+    ) -> Result<bool> {
+        let module = match shader.name.as_str() {
+            "cull.comp" => &mut self.cull_shader,
+            "quad_instanced.vert" => &mut self.vertex_shader,
+            "quad.frag" => &mut self.fragment_shader,
+            _ => return Ok(false),
+        };
+        *module = Shader::new(device, &mut Cursor::new(&shader.spv))
+            .map_err(|e| format!("create shader module: {:?}", e))?;
+        // NOTE: the previous pipelines are destroyed once the frames using them
+        //       have completed
+        if shader.name == "cull.comp" {
+            self.cull_pipeline = Self::create_cull_pipeline(
+                device,
+                &self.cull_shader,
+                &self.descriptor_set_layouts,
+            )?;
+        } else {
+            self.pipeline = Self::create_pipeline(
+                device,
+                renderpass,
+                &self.vertex_shader,
+                &self.fragment_shader,
+                &self.descriptor_set_layouts,
+            )?;
+        }
+        Ok(true)
+    }
+
+    /// Uploads the objects and culls them against the camera. Must be
+    /// recorded outside of a render pass, before `draw()` in the same frame.
+    pub unsafe fn cull<'a, I>(
+        &mut self,
+        device: &Device,
+        command_buffer: vk::CommandBuffer,
+        view_projection: Matrix4<f32>,
+        objects: I,
+    ) -> Result<()>
+    where
+        I: IntoIterator<Item = QuadView<'a>>,
+    {
+        // use the buffers of the next frame in flight
+        // NOTE: they were last used frames_in_flight frames ago, their fence
+        //       has been waited on by the renderer.
+        self.frame_index = (self.frame_index + 1) % self.frames.len();
+        let frame = &mut self.frames[self.frame_index];
+
+        // write the instances
+        self.instances.clear();
+        self.instances
+            .extend(objects.into_iter().map(QuadInstance::new));
+        let count = self.instances.len() as u32;
+        frame.reserve(device, count)?;
+        frame
+            .instance_buffer
+            .update(&self.instances)
+            .map_err(|e| format!("update instance buffer: {:?}", e))?;
+        frame
+            .uniform_buffer
+            .update(&[UniformBuffer {
+                vp: view_projection,
+                count,
+                _padding: [0; 3],
+            }])
+            .map_err(|e| format!("update uniform buffer: {:?}", e))?;
+        frame
+            .draw_buffer
+            .update(&[vk::DrawIndexedIndirectCommand {
+                index_count: (MAX_INSTANCE_QUADS * QUAD_INDICES.len()) as u32,
+                instance_count: 0,
+                first_index: 0,
+                vertex_offset: 0,
+                first_instance: 0,
+            }])
+            .map_err(|e| format!("update indirect draw buffer: {:?}", e))?;
+
+        self.stats = RenderStats {
+            draw_calls: 1,
+            quads: self.instances.iter().map(|i| i.quad_count).sum(),
+        };
+        if count == 0 {
+            return Ok(());
+        }
+
+        // cull
+        device.cmd_bind_pipeline(
+            command_buffer,
+            vk::PipelineBindPoint::COMPUTE,
+            *self.cull_pipeline,
+        );
+        device.cmd_bind_descriptor_sets(
+            command_buffer,
+            vk::PipelineBindPoint::COMPUTE,
+            self.cull_pipeline.layout,
+            0,
+            &[*frame.descriptor_set],
+            &[],
+        );
+        device.cmd_dispatch(
+            command_buffer,
+            (count + WORK_GROUP_SIZE - 1) / WORK_GROUP_SIZE,
+            1,
+            1,
+        );
+
+        // make the visible instances and their count available to the draw
+        let barriers = [*frame.visible_buffer, *frame.draw_buffer].map(|buffer| {
+            vk::BufferMemoryBarrier::builder()
+                .src_access_mask(vk::AccessFlags::SHADER_WRITE)
+                .dst_access_mask(
+                    vk::AccessFlags::INDIRECT_COMMAND_READ | vk::AccessFlags::SHADER_READ,
+                )
+                .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                .buffer(buffer)
+                .size(vk::WHOLE_SIZE)
+                .build()
+        });
+        device.cmd_pipeline_barrier(
+            command_buffer,
+            vk::PipelineStageFlags::COMPUTE_SHADER,
+            vk::PipelineStageFlags::DRAW_INDIRECT | vk::PipelineStageFlags::VERTEX_SHADER,
+            vk::DependencyFlags::empty(),
+            &[],
+            &barriers,
+            &[],
+        );
+
+        Ok(())
+    }
+
+    /// Draws the instances left by the last `cull()`.
+    pub unsafe fn draw(&self, device: &Device, command_buffer: vk::CommandBuffer) {
+        let frame = &self.frames[self.frame_index];
+
+        device.cmd_bind_descriptor_sets(
+            command_buffer,
+            vk::PipelineBindPoint::GRAPHICS,
+            self.pipeline.layout,
+            0,
+            &[*frame.descriptor_set],
+            &[],
+        );
+        device.cmd_bind_pipeline(
+            command_buffer,
+            vk::PipelineBindPoint::GRAPHICS,
+            *self.pipeline,
+        );
+        device.cmd_bind_index_buffer(command_buffer, *self.index_buffer, 0, vk::IndexType::UINT32);
+        device.cmd_draw_indexed_indirect(
+            command_buffer,
+            *frame.draw_buffer,
+            0,
+            1,
+            mem::size_of::<vk::DrawIndexedIndirectCommand>() as u32,
+        );
+    }
+
+    /// Returns the quads submitted during the last frame, before culling.
+    pub fn stats(&self) -> RenderStats {
+        self.stats
+    }
+}
+
+/// Returns the indices of the quads of an instance, 4 vertices each.
+fn instance_indices() -> [u32; MAX_INSTANCE_QUADS * QUAD_INDICES.len()] {
+    let mut indices = [0; MAX_INSTANCE_QUADS * QUAD_INDICES.len()];
+    for (i, index) in indices.iter_mut().enumerate() {
+        let quad = i / QUAD_INDICES.len();
+        *index = QUAD_INDICES[i % QUAD_INDICES.len()] + 4 * quad as u32;
+    }
+    indices
+}
+
+#[cfg(test)]
+mod tests {
+    use core::ecs::World;
+    use core::object::GameObject;
+
+    use cgmath::{Vector2, Vector3};
+
+    use super::*;
+
+    #[test]
+    fn instance_quads_match_batched_quads() {
+        let mut world = World::new();
+        world.spawn(
+            GameObject::new()
+                .with_position(Vector3::new(2.0, 3.0, 0.5))
+                .with_scale(Vector3::new(10.0, 20.0, 1.0))
+                .with_outline(Vector4::new(1.0, 1.0, 1.0, 1.0), 2.0)
+                .with_drop_shadow(Vector4::new(0.0, 0.0, 0.0, 0.5), Vector2::new(4.0, -4.0)),
+        );
+        world.spawn(GameObject::new());
+        let mut objects = world.query::<QuadView>();
+        let object = objects.next().unwrap();
+        let instance = QuadInstance::new(object);
+
+        // see outline_and_shadow_surround_object
+        assert_eq!(instance.quad_count, 3);
+        assert_eq!(instance.rects[0], Vector4::new(14.0, 36.0, 34.0, 76.0));
+        assert_eq!(instance.rects[1], Vector4::new(8.0, 38.0, 32.0, 82.0));
+        assert_eq!(instance.rects[2], Vector4::new(10.0, 40.0, 30.0, 80.0));
+        assert_eq!(instance.bounds, Vector4::new(8.0, 36.0, 34.0, 82.0));
+        assert_eq!(instance.depth, 0.5);
+        assert_eq!(instance.colors[2], object.color.color);
+
+        let plain = QuadInstance::new(objects.next().unwrap());
+        assert_eq!(plain.quad_count, 1);
+        assert_eq!(plain.rects[0], plain.bounds);
+    }
+
+    #[test]
+    fn instance_indices_follow_quads() {
+        let indices = instance_indices();
+        assert_eq!(indices[..6], QUAD_INDICES);
+        assert_eq!(indices[12..], [8, 9, 10, 10, 11, 8]);
+        assert_eq!(mem::size_of::<QuadInstance>(), 128);
+    }
+}
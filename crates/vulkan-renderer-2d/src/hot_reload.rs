@@ -0,0 +1,169 @@
+//! Recompiles the shaders of the 2D renderers when their GLSL source changes.
+//!
+//! The SPIR-V embedded at build time stays the one used on startup. The
+//! watcher polls the modification time of the sources, recompiles the
+//! modified ones and hands the new SPIR-V to the renderers, which rebuild the
+//! pipelines using them. A shader that fails to compile is reported and the
+//! pipelines keep their previous version.
+//!
+//! Includes are resolved as by build.rs, see `shader_compiler`. Modifying an
+//! include recompiles all the sources.
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time;
+
+use log::{error, warn};
+pub use shader_compiler::SHADER_INCLUDE_DIR;
+use shader_compiler::{shader_kind, Compiler};
+
+use crate::Result;
+
+/// Directory of the GLSL sources the embedded shaders are compiled from.
+pub const DEFAULT_SHADER_DIR: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/shaders");
+
+/// Extension of the files included by the sources.
+const INCLUDE_EXTENSION: &str = "glsl";
+
+/// Minimum time between two scans of the shader directory.
+const POLL_INTERVAL: time::Duration = time::Duration::from_millis(250);
+
+/// Shader recompiled from its modified source.
+#[derive(Debug)]
+pub struct ReloadedShader {
+    /// File name of the source, e.g. `quad.vert`.
+    pub name: String,
+    pub spv: Vec<u8>,
+}
+
+pub struct ShaderWatcher {
+    dir: PathBuf,
+    /// Last modification time of each source and include.
+    modified: HashMap<PathBuf, time::SystemTime>,
+    last_poll: time::Instant,
+    compiler: Compiler,
+}
+
+impl ShaderWatcher {
+    /// Watches the sources of the directory and their includes. Sources are
+    /// only compiled once modified.
+    pub fn new(dir: impl Into<PathBuf>) -> Result<Self> {
+        let mut watcher = Self {
+            dir: dir.into(),
+            modified: HashMap::new(),
+            last_poll: time::Instant::now(),
+            compiler: Compiler::new()?,
+        };
+        watcher.scan()?;
+        Ok(watcher)
+    }
+
+    /// Recompiles the sources modified since the last poll, at most every
+    /// `POLL_INTERVAL`. Sources that fail to compile are logged and skipped.
+    pub fn poll(&mut self) -> Vec<ReloadedShader> {
+        if self.last_poll.elapsed() < POLL_INTERVAL {
+            return Vec::new();
+        }
+        self.last_poll = time::Instant::now();
+
+        let modified = match self.scan() {
+            Ok(modified) => modified,
+            Err(e) => {
+                warn!("scan shader directory {}: {e}", self.dir.display());
+                return Vec::new();
+            }
+        };
+        modified
+            .into_iter()
+            .filter_map(|path| match self.compile(&path) {
+                Ok(shader) => Some(shader),
+                Err(e) => {
+                    error!("compile shader {}: {e}", path.display());
+                    None
+                }
+            })
+            .collect()
+    }
+
+    /// Records the modification time of the sources and includes. Returns
+    /// the sources to recompile: the ones modified or created since the
+    /// previous scan, or all of them when an include was.
+    fn scan(&mut self) -> Result<Vec<PathBuf>> {
+        let mut sources = Vec::new();
+        let mut modified = Vec::new();
+        let mut include_modified = false;
+        let include_dir = Path::new(SHADER_INCLUDE_DIR);
+        for dir in [self.dir.as_path(), include_dir] {
+            // NOTE: the shared includes are optional
+            if dir == include_dir && !dir.is_dir() {
+                continue;
+            }
+            for entry in fs::read_dir(dir)? {
+                let entry = entry?;
+                let path = entry.path();
+                let is_include = is_include(&path);
+                if !entry.file_type()?.is_file() || !is_include && shader_kind(&path).is_none() {
+                    continue;
+                }
+                let time = entry.metadata()?.modified()?;
+                let changed = self.modified.insert(path.clone(), time) != Some(time);
+                if is_include {
+                    include_modified |= changed;
+                } else {
+                    if changed {
+                        modified.push(path.clone());
+                    }
+                    sources.push(path);
+                }
+            }
+        }
+        Ok(if include_modified { sources } else { modified })
+    }
+
+    fn compile(&self, path: &Path) -> Result<ReloadedShader> {
+        let spv = self.compiler.compile(path)?;
+        let name = path
+            .file_name()
+            .map(|n| n.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        Ok(ReloadedShader { name, spv })
+    }
+}
+
+fn is_include(path: &Path) -> bool {
+    path.extension().and_then(|e| e.to_str()) == Some(INCLUDE_EXTENSION)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("{name}-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn new_and_modified_sources_are_found() {
+        let dir = temp_dir("shader-watcher");
+        fs::write(dir.join("a.vert"), "").unwrap();
+        fs::write(dir.join("notes.txt"), "").unwrap();
+
+        let mut watcher = ShaderWatcher::new(&dir).unwrap();
+        assert!(watcher.scan().unwrap().is_empty());
+
+        fs::write(dir.join("b.frag"), "").unwrap();
+        assert_eq!(watcher.scan().unwrap(), [dir.join("b.frag")]);
+        assert!(watcher.scan().unwrap().is_empty());
+
+        // all the sources may use a new include
+        fs::write(dir.join("common.glsl"), "").unwrap();
+        let mut sources = watcher.scan().unwrap();
+        sources.sort();
+        assert_eq!(sources, [dir.join("a.vert"), dir.join("b.frag")]);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -1,23 +1,37 @@
 #![allow(clippy::missing_safety_doc)]
 
-use core::object::GameObject;
+use core::component::Transform;
+use core::jobs::JobPool;
+use core::object::QuadView;
+use core::sprite::AnimatedSprite;
+use std::sync::Arc;
 use std::{error, result};
 use std::{io::Cursor, mem, time};
 
 use ash::vk;
-use cgmath::{Matrix4, SquareMatrix, Vector3, Vector4};
-use log::debug;
+use cgmath::{ElementWise, Matrix4, SquareMatrix, Vector2, Vector3, Vector4};
+use vulkan_renderer::bindless::BindlessTextures;
 use vulkan_renderer::buffer::Buffer;
 use vulkan_renderer::descriptor::{DescriptorPool, DescriptorSet, DescriptorSetLayout};
 use vulkan_renderer::device::Device;
-use vulkan_renderer::offset_of;
-use vulkan_renderer::pipeline::Pipeline;
+use vulkan_renderer::pipeline::{Pipeline, PipelineState};
+use vulkan_renderer::render_target::RenderTarget;
 use vulkan_renderer::renderpass::RenderPass;
 use vulkan_renderer::shader::Shader;
+use vulkan_renderer::staging::StagingRing;
+use vulkan_renderer::{include_shader, offset_of};
+
+pub mod compositor;
+pub mod culling;
+#[cfg(feature = "shader-hot-reload")]
+pub mod hot_reload;
+pub mod transition;
 
 type Result<T> = result::Result<T, Box<dyn error::Error>>;
 
 const DEFAULT_MAX_QUADS: u32 = 2000;
+/// Texture of the quads drawn without one, see `quad_textured.frag`.
+const NO_TEXTURE: u32 = u32::MAX;
 
 #[derive(Clone, Debug)]
 struct VertexInputDescription {
@@ -25,10 +39,12 @@ struct VertexInputDescription {
     attributes: Vec<vk::VertexInputAttributeDescription>,
 }
 
-#[derive(Clone, Debug, Copy)]
+#[derive(Clone, Debug, Copy, PartialEq)]
 struct Vertex {
     pos: Vector4<f32>,
     color: Vector4<f32>,
+    uv: Vector2<f32>,
+    texture: u32,
 }
 
 impl Vertex {
@@ -51,6 +67,18 @@ impl Vertex {
                 format: vk::Format::R32G32B32A32_SFLOAT,
                 offset: offset_of!(Self, color) as u32,
             },
+            vk::VertexInputAttributeDescription {
+                location: 2,
+                binding: 0,
+                format: vk::Format::R32G32_SFLOAT,
+                offset: offset_of!(Self, uv) as u32,
+            },
+            vk::VertexInputAttributeDescription {
+                location: 3,
+                binding: 0,
+                format: vk::Format::R32_UINT,
+                offset: offset_of!(Self, texture) as u32,
+            },
         ];
 
         VertexInputDescription {
@@ -80,7 +108,7 @@ const QUAD_VERTICES: [Vector4<f32>; 4] = [
     Vector4::new(-1.0, 1.0, 0.0, 1.0),
 ];
 
-#[derive(Debug, Default)]
+#[derive(Debug, Default, PartialEq)]
 struct QuadBatchData {
     vertices: Vec<Vertex>,
     indices: Vec<u32>,
@@ -94,20 +122,41 @@ impl QuadBatchData {
         }
     }
 
-    fn add(&mut self, position: Vector3<f32>, size: Vector3<f32>, color: Vector4<f32>) {
+    fn add(&mut self, quad: &Quad) {
+        let Quad {
+            position,
+            size,
+            color,
+            uv,
+            texture,
+            ..
+        } = *quad;
+
         // compute translation and scale matrices
         let m_translation = Matrix4::from_translation(position);
         let m_scale = Matrix4::from_nonuniform_scale(size.x, size.y, size.z);
 
+        // NOTE: Y points up, so the top of the texture is drawn at the top of
+        //       the quad
+        let uvs = [
+            Vector2::new(uv.x, uv.w),
+            Vector2::new(uv.z, uv.w),
+            Vector2::new(uv.z, uv.y),
+            Vector2::new(uv.x, uv.y),
+        ];
+
         // append indices
         self.indices
             .extend(QUAD_INDICES.iter().map(|i| self.vertices.len() as u32 + i));
 
         // append vertices
-        self.vertices.extend(QUAD_VERTICES.iter().map(|q| Vertex {
-            pos: m_scale * m_translation * q,
-            color,
-        }));
+        self.vertices
+            .extend(QUAD_VERTICES.iter().zip(uvs).map(|(q, uv)| Vertex {
+                pos: m_scale * m_translation * q,
+                color,
+                uv,
+                texture,
+            }));
     }
 }
 
@@ -129,6 +178,10 @@ impl QuadBatcher {
     }
 
     pub fn add_quad(&mut self, position: Vector3<f32>, size: Vector3<f32>, color: Vector4<f32>) {
+        self.push(&Quad::new(position, size, color));
+    }
+
+    fn push(&mut self, quad: &Quad) {
         let is_batch_full = self.quad_count == self.max_quads;
         if is_batch_full {
             self.current_batch += 1;
@@ -138,10 +191,78 @@ impl QuadBatcher {
             self.batches.push(QuadBatchData::new(self.max_quads));
         }
         let batch_data = &mut self.batches[self.current_batch];
-        batch_data.add(position, size, color);
+        batch_data.add(quad);
         self.quad_count += 1;
     }
 
+    /// Adds the quad of an object, preceded by the quads of its drop shadow
+    /// and outline. Quads sharing a depth are drawn in the order they are
+    /// added, so these end up behind the object.
+    pub fn add_object(&mut self, object: QuadView) {
+        for quad in object_quads(object) {
+            self.push(&quad);
+        }
+    }
+
+    /// Adds sorted quads like `add_quad()`. Once the current batch is full,
+    /// the next batches are built in parallel on the jobs of the pool.
+    fn add_sorted(&mut self, quads: &[SortedQuad], jobs: Option<&JobPool>) {
+        let max_quads = self.max_quads as usize;
+        let free = if self.batches.is_empty() {
+            0
+        } else {
+            max_quads - self.quad_count as usize
+        };
+        let (current, next) = quads.split_at(free.min(quads.len()));
+        let jobs = match jobs {
+            Some(jobs) if next.len() > max_quads => jobs,
+            _ => {
+                for SortedQuad { quad, .. } in quads {
+                    self.push(quad);
+                }
+                return;
+            }
+        };
+
+        for SortedQuad { quad, .. } in current {
+            self.push(quad);
+        }
+        let first = self.batches.len();
+        let batches = (next.len() + max_quads - 1) / max_quads;
+        self.batches.resize_with(first + batches, Default::default);
+        jobs.scope(|scope| {
+            for (chunk, batch) in next.chunks(max_quads).zip(&mut self.batches[first..]) {
+                scope.spawn(move || {
+                    *batch = QuadBatchData::new(chunk.len() as u32);
+                    for SortedQuad { quad, .. } in chunk {
+                        batch.add(quad);
+                    }
+                });
+            }
+        });
+        self.current_batch = self.batches.len() - 1;
+        self.quad_count = ((next.len() - 1) % max_quads + 1) as u32;
+    }
+
+    /// Returns the number of quads added.
+    fn len(&self) -> usize {
+        if self.batches.is_empty() {
+            0
+        } else {
+            self.current_batch * self.max_quads as usize + self.quad_count as usize
+        }
+    }
+
+    /// Returns the batch holding the last added quad and the number of
+    /// indices of that batch, which is where the next quad will be drawn.
+    pub fn end_position(&self) -> (usize, u32) {
+        let indices = self
+            .batches
+            .get(self.current_batch)
+            .map_or(0, |batch| batch.indices.len());
+        (self.current_batch, indices as u32)
+    }
+
     pub fn clear(&mut self) {
         self.quad_count = 0;
         self.current_batch = 0;
@@ -149,38 +270,298 @@ impl QuadBatcher {
     }
 }
 
+/// Quad placed like the ones of `QuadBatcher::add_quad()`: it spans
+/// `(position ± 1) * size`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Quad {
+    position: Vector3<f32>,
+    size: Vector3<f32>,
+    color: Vector4<f32>,
+    /// Texture coordinates of the corners, as the minimum U and V followed by
+    /// the maximum ones.
+    uv: Vector4<f32>,
+    texture: u32,
+    /// Drawn in the opaque phase, see `is_opaque()`.
+    opaque: bool,
+}
+
+/// Returns the quads of an object in draw order: its drop shadow and outline,
+/// if any, then the object itself.
+fn object_quads(object: QuadView) -> impl Iterator<Item = Quad> {
+    let Transform {
+        position, scale, ..
+    } = *object.transform;
+
+    // NOTE: quads are scaled after being translated, so offsets are
+    //       divided by the scale and the position of a resized quad is
+    //       rescaled to keep it centered
+    let shadow = object.shadow.map(|shadow| {
+        let position = Vector3::new(
+            position.x + shadow.offset.x / scale.x,
+            position.y + shadow.offset.y / scale.y,
+            position.z,
+        );
+        Quad::new(position, scale, shadow.color)
+    });
+    let outline = object.outline.map(|outline| {
+        let outline_scale = Vector3::new(
+            scale.x + outline.thickness,
+            scale.y + outline.thickness,
+            scale.z,
+        );
+        let position = Vector3::new(
+            position.x * scale.x / outline_scale.x,
+            position.y * scale.y / outline_scale.y,
+            position.z,
+        );
+        Quad::new(position, outline_scale, outline.color)
+    });
+    let quad = Quad::new(position, scale, object.color.color);
+    let quad = match object.sprite {
+        Some(sprite) => quad.with_sprite(sprite),
+        None => quad,
+    };
+    let shadow = shadow.map(|shadow| shadow.behind(&quad));
+    let outline = outline.map(|outline| outline.behind(&quad));
+    [shadow, outline, Some(quad)].into_iter().flatten()
+}
+
+impl Quad {
+    /// Creates a quad drawn without a texture.
+    fn new(position: Vector3<f32>, size: Vector3<f32>, color: Vector4<f32>) -> Self {
+        Self {
+            position,
+            size,
+            color,
+            uv: Vector4::new(0.0, 0.0, 1.0, 1.0),
+            texture: NO_TEXTURE,
+            opaque: color.w >= 1.0,
+        }
+    }
+
+    /// Draws the quad behind the quad of its object at the same depth, e.g. a
+    /// drop shadow. It is only drawn in the opaque phase along with an opaque
+    /// object: drawn before a transparent object, it would hide it, and drawn
+    /// transparent after an opaque object, it is hidden where they overlap.
+    fn behind(self, object: &Quad) -> Self {
+        Self {
+            opaque: self.opaque && object.opaque,
+            ..self
+        }
+    }
+
+    /// Draws the current frame of a sprite on the quad.
+    fn with_sprite(self, sprite: &AnimatedSprite) -> Self {
+        Self {
+            uv: sprite.uv(),
+            texture: sprite.atlas.texture,
+            ..self
+        }
+    }
+
+    /// Draws the quad without a texture unless its texture is among the given
+    /// number of textures of the array, whose other slots are never written.
+    fn within_textures(self, count: u32) -> Self {
+        if self.texture < count {
+            self
+        } else {
+            Self {
+                texture: NO_TEXTURE,
+                ..self
+            }
+        }
+    }
+
+    /// Returns true if the quad hides what is behind it, and is drawn in the
+    /// opaque phase.
+    fn is_opaque(&self) -> bool {
+        self.opaque
+    }
+
+    /// Returns the depth of the center of the quad once projected, smaller
+    /// depths being in front.
+    fn depth(&self, view_projection: Matrix4<f32>) -> f32 {
+        let center = self.position.mul_element_wise(self.size);
+        let clip = view_projection * center.extend(1.0);
+        clip.z / clip.w
+    }
+}
+
+/// Quad sorted into a draw phase, along with the callback of its object if it
+/// is the quad of the object itself.
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct SortedQuad {
+    quad: Quad,
+    depth: f32,
+    callback: Option<usize>,
+}
+
+/// Splits quads into opaque ones, sorted front to back so that hidden
+/// fragments fail the depth test, and transparent ones, sorted back to front
+/// so that they blend over what is behind them. Quads sharing a depth keep
+/// their order, so that outlines and shadows stay behind their object.
+fn sort_quads(
+    quads: impl IntoIterator<Item = (Quad, Option<usize>)>,
+    view_projection: Matrix4<f32>,
+) -> (Vec<SortedQuad>, Vec<SortedQuad>) {
+    profiling::scope!("sort_quads");
+    let (mut opaque, mut transparent): (Vec<_>, Vec<_>) = quads
+        .into_iter()
+        .map(|(quad, callback)| SortedQuad {
+            quad,
+            depth: quad.depth(view_projection),
+            callback,
+        })
+        .partition(|sorted| sorted.quad.is_opaque());
+    opaque.sort_by(|a, b| a.depth.total_cmp(&b.depth));
+    transparent.sort_by(|a, b| b.depth.total_cmp(&a.depth));
+    (opaque, transparent)
+}
+
+/// Quads drawn with the same pipeline, along with their buffers.
+struct QuadPhase {
+    name: &'static str,
+    batcher: QuadBatcher,
+    vertex_buffers: Vec<Buffer>,
+    index_buffers: Vec<Buffer>,
+}
+
+impl QuadPhase {
+    fn new(name: &'static str) -> Self {
+        Self {
+            name,
+            batcher: QuadBatcher::new(DEFAULT_MAX_QUADS),
+            vertex_buffers: Vec::new(),
+            index_buffers: Vec::new(),
+        }
+    }
+
+    /// Adds sorted quads, returning the positions in the batches of the
+    /// callbacks to record after them, in draw order.
+    fn add(&mut self, quads: &[SortedQuad], jobs: Option<&JobPool>) -> Vec<(usize, u32, usize)> {
+        profiling::scope!("QuadPhase.add");
+        // NOTE: batches are filled in order, so the n-th quad ends in batch
+        //       n / max_quads
+        let max_quads = self.batcher.max_quads as usize;
+        let added = self.batcher.len();
+        let callbacks = quads
+            .iter()
+            .enumerate()
+            .filter_map(|(i, sorted)| {
+                let n = added + i;
+                let index = (n % max_quads + 1) * QUAD_INDICES.len();
+                Some((n / max_quads, index as u32, sorted.callback?))
+            })
+            .collect();
+        self.batcher.add_sorted(quads, jobs);
+        callbacks
+    }
+
+    unsafe fn update_buffers(&mut self, device: &Device, staging: &mut StagingRing) -> Result<()> {
+        profiling::scope!("QuadPhase.update_buffers");
+        for (idx, batch) in self.batcher.batches.iter().enumerate() {
+            // create buffers if not exists
+            // NOTE: buffers are sized for a full batch so that they can be reused by
+            //       any batch
+            let buffer_exists = idx < self.vertex_buffers.len();
+            if !buffer_exists {
+                let max_quads = self.batcher.max_quads as u64;
+
+                // vertex buffer
+                let vertex_buffer = Buffer::new(
+                    device,
+                    vk::BufferUsageFlags::VERTEX_BUFFER | vk::BufferUsageFlags::TRANSFER_DST,
+                    vk::MemoryPropertyFlags::DEVICE_LOCAL,
+                    max_quads * QUAD_VERTICES.len() as u64 * mem::size_of::<Vertex>() as u64,
+                )
+                .map_err(|e| format!("create vertex input buffer: {:?}", e))?;
+                device.set_object_name(
+                    *vertex_buffer,
+                    &format!("renderer 2D {} vertex buffer {idx}", self.name),
+                );
+                self.vertex_buffers.push(vertex_buffer);
+
+                // index buffer
+                let index_buffer = Buffer::new(
+                    device,
+                    vk::BufferUsageFlags::INDEX_BUFFER | vk::BufferUsageFlags::TRANSFER_DST,
+                    vk::MemoryPropertyFlags::DEVICE_LOCAL,
+                    max_quads * QUAD_INDICES.len() as u64 * mem::size_of::<u32>() as u64,
+                )
+                .map_err(|e| format!("create index buffer: {:?}", e))?;
+                device.set_object_name(
+                    *index_buffer,
+                    &format!("renderer 2D {} index buffer {idx}", self.name),
+                );
+                self.index_buffers.push(index_buffer);
+            }
+
+            // upload through the staging ring
+            staging
+                .copy_to_buffer(&batch.vertices, *self.vertex_buffers[idx], 0)
+                .map_err(|e| format!("update vertex buffer: {:?}", e))?;
+            staging
+                .copy_to_buffer(&batch.indices, *self.index_buffers[idx], 0)
+                .map_err(|e| format!("update index buffer: {:?}", e))?;
+        }
+        Ok(())
+    }
+}
+
+/// Work done by the 2D renderer during the last frame.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct RenderStats {
+    pub draw_calls: u32,
+    pub quads: u32,
+}
+
 pub struct Renderer2DSystem {
     /// The vertex and fragment shaders.
+    /// NOTE: kept to rebuild the pipeline when a shader is reloaded.
     vertex_shader: Shader,
     fragment_shader: Shader,
 
-    // The descriptor pool used to allocate descriptor sets.
-    descriptor_pool: DescriptorPool,
+    // The descriptor pool used to allocate descriptor sets, only kept alive
+    // until the renderer is dropped.
+    _descriptor_pool: DescriptorPool,
 
-    // The descriptor set layout used to allocate descriptor sets.
+    // The descriptor set layout used to allocate descriptor sets, followed by
+    // the layout of the texture array once set.
     descriptor_set_layouts: Vec<DescriptorSetLayout>,
-    descriptor_sets: Vec<DescriptorSet>,
+    /// Texture array sampled by the quads of the sprites, see `set_textures()`,
+    /// along with the number of its slots written when it was set.
+    textures: Option<DescriptorSet>,
+    texture_count: u32,
 
-    /// Uniform buffer
+    /// Uniform buffers and their descriptor sets, one per frame in flight.
     uniform_buffer_data: UniformBuffer,
-    uniform_buffer: Buffer,
+    uniform_buffers: Vec<(Buffer, DescriptorSet)>,
+    frame_index: usize,
 
-    // Graphics pipeline.
-    pipeline: Pipeline,
+    // Graphics pipelines of the opaque and transparent phases, and of the
+    // depth pre-pass.
+    opaque_pipeline: Pipeline,
+    transparent_pipeline: Pipeline,
+    depth_pipeline: Pipeline,
+    depth_prepass: bool,
+    jobs: Option<Arc<JobPool>>,
 
     // stores quad data
-    quad_batcher: QuadBatcher,
+    opaque: QuadPhase,
+    transparent: QuadPhase,
 
-    // buffers
-    vertex_buffers: Vec<Buffer>,
-    index_buffers: Vec<Buffer>,
+    stats: RenderStats,
 }
 
 impl Renderer2DSystem {
-    pub unsafe fn new(device: &Device, renderpass: &RenderPass) -> Result<Self> {
+    pub unsafe fn new(
+        device: &Device,
+        renderpass: &RenderPass,
+        frames_in_flight: u32,
+    ) -> Result<Self> {
         // create shaders
-        let mut vertex_spv_file = Cursor::new(&include_bytes!("../shaders/quad.vert.spv")[..]);
-        let mut frag_spv_file = Cursor::new(&include_bytes!("../shaders/quad.frag.spv")[..]);
+        let mut vertex_spv_file = Cursor::new(&include_shader!("quad.vert")[..]);
+        let mut frag_spv_file = Cursor::new(&include_shader!("quad.frag")[..]);
 
         let vertex_shader = Shader::new(device, &mut vertex_spv_file)
             .map_err(|e| format!("create vertex shader module: {:?}", e))?;
@@ -191,243 +572,653 @@ impl Renderer2DSystem {
         // create descriptor pool
         let descriptor_pool_sizes = [vk::DescriptorPoolSize {
             ty: vk::DescriptorType::UNIFORM_BUFFER,
-            descriptor_count: 1,
+            descriptor_count: frames_in_flight,
         }];
-        let descriptor_pool = DescriptorPool::new(device, &descriptor_pool_sizes, 1)
+        let descriptor_pool = DescriptorPool::new(device, &descriptor_pool_sizes, frames_in_flight)
             .map_err(|e| format!("create descriptor pool: {:?}", e))?;
 
-        // create descriptor sets and layouts
-        let (descriptor_sets, descriptor_set_layouts) = {
-            let ds_layouts = {
-                let ds_layout_bindings = [vk::DescriptorSetLayoutBinding {
-                    binding: 0,
-                    descriptor_type: vk::DescriptorType::UNIFORM_BUFFER,
-                    descriptor_count: 1,
-                    stage_flags: vk::ShaderStageFlags::VERTEX,
-                    ..Default::default()
-                }];
-                let ds_layout = DescriptorSetLayout::new(device, &ds_layout_bindings)
-                    .map_err(|e| format!("create descriptor set layout: {:?}", e))?;
-                vec![ds_layout]
-            };
-            let ds = DescriptorSet::new(device, &descriptor_pool, &ds_layouts)
-                .map_err(|e| format!("create UBO descriptor set: {:?}", e))?;
-
-            (ds, ds_layouts)
+        // create descriptor set layouts
+        let descriptor_set_layouts = {
+            let ds_layout_bindings = [vk::DescriptorSetLayoutBinding {
+                binding: 0,
+                descriptor_type: vk::DescriptorType::UNIFORM_BUFFER,
+                descriptor_count: 1,
+                stage_flags: vk::ShaderStageFlags::VERTEX,
+                ..Default::default()
+            }];
+            let ds_layout = DescriptorSetLayout::new(device, &ds_layout_bindings)
+                .map_err(|e| format!("create descriptor set layout: {:?}", e))?;
+            vec![ds_layout]
         };
 
-        // update descriptor sets
-        let (uniform_buffer, uniform_buffer_data, uniform_buffer_data_size) = {
-            let buf_data = UniformBuffer::new(Matrix4::identity());
-            let buf_size = mem::size_of_val(&buf_data) as u64;
+        // create uniform buffers and their descriptor sets
+        // NOTE: the uniform buffer is written every frame, so each frame in flight
+        //       needs its own
+        let uniform_buffer_data = UniformBuffer::new(Matrix4::identity());
+        let uniform_buffer_data_size = mem::size_of_val(&uniform_buffer_data) as u64;
+        let mut uniform_buffers = Vec::with_capacity(frames_in_flight as usize);
+        for _ in 0..frames_in_flight {
             let mut buf = Buffer::new(
                 device,
-                device.memory_properties(),
                 vk::BufferUsageFlags::UNIFORM_BUFFER,
                 vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT,
-                buf_size,
+                uniform_buffer_data_size,
             )
             .map_err(|e| format!("create uniform buffer: {:?}", e))?;
-            buf.update(device, &[buf_data])
+            device.set_object_name(*buf, "renderer 2D uniform buffer");
+            buf.update(&[uniform_buffer_data])
                 .map_err(|e| format!("update uniform buffer: {:?}", e))?;
-            (buf, buf_data, buf_size)
-        };
 
-        descriptor_sets[0]
-            .update_ubo(device, &uniform_buffer, 0, uniform_buffer_data_size)
-            .map_err(|e| format!("update descriptor set: {:?}", e))?;
+            let ds = DescriptorSet::new(device, &descriptor_pool, &descriptor_set_layouts)
+                .map_err(|e| format!("create UBO descriptor set: {:?}", e))?[0];
+            ds.update_ubo(device, &buf, 0, uniform_buffer_data_size)
+                .map_err(|e| format!("update descriptor set: {:?}", e))?;
 
-        // create graphics pipeline
-        let pipeline = {
-            let vertex_input_description = Vertex::input_description();
-            Pipeline::new(
-                device,
-                renderpass,
-                &vertex_shader,
-                &fragment_shader,
-                &vertex_input_description.bindings,
-                &vertex_input_description.attributes,
-                &descriptor_set_layouts,
-            )
-            .map_err(|e| format!("create pipeline and layout: {:?}", e))?
-        };
+            uniform_buffers.push((buf, ds));
+        }
 
-        // create quad batcher
-        let quad_batcher = QuadBatcher::new(DEFAULT_MAX_QUADS);
+        // create graphics pipelines
+        let [opaque_pipeline, transparent_pipeline, depth_pipeline] = Self::create_pipelines(
+            device,
+            renderpass,
+            &vertex_shader,
+            &fragment_shader,
+            &descriptor_set_layouts,
+        )?;
 
         Ok(Self {
             vertex_shader,
             fragment_shader,
-            descriptor_pool,
+            _descriptor_pool: descriptor_pool,
             descriptor_set_layouts,
-            descriptor_sets,
+            textures: None,
+            texture_count: 0,
             uniform_buffer_data,
-            uniform_buffer,
-            pipeline,
-            quad_batcher,
-            vertex_buffers: Vec::new(),
-            index_buffers: Vec::new(),
+            uniform_buffers,
+            frame_index: 0,
+            opaque_pipeline,
+            transparent_pipeline,
+            depth_pipeline,
+            depth_prepass: false,
+            jobs: None,
+            opaque: QuadPhase::new("opaque"),
+            transparent: QuadPhase::new("transparent"),
+            stats: RenderStats::default(),
         })
     }
 
-    unsafe fn update_uniform_buffer(
+    /// Creates the pipelines of the opaque and transparent phases and of the
+    /// depth pre-pass.
+    unsafe fn create_pipelines(
+        device: &Device,
+        renderpass: &RenderPass,
+        vertex_shader: &Shader,
+        fragment_shader: &Shader,
+        descriptor_set_layouts: &[DescriptorSetLayout],
+    ) -> Result<[Pipeline; 3]> {
+        let vertex_input_description = Vertex::input_description();
+        let create_pipeline = |state, name| -> Result<Pipeline> {
+            let pipeline = Pipeline::new_with_state(
+                device,
+                renderpass,
+                vertex_shader,
+                fragment_shader,
+                &Default::default(),
+                state,
+                &vertex_input_description.bindings,
+                &vertex_input_description.attributes,
+                descriptor_set_layouts,
+            )
+            .map_err(|e| format!("create {name} pipeline and layout: {:?}", e))?;
+            pipeline.set_name(device, &format!("renderer 2D {name}"));
+            Ok(pipeline)
+        };
+        Ok([
+            create_pipeline(PipelineState::OPAQUE, "opaque")?,
+            create_pipeline(PipelineState::TRANSPARENT, "transparent")?,
+            create_pipeline(PipelineState::DEPTH_ONLY, "depth pre-pass")?,
+        ])
+    }
+
+    /// Returns true if the depth of opaque quads is drawn before their color.
+    pub fn depth_prepass(&self) -> bool {
+        self.depth_prepass
+    }
+
+    /// Draws the depth of opaque quads before their color, so that only their
+    /// visible fragments are shaded. Worth it when opaque quads overlap a lot.
+    pub fn set_depth_prepass(&mut self, depth_prepass: bool) {
+        self.depth_prepass = depth_prepass;
+    }
+
+    /// Builds the quad batches in parallel on the jobs of a pool, once the
+    /// quads fill several batches. Use None to build them on the calling
+    /// thread.
+    pub fn set_job_pool(&mut self, jobs: Option<Arc<JobPool>>) {
+        self.jobs = jobs;
+    }
+
+    /// Draws the frames of the sprites from a texture array, indexed by
+    /// `SpriteAtlas::texture`. Quads of sprites are drawn with their color
+    /// only until then, as are the ones whose texture was not inserted in the
+    /// array yet; set it again to draw the textures inserted since. The array
+    /// must outlive the system.
+    pub unsafe fn set_textures(
         &mut self,
         device: &Device,
-        view_projection: Matrix4<f32>,
+        renderpass: &RenderPass,
+        textures: &BindlessTextures,
     ) -> Result<()> {
-        // TIME!("Renderer2DSystem.update_uniform_buffer");
+        let mut frag_spv_file = Cursor::new(&include_shader!("quad_textured.frag")[..]);
+        self.fragment_shader = Shader::new(device, &mut frag_spv_file)
+            .map_err(|e| format!("create fragment shader module: {:?}", e))?;
+        let layout = textures
+            .compatible_layout(device)
+            .map_err(|e| format!("create texture array layout: {:?}", e))?;
+        self.descriptor_set_layouts.truncate(1);
+        self.descriptor_set_layouts.push(layout);
+        self.textures = Some(*textures.descriptor_set());
+        self.texture_count = textures.written_slots();
+
+        // NOTE: the previous pipelines are destroyed once the frames using
+        //       them have completed
+        [
+            self.opaque_pipeline,
+            self.transparent_pipeline,
+            self.depth_pipeline,
+        ] = Self::create_pipelines(
+            device,
+            renderpass,
+            &self.vertex_shader,
+            &self.fragment_shader,
+            &self.descriptor_set_layouts,
+        )?;
+        Ok(())
+    }
+
+    /// Rebuilds the pipelines if they use the shader. Returns true if they do.
+    #[cfg(feature = "shader-hot-reload")]
+    pub unsafe fn reload_shader(
+        &mut self,
+        device: &Device,
+        renderpass: &RenderPass,
+        shader: &hot_reload::ReloadedShader,
+    ) -> Result<bool> {
+        let module = match shader.name.as_str() {
+            "quad.vert" => &mut self.vertex_shader,
+            "quad.frag" if self.textures.is_none() => &mut self.fragment_shader,
+            "quad_textured.frag" if self.textures.is_some() => &mut self.fragment_shader,
+            _ => return Ok(false),
+        };
+        *module = Shader::new(device, &mut Cursor::new(&shader.spv))
+            .map_err(|e| format!("create shader module: {:?}", e))?;
+        // NOTE: the previous pipelines are destroyed once the frames using
+        //       them have completed
+        [
+            self.opaque_pipeline,
+            self.transparent_pipeline,
+            self.depth_pipeline,
+        ] = Self::create_pipelines(
+            device,
+            renderpass,
+            &self.vertex_shader,
+            &self.fragment_shader,
+            &self.descriptor_set_layouts,
+        )?;
+        Ok(true)
+    }
+
+    unsafe fn update_uniform_buffer(&mut self, view_projection: Matrix4<f32>) -> Result<()> {
+        profiling::scope!("Renderer2DSystem.update_uniform_buffer");
         self.uniform_buffer_data.vp = view_projection;
-        self.uniform_buffer
-            .update(device, &[self.uniform_buffer_data])
+        self.uniform_buffers[self.frame_index]
+            .0
+            .update(&[self.uniform_buffer_data])
             .map_err(|e| format!("update uniform buffer: {:?}", e))?;
         Ok(())
     }
 
-    unsafe fn update_buffers(&mut self, device: &Device) -> Result<()> {
-        // TIME!("Renderer2DSystem.update_buffers");
-        for (idx, batch) in self.quad_batcher.batches.iter().enumerate() {
-            // vertex buffer
-            let vertex_buffer_data = &batch.vertices;
-            let vertex_buffer_data_size = mem::size_of_val(&**vertex_buffer_data) as u64;
+    /// Draws the opaque quads of the objects front to back, then their
+    /// transparent quads back to front, those sharing a depth being drawn in
+    /// order. An object can come with a callback, which records custom
+    /// commands right after the quad of the object is drawn.
+    pub unsafe fn render<'a, I, C>(
+        &mut self,
+        device: &Device,
+        command_buffer: vk::CommandBuffer,
+        staging: &mut StagingRing,
+        _: time::Duration,
+        view_projection: Matrix4<f32>,
+        objects: I,
+    ) -> Result<()>
+    where
+        I: IntoIterator<Item = (QuadView<'a>, Option<C>)>,
+        C: FnOnce(vk::CommandBuffer, &mut StagingRing),
+    {
+        profiling::scope!("Renderer2DSystem.render");
+        // use the uniform buffer of the next frame in flight
+        // NOTE: it was last used frames_in_flight frames ago, its fence has been
+        //       waited on by the renderer.
+        self.frame_index = (self.frame_index + 1) % self.uniform_buffers.len();
+
+        // update uniform buffer
+        self.update_uniform_buffer(view_projection)
+            .map_err(|e| format!("update uniform buffer: {:?}", e))?;
 
-            // index buffer
-            let index_buffer_data = &batch.indices;
-            let index_buffer_data_size = mem::size_of_val(&**index_buffer_data) as u64;
+        // sort the quads into the opaque and transparent phases
+        // NOTE: callbacks are recorded once the quad buffers are uploaded, after
+        //       the quad of their object in its phase
+        let mut callbacks = Vec::new();
+        let texture_count = self.texture_count;
+        let quads = objects.into_iter().flat_map(|(object, callback)| {
+            let callback = callback.map(|callback| {
+                callbacks.push(Some(callback));
+                callbacks.len() - 1
+            });
+            let mut quads = object_quads(object)
+                .map(|quad| quad.within_textures(texture_count))
+                .peekable();
+            std::iter::from_fn(move || {
+                let quad = quads.next()?;
+                let is_object = quads.peek().is_none();
+                Some((quad, callback.filter(|_| is_object)))
+            })
+        });
+        let (opaque, transparent) = sort_quads(quads, view_projection);
+        let opaque_callbacks = self.opaque.add(&opaque, self.jobs.as_deref());
+        let transparent_callbacks = self.transparent.add(&transparent, self.jobs.as_deref());
 
-            // create buffers if not exists
-            let buffer_exists = idx < self.vertex_buffers.len();
-            if !buffer_exists {
-                // vertex buffer
-                let vertex_buffer = Buffer::new(
-                    device,
-                    device.memory_properties(),
-                    vk::BufferUsageFlags::VERTEX_BUFFER,
-                    vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT,
-                    vertex_buffer_data_size,
-                )
-                .map_err(|e| format!("create vertex input buffer: {:?}", e))?;
-                self.vertex_buffers.push(vertex_buffer);
+        // update quad buffers
+        self.opaque
+            .update_buffers(device, staging)
+            .map_err(|e| format!("update opaque quad buffers: {:?}", e))?;
+        self.transparent
+            .update_buffers(device, staging)
+            .map_err(|e| format!("update transparent quad buffers: {:?}", e))?;
 
-                // index buffer
-                let index_buffer = Buffer::new(
+        // record the depth pre-pass, then the opaque and transparent phases
+        self.stats = RenderStats::default();
+        if self.depth_prepass {
+            for (idx, batch) in self.opaque.batcher.batches.iter().enumerate() {
+                self.bind(
                     device,
-                    device.memory_properties(),
-                    vk::BufferUsageFlags::INDEX_BUFFER,
-                    vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT,
-                    index_buffer_data_size,
-                )
-                .map_err(|e| format!("create index buffer: {:?}", e))?;
-                self.index_buffers.push(index_buffer);
+                    command_buffer,
+                    &self.depth_pipeline,
+                    &self.opaque,
+                    idx,
+                );
+                device.cmd_draw_indexed(command_buffer, batch.indices.len() as u32, 1, 0, 0, 1);
+                self.stats.draw_calls += 1;
             }
+        }
+        for (pipeline, phase, phase_callbacks) in [
+            (&self.opaque_pipeline, &self.opaque, opaque_callbacks),
+            (
+                &self.transparent_pipeline,
+                &self.transparent,
+                transparent_callbacks,
+            ),
+        ] {
+            let mut phase_callbacks = phase_callbacks.into_iter().peekable();
+            for (idx, batch) in phase.batcher.batches.iter().enumerate() {
+                let index_count = batch.indices.len() as u32;
+                self.stats.quads += index_count / QUAD_INDICES.len() as u32;
+                self.bind(device, command_buffer, pipeline, phase, idx);
 
-            // vertex buffer
-            self.vertex_buffers
-                .get_mut(idx)
-                .expect("vertex buffer exists")
-                .update(device, vertex_buffer_data)
-                .map_err(|e| format!("update vertex buffer: {:?}", e))?;
+                // draw the batch up to each callback, then rebind what the
+                // callback may have changed
+                let mut first_index = 0;
+                while let Some((_, index, callback)) =
+                    phase_callbacks.next_if(|(batch, ..)| *batch == idx)
+                {
+                    device.cmd_draw_indexed(
+                        command_buffer,
+                        index - first_index,
+                        1,
+                        first_index,
+                        0,
+                        1,
+                    );
+                    self.stats.draw_calls += 1;
+                    if let Some(callback) = callbacks[callback].take() {
+                        callback(command_buffer, staging);
+                    }
+                    self.bind(device, command_buffer, pipeline, phase, idx);
+                    first_index = index;
+                }
 
-            // index buffer
-            self.index_buffers
-                .get_mut(idx)
-                .expect("index buffer exists")
-                .update(device, index_buffer_data)
-                .map_err(|e| format!("update index buffer: {:?}", e))?;
+                // draw
+                if first_index < index_count {
+                    device.cmd_draw_indexed(
+                        command_buffer,
+                        index_count - first_index,
+                        1,
+                        first_index,
+                        0,
+                        1,
+                    );
+                    self.stats.draw_calls += 1;
+                }
+            }
         }
+
+        // clear quad batchers
+        self.opaque.batcher.clear();
+        self.transparent.batcher.clear();
+
         Ok(())
     }
 
-    pub unsafe fn render(
+    /// Draws the objects into a render target instead of the main render pass.
+    /// The target must use the color format of the render pass the system was
+    /// created with. Must be recorded outside of a render pass. The system
+    /// renders once per frame, either into the main render pass or a target.
+    #[allow(clippy::too_many_arguments)]
+    pub unsafe fn render_to_target<'a, I, C>(
         &mut self,
         device: &Device,
         command_buffer: vk::CommandBuffer,
-        _: time::Duration,
+        target: &RenderTarget,
+        staging: &mut StagingRing,
+        delta_time: time::Duration,
         view_projection: Matrix4<f32>,
-        objects: &[GameObject],
-    ) -> Result<()> {
-        // TIME!("Renderer2DSystem.render");
-        // update uniform buffer
-        self.update_uniform_buffer(device, view_projection)
-            .map_err(|e| format!("update uniform buffer: {:?}", e))?;
-
-        // add quads
-        for object in objects {
-            self.quad_batcher.add_quad(
-                object.transform.position,
-                object.transform.scale,
-                object.color.color,
-            );
-        }
+        objects: I,
+    ) -> Result<()>
+    where
+        I: IntoIterator<Item = (QuadView<'a>, Option<C>)>,
+        C: FnOnce(vk::CommandBuffer, &mut StagingRing),
+    {
+        target.begin(device, command_buffer);
+        let result = self.render(
+            device,
+            command_buffer,
+            staging,
+            delta_time,
+            view_projection,
+            objects,
+        );
+        target.end(device, command_buffer);
+        result
+    }
 
-        // update quad buffers
-        self.update_buffers(device)
-            .map_err(|e| format!("update quad buffers: {:?}", e))?;
+    pub fn stats(&self) -> RenderStats {
+        self.stats
+    }
 
-        // record and submit command buffer
-        // bind descriptor sets (UBO)
+    /// Binds a pipeline, the uniform buffer and the quad buffers of a batch of
+    /// a phase.
+    unsafe fn bind(
+        &self,
+        device: &Device,
+        command_buffer: vk::CommandBuffer,
+        pipeline: &Pipeline,
+        phase: &QuadPhase,
+        batch: usize,
+    ) {
+        // bind descriptor sets (UBO and textures)
         device.cmd_bind_descriptor_sets(
             command_buffer,
             vk::PipelineBindPoint::GRAPHICS,
-            self.pipeline.layout,
+            pipeline.layout,
             0,
-            &[*self.descriptor_sets[0]],
+            &[*self.uniform_buffers[self.frame_index].1],
             &[],
         );
+        if let Some(textures) = self.textures {
+            device.cmd_bind_descriptor_sets(
+                command_buffer,
+                vk::PipelineBindPoint::GRAPHICS,
+                pipeline.layout,
+                1,
+                &[*textures],
+                &[],
+            );
+        }
 
         // bind pipeline
-        device.cmd_bind_pipeline(
+        device.cmd_bind_pipeline(command_buffer, vk::PipelineBindPoint::GRAPHICS, **pipeline);
+
+        // bind vertex buffers
+        device.cmd_bind_vertex_buffers(command_buffer, 0, &[*phase.vertex_buffers[batch]], &[0]);
+
+        // bind index buffer
+        device.cmd_bind_index_buffer(
             command_buffer,
-            vk::PipelineBindPoint::GRAPHICS,
-            *self.pipeline,
+            *phase.index_buffers[batch],
+            0,
+            vk::IndexType::UINT32,
         );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::ecs::World;
+    use core::object::GameObject;
+    use core::sprite::SpriteAtlas;
 
-        for (idx, batch) in self.quad_batcher.batches.iter().enumerate() {
-            let vertex_buffer = self.vertex_buffers[idx];
-            let index_buffer = self.index_buffers[idx];
-            let index_count = batch.indices.len() as u32;
+    use cgmath::Vector2;
 
-            // bind vertex buffers
-            device.cmd_bind_vertex_buffers(command_buffer, 0, &[*vertex_buffer], &[0]);
+    use super::*;
 
-            // bind index buffer
-            device.cmd_bind_index_buffer(command_buffer, *index_buffer, 0, vk::IndexType::UINT32);
+    fn bounds(vertices: &[Vertex]) -> (Vector4<f32>, Vector4<f32>) {
+        (vertices[0].pos, vertices[2].pos)
+    }
 
-            // draw
-            device.cmd_draw_indexed(command_buffer, index_count, 1, 0, 0, 1);
+    #[test]
+    fn outline_and_shadow_surround_object() {
+        let mut world = World::new();
+        world.spawn(
+            GameObject::new()
+                .with_position(Vector3::new(2.0, 3.0, 0.0))
+                .with_scale(Vector3::new(10.0, 20.0, 1.0))
+                .with_outline(Vector4::new(1.0, 1.0, 1.0, 1.0), 2.0)
+                .with_drop_shadow(Vector4::new(0.0, 0.0, 0.0, 0.5), Vector2::new(4.0, -4.0)),
+        );
+        let mut batcher = QuadBatcher::new(DEFAULT_MAX_QUADS);
+        for object in world.query::<QuadView>() {
+            batcher.add_object(object);
         }
 
-        // clear quad batcher
-        self.quad_batcher.clear();
+        let vertices = &batcher.batches[0].vertices;
+        assert_eq!(vertices.len(), 12);
 
-        Ok(())
-    }
+        // the object spans [10, 30] x [40, 80]
+        let (shadow_min, shadow_max) = bounds(&vertices[0..4]);
+        assert_eq!((shadow_min.x, shadow_min.y), (14.0, 36.0));
+        assert_eq!((shadow_max.x, shadow_max.y), (34.0, 76.0));
 
-    pub unsafe fn destroy(&mut self, device: &Device) {
-        debug!("Destroying Renderer2DSystem");
+        let (outline_min, outline_max) = bounds(&vertices[4..8]);
+        assert_eq!((outline_min.x, outline_min.y), (8.0, 38.0));
+        assert_eq!((outline_max.x, outline_max.y), (32.0, 82.0));
 
-        // NOTE: All submitted commands that refer to these resources must have
-        // completed execution.
-        device.device_wait_idle().expect("device wait idle");
+        let (min, max) = bounds(&vertices[8..12]);
+        assert_eq!((min.x, min.y), (10.0, 40.0));
+        assert_eq!((max.x, max.y), (30.0, 80.0));
+    }
 
-        // buffers
-        for mut buffer in &mut self.vertex_buffers.drain(..) {
-            buffer.destroy(device);
-        }
-        for mut buffer in &mut self.index_buffers.drain(..) {
-            buffer.destroy(device);
+    #[test]
+    fn sprites_draw_their_frame() {
+        let mut world = World::new();
+        let object = world.spawn(
+            GameObject::new()
+                .with_drop_shadow(Vector4::new(0.0, 0.0, 0.0, 0.5), Vector2::new(1.0, -1.0)),
+        );
+        let mut sprite = AnimatedSprite::new(SpriteAtlas::new(3, 2, 2), 10.0);
+        sprite.advance(time::Duration::from_millis(300));
+        world.insert(object, sprite);
+        let mut batcher = QuadBatcher::new(DEFAULT_MAX_QUADS);
+        for object in world.query::<QuadView>() {
+            batcher.add_object(object);
         }
-        // pipeline
-        self.pipeline.destroy(device);
-        // uniform buffer
-        self.uniform_buffer.destroy(device);
-        // descriptor set layout
-        for mut layout in &mut self.descriptor_set_layouts.drain(..) {
-            layout.destroy(device);
+
+        // the shadow is not textured, the last frame is the bottom right one
+        let vertices = &batcher.batches[0].vertices;
+        assert!(vertices[..4].iter().all(|v| v.texture == NO_TEXTURE));
+        assert!(vertices[4..].iter().all(|v| v.texture == 3));
+        assert_eq!(vertices[4].uv, Vector2::new(0.5, 1.0));
+        assert_eq!(vertices[6].uv, Vector2::new(1.0, 0.5));
+    }
+
+    #[test]
+    fn animated_objects_are_textured_once_their_texture_is_set() {
+        let mut world = World::new();
+        let object = world.spawn(GameObject::new());
+        world.insert(object, AnimatedSprite::new(SpriteAtlas::new(1, 2, 2), 10.0));
+        let textures = |count| -> Vec<_> {
+            world
+                .query::<QuadView>()
+                .flat_map(object_quads)
+                .map(|quad| quad.within_textures(count).texture)
+                .collect()
+        };
+
+        assert_eq!(textures(0), [NO_TEXTURE]);
+        assert_eq!(textures(1), [NO_TEXTURE]);
+        assert_eq!(textures(2), [1]);
+    }
+
+    #[test]
+    fn end_position_follows_batches() {
+        let mut world = World::new();
+        world.spawn(GameObject::new());
+        let object = world.query::<QuadView>().next().unwrap();
+        let mut batcher = QuadBatcher::new(2);
+        assert_eq!(batcher.end_position(), (0, 0));
+
+        batcher.add_object(object);
+        assert_eq!(batcher.end_position(), (0, 6));
+        batcher.add_object(object);
+        assert_eq!(batcher.end_position(), (0, 12));
+        batcher.add_object(object);
+        assert_eq!(batcher.end_position(), (1, 6));
+    }
+
+    #[test]
+    fn batches_built_in_parallel_match() {
+        let quads: Vec<_> = (0..25)
+            .map(|i| SortedQuad {
+                quad: Quad::new(
+                    Vector3::new(i as f32, 0.0, 0.0),
+                    Vector3::new(1.0, 1.0, 1.0),
+                    Vector4::new(1.0, 1.0, 1.0, 1.0),
+                ),
+                depth: 0.0,
+                callback: (i % 7 == 0).then_some(i),
+            })
+            .collect();
+        let jobs = JobPool::new(2);
+        let mut sequential = QuadPhase::new("sequential");
+        let mut parallel = QuadPhase::new("parallel");
+        sequential.batcher.max_quads = 4;
+        parallel.batcher.max_quads = 4;
+        for phase in [&mut sequential, &mut parallel] {
+            phase.batcher.add_quad(
+                Vector3::new(-1.0, 0.0, 0.0),
+                Vector3::new(1.0, 1.0, 1.0),
+                Vector4::new(1.0, 1.0, 1.0, 1.0),
+            );
         }
-        // descriptor pool
-        self.descriptor_pool.destroy(device);
-        // shaders
-        self.vertex_shader.destroy(device);
-        self.fragment_shader.destroy(device);
+
+        let callbacks = sequential.add(&quads, None);
+        assert_eq!(callbacks, [(0, 12, 0), (2, 6, 7), (3, 24, 14), (5, 18, 21)]);
+        assert_eq!(parallel.add(&quads, Some(&jobs)), callbacks);
+        assert_eq!(parallel.batcher.batches, sequential.batcher.batches);
+        assert_eq!(parallel.batcher.end_position(), (6, 12));
+        assert_eq!(sequential.batcher.end_position(), (6, 12));
+    }
+
+    #[test]
+    fn quads_are_sorted_by_phase_and_depth() {
+        let quad = |z: f32, alpha: f32| {
+            Quad::new(
+                Vector3::new(0.0, 0.0, z),
+                Vector3::new(1.0, 1.0, 1.0),
+                Vector4::new(1.0, 1.0, 1.0, alpha),
+            )
+        };
+        let quads = [
+            (quad(0.5, 1.0), None),
+            (quad(0.2, 0.5), None),
+            (quad(0.2, 1.0), Some(0)),
+            (quad(0.8, 0.5), Some(1)),
+            (quad(0.2, 1.0), Some(2)),
+            (quad(0.2, 0.5), Some(3)),
+        ];
+        let (opaque, transparent) = sort_quads(quads, Matrix4::identity());
+
+        // opaque quads front to back, transparent ones back to front, quads
+        // sharing a depth keeping their order
+        let order = |sorted: &[SortedQuad]| -> Vec<_> {
+            sorted.iter().map(|s| (s.depth, s.callback)).collect()
+        };
+        assert_eq!(
+            order(&opaque),
+            [(0.2, Some(0)), (0.2, Some(2)), (0.5, None)]
+        );
+        assert_eq!(
+            order(&transparent),
+            [(0.8, Some(1)), (0.2, None), (0.2, Some(3))]
+        );
+    }
+
+    #[test]
+    fn outlines_and_shadows_are_drawn_behind_their_object() {
+        let phases = |object: GameObject| {
+            let mut world = World::new();
+            world.spawn(object.with_position(Vector3::new(1.0, 2.0, 0.5)));
+            let quads = world
+                .query::<QuadView>()
+                .flat_map(object_quads)
+                .enumerate()
+                .map(|(i, quad)| (quad, Some(i)));
+            let (opaque, transparent) = sort_quads(quads, Matrix4::identity());
+            let order = |sorted: &[SortedQuad]| -> Vec<_> {
+                sorted.iter().map(|s| s.callback.unwrap()).collect()
+            };
+            (order(&opaque), order(&transparent))
+        };
+        let opaque = Vector4::new(1.0, 0.0, 0.0, 1.0);
+        let translucent = Vector4::new(0.0, 0.0, 0.0, 0.5);
+
+        // a translucent shadow and outline are blended after an opaque
+        // object, which hides them where they overlap since transparent
+        // quads are hidden by opaque ones at the same depth
+        let (shadow, outline, object) = (0, 1, 2);
+        assert_eq!(
+            phases(
+                GameObject::new()
+                    .with_color(opaque)
+                    .with_drop_shadow(translucent, Vector2::new(0.25, -0.25))
+                    .with_outline(translucent, 0.25)
+            ),
+            (vec![object], vec![shadow, outline])
+        );
+        assert_eq!(
+            PipelineState::TRANSPARENT.depth_compare,
+            vk::CompareOp::LESS
+        );
+
+        // an opaque outline is blended before a translucent object, rather
+        // than hiding it
+        let (outline, object) = (0, 1);
+        assert_eq!(
+            phases(
+                GameObject::new()
+                    .with_color(translucent)
+                    .with_outline(opaque, 0.25)
+            ),
+            (vec![], vec![outline, object])
+        );
+
+        // an opaque outline is drawn first in the phase of an opaque object
+        assert_eq!(
+            phases(
+                GameObject::new()
+                    .with_color(opaque)
+                    .with_outline(opaque, 0.25)
+            ),
+            (vec![outline, object], vec![])
+        );
     }
 }
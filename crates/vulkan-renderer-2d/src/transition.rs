@@ -0,0 +1,342 @@
+use std::io::Cursor;
+use std::{mem, slice};
+
+use ash::vk;
+use cgmath::Vector4;
+use vulkan_renderer::descriptor::{DescriptorPool, DescriptorSet, DescriptorSetLayout};
+use vulkan_renderer::device::Device;
+use vulkan_renderer::include_shader;
+use vulkan_renderer::pipeline::{Pipeline, PipelineState};
+use vulkan_renderer::render_target::RenderTarget;
+use vulkan_renderer::renderpass::RenderPass;
+use vulkan_renderer::shader::Shader;
+
+use crate::Result;
+
+/// Direction a wipe moves in across the viewport.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WipeDirection {
+    Left,
+    Right,
+    Up,
+    Down,
+}
+
+impl WipeDirection {
+    /// Returns the direction in uv space, with y pointing down.
+    fn uv(self) -> [f32; 2] {
+        match self {
+            Self::Left => [-1.0, 0.0],
+            Self::Right => [1.0, 0.0],
+            Self::Up => [0.0, -1.0],
+            Self::Down => [0.0, 1.0],
+        }
+    }
+}
+
+/// Push constants of fade.frag.
+#[repr(C)]
+struct FadeConstants {
+    color: [f32; 4],
+    /// Zero for a fade.
+    direction: [f32; 2],
+    amount: f32,
+}
+
+/// Draws the effects of screen transitions over the viewport of the current
+/// render pass, whatever was drawn before them: fades and wipes to a color,
+/// and crossfades from a render target holding the previous screen.
+pub struct TransitionRenderer {
+    /// The vertex and fragment shaders.
+    /// NOTE: only read to rebuild the pipelines when a shader is reloaded,
+    ///       along with the descriptor set layouts.
+    #[cfg_attr(not(feature = "shader-hot-reload"), allow(dead_code))]
+    vertex_shader: Shader,
+    #[cfg_attr(not(feature = "shader-hot-reload"), allow(dead_code))]
+    fade_shader: Shader,
+    #[cfg_attr(not(feature = "shader-hot-reload"), allow(dead_code))]
+    crossfade_shader: Shader,
+
+    // The descriptor pool used to allocate descriptor sets, only kept alive
+    // until the renderer is dropped.
+    _descriptor_pool: DescriptorPool,
+
+    // The descriptor set layout used to allocate descriptor sets.
+    #[cfg_attr(not(feature = "shader-hot-reload"), allow(dead_code))]
+    descriptor_set_layouts: Vec<DescriptorSetLayout>,
+
+    /// Descriptor sets pointing at the crossfaded target, one per frame in
+    /// flight.
+    descriptor_sets: Vec<DescriptorSet>,
+    frame_index: usize,
+
+    fade_pipeline: Pipeline,
+    crossfade_pipeline: Pipeline,
+}
+
+impl TransitionRenderer {
+    pub unsafe fn new(
+        device: &Device,
+        renderpass: &RenderPass,
+        frames_in_flight: u32,
+    ) -> Result<Self> {
+        // create shaders
+        // NOTE: the effects cover the viewport with the triangle of the compositor
+        let mut vertex_spv_file = Cursor::new(&include_shader!("composite.vert")[..]);
+        let mut fade_spv_file = Cursor::new(&include_shader!("fade.frag")[..]);
+        let mut crossfade_spv_file = Cursor::new(&include_shader!("crossfade.frag")[..]);
+
+        let vertex_shader = Shader::new(device, &mut vertex_spv_file)
+            .map_err(|e| format!("create vertex shader module: {:?}", e))?;
+
+        let fade_shader = Shader::new(device, &mut fade_spv_file)
+            .map_err(|e| format!("create fade shader module: {:?}", e))?;
+
+        let crossfade_shader = Shader::new(device, &mut crossfade_spv_file)
+            .map_err(|e| format!("create crossfade shader module: {:?}", e))?;
+
+        // create descriptor pool
+        let descriptor_pool_sizes = [vk::DescriptorPoolSize {
+            ty: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+            descriptor_count: frames_in_flight,
+        }];
+        let descriptor_pool = DescriptorPool::new(device, &descriptor_pool_sizes, frames_in_flight)
+            .map_err(|e| format!("create descriptor pool: {:?}", e))?;
+
+        // create descriptor set layouts
+        let descriptor_set_layouts = {
+            let ds_layout_bindings = [vk::DescriptorSetLayoutBinding {
+                binding: 0,
+                descriptor_type: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+                descriptor_count: 1,
+                stage_flags: vk::ShaderStageFlags::FRAGMENT,
+                ..Default::default()
+            }];
+            let ds_layout = DescriptorSetLayout::new(device, &ds_layout_bindings)
+                .map_err(|e| format!("create descriptor set layout: {:?}", e))?;
+            vec![ds_layout]
+        };
+
+        // create descriptor sets
+        // NOTE: the crossfaded target changes with each transition, so each
+        //       frame in flight points its own set at the target it draws
+        let mut descriptor_sets = Vec::with_capacity(frames_in_flight as usize);
+        for _ in 0..frames_in_flight {
+            let ds = DescriptorSet::new(device, &descriptor_pool, &descriptor_set_layouts)
+                .map_err(|e| format!("create descriptor set: {:?}", e))?[0];
+            descriptor_sets.push(ds);
+        }
+
+        // create graphics pipelines
+        let fade_pipeline =
+            Self::create_fade_pipeline(device, renderpass, &vertex_shader, &fade_shader)?;
+        let crossfade_pipeline = Self::create_crossfade_pipeline(
+            device,
+            renderpass,
+            &vertex_shader,
+            &crossfade_shader,
+            &descriptor_set_layouts,
+        )?;
+
+        Ok(Self {
+            vertex_shader,
+            fade_shader,
+            crossfade_shader,
+            _descriptor_pool: descriptor_pool,
+            descriptor_set_layouts,
+            descriptor_sets,
+            frame_index: 0,
+            fade_pipeline,
+            crossfade_pipeline,
+        })
+    }
+
+    unsafe fn create_fade_pipeline(
+        device: &Device,
+        renderpass: &RenderPass,
+        vertex_shader: &Shader,
+        fragment_shader: &Shader,
+    ) -> Result<Pipeline> {
+        let push_constant_ranges = [vk::PushConstantRange {
+            stage_flags: vk::ShaderStageFlags::FRAGMENT,
+            offset: 0,
+            size: mem::size_of::<FadeConstants>() as u32,
+        }];
+        let pipeline = Pipeline::new_with_state_and_push_constants(
+            device,
+            renderpass,
+            vertex_shader,
+            fragment_shader,
+            PipelineState::OVERLAY,
+            &[],
+            &[],
+            &[],
+            &push_constant_ranges,
+        )
+        .map_err(|e| format!("create fade pipeline and layout: {:?}", e))?;
+        pipeline.set_name(device, "fade transition");
+        Ok(pipeline)
+    }
+
+    unsafe fn create_crossfade_pipeline(
+        device: &Device,
+        renderpass: &RenderPass,
+        vertex_shader: &Shader,
+        fragment_shader: &Shader,
+        descriptor_set_layouts: &[DescriptorSetLayout],
+    ) -> Result<Pipeline> {
+        let push_constant_ranges = [vk::PushConstantRange {
+            stage_flags: vk::ShaderStageFlags::FRAGMENT,
+            offset: 0,
+            size: mem::size_of::<f32>() as u32,
+        }];
+        let pipeline = Pipeline::new_with_state_and_push_constants(
+            device,
+            renderpass,
+            vertex_shader,
+            fragment_shader,
+            PipelineState::OVERLAY,
+            &[],
+            &[],
+            descriptor_set_layouts,
+            &push_constant_ranges,
+        )
+        .map_err(|e| format!("create crossfade pipeline and layout: {:?}", e))?;
+        pipeline.set_name(device, "crossfade transition");
+        Ok(pipeline)
+    }
+
+    /// Rebuilds the pipelines using the shader. Returns true if any does.
+    #[cfg(feature = "shader-hot-reload")]
+    pub unsafe fn reload_shader(
+        &mut self,
+        device: &Device,
+        renderpass: &RenderPass,
+        shader: &crate::hot_reload::ReloadedShader,
+    ) -> Result<bool> {
+        let module = match shader.name.as_str() {
+            "composite.vert" => &mut self.vertex_shader,
+            "fade.frag" => &mut self.fade_shader,
+            "crossfade.frag" => &mut self.crossfade_shader,
+            _ => return Ok(false),
+        };
+        *module = Shader::new(device, &mut Cursor::new(&shader.spv))
+            .map_err(|e| format!("create shader module: {:?}", e))?;
+        // NOTE: the previous pipelines are destroyed once the frames using them
+        //       have completed
+        self.fade_pipeline =
+            Self::create_fade_pipeline(device, renderpass, &self.vertex_shader, &self.fade_shader)?;
+        self.crossfade_pipeline = Self::create_crossfade_pipeline(
+            device,
+            renderpass,
+            &self.vertex_shader,
+            &self.crossfade_shader,
+            &self.descriptor_set_layouts,
+        )?;
+        Ok(true)
+    }
+
+    /// Covers the viewport with a color, its alpha scaled by `amount`, from 0
+    /// to 1.
+    pub unsafe fn fade(
+        &self,
+        device: &Device,
+        command_buffer: vk::CommandBuffer,
+        color: Vector4<f32>,
+        amount: f32,
+    ) {
+        let constants = FadeConstants {
+            color: color.into(),
+            direction: [0.0; 2],
+            amount,
+        };
+        self.draw_fade(device, command_buffer, &constants);
+    }
+
+    /// Covers the fraction `amount` of the viewport with a color, from the
+    /// edge opposite to the direction.
+    pub unsafe fn wipe(
+        &self,
+        device: &Device,
+        command_buffer: vk::CommandBuffer,
+        color: Vector4<f32>,
+        direction: WipeDirection,
+        amount: f32,
+    ) {
+        let constants = FadeConstants {
+            color: color.into(),
+            direction: direction.uv(),
+            amount,
+        };
+        self.draw_fade(device, command_buffer, &constants);
+    }
+
+    unsafe fn draw_fade(
+        &self,
+        device: &Device,
+        command_buffer: vk::CommandBuffer,
+        constants: &FadeConstants,
+    ) {
+        device.cmd_bind_pipeline(
+            command_buffer,
+            vk::PipelineBindPoint::GRAPHICS,
+            *self.fade_pipeline,
+        );
+        // Safety: FadeConstants is #[repr(C)] over f32s, without padding.
+        let bytes = slice::from_raw_parts(
+            constants as *const _ as *const u8,
+            mem::size_of::<FadeConstants>(),
+        );
+        device.cmd_push_constants(
+            command_buffer,
+            self.fade_pipeline.layout,
+            vk::ShaderStageFlags::FRAGMENT,
+            0,
+            bytes,
+        );
+        device.cmd_draw(command_buffer, 3, 1, 0, 0);
+    }
+
+    /// Draws the target stretched over the viewport, blended using
+    /// `opacity`, from 0 to 1. The target must have been rendered to in this
+    /// frame or an earlier one.
+    pub unsafe fn crossfade(
+        &mut self,
+        device: &Device,
+        command_buffer: vk::CommandBuffer,
+        target: &RenderTarget,
+        opacity: f32,
+    ) -> Result<()> {
+        // use the descriptor set of the next frame in flight
+        // NOTE: it was last used frames_in_flight frames ago, its fence has been
+        //       waited on by the renderer.
+        self.frame_index = (self.frame_index + 1) % self.descriptor_sets.len();
+        let ds = self.descriptor_sets[self.frame_index];
+        ds.update_image(device, 0, *target.image_view(), **target.sampler())
+            .map_err(|e| format!("update descriptor set: {:?}", e))?;
+
+        device.cmd_bind_descriptor_sets(
+            command_buffer,
+            vk::PipelineBindPoint::GRAPHICS,
+            self.crossfade_pipeline.layout,
+            0,
+            &[*ds],
+            &[],
+        );
+        device.cmd_bind_pipeline(
+            command_buffer,
+            vk::PipelineBindPoint::GRAPHICS,
+            *self.crossfade_pipeline,
+        );
+        device.cmd_push_constants(
+            command_buffer,
+            self.crossfade_pipeline.layout,
+            vk::ShaderStageFlags::FRAGMENT,
+            0,
+            &opacity.to_ne_bytes(),
+        );
+        device.cmd_draw(command_buffer, 3, 1, 0, 0);
+
+        Ok(())
+    }
+}
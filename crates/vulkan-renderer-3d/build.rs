@@ -0,0 +1,8 @@
+use std::error::Error;
+
+const SHADERS_SRC: &str = "shaders";
+
+// compile GLSL shaders located in SHADERS_SRC to SPIR-V in OUT_DIR, see include_shader!
+fn main() -> Result<(), Box<dyn Error>> {
+    shader_compiler::compile_dir(SHADERS_SRC)
+}
@@ -0,0 +1,26 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+#extension GL_ARB_shading_language_420pack : enable
+
+// uniforms
+layout (binding = 0) uniform UBO {
+    mat4 vp;
+    // direction the light travels in, ambient intensity in w
+    vec4 light;
+} ubo;
+
+// inputs
+layout (location = 0) in vec4 color;
+layout (location = 1) in vec3 normal;
+layout (location = 2) in vec2 uv;
+
+// outputs
+layout (location = 0) out vec4 uFragColor;
+
+// lambertian diffuse lighting from a directional light, on top of an ambient
+// term
+void main() {
+    float diffuse = max(dot(normalize(normal), -ubo.light.xyz), 0.0);
+    float ambient = ubo.light.w;
+    uFragColor = vec4(color.rgb * (ambient + (1.0 - ambient) * diffuse), color.a);
+}
@@ -0,0 +1,39 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+#extension GL_ARB_shading_language_420pack : enable
+
+// uniforms
+layout (binding = 0) uniform UBO {
+    mat4 vp;
+    // direction the light travels in, ambient intensity in w
+    vec4 light;
+} ubo;
+
+// objects, one per instance, see ObjectData
+struct Object {
+    mat4 model;
+    mat4 normal;
+    vec4 color;
+};
+
+layout (std430, binding = 1) readonly buffer Objects {
+    Object objects[];
+};
+
+// inputs
+layout (location = 0) in vec3 vPos;
+layout (location = 1) in vec3 vNormal;
+layout (location = 2) in vec2 vUV;
+
+// outputs
+layout (location = 0) out vec4 color;
+layout (location = 1) out vec3 normal;
+layout (location = 2) out vec2 uv;
+
+void main() {
+    Object object = objects[gl_InstanceIndex];
+    color = object.color;
+    normal = mat3(object.normal) * vNormal;
+    uv = vUV;
+    gl_Position = ubo.vp * object.model * vec4(vPos, 1.0);
+}
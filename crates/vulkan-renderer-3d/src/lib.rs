@@ -0,0 +1,605 @@
+#![allow(clippy::missing_safety_doc)]
+
+//! Draws the objects that have a mesh, lit by a directional light.
+//!
+//! Objects are written to a storage buffer, one after the other grouped by
+//! mesh, so that the objects sharing a mesh are drawn by a single instanced
+//! draw.
+
+use core::component::Transform;
+use core::handle::HandleMap;
+use core::mesh::{Mesh, MeshId};
+use core::object::MeshView;
+use std::collections::{hash_map::Entry, HashMap};
+use std::{error, result};
+use std::{io::Cursor, mem};
+
+use ash::vk;
+use cgmath::{InnerSpace, Matrix, Matrix4, Rad, SquareMatrix, Vector2, Vector3, Vector4};
+use vulkan_renderer::buffer::Buffer;
+use vulkan_renderer::descriptor::{DescriptorPool, DescriptorSet, DescriptorSetLayout};
+use vulkan_renderer::device::Device;
+use vulkan_renderer::pipeline::Pipeline;
+use vulkan_renderer::renderpass::RenderPass;
+use vulkan_renderer::shader::Shader;
+use vulkan_renderer::staging::StagingRing;
+use vulkan_renderer::{include_shader, offset_of};
+
+type Result<T> = result::Result<T, Box<dyn error::Error>>;
+
+/// Objects the buffers are first created for.
+const INITIAL_CAPACITY: u32 = 256;
+
+#[derive(Clone, Debug)]
+struct VertexInputDescription {
+    bindings: Vec<vk::VertexInputBindingDescription>,
+    attributes: Vec<vk::VertexInputAttributeDescription>,
+}
+
+#[repr(C)]
+#[derive(Clone, Debug, Copy)]
+struct Vertex {
+    pos: Vector3<f32>,
+    normal: Vector3<f32>,
+    uv: Vector2<f32>,
+}
+
+impl Vertex {
+    fn input_description() -> VertexInputDescription {
+        let bindings = vec![vk::VertexInputBindingDescription {
+            binding: 0,
+            stride: mem::size_of::<Self>() as u32,
+            input_rate: vk::VertexInputRate::VERTEX,
+        }];
+        let attributes = vec![
+            vk::VertexInputAttributeDescription {
+                location: 0,
+                binding: 0,
+                format: vk::Format::R32G32B32_SFLOAT,
+                offset: offset_of!(Self, pos) as u32,
+            },
+            vk::VertexInputAttributeDescription {
+                location: 1,
+                binding: 0,
+                format: vk::Format::R32G32B32_SFLOAT,
+                offset: offset_of!(Self, normal) as u32,
+            },
+            vk::VertexInputAttributeDescription {
+                location: 2,
+                binding: 0,
+                format: vk::Format::R32G32_SFLOAT,
+                offset: offset_of!(Self, uv) as u32,
+            },
+        ];
+
+        VertexInputDescription {
+            bindings,
+            attributes,
+        }
+    }
+}
+
+/// Light shading the meshes, see `Renderer3DSystem::set_light()`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DirectionalLight {
+    /// Direction the light travels in.
+    pub direction: Vector3<f32>,
+    /// Fraction of the color of the meshes lit whatever the direction of
+    /// their surface, in [0, 1].
+    pub ambient: f32,
+}
+
+impl Default for DirectionalLight {
+    fn default() -> Self {
+        Self {
+            direction: Vector3::new(-1.0, -1.0, -1.0),
+            ambient: 0.2,
+        }
+    }
+}
+
+/// See mesh.vert.
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+struct UniformBuffer {
+    vp: Matrix4<f32>,
+    /// Normalized direction of the light, ambient intensity in w.
+    light: Vector4<f32>,
+}
+
+impl UniformBuffer {
+    fn new(vp: Matrix4<f32>, light: DirectionalLight) -> Self {
+        let direction = light.direction.normalize();
+        Self {
+            vp,
+            light: direction.extend(light.ambient.clamp(0.0, 1.0)),
+        }
+    }
+}
+
+/// Object drawn as an instance of its mesh, see mesh.vert.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct ObjectData {
+    model: Matrix4<f32>,
+    /// Transforms the normals of the mesh, keeping them perpendicular to
+    /// its surface when it is scaled unevenly.
+    normal: Matrix4<f32>,
+    color: Vector4<f32>,
+}
+
+impl ObjectData {
+    fn new(object: MeshView) -> Self {
+        let model = model_matrix(object.transform);
+        let normal = model
+            .invert()
+            .map_or_else(Matrix4::identity, |inverse| inverse.transpose());
+        Self {
+            model,
+            normal,
+            color: object.color.color,
+        }
+    }
+}
+
+/// Returns the matrix placing a mesh: scaled, then rotated around the X, Y
+/// and Z axes in that order, then translated.
+///
+/// NOTE: unlike quads, meshes are scaled before being translated, so that
+///       their position is in world units.
+fn model_matrix(transform: &Transform) -> Matrix4<f32> {
+    let Transform {
+        position,
+        rotation,
+        scale,
+    } = *transform;
+    Matrix4::from_translation(position)
+        * Matrix4::from_angle_z(Rad(rotation.z))
+        * Matrix4::from_angle_y(Rad(rotation.y))
+        * Matrix4::from_angle_x(Rad(rotation.x))
+        * Matrix4::from_nonuniform_scale(scale.x, scale.y, scale.z)
+}
+
+/// Returns the runs of consecutive objects sharing a mesh, as the mesh, the
+/// first object and the number of objects.
+fn instance_runs(meshes: &[MeshId]) -> impl Iterator<Item = (MeshId, u32, u32)> + '_ {
+    let mut first = 0;
+    std::iter::from_fn(move || {
+        let mesh = *meshes.get(first)?;
+        let count = meshes[first..].iter().take_while(|m| **m == mesh).count();
+        let run = (mesh, first as u32, count as u32);
+        first += count;
+        Some(run)
+    })
+}
+
+/// Work done by the 3D renderer during the last frame.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct RenderStats {
+    pub draw_calls: u32,
+    pub objects: u32,
+    pub triangles: u32,
+}
+
+/// Vertices and indices of a mesh, uploaded the first time it is drawn.
+struct GpuMesh {
+    vertex_buffer: Buffer,
+    index_buffer: Buffer,
+    index_count: u32,
+}
+
+impl GpuMesh {
+    unsafe fn upload(device: &Device, staging: &mut StagingRing, mesh: &Mesh) -> Result<Self> {
+        mesh.validate()?;
+        let vertices = mesh
+            .positions
+            .iter()
+            .zip(&mesh.normals)
+            .zip(&mesh.uvs)
+            .map(|((pos, normal), uv)| Vertex {
+                pos: *pos,
+                normal: *normal,
+                uv: *uv,
+            })
+            .collect::<Vec<_>>();
+
+        let vertex_buffer = Buffer::new(
+            device,
+            vk::BufferUsageFlags::VERTEX_BUFFER | vk::BufferUsageFlags::TRANSFER_DST,
+            vk::MemoryPropertyFlags::DEVICE_LOCAL,
+            mem::size_of_val(vertices.as_slice()) as u64,
+        )
+        .map_err(|e| format!("create vertex buffer: {:?}", e))?;
+        device.set_object_name(*vertex_buffer, "renderer 3D vertex buffer");
+        staging
+            .copy_to_buffer(&vertices, *vertex_buffer, 0)
+            .map_err(|e| format!("update vertex buffer: {:?}", e))?;
+
+        let index_buffer = Buffer::new(
+            device,
+            vk::BufferUsageFlags::INDEX_BUFFER | vk::BufferUsageFlags::TRANSFER_DST,
+            vk::MemoryPropertyFlags::DEVICE_LOCAL,
+            mem::size_of_val(mesh.indices.as_slice()) as u64,
+        )
+        .map_err(|e| format!("create index buffer: {:?}", e))?;
+        device.set_object_name(*index_buffer, "renderer 3D index buffer");
+        staging
+            .copy_to_buffer(&mesh.indices, *index_buffer, 0)
+            .map_err(|e| format!("update index buffer: {:?}", e))?;
+
+        Ok(Self {
+            vertex_buffer,
+            index_buffer,
+            index_count: mesh.indices.len() as u32,
+        })
+    }
+}
+
+/// Buffers written during a frame in flight.
+struct FrameResources {
+    uniform_buffer: Buffer,
+    object_buffer: Buffer,
+    /// Number of objects the object buffer can hold.
+    capacity: u32,
+    descriptor_set: DescriptorSet,
+}
+
+impl FrameResources {
+    unsafe fn new(
+        device: &Device,
+        descriptor_pool: &DescriptorPool,
+        descriptor_set_layouts: &[DescriptorSetLayout],
+    ) -> Result<Self> {
+        let uniform_buffer = Buffer::new(
+            device,
+            vk::BufferUsageFlags::UNIFORM_BUFFER,
+            vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT,
+            mem::size_of::<UniformBuffer>() as u64,
+        )
+        .map_err(|e| format!("create uniform buffer: {:?}", e))?;
+        device.set_object_name(*uniform_buffer, "renderer 3D uniform buffer");
+
+        let descriptor_set = DescriptorSet::new(device, descriptor_pool, descriptor_set_layouts)
+            .map_err(|e| format!("create descriptor set: {:?}", e))?[0];
+        descriptor_set
+            .update_ubo(
+                device,
+                &uniform_buffer,
+                0,
+                mem::size_of::<UniformBuffer>() as u64,
+            )
+            .map_err(|e| format!("update descriptor set: {:?}", e))?;
+
+        let object_buffer = create_object_buffer(device, INITIAL_CAPACITY)?;
+        descriptor_set
+            .update_storage_buffer(device, 1, &object_buffer)
+            .map_err(|e| format!("update descriptor set: {:?}", e))?;
+
+        Ok(Self {
+            uniform_buffer,
+            object_buffer,
+            capacity: INITIAL_CAPACITY,
+            descriptor_set,
+        })
+    }
+
+    /// Grows the object buffer to hold at least `count` objects.
+    unsafe fn reserve(&mut self, device: &Device, count: u32) -> Result<()> {
+        if count <= self.capacity {
+            return Ok(());
+        }
+        let capacity = count.next_power_of_two();
+        // NOTE: the previous buffer is destroyed once the frames using it
+        //       have completed
+        self.object_buffer = create_object_buffer(device, capacity)?;
+        self.capacity = capacity;
+        self.descriptor_set
+            .update_storage_buffer(device, 1, &self.object_buffer)
+            .map_err(|e| format!("update descriptor set: {:?}", e))?;
+        Ok(())
+    }
+}
+
+unsafe fn create_object_buffer(device: &Device, capacity: u32) -> Result<Buffer> {
+    let object_buffer = Buffer::new(
+        device,
+        vk::BufferUsageFlags::STORAGE_BUFFER,
+        vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT,
+        capacity as u64 * mem::size_of::<ObjectData>() as u64,
+    )
+    .map_err(|e| format!("create object buffer: {:?}", e))?;
+    device.set_object_name(*object_buffer, "renderer 3D object buffer");
+    Ok(object_buffer)
+}
+
+pub struct Renderer3DSystem {
+    /// The vertex and fragment shaders, along with the descriptor pool and set
+    /// layouts below.
+    /// NOTE: only kept alive until the renderer is dropped.
+    _vertex_shader: Shader,
+    _fragment_shader: Shader,
+
+    // The descriptor pool used to allocate descriptor sets.
+    _descriptor_pool: DescriptorPool,
+
+    // The descriptor set layout used to allocate descriptor sets.
+    _descriptor_set_layouts: Vec<DescriptorSetLayout>,
+
+    /// Buffers and their descriptor set, one per frame in flight.
+    frames: Vec<FrameResources>,
+    frame_index: usize,
+
+    // Graphics pipeline.
+    pipeline: Pipeline,
+
+    /// Meshes uploaded so far, released once removed from the engine.
+    meshes: HashMap<MeshId, GpuMesh>,
+
+    /// Objects of the current frame, sorted by mesh.
+    objects: Vec<(MeshId, ObjectData)>,
+    object_meshes: Vec<MeshId>,
+    object_data: Vec<ObjectData>,
+
+    light: DirectionalLight,
+
+    stats: RenderStats,
+}
+
+impl Renderer3DSystem {
+    pub unsafe fn new(
+        device: &Device,
+        renderpass: &RenderPass,
+        frames_in_flight: u32,
+    ) -> Result<Self> {
+        // create shaders
+        let mut vertex_spv_file = Cursor::new(&include_shader!("mesh.vert")[..]);
+        let mut frag_spv_file = Cursor::new(&include_shader!("mesh.frag")[..]);
+
+        let vertex_shader = Shader::new(device, &mut vertex_spv_file)
+            .map_err(|e| format!("create vertex shader module: {:?}", e))?;
+
+        let fragment_shader = Shader::new(device, &mut frag_spv_file)
+            .map_err(|e| format!("create fragment shader module: {:?}", e))?;
+
+        // create descriptor pool
+        let descriptor_pool_sizes = [
+            vk::DescriptorPoolSize {
+                ty: vk::DescriptorType::UNIFORM_BUFFER,
+                descriptor_count: frames_in_flight,
+            },
+            vk::DescriptorPoolSize {
+                ty: vk::DescriptorType::STORAGE_BUFFER,
+                descriptor_count: frames_in_flight,
+            },
+        ];
+        let descriptor_pool = DescriptorPool::new(device, &descriptor_pool_sizes, frames_in_flight)
+            .map_err(|e| format!("create descriptor pool: {:?}", e))?;
+
+        // create descriptor set layouts
+        let descriptor_set_layouts = {
+            let ds_layout_bindings = [
+                // view projection and light
+                vk::DescriptorSetLayoutBinding {
+                    binding: 0,
+                    descriptor_type: vk::DescriptorType::UNIFORM_BUFFER,
+                    descriptor_count: 1,
+                    stage_flags: vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT,
+                    ..Default::default()
+                },
+                // objects
+                vk::DescriptorSetLayoutBinding {
+                    binding: 1,
+                    descriptor_type: vk::DescriptorType::STORAGE_BUFFER,
+                    descriptor_count: 1,
+                    stage_flags: vk::ShaderStageFlags::VERTEX,
+                    ..Default::default()
+                },
+            ];
+            let ds_layout = DescriptorSetLayout::new(device, &ds_layout_bindings)
+                .map_err(|e| format!("create descriptor set layout: {:?}", e))?;
+            vec![ds_layout]
+        };
+
+        // create the buffers of each frame in flight
+        // NOTE: the buffers are written every frame, so each frame in flight
+        //       needs its own
+        let mut frames = Vec::with_capacity(frames_in_flight as usize);
+        for _ in 0..frames_in_flight {
+            frames.push(FrameResources::new(
+                device,
+                &descriptor_pool,
+                &descriptor_set_layouts,
+            )?);
+        }
+
+        // create graphics pipeline
+        let vertex_input_description = Vertex::input_description();
+        let pipeline = Pipeline::new(
+            device,
+            renderpass,
+            &vertex_shader,
+            &fragment_shader,
+            &vertex_input_description.bindings,
+            &vertex_input_description.attributes,
+            &descriptor_set_layouts,
+        )
+        .map_err(|e| format!("create pipeline and layout: {:?}", e))?;
+        pipeline.set_name(device, "renderer 3D");
+
+        Ok(Self {
+            _vertex_shader: vertex_shader,
+            _fragment_shader: fragment_shader,
+            _descriptor_pool: descriptor_pool,
+            _descriptor_set_layouts: descriptor_set_layouts,
+            frames,
+            frame_index: 0,
+            pipeline,
+            meshes: HashMap::new(),
+            objects: Vec::new(),
+            object_meshes: Vec::new(),
+            object_data: Vec::new(),
+            light: DirectionalLight::default(),
+            stats: RenderStats::default(),
+        })
+    }
+
+    pub fn light(&self) -> DirectionalLight {
+        self.light
+    }
+
+    pub fn set_light(&mut self, light: DirectionalLight) {
+        self.light = light;
+    }
+
+    pub fn stats(&self) -> RenderStats {
+        self.stats
+    }
+
+    /// Draws the objects that have a mesh among `meshes`, ignoring the
+    /// others. Meshes are uploaded the first time they are drawn, and
+    /// released once removed from `meshes`: a mesh is changed by replacing
+    /// it with a new one.
+    pub unsafe fn render<'a, I>(
+        &mut self,
+        device: &Device,
+        command_buffer: vk::CommandBuffer,
+        staging: &mut StagingRing,
+        view_projection: Matrix4<f32>,
+        meshes: &HandleMap<Mesh>,
+        objects: I,
+    ) -> Result<()>
+    where
+        I: IntoIterator<Item = MeshView<'a>>,
+    {
+        // use the buffers of the next frame in flight
+        // NOTE: they were last used frames_in_flight frames ago, their fence
+        //       has been waited on by the renderer.
+        self.frame_index = (self.frame_index + 1) % self.frames.len();
+
+        // NOTE: the buffers of released meshes are destroyed once the frames
+        //       using them have completed
+        self.meshes.retain(|id, _| meshes.contains(*id));
+
+        // gather the objects, uploading the meshes drawn for the first time
+        self.objects.clear();
+        for object in objects {
+            let id = *object.mesh;
+            let Some(mesh) = meshes.get(id) else {
+                continue;
+            };
+            if let Entry::Vacant(entry) = self.meshes.entry(id) {
+                let gpu_mesh = GpuMesh::upload(device, staging, mesh)
+                    .map_err(|e| format!("upload mesh {id:?}: {e}"))?;
+                entry.insert(gpu_mesh);
+            }
+            self.objects.push((id, ObjectData::new(object)));
+        }
+
+        // group the objects by mesh
+        self.objects.sort_unstable_by_key(|(id, _)| id.to_bits());
+        self.object_meshes.clear();
+        self.object_meshes
+            .extend(self.objects.iter().map(|(id, _)| *id));
+        self.object_data.clear();
+        self.object_data
+            .extend(self.objects.iter().map(|(_, data)| *data));
+
+        // update buffers
+        let frame = &mut self.frames[self.frame_index];
+        frame.reserve(device, self.object_data.len() as u32)?;
+        frame
+            .object_buffer
+            .update(&self.object_data)
+            .map_err(|e| format!("update object buffer: {:?}", e))?;
+        frame
+            .uniform_buffer
+            .update(&[UniformBuffer::new(view_projection, self.light)])
+            .map_err(|e| format!("update uniform buffer: {:?}", e))?;
+
+        // draw each mesh, instanced once per object
+        self.stats = RenderStats::default();
+        if self.object_data.is_empty() {
+            return Ok(());
+        }
+        device.cmd_bind_descriptor_sets(
+            command_buffer,
+            vk::PipelineBindPoint::GRAPHICS,
+            self.pipeline.layout,
+            0,
+            &[*frame.descriptor_set],
+            &[],
+        );
+        device.cmd_bind_pipeline(
+            command_buffer,
+            vk::PipelineBindPoint::GRAPHICS,
+            *self.pipeline,
+        );
+        for (id, first_object, object_count) in instance_runs(&self.object_meshes) {
+            let mesh = &self.meshes[&id];
+            device.cmd_bind_vertex_buffers(command_buffer, 0, &[*mesh.vertex_buffer], &[0]);
+            device.cmd_bind_index_buffer(
+                command_buffer,
+                *mesh.index_buffer,
+                0,
+                vk::IndexType::UINT32,
+            );
+            device.cmd_draw_indexed(
+                command_buffer,
+                mesh.index_count,
+                object_count,
+                0,
+                0,
+                first_object,
+            );
+            self.stats.draw_calls += 1;
+            self.stats.objects += object_count;
+            self.stats.triangles += mesh.index_count / 3 * object_count;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f32::consts::FRAC_PI_2;
+
+    use cgmath::assert_relative_eq;
+
+    use super::*;
+
+    #[test]
+    fn meshes_are_scaled_rotated_then_translated() {
+        let transform = Transform {
+            position: Vector3::new(10.0, 0.0, 0.0),
+            rotation: Vector3::new(0.0, 0.0, FRAC_PI_2),
+            scale: Vector3::new(2.0, 1.0, 1.0),
+        };
+        let model = model_matrix(&transform);
+        assert_relative_eq!(
+            model * Vector4::new(1.0, 0.0, 0.0, 1.0),
+            Vector4::new(10.0, 2.0, 0.0, 1.0)
+        );
+
+        // normals stay perpendicular to the stretched surface
+        let object = MeshView {
+            transform: &transform,
+            color: &Default::default(),
+            mesh: &MeshId::from_bits(0),
+        };
+        let normal = ObjectData::new(object).normal * Vector4::new(1.0, 1.0, 0.0, 0.0);
+        let tangent = model * Vector4::new(1.0, -1.0, 0.0, 0.0);
+        assert_relative_eq!(normal.dot(tangent), 0.0);
+    }
+
+    #[test]
+    fn objects_sharing_a_mesh_are_drawn_together() {
+        let mut meshes = HandleMap::new();
+        let cube = meshes.insert(Mesh::cube());
+        let other = meshes.insert(Mesh::cube());
+
+        let runs = instance_runs(&[cube, cube, other, cube]).collect::<Vec<_>>();
+        assert_eq!(runs, [(cube, 0, 2), (other, 2, 1), (cube, 3, 1)]);
+        assert_eq!(instance_runs(&[]).count(), 0);
+    }
+}
@@ -0,0 +1,424 @@
+use std::os::raw::c_void;
+
+use ash::vk;
+use log::debug;
+
+use super::find_memorytype_index;
+use crate::error::ResultExt;
+use crate::Result;
+
+/// Size of the memory blocks allocated from the device. Allocations that do
+/// not fit in a block are given a dedicated block of their own.
+const DEFAULT_BLOCK_SIZE: u64 = 64 * 1024 * 1024;
+
+/// A region of device memory suballocated from a `MemoryBlock`.
+#[derive(Clone, Copy, Debug)]
+pub struct Allocation {
+    memory: vk::DeviceMemory,
+    offset: u64,
+    size: u64,
+
+    /// Pointer to the start of this allocation if the memory is host visible.
+    /// Host visible blocks are persistently mapped.
+    mapped_ptr: Option<*mut c_void>,
+
+    block_index: usize,
+}
+
+impl Allocation {
+    /// The device memory object this allocation belongs to.
+    pub fn memory(&self) -> vk::DeviceMemory {
+        self.memory
+    }
+
+    /// Offset of this allocation within its device memory object.
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    pub fn mapped_ptr(&self) -> Option<*mut c_void> {
+        self.mapped_ptr
+    }
+}
+
+/// Device memory usage of an `Allocator`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct MemoryStats {
+    pub block_count: usize,
+    /// Memory allocated from the device, in bytes.
+    pub reserved: u64,
+    /// Memory handed out to buffers and images, in bytes.
+    pub used: u64,
+    /// Usage and budget of each memory heap of the device.
+    pub heaps: Vec<HeapStats>,
+}
+
+/// Memory usage of a device memory heap.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct HeapStats {
+    /// Size of the heap, in bytes.
+    pub size: u64,
+    /// Set for the heaps of video memory.
+    pub device_local: bool,
+    /// Memory allocated from the heap by the allocator, in bytes.
+    pub reserved: u64,
+    /// Memory allocated from the heap by all the processes, in bytes. Without
+    /// VK_EXT_memory_budget, this is the memory reserved by the allocator.
+    pub usage: u64,
+    /// Memory that can be allocated from the heap before allocations fail or
+    /// degrade performance, in bytes. Without VK_EXT_memory_budget, this is the
+    /// size of the heap.
+    pub budget: u64,
+}
+
+impl HeapStats {
+    /// Returns the fraction of the budget in use, above 1 when over budget.
+    pub fn budget_ratio(&self) -> f64 {
+        if self.budget == 0 {
+            return 0.0;
+        }
+        self.usage as f64 / self.budget as f64
+    }
+}
+
+/// Tracks the free ranges of a memory block using a sorted list of
+/// (offset, size) pairs.
+#[derive(Debug)]
+struct FreeList {
+    ranges: Vec<(u64, u64)>,
+}
+
+impl FreeList {
+    fn new(size: u64) -> Self {
+        Self {
+            ranges: vec![(0, size)],
+        }
+    }
+
+    /// First-fit allocation. Returns the aligned offset of the allocated range.
+    fn allocate(&mut self, size: u64, alignment: u64) -> Option<u64> {
+        let (idx, aligned_offset) =
+            self.ranges
+                .iter()
+                .enumerate()
+                .find_map(|(idx, &(offset, range_size))| {
+                    let aligned_offset = align_up(offset, alignment);
+                    let padding = aligned_offset - offset;
+                    (range_size >= size + padding).then_some((idx, aligned_offset))
+                })?;
+
+        let (offset, range_size) = self.ranges.remove(idx);
+        let end = offset + range_size;
+        let allocated_end = aligned_offset + size;
+        // keep the leftovers on both sides of the allocation
+        if allocated_end < end {
+            self.ranges
+                .insert(idx, (allocated_end, end - allocated_end));
+        }
+        if offset < aligned_offset {
+            self.ranges.insert(idx, (offset, aligned_offset - offset));
+        }
+
+        Some(aligned_offset)
+    }
+
+    /// Returns a range to the free list, merging it with its neighbours.
+    fn free(&mut self, offset: u64, size: u64) {
+        let idx = self.ranges.partition_point(|&(o, _)| o < offset);
+        self.ranges.insert(idx, (offset, size));
+
+        // merge with next
+        if idx + 1 < self.ranges.len() {
+            let (next_offset, next_size) = self.ranges[idx + 1];
+            if offset + size == next_offset {
+                self.ranges[idx].1 += next_size;
+                self.ranges.remove(idx + 1);
+            }
+        }
+        // merge with previous
+        if idx > 0 {
+            let (prev_offset, prev_size) = self.ranges[idx - 1];
+            if prev_offset + prev_size == offset {
+                self.ranges[idx - 1].1 += self.ranges[idx].1;
+                self.ranges.remove(idx);
+            }
+        }
+    }
+
+    fn is_empty(&self, block_size: u64) -> bool {
+        self.ranges.len() == 1 && self.ranges[0] == (0, block_size)
+    }
+}
+
+fn align_up(value: u64, alignment: u64) -> u64 {
+    if alignment == 0 {
+        return value;
+    }
+    (value + alignment - 1) / alignment * alignment
+}
+
+#[derive(Debug)]
+struct MemoryBlock {
+    memory: vk::DeviceMemory,
+    memory_type_index: u32,
+    size: u64,
+    mapped_ptr: Option<*mut c_void>,
+    free_list: FreeList,
+}
+
+/// Suballocates buffer and image memory from large device memory blocks, so
+/// that we do not hit the maxMemoryAllocationCount limit and do not fragment
+/// device memory with many small allocations.
+pub struct Allocator {
+    memory_properties: vk::PhysicalDeviceMemoryProperties,
+
+    /// Linear (buffers) and optimal (images) resources sharing a block must be
+    /// separated by this granularity. To keep things simple, every allocation
+    /// is aligned to it.
+    buffer_image_granularity: u64,
+
+    block_size: u64,
+    blocks: Vec<Option<MemoryBlock>>,
+    used: u64,
+}
+
+impl Allocator {
+    pub fn new(
+        memory_properties: vk::PhysicalDeviceMemoryProperties,
+        buffer_image_granularity: u64,
+    ) -> Self {
+        Self {
+            memory_properties,
+            buffer_image_granularity,
+            block_size: DEFAULT_BLOCK_SIZE,
+            blocks: Vec::new(),
+            used: 0,
+        }
+    }
+
+    pub unsafe fn allocate(
+        &mut self,
+        device: &ash::Device,
+        requirements: vk::MemoryRequirements,
+        properties: vk::MemoryPropertyFlags,
+    ) -> Result<Allocation> {
+        let memory_type_index =
+            find_memorytype_index(&requirements, &self.memory_properties, properties)
+                .ok_or("unable to find suitable memorytype for the allocation")?;
+        let alignment = requirements.alignment.max(self.buffer_image_granularity);
+
+        // look for room in an existing block
+        for (block_index, block) in self.blocks.iter_mut().enumerate() {
+            let Some(block) = block else { continue };
+            if block.memory_type_index != memory_type_index {
+                continue;
+            }
+            if let Some(offset) = block.free_list.allocate(requirements.size, alignment) {
+                self.used += requirements.size;
+                return Ok(block.allocation(block_index, offset, requirements.size));
+            }
+        }
+
+        // allocate a new block, or a dedicated one if the allocation is too big
+        let block_size = self.block_size.max(requirements.size);
+        let memory_type_flags =
+            self.memory_properties.memory_types[memory_type_index as usize].property_flags;
+        let mut block = MemoryBlock::new(device, memory_type_index, memory_type_flags, block_size)?;
+        let offset = block
+            .free_list
+            .allocate(requirements.size, alignment)
+            .ok_or("allocate from new memory block")?;
+
+        let block_index = match self.blocks.iter().position(Option::is_none) {
+            Some(idx) => idx,
+            None => {
+                self.blocks.push(None);
+                self.blocks.len() - 1
+            }
+        };
+        let allocation = block.allocation(block_index, offset, requirements.size);
+        self.blocks[block_index] = Some(block);
+        self.used += requirements.size;
+
+        Ok(allocation)
+    }
+
+    pub unsafe fn free(&mut self, device: &ash::Device, allocation: &Allocation) {
+        let block = self.blocks[allocation.block_index]
+            .as_mut()
+            .expect("allocation block exists");
+        block.free_list.free(allocation.offset, allocation.size);
+        self.used -= allocation.size;
+
+        // release blocks that are no longer used
+        if block.free_list.is_empty(block.size) {
+            let block = self.blocks[allocation.block_index]
+                .take()
+                .expect("allocation block exists");
+            block.destroy(device);
+        }
+    }
+
+    pub fn stats(&self) -> MemoryStats {
+        let blocks = self.blocks.iter().flatten();
+        let memory_heaps =
+            &self.memory_properties.memory_heaps[..self.memory_properties.memory_heap_count as _];
+        let mut heaps: Vec<_> = memory_heaps
+            .iter()
+            .map(|heap| HeapStats {
+                size: heap.size,
+                device_local: heap.flags.contains(vk::MemoryHeapFlags::DEVICE_LOCAL),
+                budget: heap.size,
+                ..Default::default()
+            })
+            .collect();
+        for block in blocks.clone() {
+            let heap_index = self.memory_properties.memory_types[block.memory_type_index as usize]
+                .heap_index as usize;
+            heaps[heap_index].reserved += block.size;
+            heaps[heap_index].usage += block.size;
+        }
+
+        MemoryStats {
+            block_count: blocks.clone().count(),
+            reserved: blocks.map(|block| block.size).sum(),
+            used: self.used,
+            heaps,
+        }
+    }
+
+    // Make sure to call device.device_wait_idle() prior to calling destroy.
+    pub unsafe fn destroy(&mut self, device: &ash::Device) {
+        for block in self.blocks.drain(..).flatten() {
+            block.destroy(device);
+        }
+    }
+}
+
+impl MemoryBlock {
+    unsafe fn new(
+        device: &ash::Device,
+        memory_type_index: u32,
+        memory_type_flags: vk::MemoryPropertyFlags,
+        size: u64,
+    ) -> Result<Self> {
+        debug!("Allocating memory block: type={memory_type_index} size={size}");
+
+        let allocate_info = vk::MemoryAllocateInfo {
+            allocation_size: size,
+            memory_type_index,
+            ..Default::default()
+        };
+        let memory = device
+            .allocate_memory(&allocate_info, None)
+            .context("allocate memory block")?;
+
+        // persistently map host visible blocks
+        let mapped_ptr = if memory_type_flags.contains(vk::MemoryPropertyFlags::HOST_VISIBLE) {
+            let ptr = device
+                .map_memory(memory, 0, vk::WHOLE_SIZE, vk::MemoryMapFlags::empty())
+                .context("map memory block")?;
+            Some(ptr)
+        } else {
+            None
+        };
+
+        Ok(Self {
+            memory,
+            memory_type_index,
+            size,
+            mapped_ptr,
+            free_list: FreeList::new(size),
+        })
+    }
+
+    unsafe fn allocation(&self, block_index: usize, offset: u64, size: u64) -> Allocation {
+        Allocation {
+            memory: self.memory,
+            offset,
+            size,
+            mapped_ptr: self.mapped_ptr.map(|ptr| ptr.add(offset as usize)),
+            block_index,
+        }
+    }
+
+    unsafe fn destroy(self, device: &ash::Device) {
+        debug!(
+            "Freeing memory block: type={} size={}",
+            self.memory_type_index, self.size
+        );
+        if self.mapped_ptr.is_some() {
+            device.unmap_memory(self.memory);
+        }
+        device.free_memory(self.memory, None);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn free_list_alignment() {
+        let mut free_list = FreeList::new(1024);
+        assert_eq!(free_list.allocate(10, 1), Some(0));
+        assert_eq!(free_list.allocate(10, 256), Some(256));
+        // the padding in front of the aligned allocation is still available
+        assert_eq!(free_list.allocate(100, 1), Some(10));
+    }
+
+    #[test]
+    fn stats_are_split_by_heap() {
+        let mut memory_properties = vk::PhysicalDeviceMemoryProperties {
+            memory_type_count: 2,
+            memory_heap_count: 2,
+            ..Default::default()
+        };
+        memory_properties.memory_types[1].heap_index = 1;
+        memory_properties.memory_heaps[0] = vk::MemoryHeap {
+            size: 1024,
+            flags: vk::MemoryHeapFlags::DEVICE_LOCAL,
+        };
+        memory_properties.memory_heaps[1].size = 512;
+        let mut allocator = Allocator::new(memory_properties, 1);
+        allocator.blocks.push(Some(MemoryBlock {
+            memory: vk::DeviceMemory::null(),
+            memory_type_index: 1,
+            size: 256,
+            mapped_ptr: None,
+            free_list: FreeList::new(256),
+        }));
+
+        let stats = allocator.stats();
+        assert_eq!(stats.reserved, 256);
+        assert_eq!(stats.heaps.len(), 2);
+        assert!(stats.heaps[0].device_local);
+        assert_eq!(stats.heaps[0].usage, 0);
+        assert_eq!((stats.heaps[1].usage, stats.heaps[1].budget), (256, 512));
+        assert_eq!(stats.heaps[1].budget_ratio(), 0.5);
+    }
+
+    #[test]
+    fn free_list_exhausted() {
+        let mut free_list = FreeList::new(256);
+        assert_eq!(free_list.allocate(256, 1), Some(0));
+        assert_eq!(free_list.allocate(1, 1), None);
+    }
+
+    #[test]
+    fn free_list_coalesce() {
+        let mut free_list = FreeList::new(300);
+        let a = free_list.allocate(100, 1).unwrap();
+        let b = free_list.allocate(100, 1).unwrap();
+        let c = free_list.allocate(100, 1).unwrap();
+        free_list.free(a, 100);
+        free_list.free(c, 100);
+        free_list.free(b, 100);
+        assert!(free_list.is_empty(300));
+        assert_eq!(free_list.allocate(300, 1), Some(0));
+    }
+}
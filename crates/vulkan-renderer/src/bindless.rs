@@ -0,0 +1,224 @@
+use std::collections::VecDeque;
+
+use ash::vk;
+
+use super::descriptor::{DescriptorPool, DescriptorSet, DescriptorSetLayout};
+use super::device::Device;
+use super::texture::Texture;
+use crate::error::ResultExt;
+use crate::Result;
+
+/// Binding of the texture array in its descriptor set.
+pub const BINDLESS_TEXTURE_BINDING: u32 = 0;
+
+/// Index of a texture in the bindless array, as read by the shaders.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct TextureIndex(pub u32);
+
+/// Array of combined image samplers indexed by shaders using
+/// `VK_EXT_descriptor_indexing`, see `assets/shaders/include/bindless.glsl`.
+///
+/// The array is bound once and textures are written to it while it is in use
+/// (update-after-bind), including by frames still executing, which do not
+/// access the written slots (update-unused-while-pending). Slots that were
+/// never written are not accessed (partially bound), so that the array can be
+/// much larger than the number of textures. Textures are not owned: they must
+/// outlive their slot, which is reused `frames_in_flight` frames after being
+/// removed so that no frame still executing reads the new texture.
+pub struct BindlessTextures {
+    /// NOTE: only kept alive until the textures are dropped.
+    _descriptor_pool: DescriptorPool,
+    descriptor_set_layout: DescriptorSetLayout,
+    descriptor_set: DescriptorSet,
+    slots: SlotAllocator,
+}
+
+impl BindlessTextures {
+    /// Creates an array of `capacity` textures, clamped to the limit of the
+    /// device. Fails if the device does not support descriptor indexing.
+    pub unsafe fn new(device: &Device, capacity: u32, frames_in_flight: u32) -> Result<Self> {
+        let limit = device
+            .bindless_texture_limit()
+            .ok_or("bindless textures require descriptor indexing")?;
+        let capacity = capacity.min(limit);
+
+        let pool_sizes = [vk::DescriptorPoolSize {
+            ty: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+            descriptor_count: capacity,
+        }];
+        let descriptor_pool = DescriptorPool::with_flags(
+            device,
+            &pool_sizes,
+            1,
+            vk::DescriptorPoolCreateFlags::UPDATE_AFTER_BIND,
+        )
+        .context("create bindless descriptor pool")?;
+
+        let descriptor_set_layout = create_layout(device, capacity)?;
+
+        let descriptor_set = DescriptorSet::new(
+            device,
+            &descriptor_pool,
+            std::slice::from_ref(&descriptor_set_layout),
+        )
+        .context("allocate bindless descriptor set")?[0];
+
+        Ok(Self {
+            _descriptor_pool: descriptor_pool,
+            descriptor_set_layout,
+            descriptor_set,
+            slots: SlotAllocator::new(capacity, frames_in_flight),
+        })
+    }
+
+    /// Writes a texture to a free slot of the array. The texture can be used
+    /// by the commands recorded from now on, including the ones of a command
+    /// buffer the array is already bound to.
+    pub unsafe fn insert(&mut self, device: &Device, texture: &Texture) -> Result<TextureIndex> {
+        let index = self
+            .slots
+            .allocate()
+            .ok_or("bindless texture array is full")?;
+        let image_info = vk::DescriptorImageInfo {
+            image_layout: vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+            image_view: *texture.image_view(),
+            sampler: **texture.sampler(),
+        };
+        let write_desc_sets = [vk::WriteDescriptorSet {
+            dst_set: *self.descriptor_set,
+            dst_binding: BINDLESS_TEXTURE_BINDING,
+            dst_array_element: index,
+            descriptor_count: 1,
+            descriptor_type: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+            p_image_info: &image_info,
+            ..Default::default()
+        }];
+        self.descriptor_set.update(device, &write_desc_sets)?;
+        Ok(TextureIndex(index))
+    }
+
+    /// Frees the slot of a texture. The texture must be kept alive until the
+    /// frames in flight that may use it completed.
+    pub fn remove(&mut self, index: TextureIndex) {
+        self.slots.free(index.0);
+    }
+
+    /// Makes the slots removed `frames_in_flight` frames ago available again.
+    /// Called once per frame.
+    pub fn next_frame(&mut self) {
+        self.slots.next_frame();
+    }
+
+    /// Number of textures that can be stored in the array.
+    pub fn capacity(&self) -> u32 {
+        self.slots.capacity
+    }
+
+    /// Number of slots written so far: the textures inserted are at indices
+    /// below it, while the slots from it on were never written.
+    pub fn written_slots(&self) -> u32 {
+        self.slots.next
+    }
+
+    /// Layout of the set, to create the layout of the pipelines using it.
+    pub fn layout(&self) -> &DescriptorSetLayout {
+        &self.descriptor_set_layout
+    }
+
+    pub fn descriptor_set(&self) -> &DescriptorSet {
+        &self.descriptor_set
+    }
+
+    /// Creates a layout defined like the one of the set, which pipelines can
+    /// use instead of borrowing `layout()`.
+    pub unsafe fn compatible_layout(&self, device: &Device) -> Result<DescriptorSetLayout> {
+        create_layout(device, self.slots.capacity)
+    }
+}
+
+unsafe fn create_layout(device: &Device, capacity: u32) -> Result<DescriptorSetLayout> {
+    let bindings = [vk::DescriptorSetLayoutBinding {
+        binding: BINDLESS_TEXTURE_BINDING,
+        descriptor_type: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+        descriptor_count: capacity,
+        stage_flags: vk::ShaderStageFlags::FRAGMENT,
+        ..Default::default()
+    }];
+    let binding_flags = [vk::DescriptorBindingFlags::PARTIALLY_BOUND
+        | vk::DescriptorBindingFlags::UPDATE_AFTER_BIND
+        | vk::DescriptorBindingFlags::UPDATE_UNUSED_WHILE_PENDING];
+    DescriptorSetLayout::with_flags(
+        device,
+        &bindings,
+        &binding_flags,
+        vk::DescriptorSetLayoutCreateFlags::UPDATE_AFTER_BIND_POOL,
+    )
+    .context("create bindless descriptor set layout")
+}
+
+/// Allocates the slots of the array, delaying the reuse of freed slots.
+#[derive(Debug)]
+struct SlotAllocator {
+    capacity: u32,
+    /// Slots never allocated start at this index.
+    next: u32,
+    available: Vec<u32>,
+    /// Slots freed during each of the last frames, oldest first.
+    pending: VecDeque<Vec<u32>>,
+}
+
+impl SlotAllocator {
+    fn new(capacity: u32, frames_in_flight: u32) -> Self {
+        Self {
+            capacity,
+            next: 0,
+            available: Vec::new(),
+            pending: (0..frames_in_flight.max(1)).map(|_| Vec::new()).collect(),
+        }
+    }
+
+    fn allocate(&mut self) -> Option<u32> {
+        if let Some(slot) = self.available.pop() {
+            return Some(slot);
+        }
+        (self.next < self.capacity).then(|| {
+            self.next += 1;
+            self.next - 1
+        })
+    }
+
+    fn free(&mut self, slot: u32) {
+        debug_assert!(slot < self.next, "free slot {slot} never allocated");
+        if let Some(freed) = self.pending.back_mut() {
+            freed.push(slot);
+        }
+    }
+
+    fn next_frame(&mut self) {
+        if let Some(mut freed) = self.pending.pop_front() {
+            self.available.append(&mut freed);
+            self.pending.push_back(freed);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn freed_slots_are_reused_after_the_frames_in_flight() {
+        let mut slots = SlotAllocator::new(2, 2);
+        assert_eq!(slots.allocate(), Some(0));
+        assert_eq!(slots.allocate(), Some(1));
+        assert_eq!(slots.allocate(), None);
+
+        slots.free(0);
+        assert_eq!(slots.allocate(), None);
+        slots.next_frame();
+        assert_eq!(slots.allocate(), None);
+        slots.next_frame();
+        assert_eq!(slots.allocate(), Some(0));
+        assert_eq!(slots.allocate(), None);
+    }
+}
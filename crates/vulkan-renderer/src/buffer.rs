@@ -1,24 +1,29 @@
-use std::{mem::align_of, ops::Deref};
+use std::mem::{self, align_of};
+use std::ops::Deref;
+use std::slice;
 
 use ash::{util::Align, vk};
 
-use super::find_memorytype_index;
+use super::allocator::Allocation;
+use super::deletion::{DeletionQueueHandle, Resource};
+use super::device::Device;
+use super::renderer::copy_buffer;
+use crate::error::ResultExt;
 use crate::Result;
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Debug)]
 pub struct Buffer {
     handle: vk::Buffer,
 
-    memory: vk::DeviceMemory,
+    allocation: Allocation,
     memory_requirements: vk::MemoryRequirements,
 
-    destroyed: bool,
+    deletion_queue: DeletionQueueHandle,
 }
 
 impl Buffer {
     pub unsafe fn new(
-        device: &ash::Device,
-        device_memory_properties: &vk::PhysicalDeviceMemoryProperties,
+        device: &Device,
         usage: vk::BufferUsageFlags,
         properties: vk::MemoryPropertyFlags,
         size: u64,
@@ -32,67 +37,121 @@ impl Buffer {
         };
         let buffer = device
             .create_buffer(&buffer_info, None)
-            .map_err(|e| format!("create buffer: {:?}", e))?;
+            .context("create buffer")?;
 
         // allocate memory for the buffer
         let buffer_memory_req = device.get_buffer_memory_requirements(buffer);
-        let buffer_memory_index =
-            find_memorytype_index(&buffer_memory_req, device_memory_properties, properties)
-                .ok_or("unable to find suitable memorytype for the buffer")?;
-        let allocate_info = vk::MemoryAllocateInfo {
-            allocation_size: buffer_memory_req.size,
-            memory_type_index: buffer_memory_index,
-            ..Default::default()
-        };
-        let buffer_memory = device
-            .allocate_memory(&allocate_info, None)
-            .map_err(|e| format!("allocate buffer memory: {:?}", e))?;
+        let allocation = device
+            .allocate_memory(buffer_memory_req, properties)
+            .context("allocate buffer memory")?;
         device
-            .bind_buffer_memory(buffer, buffer_memory, 0)
-            .map_err(|e| format!("bind buffer memory: {:?}", e))?;
+            .bind_buffer_memory(buffer, allocation.memory(), allocation.offset())
+            .context("bind buffer memory")?;
 
         Ok(Self {
             handle: buffer,
-            memory: buffer_memory,
+            allocation,
             memory_requirements: buffer_memory_req,
-            destroyed: false,
+            deletion_queue: device.deletion_queue(),
         })
     }
 
+    /// Creates a DEVICE_LOCAL buffer initialized with the provided data.
+    ///
+    /// The data is first written to a HOST_VISIBLE staging buffer, which is
+    /// then copied into the device local buffer using the provided command
+    /// pool. This is meant for static data: the buffer can not be mapped, so
+    /// it can not be updated using `update()`.
+    pub unsafe fn new_device_local<T: Copy>(
+        device: &Device,
+        command_pool: vk::CommandPool,
+        usage: vk::BufferUsageFlags,
+        data: &[T],
+    ) -> Result<Self> {
+        let size = mem::size_of_val(data) as u64;
+
+        let staging_buffer = {
+            let mut staging_buffer = Self::new(
+                device,
+                vk::BufferUsageFlags::TRANSFER_SRC,
+                vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT,
+                size,
+            )
+            .context("create staging buffer")?;
+            staging_buffer
+                .update(data)
+                .context("update staging buffer")?;
+            staging_buffer
+        };
+
+        let buffer = Self::new(
+            device,
+            usage | vk::BufferUsageFlags::TRANSFER_DST,
+            vk::MemoryPropertyFlags::DEVICE_LOCAL,
+            size,
+        )
+        .context("create device local buffer")?;
+
+        copy_buffer(device, command_pool, *staging_buffer, *buffer, size)
+            .context("copy staging buffer")?;
+
+        // NOTE: the staging buffer is dropped here and destroyed once the next
+        //       frame is done, the copy being submitted ahead of the frame commands
+        Ok(buffer)
+    }
+
     pub fn buffer(&self) -> &vk::Buffer {
         &self.handle
     }
 
-    pub unsafe fn update<T: Copy>(&mut self, device: &ash::Device, data: &[T]) -> Result<()> {
+    pub unsafe fn update<T: Copy>(&mut self, data: &[T]) -> Result<()> {
+        self.update_at(0, data)
+    }
+
+    /// Writes data at the given offset (in bytes) of a host visible buffer.
+    pub unsafe fn update_at<T: Copy>(&mut self, offset: u64, data: &[T]) -> Result<()> {
+        if offset + mem::size_of_val(data) as u64 > self.memory_requirements.size {
+            return Err("data does not fit in buffer".into());
+        }
+
         // obtain pointer into data
-        let buffer_ptr: *mut std::os::raw::c_void = device
-            .map_memory(
-                self.memory,
-                0,
-                self.memory_requirements.size,
-                vk::MemoryMapFlags::empty(),
-            )
-            .map_err(|e| format!("map buffer memory: {:?}", e))?;
+        // NOTE: host visible memory is persistently mapped by the allocator
+        let buffer_ptr = self
+            .allocation
+            .mapped_ptr()
+            .ok_or("buffer memory is not host visible")?
+            .add(offset as usize);
         let mut slice = Align::new(
             buffer_ptr,
             align_of::<T>() as u64,
-            self.memory_requirements.size,
+            self.memory_requirements.size - offset,
         );
 
         // copy data into buffer
         slice.copy_from_slice(data);
-        device.unmap_memory(self.memory);
 
         Ok(())
     }
 
-    pub unsafe fn destroy(&mut self, device: &ash::Device) {
-        if self.destroyed {
-            panic!("buffer already destroyed")
-        }
-        device.free_memory(self.memory, None);
-        device.destroy_buffer(self.handle, None);
-        self.destroyed = true;
+    /// Returns the content of a host visible buffer. Commands writing to the
+    /// buffer must have completed.
+    pub unsafe fn mapped_bytes(&self) -> Result<&[u8]> {
+        let buffer_ptr = self
+            .allocation
+            .mapped_ptr()
+            .ok_or("buffer memory is not host visible")?;
+        Ok(slice::from_raw_parts(
+            buffer_ptr as *const u8,
+            self.memory_requirements.size as usize,
+        ))
+    }
+}
+
+impl Drop for Buffer {
+    fn drop(&mut self) {
+        self.deletion_queue
+            .borrow_mut()
+            .push(Resource::Buffer(self.handle, self.allocation));
     }
 }
 
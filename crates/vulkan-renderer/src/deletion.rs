@@ -0,0 +1,161 @@
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::rc::Rc;
+
+use ash::vk;
+
+use super::allocator::{Allocation, Allocator};
+
+/// A Vulkan object waiting to be destroyed.
+#[derive(Debug)]
+pub enum Resource {
+    Buffer(vk::Buffer, Allocation),
+    Image(vk::Image, Allocation),
+    ImageView(vk::ImageView),
+    Framebuffer(vk::Framebuffer),
+    RenderPass(vk::RenderPass),
+    Sampler(vk::Sampler),
+    Pipeline(vk::Pipeline, vk::PipelineLayout),
+    ShaderModule(vk::ShaderModule),
+    DescriptorPool(vk::DescriptorPool),
+    DescriptorSetLayout(vk::DescriptorSetLayout),
+    /// A set allocated from a pool created with FREE_DESCRIPTOR_SET.
+    DescriptorSet(vk::DescriptorPool, vk::DescriptorSet),
+    CommandPool(vk::CommandPool),
+    CommandBuffer(vk::CommandPool, vk::CommandBuffer),
+    QueryPool(vk::QueryPool),
+}
+
+impl Resource {
+    unsafe fn destroy(self, device: &ash::Device, allocator: &mut Allocator) {
+        match self {
+            Resource::Buffer(buffer, allocation) => {
+                device.destroy_buffer(buffer, None);
+                allocator.free(device, &allocation);
+            }
+            Resource::Image(image, allocation) => {
+                device.destroy_image(image, None);
+                allocator.free(device, &allocation);
+            }
+            Resource::ImageView(view) => device.destroy_image_view(view, None),
+            Resource::Framebuffer(framebuffer) => device.destroy_framebuffer(framebuffer, None),
+            Resource::RenderPass(renderpass) => device.destroy_render_pass(renderpass, None),
+            Resource::Sampler(sampler) => device.destroy_sampler(sampler, None),
+            Resource::Pipeline(pipeline, layout) => {
+                device.destroy_pipeline(pipeline, None);
+                device.destroy_pipeline_layout(layout, None);
+            }
+            Resource::ShaderModule(module) => device.destroy_shader_module(module, None),
+            Resource::DescriptorPool(pool) => device.destroy_descriptor_pool(pool, None),
+            Resource::DescriptorSetLayout(layout) => {
+                device.destroy_descriptor_set_layout(layout, None)
+            }
+            Resource::DescriptorSet(pool, set) => {
+                // NOTE: freeing descriptor sets can not fail
+                let _ = device.free_descriptor_sets(pool, &[set]);
+            }
+            Resource::CommandPool(pool) => device.destroy_command_pool(pool, None),
+            Resource::CommandBuffer(pool, command_buffer) => {
+                device.free_command_buffers(pool, &[command_buffer])
+            }
+            Resource::QueryPool(pool) => device.destroy_query_pool(pool, None),
+        }
+    }
+}
+
+/// Holds dropped resources until the GPU is done with them.
+///
+/// Resources are tagged with the next frame to be submitted. Submissions made
+/// up to then, including one-off submits between frames, may still use them,
+/// so they are only destroyed once the fence of that frame has been waited
+/// on, which covers everything submitted before it to the queue.
+#[derive(Debug, Default)]
+pub struct DeletionQueue {
+    frame: u64,
+    pending: VecDeque<(u64, Resource)>,
+}
+
+/// Shared handle to the deletion queue of a `Device`. Wrapper types keep one
+/// so that they can schedule their destruction when dropped.
+pub type DeletionQueueHandle = Rc<RefCell<DeletionQueue>>;
+
+impl DeletionQueue {
+    pub fn push(&mut self, resource: Resource) {
+        self.pending.push_back((self.frame, resource));
+    }
+
+    pub fn len(&self) -> usize {
+        self.pending.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pending.is_empty()
+    }
+
+    /// Sets the next frame to be submitted, which resources dropped from now
+    /// on are tagged with.
+    pub(crate) fn set_frame(&mut self, frame: u64) {
+        self.frame = frame;
+    }
+
+    /// Destroys the resources tagged with the completed frame or before.
+    pub(crate) unsafe fn collect(
+        &mut self,
+        device: &ash::Device,
+        allocator: &mut Allocator,
+        completed_frame: u64,
+    ) {
+        for resource in self.retire(completed_frame) {
+            resource.destroy(device, allocator);
+        }
+    }
+
+    /// Removes the resources tagged with the completed frame or before.
+    fn retire(&mut self, completed_frame: u64) -> Vec<Resource> {
+        let count = self
+            .pending
+            .iter()
+            .take_while(|(frame, _)| *frame <= completed_frame)
+            .count();
+        self.pending
+            .drain(..count)
+            .map(|(_, resource)| resource)
+            .collect()
+    }
+
+    /// Destroys all the resources.
+    ///
+    /// Make sure to call device.device_wait_idle() prior to calling flush.
+    pub(crate) unsafe fn flush(&mut self, device: &ash::Device, allocator: &mut Allocator) {
+        for (_, resource) in self.pending.drain(..) {
+            resource.destroy(device, allocator);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resources_dropped_between_frames_retire_with_the_next_frame() {
+        let mut queue = DeletionQueue::default();
+        queue.set_frame(0);
+        queue.push(Resource::Sampler(vk::Sampler::null()));
+
+        // frame 0 was submitted, then a one-off command ran before frame 1
+        queue.set_frame(1);
+        queue.push(Resource::CommandBuffer(
+            vk::CommandPool::null(),
+            vk::CommandBuffer::null(),
+        ));
+
+        let retired = queue.retire(0);
+        assert!(matches!(retired[..], [Resource::Sampler(_)]));
+        assert_eq!(queue.len(), 1);
+
+        let retired = queue.retire(1);
+        assert!(matches!(retired[..], [Resource::CommandBuffer(..)]));
+        assert!(queue.is_empty());
+    }
+}
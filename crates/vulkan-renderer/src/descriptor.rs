@@ -3,10 +3,13 @@ use std::ops::Deref;
 use ash::vk;
 
 use super::buffer::Buffer;
+use super::deletion::{DeletionQueueHandle, Resource};
+use super::device::Device;
 use super::texture::Texture;
+use crate::error::ResultExt;
 use crate::Result;
 
-#[derive(Clone, Copy, Debug, PartialEq, PartialOrd)]
+#[derive(Debug)]
 pub struct DescriptorPool {
     /// A descriptor pool maintains a pool of descriptors, from which descriptor
     /// sets are allocated.
@@ -16,34 +19,49 @@ pub struct DescriptorPool {
     /// sets from the same pool in multiple threads simultaneously.
     pub handle: vk::DescriptorPool,
 
-    destroyed: bool,
+    deletion_queue: DeletionQueueHandle,
 }
 
 impl DescriptorPool {
     pub unsafe fn new(
-        device: &ash::Device,
+        device: &Device,
+        sizes: &[vk::DescriptorPoolSize],
+        max_count: u32,
+    ) -> Result<Self> {
+        Self::with_flags(
+            device,
+            sizes,
+            max_count,
+            vk::DescriptorPoolCreateFlags::empty(),
+        )
+    }
+
+    pub unsafe fn with_flags(
+        device: &Device,
         sizes: &[vk::DescriptorPoolSize],
         max_count: u32,
+        flags: vk::DescriptorPoolCreateFlags,
     ) -> Result<Self> {
         let descriptor_pool_info = vk::DescriptorPoolCreateInfo::builder()
+            .flags(flags)
             .pool_sizes(sizes)
             .max_sets(max_count);
         let descriptor_pool = device
             .create_descriptor_pool(&descriptor_pool_info, None)
-            .map_err(|e| format!("create descriptor pool: {:?}", e))?;
+            .context("create descriptor pool")?;
 
         Ok(Self {
             handle: descriptor_pool,
-            destroyed: false,
+            deletion_queue: device.deletion_queue(),
         })
     }
+}
 
-    pub unsafe fn destroy(&mut self, device: &ash::Device) {
-        if self.destroyed {
-            panic!("descriptor pool already destroyed")
-        }
-        device.destroy_descriptor_pool(self.handle, None);
-        self.destroyed = true;
+impl Drop for DescriptorPool {
+    fn drop(&mut self) {
+        self.deletion_queue
+            .borrow_mut()
+            .push(Resource::DescriptorPool(self.handle));
     }
 }
 
@@ -55,7 +73,7 @@ impl Deref for DescriptorPool {
     }
 }
 
-#[derive(Clone, Copy, Debug, PartialEq, PartialOrd)]
+#[derive(Debug)]
 pub struct DescriptorSetLayout {
     /// A descriptor set layout object is defined by an array of zero or more
     /// descriptor bindings. Each individual descriptor binding is specified by
@@ -64,31 +82,55 @@ pub struct DescriptorSetLayout {
     /// using immutable samplers) an array of sampler descriptors.
     pub handle: vk::DescriptorSetLayout,
 
-    destroyed: bool,
+    deletion_queue: DeletionQueueHandle,
 }
 
 impl DescriptorSetLayout {
     pub unsafe fn new(
-        device: &ash::Device,
+        device: &Device,
         bindings: &[vk::DescriptorSetLayoutBinding],
     ) -> Result<Self> {
-        let descriptor_info = vk::DescriptorSetLayoutCreateInfo::builder().bindings(bindings);
+        Self::with_flags(
+            device,
+            bindings,
+            &[],
+            vk::DescriptorSetLayoutCreateFlags::empty(),
+        )
+    }
+
+    /// Creates a layout whose bindings have flags, e.g. to be updated after
+    /// being bound. `binding_flags` is either empty or holds the flags of
+    /// each binding.
+    pub unsafe fn with_flags(
+        device: &Device,
+        bindings: &[vk::DescriptorSetLayoutBinding],
+        binding_flags: &[vk::DescriptorBindingFlags],
+        flags: vk::DescriptorSetLayoutCreateFlags,
+    ) -> Result<Self> {
+        let mut binding_flags_info =
+            vk::DescriptorSetLayoutBindingFlagsCreateInfo::builder().binding_flags(binding_flags);
+        let mut descriptor_info = vk::DescriptorSetLayoutCreateInfo::builder()
+            .flags(flags)
+            .bindings(bindings);
+        if !binding_flags.is_empty() {
+            descriptor_info = descriptor_info.push_next(&mut binding_flags_info);
+        }
         let descriptor_set_layout = device
             .create_descriptor_set_layout(&descriptor_info, None)
-            .map_err(|e| format!("create descriptor set layout: {:?}", e))?;
+            .context("create descriptor set layout")?;
 
         Ok(Self {
             handle: descriptor_set_layout,
-            destroyed: false,
+            deletion_queue: device.deletion_queue(),
         })
     }
+}
 
-    pub unsafe fn destroy(&mut self, device: &ash::Device) {
-        if self.destroyed {
-            panic!("descriptor set layout already destroyed")
-        }
-        device.destroy_descriptor_set_layout(self.handle, None);
-        self.destroyed = true;
+impl Drop for DescriptorSetLayout {
+    fn drop(&mut self) {
+        self.deletion_queue
+            .borrow_mut()
+            .push(Resource::DescriptorSetLayout(self.handle));
     }
 }
 
@@ -117,7 +159,7 @@ impl DescriptorSet {
             .set_layouts(&layouts);
         let descriptor_sets = device
             .allocate_descriptor_sets(&desc_alloc_info)
-            .map_err(|e| format!("allocate descriptor sets: {:?}", e))?;
+            .context("allocate descriptor sets")?;
 
         let descriptor_sets = descriptor_sets
             .iter()
@@ -160,6 +202,54 @@ impl DescriptorSet {
         self.update(device, &write_desc_sets)
     }
 
+    /// Points a storage buffer binding at the whole buffer.
+    pub unsafe fn update_storage_buffer(
+        &self,
+        device: &ash::Device,
+        binding: u32,
+        buffer: &Buffer,
+    ) -> Result<()> {
+        let descriptor_set_info = vk::DescriptorBufferInfo {
+            buffer: *buffer.buffer(),
+            range: vk::WHOLE_SIZE,
+            offset: 0,
+        };
+        let write_desc_sets = [vk::WriteDescriptorSet {
+            dst_set: self.handle,
+            dst_binding: binding,
+            descriptor_count: 1,
+            descriptor_type: vk::DescriptorType::STORAGE_BUFFER,
+            p_buffer_info: &descriptor_set_info,
+            ..Default::default()
+        }];
+        self.update(device, &write_desc_sets)
+    }
+
+    /// Points a combined image sampler binding at an image in the
+    /// SHADER_READ_ONLY_OPTIMAL layout.
+    pub unsafe fn update_image(
+        &self,
+        device: &ash::Device,
+        binding: u32,
+        image_view: vk::ImageView,
+        sampler: vk::Sampler,
+    ) -> Result<()> {
+        let descriptor_set_info = vk::DescriptorImageInfo {
+            image_layout: vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+            image_view,
+            sampler,
+        };
+        let write_desc_sets = [vk::WriteDescriptorSet {
+            dst_set: self.handle,
+            dst_binding: binding,
+            descriptor_count: 1,
+            descriptor_type: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+            p_image_info: &descriptor_set_info,
+            ..Default::default()
+        }];
+        self.update(device, &write_desc_sets)
+    }
+
     #[allow(unused)]
     pub unsafe fn update_texture(&self, device: &ash::Device, texture: &Texture) -> Result<()> {
         let descriptor_set_info = vk::DescriptorImageInfo {
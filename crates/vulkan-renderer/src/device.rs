@@ -1,14 +1,25 @@
+#[cfg(feature = "validation")]
 use std::borrow::Cow;
-use std::ffi::CStr;
+use std::cell::RefCell;
+use std::ffi::{CStr, CString};
 use std::ops::Deref;
 use std::os::raw::c_char;
+use std::rc::Rc;
 
-use ash::extensions::{ext, khr};
-use ash::vk::{self, DebugUtilsMessageSeverityFlagsEXT, DebugUtilsMessengerEXT};
+use ash::extensions::ext;
+use ash::extensions::khr;
+use ash::vk;
+#[cfg(feature = "validation")]
+use ash::vk::{DebugUtilsMessageSeverityFlagsEXT, DebugUtilsMessengerEXT};
 use ash::Entry;
-use log::{debug, error, info, warn};
+#[cfg(feature = "validation")]
+use log::error;
+use log::{debug, info, warn};
 use winit::window::Window;
 
+use crate::allocator::{Allocation, Allocator, MemoryStats};
+use crate::deletion::{DeletionQueue, DeletionQueueHandle, Resource};
+use crate::error::ResultExt;
 use crate::Result;
 
 #[derive(Debug, Default)]
@@ -64,22 +75,44 @@ impl From<ApiVersion> for u32 {
 // designed to use
 const API_VERSION: ApiVersion = ApiVersion::new(0, 1, 2, 0);
 
+const VALIDATION_LAYER_NAME: &[u8] = b"VK_LAYER_KHRONOS_validation\0";
+
+/// Depth formats in order of preference. D16_UNORM is always supported.
+pub const DEPTH_FORMATS: [vk::Format; 3] = [
+    vk::Format::D32_SFLOAT,
+    vk::Format::D24_UNORM_S8_UINT,
+    vk::Format::D16_UNORM,
+];
+
 pub struct Device {
+    /// Vulkan loader entry points, kept to recreate the surface.
+    entry: Entry,
+
     /// There is no global state in Vulkan and all per-application state is
     /// stored in a VkInstance object. Creating a VkInstance object initializes
     /// the Vulkan library and allows the application to pass information about
     /// itself to the implementation.
     instance: ash::Instance,
 
+    /// Names objects and labels command buffer regions for debuggers such as
+    /// RenderDoc. None if the debug utils extension is not available.
+    debug_utils: Option<ext::DebugUtils>,
+
     /// Handles Vulkan debug messages by passing them to a debug callback.
-    debug_utils_loader: ext::DebugUtils,
-    debug_callback: vk::DebugUtilsMessengerEXT,
+    /// Only set when validation is enabled.
+    #[cfg(feature = "validation")]
+    debug_messenger: Option<vk::DebugUtilsMessengerEXT>,
+    validation: bool,
 
     /// Native platform surface or window objects are abstracted by surface
     /// objects, which are represented by VkSurfaceKHR handles.
     surface: vk::SurfaceKHR,
     surface_loader: khr::Surface,
 
+    /// Set when the device was created without a window, in which case the
+    /// surface extensions are not enabled and there is never a surface.
+    headless: bool,
+
     /// Vulkan separates the concept of physical and logical devices. A physical
     /// device usually represents a single complete implementation of Vulkan
     /// (excluding instance-level functionality) available to the host, of which
@@ -93,61 +126,224 @@ pub struct Device {
     /// Logical devices are represented by VkDevice handles.
     handle: ash::Device,
 
+    /// Suballocates device memory used by buffers and images.
+    allocator: RefCell<Allocator>,
+
+    /// Resources dropped while they may still be in use by the GPU.
+    deletion_queue: DeletionQueueHandle,
+
     /// Device queue used to submit graphics command buffers.
     gfx_queue: vk::Queue,
     gfx_queue_family_index: u32,
+
+    /// Nanoseconds per timestamp tick, None if the graphics queue does not
+    /// support timestamps.
+    timestamp_period: Option<f32>,
+
+    /// Maximum number of textures in a bindless array, None if descriptor
+    /// indexing is not supported.
+    bindless_texture_limit: Option<u32>,
+
+    /// Format of the depth attachments, the most precise one supported.
+    depth_format: vk::Format,
+
+    /// Records barriers and submits work using synchronization2, None if
+    /// VK_KHR_synchronization2 is not supported.
+    synchronization2: Option<khr::Synchronization2>,
+
+    /// Set when VK_EXT_memory_budget is enabled, to report the usage and
+    /// budget of the memory heaps.
+    memory_budget: bool,
 }
 
 impl Device {
     /// Returns a new device that allows access to the underlying physical
     /// device.
-    pub unsafe fn new(app_name: impl AsRef<str>, window: &Window) -> Result<Self> {
+    ///
+    /// Validation layers are enabled if requested and available. They are
+    /// never enabled when the `validation` feature is disabled. The physical
+    /// device is the one at index `gpu` in the order they are enumerated, or
+    /// the first suitable one if None.
+    pub unsafe fn new(
+        app_name: impl AsRef<str>,
+        window: &Window,
+        validation: bool,
+        gpu: Option<usize>,
+    ) -> Result<Self> {
+        Self::create(app_name, Some(window), validation, gpu)
+    }
+
+    /// Returns a new device created without a surface, which can not present
+    /// to windows. Frames are rendered to offscreen images instead, e.g. to
+    /// run the renderer in tests and CI without a display.
+    pub unsafe fn new_headless(
+        app_name: impl AsRef<str>,
+        validation: bool,
+        gpu: Option<usize>,
+    ) -> Result<Self> {
+        Self::create(app_name, None, validation, gpu)
+    }
+
+    unsafe fn create(
+        app_name: impl AsRef<str>,
+        window: Option<&Window>,
+        validation: bool,
+        gpu: Option<usize>,
+    ) -> Result<Self> {
         // Load entry points from a Vulkan loader linked at compile time.
         // NOTE: requires that the build environment have Vulkan development packages
         // installed.
         let entry = ash::Entry::linked();
 
+        // fall back to running without validation when it can not be enabled
+        #[cfg(feature = "validation")]
+        let validation = validation && {
+            let available = has_validation_layer(&entry).context("enumerate instance layers")?;
+            if !available {
+                warn!("validation layer is not available, running without validation");
+            }
+            available
+        };
+        #[cfg(not(feature = "validation"))]
+        let validation = {
+            if validation {
+                warn!("validation feature is disabled, running without validation");
+            }
+            false
+        };
+
+        // the debug utils extension names objects for debuggers and routes
+        // validation messages to our logger
+        // NOTE: some drivers do not expose it, validation still works without
+        //       it but messages are not routed to our logger.
+        let debug_utils_enabled = has_debug_utils_extension(&entry, validation)
+            .context("enumerate instance extensions")?;
+        if validation && !debug_utils_enabled {
+            warn!("debug utils extension is not available, validation messages will not be logged");
+        }
+
         // create Vulkan instance
-        let instance = create_instance(&entry, window, app_name)?;
+        let instance = create_instance(&entry, window, app_name, validation, debug_utils_enabled)?;
+        let debug_utils = debug_utils_enabled.then(|| ext::DebugUtils::new(&entry, &instance));
 
         // setup debug callback that logs Vulkan debug messages
-        let (debug_utils_loader, debug_callback) = create_debug_callback(&entry, &instance)
-            .map_err(|e| format!("create Vulkan debug callback: {:?}", e))?;
+        #[cfg(feature = "validation")]
+        let debug_messenger = match &debug_utils {
+            Some(debug_utils) if validation => {
+                Some(create_debug_callback(debug_utils).context("create Vulkan debug callback")?)
+            }
+            _ => None,
+        };
 
         // create surface from window
-        let (surface, surface_loader) = create_surface(&entry, &instance, window)
-            .map_err(|e| format!("create Vulkan surface: {:?}", e))?;
+        // NOTE: the functions of the surface loader can not be called without
+        //       a window, the extension not being enabled
+        let surface_loader = khr::Surface::new(&entry, &instance);
+        let surface = match window {
+            Some(window) => ash_window::create_surface(&entry, &instance, &window, None)
+                .context("create Vulkan surface")?,
+            None => vk::SurfaceKHR::null(),
+        };
 
         // find physical device (graphics card) that supports graphics and our window
-        let (physical_device, gfx_queue_family_index) =
-            find_suitable_physical_device(&instance, &surface_loader, &surface).map_err(|e| {
-                format!("find suitable physical device (supports graphics): {:?}", e)
-            })?;
+        let (physical_device, gfx_queue_family_index) = find_suitable_physical_device(
+            &instance,
+            &surface_loader,
+            window.is_some().then_some(surface),
+            gpu,
+        )
+        .context("find suitable physical device (supports graphics)")?;
 
         // get physical device memory properties
         // this is used when creating different types of buffers
         let physical_device_memory_properties =
             instance.get_physical_device_memory_properties(physical_device);
 
+        let physical_device_properties = instance.get_physical_device_properties(physical_device);
+
+        // create memory allocator
+        let allocator = Allocator::new(
+            physical_device_memory_properties,
+            physical_device_properties.limits.buffer_image_granularity,
+        );
+
+        // timestamps are only written on queues with valid bits
+        let timestamp_period = {
+            let queue_families =
+                instance.get_physical_device_queue_family_properties(physical_device);
+            let valid_bits = queue_families[gfx_queue_family_index as usize].timestamp_valid_bits;
+            (valid_bits > 0).then_some(physical_device_properties.limits.timestamp_period)
+        };
+
+        // descriptor indexing is enabled when supported, for bindless textures
+        let bindless_texture_limit = bindless_texture_limit(&instance, physical_device);
+        if bindless_texture_limit.is_none() {
+            info!("descriptor indexing is not supported, bindless textures are disabled");
+        }
+
+        let depth_format = find_supported_format(
+            &instance,
+            physical_device,
+            &DEPTH_FORMATS,
+            vk::FormatFeatureFlags::DEPTH_STENCIL_ATTACHMENT,
+        )
+        .ok_or("find supported depth format")?;
+        debug!("depth format: {depth_format:?}");
+
+        // synchronization2 is enabled when supported, see the sync module
+        let synchronization2_supported = synchronization2_supported(&instance, physical_device)
+            .context("query synchronization2 support")?;
+        if !synchronization2_supported {
+            info!("synchronization2 is not supported, using the legacy barriers and submits");
+        }
+
+        let memory_budget =
+            device_extension_supported(&instance, physical_device, vk::ExtMemoryBudgetFn::name())
+                .context("query memory budget support")?;
+        if !memory_budget {
+            info!("memory budget is not supported, using the heap sizes as budgets");
+        }
+
         // create logical Vulkan device handle
-        let device = create_device(&instance, &physical_device, gfx_queue_family_index)
-            .map_err(|e| format!("create Vulkan device: {:?}", e))?;
+        let device = create_device(
+            &instance,
+            &physical_device,
+            gfx_queue_family_index,
+            bindless_texture_limit.is_some(),
+            synchronization2_supported,
+            memory_budget,
+            window.is_some(),
+        )
+        .context("create Vulkan device")?;
+        let synchronization2 =
+            synchronization2_supported.then(|| khr::Synchronization2::new(&instance, &device));
 
         // The queue handle used to submit command buffers
         // For now, use the same queue for both graphics and compute command buffers
         let gfx_queue = device.get_device_queue(gfx_queue_family_index, 0);
 
         Ok(Self {
+            entry,
             instance,
-            debug_utils_loader,
-            debug_callback,
+            debug_utils,
+            #[cfg(feature = "validation")]
+            debug_messenger,
+            validation,
             surface,
             surface_loader,
+            headless: window.is_none(),
             physical_device,
             physical_device_memory_properties,
             handle: device,
+            allocator: RefCell::new(allocator),
+            deletion_queue: Rc::new(RefCell::new(DeletionQueue::default())),
             gfx_queue,
             gfx_queue_family_index,
+            timestamp_period,
+            bindless_texture_limit,
+            depth_format,
+            synchronization2,
+            memory_budget,
         })
     }
 
@@ -156,11 +352,161 @@ impl Device {
         &self.instance
     }
 
-    /// Returns a handle to the Vulkan surface.
+    /// Returns true if validation layers are enabled.
+    pub fn validation_enabled(&self) -> bool {
+        self.validation
+    }
+
+    /// Names a Vulkan object, as shown by debuggers such as RenderDoc. Does
+    /// nothing without the debug utils extension.
+    pub unsafe fn set_object_name<H: vk::Handle>(&self, handle: H, name: &str) {
+        let Some(debug_utils) = &self.debug_utils else {
+            return;
+        };
+        let name = CString::new(name).unwrap_or_default();
+        let name_info = vk::DebugUtilsObjectNameInfoEXT::builder()
+            .object_type(H::TYPE)
+            .object_handle(handle.as_raw())
+            .object_name(&name);
+        if let Err(e) = debug_utils.set_debug_utils_object_name(self.handle.handle(), &name_info) {
+            warn!("set object name {name:?}: {e}");
+        }
+    }
+
+    /// Opens a labeled region of a command buffer, which must be closed using
+    /// `end_label()`. Does nothing without the debug utils extension.
+    pub unsafe fn begin_label(&self, command_buffer: vk::CommandBuffer, name: &str) {
+        let Some(debug_utils) = &self.debug_utils else {
+            return;
+        };
+        let name = CString::new(name).unwrap_or_default();
+        let label = vk::DebugUtilsLabelEXT::builder().label_name(&name);
+        debug_utils.cmd_begin_debug_utils_label(command_buffer, &label);
+    }
+
+    /// Closes the last region opened using `begin_label()`.
+    pub unsafe fn end_label(&self, command_buffer: vk::CommandBuffer) {
+        if let Some(debug_utils) = &self.debug_utils {
+            debug_utils.cmd_end_debug_utils_label(command_buffer);
+        }
+    }
+
+    /// Returns a handle to the Vulkan surface, null while it is destroyed or
+    /// when the device is headless.
     pub fn surface(&self) -> &vk::SurfaceKHR {
         &self.surface
     }
 
+    /// Returns true if the device was created using `new_headless()`.
+    pub fn is_headless(&self) -> bool {
+        self.headless
+    }
+
+    /// Returns the layout the main render pass leaves its images in: ready
+    /// to be presented, or to be copied from when the device is headless.
+    pub fn present_layout(&self) -> vk::ImageLayout {
+        if self.headless {
+            vk::ImageLayout::TRANSFER_SRC_OPTIMAL
+        } else {
+            vk::ImageLayout::PRESENT_SRC_KHR
+        }
+    }
+
+    /// Destroys the surface, e.g. when the application is suspended. The
+    /// swapchain using it must have been destroyed.
+    pub unsafe fn destroy_surface(&mut self) {
+        if self.surface != vk::SurfaceKHR::null() {
+            self.surface_loader.destroy_surface(self.surface, None);
+            self.surface = vk::SurfaceKHR::null();
+        }
+    }
+
+    /// Replaces the surface with a new one created from the window, which the
+    /// graphics queue must be able to present to.
+    pub unsafe fn recreate_surface(&mut self, window: &Window) -> Result<()> {
+        self.destroy_surface();
+        self.surface = self.create_window_surface(window)?;
+        Ok(())
+    }
+
+    /// Creates a surface for a window other than the one of the device, e.g.
+    /// to present to several windows. The graphics queue must be able to
+    /// present to it. It must be destroyed using `destroy_window_surface()`.
+    pub unsafe fn create_window_surface(&self, window: &Window) -> Result<vk::SurfaceKHR> {
+        if self.headless {
+            return Err("a headless device can not present to windows".into());
+        }
+        let surface = ash_window::create_surface(&self.entry, &self.instance, &window, None)
+            .context("create surface from window")?;
+
+        let supported = self
+            .surface_loader
+            .get_physical_device_surface_support(
+                self.physical_device,
+                self.gfx_queue_family_index,
+                surface,
+            )
+            .context("query surface support");
+        match supported {
+            Ok(true) => Ok(surface),
+            result => {
+                self.surface_loader.destroy_surface(surface, None);
+                result?;
+                Err("the graphics queue can not present to the new surface".into())
+            }
+        }
+    }
+
+    /// Destroys a surface created using `create_window_surface()`. The
+    /// swapchain using it must have been destroyed.
+    pub unsafe fn destroy_window_surface(&self, surface: vk::SurfaceKHR) {
+        self.surface_loader.destroy_surface(surface, None);
+    }
+
+    /// Returns the number of nanoseconds per timestamp tick, or None if the
+    /// graphics queue does not support timestamp queries.
+    pub fn timestamp_period(&self) -> Option<f32> {
+        self.timestamp_period
+    }
+
+    /// Returns the maximum number of textures in a bindless array, None if
+    /// descriptor indexing is not supported.
+    pub fn bindless_texture_limit(&self) -> Option<u32> {
+        self.bindless_texture_limit
+    }
+
+    /// Returns true if images of the format can be created with optimal
+    /// tiling and used as described by the features.
+    pub unsafe fn supports_format(
+        &self,
+        format: vk::Format,
+        features: vk::FormatFeatureFlags,
+    ) -> bool {
+        format_supported(&self.instance, self.physical_device, format, features)
+    }
+
+    /// Returns the first of the candidate formats, in order of preference,
+    /// that supports the features with optimal tiling.
+    pub unsafe fn find_supported_format(
+        &self,
+        candidates: &[vk::Format],
+        features: vk::FormatFeatureFlags,
+    ) -> Option<vk::Format> {
+        find_supported_format(&self.instance, self.physical_device, candidates, features)
+    }
+
+    /// Returns the format of the depth attachments, picked from
+    /// `DEPTH_FORMATS` when the device was created.
+    pub fn depth_format(&self) -> vk::Format {
+        self.depth_format
+    }
+
+    /// Returns the synchronization2 functions, None if the extension is not
+    /// supported.
+    pub fn synchronization2(&self) -> Option<&khr::Synchronization2> {
+        self.synchronization2.as_ref()
+    }
+
     /// Returns a handle to the graphics queue for this device.
     pub fn graphics_queue(&self) -> &vk::Queue {
         &self.gfx_queue
@@ -175,7 +521,7 @@ impl Device {
         let command_pool = self
             .handle
             .create_command_pool(&command_pool_create_info, None)
-            .map_err(|e| format!("create command pool: {:?}", e))?;
+            .context("create command pool")?;
 
         Ok(command_pool)
     }
@@ -194,7 +540,7 @@ impl Device {
         let command_buffers = self
             .handle
             .allocate_command_buffers(&command_buffer_allocate_info)
-            .map_err(|e| format!("allocate command buffers: {:?}", e))?;
+            .context("allocate command buffers")?;
 
         Ok(command_buffers)
     }
@@ -204,20 +550,64 @@ impl Device {
         &self.physical_device_memory_properties
     }
 
+    /// Allocates device memory matching the provided requirements.
+    pub unsafe fn allocate_memory(
+        &self,
+        requirements: vk::MemoryRequirements,
+        properties: vk::MemoryPropertyFlags,
+    ) -> Result<Allocation> {
+        self.allocator
+            .borrow_mut()
+            .allocate(&self.handle, requirements, properties)
+    }
+
+    /// Returns memory obtained using allocate_memory() to the allocator.
+    pub unsafe fn free_memory(&self, allocation: &Allocation) {
+        self.allocator.borrow_mut().free(&self.handle, allocation)
+    }
+
+    /// Returns the memory usage of the allocator and of each memory heap.
+    /// With VK_EXT_memory_budget, the heap usages and budgets are those
+    /// reported by the driver and account for the other processes.
+    pub fn memory_stats(&self) -> MemoryStats {
+        let mut stats = self.allocator.borrow().stats();
+        if self.memory_budget {
+            let mut budget_properties = vk::PhysicalDeviceMemoryBudgetPropertiesEXT::default();
+            {
+                let mut memory_properties = vk::PhysicalDeviceMemoryProperties2::builder()
+                    .push_next(&mut budget_properties);
+                unsafe {
+                    self.instance.get_physical_device_memory_properties2(
+                        self.physical_device,
+                        &mut memory_properties,
+                    );
+                }
+            }
+            for (heap_index, heap) in stats.heaps.iter_mut().enumerate() {
+                heap.usage = budget_properties.heap_usage[heap_index];
+                heap.budget = budget_properties.heap_budget[heap_index];
+            }
+        }
+        stats
+    }
+
     /// Returns surface attributes needed to create a swapchain for this device.
-    pub unsafe fn swapchain_support_details(&self) -> Result<SwapChainSupportDetails> {
+    pub unsafe fn swapchain_support_details(
+        &self,
+        surface: vk::SurfaceKHR,
+    ) -> Result<SwapChainSupportDetails> {
         let formats = self
             .surface_loader
-            .get_physical_device_surface_formats(self.physical_device, self.surface)
-            .map_err(|e| format!("obtain physical device surface formats: {:?}", e))?;
+            .get_physical_device_surface_formats(self.physical_device, surface)
+            .context("obtain physical device surface formats")?;
         let capabilities = self
             .surface_loader
-            .get_physical_device_surface_capabilities(self.physical_device, self.surface)
-            .map_err(|e| format!("obtain physical device surface capabilities: {:?}", e))?;
+            .get_physical_device_surface_capabilities(self.physical_device, surface)
+            .context("obtain physical device surface capabilities")?;
         let present_modes = self
             .surface_loader
-            .get_physical_device_surface_present_modes(self.physical_device, self.surface)
-            .map_err(|e| format!("obtain physical device surface present modes: {:?}", e))?;
+            .get_physical_device_surface_present_modes(self.physical_device, surface)
+            .context("obtain physical device surface present modes")?;
 
         Ok(SwapChainSupportDetails::new(
             formats,
@@ -226,17 +616,74 @@ impl Device {
         ))
     }
 
-    // Make sure to call device.device_wait_idle() prior to calling destroy.
-    pub unsafe fn destroy(&self) {
-        // device
-        self.handle.destroy_device(None);
-        // surface
-        self.surface_loader.destroy_surface(self.surface, None);
-        // debug callback
-        self.debug_utils_loader
-            .destroy_debug_utils_messenger(self.debug_callback, None);
-        // instance
-        self.instance.destroy_instance(None);
+    /// Returns true if swapchain images of the surface can not be created
+    /// because its extent is 0 in some direction, e.g. while its window is
+    /// minimized on Windows, where the maximum image extent is then 0x0.
+    pub unsafe fn surface_is_minimized(&self, surface: vk::SurfaceKHR) -> Result<bool> {
+        let capabilities = self
+            .surface_loader
+            .get_physical_device_surface_capabilities(self.physical_device, surface)
+            .context("obtain physical device surface capabilities")?;
+        let max_extent = capabilities.max_image_extent;
+        Ok(max_extent.width == 0 || max_extent.height == 0)
+    }
+
+    /// Returns a handle to the queue that wrapper types push themselves onto
+    /// when dropped.
+    pub fn deletion_queue(&self) -> DeletionQueueHandle {
+        Rc::clone(&self.deletion_queue)
+    }
+
+    /// Schedules the destruction of a resource that may still be in use by
+    /// the GPU.
+    pub fn defer_destroy(&self, resource: Resource) {
+        self.deletion_queue.borrow_mut().push(resource);
+    }
+
+    /// Tags resources dropped from now on with the given frame, the next one
+    /// to be submitted.
+    pub(crate) fn set_garbage_frame(&self, frame: u64) {
+        self.deletion_queue.borrow_mut().set_frame(frame);
+    }
+
+    /// Destroys the resources tagged with the completed frame or before.
+    pub(crate) unsafe fn collect_garbage(&self, completed_frame: u64) {
+        self.deletion_queue.borrow_mut().collect(
+            &self.handle,
+            &mut self.allocator.borrow_mut(),
+            completed_frame,
+        );
+    }
+}
+
+impl Drop for Device {
+    fn drop(&mut self) {
+        debug!("Destroying Device");
+
+        unsafe {
+            // NOTE: All submitted commands that refer to the resources waiting for
+            //       deletion must have completed execution.
+            self.handle.device_wait_idle().expect("device wait idle");
+            // resources dropped by their owners
+            self.deletion_queue
+                .borrow_mut()
+                .flush(&self.handle, self.allocator.get_mut());
+            // memory
+            self.allocator.get_mut().destroy(&self.handle);
+            // device
+            self.handle.destroy_device(None);
+            // surface
+            self.destroy_surface();
+            // debug callback
+            #[cfg(feature = "validation")]
+            if let (Some(debug_utils), Some(debug_messenger)) =
+                (&self.debug_utils, self.debug_messenger.take())
+            {
+                debug_utils.destroy_debug_utils_messenger(debug_messenger, None);
+            }
+            // instance
+            self.instance.destroy_instance(None);
+        }
     }
 }
 
@@ -250,27 +697,31 @@ impl Deref for Device {
 
 unsafe fn create_instance(
     entry: &ash::Entry,
-    window: &Window,
+    window: Option<&Window>,
     app_name: impl AsRef<str>,
+    validation: bool,
+    debug_utils: bool,
 ) -> Result<ash::Instance> {
     // gather required Vulkan layers
     // NOTE: Make sure we enable validation layers to catch any issue during
     // development. These can be logged by setting up a debug callback using
-    // DebugUtils. Should be disabled in release mode to improve
-    // performance.
-    let layer_names = [CStr::from_bytes_with_nul_unchecked(
-        b"VK_LAYER_KHRONOS_validation\0",
-    )];
-    let layers_names_raw: Vec<*const c_char> = layer_names
-        .iter()
-        .map(|raw_name| raw_name.as_ptr())
-        .collect();
+    // DebugUtils. Disable validation in shipping builds to improve performance.
+    let layers_names_raw: Vec<*const c_char> = if validation {
+        vec![VALIDATION_LAYER_NAME.as_ptr() as *const c_char]
+    } else {
+        Vec::new()
+    };
 
     // gather required vulkan extensions from the provided window handle
-    let mut extension_names = ash_window::enumerate_required_extensions(window)
-        .map_err(|e| format!("enumerate required extensions from window: {:?}", e))?
-        .to_vec();
-    extension_names.push(ext::DebugUtils::name().as_ptr());
+    let mut extension_names = match window {
+        Some(window) => ash_window::enumerate_required_extensions(window)
+            .context("enumerate required extensions from window")?
+            .to_vec(),
+        None => Vec::new(),
+    };
+    if debug_utils {
+        extension_names.push(ext::DebugUtils::name().as_ptr());
+    }
 
     let app_name_nul_terminated = format!("{}\0", app_name.as_ref());
     let app_name_bytes = app_name_nul_terminated.as_bytes();
@@ -291,32 +742,30 @@ unsafe fn create_instance(
 
     let instance = entry
         .create_instance(&create_info, None)
-        .map_err(|e| format!("Vulkan instance creation: {:?}", e))?;
+        .context("Vulkan instance creation")?;
 
     Ok(instance)
 }
 
-unsafe fn create_surface(
-    entry: &ash::Entry,
-    instance: &ash::Instance,
-    window: &Window,
-) -> Result<(vk::SurfaceKHR, khr::Surface)> {
-    let surface = ash_window::create_surface(entry, instance, &window, None)
-        .map_err(|e| format!("create surface from window: {:?}", e))?;
-    let surface_loader = khr::Surface::new(entry, instance);
-
-    Ok((surface, surface_loader))
-}
-
+/// Returns the first device with a queue supporting graphics and, unless
+/// headless, presentation to the surface. Only the device at index `gpu` is
+/// considered if set.
 unsafe fn find_suitable_physical_device(
     instance: &ash::Instance,
     surface_loader: &khr::Surface,
-    surface: &vk::SurfaceKHR,
+    surface: Option<vk::SurfaceKHR>,
+    gpu: Option<usize>,
 ) -> Result<(vk::PhysicalDevice, u32)> {
     let pdevices = instance
         .enumerate_physical_devices()
-        .map_err(|e| format!("enumerate physical devices: {:?}", e))?;
-    let (pdevice, gfx_queue_family_index) = pdevices
+        .context("enumerate physical devices")?;
+    let candidates = match gpu {
+        Some(index) => pdevices
+            .get(index..=index)
+            .ok_or_else(|| format!("no physical device {index}, {} available", pdevices.len()))?,
+        None => &pdevices[..],
+    };
+    let (pdevice, gfx_queue_family_index) = candidates
         .iter()
         .find_map(|pdevice| {
             instance
@@ -324,12 +773,17 @@ unsafe fn find_suitable_physical_device(
                 .iter()
                 .enumerate()
                 .find_map(|(index, info)| {
-                    let supports_graphic_and_surface = info
-                        .queue_flags
-                        .contains(vk::QueueFlags::GRAPHICS)
-                        && surface_loader
-                            .get_physical_device_surface_support(*pdevice, index as u32, *surface)
-                            .unwrap();
+                    let supports_graphic_and_surface =
+                        info.queue_flags.contains(vk::QueueFlags::GRAPHICS)
+                            && surface.map_or(true, |surface| {
+                                surface_loader
+                                    .get_physical_device_surface_support(
+                                        *pdevice,
+                                        index as u32,
+                                        surface,
+                                    )
+                                    .unwrap()
+                            });
                     if supports_graphic_and_surface {
                         Some((*pdevice, index))
                     } else {
@@ -342,33 +796,189 @@ unsafe fn find_suitable_physical_device(
     Ok((pdevice, gfx_queue_family_index as u32))
 }
 
+/// Returns the maximum number of textures of a bindless array, None if the
+/// descriptor indexing features it relies on are not supported.
+unsafe fn bindless_texture_limit(
+    instance: &ash::Instance,
+    physical_device: vk::PhysicalDevice,
+) -> Option<u32> {
+    let mut indexing_features = vk::PhysicalDeviceDescriptorIndexingFeatures::default();
+    {
+        let mut features = vk::PhysicalDeviceFeatures2::builder().push_next(&mut indexing_features);
+        instance.get_physical_device_features2(physical_device, &mut features);
+    }
+    let supported = [
+        indexing_features.runtime_descriptor_array,
+        indexing_features.descriptor_binding_partially_bound,
+        indexing_features.descriptor_binding_sampled_image_update_after_bind,
+        indexing_features.descriptor_binding_update_unused_while_pending,
+        indexing_features.shader_sampled_image_array_non_uniform_indexing,
+    ]
+    .iter()
+    .all(|feature| *feature == vk::TRUE);
+    if !supported {
+        return None;
+    }
+
+    let mut indexing_properties = vk::PhysicalDeviceDescriptorIndexingProperties::default();
+    {
+        let mut properties =
+            vk::PhysicalDeviceProperties2::builder().push_next(&mut indexing_properties);
+        instance.get_physical_device_properties2(physical_device, &mut properties);
+    }
+    [
+        indexing_properties.max_per_stage_descriptor_update_after_bind_samplers,
+        indexing_properties.max_per_stage_descriptor_update_after_bind_sampled_images,
+        indexing_properties.max_descriptor_set_update_after_bind_samplers,
+        indexing_properties.max_descriptor_set_update_after_bind_sampled_images,
+    ]
+    .into_iter()
+    .min()
+}
+
+/// Returns true if the device supports the extension.
+unsafe fn device_extension_supported(
+    instance: &ash::Instance,
+    physical_device: vk::PhysicalDevice,
+    name: &CStr,
+) -> Result<bool> {
+    let extensions = instance
+        .enumerate_device_extension_properties(physical_device)
+        .context("enumerate device extensions")?;
+    Ok(extensions
+        .iter()
+        .any(|extension| CStr::from_ptr(extension.extension_name.as_ptr()) == name))
+}
+
+/// Returns true if the device supports VK_KHR_synchronization2 and its
+/// feature.
+unsafe fn synchronization2_supported(
+    instance: &ash::Instance,
+    physical_device: vk::PhysicalDevice,
+) -> Result<bool> {
+    if !device_extension_supported(instance, physical_device, khr::Synchronization2::name())? {
+        return Ok(false);
+    }
+
+    let mut synchronization2_features = vk::PhysicalDeviceSynchronization2Features::default();
+    {
+        let mut features =
+            vk::PhysicalDeviceFeatures2::builder().push_next(&mut synchronization2_features);
+        instance.get_physical_device_features2(physical_device, &mut features);
+    }
+    Ok(synchronization2_features.synchronization2 == vk::TRUE)
+}
+
+unsafe fn format_supported(
+    instance: &ash::Instance,
+    physical_device: vk::PhysicalDevice,
+    format: vk::Format,
+    features: vk::FormatFeatureFlags,
+) -> bool {
+    instance
+        .get_physical_device_format_properties(physical_device, format)
+        .optimal_tiling_features
+        .contains(features)
+}
+
+unsafe fn find_supported_format(
+    instance: &ash::Instance,
+    physical_device: vk::PhysicalDevice,
+    candidates: &[vk::Format],
+    features: vk::FormatFeatureFlags,
+) -> Option<vk::Format> {
+    candidates
+        .iter()
+        .copied()
+        .find(|format| format_supported(instance, physical_device, *format, features))
+}
+
 unsafe fn create_device(
     instance: &ash::Instance,
     physical_device: &vk::PhysicalDevice,
     queue_family_index: u32,
+    descriptor_indexing: bool,
+    synchronization2: bool,
+    memory_budget: bool,
+    swapchain: bool,
 ) -> Result<ash::Device> {
     let priorities = [1.0];
     let queue_info = vk::DeviceQueueCreateInfo::builder()
         .queue_family_index(queue_family_index)
         .queue_priorities(&priorities);
 
-    let device_extension_names_raw = [khr::Swapchain::name().as_ptr()];
+    let mut device_extension_names_raw = Vec::new();
+    if swapchain {
+        device_extension_names_raw.push(khr::Swapchain::name().as_ptr());
+    }
+    if synchronization2 {
+        device_extension_names_raw.push(khr::Synchronization2::name().as_ptr());
+    }
+    if memory_budget {
+        device_extension_names_raw.push(vk::ExtMemoryBudgetFn::name().as_ptr());
+    }
     let features = vk::PhysicalDeviceFeatures {
         shader_clip_distance: 1,
         ..Default::default()
     };
-    let device_create_info = vk::DeviceCreateInfo::builder()
+    // features used by bindless textures, see BindlessTextures
+    let mut indexing_features = vk::PhysicalDeviceDescriptorIndexingFeatures::builder()
+        .runtime_descriptor_array(true)
+        .descriptor_binding_partially_bound(true)
+        .descriptor_binding_sampled_image_update_after_bind(true)
+        .descriptor_binding_update_unused_while_pending(true)
+        .shader_sampled_image_array_non_uniform_indexing(true);
+    let mut device_create_info = vk::DeviceCreateInfo::builder()
         .queue_create_infos(std::slice::from_ref(&queue_info))
         .enabled_extension_names(&device_extension_names_raw)
         .enabled_features(&features);
+    if descriptor_indexing {
+        device_create_info = device_create_info.push_next(&mut indexing_features);
+    }
+    let mut synchronization2_features =
+        vk::PhysicalDeviceSynchronization2Features::builder().synchronization2(true);
+    if synchronization2 {
+        device_create_info = device_create_info.push_next(&mut synchronization2_features);
+    }
 
     let device: ash::Device = instance
         .create_device(*physical_device, &device_create_info, None)
-        .map_err(|e| format!("create Vulkan device: {:?}", e))?;
+        .context("create Vulkan device")?;
 
     Ok(device)
 }
 
+#[cfg(feature = "validation")]
+unsafe fn has_validation_layer(entry: &Entry) -> Result<bool> {
+    let layers = entry.enumerate_instance_layer_properties()?;
+    let available = layers.iter().any(|layer| {
+        CStr::from_ptr(layer.layer_name.as_ptr()).to_bytes_with_nul() == VALIDATION_LAYER_NAME
+    });
+    Ok(available)
+}
+
+/// Returns true if the debug utils extension is provided by the
+/// implementation, an implicit layer or, if enabled, the validation layer.
+unsafe fn has_debug_utils_extension(entry: &Entry, validation: bool) -> Result<bool> {
+    let validation_layer = CStr::from_bytes_with_nul_unchecked(VALIDATION_LAYER_NAME);
+    let layers: &[Option<&CStr>] = if validation {
+        &[None, Some(validation_layer)]
+    } else {
+        &[None]
+    };
+    for layer in layers {
+        let extensions = entry.enumerate_instance_extension_properties(*layer)?;
+        let available = extensions.iter().any(|extension| {
+            CStr::from_ptr(extension.extension_name.as_ptr()) == ext::DebugUtils::name()
+        });
+        if available {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
+#[cfg(feature = "validation")]
 unsafe extern "system" fn debug_callback(
     message_severity: vk::DebugUtilsMessageSeverityFlagsEXT,
     message_type: vk::DebugUtilsMessageTypeFlagsEXT,
@@ -407,10 +1017,10 @@ unsafe extern "system" fn debug_callback(
     vk::FALSE
 }
 
+#[cfg(feature = "validation")]
 pub unsafe fn create_debug_callback(
-    entry: &Entry,
-    instance: &ash::Instance,
-) -> Result<(ext::DebugUtils, DebugUtilsMessengerEXT)> {
+    debug_utils: &ext::DebugUtils,
+) -> Result<DebugUtilsMessengerEXT> {
     let debug_info = vk::DebugUtilsMessengerCreateInfoEXT::builder()
         .message_severity(
             vk::DebugUtilsMessageSeverityFlagsEXT::ERROR
@@ -424,10 +1034,9 @@ pub unsafe fn create_debug_callback(
         )
         .pfn_user_callback(Some(debug_callback));
 
-    let debug_utils_loader = ext::DebugUtils::new(entry, instance);
-    let debug_call_back = debug_utils_loader
+    let debug_call_back = debug_utils
         .create_debug_utils_messenger(&debug_info, None)
-        .map_err(|e| format!("create debug utils messenger: {:?}", e))?;
+        .context("create debug utils messenger")?;
 
-    Ok((debug_utils_loader, debug_call_back))
+    Ok(debug_call_back)
 }
@@ -0,0 +1,75 @@
+use ash::vk;
+
+use super::buffer::Buffer;
+use super::pipeline::Pipeline;
+
+/// Records draw commands into a command buffer without exposing it.
+///
+/// The encoder only accepts resources wrapped by this crate, which makes it
+/// suitable to hand over to code that lives outside of the renderer.
+pub struct CommandEncoder<'a> {
+    device: &'a ash::Device,
+    command_buffer: vk::CommandBuffer,
+}
+
+impl<'a> CommandEncoder<'a> {
+    /// # Safety
+    /// The command buffer must be in the recording state, inside a render
+    /// pass, for the whole lifetime of the encoder.
+    pub unsafe fn new(device: &'a ash::Device, command_buffer: vk::CommandBuffer) -> Self {
+        Self {
+            device,
+            command_buffer,
+        }
+    }
+
+    pub fn bind_pipeline(&mut self, pipeline: &Pipeline) {
+        unsafe {
+            self.device.cmd_bind_pipeline(
+                self.command_buffer,
+                vk::PipelineBindPoint::GRAPHICS,
+                pipeline.handle,
+            );
+        }
+    }
+
+    pub fn bind_vertex_buffer(&mut self, buffer: &Buffer) {
+        unsafe {
+            self.device
+                .cmd_bind_vertex_buffers(self.command_buffer, 0, &[**buffer], &[0]);
+        }
+    }
+
+    /// Binds an index buffer holding u32 indices.
+    pub fn bind_index_buffer(&mut self, buffer: &Buffer) {
+        unsafe {
+            self.device.cmd_bind_index_buffer(
+                self.command_buffer,
+                **buffer,
+                0,
+                vk::IndexType::UINT32,
+            );
+        }
+    }
+
+    pub fn set_scissor(&mut self, scissor: vk::Rect2D) {
+        unsafe {
+            self.device
+                .cmd_set_scissor(self.command_buffer, 0, &[scissor]);
+        }
+    }
+
+    pub fn draw(&mut self, vertex_count: u32, instance_count: u32) {
+        unsafe {
+            self.device
+                .cmd_draw(self.command_buffer, vertex_count, instance_count, 0, 0);
+        }
+    }
+
+    pub fn draw_indexed(&mut self, index_count: u32, instance_count: u32) {
+        unsafe {
+            self.device
+                .cmd_draw_indexed(self.command_buffer, index_count, instance_count, 0, 0, 0);
+        }
+    }
+}
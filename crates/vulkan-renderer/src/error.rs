@@ -0,0 +1,143 @@
+use std::{error, fmt, io, result};
+
+use ash::vk;
+
+pub type Result<T> = result::Result<T, RendererError>;
+
+/// Errors returned by the Vulkan renderer.
+///
+/// Vulkan failures keep their `vk::Result` code, even when wrapped in context,
+/// so that callers can react to specific failures such as a lost device.
+#[derive(Debug)]
+pub enum RendererError {
+    /// A Vulkan command failed.
+    Vulkan(vk::Result),
+
+    /// Reading a file (e.g. a SPIR-V module) failed.
+    Io(io::Error),
+
+    /// The number of frames in flight is 0 or exceeds the number of swapchain
+    /// images.
+    InvalidFramesInFlight { requested: u32, image_count: u32 },
+
+    /// Drawing or ending a frame that has not been started.
+    FrameNotStarted,
+
+    /// Any other failure, described by a message.
+    Other(String),
+
+    /// Describes what was being done when the source error happened.
+    Context {
+        context: &'static str,
+        source: Box<RendererError>,
+    },
+}
+
+impl RendererError {
+    /// Returns the Vulkan result code at the root of this error, if any.
+    pub fn vk_result(&self) -> Option<vk::Result> {
+        match self {
+            Self::Vulkan(result) => Some(*result),
+            Self::Context { source, .. } => source.vk_result(),
+            _ => None,
+        }
+    }
+
+    /// The logical device has been lost and must be recreated.
+    pub fn is_device_lost(&self) -> bool {
+        self.vk_result() == Some(vk::Result::ERROR_DEVICE_LOST)
+    }
+
+    /// The surface is no longer available and must be recreated.
+    pub fn is_surface_lost(&self) -> bool {
+        self.vk_result() == Some(vk::Result::ERROR_SURFACE_LOST_KHR)
+    }
+}
+
+impl fmt::Display for RendererError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Vulkan(result) => write!(f, "{result:?}"),
+            Self::Io(e) => write!(f, "{e}"),
+            Self::InvalidFramesInFlight {
+                requested,
+                image_count,
+            } => write!(
+                f,
+                "invalid number of frames in flight: {requested} requested, must be between 1 and {image_count}"
+            ),
+            Self::FrameNotStarted => write!(f, "frame has not been started"),
+            Self::Other(message) => write!(f, "{message}"),
+            Self::Context { context, source } => write!(f, "{context}: {source}"),
+        }
+    }
+}
+
+impl error::Error for RendererError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Self::Vulkan(result) => Some(result),
+            Self::Io(e) => Some(e),
+            Self::Context { source, .. } => Some(source.as_ref()),
+            _ => None,
+        }
+    }
+}
+
+impl From<vk::Result> for RendererError {
+    fn from(result: vk::Result) -> Self {
+        Self::Vulkan(result)
+    }
+}
+
+impl From<io::Error> for RendererError {
+    fn from(e: io::Error) -> Self {
+        Self::Io(e)
+    }
+}
+
+impl From<&str> for RendererError {
+    fn from(message: &str) -> Self {
+        Self::Other(message.to_owned())
+    }
+}
+
+impl From<String> for RendererError {
+    fn from(message: String) -> Self {
+        Self::Other(message)
+    }
+}
+
+/// Adds context to the error of a result, replacing the
+/// `map_err(|e| format!("...: {:?}", e))` pattern while keeping the source.
+pub trait ResultExt<T> {
+    fn context(self, context: &'static str) -> Result<T>;
+}
+
+impl<T, E: Into<RendererError>> ResultExt<T> for result::Result<T, E> {
+    fn context(self, context: &'static str) -> Result<T> {
+        self.map_err(|e| RendererError::Context {
+            context,
+            source: Box::new(e.into()),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn context_keeps_vk_result() {
+        let result: result::Result<(), vk::Result> = Err(vk::Result::ERROR_DEVICE_LOST);
+        let e = result
+            .context("queue submit")
+            .context("immediate submit")
+            .unwrap_err();
+        assert!(e.is_device_lost());
+        assert_eq!(
+            e.to_string(),
+            "immediate submit: queue submit: ERROR_DEVICE_LOST"
+        );
+    }
+}
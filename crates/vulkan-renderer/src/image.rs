@@ -4,58 +4,53 @@ use std::ops::Deref;
 use ash::util::Align;
 use ash::vk;
 
+use super::allocator::Allocation;
 use super::buffer::Buffer;
+use super::deletion::{DeletionQueueHandle, Resource};
 use super::device::Device;
-use super::find_memorytype_index;
-use super::renderer::{copy_buffer_to_image, transition_image_layout};
+use super::renderer::{copy_buffer_to_image, single_time_command, transition_image_layout};
+use super::staging::StagingRing;
+use super::sync::{cmd_image_barriers, ImageBarrier};
+use crate::error::ResultExt;
 use crate::Result;
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Debug)]
 pub struct Image {
     create_info: vk::ImageCreateInfo,
     handle: vk::Image,
 
-    memory: vk::DeviceMemory,
+    allocation: Allocation,
     #[allow(unused)]
     memory_requirements: vk::MemoryRequirements,
 
-    destroyed: bool,
+    deletion_queue: DeletionQueueHandle,
 }
 
 impl Image {
     pub unsafe fn new(
-        device: &ash::Device,
-        device_memory_properties: &vk::PhysicalDeviceMemoryProperties,
+        device: &Device,
         create_info: vk::ImageCreateInfo,
         properties: vk::MemoryPropertyFlags,
     ) -> Result<Self> {
         let image = device
             .create_image(&create_info, None)
-            .map_err(|e| format!("create image: {:?}", e))?;
+            .context("create image")?;
 
         // allocate memory for the image
         let image_memory_req = device.get_image_memory_requirements(image);
-        let image_memory_index =
-            find_memorytype_index(&image_memory_req, device_memory_properties, properties)
-                .ok_or("unable to find suitable memorytype for the image")?;
-        let allocate_info = vk::MemoryAllocateInfo {
-            allocation_size: image_memory_req.size,
-            memory_type_index: image_memory_index,
-            ..Default::default()
-        };
-        let image_memory = device
-            .allocate_memory(&allocate_info, None)
-            .map_err(|e| format!("allocate image memory: {:?}", e))?;
+        let allocation = device
+            .allocate_memory(image_memory_req, properties)
+            .context("allocate image memory")?;
         device
-            .bind_image_memory(image, image_memory, 0)
-            .map_err(|e| format!("bind image memory: {:?}", e))?;
+            .bind_image_memory(image, allocation.memory(), allocation.offset())
+            .context("bind image memory")?;
 
         Ok(Self {
             create_info,
             handle: image,
-            memory: image_memory,
+            allocation,
             memory_requirements: image_memory_req,
-            destroyed: false,
+            deletion_queue: device.deletion_queue(),
         })
     }
 
@@ -65,19 +60,18 @@ impl Image {
         command_pool: vk::CommandPool,
         data: &[T],
     ) -> Result<()> {
-        let mut staging_buffer = {
+        let staging_buffer = {
             let staging_buffer_size = mem::size_of_val(data) as u64;
             let mut staging_buffer = Buffer::new(
                 device,
-                device.memory_properties(),
                 vk::BufferUsageFlags::TRANSFER_SRC,
                 vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT,
                 staging_buffer_size,
             )
-            .map_err(|e| format!("create staging buffer: {:?}", e))?;
+            .context("create staging buffer")?;
             staging_buffer
-                .update(device, data)
-                .map_err(|e| format!("update staging buffer: {:?}", e))?;
+                .update(data)
+                .context("update staging buffer")?;
             staging_buffer
         };
 
@@ -88,7 +82,7 @@ impl Image {
             vk::ImageLayout::UNDEFINED,
             vk::ImageLayout::TRANSFER_DST_OPTIMAL,
         )
-        .map_err(|e| format!("transition image layout: {:?}", e))?;
+        .context("transition image layout")?;
 
         copy_buffer_to_image(
             device,
@@ -98,7 +92,7 @@ impl Image {
             self.width(),
             self.height(),
         )
-        .map_err(|e| format!("copy buffer to image: {:?}", e))?;
+        .context("copy buffer to image")?;
 
         transition_image_layout(
             device,
@@ -107,25 +101,141 @@ impl Image {
             vk::ImageLayout::TRANSFER_DST_OPTIMAL,
             vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
         )
-        .map_err(|e| format!("transition image layout: {:?}", e))?;
+        .context("transition image layout")?;
+
+        // NOTE: the staging buffer is dropped here and destroyed once the next
+        //       frame is done, the copy being submitted ahead of the frame commands
+        Ok(())
+    }
+
+    /// Uploads the data of each mip level of a single layer image, e.g.
+    /// blocks of a compressed format, and leaves the image in the
+    /// SHADER_READ_ONLY_OPTIMAL layout.
+    pub unsafe fn upload_gpu_levels(
+        &mut self,
+        device: &Device,
+        command_pool: vk::CommandPool,
+        levels: &[&[u8]],
+    ) -> Result<()> {
+        self.upload_gpu_subresources(device, command_pool, levels)
+    }
+
+    /// Uploads the data of each layer of a single level image, e.g. the faces
+    /// of a cubemap, and leaves the image in the SHADER_READ_ONLY_OPTIMAL
+    /// layout.
+    pub unsafe fn upload_gpu_layers(
+        &mut self,
+        device: &Device,
+        command_pool: vk::CommandPool,
+        layers: &[&[u8]],
+    ) -> Result<()> {
+        self.upload_gpu_subresources(device, command_pool, layers)
+    }
+
+    /// Uploads the data of each mip level of each layer, all the levels of
+    /// the first layer coming first, and leaves the image in the
+    /// SHADER_READ_ONLY_OPTIMAL layout.
+    pub unsafe fn upload_gpu_subresources(
+        &mut self,
+        device: &Device,
+        command_pool: vk::CommandPool,
+        subresources: &[&[u8]],
+    ) -> Result<()> {
+        let mip_levels = self.mip_levels();
+        if subresources.len() != (mip_levels * self.array_layers()) as usize {
+            return Err("one subresource of data is required per mip level and layer".into());
+        }
+
+        // NOTE: subresource sizes are multiples of the texel block size, which
+        //       keeps the offsets aligned as required by the copies
+        let mut offsets = Vec::with_capacity(subresources.len());
+        let mut size = 0;
+        for subresource in subresources {
+            offsets.push(size);
+            size += subresource.len() as u64;
+        }
+        let mut staging_buffer = Buffer::new(
+            device,
+            vk::BufferUsageFlags::TRANSFER_SRC,
+            vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT,
+            size,
+        )
+        .context("create staging buffer")?;
+        for (subresource, offset) in subresources.iter().zip(&offsets) {
+            staging_buffer
+                .update_at(*offset, subresource)
+                .context("update staging buffer")?;
+        }
 
-        device.device_wait_idle().expect("device wait idle");
-        staging_buffer.destroy(device);
+        let regions: Vec<_> = offsets
+            .iter()
+            .zip(0..)
+            .map(|(offset, index)| {
+                let mip_level = index % mip_levels;
+                vk::BufferImageCopy {
+                    buffer_offset: *offset,
+                    buffer_row_length: 0,
+                    buffer_image_height: 0,
+                    image_subresource: vk::ImageSubresourceLayers {
+                        aspect_mask: vk::ImageAspectFlags::COLOR,
+                        mip_level,
+                        base_array_layer: index / mip_levels,
+                        layer_count: 1,
+                    },
+                    image_offset: vk::Offset3D::default(),
+                    image_extent: vk::Extent3D {
+                        width: (self.width() >> mip_level).max(1),
+                        height: (self.height() >> mip_level).max(1),
+                        depth: 1,
+                    },
+                }
+            })
+            .collect();
+        single_time_command(device, command_pool, |_, command_buffer| {
+            cmd_image_barriers(
+                device,
+                command_buffer,
+                &[ImageBarrier::undefined_to_transfer_dst(self.handle)],
+            );
+            device.cmd_copy_buffer_to_image(
+                command_buffer,
+                *staging_buffer,
+                self.handle,
+                vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                &regions,
+            );
+            cmd_image_barriers(
+                device,
+                command_buffer,
+                &[ImageBarrier::transfer_to_shader_read(self.handle)],
+            );
+        })
+        .context("copy buffer to image levels")?;
 
+        // NOTE: the staging buffer is dropped here and destroyed once the next
+        //       frame is done, the copy being submitted ahead of the frame commands
         Ok(())
     }
 
+    /// Uploads data to the image through the staging ring. Unlike
+    /// `upload_gpu()`, this does not submit commands of its own, which makes
+    /// it suitable for updates made during a frame.
+    pub unsafe fn update_staged<T: Copy>(
+        &self,
+        staging: &mut StagingRing,
+        data: &[T],
+    ) -> Result<()> {
+        staging.copy_to_image(data, self.handle, self.width(), self.height())
+    }
+
     #[allow(unused)]
-    pub unsafe fn update<T: Copy>(&mut self, device: &ash::Device, data: &[T]) -> Result<()> {
+    pub unsafe fn update<T: Copy>(&mut self, data: &[T]) -> Result<()> {
         // obtain pointer into data
-        let buffer_ptr: *mut std::os::raw::c_void = device
-            .map_memory(
-                self.memory,
-                0,
-                self.memory_requirements.size,
-                vk::MemoryMapFlags::empty(),
-            )
-            .map_err(|e| format!("map buffer memory: {:?}", e))?;
+        // NOTE: host visible memory is persistently mapped by the allocator
+        let buffer_ptr = self
+            .allocation
+            .mapped_ptr()
+            .ok_or("image memory is not host visible")?;
         let mut slice = Align::new(
             buffer_ptr,
             align_of::<T>() as u64,
@@ -134,7 +244,6 @@ impl Image {
 
         // copy data into buffer
         slice.copy_from_slice(data);
-        device.unmap_memory(self.memory);
 
         Ok(())
     }
@@ -155,12 +264,23 @@ impl Image {
         self.create_info.extent.height
     }
 
+    pub fn mip_levels(&self) -> u32 {
+        self.create_info.mip_levels
+    }
+
+    pub fn array_layers(&self) -> u32 {
+        self.create_info.array_layers
+    }
+
+    /// Creates a view of all the mip levels of the image. Array and cube views
+    /// cover all the layers, other views the first one.
     pub unsafe fn create_view(
         &self,
         device: &ash::Device,
         view_type: vk::ImageViewType,
         aspect_mask: vk::ImageAspectFlags,
     ) -> Result<vk::ImageView> {
+        let layer_count = view_layer_count(view_type, &self.create_info)?;
         let image_view_info = vk::ImageViewCreateInfo {
             view_type,
             format: *self.format(),
@@ -172,8 +292,8 @@ impl Image {
             },
             subresource_range: vk::ImageSubresourceRange {
                 aspect_mask,
-                level_count: 1,
-                layer_count: 1,
+                level_count: self.mip_levels(),
+                layer_count,
                 ..Default::default()
             },
             image: *self.image(),
@@ -181,18 +301,43 @@ impl Image {
         };
         let image_view = device
             .create_image_view(&image_view_info, None)
-            .map_err(|e| format!("create image view: {:?}", e))?;
+            .context("create image view")?;
 
         Ok(image_view)
     }
+}
 
-    pub unsafe fn destroy(&mut self, device: &ash::Device) {
-        if self.destroyed {
-            panic!("image already destroyed")
+/// Returns the number of layers of the image covered by a view, checking
+/// that the image can be viewed as such.
+fn view_layer_count(
+    view_type: vk::ImageViewType,
+    create_info: &vk::ImageCreateInfo,
+) -> Result<u32> {
+    let layers = create_info.array_layers;
+    let cube_compatible = create_info
+        .flags
+        .contains(vk::ImageCreateFlags::CUBE_COMPATIBLE);
+    match view_type {
+        vk::ImageViewType::CUBE | vk::ImageViewType::CUBE_ARRAY if !cube_compatible => {
+            Err("cube views require a CUBE_COMPATIBLE image".into())
         }
-        device.free_memory(self.memory, None);
-        device.destroy_image(self.handle, None);
-        self.destroyed = true;
+        vk::ImageViewType::CUBE if layers != 6 => Err("cube views require 6 layers".into()),
+        vk::ImageViewType::CUBE_ARRAY if layers % 6 != 0 => {
+            Err("cube array views require a multiple of 6 layers".into())
+        }
+        vk::ImageViewType::CUBE
+        | vk::ImageViewType::CUBE_ARRAY
+        | vk::ImageViewType::TYPE_1D_ARRAY
+        | vk::ImageViewType::TYPE_2D_ARRAY => Ok(layers),
+        _ => Ok(1),
+    }
+}
+
+impl Drop for Image {
+    fn drop(&mut self) {
+        self.deletion_queue
+            .borrow_mut()
+            .push(Resource::Image(self.handle, self.allocation));
     }
 }
 
@@ -203,3 +348,35 @@ impl Deref for Image {
         &self.handle
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn views_cover_the_layers_of_their_type() {
+        let layered = vk::ImageCreateInfo {
+            array_layers: 4,
+            ..Default::default()
+        };
+        let view_layers = |view_type| view_layer_count(view_type, &layered).ok();
+        assert_eq!(view_layers(vk::ImageViewType::TYPE_2D), Some(1));
+        assert_eq!(view_layers(vk::ImageViewType::TYPE_2D_ARRAY), Some(4));
+        assert_eq!(view_layers(vk::ImageViewType::CUBE), None);
+
+        let cube = vk::ImageCreateInfo {
+            flags: vk::ImageCreateFlags::CUBE_COMPATIBLE,
+            array_layers: 6,
+            ..Default::default()
+        };
+        assert_eq!(
+            view_layer_count(vk::ImageViewType::CUBE, &cube).ok(),
+            Some(6)
+        );
+        let cube = vk::ImageCreateInfo {
+            array_layers: 4,
+            ..cube
+        };
+        assert!(view_layer_count(vk::ImageViewType::CUBE, &cube).is_err());
+    }
+}
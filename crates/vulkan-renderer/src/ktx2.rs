@@ -0,0 +1,234 @@
+use ash::vk;
+
+use super::device::Device;
+use super::image::Image;
+use crate::error::ResultExt;
+use crate::Result;
+
+/// Bytes every KTX2 file starts with.
+const IDENTIFIER: [u8; 12] = [
+    0xAB, 0x4B, 0x54, 0x58, 0x20, 0x32, 0x30, 0xBB, 0x0D, 0x0A, 0x1A, 0x0A,
+];
+
+/// Size of the header and of the index following it, up to the level index.
+const HEADER_SIZE: usize = 80;
+
+/// Size of an entry of the level index.
+const LEVEL_INDEX_SIZE: usize = 24;
+
+/// Block compressed texture read from a KTX2 container.
+///
+/// Only BC1, BC3 and BC7 2D textures without supercompression are supported.
+/// Their blocks are uploaded as is, so that they stay compressed in memory.
+#[derive(Debug)]
+pub struct Ktx2<'a> {
+    format: vk::Format,
+    width: u32,
+    height: u32,
+    /// Data of each mip level, largest first.
+    levels: Vec<&'a [u8]>,
+}
+
+impl<'a> Ktx2<'a> {
+    /// Reads the header of a KTX2 file and checks that its levels hold the
+    /// expected number of blocks.
+    pub fn parse(bytes: &'a [u8]) -> Result<Self> {
+        if bytes.len() < HEADER_SIZE || bytes[..IDENTIFIER.len()] != IDENTIFIER {
+            return Err("not a KTX2 file".into());
+        }
+        let format = vk::Format::from_raw(read_u32(bytes, 12) as i32);
+        let width = read_u32(bytes, 20);
+        let height = read_u32(bytes, 24);
+        let depth = read_u32(bytes, 28);
+        let layer_count = read_u32(bytes, 32);
+        let face_count = read_u32(bytes, 36);
+        // NOTE: 0 asks the loader to generate the mip levels, only the base
+        //       level is stored
+        let level_count = read_u32(bytes, 40).max(1);
+        let supercompression = read_u32(bytes, 44);
+
+        let block_size =
+            block_size(format).ok_or_else(|| format!("unsupported KTX2 format {format:?}"))?;
+        if width == 0 || height == 0 || depth != 0 || layer_count != 0 || face_count != 1 {
+            return Err("only 2D KTX2 textures are supported".into());
+        }
+        if supercompression != 0 {
+            return Err("supercompressed KTX2 files are not supported".into());
+        }
+        if level_count > 32 - width.max(height).leading_zeros() {
+            return Err("too many KTX2 mip levels".into());
+        }
+
+        let levels = (0..level_count)
+            .map(|level| {
+                let index = HEADER_SIZE + level as usize * LEVEL_INDEX_SIZE;
+                if index + LEVEL_INDEX_SIZE > bytes.len() {
+                    return Err("truncated KTX2 level index".into());
+                }
+                let offset = read_u64(bytes, index) as usize;
+                let length = read_u64(bytes, index + 8) as usize;
+                let expected = level_size(width >> level, height >> level, block_size);
+                if length != expected {
+                    return Err(format!(
+                        "KTX2 level {level} holds {length} bytes instead of {expected}"
+                    )
+                    .into());
+                }
+                bytes
+                    .get(offset..offset.saturating_add(length))
+                    .ok_or_else(|| format!("truncated KTX2 level {level}").into())
+            })
+            .collect::<Result<_>>()?;
+
+        Ok(Self {
+            format,
+            width,
+            height,
+            levels,
+        })
+    }
+
+    pub fn format(&self) -> vk::Format {
+        self.format
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    pub fn mip_levels(&self) -> u32 {
+        self.levels.len() as u32
+    }
+
+    /// Creates a device local image holding the levels of the texture, in the
+    /// SHADER_READ_ONLY_OPTIMAL layout. Fails if the device can not sample
+    /// images of its format.
+    pub unsafe fn create_image(
+        &self,
+        device: &Device,
+        command_pool: vk::CommandPool,
+    ) -> Result<Image> {
+        let features = vk::FormatFeatureFlags::SAMPLED_IMAGE
+            | vk::FormatFeatureFlags::SAMPLED_IMAGE_FILTER_LINEAR
+            | vk::FormatFeatureFlags::TRANSFER_DST;
+        if !device.supports_format(self.format, features) {
+            return Err(format!("format {:?} is not supported by the device", self.format).into());
+        }
+
+        let create_info = vk::ImageCreateInfo::builder()
+            .image_type(vk::ImageType::TYPE_2D)
+            .format(self.format)
+            .extent(vk::Extent3D {
+                width: self.width,
+                height: self.height,
+                depth: 1,
+            })
+            .mip_levels(self.mip_levels())
+            .array_layers(1)
+            .samples(vk::SampleCountFlags::TYPE_1)
+            .tiling(vk::ImageTiling::OPTIMAL)
+            .usage(vk::ImageUsageFlags::SAMPLED | vk::ImageUsageFlags::TRANSFER_DST)
+            .sharing_mode(vk::SharingMode::EXCLUSIVE)
+            .initial_layout(vk::ImageLayout::UNDEFINED);
+        let mut image = Image::new(device, *create_info, vk::MemoryPropertyFlags::DEVICE_LOCAL)
+            .context("create compressed image")?;
+        image
+            .upload_gpu_levels(device, command_pool, &self.levels)
+            .context("upload compressed image")?;
+
+        Ok(image)
+    }
+}
+
+/// Returns the size in bytes of a 4x4 block of the supported formats.
+fn block_size(format: vk::Format) -> Option<usize> {
+    match format {
+        vk::Format::BC1_RGB_UNORM_BLOCK
+        | vk::Format::BC1_RGB_SRGB_BLOCK
+        | vk::Format::BC1_RGBA_UNORM_BLOCK
+        | vk::Format::BC1_RGBA_SRGB_BLOCK => Some(8),
+        vk::Format::BC3_UNORM_BLOCK
+        | vk::Format::BC3_SRGB_BLOCK
+        | vk::Format::BC7_UNORM_BLOCK
+        | vk::Format::BC7_SRGB_BLOCK => Some(16),
+        _ => None,
+    }
+}
+
+/// Returns the size in bytes of a level, made of whole blocks.
+fn level_size(width: u32, height: u32, block_size: usize) -> usize {
+    let blocks = |size: u32| (size.max(1) as usize + 3) / 4;
+    blocks(width) * blocks(height) * block_size
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> u32 {
+    let mut value = [0; 4];
+    value.copy_from_slice(&bytes[offset..offset + 4]);
+    u32::from_le_bytes(value)
+}
+
+fn read_u64(bytes: &[u8], offset: usize) -> u64 {
+    let mut value = [0; 8];
+    value.copy_from_slice(&bytes[offset..offset + 8]);
+    u64::from_le_bytes(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Writes a KTX2 file whose levels are stored smallest first, as
+    /// recommended by the specification.
+    fn ktx2(format: vk::Format, width: u32, height: u32, levels: &[Vec<u8>]) -> Vec<u8> {
+        let mut bytes = IDENTIFIER.to_vec();
+        let fields = [format.as_raw() as u32, 1, width, height, 0, 0, 1];
+        for field in fields.into_iter().chain([levels.len() as u32, 0]) {
+            bytes.extend(field.to_le_bytes());
+        }
+        bytes.resize(HEADER_SIZE + levels.len() * LEVEL_INDEX_SIZE, 0);
+        let mut index = Vec::new();
+        for level in levels.iter().rev() {
+            index.push((bytes.len() as u64, level.len() as u64));
+            bytes.extend(level);
+        }
+        for (level, (offset, length)) in index.into_iter().rev().enumerate() {
+            let entry = HEADER_SIZE + level * LEVEL_INDEX_SIZE;
+            bytes[entry..entry + 8].copy_from_slice(&offset.to_le_bytes());
+            bytes[entry + 8..entry + 16].copy_from_slice(&length.to_le_bytes());
+            bytes[entry + 16..entry + 24].copy_from_slice(&length.to_le_bytes());
+        }
+        bytes
+    }
+
+    #[test]
+    fn levels_are_read_largest_first() {
+        // 6x6 is 2x2 blocks, then 3x3 and 1x1 are 1 block each
+        let levels = [vec![1; 64], vec![2; 16], vec![3; 16]];
+        let bytes = ktx2(vk::Format::BC7_SRGB_BLOCK, 6, 6, &levels);
+
+        let texture = Ktx2::parse(&bytes).unwrap();
+        assert_eq!(texture.format(), vk::Format::BC7_SRGB_BLOCK);
+        assert_eq!((texture.width(), texture.height()), (6, 6));
+        assert_eq!(texture.levels, [&levels[0][..], &levels[1], &levels[2]]);
+    }
+
+    #[test]
+    fn invalid_files_are_rejected() {
+        let bytes = ktx2(vk::Format::R8G8B8A8_UNORM, 4, 4, &[vec![0; 64]]);
+        assert!(Ktx2::parse(&bytes).is_err());
+
+        // BC1 blocks are 8 bytes
+        let bytes = ktx2(vk::Format::BC1_RGB_UNORM_BLOCK, 4, 4, &[vec![0; 16]]);
+        assert!(Ktx2::parse(&bytes).is_err());
+
+        let mut bytes = ktx2(vk::Format::BC3_UNORM_BLOCK, 4, 4, &[vec![0; 16]]);
+        bytes.truncate(bytes.len() - 1);
+        assert!(Ktx2::parse(&bytes).is_err());
+
+        assert!(Ktx2::parse(b"not a texture").is_err());
+    }
+}
@@ -1,22 +1,32 @@
 #![allow(clippy::missing_safety_doc)]
 
 /// Vulkan backend package.
+pub mod allocator;
+pub mod bindless;
 pub mod buffer;
+pub mod deletion;
 pub mod descriptor;
 pub mod device;
+pub mod encoder;
+pub mod error;
 pub mod image;
+pub mod ktx2;
 pub mod pipeline;
+pub mod profiler;
+pub mod render_target;
 pub mod renderer;
 pub mod renderpass;
+mod screenshot;
 pub mod shader;
+pub mod staging;
 pub mod swapchain;
+pub mod sync;
 pub mod texture;
-
-use std::{error, result};
+pub mod window_target;
 
 use ash::vk;
 
-type Result<T> = result::Result<T, Box<dyn error::Error>>;
+use error::Result;
 
 fn find_memorytype_index(
     memory_req: &vk::MemoryRequirements,
@@ -3,7 +3,11 @@ use std::ops::Deref;
 
 use ash::vk;
 
-use super::{descriptor::DescriptorSetLayout, shader::Shader};
+use super::deletion::{DeletionQueueHandle, Resource};
+use super::descriptor::DescriptorSetLayout;
+use super::device::Device;
+use super::shader::{Shader, Specialization};
+use crate::error::ResultExt;
 use crate::Result;
 
 // Simple offset_of macro akin to C++ offsetof
@@ -18,7 +22,58 @@ macro_rules! offset_of {
     }};
 }
 
-#[derive(Clone, Copy, Debug, PartialEq, PartialOrd)]
+/// Depth and color state of a graphics pipeline.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PipelineState {
+    /// Writes the depth of the fragments passing the depth test.
+    pub depth_write: bool,
+    pub depth_compare: vk::CompareOp,
+    /// Writes the color of the fragments, blended using their alpha.
+    pub color_write: bool,
+}
+
+impl PipelineState {
+    /// Geometry drawn in order, hiding what is drawn before it at the same
+    /// depth.
+    pub const OPAQUE: Self = Self {
+        depth_write: true,
+        depth_compare: vk::CompareOp::LESS_OR_EQUAL,
+        color_write: true,
+    };
+
+    /// Geometry blended over what is behind it, drawn back to front after the
+    /// opaque geometry. It does not hide what is drawn after it and is hidden
+    /// by opaque geometry at the same depth.
+    pub const TRANSPARENT: Self = Self {
+        depth_write: false,
+        depth_compare: vk::CompareOp::LESS,
+        color_write: true,
+    };
+
+    /// Depth pre-pass writing the depth of opaque geometry only, so that
+    /// hidden fragments are not shaded when it is drawn again.
+    pub const DEPTH_ONLY: Self = Self {
+        depth_write: true,
+        depth_compare: vk::CompareOp::LESS_OR_EQUAL,
+        color_write: false,
+    };
+
+    /// Geometry blended over everything drawn before it, whatever its depth,
+    /// e.g. full screen effects. It does not write depth.
+    pub const OVERLAY: Self = Self {
+        depth_write: false,
+        depth_compare: vk::CompareOp::ALWAYS,
+        color_write: true,
+    };
+}
+
+impl Default for PipelineState {
+    fn default() -> Self {
+        Self::OPAQUE
+    }
+}
+
+#[derive(Debug)]
 pub struct Pipeline {
     pub handle: vk::Pipeline,
     /// Access to descriptor sets from a pipeline is accomplished through a
@@ -31,21 +86,154 @@ pub struct Pipeline {
     /// pipeline is created using a pipeline layout.
     pub layout: vk::PipelineLayout,
 
-    destroyed: bool,
+    deletion_queue: DeletionQueueHandle,
 }
 
 impl Pipeline {
     #[allow(clippy::too_many_arguments)]
     pub unsafe fn new(
-        device: &ash::Device,
+        device: &Device,
+        renderpass: &vk::RenderPass,
+        vertex_shader: &Shader,
+        fragment_shader: &Shader,
+        vertex_input_binding_descriptions: &[vk::VertexInputBindingDescription],
+        vertex_input_attribute_descriptions: &[vk::VertexInputAttributeDescription],
+        descriptor_set_layouts: &[DescriptorSetLayout],
+    ) -> Result<Self> {
+        Self::new_specialized(
+            device,
+            renderpass,
+            vertex_shader,
+            fragment_shader,
+            &Specialization::default(),
+            vertex_input_binding_descriptions,
+            vertex_input_attribute_descriptions,
+            descriptor_set_layouts,
+        )
+    }
+
+    /// Creates a graphics pipeline, setting the specialization constants of
+    /// both shaders. Constants a shader does not declare are ignored.
+    #[allow(clippy::too_many_arguments)]
+    pub unsafe fn new_specialized(
+        device: &Device,
         renderpass: &vk::RenderPass,
         vertex_shader: &Shader,
         fragment_shader: &Shader,
+        specialization: &Specialization,
         vertex_input_binding_descriptions: &[vk::VertexInputBindingDescription],
         vertex_input_attribute_descriptions: &[vk::VertexInputAttributeDescription],
         descriptor_set_layouts: &[DescriptorSetLayout],
+    ) -> Result<Self> {
+        Self::new_with_state(
+            device,
+            renderpass,
+            vertex_shader,
+            fragment_shader,
+            specialization,
+            PipelineState::default(),
+            vertex_input_binding_descriptions,
+            vertex_input_attribute_descriptions,
+            descriptor_set_layouts,
+        )
+    }
+
+    /// Like `new_specialized()`, using the given depth and color state.
+    #[allow(clippy::too_many_arguments)]
+    pub unsafe fn new_with_state(
+        device: &Device,
+        renderpass: &vk::RenderPass,
+        vertex_shader: &Shader,
+        fragment_shader: &Shader,
+        specialization: &Specialization,
+        state: PipelineState,
+        vertex_input_binding_descriptions: &[vk::VertexInputBindingDescription],
+        vertex_input_attribute_descriptions: &[vk::VertexInputAttributeDescription],
+        descriptor_set_layouts: &[DescriptorSetLayout],
+    ) -> Result<Self> {
+        Self::graphics(
+            device,
+            renderpass,
+            vertex_shader,
+            fragment_shader,
+            specialization,
+            state,
+            vertex_input_binding_descriptions,
+            vertex_input_attribute_descriptions,
+            descriptor_set_layouts,
+            &[],
+        )
+    }
+
+    /// Like `new()`, with push constants given to the shaders, see
+    /// `cmd_push_constants()`.
+    #[allow(clippy::too_many_arguments)]
+    pub unsafe fn new_with_push_constants(
+        device: &Device,
+        renderpass: &vk::RenderPass,
+        vertex_shader: &Shader,
+        fragment_shader: &Shader,
+        vertex_input_binding_descriptions: &[vk::VertexInputBindingDescription],
+        vertex_input_attribute_descriptions: &[vk::VertexInputAttributeDescription],
+        descriptor_set_layouts: &[DescriptorSetLayout],
+        push_constant_ranges: &[vk::PushConstantRange],
+    ) -> Result<Self> {
+        Self::graphics(
+            device,
+            renderpass,
+            vertex_shader,
+            fragment_shader,
+            &Specialization::default(),
+            PipelineState::default(),
+            vertex_input_binding_descriptions,
+            vertex_input_attribute_descriptions,
+            descriptor_set_layouts,
+            push_constant_ranges,
+        )
+    }
+
+    /// Like `new_with_state()`, with push constants given to the shaders.
+    #[allow(clippy::too_many_arguments)]
+    pub unsafe fn new_with_state_and_push_constants(
+        device: &Device,
+        renderpass: &vk::RenderPass,
+        vertex_shader: &Shader,
+        fragment_shader: &Shader,
+        state: PipelineState,
+        vertex_input_binding_descriptions: &[vk::VertexInputBindingDescription],
+        vertex_input_attribute_descriptions: &[vk::VertexInputAttributeDescription],
+        descriptor_set_layouts: &[DescriptorSetLayout],
+        push_constant_ranges: &[vk::PushConstantRange],
+    ) -> Result<Self> {
+        Self::graphics(
+            device,
+            renderpass,
+            vertex_shader,
+            fragment_shader,
+            &Specialization::default(),
+            state,
+            vertex_input_binding_descriptions,
+            vertex_input_attribute_descriptions,
+            descriptor_set_layouts,
+            push_constant_ranges,
+        )
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    unsafe fn graphics(
+        device: &Device,
+        renderpass: &vk::RenderPass,
+        vertex_shader: &Shader,
+        fragment_shader: &Shader,
+        specialization: &Specialization,
+        state: PipelineState,
+        vertex_input_binding_descriptions: &[vk::VertexInputBindingDescription],
+        vertex_input_attribute_descriptions: &[vk::VertexInputAttributeDescription],
+        descriptor_set_layouts: &[DescriptorSetLayout],
+        push_constant_ranges: &[vk::PushConstantRange],
     ) -> Result<Self> {
         // shaders
+        let specialization_info = specialization.info();
         let shader_stage_create_infos = {
             let shader_entry_name = CStr::from_bytes_with_nul_unchecked(b"main\0");
             [
@@ -53,11 +241,13 @@ impl Pipeline {
                     .module(vertex_shader.handle)
                     .name(shader_entry_name)
                     .stage(vk::ShaderStageFlags::VERTEX)
+                    .specialization_info(&specialization_info)
                     .build(),
                 vk::PipelineShaderStageCreateInfo::builder()
                     .module(fragment_shader.handle)
                     .name(shader_entry_name)
                     .stage(vk::ShaderStageFlags::FRAGMENT)
+                    .specialization_info(&specialization_info)
                     .build(),
             ]
         };
@@ -91,10 +281,15 @@ impl Pipeline {
         // depth stencil
         let depth_state_info = vk::PipelineDepthStencilStateCreateInfo::builder()
             .depth_test_enable(true)
-            .depth_write_enable(true)
-            .depth_compare_op(vk::CompareOp::LESS_OR_EQUAL);
+            .depth_write_enable(state.depth_write)
+            .depth_compare_op(state.depth_compare);
 
         // color blending
+        let color_write_mask = if state.color_write {
+            vk::ColorComponentFlags::RGBA
+        } else {
+            vk::ColorComponentFlags::empty()
+        };
         let color_blend_attachment_states = [vk::PipelineColorBlendAttachmentState::builder()
             .blend_enable(true)
             .src_color_blend_factor(vk::BlendFactor::SRC_ALPHA)
@@ -103,7 +298,7 @@ impl Pipeline {
             .src_alpha_blend_factor(vk::BlendFactor::ONE_MINUS_SRC_ALPHA)
             .dst_alpha_blend_factor(vk::BlendFactor::ZERO)
             .alpha_blend_op(vk::BlendOp::ADD)
-            .color_write_mask(vk::ColorComponentFlags::RGBA)
+            .color_write_mask(color_write_mask)
             .build()];
         let color_blend_state_info = vk::PipelineColorBlendStateCreateInfo::builder()
             .attachments(&color_blend_attachment_states);
@@ -124,10 +319,12 @@ impl Pipeline {
             .iter()
             .map(|d| d.handle)
             .collect::<Vec<_>>();
-        let layout_create_info = vk::PipelineLayoutCreateInfo::builder().set_layouts(&layouts);
+        let layout_create_info = vk::PipelineLayoutCreateInfo::builder()
+            .set_layouts(&layouts)
+            .push_constant_ranges(push_constant_ranges);
         let pipeline_layout = device
             .create_pipeline_layout(&layout_create_info, None)
-            .map_err(|e| format!("create graphics pipeline layout: {:?}", e))?;
+            .context("create graphics pipeline layout")?;
 
         // create pipeline
         let graphic_pipeline_infos = vk::GraphicsPipelineCreateInfo::builder()
@@ -147,22 +344,88 @@ impl Pipeline {
             .build();
         let graphics_pipelines = device
             .create_graphics_pipelines(vk::PipelineCache::null(), &[graphic_pipeline_infos], None)
-            .map_err(|e| format!("create graphics pipeline: {:?}", e))?;
+            .map_err(|(_, e)| e)
+            .context("create graphics pipeline")?;
 
         Ok(Self {
             handle: graphics_pipelines[0],
             layout: pipeline_layout,
-            destroyed: false,
+            deletion_queue: device.deletion_queue(),
         })
     }
+}
 
-    pub unsafe fn destroy(&mut self, device: &ash::Device) {
-        if self.destroyed {
-            panic!("pipeline already destroyed")
-        }
-        device.destroy_pipeline(self.handle, None);
-        device.destroy_pipeline_layout(self.layout, None);
-        self.destroyed = true;
+impl Pipeline {
+    /// Creates a compute pipeline running the `main` entry point of the
+    /// shader.
+    pub unsafe fn compute(
+        device: &Device,
+        shader: &Shader,
+        descriptor_set_layouts: &[DescriptorSetLayout],
+    ) -> Result<Self> {
+        Self::compute_specialized(
+            device,
+            shader,
+            &Specialization::default(),
+            descriptor_set_layouts,
+        )
+    }
+
+    /// Creates a compute pipeline, setting the specialization constants of
+    /// the shader.
+    pub unsafe fn compute_specialized(
+        device: &Device,
+        shader: &Shader,
+        specialization: &Specialization,
+        descriptor_set_layouts: &[DescriptorSetLayout],
+    ) -> Result<Self> {
+        // create pipeline layout
+        let layouts = descriptor_set_layouts
+            .iter()
+            .map(|d| d.handle)
+            .collect::<Vec<_>>();
+        let layout_create_info = vk::PipelineLayoutCreateInfo::builder().set_layouts(&layouts);
+        let pipeline_layout = device
+            .create_pipeline_layout(&layout_create_info, None)
+            .context("create compute pipeline layout")?;
+
+        // create pipeline
+        let shader_entry_name = CStr::from_bytes_with_nul_unchecked(b"main\0");
+        let specialization_info = specialization.info();
+        let stage = vk::PipelineShaderStageCreateInfo::builder()
+            .module(shader.handle)
+            .name(shader_entry_name)
+            .stage(vk::ShaderStageFlags::COMPUTE)
+            .specialization_info(&specialization_info)
+            .build();
+        let compute_pipeline_infos = vk::ComputePipelineCreateInfo::builder()
+            .stage(stage)
+            .layout(pipeline_layout)
+            .build();
+        let compute_pipelines = device
+            .create_compute_pipelines(vk::PipelineCache::null(), &[compute_pipeline_infos], None)
+            .map_err(|(_, e)| e)
+            .context("create compute pipeline")?;
+
+        Ok(Self {
+            handle: compute_pipelines[0],
+            layout: pipeline_layout,
+            deletion_queue: device.deletion_queue(),
+        })
+    }
+
+    /// Names the pipeline and its layout, as shown by debuggers.
+    pub unsafe fn set_name(&self, device: &Device, name: &str) {
+        device.set_object_name(self.handle, name);
+        device.set_object_name(self.layout, name);
+    }
+}
+
+impl Drop for Pipeline {
+    fn drop(&mut self) {
+        self.deletion_queue
+            .borrow_mut()
+            .push(Resource::Pipeline(self.handle, self.layout));
     }
 }
 
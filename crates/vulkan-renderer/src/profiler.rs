@@ -0,0 +1,249 @@
+use std::time;
+
+use ash::vk;
+use log::{debug, warn};
+
+use super::deletion::{DeletionQueueHandle, Resource};
+use super::device::Device;
+use crate::error::ResultExt;
+use crate::Result;
+
+/// Maximum number of scopes measured during a frame. Scopes started past this
+/// limit are ignored.
+pub const MAX_GPU_SCOPES: u32 = 32;
+
+/// Queries used to time the whole frame, ahead of the scope queries.
+const FRAME_QUERIES: u32 = 2;
+const QUERY_COUNT: u32 = FRAME_QUERIES + 2 * MAX_GPU_SCOPES;
+
+/// GPU time spent in a named scope.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct GpuScope {
+    pub name: &'static str,
+    pub duration: time::Duration,
+}
+
+/// Query pool of a frame in flight and the scopes written to it.
+struct FrameQueries {
+    pool: vk::QueryPool,
+    scopes: Vec<&'static str>,
+    /// Set once the frame commands reset and wrote the pool.
+    recorded: bool,
+}
+
+/// Measures the GPU time of named scopes using timestamp queries.
+///
+/// Each frame in flight has its own query pool. Its results are read once the
+/// fence of its frame has been waited on, so that reading them never stalls,
+/// which means results lag behind by the number of frames in flight. The
+/// profiler does nothing if the device does not support timestamps.
+pub struct GpuProfiler {
+    /// Nanoseconds per timestamp tick.
+    timestamp_period: f32,
+
+    frames: Vec<FrameQueries>,
+    frame: usize,
+
+    /// Scopes started and not ended yet, innermost last.
+    open: Vec<u32>,
+
+    frame_time: Option<time::Duration>,
+    results: Vec<GpuScope>,
+
+    deletion_queue: DeletionQueueHandle,
+}
+
+impl GpuProfiler {
+    pub unsafe fn new(device: &Device, frames_in_flight: u32) -> Result<Self> {
+        let mut profiler = Self {
+            timestamp_period: device.timestamp_period().unwrap_or_default(),
+            frames: Vec::with_capacity(frames_in_flight as usize),
+            frame: 0,
+            open: Vec::with_capacity(MAX_GPU_SCOPES as usize),
+            frame_time: None,
+            results: Vec::with_capacity(MAX_GPU_SCOPES as usize),
+            deletion_queue: device.deletion_queue(),
+        };
+        if device.timestamp_period().is_none() {
+            warn!("timestamp queries are not supported, GPU profiling is disabled");
+            return Ok(profiler);
+        }
+
+        let create_info = vk::QueryPoolCreateInfo::builder()
+            .query_type(vk::QueryType::TIMESTAMP)
+            .query_count(QUERY_COUNT);
+        for _ in 0..frames_in_flight {
+            let pool = device
+                .create_query_pool(&create_info, None)
+                .context("create query pool")?;
+            profiler.frames.push(FrameQueries {
+                pool,
+                scopes: Vec::with_capacity(MAX_GPU_SCOPES as usize),
+                recorded: false,
+            });
+        }
+
+        Ok(profiler)
+    }
+
+    /// Returns false if the device does not support timestamps.
+    pub fn is_supported(&self) -> bool {
+        !self.frames.is_empty()
+    }
+
+    /// GPU time of the last measured frame.
+    pub fn frame_time(&self) -> Option<time::Duration> {
+        self.frame_time
+    }
+
+    /// GPU time of the scopes of the last measured frame, in the order they
+    /// were started.
+    pub fn results(&self) -> &[GpuScope] {
+        &self.results
+    }
+
+    /// Starts timing a scope. Scopes can be nested and must be ended, using
+    /// `end_scope()`, in the same command buffer.
+    pub unsafe fn begin_scope(
+        &mut self,
+        device: &ash::Device,
+        command_buffer: vk::CommandBuffer,
+        name: &'static str,
+    ) {
+        let Some(frame) = self.frames.get_mut(self.frame) else {
+            return;
+        };
+        if frame.scopes.len() as u32 == MAX_GPU_SCOPES {
+            // NOTE: still tracked so that the matching end_scope() is ignored
+            self.open.push(u32::MAX);
+            return;
+        }
+        let query = FRAME_QUERIES + 2 * frame.scopes.len() as u32;
+        frame.scopes.push(name);
+        self.open.push(query);
+        device.cmd_write_timestamp(
+            command_buffer,
+            vk::PipelineStageFlags::TOP_OF_PIPE,
+            frame.pool,
+            query,
+        );
+    }
+
+    /// Ends the innermost scope.
+    pub unsafe fn end_scope(&mut self, device: &ash::Device, command_buffer: vk::CommandBuffer) {
+        let Some(frame) = self.frames.get(self.frame) else {
+            return;
+        };
+        let Some(query) = self.open.pop() else {
+            warn!("GPU scope ended without being started");
+            return;
+        };
+        if query != u32::MAX {
+            device.cmd_write_timestamp(
+                command_buffer,
+                vk::PipelineStageFlags::BOTTOM_OF_PIPE,
+                frame.pool,
+                query + 1,
+            );
+        }
+    }
+
+    /// Reads the results of the given frame, whose fence has been waited on,
+    /// and prepares its pool to be recorded again.
+    pub(crate) unsafe fn begin_frame(&mut self, device: &ash::Device, frame: usize) {
+        self.frame = frame;
+        self.open.clear();
+        let Some(queries) = self.frames.get_mut(frame) else {
+            return;
+        };
+        if !queries.recorded {
+            return;
+        }
+        queries.recorded = false;
+
+        let count = FRAME_QUERIES + 2 * queries.scopes.len() as u32;
+        let mut timestamps = [0u64; QUERY_COUNT as usize];
+        if let Err(e) = device.get_query_pool_results(
+            queries.pool,
+            0,
+            count,
+            &mut timestamps,
+            vk::QueryResultFlags::TYPE_64,
+        ) {
+            debug!("get query pool results: {e:?}");
+            queries.scopes.clear();
+            return;
+        }
+
+        let period = self.timestamp_period;
+        let duration = |begin: u64, end: u64| {
+            let nanos = end.saturating_sub(begin) as f64 * period as f64;
+            time::Duration::from_nanos(nanos as u64)
+        };
+        self.frame_time = Some(duration(timestamps[0], timestamps[1]));
+        self.results.clear();
+        for (name, pair) in queries
+            .scopes
+            .drain(..)
+            .zip(timestamps[FRAME_QUERIES as usize..].chunks_exact(2))
+        {
+            self.results.push(GpuScope {
+                name,
+                duration: duration(pair[0], pair[1]),
+            });
+        }
+    }
+
+    /// Resets the pool of the current frame and starts timing the frame. Must
+    /// be recorded outside of a render pass.
+    pub(crate) unsafe fn begin_commands(
+        &mut self,
+        device: &ash::Device,
+        command_buffer: vk::CommandBuffer,
+    ) {
+        let Some(queries) = self.frames.get_mut(self.frame) else {
+            return;
+        };
+        queries.scopes.clear();
+        queries.recorded = true;
+        device.cmd_reset_query_pool(command_buffer, queries.pool, 0, QUERY_COUNT);
+        device.cmd_write_timestamp(
+            command_buffer,
+            vk::PipelineStageFlags::TOP_OF_PIPE,
+            queries.pool,
+            0,
+        );
+    }
+
+    /// Ends the scopes left open and stops timing the frame.
+    pub(crate) unsafe fn end_commands(
+        &mut self,
+        device: &ash::Device,
+        command_buffer: vk::CommandBuffer,
+    ) {
+        if self.frames.is_empty() {
+            return;
+        }
+        if !self.open.is_empty() {
+            warn!("{} GPU scopes were not ended", self.open.len());
+            while !self.open.is_empty() {
+                self.end_scope(device, command_buffer);
+            }
+        }
+        device.cmd_write_timestamp(
+            command_buffer,
+            vk::PipelineStageFlags::BOTTOM_OF_PIPE,
+            self.frames[self.frame].pool,
+            1,
+        );
+    }
+}
+
+impl Drop for GpuProfiler {
+    fn drop(&mut self) {
+        let mut deletion_queue = self.deletion_queue.borrow_mut();
+        for frame in self.frames.drain(..) {
+            deletion_queue.push(Resource::QueryPool(frame.pool));
+        }
+    }
+}
@@ -0,0 +1,211 @@
+use std::cell::Cell;
+
+use ash::vk;
+
+use super::deletion::{DeletionQueueHandle, Resource};
+use super::device::Device;
+use super::image::Image;
+use super::renderer::{create_depth_image, create_depth_image_view, create_viewport_and_scissor};
+use super::renderpass::{ColorLoad, RenderPass};
+use super::texture::Sampler;
+use crate::error::ResultExt;
+use crate::Result;
+
+/// Color and depth images rendered to outside of the main render pass, using
+/// a render pass and framebuffer of their own.
+///
+/// The color image is left in the SHADER_READ_ONLY_OPTIMAL layout at the end
+/// of the render pass, so that later passes of the frame can sample it. A
+/// pipeline created for the main render pass can draw to the target when the
+/// color formats match.
+///
+/// A target loading its color image keeps the content of its last draw, the
+/// image being cleared the first time only.
+pub struct RenderTarget {
+    color_image: Image,
+    color_image_view: vk::ImageView,
+    /// NOTE: only kept alive, the image being accessed through its view.
+    _depth_image: Image,
+    depth_image_view: vk::ImageView,
+    sampler: Sampler,
+
+    renderpass: RenderPass,
+    /// Render pass used once the image was drawn to, for targets loading it.
+    load_renderpass: Option<RenderPass>,
+    /// Set once a render pass was recorded.
+    drawn: Cell<bool>,
+    framebuffer: vk::Framebuffer,
+    extent: vk::Extent2D,
+
+    deletion_queue: DeletionQueueHandle,
+}
+
+impl RenderTarget {
+    /// Creates a target of the given size, named after `name` for debuggers.
+    pub unsafe fn new(
+        device: &Device,
+        extent: vk::Extent2D,
+        color_format: vk::Format,
+        color_load: ColorLoad,
+        name: &str,
+    ) -> Result<Self> {
+        // create color image
+        let create_info = vk::ImageCreateInfo::builder()
+            .image_type(vk::ImageType::TYPE_2D)
+            .format(color_format)
+            .extent(extent.into())
+            .mip_levels(1)
+            .array_layers(1)
+            .samples(vk::SampleCountFlags::TYPE_1)
+            .tiling(vk::ImageTiling::OPTIMAL)
+            .usage(vk::ImageUsageFlags::COLOR_ATTACHMENT | vk::ImageUsageFlags::SAMPLED)
+            .sharing_mode(vk::SharingMode::EXCLUSIVE);
+        let color_image = Image::new(device, *create_info, vk::MemoryPropertyFlags::DEVICE_LOCAL)
+            .context("create color image")?;
+        device.set_object_name(*color_image, &format!("{name} color image"));
+        let color_image_view = color_image
+            .create_view(
+                device,
+                vk::ImageViewType::TYPE_2D,
+                vk::ImageAspectFlags::COLOR,
+            )
+            .context("create color image view")?;
+        device.set_object_name(color_image_view, &format!("{name} color image view"));
+
+        // create depth image
+        let depth_format = device.depth_format();
+        let depth_image = create_depth_image(device, extent.into(), depth_format)
+            .context("create depth image")?;
+        device.set_object_name(*depth_image, &format!("{name} depth image"));
+        let depth_image_view =
+            create_depth_image_view(device, depth_image.image(), depth_image.format())
+                .context("create depth image view")?;
+        device.set_object_name(depth_image_view, &format!("{name} depth image view"));
+
+        // create the sampler used by later passes
+        let sampler_info = vk::SamplerCreateInfo::builder()
+            .mag_filter(vk::Filter::LINEAR)
+            .min_filter(vk::Filter::LINEAR)
+            .address_mode_u(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+            .address_mode_v(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+            .address_mode_w(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+            .max_anisotropy(1.0)
+            .border_color(vk::BorderColor::INT_OPAQUE_BLACK)
+            .compare_op(vk::CompareOp::ALWAYS)
+            .mipmap_mode(vk::SamplerMipmapMode::NEAREST);
+        let sampler = Sampler::new(device, *sampler_info).context("create sampler")?;
+
+        // create renderpass and framebuffer
+        let renderpass =
+            RenderPass::offscreen(device, &color_format, depth_format, ColorLoad::Clear)
+                .context("create renderpass")?;
+        device.set_object_name(*renderpass, &format!("{name} renderpass"));
+        // NOTE: the render passes are compatible, they share the framebuffer
+        let load_renderpass = match color_load {
+            ColorLoad::Clear => None,
+            ColorLoad::Load => {
+                let renderpass =
+                    RenderPass::offscreen(device, &color_format, depth_format, ColorLoad::Load)
+                        .context("create load renderpass")?;
+                device.set_object_name(*renderpass, &format!("{name} load renderpass"));
+                Some(renderpass)
+            }
+        };
+        let attachments = [color_image_view, depth_image_view];
+        let framebuffer_create_info = vk::FramebufferCreateInfo::builder()
+            .render_pass(*renderpass)
+            .attachments(&attachments)
+            .width(extent.width)
+            .height(extent.height)
+            .layers(1);
+        let framebuffer = device
+            .create_framebuffer(&framebuffer_create_info, None)
+            .context("create framebuffer")?;
+        device.set_object_name(framebuffer, &format!("{name} framebuffer"));
+
+        Ok(Self {
+            color_image,
+            color_image_view,
+            _depth_image: depth_image,
+            depth_image_view,
+            sampler,
+            renderpass,
+            load_renderpass,
+            drawn: Cell::new(false),
+            framebuffer,
+            extent,
+            deletion_queue: device.deletion_queue(),
+        })
+    }
+
+    pub fn extent(&self) -> vk::Extent2D {
+        self.extent
+    }
+
+    pub fn color_format(&self) -> vk::Format {
+        *self.color_image.format()
+    }
+
+    /// View of the color image, to be sampled once the target has been drawn.
+    pub fn image_view(&self) -> &vk::ImageView {
+        &self.color_image_view
+    }
+
+    pub fn sampler(&self) -> &Sampler {
+        &self.sampler
+    }
+
+    pub fn renderpass(&self) -> &RenderPass {
+        &self.renderpass
+    }
+
+    pub fn color_load(&self) -> ColorLoad {
+        match self.load_renderpass {
+            Some(_) => ColorLoad::Load,
+            None => ColorLoad::Clear,
+        }
+    }
+
+    /// Changes the color the image is cleared to, transparent black by
+    /// default.
+    pub fn set_clear_color(&mut self, color: [f32; 4]) {
+        self.renderpass.set_clear_color(color);
+    }
+
+    /// Begins the render pass of the target and covers it with the viewport
+    /// and scissor. Must be recorded outside of any other render pass.
+    pub unsafe fn begin(&self, device: &ash::Device, command_buffer: vk::CommandBuffer) {
+        let renderpass = match &self.load_renderpass {
+            Some(renderpass) if self.drawn.get() => renderpass,
+            _ => &self.renderpass,
+        };
+        self.drawn.set(true);
+        renderpass.begin(
+            device,
+            &self.framebuffer,
+            self.extent.into(),
+            &command_buffer,
+        );
+        let (viewport, scissor) = create_viewport_and_scissor(self.extent);
+        device.cmd_set_viewport(command_buffer, 0, &[viewport]);
+        device.cmd_set_scissor(command_buffer, 0, &[scissor]);
+    }
+
+    pub unsafe fn end(&self, device: &ash::Device, command_buffer: vk::CommandBuffer) {
+        self.renderpass.end(device, &command_buffer);
+    }
+}
+
+impl Drop for RenderTarget {
+    // NOTE: the images and the sampler are dropped right after
+    fn drop(&mut self) {
+        let mut deletion_queue = self.deletion_queue.borrow_mut();
+        deletion_queue.push(Resource::Framebuffer(self.framebuffer));
+        deletion_queue.push(Resource::RenderPass(*self.renderpass));
+        if let Some(renderpass) = &self.load_renderpass {
+            deletion_queue.push(Resource::RenderPass(**renderpass));
+        }
+        deletion_queue.push(Resource::ImageView(self.color_image_view));
+        deletion_queue.push(Resource::ImageView(self.depth_image_view));
+    }
+}
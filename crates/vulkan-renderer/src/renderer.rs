@@ -1,16 +1,56 @@
+use std::cell::{RefCell, RefMut};
+use std::mem;
+use std::path::PathBuf;
+use std::thread;
+
 use ash::vk;
-use log::debug;
-use winit::window::Window;
+use log::{debug, error};
+use winit::window::{Window, WindowId};
 
+use super::deletion::Resource;
 use super::device::Device;
 use super::image::Image;
-use super::renderpass::RenderPass;
+use super::profiler::GpuProfiler;
+use super::render_target::RenderTarget;
+use super::renderpass::{ColorLoad, RenderPass};
+use super::screenshot::{read_image, Screenshot};
+use super::staging::{StagingRing, DEFAULT_STAGING_REGION_SIZE};
 use super::swapchain::Swapchain;
+use super::sync::{cmd_image_barriers, queue_submit, ImageBarrier, SemaphoreStage};
+use super::window_target::WindowTarget;
+use crate::error::{RendererError, ResultExt};
 use crate::Result;
 
-/// Number of frames in flight at any moment. This is used to isolate rendering
-/// logic related to each frame. It includes command buffers and semaphores.
-const MAX_FRAMES_IN_FLIGHT: u32 = 2;
+/// Default number of frames in flight at any moment. This is used to isolate
+/// rendering logic related to each frame. It includes command buffers and
+/// semaphores.
+pub const DEFAULT_FRAMES_IN_FLIGHT: u32 = 2;
+
+/// Options used to create a `VulkanRenderer`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RendererSettings {
+    /// Number of frames the CPU can record ahead of the GPU. Must be at least 1
+    /// and at most the number of swapchain images.
+    pub frames_in_flight: u32,
+    /// Enables validation layers, if available.
+    pub validation: bool,
+    /// Presents in FIFO mode instead of MAILBOX.
+    pub vsync: bool,
+    /// Index of the physical device to render with, in the order the Vulkan
+    /// instance enumerates them. The first suitable device is used if None.
+    pub gpu: Option<usize>,
+}
+
+impl Default for RendererSettings {
+    fn default() -> Self {
+        Self {
+            frames_in_flight: DEFAULT_FRAMES_IN_FLIGHT,
+            validation: cfg!(debug_assertions),
+            vsync: false,
+            gpu: None,
+        }
+    }
+}
 
 struct FrameData {
     /// Fences are a synchronization primitive that can be used to insert a
@@ -25,6 +65,10 @@ struct FrameData {
 
     /// Primary command buffer object used to record commands for this frame.
     command_buffer: vk::CommandBuffer,
+
+    /// Command buffer holding the staging copies of this frame. It is
+    /// submitted right before the frame command buffer.
+    upload_command_buffer: vk::CommandBuffer,
 }
 
 impl FrameData {
@@ -35,23 +79,24 @@ impl FrameData {
                 vk::FenceCreateInfo::builder().flags(vk::FenceCreateFlags::SIGNALED);
             device
                 .create_fence(&fence_create_info, None)
-                .map_err(|e| format!("create fence: {:?}", e))?
+                .context("create fence")?
         };
 
         // create semaphores
         let semaphore_create_info = vk::SemaphoreCreateInfo::builder();
         let present_semaphore = device
             .create_semaphore(&semaphore_create_info, None)
-            .map_err(|e| format!("create semaphore: {:?}", e))?;
+            .context("create semaphore")?;
         let render_semaphore = device
             .create_semaphore(&semaphore_create_info, None)
-            .map_err(|e| format!("create semaphore: {:?}", e))?;
-
-        // create command buffer
-        let command_buffer = {
-            device
-                .create_command_buffers(command_pool, 1)
-                .map_err(|e| format!("create command buffers: {:?}", e))?[0]
+            .context("create semaphore")?;
+
+        // create command buffers
+        let (command_buffer, upload_command_buffer) = {
+            let command_buffers = device
+                .create_command_buffers(command_pool, 2)
+                .context("create command buffers")?;
+            (command_buffers[0], command_buffers[1])
         };
 
         Ok(Self {
@@ -59,6 +104,7 @@ impl FrameData {
             present_semaphore,
             render_semaphore,
             command_buffer,
+            upload_command_buffer,
         })
     }
 
@@ -72,9 +118,6 @@ impl FrameData {
 }
 
 pub struct VulkanRenderer {
-    /// The device is the interface used to talk to Vulkan.
-    device: Device,
-
     /// The swapchain holds the images we will draw onto.
     swapchain: Swapchain,
 
@@ -96,6 +139,20 @@ pub struct VulkanRenderer {
     frame_number: u32,
     max_frames_in_flight: u32,
 
+    /// Staging memory used to upload dynamic data during a frame.
+    staging: RefCell<StagingRing>,
+
+    /// Timestamp queries measuring the GPU time of the frames.
+    profiler: RefCell<GpuProfiler>,
+
+    /// Path of the next screenshot, taken once the previous one is written.
+    screenshot_request: Option<PathBuf>,
+    /// Screenshot copied during a frame in flight.
+    screenshot: RefCell<Option<Screenshot>>,
+    /// Threads writing the screenshots to their files, joined when the
+    /// renderer is dropped.
+    screenshot_writers: RefCell<Vec<thread::JoinHandle<()>>>,
+
     /// depth image used in RenderPass
     depth_image: Image,
     depth_image_view: vk::ImageView,
@@ -103,14 +160,32 @@ pub struct VulkanRenderer {
     /// Framebuffers holds buffers for drawing.
     framebuffers: Vec<vk::Framebuffer>,
 
+    /// Windows drawn along with the main one, see add_window().
+    windows: Vec<WindowTarget>,
+
     /// A two-dimensional extent representing the size of the surface.
     window_extent: vk::Extent2D,
 
     /// Set to true when the surface_resolution has been updated.
     framebuffer_resized: bool,
 
+    /// Set while the swapchain can not be recreated because the surface has
+    /// no extent, see `Device::surface_is_minimized()`. It is recreated once
+    /// the window is restored.
+    swapchain_deferred: bool,
+
     /// Indicate wheter a frame has been started using begin_frame().
     frame_started: bool,
+
+    /// Set while the surface and swapchain are destroyed, see suspend().
+    suspended: bool,
+
+    /// Settings used when recreating the swapchain.
+    settings: RendererSettings,
+
+    /// The device is the interface used to talk to Vulkan.
+    /// NOTE: declared last so that it is dropped after the other fields.
+    device: Device,
 }
 
 impl VulkanRenderer {
@@ -118,10 +193,14 @@ impl VulkanRenderer {
     ///
     /// # Safety
     /// NOTHING IS SAFE HERE, GLHF
-    pub unsafe fn new(app_name: impl AsRef<str>, window: &Window) -> Result<Self> {
+    pub unsafe fn new(
+        app_name: impl AsRef<str>,
+        window: &Window,
+        settings: RendererSettings,
+    ) -> Result<Self> {
         // create device
-        let device =
-            Device::new(app_name, window).map_err(|e| format!("create device: {:?}", e))?;
+        let device = Device::new(app_name, window, settings.validation, settings.gpu)
+            .context("create device")?;
 
         let window_extent = {
             let window_size = window.inner_size();
@@ -131,36 +210,80 @@ impl VulkanRenderer {
             }
         };
 
+        Self::with_device(device, window_extent, settings)
+    }
+
+    /// Creates a Vulkan context without a window, rendering frames of the
+    /// given size to offscreen images that can be read back using
+    /// `read_frame()`, e.g. to test the render path without a display.
+    ///
+    /// # Safety
+    /// See `new()`.
+    pub unsafe fn new_headless(
+        app_name: impl AsRef<str>,
+        extent: vk::Extent2D,
+        settings: RendererSettings,
+    ) -> Result<Self> {
+        let device = Device::new_headless(app_name, settings.validation, settings.gpu)
+            .context("create device")?;
+        Self::with_device(device, extent, settings)
+    }
+
+    unsafe fn with_device(
+        device: Device,
+        window_extent: vk::Extent2D,
+        settings: RendererSettings,
+    ) -> Result<Self> {
+        // create swapchain
+        let mut swapchain =
+            create_swapchain(&device, window_extent, settings).context("create swapchain")?;
+
+        // a frame can not be in flight without an image to render to
+        // NOTE: this is checked before creating anything else, the swapchain
+        //       being needed to know the number of images
+        let image_count = swapchain.image_views().len() as u32;
+        let max_frames_in_flight = settings.frames_in_flight;
+        if max_frames_in_flight == 0 || max_frames_in_flight > image_count {
+            swapchain.destroy(&device);
+            return Err(RendererError::InvalidFramesInFlight {
+                requested: max_frames_in_flight,
+                image_count,
+            });
+        }
+
         // create command pool
         let command_pool = device
             .create_command_pool()
-            .map_err(|e| format!("create command buffer pool: {:?}", e))?;
+            .context("create command buffer pool")?;
 
         // create fame data
-        let max_frames_in_flight = MAX_FRAMES_IN_FLIGHT;
         let mut frames = Vec::with_capacity(max_frames_in_flight as usize);
         for _ in 0..max_frames_in_flight {
-            let frame_data = FrameData::new(&device, &command_pool)
-                .map_err(|e| format!("create frame data: {:?}", e))?;
+            let frame_data = FrameData::new(&device, &command_pool).context("create frame data")?;
             frames.push(frame_data);
         }
 
-        // create swapchain
-        let swapchain = Swapchain::new(&device, window_extent)
-            .map_err(|e| format!("create swapchain: {:?}", e))?;
+        // create staging ring
+        let staging = StagingRing::new(&device, DEFAULT_STAGING_REGION_SIZE, max_frames_in_flight)
+            .context("create staging ring")?;
+
+        // create GPU profiler
+        let profiler =
+            GpuProfiler::new(&device, max_frames_in_flight).context("create GPU profiler")?;
 
         // create renderpass
-        let renderpass = RenderPass::new(&device, swapchain.image_format())
-            .map_err(|e| format!("create renderpass: {:?}", e))?;
+        let depth_format = device.depth_format();
+        let renderpass = RenderPass::new(&device, swapchain.image_format(), depth_format)
+            .context("create renderpass")?;
 
         // create depth image
-        let depth_image = create_depth_image(&device, window_extent.into())
-            .map_err(|e| format!("create depth image: {:?}", e))?;
+        let depth_image = create_depth_image(&device, window_extent.into(), depth_format)
+            .context("create depth image")?;
 
         // create depth image view used for writing depth data
         let depth_image_view =
             create_depth_image_view(&device, depth_image.image(), depth_image.format())
-                .map_err(|e| format!("create depth image view: {:?}", e))?;
+                .context("create depth image view")?;
 
         // create framebuffers
         let framebuffers = create_framebuffers(
@@ -170,7 +293,7 @@ impl VulkanRenderer {
             &depth_image_view,
             window_extent,
         )
-        .map_err(|e| format!("create framebuffers: {:?}", e))?;
+        .context("create framebuffers")?;
 
         let renderer = Self {
             device,
@@ -179,13 +302,22 @@ impl VulkanRenderer {
             frames,
             frame_number: 0,
             max_frames_in_flight,
+            staging: RefCell::new(staging),
+            profiler: RefCell::new(profiler),
+            screenshot_request: None,
+            screenshot: RefCell::new(None),
+            screenshot_writers: RefCell::new(Vec::new()),
             swapchain,
             renderpass,
             depth_image,
             depth_image_view,
             framebuffers,
+            windows: Vec::new(),
             framebuffer_resized: false,
+            swapchain_deferred: false,
             frame_started: false,
+            suspended: false,
+            settings,
         };
 
         Ok(renderer)
@@ -196,12 +328,171 @@ impl VulkanRenderer {
         self.framebuffer_resized = true;
     }
 
+    /// Draws to another window from the next frame, sharing the device and
+    /// frames in flight of the main window. The window surface must support
+    /// the image format of the main window. See `draw_with_windows()`.
+    pub unsafe fn add_window(&mut self, window: &Window) -> Result<()> {
+        if self.window(window.id()).is_some() {
+            return Err("the window is already drawn to".into());
+        }
+        let mut target = WindowTarget::new(
+            &self.device,
+            window,
+            *self.swapchain.image_format(),
+            self.settings.vsync,
+            self.max_frames_in_flight,
+        )?;
+        target.set_clear_color(self.renderpass.clear_color());
+        self.windows.push(target);
+        Ok(())
+    }
+
+    /// Stops drawing to a window added using `add_window()`, e.g. before it
+    /// is closed. Does nothing if the window is not drawn to.
+    pub unsafe fn remove_window(&mut self, id: WindowId) -> Result<()> {
+        let Some(index) = self.windows.iter().position(|t| t.id() == id) else {
+            return Ok(());
+        };
+        self.device.device_wait_idle().context("device wait idle")?;
+        self.windows.remove(index).destroy(&self.device);
+        Ok(())
+    }
+
+    /// Resizes the swapchain of a window added using `add_window()`.
+    pub fn resize_window(&mut self, id: WindowId, width: u32, height: u32) {
+        if let Some(target) = self.windows.iter_mut().find(|t| t.id() == id) {
+            target.resize(width, height);
+        }
+    }
+
+    /// Returns a window added using `add_window()`.
+    pub fn window(&self, id: WindowId) -> Option<&WindowTarget> {
+        self.windows.iter().find(|t| t.id() == id)
+    }
+
+    /// Returns the windows added using `add_window()`.
+    pub fn windows(&self) -> &[WindowTarget] {
+        &self.windows
+    }
+
+    pub fn max_frames_in_flight(&self) -> u32 {
+        self.max_frames_in_flight
+    }
+
+    pub fn window_extent(&self) -> vk::Extent2D {
+        self.window_extent
+    }
+
+    /// Returns the present mode of the main window. Frames are only paced by
+    /// the display in FIFO mode.
+    pub fn present_mode(&self) -> vk::PresentModeKHR {
+        self.swapchain.present_mode()
+    }
+
+    /// Returns true if the window is minimized or reduced to 0 in any direction,
+    /// in which case frames are not rendered. This includes surfaces reported
+    /// with no extent while their window is not, until the window is restored.
+    pub fn is_minimized(&self) -> bool {
+        self.window_extent.width == 0 || self.window_extent.height == 0 || self.swapchain_deferred
+    }
+
+    /// Returns true while the swapchain is destroyed, in which case frames are
+    /// not rendered.
+    pub fn is_suspended(&self) -> bool {
+        self.suspended
+    }
+
+    /// Destroys the surface and the swapchain, e.g. when the application is
+    /// suspended and its window may not be drawn to anymore. Device objects
+    /// are kept and frames are not rendered until `resume()` is called.
+    /// Additional windows are removed and must be added again once resumed.
+    pub unsafe fn suspend(&mut self) -> Result<()> {
+        if self.suspended {
+            return Ok(());
+        }
+        self.device.device_wait_idle().context("device wait idle")?;
+
+        // a frame started but not presented is abandoned
+        if mem::take(&mut self.frame_started) {
+            self.bump_frame();
+        }
+
+        // NOTE: the destroyed handles are reset so that recreate_swapchain()
+        //       and drop() do not destroy them again
+        for framebuffer in self.framebuffers.drain(..) {
+            self.device.destroy_framebuffer(framebuffer, None);
+        }
+        self.device.destroy_image_view(self.depth_image_view, None);
+        self.depth_image_view = vk::ImageView::null();
+        self.swapchain.destroy(&self.device);
+        self.device.destroy_surface();
+        for mut target in self.windows.drain(..) {
+            target.destroy(&self.device);
+        }
+
+        self.suspended = true;
+        debug!("renderer suspended");
+        Ok(())
+    }
+
+    /// Recreates the surface from the window after a call to `suspend()`.
+    /// The swapchain is recreated by the next frame started while the window
+    /// is not minimized.
+    pub unsafe fn resume(&mut self, window: &Window) -> Result<()> {
+        if !self.suspended || *self.device.surface() != vk::SurfaceKHR::null() {
+            return Ok(());
+        }
+        self.device
+            .recreate_surface(window)
+            .context("recreate surface")?;
+
+        let window_size = window.inner_size();
+        self.resize(window_size.width, window_size.height);
+        debug!("renderer resumed");
+        Ok(())
+    }
+
+    /// Recreates the surface and the swapchain, e.g. when the surface has
+    /// been lost.
+    pub unsafe fn recreate_surface(&mut self, window: &Window) -> Result<()> {
+        self.suspend().context("destroy surface")?;
+        self.resume(window)
+    }
+
     pub unsafe fn begin_frame(&mut self) -> Result<bool> {
+        profiling::scope!("VulkanRenderer.begin_frame");
         // do not render if we are minimized or window is reduced to 0 in any direction
         if self.window_extent.width == 0 || self.window_extent.height == 0 {
             return Ok(false);
         }
 
+        // recreate the swapchain destroyed by suspend() once resumed
+        if self.suspended {
+            if *self.device.surface() == vk::SurfaceKHR::null() {
+                return Ok(false);
+            }
+            self.framebuffer_resized = false;
+            self.recreate_swapchain().context("recreate swapchain")?;
+            self.suspended = false;
+        }
+
+        // recreate the swapchain deferred while the surface had no extent
+        // once the window is restored
+        if self.swapchain_deferred {
+            if self
+                .device
+                .surface_is_minimized(*self.device.surface())
+                .context("query surface extent")?
+            {
+                return Ok(false);
+            }
+            self.framebuffer_resized = false;
+            self.recreate_swapchain().context("recreate swapchain")?;
+            if self.swapchain_deferred {
+                return Ok(false);
+            }
+        }
+
         let frame_data = self.current_frame();
         let timeout = std::u64::MAX;
 
@@ -211,37 +502,89 @@ impl VulkanRenderer {
             let fences = [frame_data.render_fence];
             self.device
                 .wait_for_fences(&fences, wait_all, timeout)
-                .map_err(|e| format!("wait for fences: {:?}", e))?;
-            self.device
-                .reset_fences(&fences)
-                .map_err(|e| format!("reset fences: {:?}", e))?;
+                .context("wait for fences")?;
+            self.device.reset_fences(&fences).context("reset fences")?;
+        }
+
+        // the previous submission of this frame has completed, its staging
+        // region can be reused
+        let region = self.frame_number % self.max_frames_in_flight;
+        self.staging.borrow_mut().begin_frame(region);
+        // and its timestamps can be read
+        self.profiler
+            .borrow_mut()
+            .begin_frame(&self.device, region as usize);
+
+        // so are the resources dropped before that submission
+        let completed_frame = self
+            .frame_number
+            .checked_sub(self.max_frames_in_flight)
+            .map(u64::from);
+        if let Some(completed_frame) = completed_frame {
+            self.device.collect_garbage(completed_frame);
         }
+        // and so can the screenshot copied during that submission
+        let mut screenshot = self.screenshot.borrow_mut();
+        if screenshot
+            .as_ref()
+            .map_or(false, |s| completed_frame >= Some(s.frame().into()))
+        {
+            if let Some(screenshot) = screenshot.take() {
+                self.write_screenshot(screenshot);
+            }
+        }
+        drop(screenshot);
 
         // acquire next image
         let suboptimal = {
             let present_semaphore = frame_data.present_semaphore;
             let render_fence = frame_data.render_fence;
             self.swapchain
-                .acquire_next_image(timeout, &present_semaphore, &render_fence)
-                .map_err(|e| format!("acquire next image: {:?}", e))?
+                .acquire_next_image(&self.device, timeout, &present_semaphore, &render_fence)
+                .context("acquire next image")?
         };
 
         // recreate swapchain if needed
         if suboptimal || self.framebuffer_resized {
             self.framebuffer_resized = false;
-            self.recreate_swapchain()
-                .map_err(|e| format!("recreate swapchain: {:?}", e))?;
+            self.recreate_swapchain().context("recreate swapchain")?;
             return Ok(false);
         }
 
+        // copy the image of this frame if a screenshot was requested
+        if self.screenshot.get_mut().is_none() {
+            if let Some(path) = self.screenshot_request.take() {
+                match Screenshot::new(
+                    &self.device,
+                    path,
+                    self.swapchain.extent(),
+                    *self.swapchain.image_format(),
+                    self.frame_number,
+                ) {
+                    Ok(screenshot) => *self.screenshot.get_mut() = Some(screenshot),
+                    Err(e) => error!("create screenshot: {e}"),
+                }
+            }
+        }
+
+        // acquire the images of the additional windows, which are skipped
+        // this frame on failure
+        let frame = self.frame_index();
+        for target in &mut self.windows {
+            if let Err(e) = target.acquire(&self.device, frame) {
+                error!("acquire image of window {:?}: {e}", target.id());
+            }
+        }
+
         self.frame_started = true;
 
         Ok(true)
     }
 
     pub unsafe fn end_frame(&mut self) -> Result<bool> {
+        profiling::scope!("VulkanRenderer.end_frame");
         if !self.frame_started {
-            return Err("submit_frame called but frame has not been prepared".into());
+            return Err(RendererError::FrameNotStarted);
         }
 
         let frame_data = self.current_frame();
@@ -251,12 +594,19 @@ impl VulkanRenderer {
         let suboptimal = self
             .swapchain
             .queue_present(&self.device, &wait_semaphores)
-            .map_err(|e| format!("queue present: {:?}", e))?;
+            .context("queue present")?;
+
+        // present the additional windows drawn by the frame
+        let frame = self.frame_index();
+        for target in &mut self.windows {
+            if let Err(e) = target.present(&self.device, frame) {
+                error!("present window {:?}: {e}", target.id());
+            }
+        }
 
         // recreate swapchain if needed
         if suboptimal {
-            self.recreate_swapchain()
-                .map_err(|e| format!("recreate swapchain: {:?}", e))?;
+            self.recreate_swapchain().context("recreate swapchain")?;
             return Ok(false);
         }
 
@@ -266,19 +616,50 @@ impl VulkanRenderer {
         Ok(true)
     }
 
+    /// Records the frame commands of `f` inside the main render pass and
+    /// submits them.
     pub unsafe fn draw<F: FnOnce(&ash::Device, vk::CommandBuffer)>(&self, f: F) -> Result<()> {
+        self.draw_with_offscreen(|_, _| {}, f)
+    }
+
+    /// Like `draw()`, first recording the commands of `offscreen` outside of
+    /// the main render pass, e.g. to draw to render targets sampled by `f`.
+    pub unsafe fn draw_with_offscreen<O, F>(&self, offscreen: O, f: F) -> Result<()>
+    where
+        O: FnOnce(&ash::Device, vk::CommandBuffer),
+        F: FnOnce(&ash::Device, vk::CommandBuffer),
+    {
+        self.draw_with_windows(offscreen, f, |_, _, _| {})
+    }
+
+    /// Like `draw_with_offscreen()`, then recording the commands of `w` inside
+    /// the render pass of each window added using `add_window()`, unless it
+    /// is minimized or its swapchain is being recreated.
+    pub unsafe fn draw_with_windows<O, F, W>(&self, offscreen: O, f: F, mut w: W) -> Result<()>
+    where
+        O: FnOnce(&ash::Device, vk::CommandBuffer),
+        F: FnOnce(&ash::Device, vk::CommandBuffer),
+        W: FnMut(&ash::Device, vk::CommandBuffer, &WindowTarget),
+    {
         if !self.frame_started {
-            return Err("draw_and_submit called but frame has not been started".into());
+            return Err(RendererError::FrameNotStarted);
         }
 
         let frame_data = self.current_frame();
 
         self.immediate_submit(
             frame_data.command_buffer,
+            frame_data.upload_command_buffer,
             frame_data.render_fence,
             frame_data.render_semaphore,
             frame_data.present_semaphore,
             |device, cb| {
+                // start timing the frame
+                self.profiler.borrow_mut().begin_commands(device, cb);
+
+                // draw to render targets
+                offscreen(device, cb);
+
                 // begin renderpass
                 let framebuffer = self.current_framebuffer();
                 self.renderpass
@@ -295,9 +676,32 @@ impl VulkanRenderer {
 
                 // end renderpass
                 self.renderpass.end(device, &cb);
+
+                // copy the rendered image if a screenshot was requested
+                if let Some(screenshot) = self
+                    .screenshot
+                    .borrow_mut()
+                    .as_mut()
+                    .filter(|s| s.frame() == self.frame_number)
+                {
+                    screenshot.record(
+                        device,
+                        cb,
+                        self.swapchain.current_image(),
+                        self.device.present_layout(),
+                    );
+                }
+
+                // draw the additional windows
+                for target in self.windows.iter().filter(|t| t.is_acquired()) {
+                    target.record(device, cb, |device, cb| w(device, cb, target));
+                }
+
+                // stop timing the frame
+                self.profiler.borrow_mut().end_commands(device, cb);
             },
         )
-        .map_err(|e| format!("immediate submit: {:?}", e))?;
+        .context("immediate submit")?;
 
         Ok(())
     }
@@ -310,17 +714,109 @@ impl VulkanRenderer {
         &self.renderpass
     }
 
-    #[allow(unused)]
-    pub unsafe fn destroy_image(&self, image: &mut Image) {
-        image.destroy(&self.device)
+    /// Returns the color the windows are cleared to.
+    pub fn clear_color(&self) -> [f32; 4] {
+        self.renderpass.clear_color()
+    }
+
+    /// Changes the color the main window and the windows added using
+    /// `add_window()` are cleared to, transparent black by default.
+    pub fn set_clear_color(&mut self, color: [f32; 4]) {
+        self.renderpass.set_clear_color(color);
+        for target in &mut self.windows {
+            target.set_clear_color(color);
+        }
+    }
+
+    /// Creates a render target using the format of the swapchain images, so
+    /// that pipelines created for the main render pass can draw to it.
+    pub unsafe fn create_render_target(
+        &self,
+        extent: vk::Extent2D,
+        color_load: ColorLoad,
+        name: &str,
+    ) -> Result<RenderTarget> {
+        let format = *self.swapchain.image_format();
+        RenderTarget::new(&self.device, extent, format, color_load, name)
+    }
+
+    /// Returns the staging ring used to upload data during the current frame.
+    /// Copies queued while drawing are executed before the frame commands.
+    pub fn staging(&self) -> RefMut<'_, StagingRing> {
+        self.staging.borrow_mut()
+    }
+
+    /// Returns the profiler measuring the GPU time of the frames. Scopes can
+    /// be timed while drawing.
+    pub fn gpu_profiler(&self) -> RefMut<'_, GpuProfiler> {
+        self.profiler.borrow_mut()
+    }
+
+    /// Saves the next frame to a PNG file. The swapchain image is copied after
+    /// the frame is drawn and written from a background thread once the frame
+    /// has completed. A single screenshot is taken at a time: a new request
+    /// replaces the pending one and waits for the previous one to be written.
+    /// Dropping the renderer waits for the screenshots to be saved.
+    pub fn capture_screenshot(&mut self, path: impl Into<PathBuf>) -> Result<()> {
+        if !self.swapchain.supports_transfer_src() {
+            return Err("swapchain images can not be copied".into());
+        }
+        let format = *self.swapchain.image_format();
+        if !Screenshot::supports_format(format) {
+            return Err(format!("unsupported swapchain format {format:?}").into());
+        }
+        self.screenshot_request = Some(path.into());
+        Ok(())
+    }
+
+    /// Writes a screenshot whose frame has completed, keeping the thread
+    /// writing it to be joined.
+    unsafe fn write_screenshot(&self, screenshot: Screenshot) {
+        let mut writers = self.screenshot_writers.borrow_mut();
+        writers.retain(|writer| !writer.is_finished());
+        match screenshot.write() {
+            Ok(writer) => writers.push(writer),
+            Err(e) => error!("write screenshot: {e}"),
+        }
+    }
+
+    /// Returns the RGBA pixels of the image drawn by the last frame of a
+    /// renderer created using `new_headless()`, row by row. Waits for the
+    /// device to be idle.
+    pub unsafe fn read_frame(&self) -> Result<Vec<u8>> {
+        if !self.swapchain.is_headless() {
+            return Err("only the frames of a headless renderer can be read".into());
+        }
+        if self.frame_started || self.frame_number == 0 {
+            return Err("no frame has been drawn".into());
+        }
+        read_image(
+            &self.device,
+            self.command_pool,
+            self.swapchain.current_image(),
+            self.device.present_layout(),
+            self.swapchain.extent(),
+            *self.swapchain.image_format(),
+        )
+        .context("read frame")
     }
 
     unsafe fn recreate_swapchain(&mut self) -> Result<()> {
         // ensure all operations on the device have been finished before destroying
         // resources
-        self.device
-            .device_wait_idle()
-            .map_err(|e| format!("device wait idle: {:?}", e))?;
+        self.device.device_wait_idle().context("device wait idle")?;
+
+        // images can not be created without an extent, the swapchain is
+        // recreated once the window is restored
+        self.swapchain_deferred = !self.device.is_headless()
+            && self
+                .device
+                .surface_is_minimized(*self.device.surface())
+                .context("query surface extent")?;
+        if self.swapchain_deferred {
+            debug!("surface has no extent, deferring swapchain recreation");
+            return Ok(());
+        }
 
         /////////////////////////////////////////
         // destroy swapchain-related components
@@ -330,9 +826,8 @@ impl VulkanRenderer {
         for framebuffer in self.framebuffers.drain(..) {
             self.device.destroy_framebuffer(framebuffer, None);
         }
-        // depth image
+        // depth image view (the image is dropped when replaced)
         self.device.destroy_image_view(self.depth_image_view, None);
-        self.depth_image.destroy(&self.device);
         // renderpass
         self.device.destroy_render_pass(*self.renderpass, None);
         // swapchain
@@ -342,20 +837,22 @@ impl VulkanRenderer {
         // recreate swapchain
         /////////////////////////////////////////
 
-        let swapchain = Swapchain::new(&self.device, self.window_extent)
-            .map_err(|e| format!("recreate swapchain: {:?}", e))?;
+        let swapchain = create_swapchain(&self.device, self.window_extent, self.settings)
+            .context("recreate swapchain")?;
 
         // create renderpass
-        let renderpass = RenderPass::new(&self.device, swapchain.image_format())
-            .map_err(|e| format!("create renderpass: {:?}", e))?;
+        let depth_format = self.device.depth_format();
+        let mut renderpass = RenderPass::new(&self.device, swapchain.image_format(), depth_format)
+            .context("create renderpass")?;
+        renderpass.set_clear_color(self.renderpass.clear_color());
 
         // create depth image
-        let depth_image = create_depth_image(&self.device, self.window_extent.into())
-            .map_err(|e| format!("create depth image: {:?}", e))?;
+        let depth_image = create_depth_image(&self.device, self.window_extent.into(), depth_format)
+            .context("create depth image")?;
 
         let depth_image_view =
             create_depth_image_view(&self.device, depth_image.image(), depth_image.format())
-                .map_err(|e| format!("create depth image view: {:?}", e))?;
+                .context("create depth image view")?;
 
         // create framebuffers
         let framebuffers = create_framebuffers(
@@ -365,7 +862,7 @@ impl VulkanRenderer {
             &depth_image_view,
             self.window_extent,
         )
-        .map_err(|e| format!("create framebuffers: {:?}", e))?;
+        .context("create framebuffers")?;
 
         /////////////////////////////////////////
         // set swapchain
@@ -386,59 +883,151 @@ impl VulkanRenderer {
     }
 
     fn current_frame(&self) -> &FrameData {
-        let idx = self.frame_number % self.max_frames_in_flight;
-        &self.frames[idx as usize]
+        &self.frames[self.frame_index()]
+    }
+
+    /// Returns the index of the current frame among the frames in flight.
+    fn frame_index(&self) -> usize {
+        (self.frame_number % self.max_frames_in_flight) as usize
     }
 
     fn bump_frame(&mut self) {
         self.frame_number += 1;
+        // NOTE: resources dropped between frames, e.g. by one-off submits,
+        //       are only covered by the fence of the next frame
+        self.device.set_garbage_frame(self.frame_number.into());
     }
 
     unsafe fn immediate_submit<F: FnOnce(&ash::Device, vk::CommandBuffer)>(
         &self,
         command_buffer: vk::CommandBuffer,
+        upload_command_buffer: vk::CommandBuffer,
         render_fence: vk::Fence,
         render_semaphore: vk::Semaphore,
         present_semaphore: vk::Semaphore,
         f: F,
     ) -> Result<()> {
+        // record frame commands
+        record_commandbuffer(&self.device, command_buffer, f).context("record commandbuffer")?;
+
+        // record the staging copies queued while recording the frame
+        let mut staging = self.staging.borrow_mut();
+        staging.grow(&self.device).context("grow staging ring")?;
+        let command_buffers = [upload_command_buffer, command_buffer];
+        let command_buffers = if staging.has_pending() {
+            record_commandbuffer(&self.device, upload_command_buffer, |device, cb| {
+                staging.record(device, cb)
+            })
+            .context("record upload commandbuffer")?;
+            &command_buffers[..]
+        } else {
+            &command_buffers[1..]
+        };
+
+        // the swapchain images are first written by the render passes, whose
+        // layout transitions wait for the color attachment output stage
+        let mut wait_semaphores = vec![SemaphoreStage {
+            semaphore: present_semaphore,
+            stage_mask: vk::PipelineStageFlags2::COLOR_ATTACHMENT_OUTPUT,
+        }];
+        let mut signal_semaphores = vec![SemaphoreStage {
+            semaphore: render_semaphore,
+            stage_mask: vk::PipelineStageFlags2::ALL_COMMANDS,
+        }];
+        let frame = self.frame_index();
+        for target in self.windows.iter().filter(|t| t.is_acquired()) {
+            let (wait, signal) = target.semaphores(frame);
+            wait_semaphores.push(wait);
+            signal_semaphores.push(signal);
+        }
+
         immediate_submit(
             &self.device,
-            command_buffer,
+            command_buffers,
             render_fence,
-            render_semaphore,
-            present_semaphore,
-            f,
+            &wait_semaphores,
+            &signal_semaphores,
         )
     }
+}
 
-    pub unsafe fn destroy(&mut self) {
+impl Drop for VulkanRenderer {
+    /// Destroys the objects owned by the renderer. Wrapper types (depth image,
+    /// staging ring, GPU profiler) are dropped afterwards and the device, dropped last,
+    /// destroys them along with the resources dropped by other subsystems.
+    fn drop(&mut self) {
         debug!("Destroying Vulkan Renderer");
 
-        // Wait for a device to become idle (completion of outstanding queue operations
-        // for all queues on a given logical device).
-        self.device.device_wait_idle().expect("device wait idle");
-        // framebuffers
-        for framebuffer in self.framebuffers.drain(..) {
-            self.device.destroy_framebuffer(framebuffer, None);
+        unsafe {
+            // Wait for a device to become idle (completion of outstanding queue operations
+            // for all queues on a given logical device).
+            self.device.device_wait_idle().expect("device wait idle");
+            // the frame a screenshot was copied in has now completed
+            if let Some(screenshot) = self.screenshot.get_mut().take() {
+                self.write_screenshot(screenshot);
+            }
+            // framebuffers
+            for framebuffer in self.framebuffers.drain(..) {
+                self.device.destroy_framebuffer(framebuffer, None);
+            }
+            // depth image view
+            self.device.destroy_image_view(self.depth_image_view, None);
+            // renderpass
+            self.device.destroy_render_pass(*self.renderpass, None);
+            // swapchain
+            self.swapchain.destroy(&self.device);
+            // additional windows
+            for mut target in self.windows.drain(..) {
+                target.destroy(&self.device);
+            }
+            for mut frame_data in self.frames.drain(..) {
+                frame_data.destroy(&self.device);
+            }
+            // command buffers
+            self.device.destroy_command_pool(self.command_pool, None);
         }
-        // depth image
-        self.device.destroy_image_view(self.depth_image_view, None);
-        self.depth_image.destroy(&self.device);
-        // renderpass
-        self.device.destroy_render_pass(*self.renderpass, None);
-        // swapchain
-        self.swapchain.destroy(&self.device);
-        for mut frame_data in self.frames.drain(..) {
-            frame_data.destroy(&self.device);
+
+        // wait for the screenshots to be saved before exiting
+        for writer in self.screenshot_writers.get_mut().drain(..) {
+            if writer.join().is_err() {
+                error!("screenshot writer panicked");
+            }
         }
-        // command buffers
-        self.device.destroy_command_pool(self.command_pool, None);
-        // device
-        self.device.destroy();
     }
 }
 
+/// Creates the swapchain presenting to the surface of the device, or the
+/// offscreen images of a headless device.
+unsafe fn create_swapchain(
+    device: &Device,
+    extent: vk::Extent2D,
+    settings: RendererSettings,
+) -> Result<Swapchain> {
+    if device.is_headless() {
+        Swapchain::headless(device, extent, settings.frames_in_flight)
+    } else {
+        Swapchain::new(device, *device.surface(), extent, settings.vsync)
+    }
+}
+
+pub unsafe fn copy_buffer(
+    device: &Device,
+    command_pool: vk::CommandPool,
+    src: vk::Buffer,
+    dst: vk::Buffer,
+    size: u64,
+) -> Result<()> {
+    let regions = [vk::BufferCopy {
+        src_offset: 0,
+        dst_offset: 0,
+        size,
+    }];
+
+    single_time_command(device, command_pool, |device, command_buffer| {
+        device.cmd_copy_buffer(command_buffer, src, dst, &regions);
+    })
+}
+
 pub unsafe fn copy_buffer_to_image(
     device: &Device,
     command_pool: vk::CommandPool,
@@ -483,56 +1072,18 @@ pub unsafe fn transition_image_layout(
     old_layout: vk::ImageLayout,
     new_layout: vk::ImageLayout,
 ) -> Result<()> {
-    let src_access_mask;
-    let dst_access_mask;
-    let source_stage;
-    let destination_stage;
-
-    if old_layout == vk::ImageLayout::UNDEFINED
-        && new_layout == vk::ImageLayout::TRANSFER_DST_OPTIMAL
-    {
-        src_access_mask = vk::AccessFlags::empty();
-        dst_access_mask = vk::AccessFlags::TRANSFER_WRITE;
-        source_stage = vk::PipelineStageFlags::TOP_OF_PIPE;
-        destination_stage = vk::PipelineStageFlags::TRANSFER;
-    } else if old_layout == vk::ImageLayout::TRANSFER_DST_OPTIMAL
-        && new_layout == vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL
-    {
-        src_access_mask = vk::AccessFlags::TRANSFER_WRITE;
-        dst_access_mask = vk::AccessFlags::SHADER_READ;
-        source_stage = vk::PipelineStageFlags::TRANSFER;
-        destination_stage = vk::PipelineStageFlags::FRAGMENT_SHADER;
-    } else {
-        return Err("Unsupported layout transition!".into());
-    }
-
-    let image_barriers = &[vk::ImageMemoryBarrier::builder()
-        .src_access_mask(src_access_mask)
-        .dst_access_mask(dst_access_mask)
-        .old_layout(old_layout)
-        .new_layout(new_layout)
-        .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
-        .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
-        .image(image)
-        .subresource_range(
-            vk::ImageSubresourceRange::builder()
-                .aspect_mask(vk::ImageAspectFlags::COLOR)
-                .level_count(1)
-                .layer_count(1)
-                .build(),
-        )
-        .build()];
+    let barrier = match (old_layout, new_layout) {
+        (vk::ImageLayout::UNDEFINED, vk::ImageLayout::TRANSFER_DST_OPTIMAL) => {
+            ImageBarrier::undefined_to_transfer_dst(image)
+        }
+        (vk::ImageLayout::TRANSFER_DST_OPTIMAL, vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL) => {
+            ImageBarrier::transfer_to_shader_read(image)
+        }
+        _ => return Err("Unsupported layout transition!".into()),
+    };
 
-    single_time_command(device, command_pool, |device, command_buffer| {
-        device.cmd_pipeline_barrier(
-            command_buffer,
-            source_stage,
-            destination_stage,
-            vk::DependencyFlags::empty(),
-            &[],
-            &[],
-            image_barriers,
-        );
+    single_time_command(device, command_pool, |_, command_buffer| {
+        cmd_image_barriers(device, command_buffer, &[barrier]);
     })
 }
 
@@ -544,56 +1095,49 @@ pub unsafe fn single_time_command<F: FnOnce(&ash::Device, vk::CommandBuffer)>(
     // create command buffer
     let command_buffer = device
         .create_command_buffers(&command_pool, 1)
-        .map_err(|e| format!("create command buffer: {:?}", e))?[0];
+        .context("create command buffer")?[0];
 
     // record command buffer
-    record_commandbuffer(device, command_buffer, f)
-        .map_err(|e| format!("record commandbuffer: {:?}", e))?;
-
-    // prepare submits
-    let submits = [vk::SubmitInfo::builder()
-        .command_buffers(&[command_buffer])
-        .build()];
+    record_commandbuffer(device, command_buffer, f).context("record commandbuffer")?;
 
     // submit command buffer to queue
-    device
-        .queue_submit(*device.graphics_queue(), &submits, vk::Fence::null())
-        .map_err(|e| format!("queue submit: {:?}", e))?;
+    queue_submit(device, &[command_buffer], &[], &[], vk::Fence::null()).context("queue submit")?;
+
+    // NOTE: the command buffer is freed once the next frame to be submitted
+    //       is done, which is submitted after it
+    device.defer_destroy(Resource::CommandBuffer(command_pool, command_buffer));
+
     Ok(())
 }
 
-unsafe fn immediate_submit<F: FnOnce(&ash::Device, vk::CommandBuffer)>(
+unsafe fn immediate_submit(
     device: &Device,
-    command_buffer: vk::CommandBuffer,
+    command_buffers: &[vk::CommandBuffer],
     render_fence: vk::Fence,
-    render_semaphore: vk::Semaphore,
-    present_semaphore: vk::Semaphore,
-    f: F,
+    wait_semaphores: &[SemaphoreStage],
+    signal_semaphores: &[SemaphoreStage],
 ) -> Result<()> {
-    // record command buffer
-    record_commandbuffer(device, command_buffer, f)
-        .map_err(|e| format!("record commandbuffer: {:?}", e))?;
-
     // wait and reset fences
-    device
-        .wait_for_fences(&[render_fence], true, std::u64::MAX)
-        .map_err(|e| format!("wait for fences: {:?}", e))?;
+    {
+        profiling::scope!("wait for render fence");
+        device
+            .wait_for_fences(&[render_fence], true, std::u64::MAX)
+            .context("wait for fences")?;
+    }
     device
         .reset_fences(&[render_fence])
-        .map_err(|e| format!("reset fences: {:?}", e))?;
-
-    // prepare submits
-    let submits = [vk::SubmitInfo::builder()
-        .wait_semaphores(&[present_semaphore])
-        .wait_dst_stage_mask(&[vk::PipelineStageFlags::BOTTOM_OF_PIPE])
-        .command_buffers(&[command_buffer])
-        .signal_semaphores(&[render_semaphore])
-        .build()];
+        .context("reset fences")?;
 
     // submit command buffer to queue
-    device
-        .queue_submit(*device.graphics_queue(), &submits, render_fence)
-        .map_err(|e| format!("queue submit: {:?}", e))?;
+    profiling::scope!("queue submit");
+    queue_submit(
+        device,
+        command_buffers,
+        wait_semaphores,
+        signal_semaphores,
+        render_fence,
+    )
+    .context("queue submit")?;
     Ok(())
 }
 
@@ -607,7 +1151,7 @@ unsafe fn record_commandbuffer<F: FnOnce(&ash::Device, vk::CommandBuffer)>(
         vk::CommandBufferBeginInfo::builder().flags(vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT);
     device
         .begin_command_buffer(command_buffer, &command_buffer_begin_info)
-        .map_err(|e| format!("begin commandbuffer: {:?}", e))?;
+        .context("begin commandbuffer")?;
 
     // record command buffer
     f(device, command_buffer);
@@ -615,7 +1159,7 @@ unsafe fn record_commandbuffer<F: FnOnce(&ash::Device, vk::CommandBuffer)>(
     // end command buffer
     device
         .end_command_buffer(command_buffer)
-        .map_err(|e| format!("end commandbuffer: {:?}", e))?;
+        .context("end commandbuffer")?;
 
     Ok(())
 }
@@ -634,7 +1178,7 @@ pub fn create_viewport_and_scissor(extent: vk::Extent2D) -> (vk::Viewport, vk::R
     (viewport, scissor)
 }
 
-unsafe fn create_framebuffers(
+pub(crate) unsafe fn create_framebuffers(
     device: &Device,
     renderpass: &vk::RenderPass,
     present_image_views: &[vk::ImageView],
@@ -642,7 +1186,7 @@ unsafe fn create_framebuffers(
     surface_resolution: vk::Extent2D,
 ) -> Result<Vec<vk::Framebuffer>> {
     let mut framebuffers = Vec::new();
-    for image_view in present_image_views {
+    for (idx, image_view) in present_image_views.iter().enumerate() {
         let framebuffer_attachments = [*image_view, *depth_image_view];
         let framebuffer_create_info = vk::FramebufferCreateInfo::builder()
             .render_pass(*renderpass)
@@ -652,17 +1196,22 @@ unsafe fn create_framebuffers(
             .layers(1);
         let framebuffer = device
             .create_framebuffer(&framebuffer_create_info, None)
-            .map_err(|e| format!("create framebuffer: {:?}", e))?;
+            .context("create framebuffer")?;
+        device.set_object_name(framebuffer, &format!("framebuffer {idx}"));
         framebuffers.push(framebuffer);
     }
 
     Ok(framebuffers)
 }
 
-unsafe fn create_depth_image(device: &Device, extent: vk::Extent3D) -> Result<Image> {
+pub(crate) unsafe fn create_depth_image(
+    device: &Device,
+    extent: vk::Extent3D,
+    format: vk::Format,
+) -> Result<Image> {
     let create_info = vk::ImageCreateInfo::builder()
         .image_type(vk::ImageType::TYPE_2D)
-        .format(vk::Format::D16_UNORM)
+        .format(format)
         .extent(extent)
         .mip_levels(1)
         .array_layers(1)
@@ -670,24 +1219,30 @@ unsafe fn create_depth_image(device: &Device, extent: vk::Extent3D) -> Result<Im
         .tiling(vk::ImageTiling::OPTIMAL)
         .usage(vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT)
         .sharing_mode(vk::SharingMode::EXCLUSIVE);
-    let image = Image::new(
-        device,
-        device.memory_properties(),
-        *create_info,
-        vk::MemoryPropertyFlags::DEVICE_LOCAL,
-    )
-    .map_err(|e| format!("create image: {:?}", e))?;
+    let image = Image::new(device, *create_info, vk::MemoryPropertyFlags::DEVICE_LOCAL)
+        .context("create image")?;
+    device.set_object_name(*image, "depth image");
 
     Ok(image)
 }
 
-unsafe fn create_depth_image_view(
+pub(crate) unsafe fn create_depth_image_view(
     device: &Device,
     image: &vk::Image,
     image_format: &vk::Format,
 ) -> Result<vk::ImageView> {
+    // NOTE: views of formats with a stencil component used as attachments
+    //       must include it
+    let aspect_mask = match *image_format {
+        vk::Format::D16_UNORM_S8_UINT
+        | vk::Format::D24_UNORM_S8_UINT
+        | vk::Format::D32_SFLOAT_S8_UINT => {
+            vk::ImageAspectFlags::DEPTH | vk::ImageAspectFlags::STENCIL
+        }
+        _ => vk::ImageAspectFlags::DEPTH,
+    };
     let subresource_range = vk::ImageSubresourceRange::builder()
-        .aspect_mask(vk::ImageAspectFlags::DEPTH)
+        .aspect_mask(aspect_mask)
         .level_count(1)
         .layer_count(1);
     let create_image_view_info = vk::ImageViewCreateInfo::builder()
@@ -699,6 +1254,7 @@ unsafe fn create_depth_image_view(
     let image_view = device
         .create_image_view(&create_image_view_info, None)
         .unwrap();
+    device.set_object_name(image_view, "depth image view");
 
     Ok(image_view)
 }
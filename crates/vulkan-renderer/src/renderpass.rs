@@ -3,8 +3,20 @@ use std::ops::Deref;
 use ash::vk;
 
 use super::device::Device;
+use crate::error::ResultExt;
 use crate::Result;
 
+/// What a render pass does with its color attachment when it begins. The
+/// depth attachment is always cleared.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ColorLoad {
+    /// Clears the attachment to the clear color of the pass.
+    #[default]
+    Clear,
+    /// Keeps the content of the attachment, to draw over the previous frame.
+    Load,
+}
+
 pub struct RenderPass {
     handle: vk::RenderPass,
 
@@ -12,10 +24,49 @@ pub struct RenderPass {
 }
 
 impl RenderPass {
-    pub unsafe fn new(device: &Device, image_format: &vk::Format) -> Result<Self> {
-        let renderpass = create_renderpass(device, image_format)
-            .map_err(|e| format!("create renderpass: {:?}", e))?;
+    /// Creates the render pass drawing to the swapchain images, which are
+    /// left ready to be presented, or to be read back on a headless device.
+    pub unsafe fn new(
+        device: &Device,
+        image_format: &vk::Format,
+        depth_format: vk::Format,
+    ) -> Result<Self> {
+        let renderpass = create_renderpass(
+            device,
+            image_format,
+            depth_format,
+            ColorLoad::Clear,
+            device.present_layout(),
+        )
+        .context("create renderpass")?;
+        device.set_object_name(renderpass, "main renderpass");
+        Ok(Self::from_handle(renderpass))
+    }
+
+    /// Creates a render pass drawing to an image that is sampled by later
+    /// passes. It is compatible with the main render pass when the color and
+    /// depth formats match, whatever the color load.
+    ///
+    /// With `ColorLoad::Load`, the image must have been drawn to before, see
+    /// `RenderTarget`.
+    pub unsafe fn offscreen(
+        device: &Device,
+        image_format: &vk::Format,
+        depth_format: vk::Format,
+        color_load: ColorLoad,
+    ) -> Result<Self> {
+        let renderpass = create_renderpass(
+            device,
+            image_format,
+            depth_format,
+            color_load,
+            vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+        )
+        .context("create offscreen renderpass")?;
+        Ok(Self::from_handle(renderpass))
+    }
 
+    fn from_handle(renderpass: vk::RenderPass) -> Self {
         // renderpass clear values
         let clear_values = vec![
             vk::ClearValue {
@@ -31,10 +82,25 @@ impl RenderPass {
             },
         ];
 
-        Ok(Self {
+        Self {
             handle: renderpass,
             clear_values,
-        })
+        }
+    }
+
+    /// Returns the color the color attachment is cleared to, transparent
+    /// black by default.
+    pub fn clear_color(&self) -> [f32; 4] {
+        // SAFETY: the first clear value is always a color
+        unsafe { self.clear_values[0].color.float32 }
+    }
+
+    /// Changes the color the color attachment is cleared to, from the next
+    /// time the pass begins. It is ignored by passes loading their content.
+    pub fn set_clear_color(&mut self, color: [f32; 4]) {
+        self.clear_values[0] = vk::ClearValue {
+            color: vk::ClearColorValue { float32: color },
+        };
     }
 
     pub unsafe fn begin(
@@ -73,22 +139,33 @@ impl Deref for RenderPass {
 unsafe fn create_renderpass(
     device: &Device,
     color_image_format: &vk::Format,
+    depth_format: vk::Format,
+    color_load: ColorLoad,
+    final_layout: vk::ImageLayout,
 ) -> Result<vk::RenderPass> {
+    // NOTE: loaded images are left in their final layout by the previous pass
+    let (load_op, initial_layout) = match color_load {
+        ColorLoad::Clear => (vk::AttachmentLoadOp::CLEAR, vk::ImageLayout::UNDEFINED),
+        ColorLoad::Load => (vk::AttachmentLoadOp::LOAD, final_layout),
+    };
     let renderpass_attachments = [
         // Color
         vk::AttachmentDescription {
             format: *color_image_format,
             samples: vk::SampleCountFlags::TYPE_1,
-            load_op: vk::AttachmentLoadOp::CLEAR,
+            load_op,
             store_op: vk::AttachmentStoreOp::STORE,
-            final_layout: vk::ImageLayout::PRESENT_SRC_KHR,
+            initial_layout,
+            final_layout,
             ..Default::default()
         },
         // Depth
         vk::AttachmentDescription {
-            format: vk::Format::D16_UNORM,
+            format: depth_format,
             samples: vk::SampleCountFlags::TYPE_1,
             load_op: vk::AttachmentLoadOp::CLEAR,
+            stencil_load_op: vk::AttachmentLoadOp::DONT_CARE,
+            stencil_store_op: vk::AttachmentStoreOp::DONT_CARE,
             initial_layout: vk::ImageLayout::UNDEFINED,
             final_layout: vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL,
             ..Default::default()
@@ -119,17 +196,33 @@ unsafe fn create_renderpass(
         [subpass]
     };
 
-    let dependencies = [vk::SubpassDependency {
-        src_subpass: vk::SUBPASS_EXTERNAL,
-        src_stage_mask: vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT
-            | vk::PipelineStageFlags::EARLY_FRAGMENT_TESTS,
-        dst_stage_mask: vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT
-            | vk::PipelineStageFlags::EARLY_FRAGMENT_TESTS,
-        dst_access_mask: vk::AccessFlags::COLOR_ATTACHMENT_WRITE
-            | vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE,
-        ..Default::default()
-    }];
-
+    let dependencies = [
+        // NOTE: waits for the previous frames to be done sampling the image
+        //       before it is written again
+        vk::SubpassDependency {
+            src_subpass: vk::SUBPASS_EXTERNAL,
+            src_stage_mask: vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT
+                | vk::PipelineStageFlags::EARLY_FRAGMENT_TESTS
+                | vk::PipelineStageFlags::FRAGMENT_SHADER,
+            dst_stage_mask: vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT
+                | vk::PipelineStageFlags::EARLY_FRAGMENT_TESTS,
+            dst_access_mask: vk::AccessFlags::COLOR_ATTACHMENT_WRITE
+                | vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE,
+            ..Default::default()
+        },
+        // make the color writes visible to the passes sampling the image
+        // NOTE: also declared by the main render pass, render passes with
+        //       different dependencies not being compatible
+        vk::SubpassDependency {
+            src_subpass: 0,
+            dst_subpass: vk::SUBPASS_EXTERNAL,
+            src_stage_mask: vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+            dst_stage_mask: vk::PipelineStageFlags::FRAGMENT_SHADER,
+            src_access_mask: vk::AccessFlags::COLOR_ATTACHMENT_WRITE,
+            dst_access_mask: vk::AccessFlags::SHADER_READ,
+            ..Default::default()
+        },
+    ];
     let renderpass_create_info = vk::RenderPassCreateInfo::builder()
         .attachments(&renderpass_attachments)
         .subpasses(&subpasses)
@@ -137,7 +230,7 @@ unsafe fn create_renderpass(
 
     let renderpass = device
         .create_render_pass(&renderpass_create_info, None)
-        .map_err(|e| format!("create renderpass: {:?}", e))?;
+        .context("create renderpass")?;
 
     Ok(renderpass)
 }
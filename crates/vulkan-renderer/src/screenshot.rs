@@ -0,0 +1,287 @@
+use std::path::{Path, PathBuf};
+use std::thread;
+
+use ash::vk;
+use log::{error, info};
+
+use super::buffer::Buffer;
+use super::device::Device;
+use super::renderer::single_time_command;
+use crate::error::ResultExt;
+use crate::Result;
+
+/// Swapchain image copied to a host visible buffer during a frame, written to
+/// a PNG file once the frame has completed.
+pub(crate) struct Screenshot {
+    path: PathBuf,
+    buffer: Buffer,
+    extent: vk::Extent2D,
+    format: vk::Format,
+
+    /// Frame the image is copied in.
+    frame: u32,
+    /// Set once the copy has been recorded in the frame commands.
+    recorded: bool,
+}
+
+impl Screenshot {
+    /// Returns true if images of the given format can be written to a PNG.
+    pub fn supports_format(format: vk::Format) -> bool {
+        matches!(
+            format,
+            vk::Format::B8G8R8A8_SRGB
+                | vk::Format::B8G8R8A8_UNORM
+                | vk::Format::R8G8B8A8_SRGB
+                | vk::Format::R8G8B8A8_UNORM
+        )
+    }
+
+    /// Creates the buffer receiving the image of the given frame.
+    pub unsafe fn new(
+        device: &Device,
+        path: PathBuf,
+        extent: vk::Extent2D,
+        format: vk::Format,
+        frame: u32,
+    ) -> Result<Self> {
+        let size = extent.width as u64 * extent.height as u64 * 4;
+        let buffer = Buffer::new(
+            device,
+            vk::BufferUsageFlags::TRANSFER_DST,
+            vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT,
+            size,
+        )
+        .context("create screenshot buffer")?;
+        device.set_object_name(*buffer, "screenshot buffer");
+
+        Ok(Self {
+            path,
+            buffer,
+            extent,
+            format,
+            frame,
+            recorded: false,
+        })
+    }
+
+    pub fn frame(&self) -> u32 {
+        self.frame
+    }
+
+    /// Copies the image, which has just been rendered to and is in the given
+    /// layout, to the buffer. Must be recorded outside of a render pass.
+    pub unsafe fn record(
+        &mut self,
+        device: &ash::Device,
+        command_buffer: vk::CommandBuffer,
+        image: vk::Image,
+        layout: vk::ImageLayout,
+    ) {
+        cmd_copy_image_to_host(
+            device,
+            command_buffer,
+            image,
+            layout,
+            self.extent,
+            *self.buffer,
+        );
+        self.recorded = true;
+    }
+
+    /// Writes the copied image to its file from a background thread, which is
+    /// returned. The frame the image was copied in must have completed.
+    pub unsafe fn write(self) -> Result<thread::JoinHandle<()>> {
+        if !self.recorded {
+            return Err("frame was not drawn".into());
+        }
+
+        let pixels =
+            read_rgba(&self.buffer, self.extent, self.format).context("read screenshot buffer")?;
+
+        let Self { path, extent, .. } = self;
+        thread::Builder::new()
+            .name("screenshot writer".into())
+            .spawn(move || write_png(&path, &pixels, extent))
+            .context("spawn screenshot writer")
+    }
+}
+
+/// Records the copy of an image, which has just been rendered to and is in
+/// the given layout, to a host visible buffer. The image is left in its
+/// layout. Must be recorded outside of a render pass.
+unsafe fn cmd_copy_image_to_host(
+    device: &ash::Device,
+    command_buffer: vk::CommandBuffer,
+    image: vk::Image,
+    layout: vk::ImageLayout,
+    extent: vk::Extent2D,
+    buffer: vk::Buffer,
+) {
+    let subresource_range = vk::ImageSubresourceRange::builder()
+        .aspect_mask(vk::ImageAspectFlags::COLOR)
+        .level_count(1)
+        .layer_count(1)
+        .build();
+
+    // wait for the render pass to write the image
+    let to_transfer = [vk::ImageMemoryBarrier::builder()
+        .src_access_mask(vk::AccessFlags::COLOR_ATTACHMENT_WRITE)
+        .dst_access_mask(vk::AccessFlags::TRANSFER_READ)
+        .old_layout(layout)
+        .new_layout(vk::ImageLayout::TRANSFER_SRC_OPTIMAL)
+        .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+        .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+        .image(image)
+        .subresource_range(subresource_range)
+        .build()];
+    device.cmd_pipeline_barrier(
+        command_buffer,
+        vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+        vk::PipelineStageFlags::TRANSFER,
+        vk::DependencyFlags::empty(),
+        &[],
+        &[],
+        &to_transfer,
+    );
+
+    let regions = [vk::BufferImageCopy {
+        image_subresource: vk::ImageSubresourceLayers {
+            aspect_mask: vk::ImageAspectFlags::COLOR,
+            mip_level: 0,
+            base_array_layer: 0,
+            layer_count: 1,
+        },
+        image_extent: vk::Extent3D {
+            width: extent.width,
+            height: extent.height,
+            depth: 1,
+        },
+        buffer_offset: 0,
+        buffer_image_height: 0,
+        buffer_row_length: 0,
+        image_offset: vk::Offset3D::default(),
+    }];
+    device.cmd_copy_image_to_buffer(
+        command_buffer,
+        image,
+        vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+        buffer,
+        &regions,
+    );
+
+    // give the image back in its layout, e.g. to the presentation engine,
+    // and make the copy visible to the host
+    let to_present = [vk::ImageMemoryBarrier::builder()
+        .src_access_mask(vk::AccessFlags::TRANSFER_READ)
+        .dst_access_mask(vk::AccessFlags::empty())
+        .old_layout(vk::ImageLayout::TRANSFER_SRC_OPTIMAL)
+        .new_layout(layout)
+        .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+        .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+        .image(image)
+        .subresource_range(subresource_range)
+        .build()];
+    let to_host = [vk::BufferMemoryBarrier::builder()
+        .src_access_mask(vk::AccessFlags::TRANSFER_WRITE)
+        .dst_access_mask(vk::AccessFlags::HOST_READ)
+        .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+        .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+        .buffer(buffer)
+        .size(vk::WHOLE_SIZE)
+        .build()];
+    device.cmd_pipeline_barrier(
+        command_buffer,
+        vk::PipelineStageFlags::TRANSFER,
+        vk::PipelineStageFlags::BOTTOM_OF_PIPE | vk::PipelineStageFlags::HOST,
+        vk::DependencyFlags::empty(),
+        &[],
+        &to_host,
+        &to_present,
+    );
+}
+
+/// Copies an image to host memory and returns its pixels in RGBA, as they
+/// would be presented. The image must be in the given layout and the frames
+/// writing it must have completed. Waits for the device to be idle.
+pub(crate) unsafe fn read_image(
+    device: &Device,
+    command_pool: vk::CommandPool,
+    image: vk::Image,
+    layout: vk::ImageLayout,
+    extent: vk::Extent2D,
+    format: vk::Format,
+) -> Result<Vec<u8>> {
+    if !Screenshot::supports_format(format) {
+        return Err(format!("unsupported image format {format:?}").into());
+    }
+    let size = extent.width as u64 * extent.height as u64 * 4;
+    let buffer = Buffer::new(
+        device,
+        vk::BufferUsageFlags::TRANSFER_DST,
+        vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT,
+        size,
+    )
+    .context("create readback buffer")?;
+
+    single_time_command(device, command_pool, |device, cb| {
+        cmd_copy_image_to_host(device, cb, image, layout, extent, *buffer);
+    })
+    .context("copy image")?;
+    device.device_wait_idle().context("device wait idle")?;
+
+    read_rgba(&buffer, extent, format)
+}
+
+/// Returns the pixels of an image copied to a buffer, converted to opaque
+/// RGBA.
+unsafe fn read_rgba(buffer: &Buffer, extent: vk::Extent2D, format: vk::Format) -> Result<Vec<u8>> {
+    let size = extent.width as usize * extent.height as usize * 4;
+    let mut pixels = buffer.mapped_bytes()?[..size].to_vec();
+    let bgra = matches!(
+        format,
+        vk::Format::B8G8R8A8_SRGB | vk::Format::B8G8R8A8_UNORM
+    );
+    to_opaque_rgba(&mut pixels, bgra);
+    Ok(pixels)
+}
+
+/// Converts the pixels to RGBA, ignoring the alpha written by the frame since
+/// the swapchain is presented as opaque.
+fn to_opaque_rgba(pixels: &mut [u8], bgra: bool) {
+    for pixel in pixels.chunks_exact_mut(4) {
+        if bgra {
+            pixel.swap(0, 2);
+        }
+        pixel[3] = u8::MAX;
+    }
+}
+
+fn write_png(path: &Path, pixels: &[u8], extent: vk::Extent2D) {
+    match ::image::save_buffer_with_format(
+        path,
+        pixels,
+        extent.width,
+        extent.height,
+        ::image::ColorType::Rgba8,
+        ::image::ImageFormat::Png,
+    ) {
+        Ok(()) => info!("screenshot saved to {}", path.display()),
+        Err(e) => error!("write screenshot to {}: {e}", path.display()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pixels_are_converted_to_opaque_rgba() {
+        let mut pixels = [1, 2, 3, 0, 5, 6, 7, 128];
+        to_opaque_rgba(&mut pixels, true);
+        assert_eq!(pixels, [3, 2, 1, 255, 7, 6, 5, 255]);
+
+        let mut pixels = [1, 2, 3, 0];
+        to_opaque_rgba(&mut pixels, false);
+        assert_eq!(pixels, [1, 2, 3, 255]);
+    }
+}
@@ -1,12 +1,29 @@
 use std::io;
+use std::mem;
 use std::ops::Deref;
 
 use ash::util::read_spv;
 use ash::vk;
 
+use super::deletion::{DeletionQueueHandle, Resource};
+use super::device::Device;
+use crate::error::ResultExt;
 use crate::Result;
 
-#[derive(Clone, Copy, Debug, PartialEq, PartialOrd)]
+/// Includes the SPIR-V compiled by the build script of the calling crate, by
+/// the file name of its GLSL source.
+///
+/// ```ignore
+/// let mut spv_file = Cursor::new(&include_shader!("quad.vert")[..]);
+/// ```
+#[macro_export]
+macro_rules! include_shader {
+    ($name:literal) => {
+        include_bytes!(concat!(env!("OUT_DIR"), "/", $name, ".spv"))
+    };
+}
+
+#[derive(Debug)]
 pub struct Shader {
     /// Shader modules contain shader code and one or more entry points. Shaders
     /// are selected from a shader module by specifying an entry point as part
@@ -18,34 +35,33 @@ pub struct Shader {
     /// appendix.
     pub handle: vk::ShaderModule,
 
-    destroyed: bool,
+    deletion_queue: DeletionQueueHandle,
 }
 
 impl Shader {
-    pub unsafe fn new<R>(device: &ash::Device, cursor: &mut R) -> Result<Self>
+    pub unsafe fn new<R>(device: &Device, cursor: &mut R) -> Result<Self>
     where
         R: io::Read + io::Seek,
     {
-        let code = read_spv(cursor)
-            .map_err(|e| format!("failed to read shader spv from cursor: {:?}", e))?;
+        let code = read_spv(cursor).context("failed to read shader spv from cursor")?;
         let shader_info = vk::ShaderModuleCreateInfo::builder().code(&code);
 
         let shader_module = device
             .create_shader_module(&shader_info, None)
-            .map_err(|e| format!("shader module error: {:?}", e))?;
+            .context("shader module error")?;
 
         Ok(Self {
             handle: shader_module,
-            destroyed: false,
+            deletion_queue: device.deletion_queue(),
         })
     }
+}
 
-    pub unsafe fn destroy(&mut self, device: &ash::Device) {
-        if self.destroyed {
-            panic!("shader already destroyed")
-        }
-        device.destroy_shader_module(self.handle, None);
-        self.destroyed = true;
+impl Drop for Shader {
+    fn drop(&mut self) {
+        self.deletion_queue
+            .borrow_mut()
+            .push(Resource::ShaderModule(self.handle));
     }
 }
 
@@ -56,3 +72,110 @@ impl Deref for Shader {
         &self.handle
     }
 }
+
+/// Scalar value of a specialization constant.
+pub trait SpecializationConstant: Copy {
+    /// Returns the value as laid out in the specialization data.
+    fn to_ne_bytes(self) -> [u8; 4];
+}
+
+impl SpecializationConstant for bool {
+    fn to_ne_bytes(self) -> [u8; 4] {
+        vk::Bool32::from(self).to_ne_bytes()
+    }
+}
+
+impl SpecializationConstant for u32 {
+    fn to_ne_bytes(self) -> [u8; 4] {
+        u32::to_ne_bytes(self)
+    }
+}
+
+impl SpecializationConstant for i32 {
+    fn to_ne_bytes(self) -> [u8; 4] {
+        i32::to_ne_bytes(self)
+    }
+}
+
+impl SpecializationConstant for f32 {
+    fn to_ne_bytes(self) -> [u8; 4] {
+        f32::to_ne_bytes(self)
+    }
+}
+
+/// Values given to the specialization constants of the shaders of a
+/// pipeline, declared in GLSL with `layout (constant_id = N)`. Variants of a
+/// shader are created from a single SPIR-V module, the constants left unset
+/// keeping their default value.
+#[derive(Clone, Debug, Default)]
+pub struct Specialization {
+    entries: Vec<vk::SpecializationMapEntry>,
+    data: Vec<u8>,
+}
+
+impl Specialization {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the value of the constant, replacing the previous one if any.
+    pub fn with<T: SpecializationConstant>(mut self, constant_id: u32, value: T) -> Self {
+        let bytes = value.to_ne_bytes();
+        match self.entries.iter().find(|e| e.constant_id == constant_id) {
+            Some(entry) => {
+                let offset = entry.offset as usize;
+                self.data[offset..offset + bytes.len()].copy_from_slice(&bytes);
+            }
+            None => {
+                self.entries.push(vk::SpecializationMapEntry {
+                    constant_id,
+                    offset: self.data.len() as u32,
+                    size: mem::size_of_val(&bytes),
+                });
+                self.data.extend_from_slice(&bytes);
+            }
+        }
+        self
+    }
+
+    /// Returns true if no constant is set.
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Returns the info given to the shader stages of a pipeline.
+    pub fn info(&self) -> vk::SpecializationInfoBuilder<'_> {
+        vk::SpecializationInfo::builder()
+            .map_entries(&self.entries)
+            .data(&self.data)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn specialization_constants_are_packed() {
+        let specialization = Specialization::new()
+            .with(0, true)
+            .with(2, 1.5f32)
+            .with(1, 7u32)
+            .with(0, false);
+
+        let offsets: Vec<_> = specialization
+            .entries
+            .iter()
+            .map(|e| (e.constant_id, e.offset, e.size))
+            .collect();
+        assert_eq!(offsets, [(0, 0, 4), (2, 4, 4), (1, 8, 4)]);
+        assert_eq!(&specialization.data[0..4], &vk::FALSE.to_ne_bytes());
+        assert_eq!(&specialization.data[4..8], &1.5f32.to_ne_bytes());
+        assert_eq!(&specialization.data[8..12], &7u32.to_ne_bytes());
+
+        let info = specialization.info();
+        assert_eq!(info.map_entry_count, 3);
+        assert_eq!(info.data_size, 12);
+        assert!(Specialization::new().is_empty());
+    }
+}
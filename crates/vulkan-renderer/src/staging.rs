@@ -0,0 +1,358 @@
+use std::{mem, slice};
+
+use ash::vk;
+use log::debug;
+
+use super::buffer::Buffer;
+use super::device::Device;
+use crate::error::ResultExt;
+use crate::Result;
+
+/// Initial size of the staging region reserved for each frame in flight. The
+/// regions grow when the uploads of a frame do not fit.
+pub const DEFAULT_STAGING_REGION_SIZE: u64 = 8 * 1024 * 1024;
+
+/// Alignment of the data written to the ring. It satisfies the
+/// optimalBufferCopyOffsetAlignment of most devices and the texel size of the
+/// formats we upload.
+const STAGING_ALIGNMENT: u64 = 16;
+
+/// Where the data of a copy is staged.
+#[derive(Clone, Copy)]
+enum Source {
+    /// In the given staging buffer.
+    Ring(vk::Buffer),
+    /// In the overflow bytes, until the ring grows.
+    Overflow,
+}
+
+struct BufferCopy {
+    src: Source,
+    dst: vk::Buffer,
+    region: vk::BufferCopy,
+}
+
+struct ImageCopy {
+    src: Source,
+    image: vk::Image,
+    region: vk::BufferImageCopy,
+}
+
+/// Persistently mapped staging buffer split in one region per frame in flight.
+///
+/// Uploads made during a frame are written to the region of that frame and
+/// the corresponding copies are recorded ahead of the frame commands, so that
+/// dynamic data can reach device local memory without creating staging
+/// buffers or waiting for the device to be idle. A region is only reused once
+/// the fence of the frame that last used it has been waited on.
+///
+/// Uploads that do not fit in the region are kept on the host until the
+/// copies are recorded, when the ring is replaced by one whose regions can
+/// hold all the uploads of the frame.
+pub struct StagingRing {
+    buffer: Buffer,
+    region_size: u64,
+    regions: u32,
+
+    /// Region used by the current frame and write offset within it.
+    region: u32,
+    offset: u64,
+    overflow: Vec<u8>,
+
+    buffer_copies: Vec<BufferCopy>,
+    image_copies: Vec<ImageCopy>,
+}
+
+impl StagingRing {
+    pub unsafe fn new(device: &Device, region_size: u64, regions: u32) -> Result<Self> {
+        Ok(Self {
+            buffer: create_buffer(device, region_size, regions)?,
+            region_size,
+            regions,
+            region: 0,
+            offset: 0,
+            overflow: Vec::new(),
+            buffer_copies: Vec::new(),
+            image_copies: Vec::new(),
+        })
+    }
+
+    /// Switches to the region of the given frame. The frame must not be in
+    /// use by the GPU anymore.
+    pub fn begin_frame(&mut self, region: u32) {
+        self.region = region;
+        self.offset = 0;
+        self.overflow.clear();
+        self.buffer_copies.clear();
+        self.image_copies.clear();
+    }
+
+    /// Queues a copy of data into dst at the given offset (in bytes).
+    pub unsafe fn copy_to_buffer<T: Copy>(
+        &mut self,
+        data: &[T],
+        dst: vk::Buffer,
+        dst_offset: u64,
+    ) -> Result<()> {
+        let (src, src_offset, size) = self.write(data)?;
+        self.buffer_copies.push(BufferCopy {
+            src,
+            dst,
+            region: vk::BufferCopy {
+                src_offset,
+                dst_offset,
+                size,
+            },
+        });
+        Ok(())
+    }
+
+    /// Queues a copy of data into the whole color image. The image is left in
+    /// the SHADER_READ_ONLY_OPTIMAL layout and its previous content is
+    /// discarded.
+    pub unsafe fn copy_to_image<T: Copy>(
+        &mut self,
+        data: &[T],
+        image: vk::Image,
+        width: u32,
+        height: u32,
+    ) -> Result<()> {
+        let (src, buffer_offset, _) = self.write(data)?;
+        self.image_copies.push(ImageCopy {
+            src,
+            image,
+            region: vk::BufferImageCopy {
+                buffer_offset,
+                buffer_row_length: 0,
+                buffer_image_height: 0,
+                image_subresource: vk::ImageSubresourceLayers {
+                    aspect_mask: vk::ImageAspectFlags::COLOR,
+                    mip_level: 0,
+                    base_array_layer: 0,
+                    layer_count: 1,
+                },
+                image_offset: vk::Offset3D::default(),
+                image_extent: vk::Extent3D {
+                    width,
+                    height,
+                    depth: 1,
+                },
+            },
+        });
+        Ok(())
+    }
+
+    /// Returns true if copies are waiting to be recorded.
+    pub fn has_pending(&self) -> bool {
+        !self.buffer_copies.is_empty() || !self.image_copies.is_empty()
+    }
+
+    /// Replaces the ring by a larger one if uploads of the current frame did
+    /// not fit in its region, and writes them there. Must be called before
+    /// `record()` once the frame is recorded.
+    pub unsafe fn grow(&mut self, device: &Device) -> Result<()> {
+        if self.overflow.is_empty() {
+            return Ok(());
+        }
+        profiling::scope!("StagingRing.grow");
+
+        let region_size =
+            grown_region_size(self.region_size, self.offset + self.overflow.len() as u64);
+        debug!(
+            "growing staging regions from {} to {} bytes",
+            self.region_size, region_size
+        );
+        // NOTE: the previous buffer is destroyed once the frames using it,
+        //       this one included, are done
+        self.buffer = create_buffer(device, region_size, self.regions)?;
+        self.region_size = region_size;
+
+        let base = self.region as u64 * region_size;
+        self.buffer
+            .update_at(base, &self.overflow)
+            .context("write staging buffer")?;
+        self.offset = self.overflow.len() as u64;
+        self.overflow.clear();
+
+        let buffer = *self.buffer;
+        let resolve = |src: &mut Source, offset: &mut u64| {
+            if let Source::Overflow = src {
+                *src = Source::Ring(buffer);
+                *offset += base;
+            }
+        };
+        for copy in &mut self.buffer_copies {
+            resolve(&mut copy.src, &mut copy.region.src_offset);
+        }
+        for copy in &mut self.image_copies {
+            resolve(&mut copy.src, &mut copy.region.buffer_offset);
+        }
+
+        Ok(())
+    }
+
+    /// Records the queued copies, surrounded by the barriers needed to use
+    /// the destinations in the commands submitted afterwards.
+    pub unsafe fn record(&mut self, device: &ash::Device, command_buffer: vk::CommandBuffer) {
+        if !self.has_pending() {
+            return;
+        }
+        profiling::scope!("StagingRing.record");
+        debug_assert!(self.overflow.is_empty(), "staging ring must grow first");
+
+        let shader_stages = vk::PipelineStageFlags::VERTEX_INPUT
+            | vk::PipelineStageFlags::VERTEX_SHADER
+            | vk::PipelineStageFlags::FRAGMENT_SHADER;
+
+        // previous frames may still read the destinations
+        let subresource_range = vk::ImageSubresourceRange::builder()
+            .aspect_mask(vk::ImageAspectFlags::COLOR)
+            .level_count(1)
+            .layer_count(1)
+            .build();
+        let image_barriers: Vec<_> = self
+            .image_copies
+            .iter()
+            .map(|copy| {
+                vk::ImageMemoryBarrier::builder()
+                    .src_access_mask(vk::AccessFlags::empty())
+                    .dst_access_mask(vk::AccessFlags::TRANSFER_WRITE)
+                    .old_layout(vk::ImageLayout::UNDEFINED)
+                    .new_layout(vk::ImageLayout::TRANSFER_DST_OPTIMAL)
+                    .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                    .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                    .image(copy.image)
+                    .subresource_range(subresource_range)
+                    .build()
+            })
+            .collect();
+        device.cmd_pipeline_barrier(
+            command_buffer,
+            shader_stages,
+            vk::PipelineStageFlags::TRANSFER,
+            vk::DependencyFlags::empty(),
+            &[],
+            &[],
+            &image_barriers,
+        );
+
+        // copies
+        for copy in self.buffer_copies.drain(..) {
+            let Source::Ring(src) = copy.src else {
+                continue;
+            };
+            device.cmd_copy_buffer(command_buffer, src, copy.dst, &[copy.region]);
+        }
+        for copy in &self.image_copies {
+            let Source::Ring(src) = copy.src else {
+                continue;
+            };
+            device.cmd_copy_buffer_to_image(
+                command_buffer,
+                src,
+                copy.image,
+                vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                &[copy.region],
+            );
+        }
+
+        // make the copies visible to the frame commands
+        let memory_barriers = [vk::MemoryBarrier::builder()
+            .src_access_mask(vk::AccessFlags::TRANSFER_WRITE)
+            .dst_access_mask(
+                vk::AccessFlags::VERTEX_ATTRIBUTE_READ
+                    | vk::AccessFlags::INDEX_READ
+                    | vk::AccessFlags::UNIFORM_READ
+                    | vk::AccessFlags::SHADER_READ,
+            )
+            .build()];
+        let image_barriers: Vec<_> = self
+            .image_copies
+            .drain(..)
+            .map(|copy| {
+                vk::ImageMemoryBarrier::builder()
+                    .src_access_mask(vk::AccessFlags::TRANSFER_WRITE)
+                    .dst_access_mask(vk::AccessFlags::SHADER_READ)
+                    .old_layout(vk::ImageLayout::TRANSFER_DST_OPTIMAL)
+                    .new_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)
+                    .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                    .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                    .image(copy.image)
+                    .subresource_range(subresource_range)
+                    .build()
+            })
+            .collect();
+        device.cmd_pipeline_barrier(
+            command_buffer,
+            vk::PipelineStageFlags::TRANSFER,
+            shader_stages,
+            vk::DependencyFlags::empty(),
+            &memory_barriers,
+            &[],
+            &image_barriers,
+        );
+    }
+
+    /// Writes data in the current region, or in the overflow bytes if it does
+    /// not fit, and returns where it is staged along with its offset and size.
+    unsafe fn write<T: Copy>(&mut self, data: &[T]) -> Result<(Source, u64, u64)> {
+        profiling::scope!("StagingRing.write");
+        let size = mem::size_of_val(data) as u64;
+        let offset = align_up(self.offset, STAGING_ALIGNMENT);
+        if offset + size > self.region_size {
+            let offset = align_up(self.overflow.len() as u64, STAGING_ALIGNMENT);
+            let bytes = slice::from_raw_parts(data.as_ptr() as *const u8, size as usize);
+            self.overflow.resize(offset as usize, 0);
+            self.overflow.extend_from_slice(bytes);
+            return Ok((Source::Overflow, offset, size));
+        }
+
+        let buffer_offset = self.region as u64 * self.region_size + offset;
+        self.buffer
+            .update_at(buffer_offset, data)
+            .context("write staging buffer")?;
+        self.offset = offset + size;
+
+        Ok((Source::Ring(*self.buffer), buffer_offset, size))
+    }
+}
+
+unsafe fn create_buffer(device: &Device, region_size: u64, regions: u32) -> Result<Buffer> {
+    let buffer = Buffer::new(
+        device,
+        vk::BufferUsageFlags::TRANSFER_SRC,
+        vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT,
+        region_size * regions as u64,
+    )
+    .context("create staging buffer")?;
+    device.set_object_name(*buffer, "staging ring");
+    Ok(buffer)
+}
+
+/// Returns the size of the regions replacing ones that could not hold the
+/// given number of bytes, at least doubling it to grow rarely.
+fn grown_region_size(region_size: u64, used: u64) -> u64 {
+    used.next_power_of_two().max(region_size * 2)
+}
+
+fn align_up(value: u64, alignment: u64) -> u64 {
+    (value + alignment - 1) / alignment * alignment
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn regions_grow_to_hold_the_uploads_of_a_frame() {
+        let region_size = DEFAULT_STAGING_REGION_SIZE;
+        assert_eq!(
+            grown_region_size(region_size, region_size + 1),
+            2 * region_size
+        );
+        assert_eq!(
+            grown_region_size(region_size, 5 * region_size),
+            8 * region_size
+        );
+    }
+}
@@ -2,8 +2,15 @@ use ash::extensions::khr;
 use ash::vk;
 
 use super::device::Device;
+use super::image::Image;
+use super::sync::{queue_submit, SemaphoreStage};
+use crate::error::ResultExt;
 use crate::Result;
 
+/// Formats of the images of a headless swapchain, in order of preference.
+pub const HEADLESS_FORMATS: [vk::Format; 2] =
+    [vk::Format::B8G8R8A8_SRGB, vk::Format::R8G8B8A8_SRGB];
+
 pub struct Swapchain {
     /// A swapchain object (a.k.a. swapchain) provides the ability to present
     /// rendering results to a surface.
@@ -13,6 +20,17 @@ pub struct Swapchain {
     /// The image format of the surface.
     image_format: vk::Format,
 
+    /// Size of the swapchain images, which can differ from the window size.
+    extent: vk::Extent2D,
+
+    /// How images are queued for presentation, see `select_present_mode()`.
+    present_mode: vk::PresentModeKHR,
+
+    /// Set when the images can be copied from, e.g. to take screenshots.
+    transfer_src: bool,
+
+    images: Vec<vk::Image>,
+
     /// Image objects are not directly accessed by pipeline shaders for reading
     /// or writing image data. Instead, image views representing contiguous
     /// ranges of the image subresources and containing additional metadata are
@@ -21,28 +39,96 @@ pub struct Swapchain {
 
     /// The image index returned by a call to acquire_next_image.
     current_image_index: usize,
+
+    /// Images rendered to in place of the swapchain ones on a headless
+    /// device, see `headless()`.
+    offscreen_images: Vec<Image>,
 }
 
 impl Swapchain {
-    pub unsafe fn new(device: &Device, window_extent: vk::Extent2D) -> Result<Self> {
+    /// Creates a swapchain presenting to the surface in MAILBOX mode when
+    /// available, or FIFO mode when vsync is requested.
+    pub unsafe fn new(
+        device: &Device,
+        surface: vk::SurfaceKHR,
+        window_extent: vk::Extent2D,
+        vsync: bool,
+    ) -> Result<Self> {
         // create swapchain
-        let (swapchain, swapchain_loader, images, image_format) =
-            create_swapchain(device, window_extent)
-                .map_err(|e| format!("create swapchain: {:?}", e))?;
+        let mut swapchain =
+            create_swapchain(device, surface, window_extent, vsync).context("create swapchain")?;
 
         // create image views used for writing image data by shaders
+        match create_present_image_views(device, &swapchain.images, swapchain.image_format) {
+            Ok(image_views) => swapchain.present_image_views = image_views,
+            Err(e) => {
+                swapchain.destroy(device);
+                return Err(e).context("create present image views from swapchain");
+            }
+        }
+
+        Ok(swapchain)
+    }
+
+    /// Creates offscreen images standing in for the swapchain of a headless
+    /// device. They are used in turn and never presented, and can be copied
+    /// from once a frame has completed.
+    pub unsafe fn headless(
+        device: &Device,
+        extent: vk::Extent2D,
+        image_count: u32,
+    ) -> Result<Self> {
+        let features =
+            vk::FormatFeatureFlags::COLOR_ATTACHMENT | vk::FormatFeatureFlags::TRANSFER_SRC;
+        let image_format = device
+            .find_supported_format(&HEADLESS_FORMATS, features)
+            .ok_or("find supported headless image format")?;
+
+        let create_info = vk::ImageCreateInfo::builder()
+            .image_type(vk::ImageType::TYPE_2D)
+            .format(image_format)
+            .extent(extent.into())
+            .mip_levels(1)
+            .array_layers(1)
+            .samples(vk::SampleCountFlags::TYPE_1)
+            .tiling(vk::ImageTiling::OPTIMAL)
+            .usage(vk::ImageUsageFlags::COLOR_ATTACHMENT | vk::ImageUsageFlags::TRANSFER_SRC)
+            .sharing_mode(vk::SharingMode::EXCLUSIVE);
+        let mut offscreen_images = Vec::with_capacity(image_count as usize);
+        for index in 0..image_count {
+            let image = Image::new(device, *create_info, vk::MemoryPropertyFlags::DEVICE_LOCAL)
+                .context("create headless image")?;
+            device.set_object_name(*image, &format!("headless image {index}"));
+            offscreen_images.push(image);
+        }
+        let images: Vec<_> = offscreen_images
+            .iter()
+            .map(|image| *image.image())
+            .collect();
+
         let present_image_views = create_present_image_views(device, &images, image_format)
-            .map_err(|e| format!("create present image views from swapchain: {:?}", e))?;
+            .context("create headless image views")?;
 
         Ok(Self {
-            swapchain,
-            swapchain_loader,
+            swapchain: vk::SwapchainKHR::null(),
+            swapchain_loader: khr::Swapchain::new(device.instance(), device),
             image_format,
+            extent,
+            // NOTE: nothing waits for offscreen images to be presented
+            present_mode: vk::PresentModeKHR::IMMEDIATE,
+            transfer_src: true,
+            images,
             present_image_views,
             current_image_index: 0,
+            offscreen_images,
         })
     }
 
+    /// Returns true if the images are offscreen ones, see `headless()`.
+    pub fn is_headless(&self) -> bool {
+        !self.offscreen_images.is_empty()
+    }
+
     pub fn current_index(&self) -> usize {
         self.current_image_index
     }
@@ -55,12 +141,46 @@ impl Swapchain {
         &self.present_image_views
     }
 
+    pub fn extent(&self) -> vk::Extent2D {
+        self.extent
+    }
+
+    /// Returns the present mode: FIFO when frames are paced by the display,
+    /// MAILBOX when they are rendered as fast as possible, the latest one
+    /// being presented.
+    pub fn present_mode(&self) -> vk::PresentModeKHR {
+        self.present_mode
+    }
+
+    /// Returns true if the images can be used as the source of a transfer.
+    pub fn supports_transfer_src(&self) -> bool {
+        self.transfer_src
+    }
+
+    /// The image returned by the last call to acquire_next_image.
+    pub fn current_image(&self) -> vk::Image {
+        self.images[self.current_image_index]
+    }
+
     pub unsafe fn acquire_next_image(
         &mut self,
+        device: &Device,
         timeout: u64,
         semaphore: &vk::Semaphore,
         fence: &vk::Fence,
     ) -> Result<bool> {
+        if self.is_headless() {
+            // NOTE: an empty submission signals the semaphore and the fence
+            //       as the acquisition of a swapchain image would
+            self.current_image_index = (self.current_image_index + 1) % self.images.len();
+            let signal = [SemaphoreStage {
+                semaphore: *semaphore,
+                stage_mask: vk::PipelineStageFlags2::ALL_COMMANDS,
+            }];
+            queue_submit(device, &[], &[], &signal, *fence).context("acquire headless image")?;
+            return Ok(false);
+        }
+
         let suboptimal = match self.swapchain_loader.acquire_next_image(
             self.swapchain,
             timeout,
@@ -73,7 +193,7 @@ impl Swapchain {
             }
             Err(e) => {
                 if e != vk::Result::ERROR_OUT_OF_DATE_KHR {
-                    return Err(format!("acquire image: {:?}", e).into());
+                    return Err(e).context("acquire image");
                 }
                 true
             }
@@ -89,6 +209,21 @@ impl Swapchain {
         device: &Device,
         wait_sempahores: &[vk::Semaphore],
     ) -> Result<bool> {
+        if self.is_headless() {
+            // NOTE: the semaphores are waited on by an empty submission so
+            //       that they are unsignaled before being signaled again
+            let wait: Vec<_> = wait_sempahores
+                .iter()
+                .map(|semaphore| SemaphoreStage {
+                    semaphore: *semaphore,
+                    stage_mask: vk::PipelineStageFlags2::ALL_COMMANDS,
+                })
+                .collect();
+            queue_submit(device, &[], &wait, &[], vk::Fence::null())
+                .context("present headless image")?;
+            return Ok(false);
+        }
+
         // queue image for presentation
         let swapchains = [self.swapchain];
         let image_indices = [self.current_image_index as u32];
@@ -104,7 +239,7 @@ impl Swapchain {
             Ok(suboptimal) => suboptimal,
             Err(e) => match e {
                 vk::Result::ERROR_OUT_OF_DATE_KHR => true,
-                err => return Err(format!("queue present: {:?}", err).into()),
+                err => return Err(err).context("queue present"),
             },
         };
 
@@ -118,35 +253,55 @@ impl Swapchain {
             device.destroy_image_view(image_view, None);
         }
         // swapchain
-        self.swapchain_loader
-            .destroy_swapchain(self.swapchain, None);
+        // NOTE: the handle is reset so that destroying it again does nothing
+        if self.swapchain != vk::SwapchainKHR::null() {
+            self.swapchain_loader
+                .destroy_swapchain(self.swapchain, None);
+            self.swapchain = vk::SwapchainKHR::null();
+        }
+        self.images.clear();
+        // NOTE: offscreen images are destroyed once the frames using them
+        //       have completed
+        self.offscreen_images.clear();
     }
 }
 
 unsafe fn create_swapchain(
     device: &Device,
+    surface: vk::SurfaceKHR,
     window_extent: vk::Extent2D,
-) -> Result<(vk::SwapchainKHR, khr::Swapchain, Vec<vk::Image>, vk::Format)> {
+    vsync: bool,
+) -> Result<Swapchain> {
     // Obtain swapchain support details from the device
     let swapchain_support = device
-        .swapchain_support_details()
-        .map_err(|e| format!("obtain swapchain support details: {:?}", e))?;
+        .swapchain_support_details(surface)
+        .context("obtain swapchain support details")?;
 
     // Select swapchain attributes
     let surface_format = select_surface_format(&swapchain_support.formats);
     let image_count = select_image_count(swapchain_support.capabilities);
     let pre_transform = select_pre_transform(swapchain_support.capabilities);
     let extent = select_extent(swapchain_support.capabilities, window_extent);
-    let present_mode = select_present_mode(&swapchain_support.present_modes);
+    let present_mode = select_present_mode(&swapchain_support.present_modes, vsync);
+
+    // allow copying the images when supported
+    let transfer_src = swapchain_support
+        .capabilities
+        .supported_usage_flags
+        .contains(vk::ImageUsageFlags::TRANSFER_SRC);
+    let mut image_usage = vk::ImageUsageFlags::COLOR_ATTACHMENT;
+    if transfer_src {
+        image_usage |= vk::ImageUsageFlags::TRANSFER_SRC;
+    }
 
     // create swapchain
     let swapchain_create_info = vk::SwapchainCreateInfoKHR::builder()
-        .surface(*device.surface())
+        .surface(surface)
         .min_image_count(image_count)
         .image_color_space(surface_format.color_space)
         .image_format(surface_format.format)
         .image_extent(extent)
-        .image_usage(vk::ImageUsageFlags::COLOR_ATTACHMENT)
+        .image_usage(image_usage)
         .image_sharing_mode(vk::SharingMode::EXCLUSIVE)
         .pre_transform(pre_transform)
         .composite_alpha(vk::CompositeAlphaFlagsKHR::OPAQUE)
@@ -156,14 +311,26 @@ unsafe fn create_swapchain(
     let swapchain_loader = khr::Swapchain::new(device.instance(), device);
     let swapchain = swapchain_loader
         .create_swapchain(&swapchain_create_info, None)
-        .map_err(|e| format!("create swapchain: {:?}", e))?;
+        .context("create swapchain")?;
 
     // obtain swapchain images
     let images = swapchain_loader
         .get_swapchain_images(swapchain)
-        .map_err(|e| format!("obtain swapchain images: {:?}", e))?;
+        .context("obtain swapchain images")?;
 
-    Ok((swapchain, swapchain_loader, images, surface_format.format))
+    // NOTE: the image views are created by the caller
+    Ok(Swapchain {
+        swapchain,
+        swapchain_loader,
+        image_format: surface_format.format,
+        extent,
+        present_mode,
+        transfer_src,
+        images,
+        present_image_views: Vec::new(),
+        current_image_index: 0,
+        offscreen_images: Vec::new(),
+    })
 }
 
 // Select optimal surface format. If not found, fallback to the first format
@@ -225,8 +392,12 @@ fn select_extent(
     current_extent
 }
 
-// Select MAILBOX present mode. If not available, fallback to FIFO.
-fn select_present_mode(present_modes: &[vk::PresentModeKHR]) -> vk::PresentModeKHR {
+// Select MAILBOX present mode. If not available or vsync is requested, fallback
+// to FIFO, which is always supported.
+fn select_present_mode(present_modes: &[vk::PresentModeKHR], vsync: bool) -> vk::PresentModeKHR {
+    if vsync {
+        return vk::PresentModeKHR::FIFO;
+    }
     present_modes
         .iter()
         .cloned()
@@ -261,7 +432,7 @@ unsafe fn create_present_image_views(
     }) {
         let image_view = device
             .create_image_view(&create_view_info, None)
-            .map_err(|e| format!("create image view: {:?}", e))?;
+            .context("create image view")?;
         image_views.push(image_view);
     }
 
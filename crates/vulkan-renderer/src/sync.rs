@@ -0,0 +1,279 @@
+use ash::vk;
+
+use super::device::Device;
+use crate::error::ResultExt;
+use crate::Result;
+
+/// Layout transition of an image and the dependency it creates, described
+/// with the stage and access masks of synchronization2.
+///
+/// Barriers are recorded with `vkCmdPipelineBarrier2` when the device supports
+/// VK_KHR_synchronization2, else with the equivalent legacy barrier. The masks
+/// must then only use the bits that exist in the legacy flags.
+#[derive(Clone, Copy, Debug)]
+pub struct ImageBarrier {
+    pub image: vk::Image,
+    pub subresource_range: vk::ImageSubresourceRange,
+    pub src_stage_mask: vk::PipelineStageFlags2,
+    pub src_access_mask: vk::AccessFlags2,
+    pub dst_stage_mask: vk::PipelineStageFlags2,
+    pub dst_access_mask: vk::AccessFlags2,
+    pub old_layout: vk::ImageLayout,
+    pub new_layout: vk::ImageLayout,
+}
+
+impl ImageBarrier {
+    /// Transitions all the mip levels and layers of a color image, without
+    /// waiting for nor blocking any work.
+    pub fn new(image: vk::Image, old_layout: vk::ImageLayout, new_layout: vk::ImageLayout) -> Self {
+        Self {
+            image,
+            subresource_range: vk::ImageSubresourceRange {
+                aspect_mask: vk::ImageAspectFlags::COLOR,
+                base_mip_level: 0,
+                level_count: vk::REMAINING_MIP_LEVELS,
+                base_array_layer: 0,
+                layer_count: vk::REMAINING_ARRAY_LAYERS,
+            },
+            src_stage_mask: vk::PipelineStageFlags2::NONE,
+            src_access_mask: vk::AccessFlags2::NONE,
+            dst_stage_mask: vk::PipelineStageFlags2::NONE,
+            dst_access_mask: vk::AccessFlags2::NONE,
+            old_layout,
+            new_layout,
+        }
+    }
+
+    /// Prepares an image to be written by transfers, discarding its content.
+    pub fn undefined_to_transfer_dst(image: vk::Image) -> Self {
+        Self::new(
+            image,
+            vk::ImageLayout::UNDEFINED,
+            vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+        )
+        .dst(
+            vk::PipelineStageFlags2::TRANSFER,
+            vk::AccessFlags2::TRANSFER_WRITE,
+        )
+    }
+
+    /// Makes the transfer writes to an image visible to the fragment shaders
+    /// sampling it.
+    pub fn transfer_to_shader_read(image: vk::Image) -> Self {
+        Self::new(
+            image,
+            vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+            vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+        )
+        .src(
+            vk::PipelineStageFlags2::TRANSFER,
+            vk::AccessFlags2::TRANSFER_WRITE,
+        )
+        .dst(
+            vk::PipelineStageFlags2::FRAGMENT_SHADER,
+            vk::AccessFlags2::SHADER_READ,
+        )
+    }
+
+    /// Waits for the accesses of the stages to be done before the transition.
+    pub fn src(
+        mut self,
+        stage_mask: vk::PipelineStageFlags2,
+        access_mask: vk::AccessFlags2,
+    ) -> Self {
+        self.src_stage_mask = stage_mask;
+        self.src_access_mask = access_mask;
+        self
+    }
+
+    /// Blocks the accesses of the stages until the transition is done.
+    pub fn dst(
+        mut self,
+        stage_mask: vk::PipelineStageFlags2,
+        access_mask: vk::AccessFlags2,
+    ) -> Self {
+        self.dst_stage_mask = stage_mask;
+        self.dst_access_mask = access_mask;
+        self
+    }
+
+    pub fn subresource_range(mut self, subresource_range: vk::ImageSubresourceRange) -> Self {
+        self.subresource_range = subresource_range;
+        self
+    }
+
+    fn to_barrier2(self) -> vk::ImageMemoryBarrier2 {
+        vk::ImageMemoryBarrier2 {
+            src_stage_mask: self.src_stage_mask,
+            src_access_mask: self.src_access_mask,
+            dst_stage_mask: self.dst_stage_mask,
+            dst_access_mask: self.dst_access_mask,
+            old_layout: self.old_layout,
+            new_layout: self.new_layout,
+            src_queue_family_index: vk::QUEUE_FAMILY_IGNORED,
+            dst_queue_family_index: vk::QUEUE_FAMILY_IGNORED,
+            image: self.image,
+            subresource_range: self.subresource_range,
+            ..Default::default()
+        }
+    }
+
+    fn to_legacy_barrier(self) -> vk::ImageMemoryBarrier {
+        vk::ImageMemoryBarrier {
+            src_access_mask: legacy_access(self.src_access_mask),
+            dst_access_mask: legacy_access(self.dst_access_mask),
+            old_layout: self.old_layout,
+            new_layout: self.new_layout,
+            src_queue_family_index: vk::QUEUE_FAMILY_IGNORED,
+            dst_queue_family_index: vk::QUEUE_FAMILY_IGNORED,
+            image: self.image,
+            subresource_range: self.subresource_range,
+            ..Default::default()
+        }
+    }
+}
+
+/// Records image barriers. Without synchronization2, a single legacy barrier
+/// waits for the union of the source stages of all the barriers.
+pub unsafe fn cmd_image_barriers(
+    device: &Device,
+    command_buffer: vk::CommandBuffer,
+    barriers: &[ImageBarrier],
+) {
+    if barriers.is_empty() {
+        return;
+    }
+
+    if let Some(synchronization2) = device.synchronization2() {
+        let image_barriers: Vec<_> = barriers.iter().map(|b| b.to_barrier2()).collect();
+        let dependency_info = vk::DependencyInfo::builder().image_memory_barriers(&image_barriers);
+        synchronization2.cmd_pipeline_barrier2(command_buffer, &dependency_info);
+        return;
+    }
+
+    let (src_stage_mask, dst_stage_mask) = barriers.iter().fold(
+        (vk::PipelineStageFlags2::NONE, vk::PipelineStageFlags2::NONE),
+        |(src, dst), b| (src | b.src_stage_mask, dst | b.dst_stage_mask),
+    );
+    let image_barriers: Vec<_> = barriers.iter().map(|b| b.to_legacy_barrier()).collect();
+    device.cmd_pipeline_barrier(
+        command_buffer,
+        legacy_stages(src_stage_mask, vk::PipelineStageFlags::TOP_OF_PIPE),
+        legacy_stages(dst_stage_mask, vk::PipelineStageFlags::BOTTOM_OF_PIPE),
+        vk::DependencyFlags::empty(),
+        &[],
+        &[],
+        &image_barriers,
+    );
+}
+
+/// Semaphore waited on or signaled by a submit, and the stages waiting for it
+/// or signaling it.
+#[derive(Clone, Copy, Debug)]
+pub struct SemaphoreStage {
+    pub semaphore: vk::Semaphore,
+    pub stage_mask: vk::PipelineStageFlags2,
+}
+
+/// Submits command buffers to the graphics queue. Without synchronization2,
+/// semaphores are signaled once all the commands completed.
+pub unsafe fn queue_submit(
+    device: &Device,
+    command_buffers: &[vk::CommandBuffer],
+    wait_semaphores: &[SemaphoreStage],
+    signal_semaphores: &[SemaphoreStage],
+    fence: vk::Fence,
+) -> Result<()> {
+    let queue = *device.graphics_queue();
+
+    if let Some(synchronization2) = device.synchronization2() {
+        let semaphore_infos = |semaphores: &[SemaphoreStage]| -> Vec<_> {
+            semaphores
+                .iter()
+                .map(|s| vk::SemaphoreSubmitInfo {
+                    semaphore: s.semaphore,
+                    stage_mask: s.stage_mask,
+                    ..Default::default()
+                })
+                .collect()
+        };
+        let wait_infos = semaphore_infos(wait_semaphores);
+        let signal_infos = semaphore_infos(signal_semaphores);
+        let command_buffer_infos: Vec<_> = command_buffers
+            .iter()
+            .map(|command_buffer| vk::CommandBufferSubmitInfo {
+                command_buffer: *command_buffer,
+                ..Default::default()
+            })
+            .collect();
+        let submits = [vk::SubmitInfo2::builder()
+            .wait_semaphore_infos(&wait_infos)
+            .command_buffer_infos(&command_buffer_infos)
+            .signal_semaphore_infos(&signal_infos)
+            .build()];
+        return synchronization2
+            .queue_submit2(queue, &submits, fence)
+            .context("queue submit2");
+    }
+
+    let wait: Vec<_> = wait_semaphores.iter().map(|s| s.semaphore).collect();
+    let wait_stages: Vec<_> = wait_semaphores
+        .iter()
+        .map(|s| legacy_stages(s.stage_mask, vk::PipelineStageFlags::TOP_OF_PIPE))
+        .collect();
+    let signal: Vec<_> = signal_semaphores.iter().map(|s| s.semaphore).collect();
+    let submits = [vk::SubmitInfo::builder()
+        .wait_semaphores(&wait)
+        .wait_dst_stage_mask(&wait_stages)
+        .command_buffers(command_buffers)
+        .signal_semaphores(&signal)
+        .build()];
+    device
+        .queue_submit(queue, &submits, fence)
+        .context("queue submit")
+}
+
+/// Returns the legacy stages equivalent to synchronization2 ones, or `none`
+/// when there are none, legacy masks not being allowed to be empty.
+fn legacy_stages(
+    stages: vk::PipelineStageFlags2,
+    none: vk::PipelineStageFlags,
+) -> vk::PipelineStageFlags {
+    // NOTE: the legacy bits keep their values in the 64-bit flags
+    debug_assert!(
+        stages.as_raw() >> 32 == 0,
+        "{stages:?} has no legacy equivalent"
+    );
+    if stages.is_empty() {
+        none
+    } else {
+        vk::PipelineStageFlags::from_raw(stages.as_raw() as u32)
+    }
+}
+
+fn legacy_access(access: vk::AccessFlags2) -> vk::AccessFlags {
+    debug_assert!(
+        access.as_raw() >> 32 == 0,
+        "{access:?} has no legacy equivalent"
+    );
+    vk::AccessFlags::from_raw(access.as_raw() as u32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn barriers_convert_to_legacy_masks() {
+        let barrier = ImageBarrier::transfer_to_shader_read(vk::Image::null()).to_legacy_barrier();
+        assert_eq!(barrier.src_access_mask, vk::AccessFlags::TRANSFER_WRITE);
+        assert_eq!(barrier.dst_access_mask, vk::AccessFlags::SHADER_READ);
+
+        let top = vk::PipelineStageFlags::TOP_OF_PIPE;
+        assert_eq!(
+            legacy_stages(vk::PipelineStageFlags2::FRAGMENT_SHADER, top),
+            vk::PipelineStageFlags::FRAGMENT_SHADER
+        );
+        assert_eq!(legacy_stages(vk::PipelineStageFlags2::NONE, top), top);
+    }
+}
@@ -1,28 +1,31 @@
+use core::handle::Handle;
 use std::ops::Deref;
 
 use ash::vk;
 
+use super::deletion::{DeletionQueueHandle, Resource};
+use super::device::Device;
 use super::image::Image;
 use crate::Result;
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Debug)]
 pub struct Sampler {
     handle: vk::Sampler,
 
-    destroyed: bool,
+    deletion_queue: DeletionQueueHandle,
 }
 
 impl Sampler {
-    pub unsafe fn new(device: &ash::Device, create_info: vk::SamplerCreateInfo) -> Result<Self> {
+    pub unsafe fn new(device: &Device, create_info: vk::SamplerCreateInfo) -> Result<Self> {
         let sampler = device.create_sampler(&create_info, None).unwrap();
 
         Ok(Self {
             handle: sampler,
-            destroyed: false,
+            deletion_queue: device.deletion_queue(),
         })
     }
 
-    pub unsafe fn basic(device: &ash::Device) -> Result<Self> {
+    pub unsafe fn basic(device: &Device) -> Result<Self> {
         let create_info = vk::SamplerCreateInfo::builder()
             .mag_filter(vk::Filter::LINEAR)
             .min_filter(vk::Filter::LINEAR)
@@ -38,16 +41,16 @@ impl Sampler {
             .mipmap_mode(vk::SamplerMipmapMode::LINEAR)
             .mip_lod_bias(0.0)
             .min_lod(0.0)
-            .max_lod(1.0);
+            .max_lod(vk::LOD_CLAMP_NONE);
         Self::new(device, *create_info)
     }
+}
 
-    pub unsafe fn destroy(&mut self, device: &ash::Device) {
-        if self.destroyed {
-            panic!("sampler already destroyed")
-        }
-        device.destroy_sampler(self.handle, None);
-        self.destroyed = true;
+impl Drop for Sampler {
+    fn drop(&mut self) {
+        self.deletion_queue
+            .borrow_mut()
+            .push(Resource::Sampler(self.handle));
     }
 }
 
@@ -59,31 +62,44 @@ impl Deref for Sampler {
     }
 }
 
-#[derive(Clone, Copy, Debug)]
+/// Typed handle to a `Texture` stored in a `HandleMap`.
+pub type TextureHandle = Handle<Texture>;
+
+#[derive(Debug)]
 pub struct Texture {
-    image: Image,
+    /// NOTE: only kept alive, the image being accessed through its view.
+    _image: Image,
     image_view: vk::ImageView,
+    view_type: vk::ImageViewType,
     sampler: Sampler,
 
-    destroyed: bool,
+    deletion_queue: DeletionQueueHandle,
 }
 
 impl Texture {
-    pub unsafe fn new(device: &ash::Device, image: Image, sampler: Sampler) -> Result<Self> {
-        let image_view = image.create_view(
-            device,
-            vk::ImageViewType::TYPE_2D,
-            vk::ImageAspectFlags::COLOR,
-        )?;
+    pub unsafe fn new(device: &Device, image: Image, sampler: Sampler) -> Result<Self> {
+        Self::with_view_type(device, image, sampler, vk::ImageViewType::TYPE_2D)
+    }
+
+    /// Creates a texture viewing the image as e.g. a cubemap (`CUBE`) or an
+    /// array of layers (`TYPE_2D_ARRAY`).
+    pub unsafe fn with_view_type(
+        device: &Device,
+        image: Image,
+        sampler: Sampler,
+        view_type: vk::ImageViewType,
+    ) -> Result<Self> {
+        let image_view = image.create_view(device, view_type, vk::ImageAspectFlags::COLOR)?;
         Ok(Self {
-            image,
+            _image: image,
             image_view,
+            view_type,
             sampler,
-            destroyed: false,
+            deletion_queue: device.deletion_queue(),
         })
     }
 
-    pub unsafe fn from_image(device: &ash::Device, image: Image) -> Result<Self> {
+    pub unsafe fn from_image(device: &Device, image: Image) -> Result<Self> {
         let sampler = Sampler::basic(device)?;
         Self::new(device, image, sampler)
     }
@@ -92,19 +108,20 @@ impl Texture {
         &self.image_view
     }
 
+    pub fn view_type(&self) -> vk::ImageViewType {
+        self.view_type
+    }
+
     pub fn sampler(&self) -> &Sampler {
         &self.sampler
     }
+}
 
-    pub unsafe fn destroy(&mut self, device: &ash::Device) {
-        if self.destroyed {
-            panic!("texture already destroyed")
-        }
-
-        self.sampler.destroy(device);
-        device.destroy_image_view(self.image_view, None);
-        self.image.destroy(device);
-
-        self.destroyed = true;
+impl Drop for Texture {
+    // NOTE: the image and the sampler are dropped right after the view
+    fn drop(&mut self) {
+        self.deletion_queue
+            .borrow_mut()
+            .push(Resource::ImageView(self.image_view));
     }
 }
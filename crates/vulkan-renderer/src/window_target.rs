@@ -0,0 +1,331 @@
+use ash::vk;
+use log::debug;
+use winit::window::{Window, WindowId};
+
+use super::device::Device;
+use super::image::Image;
+use super::renderer::{
+    create_depth_image, create_depth_image_view, create_framebuffers, create_viewport_and_scissor,
+};
+use super::renderpass::RenderPass;
+use super::swapchain::Swapchain;
+use super::sync::SemaphoreStage;
+use crate::error::ResultExt;
+use crate::Result;
+
+/// Surface, swapchain and framebuffers of a window drawn in addition to the
+/// main one, see `VulkanRenderer::add_window()`.
+///
+/// Additional windows are drawn in the command buffer of the main window and
+/// presented along with it, so that they share its frames in flight. Their
+/// swapchain must use the image format of the main one, so that pipelines
+/// created for the main render pass can draw to them.
+pub struct WindowTarget {
+    id: WindowId,
+    surface: vk::SurfaceKHR,
+    swapchain: Swapchain,
+    renderpass: RenderPass,
+
+    depth_image: Image,
+    depth_image_view: vk::ImageView,
+    framebuffers: Vec<vk::Framebuffer>,
+
+    /// Size of the window.
+    extent: vk::Extent2D,
+    resized: bool,
+
+    /// Signaled when the image drawn by each frame in flight is acquired.
+    acquire_semaphores: Vec<vk::Semaphore>,
+    /// Signaled when each frame in flight is drawn, waited on by presentation.
+    render_semaphores: Vec<vk::Semaphore>,
+    /// Set when an image has been acquired for the current frame.
+    acquired: bool,
+
+    vsync: bool,
+}
+
+impl WindowTarget {
+    /// Creates the surface and swapchain of a window. Fails if its swapchain
+    /// images can not use `image_format`.
+    pub(crate) unsafe fn new(
+        device: &Device,
+        window: &Window,
+        image_format: vk::Format,
+        vsync: bool,
+        frames_in_flight: u32,
+    ) -> Result<Self> {
+        let surface = device
+            .create_window_surface(window)
+            .context("create window surface")?;
+        let window_size = window.inner_size();
+        let extent = vk::Extent2D {
+            width: window_size.width,
+            height: window_size.height,
+        };
+
+        // NOTE: the render pass only depends on the formats, it is kept when
+        //       the swapchain is recreated
+        let depth_format = device.depth_format();
+        let renderpass = match RenderPass::new(device, &image_format, depth_format) {
+            Ok(renderpass) => renderpass,
+            Err(e) => {
+                device.destroy_window_surface(surface);
+                return Err(e).context("create window renderpass");
+            }
+        };
+        let resources =
+            create_swapchain_resources(device, surface, &renderpass, extent, image_format, vsync);
+        let (swapchain, depth_image, depth_image_view, framebuffers) = match resources {
+            Ok(resources) => resources,
+            Err(e) => {
+                device.destroy_render_pass(*renderpass, None);
+                device.destroy_window_surface(surface);
+                return Err(e);
+            }
+        };
+
+        let mut target = Self {
+            id: window.id(),
+            surface,
+            swapchain,
+            renderpass,
+            depth_image,
+            depth_image_view,
+            framebuffers,
+            extent,
+            resized: false,
+            acquire_semaphores: Vec::with_capacity(frames_in_flight as usize),
+            render_semaphores: Vec::with_capacity(frames_in_flight as usize),
+            acquired: false,
+            vsync,
+        };
+        if let Err(e) = target.create_semaphores(device, frames_in_flight) {
+            target.destroy(device);
+            return Err(e);
+        }
+        debug!("window {:?} added", target.id);
+
+        Ok(target)
+    }
+
+    unsafe fn create_semaphores(&mut self, device: &Device, frames_in_flight: u32) -> Result<()> {
+        let semaphore_create_info = vk::SemaphoreCreateInfo::builder();
+        for _ in 0..frames_in_flight {
+            let semaphore = device
+                .create_semaphore(&semaphore_create_info, None)
+                .context("create semaphore")?;
+            self.acquire_semaphores.push(semaphore);
+            let semaphore = device
+                .create_semaphore(&semaphore_create_info, None)
+                .context("create semaphore")?;
+            self.render_semaphores.push(semaphore);
+        }
+        Ok(())
+    }
+
+    pub fn id(&self) -> WindowId {
+        self.id
+    }
+
+    pub fn extent(&self) -> vk::Extent2D {
+        self.extent
+    }
+
+    pub(crate) fn set_clear_color(&mut self, color: [f32; 4]) {
+        self.renderpass.set_clear_color(color);
+    }
+
+    /// Returns true if the window is minimized or reduced to 0 in any
+    /// direction, in which case it is not drawn.
+    pub fn is_minimized(&self) -> bool {
+        self.extent.width == 0 || self.extent.height == 0
+    }
+
+    pub(crate) fn resize(&mut self, width: u32, height: u32) {
+        self.extent = vk::Extent2D { width, height };
+        self.resized = true;
+    }
+
+    /// Returns true if an image was acquired for the current frame.
+    pub(crate) fn is_acquired(&self) -> bool {
+        self.acquired
+    }
+
+    /// Acquires the image drawn by a frame. Returns false if the window is
+    /// not drawn this frame, e.g. when its swapchain had to be recreated.
+    pub(crate) unsafe fn acquire(&mut self, device: &Device, frame: usize) -> Result<bool> {
+        self.acquired = false;
+        if self.is_minimized() {
+            return Ok(false);
+        }
+        if self.resized {
+            // NOTE: the swapchain is recreated once the surface has an extent
+            if device
+                .surface_is_minimized(self.surface)
+                .context("query window surface extent")?
+            {
+                return Ok(false);
+            }
+            self.recreate_swapchain(device)
+                .context("recreate window swapchain")?;
+        }
+
+        let outdated = self
+            .swapchain
+            .acquire_next_image(
+                device,
+                std::u64::MAX,
+                &self.acquire_semaphores[frame],
+                &vk::Fence::null(),
+            )
+            .context("acquire window image")?;
+        if outdated {
+            self.resized = true;
+            return Ok(false);
+        }
+
+        self.acquired = true;
+        Ok(true)
+    }
+
+    /// Returns the semaphores waited on and signaled by the submission of
+    /// a frame drawing the window.
+    pub(crate) fn semaphores(&self, frame: usize) -> (SemaphoreStage, SemaphoreStage) {
+        let wait = SemaphoreStage {
+            semaphore: self.acquire_semaphores[frame],
+            stage_mask: vk::PipelineStageFlags2::COLOR_ATTACHMENT_OUTPUT,
+        };
+        let signal = SemaphoreStage {
+            semaphore: self.render_semaphores[frame],
+            stage_mask: vk::PipelineStageFlags2::ALL_COMMANDS,
+        };
+        (wait, signal)
+    }
+
+    /// Records the commands of `f` in the render pass of the acquired image.
+    pub(crate) unsafe fn record<F>(
+        &self,
+        device: &ash::Device,
+        command_buffer: vk::CommandBuffer,
+        f: F,
+    ) where
+        F: FnOnce(&ash::Device, vk::CommandBuffer),
+    {
+        let framebuffer = &self.framebuffers[self.swapchain.current_index()];
+        self.renderpass
+            .begin(device, framebuffer, self.extent.into(), &command_buffer);
+        let (viewport, scissor) = create_viewport_and_scissor(self.extent);
+        device.cmd_set_viewport(command_buffer, 0, &[viewport]);
+        device.cmd_set_scissor(command_buffer, 0, &[scissor]);
+        f(device, command_buffer);
+        self.renderpass.end(device, &command_buffer);
+    }
+
+    /// Presents the acquired image once the frame is drawn.
+    pub(crate) unsafe fn present(&mut self, device: &Device, frame: usize) -> Result<()> {
+        if !std::mem::take(&mut self.acquired) {
+            return Ok(());
+        }
+        let outdated = self
+            .swapchain
+            .queue_present(device, &[self.render_semaphores[frame]])
+            .context("present window image")?;
+        if outdated {
+            self.resized = true;
+        }
+        Ok(())
+    }
+
+    unsafe fn recreate_swapchain(&mut self, device: &Device) -> Result<()> {
+        device.device_wait_idle().context("device wait idle")?;
+        self.resized = false;
+
+        let image_format = *self.swapchain.image_format();
+        self.destroy_swapchain_resources(device);
+        let (swapchain, depth_image, depth_image_view, framebuffers) = create_swapchain_resources(
+            device,
+            self.surface,
+            &self.renderpass,
+            self.extent,
+            image_format,
+            self.vsync,
+        )?;
+        self.swapchain = swapchain;
+        self.depth_image = depth_image;
+        self.depth_image_view = depth_image_view;
+        self.framebuffers = framebuffers;
+
+        // NOTE: an image may have been acquired with the semaphores of the
+        //       outdated swapchain, they are replaced to be unsignaled
+        let semaphore_create_info = vk::SemaphoreCreateInfo::builder();
+        for semaphore in &mut self.acquire_semaphores {
+            device.destroy_semaphore(*semaphore, None);
+            *semaphore = device
+                .create_semaphore(&semaphore_create_info, None)
+                .context("create semaphore")?;
+        }
+        Ok(())
+    }
+
+    unsafe fn destroy_swapchain_resources(&mut self, device: &Device) {
+        for framebuffer in self.framebuffers.drain(..) {
+            device.destroy_framebuffer(framebuffer, None);
+        }
+        device.destroy_image_view(self.depth_image_view, None);
+        self.depth_image_view = vk::ImageView::null();
+        self.swapchain.destroy(device);
+    }
+
+    /// Destroys the objects of the window. The device must be idle.
+    pub(crate) unsafe fn destroy(&mut self, device: &Device) {
+        self.destroy_swapchain_resources(device);
+        device.destroy_render_pass(*self.renderpass, None);
+        for semaphore in self
+            .acquire_semaphores
+            .drain(..)
+            .chain(self.render_semaphores.drain(..))
+        {
+            device.destroy_semaphore(semaphore, None);
+        }
+        device.destroy_window_surface(self.surface);
+        debug!("window {:?} removed", self.id);
+    }
+}
+
+/// Creates the swapchain of a surface along with its depth image and
+/// framebuffers.
+unsafe fn create_swapchain_resources(
+    device: &Device,
+    surface: vk::SurfaceKHR,
+    renderpass: &RenderPass,
+    extent: vk::Extent2D,
+    image_format: vk::Format,
+    vsync: bool,
+) -> Result<(Swapchain, Image, vk::ImageView, Vec<vk::Framebuffer>)> {
+    let mut swapchain =
+        Swapchain::new(device, surface, extent, vsync).context("create window swapchain")?;
+    if *swapchain.image_format() != image_format {
+        let format = *swapchain.image_format();
+        swapchain.destroy(device);
+        return Err(format!(
+            "window images use {format:?} instead of the {image_format:?} main images"
+        )
+        .into());
+    }
+
+    let depth_image = create_depth_image(device, extent.into(), device.depth_format())
+        .context("create window depth image")?;
+    let depth_image_view =
+        create_depth_image_view(device, depth_image.image(), depth_image.format())
+            .context("create window depth image view")?;
+    let framebuffers = create_framebuffers(
+        device,
+        renderpass,
+        swapchain.image_views(),
+        &depth_image_view,
+        extent,
+    )
+    .context("create window framebuffers")?;
+
+    Ok((swapchain, depth_image, depth_image_view, framebuffers))
+}